@@ -0,0 +1,155 @@
+//! `asset-manifest.json`: the names `cache_bust()` gave a build's assets.
+//!
+//! Tools that run after `hugs build` (a service worker generator, say) read it to map original
+//! paths to hashed ones. `hugs build --reuse-asset-manifest <path>` reads it back to offer the
+//! earlier names to the registry, which keeps each one as long as the file's contents still hash
+//! the same.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{HugsError, Result};
+use crate::run::{CacheBustEntry, CacheBustRegistry};
+
+pub const ASSET_MANIFEST_FILE: &str = "asset-manifest.json";
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct AssetManifest {
+    pub assets: Vec<ManifestAsset>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestAsset {
+    /// Path the site asks for, e.g. "/theme.css"
+    pub original: String,
+    /// Path the copy is written to, e.g. "/theme.a1b2c3f4.css"
+    pub hashed: String,
+    /// Full SHA-256 of the source contents, in hex
+    pub hash: String,
+    /// Size of the written copy, in bytes
+    pub size: u64,
+}
+
+impl AssetManifest {
+    /// Describe the registry's entries as written to `output_path`, sorted by original path
+    pub fn collect(registry: &CacheBustRegistry, output_path: &Path) -> Self {
+        let mut assets: Vec<ManifestAsset> = registry
+            .entries_with_hashes()
+            .into_iter()
+            .map(|(original, entry)| {
+                let written = output_path.join(entry.hashed.trim_start_matches('/'));
+                ManifestAsset {
+                    size: written.metadata().map(|m| m.len()).unwrap_or(0),
+                    original,
+                    hashed: entry.hashed,
+                    hash: entry.hash,
+                }
+            })
+            .collect();
+        assets.sort_by(|a, b| a.original.cmp(&b.original));
+
+        Self { assets }
+    }
+
+    /// Read a manifest written by an earlier build
+    pub async fn load(path: &Path) -> Result<Self> {
+        let content = tokio::fs::read_to_string(path).await.map_err(|e| HugsError::FileRead {
+            path: path.into(),
+            cause: e,
+        })?;
+
+        Self::from_json(&content).map_err(|e| HugsError::AssetManifestParse {
+            path: path.into(),
+            reason: e.to_string(),
+        })
+    }
+
+    pub fn from_json(json: &str) -> serde_json::Result<Self> {
+        serde_json::from_str(json)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap_or_default()
+    }
+
+    /// The entries to seed a registry with
+    pub fn reusable(&self) -> HashMap<String, CacheBustEntry> {
+        self.assets
+            .iter()
+            .map(|asset| {
+                let entry = CacheBustEntry {
+                    hashed: asset.hashed.clone(),
+                    hash: asset.hash.clone(),
+                };
+                (asset.original.clone(), entry)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    use minijinja::Environment;
+
+    use crate::assets::AssetRoots;
+    use crate::deps::DepRecorder;
+    use crate::run::CacheBustFunction;
+
+    fn cache_bust(site: &Path, registry: &CacheBustRegistry, path: &str) -> String {
+        let function = CacheBustFunction::new(AssetRoots::whole_site(site), String::new(), String::new(), registry.clone());
+        let mut env = Environment::new();
+        env.add_function("cache_bust", function.to_minijinja_fn(&DepRecorder::default()));
+        env.render_str(&format!(r#"{{{{ cache_bust(path="{}") }}}}"#, path), ()).unwrap()
+    }
+
+    #[test]
+    fn test_manifest_round_trips() {
+        let site = tempfile::tempdir().unwrap();
+        std::fs::write(site.path().join("app.js"), "console.log(1)").unwrap();
+        let registry = CacheBustRegistry::new();
+        let hashed = cache_bust(site.path(), &registry, "/app.js");
+
+        let output = tempfile::tempdir().unwrap();
+        std::fs::write(output.path().join(hashed.trim_start_matches('/')), "console.log(1)").unwrap();
+
+        let manifest = AssetManifest::collect(&registry, output.path());
+        let [asset] = manifest.assets.as_slice() else {
+            panic!("expected one asset, got {:?}", manifest.assets);
+        };
+        assert_eq!((asset.original.as_str(), asset.hashed.as_str(), asset.size), ("/app.js", hashed.as_str(), 14));
+        assert_eq!(asset.hash.len(), 64);
+        assert!(hashed.contains(&asset.hash[..8]));
+
+        assert_eq!(AssetManifest::from_json(&manifest.to_json()).unwrap(), manifest);
+    }
+
+    #[test]
+    fn test_seeded_names_are_reused_only_while_contents_match() {
+        let site = tempfile::tempdir().unwrap();
+        std::fs::write(site.path().join("app.js"), "console.log(1)").unwrap();
+        std::fs::write(site.path().join("style.css"), "body {}").unwrap();
+
+        let first = CacheBustRegistry::new();
+        cache_bust(site.path(), &first, "/app.js");
+        cache_bust(site.path(), &first, "/style.css");
+        let mut manifest = AssetManifest::from_json(&AssetManifest::collect(&first, &PathBuf::new()).to_json()).unwrap();
+        // Names from an older hashing scheme are kept as long as the contents match
+        for asset in &mut manifest.assets {
+            asset.hashed = asset.hashed.replace('.', ".v1.");
+        }
+
+        std::fs::write(site.path().join("style.css"), "body { color: red }").unwrap();
+        let second = CacheBustRegistry::new();
+        second.seed(manifest.reusable());
+
+        assert_eq!(cache_bust(site.path(), &second, "/app.js"), manifest.assets[0].hashed);
+        let style = cache_bust(site.path(), &second, "/style.css");
+        assert_ne!(style, manifest.assets[1].hashed);
+        assert!(!style.contains(".v1."), "{style}");
+    }
+}
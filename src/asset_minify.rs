@@ -0,0 +1,321 @@
+//! Minifying the JavaScript and SVG files `hugs build` publishes, for `[build.minify] js` and
+//! `svg`. Hand-written scripts and SVGs exported from a drawing app are mostly comments and
+//! indentation, and nobody reads the published copies.
+//!
+//! A file that doesn't minify is published as it is, with a warning, and so is one that comes out
+//! no smaller. Cache-busted copies are named for their minified contents, since that's what's
+//! served.
+//!
+//! SVGs are cleaned rather than optimized: comments, `<metadata>` and whitespace go, while
+//! `<script>`, `<style>` and CDATA sections are kept as they are. With `svg_ids`, so do ids nothing
+//! points at. An id stays when the file itself refers to it (`href="#a"`, `url(#a)`), when the
+//! site's CSS does (`url(#a)`), when the file has a script, or when the file is a sprite sheet of
+//! `<symbol>`s, whose ids pages use from outside. It's opt-in because any page can point into any
+//! SVG with `icons.svg#id`, which I can't see from the file.
+
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::{Arc, LazyLock};
+
+use oxc_allocator::Allocator;
+use oxc_codegen::{Codegen, CodegenOptions, CommentOptions, LegalComment};
+use oxc_minifier::{CompressOptions, MangleOptions, Minifier, MinifierOptions};
+use oxc_parser::Parser;
+use oxc_span::SourceType;
+use regex::{Captures, Regex};
+
+use crate::config::Minify;
+
+/// Comments, which go, and the parts of an SVG that aren't markup, which are kept as they are.
+/// They're found together so that a comment inside a script stays, and a script inside a comment goes.
+static COMMENT_OR_VERBATIM: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?s)(<!--.*?-->)|<!\[CDATA\[.*?\]\]>|<script\b.*?</script\s*>|<style\b.*?</style\s*>").unwrap()
+});
+/// Where a verbatim part was taken out, shaped like a tag so the whitespace around it goes too
+static VERBATIM_PLACEHOLDER: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"<hugs-verbatim-(\d+)/>").unwrap());
+static METADATA: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?s)<metadata\b[^>]*/>|<metadata\b[^>]*>.*?</metadata\s*>").unwrap());
+/// `<text>` elements, whose whitespace is part of what they say
+static TEXT_ELEMENT: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?s)<text\b.*?</text\s*>").unwrap());
+/// Start and self-closing tags
+static TAG: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"<[a-zA-Z][^>]*>").unwrap());
+static ATTRIBUTE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"\s+([^\s=/>]+)\s*=\s*("[^"]*"|'[^']*')"#).unwrap());
+static BETWEEN_TAGS: LazyLock<Regex> = LazyLock::new(|| Regex::new(r">\s+<").unwrap());
+static WHITESPACE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\s+").unwrap());
+/// `#id` references, in `href`s, `url()`s and styles
+static ID_REFERENCE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"#([A-Za-z_][\w.:-]*)").unwrap());
+/// `url(#id)` references in a stylesheet
+static CSS_URL_ID: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"url\(\s*['"]?#([A-Za-z_][\w.:-]*)"#).unwrap());
+
+/// Minifies the kinds of file `[build.minify]` asks for
+#[derive(Clone)]
+pub struct AssetMinifier {
+    js: bool,
+    svg: bool,
+    /// Drop the ids of SVG elements nothing refers to
+    svg_ids: bool,
+    /// Ids the site's CSS refers to with `url(#id)`
+    css_ids: Arc<HashSet<String>>,
+}
+
+impl AssetMinifier {
+    /// A minifier for `minify`, or none when it leaves scripts and SVGs alone. `stylesheets` are
+    /// the site's CSS, for the ids SVGs have to keep.
+    pub fn new<'a>(minify: Minify, stylesheets: impl IntoIterator<Item = &'a str>) -> Option<Self> {
+        if !minify.js() && !minify.svg() {
+            return None;
+        }
+        let css_ids = stylesheets
+            .into_iter()
+            .flat_map(|css| CSS_URL_ID.captures_iter(css).map(|caps| caps[1].to_string()))
+            .collect();
+        Some(Self { js: minify.js(), svg: minify.svg(), svg_ids: minify.svg_ids(), css_ids: Arc::new(css_ids) })
+    }
+
+    /// Whether the file at `path` is a kind I minify
+    pub fn minifies(&self, path: &Path) -> bool {
+        let extension = path.extension().and_then(|ext| ext.to_str()).map(str::to_ascii_lowercase);
+        match extension.as_deref() {
+            Some("js" | "mjs") => self.js,
+            Some("svg") => self.svg,
+            _ => false,
+        }
+    }
+
+    /// The minified contents of the file at `path`, or none when it isn't a kind I minify or
+    /// doesn't get any smaller. The error says why it couldn't be minified.
+    pub fn minify(&self, path: &Path, content: &[u8]) -> Option<std::result::Result<String, String>> {
+        if !self.minifies(path) {
+            return None;
+        }
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        let minified = match extension.as_str() {
+            "svg" => minify_svg(content, self.svg_ids.then_some(self.css_ids.as_ref())),
+            _ => minify_js(content, extension == "mjs"),
+        };
+        match minified {
+            Ok(minified) if minified.len() < content.len() => Some(Ok(minified)),
+            Ok(_) => None,
+            Err(reason) => Some(Err(reason)),
+        }
+    }
+}
+
+/// How much minifying saved over a build
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct MinifyStats {
+    pub files: usize,
+    pub before: u64,
+    pub after: u64,
+}
+
+impl MinifyStats {
+    pub fn add(&mut self, before: usize, after: usize) {
+        self.files += 1;
+        self.before += before as u64;
+        self.after += after as u64;
+    }
+}
+
+/// A script, parsed as a module when it's `.mjs` or won't parse as a plain script
+fn minify_js(content: &[u8], module: bool) -> std::result::Result<String, String> {
+    let source = std::str::from_utf8(content).map_err(|_| String::from("it isn't UTF-8"))?;
+    let allocator = Allocator::default();
+
+    let kinds: &[SourceType] = if module { &[SourceType::mjs()] } else { &[SourceType::cjs(), SourceType::mjs()] };
+    let mut first_error = None;
+    for &kind in kinds {
+        let parsed = Parser::new(&allocator, source, kind).parse();
+        if let Some(error) = parsed.errors.first() {
+            first_error.get_or_insert_with(|| error.to_string());
+            continue;
+        }
+
+        let mut program = parsed.program;
+        // The safest compression keeps function and class names, which scripts may look up
+        let minified = Minifier::new(MinifierOptions {
+            mangle: Some(MangleOptions::default()),
+            compress: Some(CompressOptions::safest()),
+        })
+        .minify(&allocator, &mut program);
+        let code = Codegen::new()
+            // License comments (`/*! ... */`) stay, like every other minifier keeps them
+            .with_options(CodegenOptions {
+                comments: CommentOptions { legal: LegalComment::Inline, ..CommentOptions::disabled() },
+                ..CodegenOptions::minify()
+            })
+            .with_scoping(minified.scoping)
+            .with_private_member_mappings(minified.class_private_mappings)
+            .build(&program)
+            .code;
+        return Ok(code);
+    }
+    Err(first_error.unwrap_or_default())
+}
+
+/// An SVG without comments, metadata or whitespace between its tags. With `strip_ids`, which are
+/// the ids the site's CSS refers to, unused ids go too.
+fn minify_svg(content: &[u8], strip_ids: Option<&HashSet<String>>) -> std::result::Result<String, String> {
+    let source = std::str::from_utf8(content).map_err(|_| String::from("it isn't UTF-8"))?;
+    if !source.contains("<svg") {
+        return Err(String::from("there's no <svg> element in it"));
+    }
+
+    // Scripts, styles and CDATA aren't markup, so they're set aside until the rest is cleaned
+    let mut verbatim = Vec::new();
+    let svg = COMMENT_OR_VERBATIM.replace_all(source, |part: &Captures| {
+        if part.get(1).is_some() {
+            return String::new();
+        }
+        verbatim.push(part[0].to_string());
+        format!("<hugs-verbatim-{}/>", verbatim.len() - 1)
+    });
+    let svg = METADATA.replace_all(&svg, "");
+
+    let keep_every_id = strip_ids.is_none() || svg.contains("<symbol") || verbatim.iter().any(|part| part.starts_with("<script"));
+    let referenced: HashSet<&str> = std::iter::once(svg.as_ref())
+        .chain(verbatim.iter().map(String::as_str))
+        .flat_map(|part| ID_REFERENCE.captures_iter(part).map(|caps| caps.get(1).unwrap().as_str()))
+        .collect();
+    let keep_id = |id: &str| keep_every_id || referenced.contains(id) || strip_ids.is_some_and(|css_ids| css_ids.contains(id));
+
+    // Tags get single spaces between attributes, and attribute values lose their line breaks
+    let svg = TAG.replace_all(&svg, |tag: &Captures| {
+        let tag = &tag[0];
+        let mut cleaned = String::with_capacity(tag.len());
+        let name_end = tag.find(|c: char| c.is_whitespace() || c == '/' || c == '>').unwrap_or(tag.len());
+        cleaned.push_str(&tag[..name_end]);
+        for caps in ATTRIBUTE.captures_iter(tag) {
+            let (name, quoted) = (&caps[1], &caps[2]);
+            let value = WHITESPACE.replace_all(quoted[1..quoted.len() - 1].trim(), " ");
+            if name == "id" && !keep_id(&value) {
+                continue;
+            }
+            let quote = &quoted[..1];
+            cleaned.push_str(&format!(" {}={}{}{}", name, quote, value, quote));
+        }
+        cleaned.push_str(if tag.ends_with("/>") { "/>" } else { ">" });
+        cleaned
+    });
+
+    // Whitespace between tags goes, except inside <text>
+    let mut out = String::with_capacity(svg.len());
+    let mut last = 0;
+    for text in TEXT_ELEMENT.find_iter(&svg) {
+        out.push_str(&between_tags(&svg[last..text.start()]));
+        out.push_str(text.as_str());
+        last = text.end();
+    }
+    out.push_str(&between_tags(&svg[last..]));
+
+    let out = VERBATIM_PLACEHOLDER.replace_all(out.trim(), |placeholder: &Captures| {
+        verbatim[placeholder[1].parse::<usize>().unwrap()].clone()
+    });
+    let out = out.into_owned();
+    if source.contains("</svg") && !out.contains("</svg") {
+        return Err(String::from("cleaning it lost the closing </svg>"));
+    }
+    Ok(out)
+}
+
+/// Part of an SVG outside its `<text>` elements, without the whitespace between its tags.
+/// Whatever's around the part is a tag, so whitespace at its ends is between tags too.
+fn between_tags(part: &str) -> String {
+    let part = BETWEEN_TAGS.replace_all(part, "><");
+    let part = if part.trim_start().starts_with('<') { part.trim_start() } else { &part };
+    let part = if part.trim_end().ends_with('>') { part.trim_end() } else { part };
+    if part.trim().is_empty() { String::new() } else { part.to_string() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::MinifyEach;
+
+    fn minifier(css: &str) -> AssetMinifier {
+        AssetMinifier::new(Minify::Each(MinifyEach { html: true, css: true, js: true, svg: true, svg_ids: true }), [css]).unwrap()
+    }
+
+    fn minified(minifier: &AssetMinifier, path: &str, content: &str) -> Option<std::result::Result<String, String>> {
+        minifier.minify(Path::new(path), content.as_bytes())
+    }
+
+    #[test]
+    fn test_scripts_lose_comments_and_whitespace() {
+        let minifier = minifier("");
+        let script = "// Toggle the menu\nfunction toggleMenu(button) {\n    const menu = document.getElementById('menu');\n    menu.hidden = !menu.hidden;\n}\nwindow.toggleMenu = toggleMenu;\n";
+        let out = minified(&minifier, "js/menu.js", script).unwrap().unwrap();
+        assert!(out.len() < script.len() && !out.contains("Toggle the menu"), "{out}");
+        assert!(out.contains("function toggleMenu("), "top-level names are kept: {out}");
+
+        let module = "import { a } from './a.js';\n\nexport const b = a + 1; // one more\n";
+        let out = minified(&minifier, "js/b.js", module).unwrap().unwrap();
+        assert!(out.starts_with("import") && !out.contains("one more"), "{out}");
+
+        let Some(Err(reason)) = minified(&minifier, "js/broken.js", "function (") else {
+            panic!("a script that doesn't parse should be reported");
+        };
+        assert!(!reason.is_empty());
+        assert_eq!(minified(&minifier, "style.css", "a { }"), None);
+    }
+
+    #[test]
+    fn test_svgs_keep_the_ids_something_refers_to() {
+        let minifier = minifier(".hero { fill: url(#brand-gradient); }");
+        let svg = r##"<?xml version="1.0"?>
+<!-- Exported from a drawing app -->
+<svg xmlns="http://www.w3.org/2000/svg"
+     viewBox="0 0 10 10">
+  <metadata><rdf:RDF>lots</rdf:RDF></metadata>
+  <defs>
+    <linearGradient id="fade"><stop offset="0"/></linearGradient>
+    <linearGradient id="brand-gradient"><stop offset="1"/></linearGradient>
+  </defs>
+  <g id="layer1">
+    <rect id="rect42" fill="url(#fade)" d="M 0 0
+         L 10 10"/>
+    <text x="1">Hello <tspan>there</tspan> world</text>
+  </g>
+</svg>
+"##;
+        let out = minified(&minifier, "img/logo.svg", svg).unwrap().unwrap();
+        assert_eq!(
+            out,
+            r#"<?xml version="1.0"?><svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 10 10"><defs><linearGradient id="fade"><stop offset="0"/></linearGradient><linearGradient id="brand-gradient"><stop offset="1"/></linearGradient></defs><g><rect fill="url(#fade)" d="M 0 0 L 10 10"/><text x="1">Hello <tspan>there</tspan> world</text></g></svg>"#
+        );
+
+        // Pages use a sprite sheet's ids from outside
+        let sprites = "<svg>\n  <symbol id=\"home\"><path d=\"M0 0\"/></symbol>\n</svg>\n";
+        assert_eq!(minified(&minifier, "icons.svg", sprites).unwrap().unwrap(), r#"<svg><symbol id="home"><path d="M0 0"/></symbol></svg>"#);
+
+        assert!(minified(&minifier, "fake.svg", "not an svg at all").unwrap().is_err());
+    }
+
+    #[test]
+    fn test_svg_scripts_styles_and_cdata_are_kept_as_they_are() {
+        let minifier = minifier("");
+        let script = "<script>\n  // <b>not a tag</b>\n  if (a < b) { document.getElementById('dot').remove(); }\n</script>";
+        let style = "<style>\n  .a   >   .b { fill: red; } /* <!-- kept --> */\n</style>";
+        let cdata = "<![CDATA[\n  x  <  y\n]]>";
+        let svg = format!("<svg>\n  <!-- gone -->\n  {script}\n  {style}\n  <desc>{cdata}</desc>\n  <circle id=\"dot\" r=\"1\"/>\n</svg>\n");
+        let out = minified(&minifier, "img/dot.svg", &svg).unwrap().unwrap();
+        assert_eq!(out, format!("<svg>{script}{style}<desc>{cdata}</desc><circle id=\"dot\" r=\"1\"/></svg>"));
+    }
+
+    #[test]
+    fn test_svg_ids_are_only_dropped_when_asked() {
+        let svg = "<svg>\n  <g id=\"arrow\"><path d=\"M0 0\"/></g>\n</svg>\n";
+        // Pages can use any SVG's ids from outside, like `<use href="icons.svg#arrow">`
+        let keeping = AssetMinifier::new(Minify::Each(MinifyEach { html: true, css: true, js: false, svg: true, svg_ids: false }), []).unwrap();
+        assert_eq!(minified(&keeping, "icons.svg", svg).unwrap().unwrap(), r#"<svg><g id="arrow"><path d="M0 0"/></g></svg>"#);
+        assert_eq!(minified(&minifier(""), "icons.svg", svg).unwrap().unwrap(), r#"<svg><g><path d="M0 0"/></g></svg>"#);
+    }
+
+    #[test]
+    fn test_only_the_kinds_asked_for_are_minified() {
+        assert!(AssetMinifier::new(Minify::All(true), []).is_none());
+        let svg_only = AssetMinifier::new(Minify::Each(MinifyEach { html: true, css: true, js: false, svg: true, svg_ids: false }), []).unwrap();
+        assert_eq!(minified(&svg_only, "app.js", "// hi\nlet a = 1;\n"), None);
+        assert!(minified(&svg_only, "a.svg", "<svg>\n  <g/>\n</svg>").is_some());
+    }
+}
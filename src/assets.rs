@@ -0,0 +1,241 @@
+//! Which files in the site tree are published as static assets.
+//!
+//! `hugs dev` and `hugs build` both ask `skip_reason` before serving or copying a file, so a
+//! file is either available in both or in neither.
+//!
+//! Static files come from the `[paths] static` folder, or the site root without one. When pages
+//! are in a `[paths] content` folder of their own, files in it are published too, at the same URL
+//! paths as its pages, so an image can sit next to the post that shows it.
+
+use std::collections::HashSet;
+use std::path::{Component, Path, PathBuf};
+
+use walkdir::WalkDir;
+
+use crate::build_cache::CACHE_DIR;
+use crate::config::{AssetsConfig, PathsConfig};
+use crate::sri::LOCK_FILE;
+use crate::url::relative_path_to_url_string;
+
+/// Non-web files that stay out of the published site unless `[assets] include` lists them
+pub const DEFAULT_EXCLUDE: &[&str] = &[
+    // Scripts
+    "*.sh", "*.bash", "*.zsh", "*.fish", "*.ps1", "*.bat", "*.cmd", "*.py", "*.rb", "*.pl",
+    // Editor leftovers
+    "*~", "*.swp", "*.swo", "*.bak", "*.orig", "*.tmp", ".#*", "#*#",
+    // Version control and OS metadata
+    ".git", ".gitignore", ".gitattributes", ".hg", ".svn", ".DS_Store", "Thumbs.db",
+];
+
+/// The folders static files are published from, relative to the site root, in the order a URL
+/// path is looked up in them
+#[derive(Debug, Clone)]
+pub struct AssetRoots {
+    site_path: PathBuf,
+    roots: Vec<PathBuf>,
+}
+
+impl AssetRoots {
+    pub fn new(site_path: &Path, paths: &PathsConfig) -> Self {
+        let mut roots = vec![paths.static_dir().to_path_buf()];
+        if paths.content_dir() != paths.static_dir() {
+            roots.push(paths.content_dir().to_path_buf());
+        }
+        Self { site_path: site_path.to_path_buf(), roots }
+    }
+
+    /// Every file in the site, as when `[paths]` isn't set
+    pub fn whole_site(site_path: &Path) -> Self {
+        Self::new(site_path, &PathsConfig::default())
+    }
+
+    /// The file (relative to the site root) served at `relative`, a URL path without its leading
+    /// `/`: from the first folder that has it, or the static folder when none does
+    pub fn file(&self, relative: &Path) -> PathBuf {
+        self.roots
+            .iter()
+            .map(|root| root.join(relative))
+            .find(|file| self.site_path.join(file).is_file())
+            .unwrap_or_else(|| self.roots[0].join(relative))
+    }
+
+    /// [`AssetRoots::file`], inside the site
+    pub fn path(&self, relative: &Path) -> PathBuf {
+        self.site_path.join(self.file(relative))
+    }
+
+    /// Every file in the folders, with the URL path (without its leading `/`) it's served at.
+    /// A folder inside another is only walked as itself.
+    pub fn files(&self) -> Vec<(PathBuf, PathBuf)> {
+        let mut seen = HashSet::new();
+        let mut files = Vec::new();
+
+        for root in &self.roots {
+            let dir = self.site_path.join(root);
+            let nested: Vec<PathBuf> = self
+                .roots
+                .iter()
+                .filter(|other| *other != root && other.starts_with(root))
+                .map(|other| self.site_path.join(other))
+                .collect();
+
+            for entry in WalkDir::new(&dir)
+                .into_iter()
+                .filter_entry(|e| !nested.iter().any(|other| e.path() == other))
+                .filter_map(|e| e.ok())
+                .filter(|e| e.file_type().is_file())
+            {
+                let relative = entry.path().strip_prefix(&dir).unwrap_or(entry.path()).to_path_buf();
+                if seen.insert(relative.clone()) {
+                    files.push((entry.into_path(), relative));
+                }
+            }
+        }
+
+        files
+    }
+}
+
+/// Why a file in the site tree isn't published as a static asset
+#[derive(Debug, PartialEq)]
+pub enum SkipReason {
+    /// `_/`, config.toml and sri-lock.toml are site configuration, and `.hugs-cache/` is mine
+    SiteFile,
+    /// Markdown files are rendered as pages instead
+    Page,
+    /// An `[assets] exclude` pattern (or a default one) matched
+    Excluded(String),
+}
+
+/// Why `relative` (a path inside the site) isn't published, or `None` when it is
+pub fn skip_reason(relative: &Path, config: &AssetsConfig) -> Option<SkipReason> {
+    if relative.starts_with("_")
+        || relative.starts_with(CACHE_DIR)
+        || relative == Path::new("config.toml")
+        || relative == Path::new(LOCK_FILE)
+    {
+        return Some(SkipReason::SiteFile);
+    }
+    if relative.extension().is_some_and(|ext| ext == "md") {
+        return Some(SkipReason::Page);
+    }
+
+    let path = relative_path_to_url_string(relative);
+    if config.include.iter().any(|pattern| pattern_matches(pattern, &path)) {
+        return None;
+    }
+
+    DEFAULT_EXCLUDE
+        .iter()
+        .copied()
+        .chain(config.exclude.iter().map(String::as_str))
+        .find(|pattern| pattern_matches(pattern, &path))
+        .map(|pattern| SkipReason::Excluded(pattern.to_string()))
+}
+
+/// Patterns containing `/` match the whole site-relative path. Others match the file's name or
+/// any folder it's in, so `.git` covers everything inside `.git/`.
+pub fn pattern_matches(pattern: &str, path: &str) -> bool {
+    let pattern = pattern.trim_start_matches('/');
+    if pattern.contains('/') {
+        return glob_matches(pattern.as_bytes(), path.as_bytes());
+    }
+
+    Path::new(path).components().any(|component| match component {
+        Component::Normal(name) => glob_matches(pattern.as_bytes(), name.to_string_lossy().as_bytes()),
+        _ => false,
+    })
+}
+
+/// Glob matching where `*` matches within one path segment, `**` across segments and `?` one character
+fn glob_matches(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern {
+        [] => text.is_empty(),
+        [b'*', b'*', rest @ ..] => {
+            let rest = rest.strip_prefix(b"/").unwrap_or(rest);
+            (0..=text.len()).any(|i| glob_matches(rest, &text[i..]))
+        }
+        [b'*', rest @ ..] => (0..=text.len())
+            .take_while(|&i| i == 0 || text[i - 1] != b'/')
+            .any(|i| glob_matches(rest, &text[i..])),
+        [b'?', rest @ ..] => text.first().is_some_and(|&c| c != b'/') && glob_matches(rest, &text[1..]),
+        [c, rest @ ..] => text.first() == Some(c) && glob_matches(rest, &text[1..]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_skip_reason() {
+        let config = AssetsConfig {
+            include: vec![String::from("tools/install.sh")],
+            exclude: vec![String::from("draft-*"), String::from("notes/private/**")],
+        };
+        let skip = |path: &str| skip_reason(Path::new(path), &config);
+
+        assert_eq!(skip("_/theme.css"), Some(SkipReason::SiteFile));
+        assert_eq!(skip("config.toml"), Some(SkipReason::SiteFile));
+        assert_eq!(skip("sri-lock.toml"), Some(SkipReason::SiteFile));
+        assert_eq!(skip(".hugs-cache/readtime/0a1b.json"), Some(SkipReason::SiteFile));
+        assert_eq!(skip("blog/post.md"), Some(SkipReason::Page));
+        // Patterns never turn markdown into a static file
+        assert_eq!(skip_reason(Path::new("a.md"), &AssetsConfig { include: vec![String::from("*")], exclude: vec![] }), Some(SkipReason::Page));
+
+        assert_eq!(skip("scripts/build.sh"), Some(SkipReason::Excluded(String::from("*.sh"))));
+        assert_eq!(skip(".git/HEAD"), Some(SkipReason::Excluded(String::from(".git"))));
+        assert_eq!(skip("images/draft-cover.png"), Some(SkipReason::Excluded(String::from("draft-*"))));
+        assert_eq!(skip("notes/private/a/b.txt"), Some(SkipReason::Excluded(String::from("notes/private/**"))));
+
+        assert_eq!(skip("tools/install.sh"), None);
+        assert_eq!(skip("notes/ideas.txt"), None);
+        assert_eq!(skip("images/cover-draft.png"), None);
+        assert_eq!(skip("config.toml.example"), None);
+    }
+
+    #[test]
+    fn test_asset_roots_publish_the_static_folder_then_the_content_folder() {
+        let site = tempfile::tempdir().unwrap();
+        for file in ["static/logo.png", "static/content/x.txt", "content/logo.png", "content/blog/cover.png", "content/static/notes.txt"] {
+            let path = site.path().join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, file).unwrap();
+        }
+        let paths = PathsConfig { content: Some(PathBuf::from("content")), static_files: Some(PathBuf::from("static")) };
+        let roots = AssetRoots::new(site.path(), &paths);
+
+        let mut files: Vec<(PathBuf, PathBuf)> = roots
+            .files()
+            .into_iter()
+            .map(|(full, relative)| (full.strip_prefix(site.path()).unwrap().to_path_buf(), relative))
+            .collect();
+        files.sort();
+        let expected = [
+            ("content/blog/cover.png", "blog/cover.png"),
+            ("content/static/notes.txt", "static/notes.txt"),
+            ("static/content/x.txt", "content/x.txt"),
+            ("static/logo.png", "logo.png"),
+        ];
+        assert_eq!(files, expected.map(|(full, relative)| (PathBuf::from(full), PathBuf::from(relative))));
+
+        assert_eq!(roots.file(Path::new("logo.png")), Path::new("static/logo.png"));
+        assert_eq!(roots.file(Path::new("blog/cover.png")), Path::new("content/blog/cover.png"));
+        assert_eq!(roots.file(Path::new("missing.png")), Path::new("static/missing.png"));
+
+        // Without `[paths]`, the content folder is just a folder
+        let whole = AssetRoots::whole_site(site.path());
+        assert_eq!(whole.file(Path::new("content/logo.png")), Path::new("content/logo.png"));
+        assert_eq!(whole.files().len(), 5);
+    }
+
+    #[test]
+    fn test_glob_matches() {
+        assert!(glob_matches(b"*.sh", b"build.sh"));
+        assert!(!glob_matches(b"*.sh", b"dir/build.sh"));
+        assert!(glob_matches(b"**/*.sh", b"dir/sub/build.sh"));
+        assert!(glob_matches(b"**/*.sh", b"build.sh"));
+        assert!(glob_matches(b"file?.txt", b"file1.txt"));
+        assert!(!glob_matches(b"file?.txt", b"file10.txt"));
+    }
+}
@@ -0,0 +1,275 @@
+//! `auto_list: true` on a directory index page: a listing of the pages in that directory, added
+//! after the page's own content, so a section landing page needs no hand-written Jinja loop.
+//!
+//! Each entry is rendered with `_/list-item.md` when the site has one, and `DEFAULT_LIST_ITEM`
+//! otherwise. The entries are the pages directly in the directory, and the indexes of its
+//! subdirectories, but not the pages further down.
+
+use std::cmp::Ordering;
+
+use serde::Deserialize;
+use serde_yaml::Value as YamlValue;
+
+use crate::config::ListOrder;
+use crate::deps::DepRecorder;
+use crate::error::{HugsError, Result};
+use crate::readtime::{ReadingSpeed, ReadingTime, reading_time};
+use crate::run::{AppData, PageInfo, render_template_each};
+
+/// Markdown for one entry when the site has no `_/list-item.md`
+pub const DEFAULT_LIST_ITEM: &str = "- [{{ title }}](<{{ url }}>)\
+{% if date_parsed %} · {{ date_parsed | datefmt(\"%B %-d, %Y\") }}{% endif %} · {{ readtime }} min read\
+{% if description %}<br>{{ description }}{% endif %}";
+
+/// The listing settings in an index page's frontmatter
+#[derive(Debug, Default, Deserialize)]
+pub struct AutoListOptions {
+    #[serde(default)]
+    pub auto_list: bool,
+    /// Frontmatter key to sort by, `date` when unset
+    pub sort_by: Option<String>,
+    /// `asc` or `desc`; newest first when sorting by `date`, A to Z otherwise. Pages often use
+    /// `order` for a number of their own, which leaves the default.
+    #[serde(default, deserialize_with = "list_order")]
+    pub order: Option<ListOrder>,
+    /// `year`, or a frontmatter key, to put the entries under a heading for each value
+    pub group_by: Option<String>,
+}
+
+fn list_order<'de, D: serde::Deserializer<'de>>(deserializer: D) -> std::result::Result<Option<ListOrder>, D::Error> {
+    let value = YamlValue::deserialize(deserializer)?;
+    Ok(match value.as_str() {
+        Some("asc") => Some(ListOrder::Asc),
+        Some("desc") => Some(ListOrder::Desc),
+        _ => None,
+    })
+}
+
+/// One heading's worth of entries
+struct Group<'a> {
+    name: Option<String>,
+    pages: Vec<&'a PageInfo>,
+}
+
+/// Markdown listing the pages directly under the directory index at `index_url`, or nothing when
+/// there are none
+pub async fn render_auto_list(
+    index_url: &str,
+    options: &AutoListOptions,
+    app_data: &AppData,
+    deps: &DepRecorder,
+) -> Result<String> {
+    // Adding, removing or editing any page can change the listing
+    deps.record_pages();
+
+    let mut children: Vec<&PageInfo> = app_data
+        .pages
+        .iter()
+        .filter(|page| page.url.is_child_of(index_url))
+        .collect();
+    if children.is_empty() {
+        return Ok(String::new());
+    }
+    sort_pages(&mut children, options.sort_by.as_deref().unwrap_or("date"), options.order);
+    let groups = group_pages(children, options.group_by.as_deref());
+
+    let mut contexts = Vec::new();
+    for page in groups.iter().flat_map(|group| &group.pages) {
+        let mut ctx = serde_json::to_value(page).map_err(|e| HugsError::TemplateContext { reason: e.to_string() })?;
+        if let serde_json::Value::Object(map) = &mut ctx {
+            let time = page_reading_time(page, app_data).await;
+            map.insert("readtime".to_string(), time.minutes.into());
+            map.insert("readtime_method".to_string(), time.method.as_str().into());
+        }
+        contexts.push(ctx);
+    }
+
+    let (template, template_file) = match &app_data.list_item_template {
+        Some(template) => (template.as_str(), "_/list-item.md"),
+        None => (DEFAULT_LIST_ITEM, "the built-in list item"),
+    };
+    let items = render_template_each(template, contexts, &app_data.render_context(deps))
+    .map_err(|e| HugsError::template_render_named(
+        template_file,
+        template,
+        &e.error,
+        &e.hints,
+        e.macro_prefix_bytes,
+        e.macro_prefix_lines,
+    ))?;
+
+    let mut listing = String::new();
+    let mut items = items.into_iter();
+    for group in groups {
+        if let Some(name) = group.name {
+            listing.push_str(&format!("\n## {}\n\n", name));
+        }
+        for item in items.by_ref().take(group.pages.len()) {
+            listing.push_str(&item);
+            listing.push('\n');
+        }
+    }
+    Ok(listing)
+}
+
+/// Sort by a frontmatter key, newest first for `date` and A to Z for anything else unless
+/// `order` says otherwise. Pages without the key go last, and ties go by URL.
+pub fn sort_pages(pages: &mut [&PageInfo], key: &str, order: Option<ListOrder>) {
+    // `date` may be written many ways, but its parsed form always compares in time order
+    let field = if key == "date" { "date_parsed" } else { key };
+    let order = order.unwrap_or(if key == "date" { ListOrder::Desc } else { ListOrder::Asc });
+
+    pages.sort_by(|a, b| {
+        let by_key = match (field_of(a, field), field_of(b, field)) {
+            (Some(a), Some(b)) => match order {
+                ListOrder::Asc => compare_values(a, b),
+                ListOrder::Desc => compare_values(b, a),
+            },
+            // Pages without the key go last either way
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        };
+        by_key.then_with(|| a.url.cmp(&b.url))
+    });
+}
+
+/// Gather sorted `pages` under their `group_by` values, with the groups in the order their first
+/// page appears
+fn group_pages<'a>(pages: Vec<&'a PageInfo>, group_by: Option<&str>) -> Vec<Group<'a>> {
+    let Some(group_by) = group_by else {
+        return vec![Group { name: None, pages }];
+    };
+
+    let mut groups: Vec<Group<'a>> = Vec::new();
+    for page in pages {
+        let name = if group_by == "year" {
+            field_of(page, "date_parsed").and_then(YamlValue::as_str).and_then(|date| date.get(..4)).map(str::to_string)
+        } else {
+            field_of(page, group_by).and_then(value_to_heading)
+        };
+        let name = name.unwrap_or_else(|| String::from("Other"));
+
+        match groups.iter_mut().find(|group| group.name.as_deref() == Some(name.as_str())) {
+            Some(group) => group.pages.push(page),
+            None => groups.push(Group { name: Some(name), pages: vec![page] }),
+        }
+    }
+
+    groups
+}
+
+fn field_of<'a>(page: &'a PageInfo, key: &str) -> Option<&'a YamlValue> {
+    page.frontmatter.get(key).filter(|value| !value.is_null())
+}
+
+fn compare_values(a: &YamlValue, b: &YamlValue) -> Ordering {
+    match (a.as_f64(), b.as_f64()) {
+        (Some(a), Some(b)) => a.total_cmp(&b),
+        _ => value_to_heading(a).unwrap_or_default().cmp(&value_to_heading(b).unwrap_or_default()),
+    }
+}
+
+fn value_to_heading(value: &YamlValue) -> Option<String> {
+    match value {
+        YamlValue::String(s) => Some(s.clone()),
+        YamlValue::Number(n) => Some(n.to_string()),
+        YamlValue::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+/// Reading time of a page's source, the same as `readtime()` gives for its text
+async fn page_reading_time(page: &PageInfo, app_data: &AppData) -> ReadingTime {
+    let source = app_data.page_source(&page.file_path).await.ok();
+    let lang = page.frontmatter.get("lang").and_then(|lang| lang.as_str()).unwrap_or(&app_data.config.site.language);
+    reading_time(
+        source.as_ref().map_or("", |source| source.body()),
+        lang,
+        ReadingSpeed::from_config(&app_data.config.build),
+        &app_data.build_cache,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::run::resolve_path_to_doc;
+
+    async fn site(files: &[(&str, &str)]) -> (tempfile::TempDir, AppData) {
+        let dir = tempfile::tempdir().unwrap();
+        for (file, content) in [
+            ("_/header.md", "# Site"),
+            ("_/nav.md", "[Home](/)"),
+            ("_/footer.md", "Bye"),
+            ("_/theme.css", "body {}"),
+        ]
+        .iter()
+        .chain(files)
+        {
+            let path = dir.path().join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        }
+        let app_data = AppData::load(dir.path().to_path_buf(), "build").await.unwrap();
+        (dir, app_data)
+    }
+
+    async fn page_html(path: &str, app_data: &AppData) -> String {
+        let (_, html, _, _) = resolve_path_to_doc(path, app_data, &DepRecorder::default()).await.unwrap().unwrap();
+        html
+    }
+
+    #[tokio::test]
+    async fn test_listing_grouped_by_year() {
+        let (_dir, app_data) = site(&[
+            ("blog/index.md", "---\ntitle: Blog\nauto_list: true\ngroup_by: year\n---\nMy posts:"),
+            ("blog/first.md", "---\ntitle: First\ndate: 2023-05-01\ndescription: Where it began\n---\nHello"),
+            ("blog/second.md", "---\ntitle: Second\ndate: 2024-02-10\n---\nAgain"),
+            ("blog/third.md", "---\ntitle: Third & last\ndate: 2024-08-20\n---\nBye"),
+            ("blog/undated.md", "---\ntitle: Undated\n---\nWhenever"),
+            ("blog/drafts/index.md", "---\ntitle: Drafts\n---\nNot yet"),
+            ("blog/drafts/fourth.md", "---\ntitle: Fourth\ndate: 2024-09-01\n---\nSoon"),
+            ("about.md", "---\ntitle: About\n---\nNot in the blog"),
+        ])
+        .await;
+
+        let html = page_html("blog", &app_data).await;
+        let position = |needle: &str| html.find(needle).unwrap_or_else(|| panic!("{needle} missing from {html}"));
+
+        assert!(position("My posts:") < position("<h2>2024</h2>"));
+        assert!(position("<h2>2024</h2>") < position("Third &amp; last"));
+        assert!(position("Third &amp; last") < position(">Second<"));
+        assert!(position(">Second<") < position("<h2>2023</h2>"));
+        assert!(position("<h2>2023</h2>") < position(">First<"));
+        assert!(position(">First<") < position("<h2>Other</h2>"));
+        assert!(position("<h2>Other</h2>") < position(">Undated<"));
+
+        assert!(html.contains(r#"<a href="/blog/first">First</a> · May 1, 2023 · 1 min read<br>Where it began"#));
+        assert!(!html.contains("About"));
+        assert!(html.contains(">Drafts<") && !html.contains("Fourth"), "{html}");
+    }
+
+    #[tokio::test]
+    async fn test_directory_without_pages_lists_nothing() {
+        let (_dir, app_data) = site(&[("empty/index.md", "---\ntitle: Empty\nauto_list: true\n---\nNothing yet.")]).await;
+
+        let html = page_html("empty", &app_data).await;
+        assert_eq!(html.trim(), "<p>Nothing yet.</p>");
+    }
+
+    #[tokio::test]
+    async fn test_custom_list_item_and_sort() {
+        let (_dir, app_data) = site(&[
+            ("_/list-item.md", "* {{ title }} (#{{ order }})"),
+            ("guides/index.md", "---\ntitle: Guides\nauto_list: true\nsort_by: order\norder: desc\n---\n"),
+            ("guides/a.md", "---\ntitle: Setup\norder: 1\n---\n"),
+            ("guides/b.md", "---\ntitle: Deploy\norder: 10\n---\n"),
+            ("guides/c.md", "---\ntitle: Write\norder: 2\n---\n"),
+        ])
+        .await;
+
+        let html = page_html("guides/", &app_data).await;
+        assert!(html.contains("<li>Deploy (#10)</li>\n<li>Write (#2)</li>\n<li>Setup (#1)</li>"), "{html}");
+    }
+}
@@ -0,0 +1,288 @@
+//! Page weight budgets for `hugs build`.
+//!
+//! Once the site is written, I add up what each page makes a visitor download: its HTML file and
+//! the local stylesheets, scripts and images it loads, sized from the output directory. A page
+//! over the `[budgets]` limits, or the `budget:` in its own frontmatter, gets a warning listing its
+//! heaviest files. Assets shared by several of those pages count toward each of them, but they're
+//! listed once, in a warning of their own.
+
+use std::collections::BTreeMap;
+use std::path::{Component, Path, PathBuf};
+
+use percent_encoding::percent_decode_str;
+use serde_yaml::Value as YamlValue;
+
+use crate::config::BudgetsConfig;
+use crate::error::HugsError;
+use crate::output_scan::ScannedPage;
+
+/// How many of a page's own files its warning lists
+const BREAKDOWN_LEN: usize = 3;
+
+/// The limits one page is held to, in kilobytes
+#[derive(Debug, Default, PartialEq)]
+struct Budget {
+    html_kb: Option<u64>,
+    css_kb: Option<u64>,
+    page_total_kb: Option<u64>,
+}
+
+impl Budget {
+    /// The site's limits, with any the page sets in its `budget:` frontmatter taking their place
+    fn for_page(config: &BudgetsConfig, frontmatter: &YamlValue) -> Self {
+        let own = frontmatter.get("budget");
+        let limit = |key: &str, site: Option<u64>| own.and_then(|b| b.get(key)).and_then(YamlValue::as_u64).or(site);
+
+        Self {
+            html_kb: limit("html_kb", config.html_kb),
+            css_kb: limit("css_kb", config.css_kb),
+            page_total_kb: limit("page_total_kb", config.page_total_kb),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// One sentence per limit the page goes over
+    fn problems(&self, weight: &PageWeight) -> Vec<String> {
+        let checks = [
+            (self.page_total_kb, weight.total(), "The page loads", "in total", "page_total_kb"),
+            (self.html_kb, weight.html, "Its HTML is", "", "html_kb"),
+            (self.css_kb, weight.css(), "Its stylesheets come to", "", "css_kb"),
+        ];
+
+        checks
+            .into_iter()
+            .filter_map(|(limit, size, subject, qualifier, key)| {
+                let limit = limit?;
+                (size > limit * 1024).then(|| {
+                    let size = format!("{} KB", kb(size));
+                    let size = if qualifier.is_empty() { size } else { format!("{} {}", size, qualifier) };
+                    format!("{} {}, over the {} KB `{}` budget.", subject, size, limit, key)
+                })
+            })
+            .collect()
+    }
+}
+
+/// What a page makes a visitor download, in bytes
+struct PageWeight {
+    html: u64,
+    /// Root-relative path and size of each local asset the page loads
+    assets: Vec<(String, u64)>,
+}
+
+impl PageWeight {
+    /// Weigh a scanned page, sizing its assets from the output directory. Assets that weren't
+    /// written (typos, or files served from somewhere else) don't count.
+    fn measure(scan: &ScannedPage, output_path: &Path) -> Self {
+        let assets = scan
+            .subresources
+            .iter()
+            .filter_map(|path| {
+                let size = output_file(output_path, path)?.metadata().ok()?.len();
+                Some((path.clone(), size))
+            })
+            .collect();
+
+        Self {
+            html: scan.html_len,
+            assets,
+        }
+    }
+
+    fn css(&self) -> u64 {
+        self.assets.iter().filter(|(path, _)| path.ends_with(".css")).map(|(_, size)| size).sum()
+    }
+
+    fn total(&self) -> u64 {
+        self.html + self.assets.iter().map(|(_, size)| size).sum::<u64>()
+    }
+}
+
+/// The written file a root-relative URL path is served from
+fn output_file(output_path: &Path, path: &str) -> Option<PathBuf> {
+    let decoded = percent_decode_str(path.trim_start_matches('/')).decode_utf8().ok()?;
+    let relative = Path::new(decoded.as_ref());
+    if relative.components().any(|c| !matches!(c, Component::Normal(_))) {
+        return None;
+    }
+
+    let file = output_path.join(relative);
+    file.is_file().then_some(file)
+}
+
+/// Kilobytes, rounded up so a small file never shows as 0 KB
+fn kb(bytes: u64) -> u64 {
+    bytes.div_ceil(1024)
+}
+
+/// Weigh every page with a budget against it. Returns a warning per page that's over, then one
+/// for the assets more than one of those pages loads.
+pub fn check_budgets(scanned: &[ScannedPage], config: &BudgetsConfig, output_path: &Path) -> Vec<HugsError> {
+    let mut over = Vec::new();
+    for scan in scanned {
+        let budget = Budget::for_page(config, &scan.page.frontmatter);
+        if budget.is_empty() {
+            continue;
+        }
+        let weight = PageWeight::measure(scan, output_path);
+        let problems = budget.problems(&weight);
+        if !problems.is_empty() {
+            over.push((scan.page, weight, problems));
+        }
+    }
+
+    // Asset path -> (size, number of over-budget pages loading it)
+    let mut loaded_by: BTreeMap<&str, (u64, usize)> = BTreeMap::new();
+    for (_, weight, _) in &over {
+        for (path, size) in &weight.assets {
+            loaded_by.entry(path.as_str()).or_insert((*size, 0)).1 += 1;
+        }
+    }
+    loaded_by.retain(|_, (_, count)| *count > 1);
+
+    let mut warnings: Vec<HugsError> = over
+        .iter()
+        .map(|(page, weight, problems)| HugsError::PageOverBudget {
+            url: page.url.as_str().into(),
+            help_text: page_breakdown(weight, problems, &loaded_by),
+        })
+        .collect();
+
+    if !loaded_by.is_empty() {
+        let mut shared: Vec<_> = loaded_by.into_iter().collect();
+        shared.sort_by(|(a_path, (a_size, _)), (b_path, (b_size, _))| b_size.cmp(a_size).then(a_path.cmp(b_path)));
+
+        let lines: Vec<String> = shared
+            .iter()
+            .map(|(path, (size, count))| format!("{:>6} KB  {} (loaded by {} of them)", kb(*size), path, count))
+            .collect();
+        warnings.push(HugsError::SharedAssetsOverBudget {
+            help_text: format!(
+                "Every visitor downloads these with each page, so they count toward every over-budget page that loads them:\n{}",
+                lines.join("\n")
+            ),
+        });
+    }
+
+    warnings
+}
+
+/// What a page is over, and the heaviest of its own files. Shared assets are summed here and
+/// listed in their own warning.
+fn page_breakdown(weight: &PageWeight, problems: &[String], shared: &BTreeMap<&str, (u64, usize)>) -> String {
+    let mut own: Vec<(&str, u64)> = vec![("HTML", weight.html)];
+    let mut shared_size = 0;
+    for (path, size) in &weight.assets {
+        if shared.contains_key(path.as_str()) {
+            shared_size += size;
+        } else {
+            own.push((path, *size));
+        }
+    }
+    own.sort_by(|(a_path, a_size), (b_path, b_size)| b_size.cmp(a_size).then(a_path.cmp(b_path)));
+
+    let mut text = problems.join("\n");
+    text.push_str("\n\nHeaviest files:");
+    for (path, size) in own.iter().take(BREAKDOWN_LEN) {
+        text.push_str(&format!("\n{:>6} KB  {}", kb(*size), path));
+    }
+    if own.len() > BREAKDOWN_LEN {
+        let rest: u64 = own[BREAKDOWN_LEN..].iter().map(|(_, size)| size).sum();
+        text.push_str(&format!("\n{:>6} KB  {} more files", kb(rest), own.len() - BREAKDOWN_LEN));
+    }
+    if shared_size > 0 {
+        text.push_str(&format!("\n{:>6} KB  assets shared with other pages over budget (listed once below)", kb(shared_size)));
+    }
+    text.push_str("\n\nIf this page is meant to be heavy, give it its own `budget:` in frontmatter.");
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output_scan::scan_pages;
+    use crate::run::PageInfo;
+    use crate::url::SiteUrl;
+
+    fn page(url: &str, frontmatter: &str) -> PageInfo {
+        PageInfo {
+            url: SiteUrl::parse(url),
+            file_path: format!("{}.md", url.trim_matches('/')),
+            frontmatter: serde_yaml::from_str(frontmatter).unwrap(),
+        }
+    }
+
+    fn write(output: &Path, path: &str, contents: &str) {
+        let file = output.join(path);
+        std::fs::create_dir_all(file.parent().unwrap()).unwrap();
+        std::fs::write(file, contents).unwrap();
+    }
+
+    fn site() -> tempfile::TempDir {
+        let output = tempfile::tempdir().unwrap();
+        write(output.path(), "theme.css", &"a".repeat(3 * 1024));
+        write(output.path(), "images/hero.jpg", &"b".repeat(10 * 1024));
+        write(output.path(), "images/thumb.jpg", &"c".repeat(1024));
+        let head = r#"<link rel="stylesheet" href="/theme.css"><script src="https://cdn.example/x.js"></script>"#;
+        write(output.path(), "gallery/index.html", &format!(r#"{head}<img src="../images/hero.jpg"><img src="/images/hero.jpg"><a href="/about">x</a>"#));
+        write(output.path(), "about/index.html", &format!(r#"{head}<img src="/images/thumb.jpg">"#));
+        write(output.path(), "index.html", head);
+        output
+    }
+
+    #[test]
+    fn test_page_weight_counts_each_local_asset_once() {
+        let output = site();
+        let pages = [page("/gallery", "title: Gallery")];
+        let weight = PageWeight::measure(&scan_pages(&pages, output.path())[0], output.path());
+
+        let paths: Vec<&str> = weight.assets.iter().map(|(path, _)| path.as_str()).collect();
+        assert_eq!(paths, vec!["/theme.css", "/images/hero.jpg"]);
+        assert_eq!(weight.css(), 3 * 1024);
+        assert_eq!(weight.total(), weight.html + 13 * 1024);
+    }
+
+    #[test]
+    fn test_shared_assets_are_listed_once() {
+        let output = site();
+        let pages = [page("/", ""), page("/about", ""), page("/gallery", "")];
+        let config = BudgetsConfig { page_total_kb: Some(3), ..Default::default() };
+
+        let warnings = check_budgets(&scan_pages(&pages, output.path()), &config, output.path());
+        let urls: Vec<String> = warnings
+            .iter()
+            .filter_map(|w| match w {
+                HugsError::PageOverBudget { url, .. } => Some(url.0.clone()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(urls, vec!["/", "/about", "/gallery"]);
+
+        let Some(HugsError::PageOverBudget { help_text, .. }) = warnings.get(2) else { unreachable!() };
+        assert!(help_text.contains("/images/hero.jpg"), "{help_text}");
+        assert!(!help_text.contains("/theme.css"), "{help_text}");
+
+        let Some(HugsError::SharedAssetsOverBudget { help_text }) = warnings.last() else {
+            panic!("expected a shared assets warning, got {warnings:?}");
+        };
+        assert!(help_text.contains("/theme.css (loaded by 3 of them)"), "{help_text}");
+        assert!(!help_text.contains("hero"), "{help_text}");
+    }
+
+    #[test]
+    fn test_frontmatter_budget_overrides_the_site_budget() {
+        let output = site();
+        let config = BudgetsConfig { page_total_kb: Some(8), css_kb: Some(1), ..Default::default() };
+
+        let pages = [page("/gallery", "budget:\n  page_total_kb: 20")];
+        let warnings = check_budgets(&scan_pages(&pages, output.path()), &config, output.path());
+        let [HugsError::PageOverBudget { help_text, .. }] = warnings.as_slice() else {
+            panic!("expected one page over budget, got {warnings:?}");
+        };
+        assert!(help_text.contains("`css_kb`"), "{help_text}");
+        assert!(!help_text.contains("`page_total_kb`"), "{help_text}");
+    }
+}
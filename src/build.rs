@@ -1,114 +1,677 @@
-use std::path::PathBuf;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::time::Instant;
 
+use chrono::Utc;
+use miette::{Diagnostic, LabeledSpan, Severity, SourceCode};
 use tokio::task::JoinSet;
-use walkdir::WalkDir;
 
+use crate::asset_manifest::{ASSET_MANIFEST_FILE, AssetManifest};
+use crate::asset_minify::MinifyStats;
+use crate::assets::{SkipReason, skip_reason};
+use crate::budgets::check_budgets;
+use crate::build_cache::BuildCache;
+use crate::clean::remove_output_dir;
+use crate::config::{FailedPages, FeedConfig, SiteMetadata};
 use crate::console;
+use crate::dates::DateParser;
+use crate::deps::DepRecorder;
+use crate::duplicates::{PageFingerprint, find_duplicates};
 use crate::error::{HugsError, Result};
-use crate::feed::{collect_feed_items, generate_atom, generate_rss};
-use crate::minify::{minify_css_content, minify_html_content, MinifyConfig};
-use crate::run::{render_notfound_page, render_page_html, render_dynamic_page_html, resolve_path_to_doc, resolve_dynamic_doc, DynamicContext, AppData};
+use crate::feed::{FeedItem, FeedPage, collect_feed_items, feed_pages, generate_atom, generate_rss, render_item_contents};
+use crate::freshness::{find_stale_pages, git_commit_dates, stale_page_warnings, stale_pages_json};
+use crate::inline::inline_assets;
+use crate::lastmod::{HashingWriter, LastmodHistory, hash_html};
+use crate::mem_stats::{WrittenPage, format_size, print_mem_stats};
+use crate::minify::{minify_css_content, minify_html_content, minify_html_owned, MinifyConfig};
+use crate::orphans::{dynamic_orphan_warnings, dynamic_orphans_json, find_orphans, orphan_warnings, orphans_json, split_dynamic_orphans};
+use crate::notfound::relative_urls;
+use crate::headers::write_preload_headers;
+use crate::output_scan::{check_unbusted_stylesheets, scan_pages};
+use crate::protect::protect_for_build;
+use crate::redirects::{BuildManifest, detect_moved_pages, page_aliases, render_redirect_page};
+use crate::run::{render_notfound_page, resolve_page, page_for_request, AppData, PageInfo, PreparedPage};
+use crate::url::SiteUrl;
 use crate::sitemap::generate_sitemap;
+use crate::sri::add_subresource_integrity;
+use crate::text_output::{NormalizingWriter, output_text, write_text_output, write_text_output_atomically};
+use crate::xml_check::{check_well_formed, find_culprit};
+
+/// How `hugs build` reports what its optional checks found, like orphan pages
+#[derive(Debug, Clone, Copy, Default, PartialEq, clap::ValueEnum)]
+pub enum ReportFormat {
+    /// As warnings, along with everything else the build warns about
+    #[default]
+    Text,
+    /// As one JSON object on stdout, with a key for each report, for scripts
+    Json,
+}
+
+/// An optional check `hugs build --report` turns on, on top of the ones in `[build]`
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum Report {
+    /// Pages nothing links to, like `orphan_report`
+    Orphans,
+    /// Dynamic pages that make pages nothing links to, like `dynamic_orphan_report`
+    DynamicOrphans,
+    /// Pages that haven't changed in a while, like `freshness_report`
+    Freshness,
+}
+
+/// A warning the build ran into, and how it's shown: `--strict` shows every one as an error
+#[derive(Debug, Clone)]
+struct BuildWarning {
+    error: HugsError,
+    severity: Severity,
+    /// The diagnostic code the summary and grouping go by
+    code: String,
+    /// The file the warning points into, if it points into one
+    file: Option<String>,
+    /// Where in `file` it points, as offsets and lengths
+    spans: Vec<(usize, usize)>,
+    /// The page that ran into it, when `file` is one pages share, like a feed's item template
+    /// and the macros it calls
+    page: Option<String>,
+}
+
+impl BuildWarning {
+    fn new(error: HugsError, severity: Severity) -> Self {
+        Self::for_page(error, severity, None)
+    }
+
+    fn for_page(error: HugsError, severity: Severity, page: Option<String>) -> Self {
+        let code = error.code().map_or_else(|| String::from("hugs::warning"), |code| code.to_string());
+        let labels: Vec<LabeledSpan> = error.labels().map(Iterator::collect).unwrap_or_default();
+        let file = labels.first().and_then(|label| {
+            let contents = error.source_code()?.read_span(label.inner(), 0, 0).ok()?;
+            contents.name().map(String::from)
+        });
+        let spans = labels.iter().map(|label| (label.offset(), label.len())).collect();
+        Self { error, severity, code, file, spans, page }
+    }
+
+    /// The message and help with the file and page taken out, so the same mistake in two files
+    /// reads the same
+    fn normalized_message(&self) -> String {
+        let help = self.error.help().map(|help| help.to_string()).unwrap_or_default();
+        let mut message = format!("{}\n{}", self.error, help);
+        if let Some(file) = &self.file {
+            message = message.replace(file.as_str(), "<file>");
+        }
+        if let Some(page) = &self.page {
+            message = message.replace(page.as_str(), "<page>");
+        }
+        message.split_whitespace().collect::<Vec<_>>().join(" ")
+    }
+
+    /// The page the warning is about: the one that ran into it, or else the file it points into
+    fn page_or_file(&self) -> Option<&str> {
+        self.page.as_deref().or(self.file.as_deref())
+    }
+}
+
+impl fmt::Display for BuildWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.error.fmt(f)
+    }
+}
+
+impl std::error::Error for BuildWarning {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.error.source()
+    }
+}
+
+impl Diagnostic for BuildWarning {
+    fn code<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        self.error.code()
+    }
+
+    fn severity(&self) -> Option<Severity> {
+        Some(self.severity)
+    }
+
+    fn help<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        self.error.help()
+    }
+
+    fn url<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        self.error.url()
+    }
+
+    fn source_code(&self) -> Option<&dyn SourceCode> {
+        self.error.source_code()
+    }
+
+    fn labels(&self) -> Option<Box<dyn Iterator<Item = LabeledSpan> + '_>> {
+        self.error.labels()
+    }
+
+    fn related<'a>(&'a self) -> Option<Box<dyn Iterator<Item = &'a dyn Diagnostic> + 'a>> {
+        self.error.related()
+    }
+
+    fn diagnostic_source(&self) -> Option<&dyn Diagnostic> {
+        self.error.diagnostic_source()
+    }
+}
+
+/// Written in place of a page that failed to render, with `failed_pages = "placeholder"`
+const PLACEHOLDER_PAGE: &str = "<!doctype html><html><head><meta charset=\"utf-8\"><meta name=\"robots\" content=\"noindex\"><title>This page failed to build</title></head><body><h1>This page failed to build</h1><p>It will be back after the next build.</p></body></html>\n";
 
 /// Collected warnings during the build process
 #[derive(Default)]
 struct BuildWarnings {
-    warnings: Vec<HugsError>,
+    warnings: Vec<BuildWarning>,
+    /// Every warning fails the build once it's done, from `--strict` or `build.strict`
+    strict: bool,
+    /// What `console::warn` had printed when the build started, so the ones printed while loading
+    /// the site count too
+    printed_before: usize,
+    /// Pages that failed to render while the build kept going, shown apart from the warnings
+    failures: Vec<PageFailure>,
+    /// Show every warning in full, instead of the same warning once for all the files it's in
+    verbose: bool,
 }
 
 impl BuildWarnings {
+    fn new(strict: bool, verbose: bool) -> Self {
+        Self { warnings: Vec::new(), strict, printed_before: console::warnings_printed(), failures: Vec::new(), verbose }
+    }
+
     fn add(&mut self, error: HugsError) {
-        self.warnings.push(error);
+        let severity = if self.strict { Severity::Error } else { error.severity().unwrap_or(Severity::Warning) };
+        self.warnings.push(BuildWarning::new(error, severity));
+    }
+
+    /// A warning `page` ran into in a file other pages share too
+    fn add_for_page(&mut self, error: HugsError, page: String) {
+        let severity = if self.strict { Severity::Error } else { error.severity().unwrap_or(Severity::Warning) };
+        self.warnings.push(BuildWarning::for_page(error, severity, Some(page)));
+    }
+
+    /// Warnings printed as they happened instead of collected, like a page skipped while loading
+    fn printed(&self) -> usize {
+        console::warnings_printed().saturating_sub(self.printed_before)
     }
 
-    /// Display all collected warnings using miette's fancy formatting
+    /// How many warnings there were, counting the printed ones, when strict mode makes them fail
+    /// the build
+    fn promoted(&self) -> Option<usize> {
+        let count = self.warnings.len() + self.printed();
+        (self.strict && count > 0).then_some(count)
+    }
+
+    /// Diagnostic codes and how many warnings had each, most common first
+    fn summary(&self) -> Vec<(String, usize)> {
+        let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+        for warning in &self.warnings {
+            *counts.entry(warning.code.clone()).or_default() += 1;
+        }
+        if self.printed() > 0 {
+            counts.insert(String::from("printed while building"), self.printed());
+        }
+
+        let mut summary: Vec<(String, usize)> = counts.into_iter().collect();
+        summary.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+        summary
+    }
+
+    /// Display all collected warnings using miette's fancy formatting, then the pages that failed
     fn display(&self) {
-        if self.warnings.is_empty() {
+        self.display_warnings();
+        self.display_failures();
+    }
+
+    fn display_warnings(&self) {
+        if self.warnings.is_empty() && self.promoted().is_none() {
             return;
         }
 
         eprintln!();
-        let warning_word = if self.warnings.len() == 1 {
+        let count = self.warnings.len() + if self.strict { self.printed() } else { 0 };
+        let warning_word = if count == 1 {
             "warning"
         } else {
             "warnings"
         };
-        eprintln!(
-            "\x1b[33;1m⚠ Build completed with {} {}\x1b[0m\n",
-            self.warnings.len(),
-            warning_word
-        );
+        if self.strict {
+            eprintln!("\x1b[31;1m✗ Build completed with {} {}, which strict mode fails\x1b[0m\n", count, warning_word);
+        } else {
+            eprintln!(
+                "\x1b[33;1m⚠ Build completed with {} {}\x1b[0m\n",
+                count,
+                warning_word
+            );
+        }
 
-        for warning in &self.warnings {
-            let report = miette::Report::new(warning.clone());
-            eprintln!("{:?}", report);
+        let warnings = self.sorted_warnings();
+        if self.verbose {
+            for warning in warnings {
+                eprintln!("{:?}", miette::Report::new(warning));
+            }
+        } else {
+            for group in group_warnings(&warnings) {
+                eprintln!("{:?}", miette::Report::new(group.first().clone()));
+                if let Some(footer) = group.footer() {
+                    eprintln!("{}\n", footer);
+                }
+            }
+        }
+
+        if self.strict {
+            eprintln!("Warnings by code:");
+            for (code, count) in self.summary() {
+                eprintln!("{:>6}  {}", count, code);
+            }
+            eprintln!();
+        }
+    }
+
+    /// Every failed page's error, then the failures grouped by code with the files that had each
+    fn display_failures(&self) {
+        if self.failures.is_empty() {
+            return;
+        }
+
+        eprintln!();
+        let page_word = if self.failures.len() == 1 { "page" } else { "pages" };
+        eprintln!("\x1b[31;1m✗ {} {} failed to build\x1b[0m\n", self.failures.len(), page_word);
+
+        let mut by_code: BTreeMap<String, Vec<&str>> = BTreeMap::new();
+        for failure in &self.failures {
+            eprintln!("{}:", failure.file_path);
+            eprintln!("{:?}", miette::Report::new(failure.error.clone()));
+            let code = failure.error.code().map_or_else(|| String::from("(no code)"), |code| code.to_string());
+            by_code.entry(code).or_default().push(&failure.file_path);
+        }
+
+        let mut groups: Vec<(String, Vec<&str>)> = by_code.into_iter().collect();
+        groups.sort_by_key(|(_, files)| std::cmp::Reverse(files.len()));
+        eprintln!("Failures by code:");
+        for (code, mut files) in groups {
+            files.sort();
+            eprintln!("{:>6}  {}: {}", files.len(), code, files.join(", "));
+        }
+        eprintln!();
+    }
+
+    /// The warnings by file, then where in it they point, then code, so every build of the same
+    /// site lists them in the same order however its pages finished
+    fn sorted_warnings(&self) -> Vec<BuildWarning> {
+        let mut warnings = self.warnings.clone();
+        warnings.sort_by(|a, b| (&a.file, &a.spans, &a.code).cmp(&(&b.file, &b.spans, &b.code)));
+        warnings
+    }
+
+    /// Every warning on its own, for `--format json`
+    fn json(&self) -> serde_json::Value {
+        let warnings = self.sorted_warnings().into_iter().map(|warning| {
+            serde_json::json!({
+                "code": warning.code,
+                "file": warning.file,
+                "page": warning.page,
+                "message": warning.error.to_string(),
+                "severity": if warning.severity == Severity::Error { "error" } else { "warning" },
+            })
+        });
+        serde_json::Value::Array(warnings.collect())
+    }
+}
+
+/// The most files a grouped warning names before it stops listing them
+const GROUP_FILES_SHOWN: usize = 10;
+
+/// Warnings that are the same mistake, shown once with the files the rest were in
+#[derive(Debug)]
+struct WarningGroup<'a> {
+    /// In the order they came, the first being the one shown
+    warnings: Vec<&'a BuildWarning>,
+}
+
+impl<'a> WarningGroup<'a> {
+    fn first(&self) -> &'a BuildWarning {
+        self.warnings[0]
+    }
+
+    /// Whether `warning` is one more of this group's mistake. It isn't when the group already has
+    /// a warning in its file pointing somewhere else.
+    fn takes(&self, warning: &BuildWarning) -> bool {
+        !self.warnings.iter().any(|other| other.file.is_some() && other.file == warning.file && other.spans != warning.spans)
+    }
+
+    /// The pages the rest of the group were about, each once, leaving out the first one's
+    fn other_pages(&self) -> Vec<&'a str> {
+        let mut pages: Vec<&str> = Vec::new();
+        for page in self.warnings.iter().filter_map(|warning| warning.page_or_file()) {
+            if self.first().page_or_file() != Some(page) && !pages.contains(&page) {
+                pages.push(page);
+            }
+        }
+        pages
+    }
+
+    /// Says what else was like the warning shown, if anything was
+    fn footer(&self) -> Option<String> {
+        let files = self.other_pages();
+        if files.is_empty() {
+            let more = self.warnings.len() - 1;
+            let times = if more == 1 { "time" } else { "times" };
+            return (more > 0).then(|| format!("... and {} more {}", more, times));
+        }
+
+        let pages = if files.len() == 1 { "page" } else { "pages" };
+        let mut shown = files.iter().take(GROUP_FILES_SHOWN).copied().collect::<Vec<_>>().join(", ");
+        if files.len() > GROUP_FILES_SHOWN {
+            shown.push_str(", … (truncated)");
+        }
+        Some(format!("... and {} more {}: {}", files.len(), pages, shown))
+    }
+}
+
+/// Group warnings with the same code and message, in the order they first came. Two warnings in
+/// the same file only share a group when they point at the same place, so different mistakes in
+/// one file are each shown.
+fn group_warnings(warnings: &[BuildWarning]) -> Vec<WarningGroup<'_>> {
+    let mut groups: Vec<WarningGroup> = Vec::new();
+    let mut by_key: HashMap<(&str, String), Vec<usize>> = HashMap::new();
+
+    for warning in warnings {
+        let candidates = by_key.entry((warning.code.as_str(), warning.normalized_message())).or_default();
+        match candidates.iter().copied().find(|&index| groups[index].takes(warning)) {
+            Some(index) => groups[index].warnings.push(warning),
+            None => {
+                candidates.push(groups.len());
+                groups.push(WarningGroup { warnings: vec![warning] });
+            }
         }
     }
+    groups
+}
+
+/// How `hugs build` was asked to build, from its command line flags
+#[derive(Debug, Clone, Default)]
+pub struct BuildOptions {
+    /// An earlier build's `asset-manifest.json`, whose cache-busted names are kept for files that
+    /// haven't changed
+    pub reuse_asset_manifest: Option<PathBuf>,
+    /// Print the peak memory use and biggest pages afterwards
+    pub mem_stats: bool,
+    /// Clean an output directory that holds the site, once you confirm it
+    pub force: bool,
+    /// Fail the build if it had any warnings, after writing everything so they're all reported at once
+    pub strict: bool,
+    /// Carry on past pages that fail to render and report them all at the end, failing the build then
+    pub keep_going: bool,
+    /// Checks to turn on as if they were on in `[build]`
+    pub reports: Vec<Report>,
+    pub format: ReportFormat,
+    /// Keep the results of expensive template functions in `.hugs-cache/` for the next build
+    pub use_cache: bool,
+    /// Show every warning in full, instead of the same warning once for all the pages it's on
+    pub verbose: bool,
 }
 
-pub async fn run_build(site_path: PathBuf, output_path: PathBuf) -> Result<()> {
+/// Build the site into `output_path`
+pub async fn run_build(site_path: PathBuf, output_path: PathBuf, options: BuildOptions) -> Result<()> {
+    let BuildOptions { reuse_asset_manifest, mem_stats, force, strict, keep_going, reports, format, use_cache, verbose } = options;
     let build_start_instant = Instant::now();
 
     console::status("Building", format!("{} -> {}", site_path.display(), output_path.display()));
 
-    let mut warnings = BuildWarnings::default();
+    let mut warnings = BuildWarnings::new(strict, verbose);
 
     // Load site data (wrapped in Arc for parallel rendering)
-    let app_data = Arc::new(AppData::load(site_path, "build").await?);
-    let minify_config = MinifyConfig::new(app_data.config.build.minify);
+    let mut app_data = AppData::load(site_path, "build").await?;
+    if use_cache {
+        app_data.build_cache = BuildCache::open(&app_data.site_path);
+    }
+    let app_data = Arc::new(app_data);
+    warnings.strict |= app_data.config.build.strict;
+    let minify_config = MinifyConfig::from_build(&app_data.config.build);
+
+    // Read the asset manifest before cleaning too, since it may well live in the output directory
+    if let Some(path) = reuse_asset_manifest {
+        let manifest = AssetManifest::load(&path).await?;
+        app_data.cache_bust_registry.seed(manifest.reusable());
+    }
+
+    // Read the previous build's manifest before cleaning, to spot pages that moved
+    let previous_manifest = BuildManifest::load_previous(&app_data.site_path, &output_path).await;
+    let previous_lastmod = if use_cache { LastmodHistory::load_previous(&app_data.site_path, &output_path).await } else { None };
 
     // Clean/create output directory
-    clean_output_directory(&output_path).await?;
+    clean_output_directory(&app_data.site_path, &output_path, force).await?;
+
+    // Pages win over static and generated files that want the same output path
+    let mut page_outputs = PageOutputs::new(&app_data.pages);
 
     // Render all pages (in parallel)
-    let page_count =
-        render_all_pages(Arc::clone(&app_data), output_path.clone(), minify_config).await?;
+    let keep_going = keep_going || app_data.config.build.keep_going;
+    let RenderedPages { written: mut written_pages, fingerprints, failures } =
+        render_all_pages(Arc::clone(&app_data), output_path.clone(), minify_config, keep_going).await?;
+    warnings.failures = failures;
+
+    // A page that failed isn't on the site, so the sitemap, feeds and manifest leave it out
+    let app_data = if warnings.failures.is_empty() {
+        app_data
+    } else {
+        let failed: HashSet<&str> = warnings.failures.iter().map(|failure| failure.url.as_str()).collect();
+        let mut built = (*app_data).clone();
+        built.pages = Arc::new(app_data.pages.iter().filter(|page| !failed.contains(page.url.as_str())).cloned().collect());
+        Arc::new(built)
+    };
 
     // Render 404 page if it exists
-    render_404_page(&app_data, &output_path, &minify_config).await?;
+    render_404_page(&app_data, &output_path, &minify_config, &mut warnings).await?;
+
+    // Pin the external scripts and stylesheets the pages load, before their hashes are recorded
+    if app_data.config.sri.enabled {
+        let sri_warnings = add_subresource_integrity(
+            &app_data.pages,
+            &mut written_pages,
+            &app_data.site_path,
+            &output_path,
+            &app_data.config.sri,
+            &app_data.build_cache,
+            app_data.config.build.output_normalize,
+        )?;
+        for warning in sri_warnings {
+            warnings.add(warning);
+        }
+    }
+
+    // Write redirect pages for old URLs listed in `aliases`
+    write_alias_redirects(&app_data, &output_path, &mut page_outputs, &mut warnings).await?;
+
+    // Record this build's page URLs and warn about pages that moved since the last one
+    write_build_manifest(&app_data, previous_manifest.as_ref(), &mut warnings).await?;
+
+    // Note which pages changed since the last build, for the sitemap's lastmod
+    let lastmod_history = write_lastmod_history(&app_data.site_path, previous_lastmod.as_ref(), &written_pages, use_cache).await?;
 
     // Generate feeds
-    let feed_count = generate_feeds(&app_data, &output_path, &mut warnings).await?;
+    let feed_count = generate_feeds(&app_data, &output_path, &page_outputs, &mut warnings).await?;
 
     // Generate sitemap
-    let sitemap_generated = generate_sitemap_file(&app_data, &output_path, &mut warnings).await?;
+    let sitemap_generated = generate_sitemap_file(&app_data, &output_path, &lastmod_history, &page_outputs, &mut warnings).await?;
 
     // Copy static assets
-    let asset_count = copy_static_assets(&app_data.site_path, &output_path).await?;
+    let mut minify_stats = MinifyStats::default();
+    let asset_count = copy_static_assets(&app_data, &output_path, &page_outputs, &mut minify_stats, &mut warnings).await?;
 
     // Write cache-busted assets (from cache_bust() template function)
-    write_cache_busted_assets(&app_data, &output_path, &minify_config).await?;
+    write_cache_busted_assets(&app_data, &output_path, &minify_config, &page_outputs, &mut minify_stats, &mut warnings).await?;
+    if minify_stats.files > 0 {
+        console::status(
+            "Minified",
+            format!(
+                "{} scripts and SVGs, {} -> {} ({}% smaller)",
+                minify_stats.files,
+                format_size(minify_stats.before),
+                format_size(minify_stats.after),
+                100 - minify_stats.after * 100 / minify_stats.before.max(1)
+            ),
+        );
+    }
 
     // Write theme.css (only if not cache-busted)
-    write_theme_css(&app_data, &output_path, &minify_config).await?;
+    write_theme_css(&app_data, &output_path, &minify_config, &page_outputs, &mut warnings).await?;
+
+    // Record the cache-busted names for tools that run after the build
+    write_asset_manifest(&app_data, &output_path, &page_outputs, &mut warnings).await?;
+
+    if app_data.config.build.duplicate_detection {
+        for warning in find_duplicates(&fingerprints, app_data.config.build.duplicate_threshold) {
+            warnings.add(warning);
+        }
+    }
+
+    for warning in app_data.title_failures.warnings() {
+        warnings.add(warning);
+    }
+
+    if let Some((count, languages)) = app_data.unknown_languages.summary() {
+        warnings.add(HugsError::UnknownCodeLanguages {
+            count: count.into(),
+            languages: languages.join(", "),
+        });
+    }
+
+    // Read the written pages back once for the checks that look at what they load
+    let scanned = scan_pages(&app_data.pages, &output_path);
+
+    if app_data.config.build.warn_unbusted_css
+        && let Some(warning) = check_unbusted_stylesheets(&scanned, &app_data.cache_bust_registry)
+    {
+        warnings.add(warning);
+    }
+
+    if app_data.config.hosting.auto_preload_headers
+        && let Some(warning) = write_preload_headers(
+            &scanned,
+            &app_data.asset_roots,
+            &output_path,
+            &app_data.config.assets,
+            app_data.config.hosting.provider,
+            app_data.config.build.output_normalize,
+        )?
+    {
+        warnings.add(warning);
+    }
+
+    // Reports that go to stdout with `--format json` instead of being warnings, with the warnings
+    let mut report = serde_json::Map::new();
+
+    let build_config = &app_data.config.build;
+    let orphan_report = build_config.orphan_report || reports.contains(&Report::Orphans);
+    let dynamic_orphan_report = build_config.dynamic_orphan_report || reports.contains(&Report::DynamicOrphans);
+    if orphan_report || dynamic_orphan_report {
+        let orphans = find_orphans(&scanned, &app_data.pages, &app_data.nav_html);
+        let (mut orphans, dynamic_orphans) = if dynamic_orphan_report {
+            split_dynamic_orphans(orphans, &app_data.pages)
+        } else {
+            (orphans, Vec::new())
+        };
+        // A dynamic page's orphans are its own report when that's on, and in no other
+        if !orphan_report {
+            orphans.clear();
+        }
+
+        match format {
+            ReportFormat::Text => {
+                for warning in orphan_warnings(&orphans).into_iter().chain(dynamic_orphan_warnings(&dynamic_orphans)) {
+                    warnings.add(warning);
+                }
+            }
+            ReportFormat::Json => {
+                if orphan_report {
+                    report.insert(String::from("orphans"), orphans_json(&orphans));
+                }
+                if dynamic_orphan_report {
+                    report.insert(String::from("dynamic_orphans"), dynamic_orphans_json(&dynamic_orphans));
+                }
+            }
+        }
+    }
+
+    if build_config.freshness_report || reports.contains(&Report::Freshness) {
+        let commit_dates = git_commit_dates(&app_data.site_path);
+        let stale = find_stale_pages(&app_data.pages, &commit_dates, &build_config.freshness_buckets, Utc::now());
+        match format {
+            ReportFormat::Text => {
+                for warning in stale_page_warnings(&stale) {
+                    warnings.add(warning);
+                }
+            }
+            ReportFormat::Json => {
+                report.insert(String::from("freshness"), stale_pages_json(&stale));
+            }
+        }
+    }
+
+    // Weigh pages against their budgets, now that everything they load is written
+    let mut over_budget = 0;
+    for warning in check_budgets(&scanned, &app_data.config.budgets, &output_path) {
+        if matches!(warning, HugsError::PageOverBudget { .. }) {
+            over_budget += 1;
+        }
+        warnings.add(warning);
+    }
+    // Strict mode fails on them along with everything else, once the build is done
+    if format == ReportFormat::Json {
+        report.insert(String::from("warnings"), warnings.json());
+        println!("{}", serde_json::to_string_pretty(&report).expect("the report is plain JSON"));
+    }
+
+    if over_budget > 0 && app_data.config.budgets.fail && !warnings.strict && warnings.failures.is_empty() {
+        warnings.display();
+        return Err(HugsError::BudgetsExceeded { count: over_budget.into() });
+    }
+
+    // Forget results no recent build has used, now that this one has marked the ones it did
+    let cache = &app_data.build_cache;
+    cache.evict(app_data.config.build.cache_evict_after);
+    if let (reused @ 1.., computed) = cache.stats() {
+        console::status("Cached", format!("reused {} template function results, worked out {}", reused, computed));
+    }
 
     let sitemap_msg = if sitemap_generated { ", sitemap" } else { "" };
+    let failed_msg = if warnings.failures.is_empty() { String::new() } else { format!(" ({} failed)", warnings.failures.len()) };
     console::status(
         "Finished",
         &format!(
-            "{} pages, {} feeds{}, {} assets in {:.2}s",
-            page_count, feed_count, sitemap_msg, asset_count, build_start_instant.elapsed().as_secs_f64()
+            "{} pages{}, {} feeds{}, {} assets in {:.2}s",
+            written_pages.len(), failed_msg, feed_count, sitemap_msg, asset_count, build_start_instant.elapsed().as_secs_f64()
         )
     );
 
+    if mem_stats {
+        print_mem_stats(&written_pages);
+    }
+
     // Display any collected warnings with fancy formatting
     warnings.display();
 
+    if !warnings.failures.is_empty() {
+        return Err(HugsError::PagesFailed { count: warnings.failures.len().into() });
+    }
+
+    if let Some(count) = warnings.promoted() {
+        return Err(HugsError::StrictWarnings { count: count.into() });
+    }
+
     Ok(())
 }
 
-async fn clean_output_directory(output_path: &PathBuf) -> Result<()> {
+async fn clean_output_directory(site_path: &Path, output_path: &PathBuf, force: bool) -> Result<()> {
     if output_path.exists() {
         console::status("Cleaning", output_path.display());
-        tokio::fs::remove_dir_all(output_path)
-            .await
-            .map_err(|e| HugsError::CreateDir {
-                path: output_path.into(),
-                cause: e,
-            })?;
+        remove_output_dir(site_path, output_path, force).await?;
     }
     tokio::fs::create_dir_all(output_path)
         .await
@@ -119,88 +682,273 @@ async fn clean_output_directory(output_path: &PathBuf) -> Result<()> {
     Ok(())
 }
 
+/// Render and write every page. With `duplicate_detection` on, each page's content is
+/// fingerprinted on the way.
 async fn render_all_pages(
     app_data: Arc<AppData>,
     output_path: PathBuf,
     minify_config: MinifyConfig,
-) -> Result<usize> {
+    keep_going: bool,
+) -> Result<RenderedPages> {
     let page_count = app_data.pages.len();
     let progress = console::create_progress_bar(page_count as u64, "pages");
     let completed = Arc::new(AtomicUsize::new(0));
 
-    let mut join_set: JoinSet<Result<()>> = JoinSet::new();
+    let mut join_set: JoinSet<(PageInfo, Result<WrittenPageOutcome>)> = JoinSet::new();
 
     for page_info in app_data.pages.iter() {
         let app_data = Arc::clone(&app_data);
         let output_path = output_path.clone();
-        let url = page_info.url.clone();
-        let file_path = page_info.file_path.clone();
+        let page_info = page_info.clone();
         let completed = Arc::clone(&completed);
-        let dynamic_ctx = DynamicContext::from_page_info(page_info);
 
         join_set.spawn(async move {
-            let html_out = if let Some(ctx) = &dynamic_ctx {
-                let (frontmatter, doc_html, _resolvable_path, frontmatter_json) =
-                    resolve_dynamic_doc(&file_path, ctx, &app_data).await?;
-                render_dynamic_page_html(&frontmatter, &frontmatter_json, &doc_html, &url, &app_data, "")?
-            } else {
-                let request_path = url.trim_start_matches('/');
-                let (frontmatter, doc_html, resolvable_path, frontmatter_json) =
-                    resolve_path_to_doc(request_path, &app_data)
-                        .await?
-                        .ok_or_else(|| HugsError::PageResolve {
-                            url: url.clone().into(),
-                            file_path: file_path.clone().into(),
-                        })?;
-                render_page_html(&frontmatter, &frontmatter_json, &doc_html, &resolvable_path, &app_data, "")?
-            };
-
-            let final_html = minify_html_content(&html_out, &minify_config);
-
-            let output_file = url_to_output_path(&url, &output_path);
-            if let Some(parent) = output_file.parent() {
-                tokio::fs::create_dir_all(parent)
-                    .await
-                    .map_err(|e| HugsError::CreateDir {
-                        path: parent.into(),
-                        cause: e,
-                    })?;
-            }
-
-            tokio::fs::write(&output_file, final_html)
-                .await
-                .map_err(|e| HugsError::FileWrite {
-                    path: (&output_file).into(),
-                    cause: e,
-                })?;
-
+            let result = write_page(&page_info, &app_data, &output_path, &minify_config).await;
             completed.fetch_add(1, Ordering::Relaxed);
-            Ok(())
+            (page_info, result)
         });
     }
 
+    let mut rendered = RenderedPages { written: Vec::with_capacity(page_count), fingerprints: Vec::new(), failures: Vec::new() };
     while let Some(result) = join_set.join_next().await {
         progress.set_position(completed.load(Ordering::Relaxed) as u64);
-        result.map_err(|e| HugsError::TaskJoin {
+        let (page_info, result) = result.map_err(|e| HugsError::TaskJoin {
             reason: e.to_string(),
-        })??;
+        })?;
+        match result {
+            Ok((page, fingerprint)) => {
+                rendered.written.push(page);
+                rendered.fingerprints.extend(fingerprint);
+            }
+            // The rest of the pages carry on, and the failures are reported together at the end
+            Err(error) if keep_going => {
+                if app_data.config.build.failed_pages == FailedPages::Placeholder {
+                    write_placeholder_page(&page_info, &output_path).await?;
+                }
+                rendered.failures.push(PageFailure { url: page_info.url.to_string(), file_path: page_info.file_path, error });
+            }
+            Err(error) => return Err(error),
+        }
     }
 
     console::progress_finish(&progress);
-    Ok(page_count)
+    // Pages finish in whatever order they finish; what comes after shouldn't depend on it
+    rendered.written.sort_by(|a, b| a.url.cmp(&b.url));
+    rendered.failures.sort_by(|a, b| a.file_path.cmp(&b.file_path));
+    Ok(rendered)
 }
 
-fn url_to_output_path(url: &str, output_path: &PathBuf) -> PathBuf {
-    if url == "/" {
-        output_path.join("index.html")
-    } else if url.ends_with('/') {
-        // /blog/ -> dist/blog/index.html
-        let dir = url.trim_matches('/');
-        output_path.join(dir).join("index.html")
+/// A written page, with its fingerprint when duplicate detection is on
+type WrittenPageOutcome = (WrittenPage, Option<PageFingerprint>);
+
+/// What `render_all_pages` wrote, and the pages it couldn't when the build keeps going
+struct RenderedPages {
+    written: Vec<WrittenPage>,
+    fingerprints: Vec<PageFingerprint>,
+    failures: Vec<PageFailure>,
+}
+
+/// A page that failed to render during a build that keeps going
+struct PageFailure {
+    url: String,
+    file_path: String,
+    error: HugsError,
+}
+
+/// Render one page and write it to its place in `output_path`
+async fn write_page(
+    page_info: &PageInfo,
+    app_data: &Arc<AppData>,
+    output_path: &Path,
+    minify_config: &MinifyConfig,
+) -> Result<WrittenPageOutcome> {
+    let prepared = prepare_page(page_info, app_data).await?;
+    let fingerprint = app_data
+        .config
+        .build
+        .duplicate_detection
+        .then(|| PageFingerprint::new(page_info, prepared.content()))
+        .flatten();
+    let streamed = prepared.content_len() as u64 >= app_data.config.build.stream_pages_over_kb.saturating_mul(1024);
+
+    let normalize = app_data.config.build.output_normalize;
+    let output_file = create_output_file_dir(&page_info.url, output_path).await?;
+
+    // A huge page goes straight to disk, rather than sitting in memory as the rendered
+    // page and again as the minified one
+    let (bytes, hash) = if streamed {
+        // Don't leave half a page behind for the deploy to pick up
+        match stream_page(prepared, Arc::clone(app_data), output_file.clone(), normalize).await {
+            Ok(written) => written,
+            Err(e) => {
+                let _ = tokio::fs::remove_file(&output_file).await;
+                return Err(e);
+            }
+        }
     } else {
-        // /about -> dist/about/index.html
-        let dir = url.trim_start_matches('/');
-        output_path.join(dir).join("index.html")
+        let final_html = minify_html_owned(prepared.render(app_data)?, minify_config);
+        let final_html = output_text(&final_html, normalize);
+        write_text_output(&output_file, &final_html, false).await?;
+        (final_html.len() as u64, hash_html(final_html.as_bytes()))
+    };
+
+    Ok((WrittenPage { url: page_info.url.to_string(), bytes, streamed, hash }, fingerprint))
+}
+
+/// Render a page straight into `output_file`, normalized on the way when `normalize` is on.
+/// Returns how many bytes were written and their hash. The writes block, so it runs on tokio's
+/// blocking threads rather than holding up the pages rendering next to it.
+async fn stream_page(prepared: PreparedPage, app_data: Arc<AppData>, output_file: PathBuf, normalize: bool) -> Result<(u64, String)> {
+    let written = tokio::task::spawn_blocking(move || -> std::result::Result<(u64, String), Box<HugsError>> {
+        let write_error = |e| HugsError::FileWrite {
+            path: output_file.as_path().into(),
+            cause: e,
+        };
+
+        let file = std::fs::File::create(&output_file).map_err(write_error)?;
+        let mut out = NormalizingWriter::new(HashingWriter::new(BufWriter::new(file)), normalize);
+        prepared.render_to(&app_data, &mut out)?;
+        let mut out = out.finish().map_err(write_error)?;
+        out.flush().map_err(write_error)?;
+        let (_, bytes, hash) = out.finish();
+        Ok((bytes, hash))
+    })
+    .await
+    .map_err(|e| HugsError::TaskJoin { reason: e.to_string() })?;
+    written.map_err(|e| *e)
+}
+
+/// Where the page at `url` is written, with its folder created
+async fn create_output_file_dir(url: &SiteUrl, output_path: &Path) -> Result<PathBuf> {
+    let output_file = url_to_output_path(url, output_path);
+    if let Some(parent) = output_file.parent() {
+        tokio::fs::create_dir_all(parent)
+            .await
+            .map_err(|e| HugsError::CreateDir {
+                path: parent.into(),
+                cause: e,
+            })?;
+    }
+    Ok(output_file)
+}
+
+/// Stand in for a page that failed to render, so links to it don't 404
+async fn write_placeholder_page(page_info: &PageInfo, output_path: &Path) -> Result<()> {
+    let output_file = create_output_file_dir(&page_info.url, output_path).await?;
+    write_text_output(&output_file, PLACEHOLDER_PAGE, false).await
+}
+
+/// Render one page the way the build writes it, before minification
+pub async fn render_page(page_info: &PageInfo, app_data: &AppData) -> Result<String> {
+    prepare_page(page_info, app_data).await?.render(app_data)
+}
+
+/// Render one page up to its root template
+pub async fn prepare_page(page_info: &PageInfo, app_data: &AppData) -> Result<PreparedPage> {
+    let deps = DepRecorder::default();
+    let mut page = resolve_page(page_info.url.as_str().trim_start_matches('/'), app_data, &deps)
+        .await?
+        .ok_or_else(|| HugsError::PageResolve {
+            url: page_info.url.to_string().into(),
+            file_path: page_info.file_path.clone().into(),
+        })?;
+    page.content = protect_for_build(&page.frontmatter, page.content, &page_info.file_path)?;
+    page.prepare(app_data, "", &deps)
+}
+
+/// Render the page at `url` into one self-contained HTML file at `output_file`
+pub async fn run_single_file_build(site_path: PathBuf, url: &str, output_file: PathBuf) -> Result<()> {
+    let build_start_instant = Instant::now();
+
+    console::status("Building", format!("{} {} -> {}", site_path.display(), url, output_file.display()));
+
+    let app_data = AppData::load(site_path, "build").await?;
+    let minify_config = MinifyConfig::from_build(&app_data.config.build);
+
+    let page_info = page_for_request(&app_data.pages, url.trim_end_matches('/'))
+        .ok_or_else(|| HugsError::SingleFilePageNotFound { url: url.into() })?;
+
+    let html_out = render_page(page_info, &app_data).await?;
+    let (html_out, inline_warnings) = inline_assets(&html_out, page_info.url.as_str(), &app_data)?;
+    let final_html = minify_html_content(&html_out, &minify_config);
+
+    if let Some(parent) = output_file.parent().filter(|p| !p.as_os_str().is_empty()) {
+        tokio::fs::create_dir_all(parent)
+            .await
+            .map_err(|e| HugsError::CreateDir {
+                path: parent.into(),
+                cause: e,
+            })?;
+    }
+    write_text_output(&output_file, &final_html, app_data.config.build.output_normalize).await?;
+
+    console::status(
+        "Finished",
+        format!(
+            "{} ({} KB) in {:.2}s",
+            output_file.display(),
+            final_html.len().div_ceil(1024),
+            build_start_instant.elapsed().as_secs_f64()
+        ),
+    );
+
+    let mut warnings = BuildWarnings::default();
+    for warning in inline_warnings {
+        warnings.add(warning);
+    }
+    warnings.display();
+
+    Ok(())
+}
+
+fn url_to_output_path(url: &SiteUrl, output_path: &Path) -> PathBuf {
+    output_path.join(url.output_path())
+}
+
+/// Output paths taken by rendered pages, and then by the redirects at their aliases. A page wins
+/// any collision with a static or generated file, the same way `hugs dev` serves the page first.
+struct PageOutputs {
+    /// Page output file -> source file of the page, or of the page an alias redirects to
+    files: HashMap<PathBuf, String>,
+    /// Directories holding page output files -> source file of a page inside
+    dirs: HashMap<PathBuf, String>,
+}
+
+impl PageOutputs {
+    fn new(pages: &[PageInfo]) -> Self {
+        let mut outputs = Self { files: HashMap::new(), dirs: HashMap::new() };
+        for page in pages {
+            outputs.claim(&page.url.output_path(), page.file_path.clone());
+        }
+        outputs
+    }
+
+    /// Take `file` for `owner`, so later files at the same path collide with it
+    fn claim(&mut self, file: &Path, owner: String) {
+        for dir in file.ancestors().skip(1).filter(|d| !d.as_os_str().is_empty()) {
+            self.dirs.entry(dir.to_path_buf()).or_insert_with(|| owner.clone());
+        }
+        self.files.insert(file.to_path_buf(), owner);
+    }
+
+    /// Whether writing `relative` would clash with a page, either landing on its file or on one
+    /// of the directories it lives in. Records a warning naming both sources if it does.
+    fn collides(&self, relative: &Path, other: &str, warnings: &mut BuildWarnings) -> bool {
+        let page = self.files.get(relative).or_else(|| self.dirs.get(relative)).or_else(|| {
+            // A page file where this one needs a directory
+            relative.ancestors().skip(1).find_map(|dir| self.files.get(dir))
+        });
+
+        let Some(page) = page else {
+            return false;
+        };
+
+        warnings.add(HugsError::OutputCollision {
+            path: relative.into(),
+            page: page.as_str().into(),
+            other: other.to_string(),
+        });
+        true
     }
 }
 
@@ -208,39 +956,155 @@ async fn render_404_page(
     app_data: &AppData,
     output_path: &PathBuf,
     minify_config: &MinifyConfig,
+    warnings: &mut BuildWarnings,
 ) -> Result<()> {
     if let Some(html) = render_notfound_page(app_data, "").await {
+        // The page's own links are made absolute as it renders, so these come from what it shares
+        let relative = relative_urls(&html);
+        if !relative.is_empty() {
+            warnings.add(HugsError::NotFoundRelativeUrls {
+                output: "404.html".into(),
+                urls: relative.iter().map(|url| format!("`{}`", url)).collect::<Vec<_>>().join(", "),
+                help_text: String::from(
+                    "I point the links in `[404].md` at the site root myself, so these come from the header, nav, footer or `head_extra` in config.toml. Start them with `/`, like `/theme.css`, so they work from any path.",
+                ),
+            });
+        }
+
         let final_html = minify_html_content(&html, minify_config);
         let output_file = output_path.join("404.html");
         console::status("Rendering", "404.html");
-        tokio::fs::write(&output_file, final_html)
+        write_text_output(&output_file, &final_html, app_data.config.build.output_normalize).await?;
+    }
+    Ok(())
+}
+
+/// Write a redirect page at each alias. Aliases were checked to stay inside the site when the
+/// pages loaded. Each one claims its output file, so a later static or generated file that wants
+/// it is skipped with a warning, and so is a second alias at the same place.
+async fn write_alias_redirects(
+    app_data: &AppData,
+    output_path: &Path,
+    page_outputs: &mut PageOutputs,
+    warnings: &mut BuildWarnings,
+) -> Result<()> {
+    for page in app_data.pages.iter() {
+        for alias in page_aliases(&page.frontmatter) {
+            // In the `html` style the old URL is an HTML file too, old.html rather than old/index.html
+            let alias_url = SiteUrl::parse(&alias).in_style(app_data.config.build.url_style);
+            if app_data.pages.iter().any(|p| p.url.same_page(alias_url.as_str())) {
+                console::warn(format!(
+                    "{} lists the alias {}, but a page already lives there, skipping",
+                    page.file_path, alias
+                ));
+                continue;
+            }
+
+            let relative = alias_url.output_path();
+            let owner = format!("{} (its alias {})", page.file_path, alias);
+            if page_outputs.collides(&relative, &format!("the alias {} of {}", alias, page.file_path), warnings) {
+                continue;
+            }
+            page_outputs.claim(&relative, owner);
+
+            let html = render_redirect_page(&page.url, &app_data.config.site)?;
+            let output_file = output_path.join(&relative);
+            if let Some(parent) = output_file.parent() {
+                tokio::fs::create_dir_all(parent)
+                    .await
+                    .map_err(|e| HugsError::CreateDir {
+                        path: parent.into(),
+                        cause: e,
+                    })?;
+            }
+
+            console::status("Redirecting", format!("{} -> {}", alias, page.url));
+            write_text_output(&output_file, &html, app_data.config.build.output_normalize).await?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn write_build_manifest(
+    app_data: &AppData,
+    previous: Option<&BuildManifest>,
+    warnings: &mut BuildWarnings,
+) -> Result<()> {
+    let manifest = BuildManifest::collect(app_data).await;
+
+    if let Some(previous) = previous {
+        for warning in detect_moved_pages(previous, &manifest, &app_data.pages) {
+            warnings.add(warning);
+        }
+    }
+
+    // Beside the site rather than in the output, which is published and shouldn't name source files
+    let manifest_path = BuildManifest::path(&app_data.site_path);
+    if let Some(parent) = manifest_path.parent() {
+        tokio::fs::create_dir_all(parent)
+            .await
+            .map_err(|e| HugsError::CreateDir {
+                path: parent.into(),
+                cause: e,
+            })?;
+    }
+    write_text_output(&manifest_path, &manifest.to_json(), false).await
+}
+
+/// This build's lastmod history, kept in `.hugs-cache/` for the next build unless `save` is off
+async fn write_lastmod_history(
+    site_path: &Path,
+    previous: Option<&LastmodHistory>,
+    written_pages: &[WrittenPage],
+    save: bool,
+) -> Result<LastmodHistory> {
+    let hashes = written_pages.iter().map(|page| (page.url.clone(), page.hash.clone()));
+    let history = LastmodHistory::update(previous, hashes, chrono::Utc::now());
+    if !save {
+        return Ok(history);
+    }
+
+    let history_path = LastmodHistory::path(site_path);
+    if let Some(parent) = history_path.parent() {
+        tokio::fs::create_dir_all(parent)
             .await
-            .map_err(|e| HugsError::FileWrite {
-                path: (&output_file).into(),
+            .map_err(|e| HugsError::CreateDir {
+                path: parent.into(),
                 cause: e,
             })?;
     }
-    Ok(())
+    write_text_output(&history_path, &history.to_json(), false).await?;
+    Ok(history)
 }
 
-async fn copy_static_assets(site_path: &PathBuf, output_path: &PathBuf) -> Result<usize> {
+async fn copy_static_assets(
+    app_data: &AppData,
+    output_path: &Path,
+    page_outputs: &PageOutputs,
+    stats: &mut MinifyStats,
+    warnings: &mut BuildWarnings,
+) -> Result<usize> {
     let mut count = 0;
+    let mut excluded = 0;
 
-    for entry in WalkDir::new(site_path)
-        .into_iter()
-        .filter_map(|e| e.ok())
-        .filter(|e| e.file_type().is_file())
-    {
-        let path = entry.path();
-        let relative = path.strip_prefix(site_path).unwrap_or(path);
+    for (path, relative) in app_data.asset_roots.files() {
+        let (path, relative) = (path.as_path(), relative.as_path());
 
-        // Skip _ directory
-        if relative.starts_with("_") {
+        // Site files, pages, `[assets] exclude` matches and what git ignores, the same as `hugs dev` skips
+        if app_data.git_ignored.contains(path) {
             continue;
         }
+        match skip_reason(relative, &app_data.config.assets) {
+            None => {}
+            Some(SkipReason::Excluded(_)) => {
+                excluded += 1;
+                continue;
+            }
+            Some(_) => continue,
+        }
 
-        // Skip markdown files (they're rendered as pages)
-        if path.extension().is_some_and(|ext| ext == "md") {
+        if page_outputs.collides(relative, &format!("the static file {}", relative.display()), warnings) {
             continue;
         }
 
@@ -255,49 +1119,49 @@ async fn copy_static_assets(site_path: &PathBuf, output_path: &PathBuf) -> Resul
                 })?;
         }
 
-        tokio::fs::copy(path, &output_file)
-            .await
-            .map_err(|e| HugsError::CopyFile {
-                src: path.into(),
-                dest: (&output_file).into(),
-                cause: e,
-            })?;
+        publish_asset(app_data, path, relative, &output_file, stats, warnings).await?;
         count += 1;
     }
 
     if count > 0 {
         console::status("Copying", format!("{} static assets", count));
     }
+    if excluded > 0 {
+        console::status("Skipped", format!("{} files excluded by [assets] in config.toml", excluded));
+    }
 
     Ok(count)
 }
 
 async fn write_theme_css(
     app_data: &AppData,
-    output_path: &PathBuf,
+    output_path: &Path,
     minify_config: &MinifyConfig,
+    page_outputs: &PageOutputs,
+    warnings: &mut BuildWarnings,
 ) -> Result<()> {
     let entries = app_data.cache_bust_registry.entries();
     if entries.contains_key("/theme.css") {
         return Ok(());
     }
 
+    if page_outputs.collides(Path::new("theme.css"), "your theme stylesheet (_/theme.css)", warnings) {
+        return Ok(());
+    }
+
     console::status("Writing", "theme.css");
     let css_path = output_path.join("theme.css");
     let final_css = minify_css_content(&app_data.theme_css, minify_config);
-    tokio::fs::write(&css_path, final_css)
-        .await
-        .map_err(|e| HugsError::FileWrite {
-            path: (&css_path).into(),
-            cause: e,
-        })?;
-    Ok(())
+    write_text_output(&css_path, &final_css, app_data.config.build.output_normalize).await
 }
 
 async fn write_cache_busted_assets(
     app_data: &AppData,
-    output_path: &PathBuf,
+    output_path: &Path,
     minify_config: &MinifyConfig,
+    page_outputs: &PageOutputs,
+    stats: &mut MinifyStats,
+    warnings: &mut BuildWarnings,
 ) -> Result<()> {
     let entries = app_data.cache_bust_registry.entries();
 
@@ -308,30 +1172,23 @@ async fn write_cache_busted_assets(
     for (original_path, hashed_path) in entries {
         let hashed_filename = hashed_path.trim_start_matches('/');
 
+        let source = format!("the cache-busted copy of {}", original_path);
+        if page_outputs.collides(Path::new(hashed_filename), &source, warnings) {
+            continue;
+        }
+
         if original_path == "/theme.css" {
             let dest = output_path.join(hashed_filename);
             console::status("Writing", &hashed_path);
             let final_css = minify_css_content(&app_data.theme_css, minify_config);
-            tokio::fs::write(&dest, final_css)
-                .await
-                .map_err(|e| HugsError::FileWrite {
-                    path: (&dest).into(),
-                    cause: e,
-                })?;
+            write_text_output(&dest, &final_css, app_data.config.build.output_normalize).await?;
         } else if original_path == "/highlight.css" {
             let dest = output_path.join(hashed_filename);
             console::status("Writing", &hashed_path);
             let final_css = minify_css_content(&app_data.highlight_css, minify_config);
-            tokio::fs::write(&dest, final_css)
-                .await
-                .map_err(|e| HugsError::FileWrite {
-                    path: (&dest).into(),
-                    cause: e,
-                })?;
+            write_text_output(&dest, &final_css, app_data.config.build.output_normalize).await?;
         } else {
-            let src = app_data
-                .site_path
-                .join(original_path.trim_start_matches('/'));
+            let src = app_data.asset_roots.path(Path::new(original_path.trim_start_matches('/')));
             let dest = output_path.join(hashed_filename);
 
             if let Some(parent) = dest.parent() {
@@ -344,22 +1201,91 @@ async fn write_cache_busted_assets(
             }
 
             console::status("Writing", &hashed_path);
-            tokio::fs::copy(&src, &dest)
-                .await
-                .map_err(|e| HugsError::CopyFile {
-                    src: (&src).into(),
-                    dest: (&dest).into(),
-                    cause: e,
-                })?;
+            publish_asset(app_data, &src, Path::new(original_path.trim_start_matches('/')), &dest, stats, warnings).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Copy the static file at `src` to `dest`, minified when `[build.minify]` asks for its kind. One
+/// that doesn't minify is copied as it is, with a warning.
+async fn publish_asset(
+    app_data: &AppData,
+    src: &Path,
+    relative: &Path,
+    dest: &Path,
+    stats: &mut MinifyStats,
+    warnings: &mut BuildWarnings,
+) -> Result<()> {
+    if let Some(minifier) = app_data.asset_minifier.as_ref().filter(|minifier| minifier.minifies(relative)) {
+        let content = tokio::fs::read(src).await.map_err(|e| HugsError::FileRead { path: src.into(), cause: e })?;
+        match minifier.minify(relative, &content) {
+            Some(Ok(minified)) => {
+                stats.add(content.len(), minified.len());
+                return write_text_output(dest, &minified, app_data.config.build.output_normalize).await;
+            }
+            Some(Err(reason)) => warnings.add(HugsError::AssetNotMinified { path: relative.into(), reason }),
+            None => {}
         }
     }
 
+    tokio::fs::copy(src, dest).await.map_err(|e| HugsError::CopyFile {
+        src: src.into(),
+        dest: dest.into(),
+        cause: e,
+    })?;
     Ok(())
 }
 
+async fn write_asset_manifest(
+    app_data: &AppData,
+    output_path: &Path,
+    page_outputs: &PageOutputs,
+    warnings: &mut BuildWarnings,
+) -> Result<()> {
+    let manifest = AssetManifest::collect(&app_data.cache_bust_registry, output_path);
+    if manifest.assets.is_empty() {
+        return Ok(());
+    }
+
+    if page_outputs.collides(Path::new(ASSET_MANIFEST_FILE), "the asset manifest", warnings) {
+        return Ok(());
+    }
+
+    let manifest_path = output_path.join(ASSET_MANIFEST_FILE);
+    console::status("Writing", ASSET_MANIFEST_FILE);
+    write_text_output(&manifest_path, &manifest.to_json(), app_data.config.build.output_normalize).await
+}
+
+/// The warning for a feed or sitemap `output` that isn't well-formed, naming the page that breaks
+/// it when one does. Otherwise the problem is in `surroundings`, what every page shares.
+fn malformed_xml(output: &str, reason: String, culprit: Option<&PageInfo>, surroundings: &str) -> HugsError {
+    let help_text = match culprit {
+        Some(page) => format!(
+            "{} ({}) breaks it: something in its frontmatter or content can't go in XML, like an HTML entity such as &nbsp; in a description. Fix it there and build again.",
+            page.file_path, page.url
+        ),
+        None => format!("No one page breaks it on its own, so look at {}.", surroundings),
+    };
+    HugsError::MalformedXml { output: output.into(), reason, help_text }
+}
+
+type FeedGenerator = fn(&[FeedItem], &FeedPage, &FeedConfig, &SiteMetadata) -> Result<String>;
+
+/// Remove an earlier build's copy of a file this build rejected, so it isn't deployed as if it
+/// were current
+async fn remove_stale_output(path: &Path) -> Result<()> {
+    match tokio::fs::remove_file(path).await {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(HugsError::FileWrite { path: path.into(), cause: e }),
+        _ => Ok(()),
+    }
+}
+
 async fn generate_feeds(
     app_data: &AppData,
-    output_path: &PathBuf,
+    output_path: &Path,
+    page_outputs: &PageOutputs,
     warnings: &mut BuildWarnings,
 ) -> Result<usize> {
     if app_data.config.feeds.is_empty() {
@@ -369,46 +1295,58 @@ async fn generate_feeds(
     let mut count = 0;
 
     for feed_config in &app_data.config.feeds {
-        let items = collect_feed_items(&app_data.pages, feed_config, &app_data.config.site);
-
-        // Generate RSS if configured
-        if let Some(rss_filename) = &feed_config.output_rss {
-            match generate_rss(&items, feed_config, &app_data.config.site) {
-                Ok(rss_xml) => {
-                    let rss_path = output_path.join(rss_filename);
-                    console::status("Generating", format!("{} ({} items)", rss_filename, items.len()));
-                    tokio::fs::write(&rss_path, rss_xml)
-                        .await
-                        .map_err(|e| HugsError::FileWrite {
-                            path: (&rss_path).into(),
-                            cause: e,
-                        })?;
-                    count += 1;
-                }
-                Err(e) => {
-                    warnings.add(e);
-                }
-            }
+        let mut items = collect_feed_items(&app_data.pages, feed_config, &app_data.config);
+        for (page, warning) in render_item_contents(&mut items, feed_config, app_data).await? {
+            warnings.add_for_page(warning, page);
         }
 
-        // Generate Atom if configured
-        if let Some(atom_filename) = &feed_config.output_atom {
-            match generate_atom(&items, feed_config, &app_data.config.site) {
-                Ok(atom_xml) => {
-                    let atom_path = output_path.join(atom_filename);
-                    console::status("Generating", format!("{} ({} items)", atom_filename, items.len()));
-                    tokio::fs::write(&atom_path, atom_xml)
-                        .await
-                        .map_err(|e| HugsError::FileWrite {
-                            path: (&atom_path).into(),
-                            cause: e,
-                        })?;
-                    count += 1;
+        let formats = [
+            (&feed_config.output_rss, "RSS", generate_rss as FeedGenerator),
+            (&feed_config.output_atom, "Atom", generate_atom),
+        ];
+        for (output, format, generate) in formats {
+            let Some(output) = output else {
+                continue;
+            };
+
+            // A paginated feed is written as several files, each linking to its neighbours
+            let mut written = false;
+            for page in feed_pages(output, items.len(), feed_config) {
+                let source = format!("the {} {} feed", feed_config.name, format);
+                if page_outputs.collides(Path::new(&page.filename), &source, warnings) {
+                    continue;
                 }
-                Err(e) => {
-                    warnings.add(e);
+
+                match generate(&items, &page, feed_config, &app_data.config.site) {
+                    Ok(xml) => {
+                        if let Some(reason) = check_well_formed(&xml) {
+                            let page_items = &items[page.items.clone()];
+                            let culprit = find_culprit(page_items, |some| {
+                                let some_page = FeedPage { filename: page.filename.clone(), items: 0..some.len(), prev: None, next: None };
+                                !generate(some, &some_page, feed_config, &app_data.config.site)
+                                    .is_ok_and(|xml| check_well_formed(&xml).is_none())
+                            });
+                            let culprit = culprit.map(|index| &page_items[index].page);
+                            warnings.add(malformed_xml(&page.filename, reason, culprit, "the feed's settings and `item_template`"));
+                            remove_stale_output(&output_path.join(&page.filename)).await?;
+                            continue;
+                        }
+
+                        let feed_path = output_path.join(&page.filename);
+                        console::status("Generating", format!("{} ({} items)", page.filename, page.items.len()));
+                        write_text_output_atomically(&feed_path, &xml, app_data.config.build.output_normalize)?;
+                        written = true;
+                    }
+                    Err(e) => {
+                        // Missing site details, which every page would run into too
+                        warnings.add(e);
+                        break;
+                    }
                 }
             }
+            if written {
+                count += 1;
+            }
         }
     }
 
@@ -417,23 +1355,37 @@ async fn generate_feeds(
 
 async fn generate_sitemap_file(
     app_data: &AppData,
-    output_path: &PathBuf,
+    output_path: &Path,
+    lastmod_history: &LastmodHistory,
+    page_outputs: &PageOutputs,
     warnings: &mut BuildWarnings,
 ) -> Result<bool> {
     if app_data.config.site.url.is_none() {
         return Ok(false);
     }
 
-    match generate_sitemap(&app_data.pages, &app_data.config.site) {
+    if page_outputs.collides(Path::new("sitemap.xml"), "the sitemap", warnings) {
+        return Ok(false);
+    }
+
+    let template = app_data.sitemap_template.as_deref();
+    let dates = DateParser::new(&app_data.config);
+    match generate_sitemap(&app_data.pages, &app_data.config.site, &dates, template, Some(lastmod_history)) {
         Ok(sitemap_xml) => {
+            if let Some(reason) = check_well_formed(&sitemap_xml) {
+                let culprit = find_culprit(&app_data.pages, |some| {
+                    !generate_sitemap(some, &app_data.config.site, &dates, template, Some(lastmod_history))
+                        .is_ok_and(|xml| check_well_formed(&xml).is_none())
+                });
+                let culprit = culprit.map(|index| &app_data.pages[index]);
+                warnings.add(malformed_xml("sitemap.xml", reason, culprit, "`_/sitemap.jinja`"));
+                remove_stale_output(&output_path.join("sitemap.xml")).await?;
+                return Ok(false);
+            }
+
             let sitemap_path = output_path.join("sitemap.xml");
             console::status("Generating", format!("sitemap.xml ({} urls)", app_data.pages.len()));
-            tokio::fs::write(&sitemap_path, sitemap_xml)
-                .await
-                .map_err(|e| HugsError::FileWrite {
-                    path: (&sitemap_path).into(),
-                    cause: e,
-                })?;
+            write_text_output_atomically(&sitemap_path, &sitemap_xml, app_data.config.build.output_normalize)?;
             Ok(true)
         }
         Err(e) => {
@@ -442,3 +1394,126 @@ async fn generate_sitemap_file(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use miette::NamedSource;
+
+    /// The same mistake at `offset` in `file`
+    fn main_content_warning(file: &str, offset: usize) -> BuildWarning {
+        let error = HugsError::ContentTemplateUsesMainContent {
+            file: file.into(),
+            src: NamedSource::new(file, "{{ main_content }} and {{ main_content }}".to_string()),
+            span: (offset, 16).into(),
+        };
+        BuildWarning::new(error, Severity::Warning)
+    }
+
+    fn shape<'a>(groups: &[WarningGroup<'a>]) -> Vec<(Option<&'a str>, usize, Option<String>)> {
+        groups.iter().map(|group| (group.first().file.as_deref(), group.warnings.len(), group.footer())).collect()
+    }
+
+    #[test]
+    fn test_same_warning_on_many_pages_is_grouped() {
+        let mut warnings: Vec<BuildWarning> = (0..300).map(|i| main_content_warning(&format!("page-{i}.md"), 3)).collect();
+        warnings.push(BuildWarning::new(HugsError::AssetNotMinified { path: "app.js".into(), reason: "bad".into() }, Severity::Warning));
+
+        let groups = group_warnings(&warnings);
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].first().file.as_deref(), Some("page-0.md"));
+        assert_eq!(groups[0].warnings.len(), 300);
+        assert_eq!(
+            groups[0].footer().unwrap(),
+            "... and 299 more pages: page-1.md, page-2.md, page-3.md, page-4.md, page-5.md, page-6.md, page-7.md, page-8.md, page-9.md, page-10.md, … (truncated)"
+        );
+        assert_eq!(groups[1].first().file, None);
+        assert_eq!(groups[1].footer(), None);
+    }
+
+    #[test]
+    fn test_warnings_from_a_shared_macro_list_the_pages() {
+        // Every page calling the same broken macro points at the same place in the same file
+        let macro_warning = |page: &str| {
+            let error = HugsError::ContentTemplateUsesMainContent {
+                file: "_/macros.jinja".into(),
+                src: NamedSource::new("_/macros.jinja", "{{ main_content }} and {{ main_content }}".to_string()),
+                span: (3, 16).into(),
+            };
+            BuildWarning::for_page(error, Severity::Warning, Some(page.to_string()))
+        };
+        let warnings: Vec<BuildWarning> = ["a.md", "b.md", "c.md", "b.md"].into_iter().map(macro_warning).collect();
+
+        let groups = group_warnings(&warnings);
+        assert_eq!(shape(&groups), vec![(Some("_/macros.jinja"), 4, Some(String::from("... and 2 more pages: b.md, c.md")))]);
+        assert_eq!(groups[0].first().page.as_deref(), Some("a.md"));
+    }
+
+    #[test]
+    fn test_different_spans_in_one_file_stay_apart() {
+        let warnings = vec![
+            main_content_warning("a.md", 3),
+            main_content_warning("a.md", 26),
+            main_content_warning("b.md", 3),
+            main_content_warning("b.md", 26),
+            main_content_warning("a.md", 3),
+            main_content_warning("c.md", 3),
+        ];
+        assert_eq!(
+            shape(&group_warnings(&warnings)),
+            vec![
+                (Some("a.md"), 4, Some(String::from("... and 2 more pages: b.md, c.md"))),
+                (Some("a.md"), 2, Some(String::from("... and 1 more page: b.md"))),
+            ]
+        );
+
+        // Warnings without a file are grouped by their message alone
+        let unminified = |reason: &str| {
+            BuildWarning::new(HugsError::AssetNotMinified { path: "app.js".into(), reason: reason.into() }, Severity::Warning)
+        };
+        let warnings = vec![unminified("bad"), unminified("bad"), unminified("worse")];
+        assert_eq!(
+            shape(&group_warnings(&warnings)),
+            vec![(None, 2, Some(String::from("... and 1 more time"))), (None, 1, None)]
+        );
+    }
+
+    #[test]
+    fn test_warnings_are_shown_in_the_same_order_every_build() {
+        let mut warnings = BuildWarnings::new(false, false);
+        warnings.warnings = vec![
+            main_content_warning("b.md", 3),
+            BuildWarning::new(HugsError::AssetNotMinified { path: "app.js".into(), reason: "bad".into() }, Severity::Warning),
+            main_content_warning("a.md", 26),
+            main_content_warning("a.md", 3),
+        ];
+
+        let order: Vec<(Option<String>, usize)> = warnings
+            .sorted_warnings()
+            .into_iter()
+            .map(|warning| (warning.file, warning.spans.first().map_or(0, |&(offset, _)| offset)))
+            .collect();
+        assert_eq!(
+            order,
+            vec![(None, 0), (Some(String::from("a.md")), 3), (Some(String::from("a.md")), 26), (Some(String::from("b.md")), 3)]
+        );
+    }
+
+    #[test]
+    fn test_aliases_claim_their_output_files() {
+        let post = PageInfo { url: SiteUrl::parse("/post"), file_path: String::from("post.md"), frontmatter: serde_yaml::Value::Null };
+        let mut outputs = PageOutputs::new(&[post]);
+        let mut warnings = BuildWarnings::new(false, false);
+
+        let alias = SiteUrl::parse("/old").output_path();
+        assert!(!outputs.collides(&alias, "the alias /old of post.md", &mut warnings));
+        outputs.claim(&alias, String::from("post.md (its alias /old)"));
+
+        // A static file, or another page's alias, at the same place is skipped
+        assert!(outputs.collides(Path::new("old/index.html"), "the static file old/index.html", &mut warnings));
+        assert!(outputs.collides(Path::new("old"), "the static file old", &mut warnings));
+        assert!(!outputs.collides(Path::new("old/photo.jpg"), "the static file old/photo.jpg", &mut warnings));
+        assert_eq!(warnings.warnings.len(), 2);
+    }
+}
+
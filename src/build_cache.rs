@@ -0,0 +1,307 @@
+//! `.hugs-cache/`: results of expensive template functions, kept from one `hugs build` to the next.
+//!
+//! Each result is a file of its own, `.hugs-cache/<function>/<hash of its input>.json`, written to a
+//! temporary file and moved into place, so pages rendering in parallel never read half an entry and
+//! never wait on each other while one computes. An entry I can't read is a miss: it's computed and
+//! written again. Every build counts itself in `.hugs-cache/builds`, each entry remembers the last
+//! build that used it, and at the end of a build I delete the entries that no build has used for
+//! `[build] cache_evict_after` builds.
+//!
+//! `hugs build --no-cache` neither reads nor writes the cache, and `hugs clean --cache` deletes it.
+//! Keep it out of git; it's only ever a shortcut.
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+
+use serde::de::{DeserializeOwned, IgnoredAny};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use walkdir::WalkDir;
+
+use crate::error::{HugsError, Result};
+use crate::text_output::write_atomically;
+
+/// Where the cache lives, relative to the site root
+pub const CACHE_DIR: &str = ".hugs-cache";
+
+/// The number of the last build, in the cache folder
+const BUILDS_FILE: &str = "builds";
+
+/// Results of template functions, shared by every render of a build. The default one is off:
+/// everything is computed, and nothing is kept.
+#[derive(Clone, Default)]
+pub struct BuildCache {
+    store: Option<Arc<Store>>,
+    computed: Arc<AtomicUsize>,
+    reused: Arc<AtomicUsize>,
+}
+
+struct Store {
+    dir: PathBuf,
+    /// This build's number
+    build: u64,
+    /// Entries this build has already marked as used
+    touched: Mutex<HashSet<PathBuf>>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Entry<T> {
+    /// The last build that used the entry
+    build: u64,
+    value: T,
+}
+
+impl BuildCache {
+    /// The site's cache, for a new build
+    pub fn open(site_path: &Path) -> Self {
+        let dir = site_path.join(CACHE_DIR);
+        let builds_file = dir.join(BUILDS_FILE);
+        let last: u64 = std::fs::read_to_string(&builds_file)
+            .ok()
+            .and_then(|builds| builds.trim().parse().ok())
+            .unwrap_or(0);
+        let build = last + 1;
+
+        // A cache that can't be written is only slower, so the build carries on without one
+        if std::fs::create_dir_all(&dir).is_err() || write_atomically(&builds_file, &build.to_string()).is_err() {
+            return Self::default();
+        }
+
+        Self {
+            store: Some(Arc::new(Store { dir, build, touched: Mutex::default() })),
+            ..Self::default()
+        }
+    }
+
+    /// What `function` gives for `input`, from the cache when an earlier render or build kept it,
+    /// or by calling `compute` and keeping what it returns
+    pub fn get_or_compute<K, T>(&self, function: &str, input: &K, compute: impl FnOnce() -> T) -> T
+    where
+        K: Serialize + ?Sized,
+        T: Serialize + DeserializeOwned,
+    {
+        self.try_get_or_compute(function, input, || Ok::<_, std::convert::Infallible>(compute()))
+            .unwrap_or_else(|never| match never {})
+    }
+
+    /// [`BuildCache::get_or_compute`] for a `compute` that can fail. Failures aren't kept, so
+    /// they're tried again next time.
+    pub fn try_get_or_compute<K, T, E>(
+        &self,
+        function: &str,
+        input: &K,
+        compute: impl FnOnce() -> std::result::Result<T, E>,
+    ) -> std::result::Result<T, E>
+    where
+        K: Serialize + ?Sized,
+        T: Serialize + DeserializeOwned,
+    {
+        let Some(store) = &self.store else {
+            self.computed.fetch_add(1, Ordering::Relaxed);
+            return compute();
+        };
+
+        let path = store.entry_path(function, input);
+        if let Some(entry) = std::fs::read(&path).ok().and_then(|json| serde_json::from_slice::<Entry<T>>(&json).ok()) {
+            self.reused.fetch_add(1, Ordering::Relaxed);
+            if entry.build < store.build && store.touch(&path) {
+                store.write(&path, &entry.value);
+            }
+            return Ok(entry.value);
+        }
+
+        self.computed.fetch_add(1, Ordering::Relaxed);
+        let value = compute()?;
+        store.touch(&path);
+        store.write(&path, &value);
+        Ok(value)
+    }
+
+    /// How many results came from the cache, and how many were computed
+    pub fn stats(&self) -> (usize, usize) {
+        (self.reused.load(Ordering::Relaxed), self.computed.load(Ordering::Relaxed))
+    }
+
+    /// Delete the entries no build has used for `max_unused` builds, and any I can't read.
+    /// Returns how many went.
+    pub fn evict(&self, max_unused: u64) -> usize {
+        let Some(store) = &self.store else {
+            return 0;
+        };
+
+        let mut removed = 0;
+        for entry in WalkDir::new(&store.dir).min_depth(2).max_depth(2).into_iter().filter_map(|e| e.ok()) {
+            let path = entry.path();
+            if path.extension().is_none_or(|ext| ext != "json") {
+                continue;
+            }
+            let last_used = std::fs::read(path)
+                .ok()
+                .and_then(|json| serde_json::from_slice::<Entry<IgnoredAny>>(&json).ok())
+                .map(|entry| entry.build);
+            let stale = last_used.is_none_or(|build| build + max_unused <= store.build);
+            if stale && std::fs::remove_file(path).is_ok() {
+                removed += 1;
+            }
+        }
+        removed
+    }
+}
+
+impl Store {
+    /// The file holding `function`'s result for `input`. The hugs version is part of the key, so
+    /// an upgrade never reuses results an older version worked out differently.
+    fn entry_path<K: Serialize + ?Sized>(&self, function: &str, input: &K) -> PathBuf {
+        let mut hasher = Sha256::new();
+        hasher.update(env!("CARGO_PKG_VERSION"));
+        hasher.update([0]);
+        hasher.update(serde_json::to_vec(input).unwrap_or_default());
+        self.dir.join(function).join(format!("{}.json", hex::encode(hasher.finalize())))
+    }
+
+    /// Mark the entry at `path` used by this build. Returns whether it wasn't already.
+    fn touch(&self, path: &Path) -> bool {
+        self.touched.lock().unwrap_or_else(PoisonError::into_inner).insert(path.to_path_buf())
+    }
+
+    /// Keep `value` at `path` as used by this build. Failing to only costs a later build the
+    /// time to compute it again.
+    fn write<T: Serialize>(&self, path: &Path, value: &T) {
+        let Ok(json) = serde_json::to_string(&Entry { build: self.build, value }) else {
+            return;
+        };
+        if let Some(dir) = path.parent()
+            && std::fs::create_dir_all(dir).is_ok()
+        {
+            let _ = write_atomically(path, &json);
+        }
+    }
+}
+
+/// Delete the site's cache, for `hugs clean --cache`. Returns whether there was one.
+pub async fn remove_cache(site_path: &Path) -> Result<bool> {
+    let dir = site_path.join(CACHE_DIR);
+    if !dir.exists() {
+        return Ok(false);
+    }
+    tokio::fs::remove_dir_all(&dir)
+        .await
+        .map_err(|e| HugsError::RemoveDir { path: dir.into(), cause: e })?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn counted(cache: &BuildCache, input: &str, computes: &AtomicUsize) -> usize {
+        cache.get_or_compute("length", input, || {
+            computes.fetch_add(1, Ordering::Relaxed);
+            input.len()
+        })
+    }
+
+    #[test]
+    fn test_later_builds_reuse_results() {
+        let site = tempfile::tempdir().unwrap();
+        let computes = AtomicUsize::new(0);
+
+        let first = BuildCache::open(site.path());
+        assert_eq!(counted(&first, "hello", &computes), 5);
+        assert_eq!(counted(&first, "hello", &computes), 5);
+        assert_eq!(counted(&first, "hi", &computes), 2);
+        assert_eq!(computes.load(Ordering::Relaxed), 2);
+        assert_eq!(first.stats(), (1, 2));
+
+        let second = BuildCache::open(site.path());
+        assert_eq!(counted(&second, "hello", &computes), 5);
+        assert_eq!(counted(&second, "hi", &computes), 2);
+        assert_eq!(computes.load(Ordering::Relaxed), 2);
+        assert_eq!(second.stats(), (2, 0));
+
+        // Turned off, everything is computed
+        let off = BuildCache::default();
+        assert_eq!(counted(&off, "hello", &computes), 5);
+        assert_eq!(computes.load(Ordering::Relaxed), 3);
+    }
+
+    #[test]
+    fn test_corrupt_entries_are_misses() {
+        let site = tempfile::tempdir().unwrap();
+        let computes = AtomicUsize::new(0);
+        let cache = BuildCache::open(site.path());
+        counted(&cache, "hello", &computes);
+
+        let entry = WalkDir::new(site.path().join(CACHE_DIR).join("length"))
+            .min_depth(1)
+            .into_iter()
+            .next()
+            .unwrap()
+            .unwrap();
+        std::fs::write(entry.path(), "{ not json").unwrap();
+
+        let cache = BuildCache::open(site.path());
+        assert_eq!(counted(&cache, "hello", &computes), 5);
+        assert_eq!(computes.load(Ordering::Relaxed), 2);
+        let cache = BuildCache::open(site.path());
+        assert_eq!(counted(&cache, "hello", &computes), 5);
+        assert_eq!(computes.load(Ordering::Relaxed), 2);
+
+        // Failures are tried again
+        let failed: std::result::Result<usize, &str> = cache.try_get_or_compute("length", "nope", || Err("no"));
+        assert!(failed.is_err());
+        assert_eq!(cache.try_get_or_compute("length", "nope", || Ok::<_, &str>(4)), Ok(4));
+    }
+
+    #[test]
+    fn test_unused_entries_are_evicted() {
+        let site = tempfile::tempdir().unwrap();
+        let computes = AtomicUsize::new(0);
+
+        let cache = BuildCache::open(site.path());
+        counted(&cache, "old", &computes);
+        counted(&cache, "kept", &computes);
+        std::fs::write(site.path().join(CACHE_DIR).join("length").join("junk.json"), "").unwrap();
+        assert_eq!(cache.evict(2), 1);
+
+        // Used in the second build, "kept" stays when "old" goes
+        let cache = BuildCache::open(site.path());
+        counted(&cache, "kept", &computes);
+        assert_eq!(cache.evict(2), 0);
+        let cache = BuildCache::open(site.path());
+        assert_eq!(cache.evict(2), 1);
+
+        counted(&cache, "kept", &computes);
+        counted(&cache, "old", &computes);
+        assert_eq!(computes.load(Ordering::Relaxed), 3);
+    }
+
+    #[tokio::test]
+    async fn test_parallel_renders_share_entries() {
+        let site = tempfile::tempdir().unwrap();
+        let cache = BuildCache::open(site.path());
+        let computes = Arc::new(AtomicUsize::new(0));
+
+        let mut join_set = tokio::task::JoinSet::new();
+        for i in 0..32 {
+            let cache = cache.clone();
+            let computes = Arc::clone(&computes);
+            join_set.spawn_blocking(move || counted(&cache, &format!("page {}", i % 4), &computes));
+        }
+        while let Some(length) = join_set.join_next().await {
+            assert_eq!(length.unwrap(), 6);
+        }
+
+        let computes_before = computes.load(Ordering::Relaxed);
+        let cache = BuildCache::open(site.path());
+        for i in 0..4 {
+            counted(&cache, &format!("page {}", i), &computes);
+        }
+        assert_eq!(computes.load(Ordering::Relaxed), computes_before);
+
+        assert!(remove_cache(site.path()).await.unwrap());
+        assert!(!site.path().join(CACHE_DIR).exists());
+    }
+}
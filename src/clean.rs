@@ -0,0 +1,231 @@
+//! Deleting build output: `hugs build` empties its output folder before writing, and `hugs clean`
+//! removes it. Both refuse to delete a folder that would take the site (or much more) with it.
+
+use std::path::{Component, Path, PathBuf};
+
+use dialoguer::{theme::ColorfulTheme, Confirm};
+
+use crate::build_cache::{CACHE_DIR, remove_cache};
+use crate::console;
+use crate::error::{HugsError, Result, StyledPath};
+
+/// Why deleting an output folder would be a mistake
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputDanger {
+    /// The output folder is the site itself
+    IsSite,
+    /// The site is somewhere inside the output folder
+    ContainsSite,
+    FilesystemRoot,
+    HomeDir,
+}
+
+impl OutputDanger {
+    fn reason(self) -> &'static str {
+        match self {
+            OutputDanger::IsSite => "it's your site's source folder",
+            OutputDanger::ContainsSite => "your site's source folder is inside it",
+            OutputDanger::FilesystemRoot => "it's the root of the filesystem",
+            OutputDanger::HomeDir => "it's your home folder",
+        }
+    }
+}
+
+/// Remove the build output at `output_path`, for `hugs clean`, and the site's build cache too
+/// with `cache`
+pub async fn run_clean(site_path: PathBuf, output_path: PathBuf, force: bool, cache: bool) -> Result<()> {
+    if cache {
+        match remove_cache(&site_path).await? {
+            true => console::status("Removed", site_path.join(CACHE_DIR).display()),
+            false => console::status("Clean", format!("no cache to remove at {}", site_path.join(CACHE_DIR).display())),
+        }
+    }
+
+    if !output_path.exists() {
+        console::status("Clean", format!("nothing to remove at {}", output_path.display()));
+        return Ok(());
+    }
+
+    remove_output_dir(&site_path, &output_path, force).await?;
+    console::status("Removed", output_path.display());
+    Ok(())
+}
+
+/// Delete `output_path` and everything in it, unless it's somewhere dangerous. With `force`, a
+/// dangerous folder is deleted only after you confirm it.
+pub async fn remove_output_dir(site_path: &Path, output_path: &Path, force: bool) -> Result<()> {
+    if let Some(danger) = output_danger(site_path, output_path) {
+        confirm_removal(output_path, danger, force)?;
+    }
+
+    tokio::fs::remove_dir_all(output_path)
+        .await
+        .map_err(|e| HugsError::RemoveDir {
+            path: output_path.into(),
+            cause: e,
+        })
+}
+
+/// What deleting `output_path` would destroy beyond the build output, if anything
+pub fn output_danger(site_path: &Path, output_path: &Path) -> Option<OutputDanger> {
+    let home = std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE")).map(PathBuf::from);
+    output_danger_with_home(site_path, output_path, home.as_deref())
+}
+
+fn output_danger_with_home(site_path: &Path, output_path: &Path, home: Option<&Path>) -> Option<OutputDanger> {
+    let output = resolve(output_path);
+    let site = resolve(site_path);
+
+    if output.parent().is_none() {
+        Some(OutputDanger::FilesystemRoot)
+    } else if output == site {
+        Some(OutputDanger::IsSite)
+    } else if site.starts_with(&output) {
+        Some(OutputDanger::ContainsSite)
+    } else if home.is_some_and(|home| resolve(home) == output) {
+        Some(OutputDanger::HomeDir)
+    } else {
+        None
+    }
+}
+
+/// `path` made absolute, with symlinks and `..` resolved, so `../my-site` and `.` compare equal
+/// from inside `my-site`. The part of the path that doesn't exist yet is normalized by hand.
+fn resolve(path: &Path) -> PathBuf {
+    let absolute = std::env::current_dir().map(|cwd| cwd.join(path)).unwrap_or_else(|_| path.to_path_buf());
+
+    let mut existing = absolute.as_path();
+    let mut missing = Vec::new();
+    let mut resolved = loop {
+        if let Ok(canonical) = existing.canonicalize() {
+            break canonical;
+        }
+        match (existing.parent(), existing.components().next_back()) {
+            (Some(parent), Some(last)) => {
+                missing.push(last);
+                existing = parent;
+            }
+            _ => break existing.to_path_buf(),
+        }
+    };
+
+    for component in missing.into_iter().rev() {
+        match component {
+            Component::ParentDir => {
+                resolved.pop();
+            }
+            Component::CurDir => {}
+            other => resolved.push(other),
+        }
+    }
+    resolved
+}
+
+/// Ask before deleting a dangerous folder, which is only offered with `--force`
+fn confirm_removal(output_path: &Path, danger: OutputDanger, force: bool) -> Result<()> {
+    if !force {
+        return Err(HugsError::UnsafeOutputDir {
+            path: output_path.into(),
+            reason: danger.reason().to_string(),
+        });
+    }
+
+    let confirmed = Confirm::with_theme(&ColorfulTheme::default())
+        .with_prompt(format!(
+            "Delete {} and everything in it? ({})",
+            StyledPath::from(output_path),
+            danger.reason()
+        ))
+        .default(false)
+        .interact()
+        .map_err(|e| HugsError::InputError { cause: e.to_string() })?;
+
+    if confirmed {
+        Ok(())
+    } else {
+        Err(HugsError::CleanDeclined { path: output_path.into() })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn danger(site: &Path, output: &Path) -> Option<OutputDanger> {
+        output_danger_with_home(site, output, None)
+    }
+
+    #[test]
+    fn test_output_in_the_site_or_next_to_it_is_fine() {
+        let root = tempfile::tempdir().unwrap();
+        let site = root.path().join("my-site");
+        std::fs::create_dir(&site).unwrap();
+
+        assert_eq!(danger(&site, &site.join("dist")), None);
+        assert_eq!(danger(&site, &root.path().join("public")), None);
+        assert_eq!(danger(&site, &site.join("../my-site-dist")), None);
+    }
+
+    #[test]
+    fn test_output_that_is_the_site_is_refused() {
+        let root = tempfile::tempdir().unwrap();
+        let site = root.path().join("my-site");
+        std::fs::create_dir_all(site.join("blog")).unwrap();
+
+        assert_eq!(danger(&site, &site), Some(OutputDanger::IsSite));
+        assert_eq!(danger(&site, &site.join("blog/..")), Some(OutputDanger::IsSite));
+        assert_eq!(danger(&site, &site.join("dist/../..").join("my-site")), Some(OutputDanger::IsSite));
+
+        #[cfg(unix)]
+        {
+            let link = root.path().join("link-to-site");
+            std::os::unix::fs::symlink(&site, &link).unwrap();
+            assert_eq!(danger(&site, &link), Some(OutputDanger::IsSite));
+        }
+    }
+
+    #[test]
+    fn test_output_holding_the_site_is_refused() {
+        let root = tempfile::tempdir().unwrap();
+        let site = root.path().join("sites/my-site");
+        std::fs::create_dir_all(&site).unwrap();
+
+        assert_eq!(danger(&site, &root.path().join("sites")), Some(OutputDanger::ContainsSite));
+        assert_eq!(danger(&site, &site.join("..")), Some(OutputDanger::ContainsSite));
+        assert_eq!(danger(&site, root.path()), Some(OutputDanger::ContainsSite));
+    }
+
+    #[test]
+    fn test_root_and_home_are_refused() {
+        let root = tempfile::tempdir().unwrap();
+        let site = root.path().join("my-site");
+        let home = root.path().join("home");
+        std::fs::create_dir(&site).unwrap();
+        std::fs::create_dir(&home).unwrap();
+
+        let filesystem_root = resolve(&site).ancestors().last().unwrap().to_path_buf();
+        assert_eq!(danger(&site, &filesystem_root), Some(OutputDanger::FilesystemRoot));
+        assert_eq!(
+            output_danger_with_home(&site, &home.join("../home"), Some(&home)),
+            Some(OutputDanger::HomeDir)
+        );
+        assert_eq!(output_danger_with_home(&site, &home.join("dist"), Some(&home)), None);
+    }
+
+    #[tokio::test]
+    async fn test_refused_output_is_left_alone() {
+        let root = tempfile::tempdir().unwrap();
+        let site = root.path().join("my-site");
+        std::fs::create_dir(&site).unwrap();
+        std::fs::write(site.join("index.md"), "# Hi").unwrap();
+
+        let result = remove_output_dir(&site, &site, false).await;
+        assert!(matches!(result, Err(HugsError::UnsafeOutputDir { .. })));
+        assert!(site.join("index.md").exists());
+
+        let dist = site.join("dist");
+        std::fs::create_dir(&dist).unwrap();
+        remove_output_dir(&site, &dist, false).await.unwrap();
+        assert!(!dist.exists());
+    }
+}
@@ -0,0 +1,159 @@
+//! `code_languages()`: the languages of the code blocks across the site, for a page that lists
+//! them or a filter over posts by language.
+//!
+//! The scan reads each page's fences as it reads the file, so the function only looks up what's
+//! already gathered. A language counts its blocks and lists the pages that have one. A dynamic
+//! page's blocks are counted once, for its `[param].md` file, but every page the file makes is
+//! listed. Names are lowercased, since `Rust` and `rust` highlight the same.
+//!
+//! Each language also says whether the highlighter knows it, after `[build.syntax_highlighting]
+//! aliases` and mine, so a template can point out a typo'd fence like `rsut`.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::{Arc, LazyLock};
+
+use minijinja::value::Value;
+use regex::Regex;
+use serde::Serialize;
+
+use crate::deps::DepRecorder;
+use crate::run::PageInfo;
+
+/// A fence line, opening or closing, which may sit in a list or a quote
+static FENCE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^[ \t>]*(`{3,}|~{3,})(.*)$").unwrap());
+
+/// The language of each fenced code block in `markdown` that names one, in order
+pub fn fence_languages(markdown: &str) -> Vec<String> {
+    let mut languages = Vec::new();
+    // The open fence's character and length: only a fence at least as long closes it
+    let mut open: Option<(char, usize)> = None;
+    for line in markdown.lines() {
+        let Some(captures) = FENCE.captures(line) else { continue };
+        let fence = &captures[1];
+        let info = captures[2].trim();
+        let marker = if fence.starts_with('`') { '`' } else { '~' };
+        if marker == '`' && info.contains('`') {
+            // Inline code, not a fence
+            continue;
+        }
+
+        match open {
+            Some((open_marker, open_len)) => {
+                if marker == open_marker && fence.len() >= open_len && info.is_empty() {
+                    open = None;
+                }
+            }
+            None => {
+                open = Some((marker, fence.len()));
+                if let Some(language) = info.split_whitespace().next() {
+                    languages.push(language.to_lowercase());
+                }
+            }
+        }
+    }
+    languages
+}
+
+/// One language as `code_languages()` gives it
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct CodeLanguage {
+    /// As the fences name it
+    pub name: String,
+    pub blocks: usize,
+    /// URLs of the pages with a block in this language
+    pub pages: Vec<String>,
+    /// Whether the highlighter knows the language
+    pub supported: bool,
+    /// The grammar it's highlighted with, like `go` for `golang`
+    pub grammar: Option<String>,
+}
+
+/// The site's code languages, most blocks first
+#[derive(Debug, Clone, Default)]
+pub struct CodeLanguages {
+    pub languages: Arc<Vec<CodeLanguage>>,
+}
+
+impl CodeLanguages {
+    /// Gather the languages from the fences of each page file, by path relative to the site
+    /// root. `grammar` finds the grammar a name is highlighted with, if there is one.
+    pub fn collect(fences: &HashMap<String, Vec<String>>, pages: &[PageInfo], grammar: impl Fn(&str) -> Option<String>) -> Self {
+        let mut urls: HashMap<&str, Vec<&str>> = HashMap::new();
+        for page in pages {
+            urls.entry(page.file_path.as_str()).or_default().push(page.url.as_str());
+        }
+
+        let mut by_name: BTreeMap<&str, (usize, BTreeSet<&str>)> = BTreeMap::new();
+        for (file, languages) in fences {
+            for language in languages {
+                let (blocks, pages) = by_name.entry(language.as_str()).or_default();
+                *blocks += 1;
+                pages.extend(urls.get(file.as_str()).into_iter().flatten().copied());
+            }
+        }
+
+        let mut languages: Vec<CodeLanguage> = by_name
+            .into_iter()
+            .map(|(name, (blocks, pages))| {
+                let grammar = grammar(name);
+                CodeLanguage {
+                    name: name.to_string(),
+                    blocks,
+                    pages: pages.into_iter().map(String::from).collect(),
+                    supported: grammar.is_some(),
+                    grammar,
+                }
+            })
+            .collect();
+        // Sorting is stable, so languages with as many blocks stay in name order
+        languages.sort_by_key(|language| std::cmp::Reverse(language.blocks));
+        Self { languages: Arc::new(languages) }
+    }
+
+    /// `code_languages()`: the languages, as a list of `name`, `blocks`, `pages`, `supported`
+    /// and `grammar`. Any page's code can change them, so it's recorded as a `pages()` call.
+    pub fn to_minijinja_fn(&self, deps: &DepRecorder) -> impl Fn() -> Value + Send + Sync + 'static {
+        let languages = Arc::clone(&self.languages);
+        let deps = deps.clone();
+        move || {
+            deps.record_pages();
+            Value::from_serialize(&*languages)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fences_name_their_languages() {
+        let markdown = "\
+```rust
+fn main() {}
+```
+
+- In a list:
+  ~~~ Python title=\"x\"
+  print(1)
+  ~~~
+
+````markdown
+```js
+not a block of its own
+```
+````
+
+```
+no language
+```
+
+Inline ```code``` isn't a fence.
+
+> ```toml
+> a = 1
+> ```
+";
+        assert_eq!(fence_languages(markdown), ["rust", "python", "markdown", "toml"]);
+    }
+}
@@ -0,0 +1,261 @@
+//! Page lists: what `pages()` picks for its arguments, and the named `[collections]` in
+//! config.toml that `collection(name)` and feeds use. A collection's `filter` takes the same
+//! arguments as `pages()` and goes through the same code, so the two never disagree.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use minijinja::value::{Kwargs, Value};
+
+use crate::auto_list::sort_pages;
+use crate::config::{CollectionConfig, FeedConfig, ListOrder, PageQuery, SiteConfig};
+use crate::console;
+use crate::error::{HugsError, Result, find_best_match};
+use crate::run::{PageInfo, page_for_request};
+
+impl PageQuery {
+    /// The query `pages(within=..., sort_by=..., order=..., limit=...)` asks for. Any other
+    /// argument is an error, so a typo like `withn=` doesn't quietly list every page.
+    pub fn from_kwargs(kwargs: &Kwargs) -> std::result::Result<Self, minijinja::Error> {
+        let order: Option<String> = kwargs.get("order")?;
+        let order = match order.as_deref() {
+            None => None,
+            Some("asc") => Some(ListOrder::Asc),
+            Some("desc") => Some(ListOrder::Desc),
+            Some(other) => {
+                return Err(minijinja::Error::new(
+                    minijinja::ErrorKind::InvalidOperation,
+                    format!("order can be \"asc\" or \"desc\", not {:?}", other),
+                ));
+            }
+        };
+
+        let query = Self {
+            within: kwargs.get("within")?,
+            sort_by: kwargs.get("sort_by")?,
+            order,
+            limit: kwargs.get("limit")?,
+        };
+        kwargs.assert_all_used()?;
+        Ok(query)
+    }
+
+    pub fn select<'a>(&self, pages: &'a [PageInfo]) -> Vec<&'a PageInfo> {
+        let mut selected: Vec<&PageInfo> = match &self.within {
+            Some(prefix) => pages.iter().filter(|page| page.url.is_within(prefix)).collect(),
+            None => pages.iter().collect(),
+        };
+
+        if let Some(key) = &self.sort_by {
+            sort_pages(&mut selected, key, self.order);
+        }
+        if let Some(limit) = self.limit {
+            selected.truncate(limit);
+        }
+        selected
+    }
+}
+
+/// The pages in a collection, in its order. Listed URLs without a page are left out.
+pub fn collection_pages<'a>(collection: &CollectionConfig, pages: &'a [PageInfo]) -> Vec<&'a PageInfo> {
+    match collection {
+        CollectionConfig::Pages(urls) => urls.iter().filter_map(|url| page_for_request(pages, url)).collect(),
+        CollectionConfig::Filter(query) => query.select(pages),
+    }
+}
+
+/// `collection(name)`: the pages in one of the site's `[collections]`
+pub fn collection_function(
+    collections: &BTreeMap<String, CollectionConfig>,
+    pages: &[PageInfo],
+    name: &str,
+) -> std::result::Result<Value, minijinja::Error> {
+    let Some(collection) = collections.get(name) else {
+        let names: Vec<String> = collections.keys().cloned().collect();
+        let hint = match find_best_match(name, &names) {
+            Some(suggestion) => format!("did you mean {:?}?", suggestion),
+            None if names.is_empty() => String::from("add one under [collections] in config.toml"),
+            None => format!("the collections are {}", names.join(", ")),
+        };
+        return Err(minijinja::Error::new(
+            minijinja::ErrorKind::InvalidOperation,
+            format!("there's no collection called {:?}; {}", name, hint),
+        ));
+    };
+
+    Ok(Value::from_serialize(collection_pages(collection, pages)))
+}
+
+/// The pages a feed is made from
+pub fn feed_source_pages<'a>(feed: &FeedConfig, config: &SiteConfig, pages: &'a [PageInfo]) -> Vec<&'a PageInfo> {
+    match (&feed.collection, &feed.source) {
+        (Some(name), _) => config.collections.get(name).map_or_else(Vec::new, |c| collection_pages(c, pages)),
+        (None, source) => PageQuery { within: source.clone(), ..PageQuery::default() }.select(pages),
+    }
+}
+
+/// Check the collections and the feeds that use them once the site's pages are known. A listed
+/// URL without a page is only a warning, since the page may be a draft that's coming back.
+pub fn check_collections(config: &SiteConfig, pages: &[PageInfo]) -> Result<()> {
+    for feed in &config.feeds {
+        match (&feed.source, &feed.collection) {
+            (Some(source), None) => warn_if_source_is_empty(&feed.name, source, pages),
+            (None, Some(name)) if config.collections.contains_key(name) => {}
+            (None, Some(name)) => {
+                let names: Vec<String> = config.collections.keys().cloned().collect();
+                let help_text = match find_best_match(name, &names) {
+                    Some(suggestion) => format!("Did you mean `collection = \"{}\"`?", suggestion),
+                    None => format!("Add it to config.toml:\n\n[collections.{}]\npages = [\"/blog/first-post\"]", name),
+                };
+                return Err(HugsError::FeedUnknownCollection {
+                    feed_name: feed.name.clone().into(),
+                    collection: name.clone().into(),
+                    help_text,
+                });
+            }
+            _ => return Err(HugsError::FeedSource { feed_name: feed.name.clone().into() }),
+        }
+    }
+
+    let urls: Vec<String> = pages.iter().map(|page| page.url.to_string()).collect();
+    for (name, collection) in &config.collections {
+        let CollectionConfig::Pages(listed) = collection else {
+            continue;
+        };
+        for url in listed.iter().filter(|url| page_for_request(pages, url).is_none()) {
+            let suggestion = find_best_match(url, &urls).map(|s| format!("; did you mean {}?", s)).unwrap_or_default();
+            console::warn(format!("the {} collection lists {}, but there's no page there{}", name, url, suggestion));
+        }
+    }
+
+    Ok(())
+}
+
+/// A feed `source` with no pages under it is most likely a typo, so suggest a folder that has some
+fn warn_if_source_is_empty(feed_name: &str, source: &str, pages: &[PageInfo]) {
+    if let Some(suggestion) = empty_source_suggestion(source, pages) {
+        let suggestion = suggestion.map(|s| format!("; did you mean {}?", s)).unwrap_or_default();
+        console::warn(format!("the {} feed takes pages from under {}, but there aren't any{}", feed_name, source, suggestion));
+    }
+}
+
+/// `None` when some page is under `source`, otherwise the folder with pages it's closest to
+fn empty_source_suggestion(source: &str, pages: &[PageInfo]) -> Option<Option<String>> {
+    if pages.iter().any(|page| page.url.is_within(source)) {
+        return None;
+    }
+
+    let folders: Vec<String> = pages
+        .iter()
+        .flat_map(|page| {
+            let segments: Vec<&str> = page.url.as_str().trim_matches('/').split('/').collect();
+            // A page's own URL is a folder when it's a directory index
+            let depth = if page.url.is_index() { segments.len() } else { segments.len() - 1 };
+            (1..=depth).map(move |n| format!("/{}", segments[..n].join("/")))
+        })
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+    Some(find_best_match(source.trim_end_matches('/'), &folders).map(str::to_string))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::url::SiteUrl;
+
+    fn page(url: &str, frontmatter: &str) -> PageInfo {
+        PageInfo {
+            url: SiteUrl::parse(url),
+            file_path: format!("{}.md", url.trim_matches('/')),
+            frontmatter: serde_yaml::from_str(frontmatter).unwrap(),
+        }
+    }
+
+    fn site_pages() -> Vec<PageInfo> {
+        vec![
+            page("/", "title: Home"),
+            page("/blog/", "title: Blog"),
+            page("/blog/old", "title: Old\ndate_parsed: 2022-01-01T00:00:00+00:00"),
+            page("/blog/new", "title: New\ndate_parsed: 2024-01-01T00:00:00+00:00"),
+            page("/blog/mid", "title: Mid\ndate_parsed: 2023-01-01T00:00:00+00:00"),
+            page("/docs/intro", "title: Intro"),
+        ]
+    }
+
+    fn config(toml: &str) -> SiteConfig {
+        toml::from_str(toml).unwrap()
+    }
+
+    fn urls(pages: &[&PageInfo]) -> Vec<String> {
+        pages.iter().map(|page| page.url.to_string()).collect()
+    }
+
+    #[test]
+    fn test_listed_and_filtered_collections() {
+        let pages = site_pages();
+        let config = config(
+            "[collections.featured]\npages = [\"/docs/intro\", \"/blog/old/\", \"/nowhere\"]\n\
+            [collections.recent]\nfilter = { within = \"/blog\", limit = 2, sort_by = \"date\" }\n",
+        );
+
+        assert_eq!(urls(&collection_pages(&config.collections["featured"], &pages)), ["/docs/intro", "/blog/old"]);
+        assert_eq!(urls(&collection_pages(&config.collections["recent"], &pages)), ["/blog/new", "/blog/mid"]);
+    }
+
+    #[test]
+    fn test_filters_match_pages_kwargs() {
+        let pages = site_pages();
+        let mut env = minijinja::Environment::new();
+        let for_pages = pages.clone();
+        env.add_function("pages", move |kwargs: Kwargs| {
+            Ok(Value::from_serialize(PageQuery::from_kwargs(&kwargs)?.select(&for_pages)))
+        });
+        let config = config("[collections.oldest]\nfilter = { within = \"/blog\", sort_by = \"date\", order = \"asc\", limit = 2 }\n");
+        let collections = config.collections.clone();
+        let for_collection = pages.clone();
+        env.add_function("collection", move |name: String| collection_function(&collections, &for_collection, &name));
+
+        let render = |template: &str| env.render_str(template, ()).unwrap();
+        let listed = "{% for page in PAGES %}{{ page.url }} {% endfor %}";
+        assert_eq!(
+            render(&listed.replace("PAGES", "pages(within=\"/blog\", sort_by=\"date\", order=\"asc\", limit=2)")),
+            render(&listed.replace("PAGES", "collection(\"oldest\")"))
+        );
+        assert_eq!(render(&listed.replace("PAGES", "collection(\"oldest\")")), "/blog/old /blog/mid ");
+
+        let unknown = env.render_str("{{ collection(\"olderst\") }}", ()).unwrap_err();
+        assert!(unknown.to_string().contains("did you mean \"oldest\"?"), "{unknown}");
+        assert!(env.render_str("{{ pages(order=\"newest\") }}", ()).is_err());
+        let typo = env.render_str("{{ pages(withn=\"/blog\") }}", ()).unwrap_err();
+        assert!(typo.to_string().contains("withn"), "{typo}");
+    }
+
+    #[test]
+    fn test_feeds_from_collections() {
+        let pages = site_pages();
+        let config = config(
+            "[[feeds]]\nname = \"featured\"\ncollection = \"featured\"\n\
+            [collections.featured]\npages = [\"/blog/mid\", \"/docs/intro\"]\n",
+        );
+        assert!(check_collections(&config, &pages).is_ok());
+        assert_eq!(urls(&feed_source_pages(&config.feeds[0], &config, &pages)), ["/blog/mid", "/docs/intro"]);
+
+        let typo = self::config("[[feeds]]\nname = \"x\"\ncollection = \"featurd\"\n[collections.featured]\npages = []\n");
+        let Err(HugsError::FeedUnknownCollection { help_text, .. }) = check_collections(&typo, &pages) else {
+            panic!("expected an unknown collection error");
+        };
+        assert!(help_text.contains("collection = \"featured\""), "{help_text}");
+
+        assert_eq!(empty_source_suggestion("/blgo", &pages), Some(Some(String::from("/blog"))));
+        assert_eq!(empty_source_suggestion("/doc", &pages), Some(Some(String::from("/docs"))));
+        assert_eq!(empty_source_suggestion("/nowhere-near", &pages), Some(None));
+        assert_eq!(empty_source_suggestion("/blog/", &pages), None);
+        assert_eq!(empty_source_suggestion("/", &pages), None);
+
+        let both = self::config("[[feeds]]\nname = \"x\"\nsource = \"/blog\"\ncollection = \"featured\"\n");
+        assert!(matches!(check_collections(&both, &pages), Err(HugsError::FeedSource { .. })));
+
+        let neither = toml::from_str::<SiteConfig>("[collections.empty]\n");
+        assert!(neither.unwrap_err().to_string().contains("a collection needs `pages`"));
+    }
+}
@@ -0,0 +1,220 @@
+//! `qr(data=...)` and `avatar(email=...)`: images computed at render time and put straight into
+//! the page as SVG, so there's no file to write or serve. They're dressed like `inline_svg()`'s,
+//! with `class` and a label for screen readers.
+//!
+//! Each image is made once per load of the site and kept, since a template in a loop or on every
+//! page asks for the same one over and over.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use minijinja::value::{Kwargs, Value};
+use minijinja::{Error, ErrorKind};
+use qrcode::QrCode;
+use qrcode::render::svg;
+use sha2::{Digest, Sha256};
+
+use crate::inline_svg::dress;
+
+/// Smallest and biggest `size`, in pixels. Past these a QR code is too small to scan, or the
+/// page is carrying a poster.
+const MIN_SIZE: u32 = 16;
+const MAX_SIZE: u32 = 2048;
+
+const DEFAULT_QR_SIZE: u32 = 200;
+const DEFAULT_AVATAR_SIZE: u32 = 64;
+
+/// Cells across an avatar. The left half (and middle) are drawn and mirrored, like a face.
+const AVATAR_CELLS: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Kind {
+    Qr,
+    Avatar,
+}
+
+/// What an image was made from
+type Source = (Kind, String, u32);
+
+#[derive(Clone, Default)]
+pub struct ComputedSvgs {
+    /// Images made so far, by what they were made from
+    made: Arc<Mutex<HashMap<Source, Arc<str>>>>,
+}
+
+impl ComputedSvgs {
+    /// `qr(data, size=200, class=none, alt=none)`: `data` as a QR code `size` pixels square
+    pub fn qr_fn(&self) -> impl Fn(Kwargs) -> std::result::Result<Value, Error> + Send + Sync + 'static {
+        let computed = self.clone();
+        move |kwargs: Kwargs| {
+            let data: String = kwargs.get("data")?;
+            let size = checked_size(kwargs.get("size")?, DEFAULT_QR_SIZE, "qr")?;
+            let class: Option<String> = kwargs.get("class")?;
+            let alt: Option<String> = kwargs.get("alt")?;
+            kwargs.assert_all_used()?;
+
+            if data.is_empty() {
+                return Err(Error::new(ErrorKind::InvalidOperation, "qr needs some `data` to encode"));
+            }
+            let svg = computed.get_or_make(Kind::Qr, &data, size, || qr_svg(&data, size))?;
+            Ok(Value::from_safe_string(dress(&svg, class.as_deref(), alt.as_deref(), false).unwrap_or_default()))
+        }
+    }
+
+    /// `avatar(email, size=64, class=none, alt=none)`: a pattern of squares that's the same every
+    /// time for the same address, for people without a photo
+    pub fn avatar_fn(&self) -> impl Fn(Kwargs) -> std::result::Result<Value, Error> + Send + Sync + 'static {
+        let computed = self.clone();
+        move |kwargs: Kwargs| {
+            let email: String = kwargs.get("email")?;
+            let size = checked_size(kwargs.get("size")?, DEFAULT_AVATAR_SIZE, "avatar")?;
+            let class: Option<String> = kwargs.get("class")?;
+            let alt: Option<String> = kwargs.get("alt")?;
+            kwargs.assert_all_used()?;
+
+            let email = email.trim().to_lowercase();
+            let svg = computed.get_or_make(Kind::Avatar, &email, size, || Ok(avatar_svg(&email, size)))?;
+            Ok(Value::from_safe_string(dress(&svg, class.as_deref(), alt.as_deref(), false).unwrap_or_default()))
+        }
+    }
+
+    fn get_or_make(
+        &self,
+        kind: Kind,
+        source: &str,
+        size: u32,
+        make: impl FnOnce() -> std::result::Result<String, Error>,
+    ) -> std::result::Result<Arc<str>, Error> {
+        let key = (kind, source.to_string(), size);
+        if let Some(svg) = self.made.lock().unwrap_or_else(PoisonError::into_inner).get(&key) {
+            return Ok(Arc::clone(svg));
+        }
+
+        let svg: Arc<str> = make()?.into();
+        self.made.lock().unwrap_or_else(PoisonError::into_inner).insert(key, Arc::clone(&svg));
+        Ok(svg)
+    }
+}
+
+/// `size`, or `default` when it's not given, as long as it's between the bounds
+fn checked_size(size: Option<i64>, default: u32, function: &str) -> std::result::Result<u32, Error> {
+    let Some(size) = size else { return Ok(default) };
+    u32::try_from(size).ok().filter(|size| (MIN_SIZE..=MAX_SIZE).contains(size)).ok_or_else(|| {
+        Error::new(
+            ErrorKind::InvalidOperation,
+            format!("{} takes a `size` from {} to {} pixels, not {}", function, MIN_SIZE, MAX_SIZE, size),
+        )
+    })
+}
+
+/// A QR code of `data`, drawn one unit per module (with the quiet zone around it) and scaled to
+/// `size` pixels
+fn qr_svg(data: &str, size: u32) -> std::result::Result<String, Error> {
+    let code = QrCode::new(data.as_bytes()).map_err(|e| {
+        Error::new(
+            ErrorKind::InvalidOperation,
+            format!("qr can't encode {} bytes of `data` ({}). A QR code holds about 2,300 at most, and scans best with far fewer, so try a shorter URL.", data.len(), e),
+        )
+    })?;
+
+    let svg = code.render::<svg::Color>().module_dimensions(1, 1).build();
+    // Modules are whole units of the viewBox, so only the width and height change with `size`
+    let modules = code.width() + 8;
+    Ok(svg.replacen(
+        &format!(r#"width="{0}" height="{0}""#, modules),
+        &format!(r#"width="{0}" height="{0}""#, size),
+        1,
+    ))
+}
+
+/// An identicon for `email`: a mirrored grid of squares in a color from its hash
+fn avatar_svg(email: &str, size: u32) -> String {
+    let hash = Sha256::digest(email.as_bytes());
+    let hue = u16::from_be_bytes([hash[0], hash[1]]) % 360;
+    let color = format!("hsl({}, 55%, 50%)", hue);
+
+    let half = AVATAR_CELLS.div_ceil(2);
+    let mut path = String::new();
+    for row in 0..AVATAR_CELLS {
+        for col in 0..half {
+            let bit = row * half + col;
+            if hash[2 + bit / 8] & (1 << (bit % 8)) == 0 {
+                continue;
+            }
+            for x in [col, AVATAR_CELLS - 1 - col] {
+                path.push_str(&format!("M{} {}h1v1h-1z", x + 1, row + 1));
+                if x == AVATAR_CELLS - 1 - x {
+                    break;
+                }
+            }
+        }
+    }
+
+    let view = AVATAR_CELLS + 2;
+    format!(
+        r##"<svg xmlns="http://www.w3.org/2000/svg" width="{size}" height="{size}" viewBox="0 0 {view} {view}" shape-rendering="crispEdges"><rect width="{view}" height="{view}" fill="#f0f0f0"/><path fill="{color}" d="{path}"/></svg>"##
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env() -> minijinja::Environment<'static> {
+        let computed = ComputedSvgs::default();
+        let mut env = minijinja::Environment::new();
+        env.add_function("qr", computed.qr_fn());
+        env.add_function("avatar", computed.avatar_fn());
+        env
+    }
+
+    #[test]
+    fn test_qr_codes_are_sized_svg() {
+        let env = env();
+        let svg = env.render_str(r#"{{ qr(data="https://example.com/vcard", size=120, class="qr", alt="Contact card") }}"#, ()).unwrap();
+        assert!(svg.starts_with(r#"<svg xmlns="http://www.w3.org/2000/svg" version="1.1" width="120" height="120" viewBox="0 0 "#), "{svg}");
+        assert!(svg.contains(r#"class="qr" role="img" aria-label="Contact card""#), "{svg}");
+        assert!(svg.ends_with("</svg>") && !svg.contains("<?xml"), "{svg}");
+
+        let plain = env.render_str(r#"{{ qr(data="hi") }}"#, ()).unwrap();
+        assert!(plain.contains(r#"width="200""#) && plain.contains(r#"aria-hidden="true""#), "{plain}");
+    }
+
+    #[test]
+    fn test_bad_sizes_and_data_are_explained() {
+        let env = env();
+        let err = env.render_str(r#"{{ qr(data="hi", size=4) }}"#, ()).unwrap_err();
+        assert!(err.to_string().contains("`size` from 16 to 2048 pixels, not 4"), "{err}");
+        let err = env.render_str(r#"{{ avatar(email="a@b.c", size=-1) }}"#, ()).unwrap_err();
+        assert!(err.to_string().contains("avatar takes a `size`"), "{err}");
+        let err = env.render_str(r#"{{ qr(data=data) }}"#, minijinja::context! { data => "x".repeat(5000) }).unwrap_err();
+        assert!(err.to_string().contains("can't encode 5000 bytes"), "{err}");
+        let err = env.render_str(r#"{{ qr(data="") }}"#, ()).unwrap_err();
+        assert!(err.to_string().contains("needs some `data`"), "{err}");
+    }
+
+    #[test]
+    fn test_avatars_are_the_same_for_the_same_address() {
+        let env = env();
+        let render = |email: &str| env.render_str(r#"{{ avatar(email=email) }}"#, minijinja::context! { email }).unwrap();
+        assert_eq!(render("Ada@Example.com "), render("ada@example.com"));
+        assert_ne!(render("ada@example.com"), render("grace@example.com"));
+        assert!(render("ada@example.com").contains(r#"width="64""#));
+    }
+
+    #[test]
+    fn test_images_are_made_once() {
+        let computed = ComputedSvgs::default();
+        let mut made = 0;
+        for _ in 0..3 {
+            computed
+                .get_or_make(Kind::Qr, "hi", 100, || {
+                    made += 1;
+                    qr_svg("hi", 100)
+                })
+                .unwrap();
+        }
+        assert_eq!(made, 1);
+        assert_eq!(computed.made.lock().unwrap().len(), 1);
+    }
+}
@@ -1,38 +1,283 @@
-use std::path::PathBuf;
+use std::collections::BTreeMap;
+use std::path::{Component, Path, PathBuf};
 
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use toml::Spanned;
 
+use crate::dates::{DatesConfig, Timezone};
 use crate::error::{HugsError, Result};
+use crate::url::{BaseUrl, SiteUrl, relative_path_to_url_string};
 
-#[derive(Debug, Clone, Deserialize, Default)]
+#[derive(Debug, Clone, Deserialize, Serialize, Default)]
 pub struct SiteConfig {
+    /// The last `hugs upgrade` migration the site has had
+    #[serde(default)]
+    pub upgrade_level: u32,
     #[serde(default)]
     pub site: SiteMetadata,
     #[serde(default)]
     pub feeds: Vec<FeedConfig>,
     #[serde(default)]
     pub build: BuildConfig,
+    #[serde(default)]
+    pub template: TemplateConfig,
+    #[serde(default)]
+    pub dev: DevConfig,
+    #[serde(default)]
+    pub assets: AssetsConfig,
+    #[serde(default)]
+    pub budgets: BudgetsConfig,
+    #[serde(default)]
+    pub hosting: HostingConfig,
+    #[serde(default)]
+    pub paths: PathsConfig,
+    #[serde(default)]
+    pub sri: SriConfig,
+    #[serde(default)]
+    pub migrate: MigrateConfig,
+    #[serde(default)]
+    pub dates: DatesConfig,
+    /// Named page lists, for `collection(name)` and feeds
+    #[serde(default)]
+    pub collections: BTreeMap<String, CollectionConfig>,
+
+    /// Raw contents of config.toml (empty when the file doesn't exist), kept for error spans
+    #[serde(skip)]
+    pub source: String,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct BuildConfig {
-    /// Enable HTML and CSS minification
-    #[serde(default = "default_true")]
-    pub minify: bool,
+    /// Enable HTML and CSS minification, or a `[build.minify]` table saying which kinds of file
+    #[serde(default)]
+    pub minify: Minify,
+
+    /// How pages are written; when unset, `minify` picks between minified and raw HTML
+    #[serde(default)]
+    pub html_output: Option<HtmlOutput>,
 
     /// Syntax highlighting configuration
     #[serde(default)]
     pub syntax_highlighting: SyntaxHighlightConfig,
 
+    /// Render every page even when some fail, reporting all the failures at the end
+    #[serde(default)]
+    pub keep_going: bool,
+
+    /// What `keep_going` leaves in place of a page that failed
+    #[serde(default)]
+    pub failed_pages: FailedPages,
+
     /// Reading speed in words per minute for readtime calculation
     #[serde(default = "default_reading_speed")]
     pub reading_speed: u32,
+
+    /// Reading speed in characters per minute, for pages in Chinese, Japanese or Korean
+    #[serde(default = "default_cjk_reading_speed")]
+    pub cjk_reading_speed: u32,
+
+    /// Site files that may be left empty without a warning, e.g. "_/footer.md"
+    #[serde(default = "default_allow_empty")]
+    pub allow_empty: Vec<String>,
+
+    /// Characters allowed in dynamic page parameter values, which end up in URLs
+    #[serde(default)]
+    pub dynamic_param_chars: ParamCharset,
+
+    /// Largest image `--single-file` embeds, in kilobytes; bigger ones stay links to the live site
+    #[serde(default = "default_single_file_image_limit_kb")]
+    pub single_file_image_limit_kb: u64,
+
+    /// Warn when pages link theme.css or highlight.css without `cache_bust()`
+    #[serde(default = "default_true")]
+    pub warn_unbusted_css: bool,
+
+    /// Pages whose rendered content is at least this many kilobytes are written to disk as they
+    /// render, without minification, instead of being built up in memory first
+    #[serde(default = "default_stream_pages_over_kb")]
+    pub stream_pages_over_kb: u64,
+
+    /// Largest page file I'll read, in kilobytes; bigger `.md` files are skipped with a warning
+    #[serde(default = "default_max_page_size_kb")]
+    pub max_page_size_kb: u64,
+
+    /// Leave out pages and static files that git ignores
+    #[serde(default)]
+    pub respect_gitignore: bool,
+
+    /// Frontmatter keys kept out of `pages()` and feeds, on top of any starting with `_`. The page
+    /// itself still sees them.
+    #[serde(default)]
+    pub private_frontmatter_keys: Vec<String>,
+
+    /// Warn about pages whose content is nearly the same as another page's
+    #[serde(default)]
+    pub duplicate_detection: bool,
+
+    /// How alike two pages must be for `duplicate_detection` to warn, from 0 to 1
+    #[serde(default = "default_duplicate_threshold")]
+    pub duplicate_threshold: f64,
+
+    /// Warn about pages that no other page and not the nav links to
+    #[serde(default)]
+    pub orphan_report: bool,
+
+    /// Warn about dynamic pages that make pages nothing links to, one warning per page file
+    #[serde(default)]
+    pub dynamic_orphan_report: bool,
+
+    /// Warn about pages whose `updated` or `date`, or last git commit, is older than the
+    /// `freshness_buckets`
+    #[serde(default)]
+    pub freshness_report: bool,
+
+    /// Ages in months past which `freshness_report` lists a page, each page under the oldest
+    #[serde(default = "default_freshness_buckets")]
+    pub freshness_buckets: Vec<u32>,
+
+    /// Fail the build when it has any warnings, the same as `hugs build --strict`
+    #[serde(default)]
+    pub strict: bool,
+
+    /// How many builds an entry in `.hugs-cache/` is kept for when none of them use it
+    #[serde(default = "default_cache_evict_after")]
+    pub cache_evict_after: u64,
+
+    /// Write text files with LF line endings, no trailing whitespace and one final newline
+    #[serde(default)]
+    pub output_normalize: bool,
+
+    /// Whether a page is written as `about/index.html` or `about.html`, and linked to to match
+    #[serde(default)]
+    pub url_style: UrlStyle,
+}
+
+/// What `hugs build` minifies: `minify = true` (the default) or `false` for pages and stylesheets,
+/// or a `[build.minify]` table to choose each kind of file
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum Minify {
+    All(bool),
+    Each(MinifyEach),
+}
+
+impl Default for Minify {
+    fn default() -> Self {
+        Minify::All(true)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct MinifyEach {
+    #[serde(default = "default_true")]
+    pub html: bool,
+    #[serde(default = "default_true")]
+    pub css: bool,
+    /// Static `.js` and `.mjs` files
+    #[serde(default)]
+    pub js: bool,
+    /// Static `.svg` files
+    #[serde(default)]
+    pub svg: bool,
+    /// Drop the ids of SVG elements nothing in the file or the site's CSS refers to
+    #[serde(default)]
+    pub svg_ids: bool,
+}
+
+impl Minify {
+    pub fn html(self) -> bool {
+        match self {
+            Minify::All(all) => all,
+            Minify::Each(each) => each.html,
+        }
+    }
+
+    pub fn css(self) -> bool {
+        match self {
+            Minify::All(all) => all,
+            Minify::Each(each) => each.css,
+        }
+    }
+
+    pub fn js(self) -> bool {
+        matches!(self, Minify::Each(MinifyEach { js: true, .. }))
+    }
+
+    pub fn svg(self) -> bool {
+        matches!(self, Minify::Each(MinifyEach { svg: true, .. }))
+    }
+
+    pub fn svg_ids(self) -> bool {
+        matches!(self, Minify::Each(MinifyEach { svg: true, svg_ids: true, .. }))
+    }
+}
+
+/// Which characters a dynamic page parameter value may use. Path separators, `?`, `#`, `%`,
+/// control characters and `.`/`..` are never allowed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ParamCharset {
+    /// Any other character, including spaces, accents and emoji (percent-encoded in links)
+    #[default]
+    Unicode,
+    /// Only ASCII letters, digits, `-`, `_`, `.` and `~`, which never need encoding
+    Ascii,
+}
+
+/// How built HTML is written out
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HtmlOutput {
+    /// Minified, the smallest files
+    Minify,
+    /// Consistently indented, so diffs of the output show only real content changes
+    Pretty,
+    /// Exactly as the templates rendered it
+    Raw,
+}
+
+impl BuildConfig {
+    pub fn html_output(&self) -> HtmlOutput {
+        self.html_output.unwrap_or(if self.minify.html() { HtmlOutput::Minify } else { HtmlOutput::Raw })
+    }
 }
 
 fn default_reading_speed() -> u32 {
     200
 }
 
+fn default_cjk_reading_speed() -> u32 {
+    500
+}
+
+fn default_single_file_image_limit_kb() -> u64 {
+    512
+}
+
+fn default_stream_pages_over_kb() -> u64 {
+    4096
+}
+
+fn default_max_page_size_kb() -> u64 {
+    10 * 1024
+}
+
+fn default_cache_evict_after() -> u64 {
+    10
+}
+
+fn default_duplicate_threshold() -> f64 {
+    0.9
+}
+
+fn default_freshness_buckets() -> Vec<u32> {
+    vec![6, 12, 24]
+}
+
+fn default_allow_empty() -> Vec<String> {
+    vec!["_/footer.md".to_string()]
+}
+
 fn default_true() -> bool {
     true
 }
@@ -40,14 +285,278 @@ fn default_true() -> bool {
 impl Default for BuildConfig {
     fn default() -> Self {
         Self {
-            minify: true,
+            minify: Minify::default(),
+            html_output: None,
             syntax_highlighting: SyntaxHighlightConfig::default(),
+            keep_going: false,
+            failed_pages: FailedPages::default(),
             reading_speed: default_reading_speed(),
+            cjk_reading_speed: default_cjk_reading_speed(),
+            allow_empty: default_allow_empty(),
+            dynamic_param_chars: ParamCharset::default(),
+            single_file_image_limit_kb: default_single_file_image_limit_kb(),
+            warn_unbusted_css: true,
+            stream_pages_over_kb: default_stream_pages_over_kb(),
+            max_page_size_kb: default_max_page_size_kb(),
+            respect_gitignore: false,
+            private_frontmatter_keys: Vec::new(),
+            duplicate_detection: false,
+            duplicate_threshold: default_duplicate_threshold(),
+            orphan_report: false,
+            dynamic_orphan_report: false,
+            freshness_report: false,
+            freshness_buckets: default_freshness_buckets(),
+            strict: false,
+            cache_evict_after: default_cache_evict_after(),
+            output_normalize: false,
+            url_style: UrlStyle::default(),
+        }
+    }
+}
+
+/// Page weight limits checked after `hugs build`, in kilobytes. A page can override any of them
+/// with a `budget:` mapping in its frontmatter.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct BudgetsConfig {
+    /// Size of the page's HTML file
+    pub html_kb: Option<u64>,
+    /// Local stylesheets the page loads, together
+    pub css_kb: Option<u64>,
+    /// The HTML plus every local stylesheet, script and image the page loads
+    pub page_total_kb: Option<u64>,
+    /// Fail the build instead of warning when a page is over budget
+    #[serde(default)]
+    pub fail: bool,
+}
+
+/// What a build that keeps going writes for a page that failed to render
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FailedPages {
+    /// Nothing, so the page is missing from the output
+    #[default]
+    Omit,
+    /// A short page saying it failed to build, so links to it still work
+    Placeholder,
+}
+
+/// What `hugs build` writes for the host serving the site
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct HostingConfig {
+    /// The host reading the `_headers` file
+    #[serde(default)]
+    pub provider: HostingProvider,
+    /// Add a `_headers` rule for each page preloading its stylesheets and fonts
+    #[serde(default)]
+    pub auto_preload_headers: bool,
+}
+
+/// Subresource Integrity for external scripts and stylesheets, added by `hugs build`
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct SriConfig {
+    /// Add `integrity` attributes to the tags loading files from `hosts`
+    #[serde(default)]
+    pub enabled: bool,
+    /// Hosts whose files are hashed, like "cdn.jsdelivr.net"
+    #[serde(default)]
+    pub hosts: Vec<String>,
+    /// Fetch pinned files again, and fail the build when one changed
+    #[serde(default)]
+    pub verify: bool,
+}
+
+/// Settings for `hugs migrate links`
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct MigrateConfig {
+    /// Frontmatter fields holding a URL (or a list of them) that are rewritten along with links
+    #[serde(default = "default_link_fields")]
+    pub link_fields: Vec<String>,
+}
+
+impl Default for MigrateConfig {
+    fn default() -> Self {
+        Self { link_fields: default_link_fields() }
+    }
+}
+
+fn default_link_fields() -> Vec<String> {
+    vec![String::from("image")]
+}
+
+/// Hosts that read a `_headers` file from the root of the output
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HostingProvider {
+    /// Cloudflare Pages, which sends `Link: rel=preload` headers as early hints
+    #[default]
+    Cloudflare,
+    Netlify,
+}
+
+impl HostingProvider {
+    pub fn name(self) -> &'static str {
+        match self {
+            HostingProvider::Cloudflare => "Cloudflare Pages",
+            HostingProvider::Netlify => "Netlify",
+        }
+    }
+
+    /// How many path rules the host reads from `_headers`, if it stops somewhere
+    pub fn header_rule_limit(self) -> Option<usize> {
+        match self {
+            HostingProvider::Cloudflare => Some(100),
+            HostingProvider::Netlify => None,
+        }
+    }
+}
+
+/// Folders inside the site for pages and static files, so the rest of a repository (a README, CI
+/// config) can sit at the site root without being published. `_/` and config.toml stay at the root.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct PathsConfig {
+    /// Folder of pages, whose layout inside it gives their URLs. Unset, it's the whole site
+    #[serde(default)]
+    pub content: Option<PathBuf>,
+    /// Folder of static files, published at the root of the site. Unset, it's the whole site
+    #[serde(default, rename = "static")]
+    pub static_files: Option<PathBuf>,
+}
+
+impl PathsConfig {
+    /// The content folder, relative to the site root (empty for the root itself)
+    pub fn content_dir(&self) -> &Path {
+        self.content.as_deref().unwrap_or(Path::new(""))
+    }
+
+    /// The static folder, relative to the site root (empty for the root itself)
+    pub fn static_dir(&self) -> &Path {
+        self.static_files.as_deref().unwrap_or(Path::new(""))
+    }
+
+    /// Where the page file at `file` (relative to the site root) sits in the content folder,
+    /// which is what its URL comes from
+    pub fn url_path<'a>(&self, file: &'a Path) -> &'a Path {
+        file.strip_prefix(self.content_dir()).unwrap_or(file)
+    }
+
+    /// The page file (relative to the site root) at `url_path` in the content folder
+    pub fn page_file(&self, url_path: &str) -> String {
+        relative_path_to_url_string(&self.content_dir().join(url_path))
+    }
+
+    /// Make sure each folder that's set is an existing folder inside `site_path`, other than `_/`
+    pub fn check(&self, site_path: &Path) -> Result<()> {
+        for (key, folder) in [("content", &self.content), ("static", &self.static_files)] {
+            let Some(folder) = folder else { continue };
+            let reason = if !folder.components().all(|c| matches!(c, Component::Normal(_))) {
+                Some("it has to be a folder inside the site")
+            } else if folder.to_string_lossy().starts_with('_') {
+                Some("folders starting with `_` hold the site's templates")
+            } else if !site_path.join(folder).is_dir() {
+                Some("there's no such folder in the site")
+            } else {
+                None
+            };
+            if let Some(reason) = reason {
+                return Err(HugsError::PathsFolder { key, folder: folder.into(), reason });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Which files outside `_/` are published as static assets, by glob pattern
+#[derive(Debug, Clone, Deserialize, Serialize, Default)]
+pub struct AssetsConfig {
+    /// Files to publish even when an exclude pattern (including a default one) matches
+    #[serde(default)]
+    pub include: Vec<String>,
+
+    /// Files to keep out of the site, on top of the default scripts and editor leftovers
+    #[serde(default)]
+    pub exclude: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct DevConfig {
+    /// Maximum number of rendered pages the dev server keeps in memory (0 disables caching)
+    #[serde(default = "default_render_cache_size")]
+    pub render_cache_size: usize,
+
+    /// Honor `?__hugs_delay`, `?__hugs_status` and `?__hugs_nominify` on page requests, for
+    /// testing loading and error states. Listed at `/__hugs/debug`.
+    #[serde(default)]
+    pub debug_tools: bool,
+
+    /// Origins that may fetch from the dev server cross-origin, or `"*"` for any
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub cors: Vec<String>,
+
+    /// Send CORS headers with HTML pages too, not just files and other responses
+    #[serde(default)]
+    pub cors_pages: bool,
+
+    /// Commands to run after the site reloads, before the browser does
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub on_reload: Vec<String>,
+
+    /// Seconds an `on_reload` command may run before it's stopped
+    #[serde(default = "default_on_reload_timeout_secs")]
+    pub on_reload_timeout_secs: u64,
+
+    /// Files the `on_reload` commands write, as `[assets]`-style patterns. Changes to them don't
+    /// reload the site, so a command can't keep setting itself off.
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub on_reload_outputs: Vec<String>,
+
+    /// Seconds a page may take to render before I stop waiting and show why (0 waits forever)
+    #[serde(default = "default_render_timeout_secs")]
+    pub render_timeout_secs: u64,
+}
+
+/// A single string or a list of them
+fn one_or_many<'de, D: serde::Deserializer<'de>>(deserializer: D) -> std::result::Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(one) => vec![one],
+        OneOrMany::Many(many) => many,
+    })
+}
+
+fn default_render_cache_size() -> usize {
+    256
+}
+
+fn default_on_reload_timeout_secs() -> u64 {
+    30
+}
+
+fn default_render_timeout_secs() -> u64 {
+    15
+}
+
+impl Default for DevConfig {
+    fn default() -> Self {
+        Self {
+            render_cache_size: default_render_cache_size(),
+            debug_tools: false,
+            cors: Vec::new(),
+            cors_pages: false,
+            on_reload: Vec::new(),
+            on_reload_timeout_secs: default_on_reload_timeout_secs(),
+            on_reload_outputs: Vec::new(),
+            render_timeout_secs: default_render_timeout_secs(),
         }
     }
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct SyntaxHighlightConfig {
     /// Enable syntax highlighting for code blocks
     #[serde(default = "default_true")]
@@ -56,26 +565,47 @@ pub struct SyntaxHighlightConfig {
     /// Theme name for syntax highlighting
     #[serde(default = "default_theme")]
     pub theme: String,
+
+    /// Pages with at least this many code blocks highlight them in parallel
+    #[serde(default = "default_parallel_blocks")]
+    pub parallel_blocks: usize,
+
+    /// Fence languages to highlight as another, like `shell = "bash"`, over the ones I know.
+    /// Languages I already have a grammar for keep it.
+    #[serde(default)]
+    pub aliases: BTreeMap<String, String>,
+
+    /// Guess the language of blocks in one I don't know, from a shebang line or how the code looks
+    #[serde(default)]
+    pub autodetect: bool,
 }
 
 fn default_theme() -> String {
     "one-dark-pro".to_string()
 }
 
+fn default_parallel_blocks() -> usize {
+    16
+}
+
 impl Default for SyntaxHighlightConfig {
     fn default() -> Self {
         Self {
             enabled: true,
             theme: default_theme(),
+            parallel_blocks: default_parallel_blocks(),
+            aliases: BTreeMap::new(),
+            autodetect: false,
         }
     }
 }
 
-#[derive(Debug, Clone, Deserialize, Default)]
+#[derive(Debug, Clone, Deserialize, Serialize, Default)]
 pub struct SiteMetadata {
     pub title: Option<String>,
     pub description: Option<String>,
-    pub url: Option<String>,
+    /// Where the site is published, which may have a path like `https://example.com/blog`
+    pub url: Option<BaseUrl>,
     pub author: Option<String>,
     #[serde(default = "default_language")]
     pub language: String,
@@ -85,29 +615,263 @@ pub struct SiteMetadata {
     pub title_template: Option<String>,
     /// Raw HTML to inject at the end of <head> (e.g. analytics scripts)
     pub head_extra: Option<String>,
+    /// Extra meta tags on every page, name -> content (prefix the name with `property:` for og-style tags)
+    #[serde(default)]
+    pub meta: BTreeMap<String, String>,
+    /// Whether page URLs keep the file names' characters or spell them in plain ASCII
+    #[serde(default)]
+    pub url_transliteration: UrlTransliteration,
+    /// The timezone of dates written without an offset, like `2024-01-15`
+    #[serde(default)]
+    pub timezone: Timezone,
+}
+
+impl SiteMetadata {
+    /// The absolute URL of `url` under `url` in `[site]`, or just its path when there's none
+    pub fn absolute_url(&self, url: &SiteUrl) -> String {
+        match &self.url {
+            Some(base) => base.absolute_url(url),
+            None => url.encoded().into_owned(),
+        }
+    }
+}
+
+/// How file names and dynamic parameter values become URL segments
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UrlTransliteration {
+    /// As written, percent-encoded in links where needed
+    #[default]
+    Keep,
+    /// Spelled in ASCII and slugified, so `Über uns` is served at `uber-uns`
+    Ascii,
+}
+
+/// How page URLs and the files pages are written to are shaped
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UrlStyle {
+    /// `/about`, written to `about/index.html`
+    #[default]
+    Directory,
+    /// `/about.html`, written to `about.html`, for hosts that don't serve a folder's index.html
+    Html,
 }
 
 fn default_language() -> String {
     "en-us".to_string()
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct FeedConfig {
     pub name: String,
     pub title: Option<String>,
     pub description: Option<String>,
-    pub source: String,
+    /// URL prefix of the pages in the feed
+    pub source: Option<String>,
+    /// Or a `[collections]` name, to take the feed's pages from
+    pub collection: Option<String>,
     pub output_rss: Option<String>,
     pub output_atom: Option<String>,
     #[serde(default = "default_limit")]
     pub limit: usize,
+    /// Template under `_/` rendered for each item, producing its description/content
+    pub item_template: Option<String>,
+    /// Include each page's rendered HTML in its item (as `content` in the item template)
+    #[serde(default)]
+    pub full_content: bool,
+    /// Write every item, `limit` per file, with later pages linked from earlier ones (RFC 5005)
+    #[serde(default)]
+    pub paginate: bool,
+    /// Most categories in one item, from its page's `tags` and `categories`
+    #[serde(default = "default_category_limit")]
+    pub category_limit: usize,
+    /// Dynamic page whose pages belong to the tags, like `blog/[tag].md`, for linking each category
+    pub category_pages: Option<String>,
 }
 
 fn default_limit() -> usize {
     20
 }
 
+fn default_category_limit() -> usize {
+    10
+}
+
+/// Which pages a page list holds and in what order, for `pages()` and `[collections]` filters
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct PageQuery {
+    /// Only pages under this URL, leaving out its index page
+    pub within: Option<String>,
+    /// Frontmatter key to sort by; unsorted pages come in the order I found them
+    pub sort_by: Option<String>,
+    /// `asc` or `desc`; newest first when sorting by `date`, A to Z otherwise
+    pub order: Option<ListOrder>,
+    /// At most this many pages, after sorting
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ListOrder {
+    Asc,
+    Desc,
+}
+
+/// `[collections.<name>]`: a named list of pages, either listed one by one or picked by a query
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(try_from = "CollectionFields", rename_all = "lowercase")]
+pub enum CollectionConfig {
+    /// `pages = [...]`: these page URLs, in this order
+    Pages(Vec<String>),
+    /// `filter = { ... }`: what `pages()` returns for the same arguments
+    Filter(PageQuery),
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CollectionFields {
+    pages: Option<Vec<String>>,
+    filter: Option<PageQuery>,
+}
+
+impl TryFrom<CollectionFields> for CollectionConfig {
+    type Error = &'static str;
+
+    fn try_from(fields: CollectionFields) -> std::result::Result<Self, Self::Error> {
+        match (fields.pages, fields.filter) {
+            (Some(pages), None) => Ok(Self::Pages(pages)),
+            (None, Some(filter)) => Ok(Self::Filter(filter)),
+            (Some(_), Some(_)) => Err("a collection takes either `pages` or `filter`, not both"),
+            (None, None) => Err("a collection needs `pages`, a list of page URLs, or `filter`, with the arguments pages() takes"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, Default)]
+pub struct TemplateConfig {
+    /// Reusable template functions, e.g.
+    /// `excerpt_url = { params = ["p"], body = "{{ p.url }}#excerpt" }`
+    #[serde(default)]
+    pub functions: BTreeMap<Spanned<String>, TemplateFunctionConfig>,
+
+    /// Drop the first newline after a block tag like `{% if %}`
+    #[serde(default)]
+    pub trim_blocks: bool,
+
+    /// Strip spaces and tabs before a block tag at the start of a line
+    #[serde(default)]
+    pub lstrip_blocks: bool,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct TemplateFunctionConfig {
+    /// Parameter names, in call order
+    #[serde(default)]
+    pub params: Vec<Spanned<String>>,
+    /// Jinja snippet producing the function's output
+    pub body: Spanned<String>,
+}
+
+/// Just enough of config.toml to find where `site.title_template` is written
+#[derive(Deserialize)]
+struct TitleTemplateLocation {
+    site: Option<SiteTitleTemplateLocation>,
+}
+
+#[derive(Deserialize)]
+struct SiteTitleTemplateLocation {
+    title_template: Option<Spanned<String>>,
+}
+
+/// Just enough of config.toml to see `site.url` as it's written, before it's tidied up
+#[derive(Deserialize)]
+struct SiteUrlLocation {
+    site: Option<SiteUrlWritten>,
+}
+
+#[derive(Deserialize)]
+struct SiteUrlWritten {
+    url: Option<String>,
+}
+
+/// `site.url` loses a trailing slash after a path when it loads, since page paths bring their
+/// own. A bare host's slash is how everyone writes it, so that one passes without a word.
+fn warn_site_url_trailing_slash(site: &SiteMetadata, source: &str) {
+    let Some(base) = site.url.as_ref().filter(|base| base.has_path()) else {
+        return;
+    };
+    let Ok(SiteUrlLocation { site: Some(SiteUrlWritten { url: Some(written) }) }) = toml::from_str(source) else {
+        return;
+    };
+    if written.trim().ends_with('/') {
+        crate::console::warn(format!(
+            "`url` in the [site] section of config.toml ends with a slash; I'm using `{}`, and pages go after it",
+            base
+        ));
+    }
+}
+
+#[derive(Deserialize)]
+struct HighlightThemeLocation {
+    build: Option<BuildHighlightThemeLocation>,
+}
+
+#[derive(Deserialize)]
+struct BuildHighlightThemeLocation {
+    syntax_highlighting: Option<SyntaxHighlightThemeLocation>,
+}
+
+#[derive(Deserialize)]
+struct SyntaxHighlightThemeLocation {
+    theme: Option<Spanned<String>>,
+    #[serde(default)]
+    aliases: BTreeMap<String, Spanned<String>>,
+}
+
+/// Where each feed's output file names are written in config.toml
+#[derive(Deserialize)]
+struct FeedsLocation {
+    #[serde(default)]
+    feeds: Vec<FeedOutputLocation>,
+}
+
+#[derive(Deserialize)]
+struct FeedOutputLocation {
+    output_rss: Option<Spanned<String>>,
+    output_atom: Option<Spanned<String>>,
+}
+
 impl SiteConfig {
+    /// Byte range of the `index`th feed's `output_rss` (or `output_atom` when `atom` is set)
+    /// value in config.toml, for error spans
+    pub fn feed_output_span(&self, index: usize, atom: bool) -> Option<std::ops::Range<usize>> {
+        let location: FeedsLocation = toml::from_str(&self.source).ok()?;
+        let feed = location.feeds.into_iter().nth(index)?;
+        let output = if atom { feed.output_atom } else { feed.output_rss };
+        Some(output?.span())
+    }
+
+    /// Byte range of the `title_template` value in config.toml, for error spans
+    pub fn title_template_span(&self) -> Option<std::ops::Range<usize>> {
+        let location: TitleTemplateLocation = toml::from_str(&self.source).ok()?;
+        Some(location.site?.title_template?.span())
+    }
+
+    /// Byte range of the `syntax_highlighting.theme` value in config.toml, for error spans
+    pub fn highlight_theme_span(&self) -> Option<std::ops::Range<usize>> {
+        let location: HighlightThemeLocation = toml::from_str(&self.source).ok()?;
+        Some(location.build?.syntax_highlighting?.theme?.span())
+    }
+
+    /// Byte range of the language `alias` points at under `syntax_highlighting.aliases` in
+    /// config.toml, for error spans
+    pub fn highlight_alias_span(&self, alias: &str) -> Option<std::ops::Range<usize>> {
+        let location: HighlightThemeLocation = toml::from_str(&self.source).ok()?;
+        Some(location.build?.syntax_highlighting?.aliases.get(alias)?.span())
+    }
+
     pub async fn load(site_path: &PathBuf) -> Result<Self> {
         let config_path = site_path.join("config.toml");
 
@@ -122,6 +886,10 @@ impl SiteConfig {
                 cause: e,
             })?;
 
-        toml::from_str(&content).map_err(|e| HugsError::config_parse(&config_path, &content, e))
+        let mut config: SiteConfig = toml::from_str(&content)
+            .map_err(|e| HugsError::config_parse(&config_path, &content, e))?;
+        warn_site_url_trailing_slash(&config.site, &content);
+        config.source = content;
+        Ok(config)
     }
 }
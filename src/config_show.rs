@@ -0,0 +1,393 @@
+//! `hugs config show`: the settings a build of the site would use, with every default filled in
+//! and each value marked with where it came from, a line of config.toml or my default. Keys in
+//! config.toml that aren't settings are reported too, since I'd otherwise ignore them silently.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::PathBuf;
+
+use serde_json::{Map, Value};
+use toml::de::{DeTable, DeValue};
+
+use crate::config::SiteConfig;
+use crate::console;
+use crate::error::{Result, find_best_match};
+
+/// How `hugs config show` prints the settings
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum ConfigFormat {
+    /// config.toml, with a comment after each value saying where it came from
+    Toml,
+    /// `config`, `sources` and `unknown_keys` objects, for scripts
+    Json,
+}
+
+/// Load the site's config the way `hugs build` does and print it in `format`
+pub async fn run_config_show(site_path: PathBuf, format: ConfigFormat) -> Result<()> {
+    let config = SiteConfig::load(&site_path).await?;
+    crate::run::validate_title_template(&config)?;
+    crate::feed::validate_feed_outputs(&config)?;
+    crate::highlight::init_registry()?;
+    crate::run::validate_highlighting(&config)?;
+
+    let snapshot = ConfigSnapshot::new(&config);
+    for unknown in snapshot.unknown_keys() {
+        console::warn(unknown);
+    }
+
+    match format {
+        ConfigFormat::Toml => print!("{}", snapshot.to_toml()),
+        ConfigFormat::Json => {
+            println!("{}", serde_json::to_string_pretty(&snapshot.to_json()).expect("the snapshot is plain JSON"))
+        }
+    }
+    Ok(())
+}
+
+/// One step into the config: a table key, or a position in an array of tables like `[[feeds]]`
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+type KeyPath = Vec<Segment>;
+
+/// A path as you'd describe it, like `build.syntax_highlighting.theme` or `feeds[0].name`
+fn display_path(path: &[Segment]) -> String {
+    let mut out = String::new();
+    for segment in path {
+        match segment {
+            Segment::Key(key) => {
+                if !out.is_empty() {
+                    out.push('.');
+                }
+                out.push_str(key);
+            }
+            Segment::Index(index) => {
+                let _ = write!(out, "[{}]", index);
+            }
+        }
+    }
+    out
+}
+
+/// A key in config.toml that isn't a setting
+#[derive(Debug, PartialEq)]
+pub struct UnknownKey {
+    pub path: String,
+    pub line: usize,
+    pub suggestion: Option<String>,
+}
+
+impl std::fmt::Display for UnknownKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "config.toml:{}: `{}` isn't a setting I know, so I ignore it", self.line, self.path)?;
+        if let Some(suggestion) = &self.suggestion {
+            write!(f, " (did you mean `{}`?)", suggestion)?;
+        }
+        Ok(())
+    }
+}
+
+pub struct ConfigSnapshot {
+    /// Every setting, defaults included
+    effective: Value,
+    /// The line of config.toml each key written there is on
+    lines: BTreeMap<KeyPath, usize>,
+}
+
+impl ConfigSnapshot {
+    pub fn new(config: &SiteConfig) -> Self {
+        let effective = serde_json::to_value(config).expect("the config is plain data");
+        let mut lines = BTreeMap::new();
+        if let Ok(table) = DeTable::parse(&config.source) {
+            collect_lines(&config.source, table.get_ref(), &mut Vec::new(), &mut lines);
+        }
+        Self { effective, lines }
+    }
+
+    /// Where the value at `path` came from
+    fn source(&self, path: &[Segment]) -> String {
+        match self.lines.get(path) {
+            Some(line) => format!("config.toml:{}", line),
+            None => String::from("default"),
+        }
+    }
+
+    /// Keys written in config.toml that no setting reads, outermost first. Under an unknown table
+    /// only the table itself is reported.
+    pub fn unknown_keys(&self) -> Vec<UnknownKey> {
+        let mut unknown: Vec<(&KeyPath, UnknownKey)> = Vec::new();
+        for (path, &line) in &self.lines {
+            if unknown.iter().any(|(reported, _)| path.starts_with(reported)) || lookup(&self.effective, path).is_some() {
+                continue;
+            }
+            let Some((Segment::Key(key), parent)) = path.split_last() else {
+                continue;
+            };
+            let siblings = lookup(&self.effective, parent)
+                .and_then(Value::as_object)
+                .map(|table| table.keys().cloned().collect::<Vec<_>>())
+                .unwrap_or_default();
+            let suggestion = find_best_match(key, &siblings).map(str::to_string);
+            unknown.push((path, UnknownKey { path: display_path(path), line, suggestion }));
+        }
+        unknown.into_iter().map(|(_, key)| key).collect()
+    }
+
+    /// The settings as config.toml, each value followed by where it came from. Unset optional
+    /// settings are listed as comments.
+    pub fn to_toml(&self) -> String {
+        let mut out = String::new();
+        if let Value::Object(root) = &self.effective {
+            self.write_table(&mut out, &mut Vec::new(), root);
+        }
+        out.trim_start().to_string()
+    }
+
+    fn write_table(&self, out: &mut String, path: &mut KeyPath, table: &Map<String, Value>) {
+        // Plain values first, since every key after a [header] belongs to that header's table
+        for (key, value) in table {
+            if is_table(value) || is_table_array(value) {
+                continue;
+            }
+            path.push(Segment::Key(key.clone()));
+            let _ = match value {
+                Value::Null => writeln!(out, "# {} is unset", toml_key(key)),
+                _ => writeln!(out, "{} = {}  # {}", toml_key(key), inline_value(value), self.source(path)),
+            };
+            path.pop();
+        }
+
+        for (key, value) in table {
+            path.push(Segment::Key(key.clone()));
+            match value {
+                Value::Object(inner) if is_table(value) => {
+                    let _ = writeln!(out, "\n[{}]", header(path));
+                    self.write_table(out, path, inner);
+                }
+                Value::Array(items) if is_table_array(value) => {
+                    for (index, item) in items.iter().enumerate() {
+                        path.push(Segment::Index(index));
+                        let _ = writeln!(out, "\n[[{}]]  # {}", header(path), self.source(path));
+                        if let Value::Object(inner) = item {
+                            self.write_table(out, path, inner);
+                        }
+                        path.pop();
+                    }
+                }
+                _ => {}
+            }
+            path.pop();
+        }
+    }
+
+    /// `config` holds every setting, `sources` says where each value came from by its path, and
+    /// `unknown_keys` lists keys in config.toml that aren't settings
+    pub fn to_json(&self) -> Value {
+        let mut sources = Map::new();
+        self.collect_sources(&self.effective, &mut Vec::new(), &mut sources);
+        let unknown_keys = self
+            .unknown_keys()
+            .into_iter()
+            .map(|key| serde_json::json!({ "key": key.path, "line": key.line, "suggestion": key.suggestion }))
+            .collect();
+
+        serde_json::json!({
+            "config": self.effective,
+            "sources": sources,
+            "unknown_keys": Value::Array(unknown_keys),
+        })
+    }
+
+    fn collect_sources(&self, value: &Value, path: &mut KeyPath, sources: &mut Map<String, Value>) {
+        match value {
+            Value::Object(table) if is_table(value) || path.is_empty() => {
+                for (key, value) in table {
+                    path.push(Segment::Key(key.clone()));
+                    self.collect_sources(value, path, sources);
+                    path.pop();
+                }
+            }
+            Value::Array(items) if is_table_array(value) => {
+                for (index, item) in items.iter().enumerate() {
+                    path.push(Segment::Index(index));
+                    self.collect_sources(item, path, sources);
+                    path.pop();
+                }
+            }
+            _ => {
+                sources.insert(display_path(path), Value::String(self.source(path)));
+            }
+        }
+    }
+}
+
+/// Note the line of every key in `table`, and in the tables under it
+fn collect_lines(source: &str, table: &DeTable, path: &mut KeyPath, lines: &mut BTreeMap<KeyPath, usize>) {
+    for (key, value) in table.iter() {
+        path.push(Segment::Key(key.get_ref().to_string()));
+        lines.insert(path.clone(), line_of(source, key.span().start));
+        match value.get_ref() {
+            DeValue::Table(inner) => collect_lines(source, inner, path, lines),
+            DeValue::Array(items) => {
+                for (index, item) in items.iter().enumerate() {
+                    if let DeValue::Table(inner) = item.get_ref() {
+                        path.push(Segment::Index(index));
+                        lines.insert(path.clone(), line_of(source, item.span().start));
+                        collect_lines(source, inner, path, lines);
+                        path.pop();
+                    }
+                }
+            }
+            _ => {}
+        }
+        path.pop();
+    }
+}
+
+fn line_of(source: &str, offset: usize) -> usize {
+    source[..offset.min(source.len())].matches('\n').count() + 1
+}
+
+fn lookup<'a>(value: &'a Value, path: &[Segment]) -> Option<&'a Value> {
+    path.iter().try_fold(value, |value, segment| match segment {
+        Segment::Key(key) => value.as_object()?.get(key),
+        Segment::Index(index) => value.as_array()?.get(*index),
+    })
+}
+
+/// Whether `value` is printed as its own `[table]` rather than inline
+fn is_table(value: &Value) -> bool {
+    value.as_object().is_some_and(|table| !table.is_empty())
+}
+
+/// Whether `value` is printed as `[[table]]` entries rather than inline
+fn is_table_array(value: &Value) -> bool {
+    value.as_array().is_some_and(|items| !items.is_empty() && items.iter().all(Value::is_object))
+}
+
+/// A table header's name. Positions in arrays of tables are left out, since a header after
+/// `[[feeds]]` already belongs to the latest feed.
+fn header(path: &[Segment]) -> String {
+    path.iter()
+        .filter_map(|segment| match segment {
+            Segment::Key(key) => Some(toml_key(key)),
+            Segment::Index(_) => None,
+        })
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+fn toml_key(key: &str) -> String {
+    if !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+        key.to_string()
+    } else {
+        Value::String(key.to_string()).to_string()
+    }
+}
+
+fn inline_value(value: &Value) -> String {
+    match value {
+        // JSON's string escapes are all valid in a TOML basic string
+        Value::String(_) | Value::Bool(_) | Value::Number(_) => value.to_string(),
+        Value::Array(items) => format!("[{}]", items.iter().map(inline_value).collect::<Vec<_>>().join(", ")),
+        Value::Object(table) if table.is_empty() => String::from("{}"),
+        Value::Object(table) => format!(
+            "{{ {} }}",
+            table
+                .iter()
+                .filter(|(_, value)| !value.is_null())
+                .map(|(key, value)| format!("{} = {}", toml_key(key), inline_value(value)))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        Value::Null => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(source: &str) -> ConfigSnapshot {
+        let mut config: SiteConfig = toml::from_str(source).unwrap();
+        config.source = source.to_string();
+        ConfigSnapshot::new(&config)
+    }
+
+    const SOURCE: &str = r#"[site]
+title = "Cozy"
+tagline = "hi"
+
+[build]
+minfy = false
+reading_speed = 250
+
+[build.syntax_highlighting]
+theme = "github-dark"
+
+[[feeds]]
+name = "blog"
+source = "/blog/"
+
+[collections.recent]
+filter = { within = "/blog/", limit = 3 }
+
+[deploy]
+target = "s3"
+"#;
+
+    #[test]
+    fn test_values_say_where_they_came_from() {
+        let toml = snapshot(SOURCE).to_toml();
+        let has = |line: &str| assert!(toml.lines().any(|l| l == line), "missing `{}` in:\n{}", line, toml);
+
+        has(r#"title = "Cozy"  # config.toml:2"#);
+        has("reading_speed = 250  # config.toml:7");
+        has("minify = true  # default");
+        has(r#"theme = "github-dark"  # config.toml:10"#);
+        has(r#"allow_empty = ["_/footer.md"]  # default"#);
+        has("# html_output is unset");
+        has("[[feeds]]  # config.toml:12");
+        has(r#"name = "blog"  # config.toml:13"#);
+        has("limit = 20  # default");
+        has("[collections.recent.filter]");
+        has("limit = 3  # config.toml:17");
+        has("[build.syntax_highlighting]");
+
+        // What it prints is a config.toml meaning the same thing
+        let reparsed: SiteConfig = toml::from_str(&toml).unwrap();
+        assert_eq!(reparsed.build.reading_speed, 250);
+        assert_eq!(reparsed.feeds[0].source.as_deref(), Some("/blog/"));
+        assert_eq!(reparsed.build.syntax_highlighting.theme, "github-dark");
+    }
+
+    #[test]
+    fn test_unknown_keys_are_reported() {
+        let unknown = snapshot(SOURCE).unknown_keys();
+        assert_eq!(
+            unknown,
+            vec![
+                UnknownKey { path: String::from("build.minfy"), line: 6, suggestion: Some(String::from("minify")) },
+                UnknownKey { path: String::from("deploy"), line: 19, suggestion: None },
+                UnknownKey { path: String::from("site.tagline"), line: 3, suggestion: None },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_json_sources_sit_beside_the_config() {
+        let json = snapshot(SOURCE).to_json();
+        assert_eq!(json["config"]["build"]["reading_speed"], 250);
+        assert_eq!(json["sources"]["build.reading_speed"], "config.toml:7");
+        assert_eq!(json["sources"]["build.minify"], "default");
+        assert_eq!(json["sources"]["feeds[0].name"], "config.toml:13");
+        assert_eq!(json["unknown_keys"][0]["key"], "build.minfy");
+
+        // Without a config.toml, everything is a default
+        let json = snapshot("").to_json();
+        assert!(json["sources"].as_object().unwrap().values().all(|source| source == "default"));
+    }
+}
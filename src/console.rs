@@ -1,3 +1,5 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
 use indicatif::{ProgressBar, ProgressStyle};
 use owo_colors::OwoColorize;
 
@@ -13,10 +15,18 @@ pub fn status_cyan(verb: &str, message: impl std::fmt::Display) {
     eprintln!("{} {}", status_style(verb, owo_colors::AnsiColors::Cyan), message);
 }
 
+/// How many warnings `warn` has printed, for `hugs build --strict` to count
+static WARNINGS_PRINTED: AtomicUsize = AtomicUsize::new(0);
+
 pub fn warn(message: impl std::fmt::Display) {
+    WARNINGS_PRINTED.fetch_add(1, Ordering::Relaxed);
     eprintln!("{} {}", status_style("Warning", owo_colors::AnsiColors::Yellow), message);
 }
 
+pub fn warnings_printed() -> usize {
+    WARNINGS_PRINTED.load(Ordering::Relaxed)
+}
+
 pub fn create_progress_bar(total: u64, message: &str) -> ProgressBar {
     let pb = ProgressBar::new(total);
     pb.set_style(
@@ -0,0 +1,295 @@
+//! Content templates, which wrap each page's body. `_/content.md` wraps every page, and a
+//! `_content.md` inside a content folder wraps the pages under it instead, so `blog/_content.md`
+//! can give posts a byline while `docs/_content.md` gives the docs an edit link. A page uses the
+//! nearest one above it: its folder's, then its parent folder's, up to `_/content.md`.
+//!
+//! Rendering goes one way. The page body renders first, with `content` and `main_content` empty.
+//! The content template gets the body as `content` and renders into `main_content`, which only
+//! the root template uses. So a content template can't use `main_content` (it's what the template
+//! is making), and printing `content` twice prints the page twice.
+
+use std::borrow::Cow;
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, LazyLock, Mutex, PoisonError};
+
+use miette::{NamedSource, SourceSpan};
+use regex::Regex;
+
+use crate::console;
+use crate::error::{HugsError, HugsResultExt, Result};
+use crate::run::TemplateError;
+use crate::url::relative_path_to_url_string;
+
+/// File name of a content template for the folder it's in
+pub const SECTION_TEMPLATE_FILE: &str = "_content.md";
+
+/// The template pages render inside when the site has no `_/content.md`
+const DEFAULT_TEMPLATE: &str = "{{ content }}";
+
+/// `{{ content }}`, `{{- content | safe }}` and the like, each place a template prints the page,
+/// along with the `if` tags that decide which of them print
+static CONTENT_OUTPUT: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"\{\{[-+]?\s*content\s*(?:\|[^}]*)?[-+]?\}\}|\{%[-+]?\s*(if|elif|else|endif)\b").unwrap()
+});
+/// `main_content` inside a tag, to point an error at
+static MAIN_CONTENT_USE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\{[{%][^}]*?\b(main_content)\b").unwrap());
+/// `content` or `main_content` printed in a page body, where they're always empty
+static BODY_CONTENT_OUTPUT: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\{\{[-+]?\s*((?:main_)?content)\s*(?:\|[^}]*)?[-+]?\}\}").unwrap());
+
+/// `{# comments #}` and `{% raw %}` blocks, which talk about `content` without printing it
+static NOT_CODE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?s)\{#.*?#\}|\{%[-+]?\s*raw\s*[-+]?%\}.*?\{%[-+]?\s*endraw\s*[-+]?%\}").unwrap());
+
+#[derive(Debug)]
+pub struct ContentTemplate {
+    /// Where it came from, relative to the site root, for naming it in errors
+    pub file: String,
+    pub template: String,
+}
+
+impl ContentTemplate {
+    /// An error rendering this template, named by the file it came from
+    pub fn render_error(&self, e: &TemplateError) -> HugsError {
+        HugsError::template_render_named(&self.file, &self.template, &e.error, &e.hints, e.macro_prefix_bytes, e.macro_prefix_lines)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ContentTemplates {
+    /// `_/content.md`, or `{{ content }}` without one
+    site: Arc<ContentTemplate>,
+    /// `_content.md` files by the folder they're in, like `blog` or `docs/api`
+    sections: HashMap<String, Arc<ContentTemplate>>,
+    /// The template each page folder resolved to, since every page in a folder uses the same one
+    resolved: Arc<Mutex<HashMap<String, Arc<ContentTemplate>>>>,
+}
+
+impl ContentTemplates {
+    /// Read `_/content.md` (given as `site`, when there is one) and the `_content.md` files in
+    /// the folders above the pages at `page_files` (relative to the site root)
+    pub async fn load(site_path: &Path, site: Option<String>, page_files: impl IntoIterator<Item = &str>) -> Result<Self> {
+        let site = Arc::new(match site {
+            Some(template) => {
+                if let Some(e) = check_content_template("_/content.md", &template, "every page") {
+                    return Err(e);
+                }
+                ContentTemplate { file: String::from("_/content.md"), template }
+            }
+            None => ContentTemplate { file: String::from("_/content.md"), template: String::from(DEFAULT_TEMPLATE) },
+        });
+
+        let mut sections = HashMap::new();
+        for path in section_template_paths(site_path, page_files) {
+            let relative = path.strip_prefix(site_path).unwrap_or(&path);
+            let file = relative_path_to_url_string(relative);
+            let dir = file.strip_suffix(SECTION_TEMPLATE_FILE).unwrap_or("").trim_end_matches('/').to_string();
+            let template = tokio::fs::read_to_string(&path).await.with_file_read(&path)?;
+            if let Some(e) = check_content_template(&file, &template, &format!("every page in {}/", dir)) {
+                return Err(e);
+            }
+            sections.insert(dir, Arc::new(ContentTemplate { file, template }));
+        }
+
+        Ok(Self { site, sections, resolved: Arc::default() })
+    }
+
+    /// The template for the page file at `source` (relative to the site root): the `_content.md`
+    /// nearest above it, or the site's
+    pub fn for_page(&self, source: &str) -> Arc<ContentTemplate> {
+        let source = source.replace('\\', "/");
+        let dir = source.rsplit_once('/').map_or("", |(dir, _)| dir);
+
+        let mut resolved = self.resolved.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(template) = resolved.get(dir) {
+            return Arc::clone(template);
+        }
+
+        let mut folder = dir;
+        let template = loop {
+            if let Some(template) = self.sections.get(folder) {
+                break Arc::clone(template);
+            }
+            match folder.rsplit_once('/') {
+                Some((parent, _)) => folder = parent,
+                None if !folder.is_empty() => folder = "",
+                None => break Arc::clone(&self.site),
+            }
+        };
+        resolved.insert(dir.to_string(), Arc::clone(&template));
+        template
+    }
+}
+
+/// Whether `path` (relative to the site root) is a folder's content template rather than a page
+pub fn is_section_template(path: &Path) -> bool {
+    path.file_name().is_some_and(|name| name == SECTION_TEMPLATE_FILE)
+}
+
+/// The `_content.md` files that could wrap the pages at `page_files`: those in each page's
+/// folder and the folders above it, short of the site root, sorted by path
+fn section_template_paths<'a>(site_path: &Path, page_files: impl IntoIterator<Item = &'a str>) -> Vec<PathBuf> {
+    let mut folders = BTreeSet::new();
+    for file in page_files {
+        let mut folder = file.replace('\\', "/");
+        while let Some((parent, _)) = folder.rsplit_once('/') {
+            folder = parent.to_string();
+            if !folders.insert(folder.clone()) {
+                break;
+            }
+        }
+    }
+
+    folders
+        .into_iter()
+        .map(|folder| site_path.join(folder).join(SECTION_TEMPLATE_FILE))
+        .filter(|path| path.is_file())
+        .collect()
+}
+
+/// Hold a content template to the rendering contract. Using `main_content`, which it's making,
+/// is the error returned, and printing `content` other than once is warned about.
+fn check_content_template(file: &str, template: &str, pages: &str) -> Option<HugsError> {
+    if uses_variable(template, "main_content") == Some(true) {
+        let span = MAIN_CONTENT_USE
+            .captures(&template_code(template))
+            .and_then(|caps| caps.get(1))
+            .map_or(SourceSpan::new(0.into(), 0), |name| SourceSpan::new(name.start().into(), name.len()));
+        return Some(HugsError::ContentTemplateUsesMainContent {
+            file: file.into(),
+            src: NamedSource::new(file, template.to_string()),
+            span,
+        });
+    }
+
+    if !content_template_uses_content(template) {
+        console::warn(format!("{} never uses {{{{ content }}}}, so {} will render without its body", file, pages));
+    }
+    let printed = times_printed(template);
+    if printed > 1 {
+        console::warn(format!(
+            "{} prints {{{{ content }}}} {} times, so {} will show its body {} times",
+            file, printed, pages, printed
+        ));
+    }
+    None
+}
+
+/// `template` with its comments and raw blocks blanked out, keeping the rest where it was
+fn template_code(template: &str) -> Cow<'_, str> {
+    NOT_CODE.replace_all(template, |caps: &regex::Captures| " ".repeat(caps[0].len()))
+}
+
+/// The most times a template prints `content` in one render. Branches of an `if` are counted
+/// apart, since only one of them prints.
+fn times_printed(template: &str) -> usize {
+    // For each `if` open around this point, the most any finished branch printed and what the
+    // current one has so far. The bottom entry is the template itself.
+    let mut branches: Vec<(usize, usize)> = vec![(0, 0)];
+    for caps in CONTENT_OUTPUT.captures_iter(&template_code(template)) {
+        match caps.get(1).map(|tag| tag.as_str()) {
+            None => branches.last_mut().unwrap().1 += 1,
+            Some("if") => branches.push((0, 0)),
+            Some("elif" | "else") => {
+                let (most, current) = branches.last_mut().unwrap();
+                *most = (*most).max(*current);
+                *current = 0;
+            }
+            _ if branches.len() > 1 => {
+                let (most, current) = branches.pop().unwrap();
+                branches.last_mut().unwrap().1 += most.max(current);
+            }
+            _ => {}
+        }
+    }
+    branches.iter().map(|(most, current)| (*most).max(*current)).sum()
+}
+
+/// The variable a page body prints that's always empty there, `content` or `main_content`. The
+/// body is what becomes `content`, so it can't include itself.
+pub fn body_prints_content(body: &str) -> Option<&'static str> {
+    let code = template_code(body);
+    let caps = BODY_CONTENT_OUTPUT.captures(&code)?;
+    Some(if &caps[1] == "main_content" { "main_content" } else { "content" })
+}
+
+/// Whether a template reads the variable `name`, or none if it doesn't parse. Those are left for
+/// the renderer to report.
+fn uses_variable(template: &str, name: &str) -> Option<bool> {
+    let env = minijinja::Environment::new();
+    env.template_from_str(template).ok().map(|tmpl| tmpl.undeclared_variables(false).contains(name))
+}
+
+/// Whether a content template ever reads `content`. Templates that don't parse are left for the
+/// renderer to report.
+pub fn content_template_uses_content(template: &str) -> bool {
+    uses_variable(template, "content").unwrap_or(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn templates(sections: &[&str]) -> ContentTemplates {
+        let template = |file: &str| Arc::new(ContentTemplate { file: file.to_string(), template: String::from(DEFAULT_TEMPLATE) });
+        ContentTemplates {
+            site: template("_/content.md"),
+            sections: sections
+                .iter()
+                .map(|dir| (dir.to_string(), template(&format!("{}/{}", dir, SECTION_TEMPLATE_FILE))))
+                .collect(),
+            resolved: Arc::default(),
+        }
+    }
+
+    #[test]
+    fn test_content_templates_keep_the_contract() {
+        assert!(check_content_template("_/content.md", "<article>{{ content }}</article>", "every page").is_none());
+        // Twice is only a warning, and setting it aside to print once is fine
+        assert!(check_content_template("_/content.md", "{{ content }}\n{{- content | safe -}}", "every page").is_none());
+        assert_eq!(times_printed("{{ content }}\n{{- content | safe -}}\n{{ contents }}"), 2);
+        // Only one branch of an `if` prints
+        assert_eq!(
+            times_printed("{% if blog %}<article>{{ content }}</article>{% elif docs %}{{ content }}{% else %}{{ content }}{% endif %}"),
+            1
+        );
+        assert_eq!(times_printed("{# print {{ content }} once #}{% raw %}{{ content }}{% endraw %}{{ content }}"), 1);
+        assert_eq!(times_printed("{% if wide %}{{ content }}{{ content }}{% endif %}\n{{ content }}"), 3);
+
+        let Some(HugsError::ContentTemplateUsesMainContent { span, .. }) =
+            check_content_template("blog/_content.md", "# {{ title }}\n{% set x = main_content %}", "every page in blog/")
+        else {
+            panic!("main_content should be an error");
+        };
+        assert_eq!((span.offset(), span.len()), (25, 12));
+    }
+
+    #[test]
+    fn test_page_bodies_printing_content_are_caught() {
+        assert_eq!(body_prints_content("Intro\n\n{{ content }}"), Some("content"));
+        assert_eq!(body_prints_content("{{- main_content | safe }}"), Some("main_content"));
+        assert_eq!(body_prints_content("{{ page.content }} and {{ contents }}"), None);
+        assert_eq!(body_prints_content("Write `{% raw %}{{ content }}{% endraw %}` to show templates"), None);
+    }
+
+    #[test]
+    fn test_nearest_template_wins() {
+        let templates = templates(&["blog", "docs", "docs/api"]);
+        let file = |source: &str| templates.for_page(source).file.clone();
+
+        assert_eq!(file("index.md"), "_/content.md");
+        assert_eq!(file("about.md"), "_/content.md");
+        assert_eq!(file("blog/index.md"), "blog/_content.md");
+        assert_eq!(file("blog/2024/recap.md"), "blog/_content.md");
+        assert_eq!(file("blog/[tag].md"), "blog/_content.md");
+        assert_eq!(file("docs/api/client.md"), "docs/api/_content.md");
+        assert_eq!(file("docs/guide.md"), "docs/_content.md");
+        assert_eq!(file("blogroll/links.md"), "_/content.md");
+        assert_eq!(file("docs\\api\\server.md"), "docs/api/_content.md");
+
+        // Asking again comes from the per-folder cache, with the same answer
+        assert_eq!(file("blog/2024/other.md"), "blog/_content.md");
+        assert!(templates.resolved.lock().unwrap().contains_key("blog/2024"));
+    }
+}
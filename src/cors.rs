@@ -0,0 +1,95 @@
+//! Cross-origin requests to `hugs dev`, for a frontend on another localhost port that fetches the
+//! site's files. The origins in `dev.cors` get answers to their preflight `OPTIONS` requests and
+//! `Access-Control-Allow-Origin` on everything but HTML pages, unless `dev.cors_pages` is on too.
+
+use std::sync::Arc;
+
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{self, HeaderValue};
+use actix_web::http::Method;
+use actix_web::middleware::Next;
+use actix_web::{HttpResponse, web};
+
+use crate::config::DevConfig;
+use crate::dev::DevAppState;
+
+/// How long a browser may remember a preflight answer, in seconds
+const PREFLIGHT_MAX_AGE: &str = "600";
+
+/// The `Access-Control-Allow-Origin` value for `origin`, when the site lets it in
+fn allowed_origin(dev: &DevConfig, origin: &str) -> Option<String> {
+    let origin = origin.trim_end_matches('/');
+    dev.cors.iter().find_map(|allowed| match allowed.trim_end_matches('/') {
+        "*" => Some(String::from("*")),
+        allowed if allowed.eq_ignore_ascii_case(origin) => Some(origin.to_string()),
+        _ => None,
+    })
+}
+
+fn is_html(res: &ServiceResponse<impl MessageBody>) -> bool {
+    res.headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("text/html"))
+}
+
+/// Middleware for the dev server: answers preflights and marks responses readable cross-origin
+pub async fn cors(req: ServiceRequest, next: Next<impl MessageBody + 'static>) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let site = match req.app_data::<web::Data<Arc<DevAppState>>>() {
+        Some(state) => state.snapshot().await,
+        None => return Ok(next.call(req).await?.map_into_boxed_body()),
+    };
+    let Some(app_data) = site.app_data() else {
+        return Ok(next.call(req).await?.map_into_boxed_body());
+    };
+    let dev = &app_data.config.dev;
+    let allow = req
+        .headers()
+        .get(header::ORIGIN)
+        .and_then(|origin| origin.to_str().ok())
+        .and_then(|origin| allowed_origin(dev, origin))
+        .and_then(|allow| HeaderValue::from_str(&allow).ok());
+    let Some(allow) = allow else {
+        return Ok(next.call(req).await?.map_into_boxed_body());
+    };
+
+    if req.method() == Method::OPTIONS && req.headers().contains_key(header::ACCESS_CONTROL_REQUEST_METHOD) {
+        let mut preflight = HttpResponse::NoContent();
+        preflight
+            .insert_header((header::ACCESS_CONTROL_ALLOW_ORIGIN, allow))
+            .insert_header((header::ACCESS_CONTROL_ALLOW_METHODS, "GET, HEAD, OPTIONS"))
+            .insert_header((header::ACCESS_CONTROL_MAX_AGE, PREFLIGHT_MAX_AGE))
+            .insert_header((header::VARY, "Origin"));
+        if let Some(headers) = req.headers().get(header::ACCESS_CONTROL_REQUEST_HEADERS) {
+            preflight.insert_header((header::ACCESS_CONTROL_ALLOW_HEADERS, headers.clone()));
+        }
+        return Ok(req.into_response(preflight.finish()));
+    }
+
+    let cors_pages = dev.cors_pages;
+    let mut res = next.call(req).await?;
+    if cors_pages || !is_html(&res) {
+        let headers = res.headers_mut();
+        headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, allow);
+        headers.append(header::VARY, HeaderValue::from_static("Origin"));
+    }
+    Ok(res.map_into_boxed_body())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_allowed_origins() {
+        let dev = |cors: &[&str]| DevConfig { cors: cors.iter().map(|origin| origin.to_string()).collect(), ..DevConfig::default() };
+
+        let listed = dev(&["http://localhost:5173/", "http://127.0.0.1:3000"]);
+        assert_eq!(allowed_origin(&listed, "http://localhost:5173"), Some(String::from("http://localhost:5173")));
+        assert_eq!(allowed_origin(&listed, "http://127.0.0.1:3000"), Some(String::from("http://127.0.0.1:3000")));
+        assert_eq!(allowed_origin(&listed, "http://localhost:3000"), None);
+        assert_eq!(allowed_origin(&dev(&["*"]), "https://anywhere.test"), Some(String::from("*")));
+        assert_eq!(allowed_origin(&dev(&[]), "http://localhost:5173"), None);
+    }
+}
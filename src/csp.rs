@@ -0,0 +1,145 @@
+//! Content-Security-Policy meta tags, read in `hugs dev` so a strict policy on the site doesn't
+//! quietly stop the live reload script from loading.
+//!
+//! Only `<meta http-equiv="Content-Security-Policy">` is checked: the dev server sends no CSP
+//! header of its own, so that's the only way a policy gets into the page.
+
+use std::sync::LazyLock;
+
+use regex::Regex;
+
+static META_TAG: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?is)<meta\b[^>]*>").unwrap());
+
+static ATTRIBUTE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"(?is)([a-z-]+)\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s"'>]+))"#).unwrap());
+
+/// Directives that decide whether a `<script src>` loads, most specific first
+const SCRIPT_DIRECTIVES: [&str; 3] = ["script-src-elem", "script-src", "default-src"];
+
+/// Sources that let a script load from the page's own origin
+const SELF_SOURCES: [&str; 3] = ["'self'", "*", "http:"];
+
+/// The policy in each CSP meta tag in `html`
+fn meta_policies(html: &str) -> Vec<String> {
+    META_TAG
+        .find_iter(html)
+        .filter_map(|tag| {
+            let mut http_equiv = None;
+            let mut content = None;
+            for caps in ATTRIBUTE.captures_iter(tag.as_str()) {
+                let value = caps.get(2).or(caps.get(3)).or(caps.get(4)).map_or("", |m| m.as_str());
+                match caps[1].to_ascii_lowercase().as_str() {
+                    "http-equiv" => http_equiv = Some(value),
+                    "content" => content = Some(value),
+                    _ => {}
+                }
+            }
+            http_equiv
+                .filter(|name| name.eq_ignore_ascii_case("content-security-policy"))
+                .and(content)
+                .map(unescape_attribute)
+        })
+        .collect()
+}
+
+/// The directive to put in the page's policy so it allows a script from the page's own origin,
+/// or `None` when every policy already does
+pub fn self_script_fix(html: &str) -> Option<String> {
+    meta_policies(html).iter().find_map(|policy| {
+        let directives = parse_policy(policy);
+        let (name, sources) = SCRIPT_DIRECTIVES
+            .iter()
+            .find_map(|name| directives.iter().find(|(directive, _)| directive == name))?;
+
+        let allowed = sources.iter().any(|source| SELF_SOURCES.contains(&source.to_ascii_lowercase().as_str()));
+        if allowed {
+            return None;
+        }
+
+        // `'none'` can't be combined with anything, so it goes
+        let mut fixed: Vec<&str> = sources
+            .iter()
+            .map(String::as_str)
+            .filter(|source| !source.eq_ignore_ascii_case("'none'"))
+            .collect();
+        fixed.push("'self'");
+        // Widening `default-src` would let in more than scripts
+        let name = if name == "default-src" { "script-src" } else { name.as_str() };
+        Some(format!("{} {}", name, fixed.join(" ")))
+    })
+}
+
+/// A policy's directives in order, with their sources. Only the first of a repeated directive
+/// counts, as in browsers.
+fn parse_policy(policy: &str) -> Vec<(String, Vec<String>)> {
+    let mut directives: Vec<(String, Vec<String>)> = Vec::new();
+    for directive in policy.split(';') {
+        let mut parts = directive.split_ascii_whitespace();
+        let Some(name) = parts.next() else {
+            continue;
+        };
+        let name = name.to_ascii_lowercase();
+        if directives.iter().any(|(existing, _)| *existing == name) {
+            continue;
+        }
+        directives.push((name, parts.map(str::to_string).collect()));
+    }
+    directives
+}
+
+fn unescape_attribute(value: &str) -> String {
+    value
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(policy: &str) -> String {
+        format!(
+            r#"<html><head><meta charset="utf-8"><meta http-equiv="Content-Security-Policy" content="{}"></head></html>"#,
+            policy
+        )
+    }
+
+    #[test]
+    fn test_policies_that_allow_the_script() {
+        for policy in [
+            "default-src 'self'",
+            "script-src 'self' https://cdn.example.com",
+            "default-src 'self'; img-src 'none'",
+            "script-src *",
+            "default-src 'none'; script-src-elem 'SELF'",
+        ] {
+            assert_eq!(self_script_fix(&page(policy)), None, "{policy}");
+        }
+        assert_eq!(self_script_fix("<html><head><title>No policy</title></head></html>"), None);
+        assert_eq!(self_script_fix(r#"<meta name="description" content="script-src 'none'">"#), None);
+    }
+
+    #[test]
+    fn test_fix_keeps_the_existing_sources() {
+        assert_eq!(
+            self_script_fix(&page("default-src 'self'; script-src https://cdn.example.com 'sha256-abc'")).as_deref(),
+            Some("script-src https://cdn.example.com 'sha256-abc' 'self'")
+        );
+        assert_eq!(
+            self_script_fix(&page("default-src 'none'; style-src 'self'")).as_deref(),
+            Some("script-src 'self'")
+        );
+        assert_eq!(
+            self_script_fix(&page("script-src 'none'; script-src 'self'")).as_deref(),
+            Some("script-src 'self'")
+        );
+    }
+
+    #[test]
+    fn test_single_quoted_and_escaped_attributes() {
+        let html = r#"<META HTTP-EQUIV='content-security-policy' CONTENT="script-src &#39;unsafe-inline&#39;">"#;
+        assert_eq!(self_script_fix(html).as_deref(), Some("script-src 'unsafe-inline' 'self'"));
+    }
+}
@@ -0,0 +1,309 @@
+//! Reading the dates in frontmatter and the values given to `datefmt`.
+//!
+//! Both read dates the same way: RFC 3339 and RFC 2822 carry their own offset, while dates without
+//! one (`2024-01-15`, `2024-01-15 10:30:00`, `15 Jan 2024`, and whatever `[dates] formats` adds)
+//! are read in the site's `timezone`, so a post dated `2024-01-15` in Tokyo is still published on
+//! the 15th there. A named timezone like `America/New_York` follows its daylight saving changes.
+
+use std::fmt;
+
+use chrono::format::{Parsed, StrftimeItems, parse};
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, TimeDelta, TimeZone, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+
+use crate::config::SiteConfig;
+use crate::console;
+
+/// The formats of dates without an offset I read without being told to, and how to describe them
+const NAIVE_FORMATS: &[(&str, &str)] = &[
+    ("%Y-%m-%d %H:%M:%S", "YYYY-MM-DD HH:MM:SS"),
+    ("%Y-%m-%dT%H:%M:%S", "YYYY-MM-DDTHH:MM:SS"),
+    ("%Y-%m-%d", "YYYY-MM-DD"),
+    ("%d %b %Y", "DD Mon YYYY"),
+    ("%d %B %Y", "DD Month YYYY"),
+];
+
+/// The timezone dates without an offset are in, written as `UTC`, an IANA name like
+/// `Asia/Tokyo`, or an offset like `+05:30`
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Timezone(Zone);
+
+/// A named zone follows its daylight saving changes; an offset is the same all year
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Zone {
+    Offset(FixedOffset),
+    Named(Tz),
+}
+
+impl Default for Timezone {
+    fn default() -> Self {
+        Timezone(Zone::Offset(FixedOffset::east_opt(0).expect("UTC is a valid offset")))
+    }
+}
+
+impl TryFrom<String> for Timezone {
+    type Error = String;
+
+    fn try_from(value: String) -> std::result::Result<Self, Self::Error> {
+        if value.eq_ignore_ascii_case("utc") || value == "Z" {
+            return Ok(Timezone::default());
+        }
+        if let Ok(offset) = value.parse() {
+            return Ok(Timezone(Zone::Offset(offset)));
+        }
+        value.parse().map(|tz| Timezone(Zone::Named(tz))).map_err(|_| {
+            format!(
+                "`{}` isn't a timezone I know. Write `UTC`, a name like `Europe/Berlin`, or an offset from UTC like `+05:30`",
+                value
+            )
+        })
+    }
+}
+
+impl From<Timezone> for String {
+    fn from(timezone: Timezone) -> Self {
+        timezone.to_string()
+    }
+}
+
+impl fmt::Display for Timezone {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Zone::Offset(offset) if offset.local_minus_utc() == 0 => f.write_str("UTC"),
+            Zone::Offset(offset) => write!(f, "{}", offset),
+            Zone::Named(tz) => f.write_str(tz.name()),
+        }
+    }
+}
+
+/// A chrono format string from `[dates] formats`, checked when the config loads
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct DateFormat(String);
+
+impl TryFrom<String> for DateFormat {
+    type Error = String;
+
+    fn try_from(value: String) -> std::result::Result<Self, Self::Error> {
+        match StrftimeItems::new(&value).parse() {
+            Ok(_) => Ok(DateFormat(value)),
+            Err(_) => Err(format!("`{}` isn't a date format I can read dates with, see the `datefmt` docs for the % codes", value)),
+        }
+    }
+}
+
+impl From<DateFormat> for String {
+    fn from(format: DateFormat) -> Self {
+        format.0
+    }
+}
+
+/// Extra date formats a site's frontmatter uses
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct DatesConfig {
+    /// Chrono format strings tried after the built-in formats, like "%d/%m/%Y"
+    #[serde(default)]
+    pub formats: Vec<DateFormat>,
+}
+
+/// Reads date strings the way the site's config says to
+#[derive(Debug, Clone, Default)]
+pub struct DateParser {
+    timezone: Timezone,
+    formats: Vec<DateFormat>,
+}
+
+impl DateParser {
+    pub fn new(config: &SiteConfig) -> Self {
+        Self { timezone: config.site.timezone, formats: config.dates.formats.clone() }
+    }
+
+    /// `s` as an instant, or `None` when it's in none of the formats. Each caller decides whether
+    /// that's worth a warning or an error, saying so with [`DateParser::unparseable`].
+    pub fn parse(&self, s: &str) -> Option<DateTime<Utc>> {
+        let s = s.trim();
+        if let Ok(dt) = DateTime::parse_from_rfc3339(s).or_else(|_| DateTime::parse_from_rfc2822(s)) {
+            return Some(dt.with_timezone(&Utc));
+        }
+
+        if let Some(ndt) = NAIVE_FORMATS.iter().find_map(|(format, _)| parse_naive(s, format)) {
+            return self.in_timezone(ndt);
+        }
+
+        self.formats.iter().find_map(|format| match parse_with_offset(s, &format.0) {
+            Some(dt) => Some(dt.with_timezone(&Utc)),
+            None => self.in_timezone(parse_naive(s, &format.0)?),
+        })
+    }
+
+    /// `dt` in the site's timezone, for showing the day and time it was there
+    pub fn local(&self, dt: DateTime<Utc>) -> DateTime<FixedOffset> {
+        match self.timezone.0 {
+            Zone::Offset(offset) => dt.with_timezone(&offset),
+            Zone::Named(tz) => dt.with_timezone(&tz).fixed_offset(),
+        }
+    }
+
+    /// Why `s` wasn't read as a date, listing the formats that would have been
+    pub fn unparseable(&self, s: &str) -> String {
+        let mut formats: Vec<&str> = vec!["RFC 3339 (YYYY-MM-DDTHH:MM:SSZ)", "RFC 2822"];
+        formats.extend(NAIVE_FORMATS.iter().map(|(_, name)| *name));
+        formats.extend(self.formats.iter().map(|format| format.0.as_str()));
+        format!("couldn't parse date '{}'. Supported: {}", s, formats.join(", "))
+    }
+
+    fn in_timezone(&self, ndt: NaiveDateTime) -> Option<DateTime<Utc>> {
+        match self.timezone.0 {
+            Zone::Offset(offset) => local_to_utc(&offset, ndt),
+            Zone::Named(tz) => local_to_utc(&tz, ndt),
+        }
+    }
+}
+
+/// `ndt` on the clocks in `zone` as an instant. When the clocks go back, the first of the two is
+/// taken; a time they skip going forward is read an hour later.
+fn local_to_utc(zone: &impl TimeZone, ndt: NaiveDateTime) -> Option<DateTime<Utc>> {
+    zone.from_local_datetime(&ndt)
+        .earliest()
+        .or_else(|| zone.from_local_datetime(&(ndt + TimeDelta::hours(1))).earliest())
+        .map(|dt| dt.with_timezone(&Utc))
+}
+
+/// `s` read with `format` as a date with an offset
+fn parse_with_offset(s: &str, format: &str) -> Option<DateTime<FixedOffset>> {
+    let mut parsed = Parsed::new();
+    parse(&mut parsed, s, StrftimeItems::new(format)).ok()?;
+    parsed.offset?;
+    parsed.to_datetime().ok()
+}
+
+/// `s` read with `format` as a date and time without an offset, at midnight when it has no time
+fn parse_naive(s: &str, format: &str) -> Option<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(s, format)
+        .ok()
+        .or_else(|| NaiveDate::parse_from_str(s, format).ok()?.and_hms_opt(0, 0, 0))
+}
+
+/// Published and last-updated dates of a page
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct PageDates {
+    pub published: Option<DateTime<Utc>>,
+    pub updated: Option<DateTime<Utc>>,
+}
+
+impl PageDates {
+    /// When the page last changed: its updated date, or its published date if it was never updated
+    pub fn last_modified(&self) -> Option<DateTime<Utc>> {
+        self.updated.or(self.published)
+    }
+}
+
+/// Extract and parse both the published and last-updated dates from frontmatter, warning about
+/// values that aren't dates
+pub fn extract_dates(frontmatter: &serde_yaml::Value, dates: &DateParser) -> PageDates {
+    let parse = |s: &str| {
+        let date = dates.parse(s);
+        if date.is_none() {
+            console::warn(dates.unparseable(s));
+        }
+        date
+    };
+
+    let published = frontmatter
+        .get("date")
+        .or_else(|| frontmatter.get("published"))
+        .or_else(|| frontmatter.get("created"))
+        .or_else(|| frontmatter.get("pubDate"))
+        .and_then(|v| v.as_str())
+        .and_then(parse);
+
+    let updated = frontmatter
+        .get("updated")
+        .or_else(|| frontmatter.get("lastmod"))
+        .or_else(|| frontmatter.get("modified"))
+        .and_then(|v| v.as_str())
+        .and_then(parse);
+
+    PageDates { published, updated }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parser(timezone: &str, formats: &[&str]) -> DateParser {
+        DateParser {
+            timezone: Timezone::try_from(timezone.to_string()).unwrap(),
+            formats: formats.iter().map(|f| DateFormat::try_from(f.to_string()).unwrap()).collect(),
+        }
+    }
+
+    fn utc(s: &str) -> Option<DateTime<Utc>> {
+        Some(DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc))
+    }
+
+    #[test]
+    fn test_each_format() {
+        let dates = parser("UTC", &["%d/%m/%Y", "%d.%m.%Y %H:%M %z"]);
+        for (input, expected) in [
+            ("2024-01-15T10:30:00Z", "2024-01-15T10:30:00Z"),
+            ("2024-01-15T10:30:00+02:00", "2024-01-15T08:30:00Z"),
+            ("Mon, 15 Jan 2024 10:30:00 +0000", "2024-01-15T10:30:00Z"),
+            ("2024-01-15 10:30:00", "2024-01-15T10:30:00Z"),
+            ("2024-01-15T10:30:00", "2024-01-15T10:30:00Z"),
+            ("2024-01-15", "2024-01-15T00:00:00Z"),
+            ("15 Jan 2024", "2024-01-15T00:00:00Z"),
+            ("15 January 2024", "2024-01-15T00:00:00Z"),
+            ("15/01/2024", "2024-01-15T00:00:00Z"),
+            ("15.01.2024 10:30 +0100", "2024-01-15T09:30:00Z"),
+        ] {
+            assert_eq!(dates.parse(input), utc(expected), "{input}");
+        }
+
+        for input in ["invalid", "2024-13-01", "01/15/2024", ""] {
+            assert_eq!(dates.parse(input), None, "{input}");
+        }
+        assert_eq!(parser("UTC", &[]).parse("15/01/2024"), None);
+    }
+
+    #[test]
+    fn test_timezone_applies_to_dates_without_an_offset() {
+        let dates = parser("+05:30", &["%d/%m/%Y"]);
+        assert_eq!(dates.parse("2024-01-15"), utc("2024-01-14T18:30:00Z"));
+        assert_eq!(dates.parse("2024-01-15 10:30:00"), utc("2024-01-15T05:00:00Z"));
+        assert_eq!(dates.parse("15/01/2024"), utc("2024-01-14T18:30:00Z"));
+        assert_eq!(dates.local(dates.parse("15 Jan 2024").unwrap()).format("%Y-%m-%d").to_string(), "2024-01-15");
+
+        // Dates with an offset keep theirs
+        assert_eq!(dates.parse("2024-01-15T10:30:00Z"), utc("2024-01-15T10:30:00Z"));
+        assert_eq!(parser("-0800", &[]).parse("2024-01-15"), utc("2024-01-15T08:00:00Z"));
+    }
+
+    #[test]
+    fn test_named_timezones_follow_daylight_saving() {
+        let dates = parser("America/New_York", &[]);
+        assert_eq!(dates.parse("2024-01-15"), utc("2024-01-15T05:00:00Z"));
+        assert_eq!(dates.parse("2024-07-15"), utc("2024-07-15T04:00:00Z"));
+        assert_eq!(dates.local(utc("2024-07-15T03:00:00Z").unwrap()).to_rfc3339(), "2024-07-14T23:00:00-04:00");
+
+        // 02:30 doesn't happen the day the clocks go forward, and 01:30 happens twice when they go back
+        assert_eq!(dates.parse("2024-03-10 02:30:00"), utc("2024-03-10T07:30:00Z"));
+        assert_eq!(dates.parse("2024-11-03 01:30:00"), utc("2024-11-03T05:30:00Z"));
+    }
+
+    #[test]
+    fn test_config_values_are_checked() {
+        assert_eq!(String::from(Timezone::try_from("utc".to_string()).unwrap()), "UTC");
+        assert_eq!(String::from(Timezone::try_from("+05:30".to_string()).unwrap()), "+05:30");
+        assert_eq!(String::from(Timezone::try_from("Europe/Berlin".to_string()).unwrap()), "Europe/Berlin");
+        assert!(Timezone::try_from("Europe/Berlni".to_string()).unwrap_err().contains("+05:30"));
+        assert!(DateFormat::try_from("%Q".to_string()).is_err());
+
+        let error = toml::from_str::<SiteConfig>("[site]\ntimezone = \"Mars\"").unwrap_err();
+        assert!(error.to_string().contains("`Mars` isn't a timezone"), "{error}");
+        let config: SiteConfig = toml::from_str("[site]\ntimezone = \"+09:00\"\n[dates]\nformats = [\"%d/%m/%Y\"]").unwrap();
+        assert_eq!(DateParser::new(&config).parse("15/01/2024"), utc("2024-01-14T15:00:00Z"));
+    }
+}
@@ -0,0 +1,145 @@
+//! What a page's render read, so the dev server can tell which pages a file change affects.
+//!
+//! Template functions record into a `DepRecorder` while a page renders. The result is kept per
+//! URL on `AppData`, and the watcher uses it to drop only the cached pages a change could touch.
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use crate::content_templates::is_section_template;
+
+/// Inputs a render touched, beyond the site-wide files every page uses
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RenderDeps {
+    /// Files read, relative to the site root (the page's own source, `cache_bust` targets)
+    pub files: BTreeSet<PathBuf>,
+    /// Whether `pages()` was called, so adding, removing or editing any page matters
+    pub pages: bool,
+    /// Macros the templates referenced, and the macros those call
+    pub macros: BTreeSet<String>,
+}
+
+impl RenderDeps {
+    pub fn merge(&mut self, other: &RenderDeps) {
+        self.files.extend(other.files.iter().cloned());
+        self.pages |= other.pages;
+        self.macros.extend(other.macros.iter().cloned());
+    }
+
+    /// Whether changing `path` (relative to the site root) could change this render.
+    /// `macro_files` holds each macro's file, by macro name.
+    pub fn affected_by(&self, path: &Path, macro_files: &HashMap<String, String>) -> bool {
+        self.files.contains(path)
+            || (self.pages && !path.starts_with("_") && path.extension().is_some_and(|ext| ext == "md"))
+            || self.macros.iter().any(|name| macro_files.get(name).is_some_and(|file| Path::new(file) == path))
+    }
+}
+
+/// Shared handle the template functions of one render record into
+#[derive(Debug, Clone, Default)]
+pub struct DepRecorder {
+    deps: Arc<Mutex<RenderDeps>>,
+}
+
+impl DepRecorder {
+    /// Lock the deps, recovering them if a render panicked while holding the lock.
+    /// Every update is a single insert, so they're never left half-written.
+    fn lock(&self) -> MutexGuard<'_, RenderDeps> {
+        self.deps.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn record_file(&self, path: impl Into<PathBuf>) {
+        self.lock().files.insert(path.into());
+    }
+
+    pub fn record_pages(&self) {
+        self.lock().pages = true;
+    }
+
+    pub fn record_macro(&self, name: &str) {
+        self.lock().macros.insert(name.to_string());
+    }
+
+    pub fn snapshot(&self) -> RenderDeps {
+        self.lock().clone()
+    }
+}
+
+/// A macro's file, in `_/macros/`
+fn is_macro_file(path: &Path) -> bool {
+    path.starts_with("_/macros") && path.extension().is_some_and(|ext| ext == "md")
+}
+
+/// Files every render depends on: the `_/` templates other than macros, and config.toml. A
+/// folder's `_content.md` counts too, since adding one changes which template its pages use. So
+/// does a macro file that isn't one of `macro_files`, since a page may have used its name before
+/// there was a macro by it.
+fn affects_every_page(path: &Path, macro_files: &HashMap<String, String>) -> bool {
+    if is_macro_file(path) {
+        return !macro_files.values().any(|file| Path::new(file) == path);
+    }
+    path.starts_with("_") || path == Path::new("config.toml") || is_section_template(path)
+}
+
+/// URLs in `page_deps` whose render could change because of `changed` (site-relative paths), given
+/// the macros' files when the pages were rendered. `None` means every page may have changed.
+pub fn affected_pages(
+    page_deps: &HashMap<String, RenderDeps>,
+    changed: &[PathBuf],
+    macro_files: &HashMap<String, String>,
+) -> Option<HashSet<String>> {
+    if changed.iter().any(|path| affects_every_page(path, macro_files)) {
+        return None;
+    }
+
+    Some(
+        page_deps
+            .iter()
+            .filter(|(_, deps)| changed.iter().any(|path| deps.affected_by(path, macro_files)))
+            .map(|(url, _)| url.clone())
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_affected_pages() {
+        let mut listing = RenderDeps { pages: true, ..Default::default() };
+        listing.files.insert(PathBuf::from("index.md"));
+        let mut styled = RenderDeps::default();
+        styled.files.insert(PathBuf::from("about.md"));
+        styled.files.insert(PathBuf::from("assets/site.css"));
+        styled.macros.insert(String::from("note"));
+        let page_deps = HashMap::from([(String::from("/"), listing), (String::from("/about"), styled)]);
+        let macro_files = HashMap::from([
+            (String::from("note"), String::from("_/macros/note.md")),
+            (String::from("card"), String::from("_/macros/card.md")),
+        ]);
+
+        let affected = |changed: &[&str]| {
+            let changed: Vec<PathBuf> = changed.iter().map(PathBuf::from).collect();
+            affected_pages(&page_deps, &changed, &macro_files).map(|urls| {
+                let mut urls: Vec<String> = urls.into_iter().collect();
+                urls.sort();
+                urls
+            })
+        };
+
+        assert_eq!(affected(&["assets/site.css"]), Some(vec![String::from("/about")]));
+        // Any page can change what pages() returns
+        assert_eq!(affected(&["blog/new-post.md"]), Some(vec![String::from("/")]));
+        assert_eq!(affected(&["about.md"]), Some(vec![String::from("/"), String::from("/about")]));
+        assert_eq!(affected(&["assets/logo.svg"]), Some(vec![]));
+        assert_eq!(affected(&["assets/logo.svg", "_/nav.md"]), None);
+        assert_eq!(affected(&["config.toml"]), None);
+        assert_eq!(affected(&["blog/_content.md"]), None);
+        // A macro only matters to the pages that use it, unless it's new
+        assert_eq!(affected(&["_/macros/note.md"]), Some(vec![String::from("/about")]));
+        assert_eq!(affected(&["_/macros/card.md"]), Some(vec![]));
+        assert_eq!(affected(&["_/macros/alert.md"]), None);
+    }
+}
@@ -1,28 +1,44 @@
-use std::path::PathBuf;
-use std::sync::Arc;
-use std::time::Duration;
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, LazyLock, Mutex, PoisonError};
+use std::time::{Duration, Instant};
 
 use actix::{Actor, ActorContext, AsyncContext, StreamHandler};
-use actix_web::{App, HttpRequest, HttpResponse, HttpServer, get, http::header::ContentType, web};
+use actix_web::{App, HttpRequest, HttpResponse, get, http::header::ContentType, middleware, web};
 use actix_web_actors::ws;
+use base64::{Engine, engine::general_purpose::STANDARD};
+use chrono::{DateTime, Local};
 use miette::Diagnostic;
 use notify::{Config, RecommendedWatcher, RecursiveMode, Watcher, EventKind, event::ModifyKind};
 use owo_colors::OwoColorize;
+use percent_encoding::{NON_ALPHANUMERIC, utf8_percent_encode};
+use sha2::{Digest, Sha256};
 use thiserror::Error;
 use tokio::sync::{RwLock, broadcast};
 
+use crate::build_cache::CACHE_DIR;
 use crate::console;
+use crate::cors::cors;
 
-use crate::error::{render_error_html, HugsError, Result};
+use crate::csp::self_script_fix;
+use crate::dates::DateParser;
+use crate::deps::{DepRecorder, affected_pages};
+use crate::dev_state::{DevState, STATE_FILE};
+use crate::error::{error_summary, render_error_html, HugsError, Result};
 use crate::minify::{minify_css_content, minify_html_content, MinifyConfig};
+use crate::missing_assets::{MissingAssets, is_asset_path, referring_page};
+use crate::render_cache::RenderCache;
+use crate::render_stats::RenderStats;
+use crate::reload_hooks::{is_hook_output, run_on_reload};
 use crate::run::{
-    render_notfound_page, render_page_html, render_dynamic_page_html, resolve_path_to_doc,
-    resolve_dynamic_doc, try_serve_static_file, AppData, DynamicContext,
+    escape_html, page_for_request, render_notfound_page, render_requested_page, route_request, AppData, PageResponse,
+    Route, SourceReads,
 };
+use crate::server::{bind_preferring, bind_with_retry};
 use crate::sitemap::generate_sitemap;
-
-/// Maximum number of port retry attempts before giving up
-const MAX_PORT_RETRIES: u16 = 50;
+use crate::slow_pages::SlowPages;
+use crate::timings::{self, Phase};
+use crate::url::encode_url_path;
 
 /// The default port number assigned for the dev server if no port is explicitly given
 const DEFAULT_PORT: u16 = 8080;
@@ -64,8 +80,12 @@ impl PortChangedWarning {
     }
 }
 
-const LIVE_RELOAD_SCRIPT: &str = r#"<script>
-(function() {
+/// Put in every page `hugs dev` serves. A separate file rather than an inline script, so a
+/// Content-Security-Policy without `'unsafe-inline'` still lets it run.
+const LIVE_RELOAD_SCRIPT: &str = r#"<script src="/__hugs/reload.js"></script>"#;
+
+/// The live reload script, served at `/__hugs/reload.js`
+const LIVE_RELOAD_JS: &str = r#"(function() {
     let reloading = false;
     let wasConnected = false;
     function connect() {
@@ -100,15 +120,120 @@ const LIVE_RELOAD_SCRIPT: &str = r#"<script>
     }
     connect();
 })();
-</script>"#;
+"#;
+
+/// The CSP source for `LIVE_RELOAD_JS`, for a site that puts the script inline itself
+static LIVE_RELOAD_HASH: LazyLock<String> =
+    LazyLock::new(|| format!("'sha256-{}'", STANDARD.encode(Sha256::digest(LIVE_RELOAD_JS.as_bytes()))));
+
+/// The site as requests see it
+// Only ever handled behind an Arc, so the difference in variant size costs nothing
+#[allow(clippy::large_enum_variant)]
+pub enum SiteState {
+    Ready(AppData),
+    /// A reload failed after the site had loaded, so requests are served from the last site that
+    /// did, with a bar on each page saying why
+    Stale {
+        site: AppData,
+        /// When `site` loaded
+        loaded_at: DateTime<Local>,
+        error: HugsError,
+    },
+    /// The site data has never loaded, so every request shows why
+    Failed(HugsError),
+}
+
+impl SiteState {
+    /// The site to serve from, unless there's none to serve
+    pub fn app_data(&self) -> Option<&AppData> {
+        match self {
+            SiteState::Ready(site) | SiteState::Stale { site, .. } => Some(site),
+            SiteState::Failed(_) => None,
+        }
+    }
+
+    /// What happens to the site after a reload that failed with `error`. A site that had loaded
+    /// is kept, along with when it loaded, but forgets its cache-busted names: files changed
+    /// since it hashed them.
+    fn after_failed_reload(&self, error: HugsError, last_loaded: DateTime<Local>) -> SiteState {
+        if let Some(site) = self.app_data() {
+            site.cache_bust_registry.take();
+        }
+        match self {
+            SiteState::Ready(site) => SiteState::Stale { site: site.clone(), loaded_at: last_loaded, error },
+            SiteState::Stale { site, loaded_at, .. } => SiteState::Stale { site: site.clone(), loaded_at: *loaded_at, error },
+            SiteState::Failed(_) => SiteState::Failed(error),
+        }
+    }
+}
 
 pub struct DevAppState {
-    pub app_data: RwLock<Option<AppData>>,
-    /// Stores an error when site data couldn't be loaded (startup or reload error)
-    /// When this is Some, all page requests will show this error
-    pub startup_error: RwLock<Option<HugsError>>,
+    /// Swapped whole on reload. A request takes one snapshot when it starts and uses only that,
+    /// so it never sees the new site's error next to the old site's data.
+    pub site: RwLock<Arc<SiteState>>,
     pub reload_tx: broadcast::Sender<()>,
     pub minify_config: MinifyConfig,
+    /// Rendered pages, valid for as long as the site data they were rendered from
+    pub render_cache: Mutex<RenderCache>,
+    /// Log render cache hits and misses
+    pub verbose: bool,
+    /// What I remember about the site between runs
+    pub dev_state: Mutex<DevState>,
+    /// Site to save `dev_state` in; unset when `--port` was given, which leaves the state file alone
+    pub state_site_path: Option<PathBuf>,
+    /// Files pages asked for that aren't there, for the terminal and `/__hugs/api`
+    pub missing_assets: Mutex<MissingAssets>,
+    /// Pages that took too long to render, which aren't tried again until the site reloads
+    pub slow_pages: Mutex<SlowPages>,
+    /// How long pages and site loads have taken, for `/__hugs/stats`
+    pub render_stats: Mutex<RenderStats>,
+}
+
+impl DevAppState {
+    /// The current site, for one request to use from start to finish
+    pub async fn snapshot(&self) -> Arc<SiteState> {
+        Arc::clone(&*self.site.read().await)
+    }
+
+    /// Swap in a newly loaded site, or the error from loading it. Requests already running keep
+    /// the snapshot they took.
+    pub async fn replace_site(&self, site: SiteState) {
+        *self.site.write().await = Arc::new(site);
+    }
+
+    /// Look up a page rendered from the current site data
+    fn cached_page(&self, url: &str, app_data: &AppData) -> Option<String> {
+        let mut cache = self.render_cache.lock().unwrap_or_else(PoisonError::into_inner);
+        let html = cache.get(url, app_data.generation, app_data.config.dev.render_cache_size);
+
+        if self.verbose {
+            let outcome = if html.is_some() { "hit" } else { "miss" };
+            console::status_cyan(
+                "Cache",
+                format!("{} {} ({} hits, {} misses)", outcome, url, cache.hits, cache.misses),
+            );
+        }
+
+        html
+    }
+
+    fn cache_page(&self, url: &str, app_data: &AppData, html: &str) {
+        self.render_cache
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(url, app_data.generation, html.to_string());
+    }
+
+    /// Remember a page that was just viewed, so `hugs dev --open` can come back to it
+    fn remember_page(&self, url: &str) {
+        let Some(site_path) = &self.state_site_path else {
+            return;
+        };
+        let mut dev_state = self.dev_state.lock().unwrap_or_else(PoisonError::into_inner);
+        if dev_state.record_page(url) {
+            dev_state.save(site_path);
+        }
+    }
 }
 
 struct LiveReloadWs {
@@ -159,19 +284,23 @@ async fn live_reload_ws(
     ws::start(LiveReloadWs::new(reload_rx), &req, stream)
 }
 
+#[get("/__hugs/reload.js")]
+async fn reload_js() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type(ContentType(mime_guess::mime::APPLICATION_JAVASCRIPT_UTF_8))
+        .body(LIVE_RELOAD_JS)
+}
+
 #[get("/theme.css")]
 async fn theme(state: web::Data<Arc<DevAppState>>) -> HttpResponse {
-    // Check for startup error
-    if let Some(error) = state.startup_error.read().await.as_ref() {
-        return HttpResponse::InternalServerError()
-            .content_type(ContentType::html())
-            .body(render_error_html(error, LIVE_RELOAD_SCRIPT));
+    let site = state.snapshot().await;
+    if let Some(response) = page_instead(&state, &site, "theme.css").await {
+        return response;
     }
 
-    let app_data_guard = state.app_data.read().await;
-    let app_data = match app_data_guard.as_ref() {
-        Some(data) => data,
-        None => return HttpResponse::InternalServerError().body("I couldn't load the site data"),
+    let app_data = match site.as_ref() {
+        SiteState::Failed(error) => return error_response(error, "theme.css"),
+        site => site.app_data().expect("only a failed site has no data"),
     };
     let css = minify_css_content(&app_data.theme_css, &state.minify_config);
     HttpResponse::Ok()
@@ -182,18 +311,15 @@ async fn theme(state: web::Data<Arc<DevAppState>>) -> HttpResponse {
 /// Handle cache-busted theme CSS (e.g., /theme.a1b2c3f4.css)
 /// In dev mode, we serve the theme CSS regardless of the hash value
 #[get("/theme.{hash}.css")]
-async fn theme_hashed(state: web::Data<Arc<DevAppState>>) -> HttpResponse {
-    // Check for startup error
-    if let Some(error) = state.startup_error.read().await.as_ref() {
-        return HttpResponse::InternalServerError()
-            .content_type(ContentType::html())
-            .body(render_error_html(error, LIVE_RELOAD_SCRIPT));
+async fn theme_hashed(hash: web::Path<String>, state: web::Data<Arc<DevAppState>>) -> HttpResponse {
+    let site = state.snapshot().await;
+    if let Some(response) = page_instead(&state, &site, &format!("theme.{}.css", hash)).await {
+        return response;
     }
 
-    let app_data_guard = state.app_data.read().await;
-    let app_data = match app_data_guard.as_ref() {
-        Some(data) => data,
-        None => return HttpResponse::InternalServerError().body("I couldn't load the site data"),
+    let app_data = match site.as_ref() {
+        SiteState::Failed(error) => return error_response(error, "theme.css"),
+        site => site.app_data().expect("only a failed site has no data"),
     };
     let css = minify_css_content(&app_data.theme_css, &state.minify_config);
     HttpResponse::Ok()
@@ -203,203 +329,478 @@ async fn theme_hashed(state: web::Data<Arc<DevAppState>>) -> HttpResponse {
 
 #[get("/sitemap.xml")]
 async fn sitemap(state: web::Data<Arc<DevAppState>>) -> HttpResponse {
-    // Check for startup error
-    if let Some(error) = state.startup_error.read().await.as_ref() {
-        return HttpResponse::InternalServerError()
-            .content_type(ContentType::html())
-            .body(render_error_html(error, LIVE_RELOAD_SCRIPT));
+    let site = state.snapshot().await;
+    if let Some(response) = page_instead(&state, &site, "sitemap.xml").await {
+        return response;
     }
 
-    let app_data_guard = state.app_data.read().await;
-    let app_data = match app_data_guard.as_ref() {
-        Some(data) => data,
-        None => return HttpResponse::InternalServerError().body("I couldn't load the site data"),
+    let app_data = match site.as_ref() {
+        SiteState::Failed(error) => return error_response(error, "sitemap.xml"),
+        site => site.app_data().expect("only a failed site has no data"),
     };
-    match generate_sitemap(&app_data.pages, &app_data.config.site) {
+    match generate_sitemap(&app_data.pages, &app_data.config.site, &DateParser::new(&app_data.config), app_data.sitemap_template.as_deref(), None) {
         Ok(xml) => HttpResponse::Ok()
             .content_type(ContentType::xml())
             .body(xml),
-        Err(e) => HttpResponse::InternalServerError()
-            .content_type(ContentType::html())
-            .body(render_error_html(&e, LIVE_RELOAD_SCRIPT)),
+        Err(e) => error_response(&e, "sitemap.xml"),
     }
 }
 
-/// Try to match a URL path against dynamic page patterns
-/// Returns (source_file_path, DynamicContext) if a match is found
-fn match_dynamic_page(url_path: &str, app_data: &AppData) -> Option<(String, DynamicContext)> {
-    use serde_yaml::Value as YamlValue;
+/// Serve the page at `path` if there is one, ahead of a generated file at the same URL (as in `hugs build`)
+async fn page_instead(state: &DevAppState, site: &Arc<SiteState>, path: &str) -> Option<HttpResponse> {
+    let claimed = site.app_data().is_some_and(|data| page_for_request(&data.pages, path).is_some());
 
-    for def in app_data.dynamic_defs.iter() {
-        // Convert source path to a pattern (e.g., "blog/[slug].md" -> regex to match "blog/*")
-        let source_path_str = def.source_path.to_string_lossy();
-        let source_without_ext = source_path_str.strip_suffix(".md").unwrap_or(&source_path_str);
+    if claimed {
+        Some(serve_path(path, state, site, false).await)
+    } else {
+        None
+    }
+}
 
-        // Create the pattern by replacing [param] with a capture group
-        let placeholder = format!("[{}]", def.param_name);
+/// Extensions of requests for files a browser reads as something other than a page
+const NON_PAGE_EXTENSIONS: &[&str] = &["css", "js", "mjs", "json", "map", "webmanifest", "xml", "svg", "txt"];
 
-        // Check if the URL could match this pattern
-        // Split both paths into segments and compare
-        let pattern_segments: Vec<&str> = source_without_ext.split('/').collect();
-        let url_segments: Vec<&str> = url_path.split('/').filter(|s| !s.is_empty()).collect();
+/// The error response for a request at `path` while the site can't be loaded or rendered. Pages
+/// get the full error page. Stylesheets, scripts, XML and JSON get a one-line summary as plain
+/// text, since the browser would try to read an HTML page as whatever it asked for and bury the
+/// real problem under its own errors. The full error is still on any page and in the terminal.
+fn error_response(error: &HugsError, path: &str) -> HttpResponse {
+    let extension = Path::new(path.trim_end_matches('/')).extension().and_then(|ext| ext.to_str()).unwrap_or("");
+    if !NON_PAGE_EXTENSIONS.iter().any(|non_page| extension.eq_ignore_ascii_case(non_page)) {
+        return HttpResponse::InternalServerError()
+            .content_type(ContentType::html())
+            .body(render_error_html(error, LIVE_RELOAD_SCRIPT));
+    }
 
-        if pattern_segments.len() != url_segments.len() {
-            continue;
-        }
+    HttpResponse::InternalServerError()
+        .content_type(ContentType::plaintext())
+        .body(format!("{}\nThe full error is in the terminal and on any page of the site.\n", error_summary(error)))
+}
 
-        let mut matched_value: Option<String> = None;
-        let mut all_match = true;
+/// Where the list of debug switches is served when `dev.debug_tools` is on
+const DEBUG_INDEX_PATH: &str = "__hugs/debug";
+
+/// Longest delay `?__hugs_delay` can ask for
+const MAX_DEBUG_DELAY: Duration = Duration::from_secs(60);
+
+/// Query parameters that change how a page is served, for testing a theme's loading and error
+/// states. Only honored with `dev.debug_tools = true`.
+#[derive(Debug, Default, PartialEq)]
+struct DebugSwitches {
+    /// `__hugs_delay=<ms>`: wait this long before responding
+    delay: Option<Duration>,
+    /// `__hugs_status=<code>`: answer with this status and the error page
+    status: Option<actix_web::http::StatusCode>,
+    /// `__hugs_nominify=1`: skip minification (and the render cache) for this response
+    no_minify: bool,
+}
 
-        for (pattern_seg, url_seg) in pattern_segments.iter().zip(url_segments.iter()) {
-            if *pattern_seg == placeholder {
-                matched_value = Some(url_seg.to_string());
-            } else if pattern_seg != url_seg {
-                all_match = false;
-                break;
+impl DebugSwitches {
+    /// Example use and description of each switch, for `/__hugs/debug`
+    const ALL: &[(&str, &str)] = &[
+        ("?__hugs_delay=2000", "Wait this many milliseconds (up to 60000) before responding"),
+        ("?__hugs_status=500", "Answer with this status code and the error page (404 shows your 404 page)"),
+        ("?__hugs_nominify=1", "Send the page without minification, for comfortable view-source"),
+    ];
+
+    fn from_query(query: &str) -> Self {
+        let mut switches = Self::default();
+        let params = web::Query::<Vec<(String, String)>>::from_query(query).map(|q| q.into_inner()).unwrap_or_default();
+        for (key, value) in params {
+            match key.as_str() {
+                "__hugs_delay" => {
+                    switches.delay = value.parse().ok().map(|ms| Duration::from_millis(ms).min(MAX_DEBUG_DELAY));
+                }
+                "__hugs_status" => {
+                    switches.status = value
+                        .parse()
+                        .ok()
+                        .and_then(|code| actix_web::http::StatusCode::from_u16(code).ok())
+                        .filter(|code| code.is_client_error() || code.is_server_error());
+                }
+                "__hugs_nominify" => switches.no_minify = !matches!(value.as_str(), "0" | "false"),
+                _ => {}
             }
         }
+        switches
+    }
+}
 
-        if all_match {
-            if let Some(value_str) = matched_value {
-                // Check if this value is in the allowed list
-                let value_yaml = YamlValue::String(value_str.clone());
-                let value_matches = def.param_values.iter().any(|v| {
-                    match v {
-                        YamlValue::String(s) => s == &value_str,
-                        YamlValue::Number(n) => n.to_string() == value_str,
-                        _ => false,
-                    }
-                });
+/// The `/__hugs/debug` page listing the switches
+fn debug_index() -> HttpResponse {
+    let rows: String = DebugSwitches::ALL
+        .iter()
+        .map(|(example, description)| format!("<tr><td><code>{}</code></td><td>{}</td></tr>\n", example, description))
+        .collect();
+
+    HttpResponse::Ok().content_type(ContentType::html()).body(format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>Hugs debug tools</title></head><body>\n\
+        <h1>Debug tools</h1>\n<p>Add these to any page URL while <code>dev.debug_tools</code> is on. They never affect <code>hugs build</code>.</p>\n\
+        <table>\n{}</table>\n<p><a href=\"/__hugs/api\">/__hugs/api</a> has, as JSON, the files pages asked for that aren't there.</p>\n\
+        <p><a href=\"/__hugs/stats\">/__hugs/stats</a> shows how long each page took to render.</p>\n</body></html>\n",
+        rows
+    ))
+}
 
-                if value_matches {
-                    // Find the actual YAML value (to preserve type)
-                    let param_value = def.param_values.iter()
-                        .find(|v| match v {
-                            YamlValue::String(s) => s == &value_str,
-                            YamlValue::Number(n) => n.to_string() == value_str,
-                            _ => false,
-                        })
-                        .cloned()
-                        .unwrap_or(value_yaml);
-
-                    return Some((
-                        def.source_path.to_string_lossy().to_string(),
-                        DynamicContext {
-                            param_name: def.param_name.clone(),
-                            param_value,
-                        },
-                    ));
-                }
+/// Where the JSON for tools is served when `dev.debug_tools` is on
+const DEBUG_API_PATH: &str = "__hugs/api";
+
+/// `/__hugs/api`: what the dev server knows that tools might want to show
+fn debug_api(state: &DevAppState) -> HttpResponse {
+    let missing = state.missing_assets.lock().unwrap_or_else(PoisonError::into_inner);
+    HttpResponse::Ok().json(serde_json::json!({ "missing_assets": missing.entries() }))
+}
+
+/// Where the render statistics are served when `dev.debug_tools` is on, as a table and as JSON
+const STATS_PATH: &str = "__hugs/stats";
+const STATS_API_PATH: &str = "__hugs/api/stats";
+
+/// Milliseconds, to one decimal place
+fn millis(duration: Duration) -> String {
+    format!("{:.1}", duration.as_secs_f64() * 1000.0)
+}
+
+/// `/__hugs/stats`: a table of how long each page took to render, slowest first
+fn stats_page(state: &DevAppState) -> HttpResponse {
+    let render_stats = state.render_stats.lock().unwrap_or_else(PoisonError::into_inner);
+    let rows: String = render_stats
+        .pages()
+        .into_iter()
+        .map(|(url, stats)| {
+            let phases = &stats.last_timings;
+            format!(
+                "<tr><td><a href=\"{}\">{}</a></td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+                escape_html(&encode_url_path(url)),
+                escape_html(url),
+                stats.renders,
+                stats.cache_hits,
+                millis(stats.last_render),
+                millis(phases.template),
+                millis(phases.markdown),
+                millis(phases.highlight),
+                millis(phases.minify),
+            )
+        })
+        .collect();
+    let last_load = render_stats.last_load.map_or_else(|| String::from("not yet"), |duration| format!("{} ms", millis(duration)));
+
+    HttpResponse::Ok().content_type(ContentType::html()).body(format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>Hugs render stats</title></head><body>\n\
+        <h1>Render stats</h1>\n<p>The site has reloaded {} times since <code>hugs dev</code> started, and the last load took {}.</p>\n\
+        <p>Times are from each page's last render, in milliseconds. Requests the render cache answers aren't renders.</p>\n\
+        <table>\n<tr><th>Page</th><th>Renders</th><th>Cache hits</th><th>Total</th><th>Templates</th><th>Markdown</th><th>Highlighting</th><th>Minifying</th></tr>\n{}</table>\n\
+        <p><a href=\"/{}\">/{}</a> has the same as JSON.</p>\n</body></html>\n",
+        render_stats.reloads, last_load, rows, STATS_API_PATH, STATS_API_PATH
+    ))
+}
+
+/// `/__hugs/api/stats`: the render statistics as JSON
+fn stats_api(state: &DevAppState) -> HttpResponse {
+    let render_stats = state.render_stats.lock().unwrap_or_else(PoisonError::into_inner);
+    let pages: Vec<serde_json::Value> = render_stats
+        .pages()
+        .into_iter()
+        .map(|(url, stats)| {
+            let mut entry = serde_json::to_value(stats).unwrap_or_default();
+            if let Some(fields) = entry.as_object_mut() {
+                fields.insert(String::from("url"), url.into());
             }
+            entry
+        })
+        .collect();
+    HttpResponse::Ok().json(serde_json::json!({
+        "reloads": render_stats.reloads,
+        "last_load_ms": render_stats.last_load.map(|duration| duration.as_secs_f64() * 1000.0),
+        "pages": pages,
+    }))
+}
+
+/// How long I wait after a page's first missing file before listing them, so a page missing
+/// several gets one warning
+const MISSING_ASSET_DEBOUNCE: Duration = Duration::from_millis(500);
+
+#[get("/{tail:.*}")]
+async fn page(req: HttpRequest, path: web::Path<String>, state: web::Data<Arc<DevAppState>>) -> HttpResponse {
+    let response = respond(&req, &path, &state).await;
+    if is_asset_path(&path) {
+        track_missing_asset(&req, &path, response.status(), &state);
+    }
+    response
+}
+
+/// Remember a missing file with the page that asked for it, or forget it once it's served
+fn track_missing_asset(req: &HttpRequest, path: &str, status: actix_web::http::StatusCode, state: &Arc<DevAppState>) {
+    let asset = format!("/{}", path.trim_start_matches('/'));
+    let mut missing = state.missing_assets.lock().unwrap_or_else(PoisonError::into_inner);
+    if status != actix_web::http::StatusCode::NOT_FOUND {
+        if status.is_success() {
+            missing.resolved(&asset);
         }
+        return;
     }
 
-    None
+    let referrer = req
+        .headers()
+        .get(actix_web::http::header::REFERER)
+        .and_then(|referer| referer.to_str().ok())
+        .and_then(|referer| referring_page(referer, req.connection_info().host()));
+    if let Some(referrer) = referrer
+        && missing.record(&referrer, &asset)
+        && !missing.report_scheduled
+    {
+        missing.report_scheduled = true;
+        let state = Arc::clone(state);
+        tokio::spawn(async move {
+            tokio::time::sleep(MISSING_ASSET_DEBOUNCE).await;
+            let mut missing = state.missing_assets.lock().unwrap_or_else(PoisonError::into_inner);
+            missing.report_scheduled = false;
+            for (referrer, assets) in missing.take_unreported() {
+                console::warn(format!("page {} references missing {}", referrer, assets.join(", ")));
+            }
+        });
+    }
 }
 
-#[get("/{tail:.*}")]
-async fn page(path: web::Path<String>, state: web::Data<Arc<DevAppState>>) -> HttpResponse {
-    // Check for startup error first - if there's an error, show it for all requests
-    if let Some(error) = state.startup_error.read().await.as_ref() {
-        return HttpResponse::InternalServerError()
+async fn respond(req: &HttpRequest, path: &str, state: &DevAppState) -> HttpResponse {
+    if path.trim_end_matches('/') == RETRY_PATH {
+        return retry_slow_page(req, state);
+    }
+
+    let site = state.snapshot().await;
+    let debug_tools = site.app_data().is_some_and(|data| data.config.dev.debug_tools);
+    if !debug_tools {
+        return serve_path(path, state, &site, false).await;
+    }
+
+    match path.trim_end_matches('/') {
+        DEBUG_INDEX_PATH => return debug_index(),
+        DEBUG_API_PATH => return debug_api(state),
+        STATS_PATH => return stats_page(state),
+        STATS_API_PATH => return stats_api(state),
+        _ => {}
+    }
+
+    let switches = DebugSwitches::from_query(req.query_string());
+    if let Some(delay) = switches.delay {
+        tokio::time::sleep(delay).await;
+    }
+
+    match switches.status {
+        Some(status) if status == actix_web::http::StatusCode::NOT_FOUND => serve_not_found(state, &site).await,
+        Some(status) => HttpResponse::build(status)
             .content_type(ContentType::html())
-            .body(render_error_html(error, LIVE_RELOAD_SCRIPT));
+            .body(render_error_html(
+                &HugsError::DevForcedStatus { status: status.as_u16().into() },
+                LIVE_RELOAD_SCRIPT,
+            )),
+        None => serve_path(path, state, &site, switches.no_minify).await,
     }
+}
 
-    let app_data_guard = state.app_data.read().await;
-    let app_data = match app_data_guard.as_ref() {
-        Some(data) => data,
-        None => {
-            // No app data and no error - this shouldn't happen, but handle gracefully
-            return HttpResponse::InternalServerError()
-                .content_type(ContentType::html())
-                .body("I couldn't load the site data");
-        }
+/// The site's 404 page, or a plain one when it has none
+async fn serve_not_found(state: &DevAppState, site: &SiteState) -> HttpResponse {
+    let html = match site.app_data() {
+        Some(app_data) => render_notfound_page(app_data, LIVE_RELOAD_SCRIPT).await,
+        None => None,
     };
 
-    // Normalize path by trimming trailing slashes
-    let path_str = path.trim_end_matches('/');
+    match html {
+        Some(html) => HttpResponse::NotFound()
+            .content_type(ContentType::html())
+            .body(minify_html_content(&html, &state.minify_config)),
+        None => HttpResponse::NotFound()
+            .content_type(ContentType::html())
+            .body("Not Found"),
+    }
+}
 
-    if let Some(response) = try_serve_static_file(path_str, &app_data).await {
-        return response;
+/// Where the timeout page's link to render a slow page again goes
+const RETRY_PATH: &str = "__hugs/retry";
+
+/// `/__hugs/retry?url=...`: render the page at `url` again on its next request, and go there
+fn retry_slow_page(req: &HttpRequest, state: &DevAppState) -> HttpResponse {
+    let params = web::Query::<Vec<(String, String)>>::from_query(req.query_string()).map(|q| q.into_inner()).unwrap_or_default();
+    // Only a page on this site, never somewhere else
+    let url = params
+        .into_iter()
+        .find(|(key, _)| key == "url")
+        .map(|(_, url)| url)
+        .filter(|url| url.starts_with('/') && !url.starts_with("//"))
+        .unwrap_or_else(|| String::from("/"));
+
+    state.slow_pages.lock().unwrap_or_else(PoisonError::into_inner).retry(&url);
+    HttpResponse::Found()
+        .insert_header((actix_web::http::header::LOCATION, encode_url_path(&url)))
+        .finish()
+}
+
+/// Run `render` on a thread of its own, giving up on it after `limit`. There's no stopping a
+/// render partway, so one given up on runs to the end, but nothing waits for it.
+async fn within<T: Send + 'static>(limit: Duration, render: impl Future<Output = T> + Send + 'static) -> Option<T> {
+    let runtime = tokio::runtime::Handle::current();
+    let task = tokio::task::spawn_blocking(move || runtime.block_on(render));
+    match tokio::time::timeout(limit, task).await {
+        Ok(Ok(result)) => Some(result),
+        Ok(Err(error)) if error.is_panic() => std::panic::resume_unwind(error.into_panic()),
+        Ok(Err(_)) | Err(_) => None,
     }
+}
 
-    // First try to resolve as a static page
-    match resolve_path_to_doc(path_str, &app_data).await {
-        Ok(Some((frontmatter, doc_html, resolvable_path, frontmatter_json))) => {
-            match render_page_html(
-                &frontmatter,
-                &frontmatter_json,
-                &doc_html,
-                &resolvable_path,
-                &app_data,
-                LIVE_RELOAD_SCRIPT,
-            ) {
-                Ok(html_out) => {
-                    let final_html = minify_html_content(&html_out, &state.minify_config);
-                    HttpResponse::Ok()
-                        .content_type(ContentType::html())
-                        .body(final_html)
-                }
-                Err(e) => HttpResponse::InternalServerError()
-                    .content_type(ContentType::html())
-                    .body(render_error_html(&e, LIVE_RELOAD_SCRIPT)),
-            }
+/// The page served instead of `url` after it took longer than `secs` to render, `timeouts` times in a row
+fn render_timeout_response(url: &str, secs: u64, timeouts: u32) -> HttpResponse {
+    let in_a_row = match timeouts {
+        0 | 1 => String::new(),
+        times => format!(" It's timed out {} times in a row.", times),
+    };
+    let error = HugsError::DevRenderTimeout {
+        url: url.into(),
+        secs: secs.into(),
+        help_text: format!(
+            "A loop over `pages()` inside another is the usual cause, since it runs once for every pair of pages. `{{{{ value | help }}}}` shows what a variable holds, to check a loop is over what you think it is. If the page is only slow, raise `[dev] render_timeout_secs`. I won't render it again until the site reloads, so it can't hold up the rest of the dev server.{}",
+            in_a_row
+        ),
+    };
+    let retry_link = format!(
+        "<p style=\"max-width: 900px; margin: 1rem auto\"><a href=\"/{}?url={}\">Try rendering it again</a></p>\n",
+        RETRY_PATH,
+        utf8_percent_encode(url, NON_ALPHANUMERIC)
+    );
+
+    HttpResponse::ServiceUnavailable()
+        .content_type(ContentType::html())
+        .body(render_error_html(&error, &(retry_link + LIVE_RELOAD_SCRIPT)))
+}
+
+/// Serve the page or file at `path` from `site`. With `no_minify`, the page is rendered fresh and
+/// sent as is.
+async fn serve_path(path: &str, state: &DevAppState, site: &Arc<SiteState>, no_minify: bool) -> HttpResponse {
+    let app_data = match site.as_ref() {
+        SiteState::Failed(error) => return error_response(error, path),
+        site => site.app_data().expect("only a failed site has no data"),
+    };
+
+    let path_str = match route_request(path.trim_end_matches('/'), app_data).await {
+        Route::Page(path_str) => path_str,
+        Route::File(response) => return response,
+    };
+
+    let cache_key = format!("/{}", path_str);
+    if !no_minify && let Some(html) = state.cached_page(&cache_key, app_data) {
+        state.remember_page(&cache_key);
+        state.render_stats.lock().unwrap_or_else(PoisonError::into_inner).cache_hit(&cache_key);
+        return HttpResponse::Ok()
+            .content_type(ContentType::html())
+            .body(with_stale_bar(html, site));
+    }
+    let minify_config = if no_minify { MinifyConfig::new(false) } else { state.minify_config };
+
+    // Records what the page reads, so the watcher knows which changes make it stale
+    let deps = DepRecorder::default();
+
+    let started = Instant::now();
+    let timeout_secs = app_data.config.dev.render_timeout_secs;
+    let (rendered, mut render_timings) = if timeout_secs == 0 {
+        timings::measure(render_requested_page(path_str, app_data, LIVE_RELOAD_SCRIPT, &deps)).await
+    } else {
+        let skipped = state.slow_pages.lock().unwrap_or_else(PoisonError::into_inner).skip(&cache_key, app_data.generation);
+        if let Some(timeouts) = skipped {
+            return render_timeout_response(&cache_key, timeout_secs, timeouts);
         }
-        Ok(None) => {
-            // Static page not found - try to match against dynamic pages
-            if let Some((source_path, dynamic_ctx)) = match_dynamic_page(path_str, &app_data) {
-                match resolve_dynamic_doc(&source_path, &dynamic_ctx, &app_data).await {
-                    Ok((frontmatter, doc_html, _resolvable_path, frontmatter_json)) => {
-                        // Build the page URL from the request path
-                        let page_url = format!("/{}", path_str);
-                        match render_dynamic_page_html(
-                            &frontmatter,
-                            &frontmatter_json,
-                            &doc_html,
-                            &page_url,
-                            &app_data,
-                            LIVE_RELOAD_SCRIPT,
-                        ) {
-                            Ok(html_out) => {
-                                let final_html = minify_html_content(&html_out, &state.minify_config);
-                                return HttpResponse::Ok()
-                                    .content_type(ContentType::html())
-                                    .body(final_html);
-                            }
-                            Err(e) => {
-                                return HttpResponse::InternalServerError()
-                                    .content_type(ContentType::html())
-                                    .body(render_error_html(&e, LIVE_RELOAD_SCRIPT));
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        return HttpResponse::InternalServerError()
-                            .content_type(ContentType::html())
-                            .body(render_error_html(&e, LIVE_RELOAD_SCRIPT));
-                    }
-                }
-            }
 
-            // No match found - show 404 page
-            if let Some(html) = render_notfound_page(&app_data, LIVE_RELOAD_SCRIPT).await {
-                let final_html = minify_html_content(&html, &state.minify_config);
-                HttpResponse::NotFound()
-                    .content_type(ContentType::html())
-                    .body(final_html)
-            } else {
-                HttpResponse::NotFound()
-                    .content_type(ContentType::html())
-                    .body("Not Found")
+        // On its own thread, so a page stuck in a huge loop doesn't hold up a server worker
+        let (site, path, render_deps) = (Arc::clone(site), path_str.to_string(), deps.clone());
+        let render = async move {
+            let Some(app_data) = site.app_data() else {
+                unreachable!("only a site that loaded has pages to render");
+            };
+            timings::measure(render_requested_page(&path, app_data, LIVE_RELOAD_SCRIPT, &render_deps)).await
+        };
+        let rendered = within(Duration::from_secs(timeout_secs), render).await;
+        let mut slow_pages = state.slow_pages.lock().unwrap_or_else(PoisonError::into_inner);
+        match rendered {
+            Some(rendered) => {
+                slow_pages.rendered(&cache_key);
+                rendered
+            }
+            None => {
+                let timeouts = slow_pages.timed_out(&cache_key, app_data.generation);
+                console::warn(format!(
+                    "{} took more than {} seconds to render, so I'll skip it until the site reloads",
+                    cache_key, timeout_secs
+                ));
+                return render_timeout_response(&cache_key, timeout_secs, timeouts);
             }
         }
-        Err(e) => {
-            // Error occurred while processing - show error in page
-            HttpResponse::InternalServerError()
+    };
+
+    match rendered {
+        Ok(PageResponse::Page(html_out)) => {
+            state.remember_page(&cache_key);
+            warn_if_reload_blocked(&cache_key, &html_out);
+            let minify_started = Instant::now();
+            let final_html = minify_html_content(&html_out, &minify_config);
+            render_timings.add(Phase::Minify, minify_started.elapsed());
+            state.render_stats.lock().unwrap_or_else(PoisonError::into_inner).rendered(&cache_key, started.elapsed(), render_timings);
+            if !no_minify {
+                state.cache_page(&cache_key, app_data, &final_html);
+            }
+            HttpResponse::Ok()
                 .content_type(ContentType::html())
-                .body(render_error_html(&e, LIVE_RELOAD_SCRIPT))
+                .body(with_stale_bar(final_html, site))
         }
+        // Temporary redirects, so browsers don't cache them while the site is being edited
+        Ok(PageResponse::Redirect(url)) => HttpResponse::Found()
+            .insert_header((actix_web::http::header::LOCATION, url))
+            .finish(),
+        Ok(PageResponse::NotFound(Some(html))) => HttpResponse::NotFound()
+            .content_type(ContentType::html())
+            .body(minify_html_content(&html, &minify_config)),
+        Ok(PageResponse::NotFound(None)) => HttpResponse::NotFound()
+            .content_type(ContentType::html())
+            .body("Not Found"),
+        // Error occurred while processing - show error in page
+        Err(e) => error_response(&e, path),
+    }
+}
+
+/// `html` with a bar across the top saying why it's from the last site that loaded, when `site` is
+/// stale, or which of the header, nav and footer are their last version that worked. Added as the
+/// page is sent, so the render cache keeps the page without it.
+fn with_stale_bar(mut html: String, site: &SiteState) -> String {
+    let message = match site {
+        SiteState::Stale { loaded_at, error, .. } => format!(
+            "Showing the last good version, from {}. Fix this to update: {}",
+            loaded_at.format("%H:%M"),
+            escape_html(&error_summary(error))
+        ),
+        SiteState::Ready(site) if !site.chrome_errors.is_empty() => {
+            let errors: Vec<String> = site.chrome_errors.iter().map(|error| escape_html(&error_summary(error))).collect();
+            format!("Showing the last version of this that worked. Fix it to update: {}", errors.join("; "))
+        }
+        _ => return html,
+    };
+
+    let bar = format!(
+        "<div id=\"hugs-stale\" style=\"position: fixed; top: 0; left: 0; right: 0; z-index: 2147483647; padding: 0.5rem 1rem; background: #fde68a; color: #78350f; font: 14px/1.4 system-ui, sans-serif; border-bottom: 1px solid #f59e0b\">{}</div>",
+        message
+    );
+    match html.rfind("</body>") {
+        Some(end) => html.insert_str(end, &bar),
+        None => html.push_str(&bar),
+    }
+    html
+}
+
+/// Say so when the page's Content-Security-Policy won't let the live reload script load, and what
+/// to add to it. Checked when a page is rendered, so a cached page doesn't warn again.
+fn warn_if_reload_blocked(url: &str, html: &str) {
+    if let Some(fix) = self_script_fix(html) {
+        console::warn(format!(
+            "the Content-Security-Policy on {} blocks live reload; add {} to it (or {} if you inline the script yourself)",
+            url,
+            fix.cyan().bold(),
+            LIVE_RELOAD_HASH.as_str().cyan()
+        ));
     }
 }
 
@@ -407,18 +808,22 @@ fn start_file_watcher(
     site_path: PathBuf,
     state: Arc<DevAppState>,
 ) -> notify::Result<RecommendedWatcher> {
-    let (tx, mut rx) = tokio::sync::mpsc::channel::<()>(100);
+    let (tx, mut rx) = tokio::sync::mpsc::channel::<Vec<PathBuf>>(100);
 
     let watcher = RecommendedWatcher::new(
         move |res: std::result::Result<notify::Event, notify::Error>| {
-            if let Ok(event) = res {
+            if let Ok(mut event) = res {
                 // Only trigger on actual file modifications (write, content change)
                 let dominated = matches!(
                     event.kind,
                     EventKind::Create(_) | EventKind::Remove(_) | EventKind::Modify(ModifyKind::Data(_))
                 );
-                if dominated {
-                    let _ = tx.blocking_send(());
+                // Saving the dev state isn't a change to the site, and neither is a build filling its cache
+                event.paths.retain(|path| {
+                    !path.ends_with(STATE_FILE) && !path.components().any(|part| part.as_os_str() == CACHE_DIR)
+                });
+                if dominated && !event.paths.is_empty() {
+                    let _ = tx.blocking_send(event.paths);
                 }
             }
         },
@@ -429,11 +834,17 @@ fn start_file_watcher(
     tokio::spawn(async move {
         const DEBOUNCE_MS: u64 = 150;
 
+        // Changes since the last successful reload, relative to the site root
+        let mut changed = BTreeSet::new();
+        // When the site being served loaded, which the server started with
+        let mut last_loaded = Local::now();
+
         loop {
             // Wait for the first event
-            if rx.recv().await.is_none() {
+            let Some(paths) = rx.recv().await else {
                 break;
-            }
+            };
+            changed.extend(paths.iter().map(|path| site_relative(path, &site_path_clone)));
 
             // Debounce: wait for events to stop arriving
             loop {
@@ -441,10 +852,11 @@ fn start_file_watcher(
 
                 tokio::select! {
                     result = rx.recv() => {
-                        if result.is_none() {
+                        let Some(paths) = result else {
                             return;
-                        }
+                        };
                         // Event received - continue loop to reset timer
+                        changed.extend(paths.iter().map(|path| site_relative(path, &site_path_clone)));
                     }
                     _ = sleep => {
                         break; // Quiet period elapsed
@@ -452,44 +864,109 @@ fn start_file_watcher(
                 }
             }
 
+            // What the on_reload commands write isn't a change to react to, and with
+            // `respect_gitignore` neither is a file git ignores
+            if let Some(site) = state.snapshot().await.app_data() {
+                changed.retain(|path| !is_hook_output(&site.config.dev, path) && !site.git_ignored.contains(&site_path_clone.join(path)));
+            }
+            if changed.is_empty() {
+                continue;
+            }
+
             console::status_cyan("Watching", "file change detected, reloading...");
 
-            match AppData::load(site_path_clone.clone(), "dev").await {
-                Ok(new_data) => {
-                    // Clear any previous error
-                    {
-                        let mut error = state.startup_error.write().await;
-                        *error = None;
-                    }
-                    // Update app data
-                    {
-                        let mut app_data = state.app_data.write().await;
-                        *app_data = Some(new_data);
+            // A stale site is the last one that loaded, and `changed` has every change since
+            let snapshot = state.snapshot().await;
+            let load_started = Instant::now();
+            let loaded = match snapshot.app_data() {
+                Some(previous) => AppData::reload(site_path_clone.clone(), "dev", previous).await,
+                None => AppData::load(site_path_clone.clone(), "dev").await,
+            };
+            state.render_stats.lock().unwrap_or_else(PoisonError::into_inner).loaded(load_started.elapsed(), true);
+            match loaded {
+                Ok(mut new_data) => {
+                    new_data.source_reads = SourceReads::Fresh;
+                    for error in &new_data.chrome_errors {
+                        console::warn("pages keep the last version of this that worked until it's fixed");
+                        eprintln!("{:?}", miette::Report::new(error.clone()));
                     }
+                    let changed_paths: Vec<PathBuf> = std::mem::take(&mut changed).into_iter().collect();
+                    keep_unaffected_pages(&state, &new_data, &changed_paths).await;
+                    let dev_config = new_data.config.dev.clone();
+
+                    state.replace_site(SiteState::Ready(new_data)).await;
+                    last_loaded = Local::now();
+                    run_on_reload(&dev_config, &site_path_clone, &changed_paths).await;
                     let _ = state.reload_tx.send(());
                     console::status("Reloaded", "site data");
                 }
                 Err(e) => {
                     console::warn("couldn't reload site data");
+
                     let report = miette::Report::new(e.clone());
                     eprintln!("{:?}", report);
 
-                    // Store the error so it's shown in the browser
-                    {
-                        let mut error = state.startup_error.write().await;
-                        *error = Some(e);
-                    }
+                    // Keep serving the last site that loaded, if one did, with the error over it
+                    // until a reload works
+                    state.replace_site(snapshot.after_failed_reload(e, last_loaded)).await;
                     // Still trigger reload so the browser refreshes and shows the error
                     let _ = state.reload_tx.send(());
                 }
             }
+            // The change may have added the missing files, or taken out what asked for them
+            state.missing_assets.lock().unwrap_or_else(PoisonError::into_inner).clear();
         }
     });
 
     Ok(watcher)
 }
 
-pub async fn run_dev_server(path: PathBuf, requested_port: Option<u16>) -> Result<()> {
+/// A changed path relative to the site root. Paths the watcher reports some other way are kept
+/// as they are, which counts as a change to every page.
+fn site_relative(path: &Path, site_path: &Path) -> PathBuf {
+    let canonical_site = site_path.canonicalize().ok();
+    path.strip_prefix(site_path)
+        .ok()
+        .or_else(|| path.strip_prefix(canonical_site.as_ref()?).ok())
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| PathBuf::from("_"))
+}
+
+/// Carry the cached pages that didn't read any of `changed` over to `new_data`, along with what
+/// they read. Called before `new_data` is swapped in, so no request renders against it yet.
+async fn keep_unaffected_pages(state: &DevAppState, new_data: &AppData, changed: &[PathBuf]) {
+    let site = state.snapshot().await;
+    let Some(old_data) = site.app_data() else {
+        return;
+    };
+
+    let old_deps = old_data.lock_page_deps();
+    let affected = affected_pages(&old_deps, changed, &old_data.macro_trace.files);
+
+    let mut cache = state.render_cache.lock().unwrap_or_else(PoisonError::into_inner);
+    match &affected {
+        Some(affected) => {
+            cache.carry_over(new_data.generation, |url| !affected.contains(url));
+            new_data.lock_page_deps().extend(
+                old_deps
+                    .iter()
+                    .filter(|(url, _)| !affected.contains(*url))
+                    .map(|(url, deps)| (url.clone(), deps.clone())),
+            );
+        }
+        None => cache.carry_over(new_data.generation, |_| false),
+    }
+
+    if state.verbose {
+        let dropped = match &affected {
+            Some(affected) => format!("{} affected", affected.len()),
+            None => String::from("all"),
+        };
+        console::status_cyan("Cache", format!("dropped {} pages after the change", dropped));
+    }
+}
+
+pub async fn run_dev_server(path: PathBuf, requested_port: Option<u16>, open: bool, verbose: bool) -> Result<()> {
     console::status("Starting", "development server with live reload");
     console::status("Watching", path.display());
 
@@ -497,10 +974,16 @@ pub async fn run_dev_server(path: PathBuf, requested_port: Option<u16>) -> Resul
 
     // Try to load the site data, but don't fail if there's an error
     // Instead, store the error and show it in the browser
-    let (app_data, startup_error, minify_config) = match AppData::load(path.clone(), "dev").await {
-        Ok(data) => {
-            let minify = MinifyConfig::new(data.config.build.minify);
-            (Some(data), None, minify)
+    let load_started = Instant::now();
+    let loaded = AppData::load(path.clone(), "dev").await;
+    let mut render_stats = RenderStats::default();
+    render_stats.loaded(load_started.elapsed(), false);
+    let (site, minify_config) = match loaded {
+        Ok(mut data) => {
+            // Serve edits to a page right away, without waiting for the reload they trigger
+            data.source_reads = SourceReads::Fresh;
+            let minify = MinifyConfig::from_build(&data.config.build);
+            (SiteState::Ready(data), minify)
         }
         Err(e) => {
             // Print the error to terminal as well
@@ -510,15 +993,30 @@ pub async fn run_dev_server(path: PathBuf, requested_port: Option<u16>) -> Resul
             console::status_cyan("Waiting", "for file changes to retry...");
 
             // Use default minify config when we can't load the site
-            (None, Some(e), MinifyConfig::new(false))
+            (SiteState::Failed(e), MinifyConfig::new(false))
         }
     };
 
+    let dev_state = DevState::load(&path);
+    let remembered_port = dev_state.port;
+    let open_page = dev_state
+        .recent_pages
+        .first()
+        .filter(|url| site.app_data().is_some_and(|data| page_for_request(&data.pages, url).is_some()))
+        .cloned()
+        .unwrap_or_else(|| "/".to_string());
+
     let state = Arc::new(DevAppState {
-        app_data: RwLock::new(app_data),
-        startup_error: RwLock::new(startup_error),
+        site: RwLock::new(Arc::new(site)),
         reload_tx,
         minify_config,
+        render_cache: Mutex::new(RenderCache::default()),
+        verbose,
+        dev_state: Mutex::new(dev_state),
+        state_site_path: requested_port.is_none().then(|| path.clone()),
+        missing_assets: Mutex::new(MissingAssets::default()),
+        slow_pages: Mutex::new(SlowPages::default()),
+        render_stats: Mutex::new(render_stats),
     });
 
     let mut watcher = start_file_watcher(path.clone(), Arc::clone(&state))
@@ -531,15 +1029,49 @@ pub async fn run_dev_server(path: PathBuf, requested_port: Option<u16>) -> Resul
             cause: e,
         })?;
 
-    let (server, actual_port) = try_bind_server(Arc::clone(&state), &path, requested_port)?;
+    let server_state = Arc::clone(&state);
+    let app_factory = move || {
+        App::new()
+            .app_data(web::Data::new(Arc::clone(&server_state)))
+            .wrap(middleware::from_fn(cors))
+            .service(live_reload_ws)
+            .service(reload_js)
+            .service(theme)
+            .service(theme_hashed)
+            .service(sitemap)
+            .service(page)
+    };
+    let command = format!("hugs dev {}", path.display());
+    let (server, actual_port) = match requested_port {
+        Some(port) => bind_with_retry(app_factory, "127.0.0.1", port, true, &command)?,
+        None => bind_preferring(app_factory, "127.0.0.1", remembered_port, DEFAULT_PORT, &command)?,
+    };
 
     console::status("Listening", format!("http://127.0.0.1:{}", actual_port));
 
     // Display warning if port changed (after the server starting log)
-    if requested_port.is_none() && actual_port != DEFAULT_PORT {
+    if requested_port.is_none() && actual_port != DEFAULT_PORT && Some(actual_port) != remembered_port {
         PortChangedWarning::new(actual_port).display();
     }
 
+    // Remember the port, so tomorrow's run tries it first
+    if requested_port.is_none() && remembered_port != Some(actual_port) {
+        let mut dev_state = state.dev_state.lock().unwrap_or_else(PoisonError::into_inner);
+        dev_state.port = Some(actual_port);
+        dev_state.save(&path);
+    }
+
+    if open {
+        let url = format!("http://127.0.0.1:{}{}", actual_port, encode_url_path(&open_page));
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            if let Err(e) = open::that(&url) {
+                console::warn(format!("couldn't open browser: {}", e));
+                println!("  Open {} in your browser", url.cyan());
+            }
+        });
+    }
+
     server
         .await
         .map_err(|e| HugsError::ServerRuntime { cause: e })?;
@@ -548,65 +1080,626 @@ pub async fn run_dev_server(path: PathBuf, requested_port: Option<u16>) -> Resul
     Ok(())
 }
 
-/// Attempt to bind to a port, retrying with incrementing ports if port was not explicitly specified
-fn try_bind_server(
-    state: Arc<DevAppState>,
-    path: &PathBuf,
-    requested_port: Option<u16>,
-) -> Result<(actix_web::dev::Server, u16)> {
-    if let Some(port) = requested_port {
-        // Port was explicitly specified: fail immediately if unavailable
-        let state_for_server = Arc::clone(&state);
-        let server = HttpServer::new(move || {
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::{Method, header};
+    use actix_web::middleware::from_fn;
+    use actix_web::test;
+    use std::collections::HashSet;
+
+    async fn fixture_state() -> Arc<DevAppState> {
+        let site_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/basic/site");
+        let app_data = AppData::load(site_path, "dev").await.unwrap();
+        let (reload_tx, _) = broadcast::channel(16);
+
+        Arc::new(DevAppState {
+            site: RwLock::new(Arc::new(SiteState::Ready(app_data))),
+            reload_tx,
+            minify_config: MinifyConfig::new(false),
+            render_cache: Mutex::new(RenderCache::default()),
+            verbose: false,
+            dev_state: Mutex::new(DevState::default()),
+            state_site_path: None,
+            missing_assets: Mutex::new(MissingAssets::default()),
+            slow_pages: Mutex::new(SlowPages::default()),
+            render_stats: Mutex::new(RenderStats::default()),
+        })
+    }
+
+    async fn fixture_site_path(state: &DevAppState) -> PathBuf {
+        match state.snapshot().await.as_ref() {
+            SiteState::Ready(app_data) => app_data.site_path.clone(),
+            SiteState::Stale { .. } | SiteState::Failed(_) => panic!("the fixture site should load"),
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_percent_encoded_paths_resolve_like_the_build() {
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(fixture_state().await))
+                .service(page),
+        )
+        .await;
+
+        for uri in [
+            "/assets/notes%20%231.txt",
+            "/assets/caf%C3%A9.svg",
+            "/notes/hello%20world",
+            "/notes/hello%20world?utm_source=feed",
+            "/tags/hello%20world",
+            "/tags/caf%C3%A9",
+            "/tags/%F0%9F%8E%89",
+        ] {
+            let res = test::call_service(&app, test::TestRequest::get().uri(uri).to_request()).await;
+            assert_eq!(res.status(), 200, "{} should resolve", uri);
+        }
+
+        let res = test::call_service(&app, test::TestRequest::get().uri("/notes/hello%20world").to_request()).await;
+        let html = String::from_utf8(test::read_body(res).await.to_vec()).unwrap();
+        assert!(html.contains("https://golden.example/notes/hello%20world"), "canonical URL should be encoded");
+    }
+
+    #[actix_web::test]
+    async fn test_debug_switches_from_query() {
+        assert_eq!(
+            DebugSwitches::from_query("utm_source=feed&__hugs_delay=250&__hugs_status=503&__hugs_nominify=1"),
+            DebugSwitches {
+                delay: Some(Duration::from_millis(250)),
+                status: Some(actix_web::http::StatusCode::SERVICE_UNAVAILABLE),
+                no_minify: true,
+            }
+        );
+        // Only error statuses, and delays are capped
+        assert_eq!(DebugSwitches::from_query("__hugs_status=200").status, None);
+        assert_eq!(DebugSwitches::from_query("__hugs_delay=9999999").delay, Some(MAX_DEBUG_DELAY));
+        assert_eq!(DebugSwitches::from_query("__hugs_nominify=0"), DebugSwitches::default());
+    }
+
+    #[actix_web::test]
+    async fn test_debug_switches_need_debug_tools() {
+        let state = fixture_state().await;
+        let app = test::init_service(App::new().app_data(web::Data::new(Arc::clone(&state))).service(page)).await;
+        let get = |uri: &'static str| {
+            let app = &app;
+            async move {
+                let res = test::call_service(app, test::TestRequest::get().uri(uri).to_request()).await;
+                let status = res.status();
+                (status, String::from_utf8(test::read_body(res).await.to_vec()).unwrap())
+            }
+        };
+
+        assert_eq!(get("/about?__hugs_status=500").await.0, 200);
+        assert_eq!(get("/__hugs/debug").await.0, 404);
+
+        let mut app_data = AppData::load(fixture_site_path(&state).await, "dev").await.unwrap();
+        app_data.config.dev.debug_tools = true;
+        state.replace_site(SiteState::Ready(app_data)).await;
+
+        let (status, html) = get("/about?__hugs_status=500").await;
+        assert_eq!(status, 500);
+        assert!(html.contains("__hugs_status"), "the error page should say why");
+        assert_eq!(get("/about?__hugs_status=404").await.0, 404);
+
+        let (status, html) = get("/__hugs/debug").await;
+        assert_eq!(status, 200);
+        for (example, _) in DebugSwitches::ALL {
+            assert!(html.contains(example), "{} should be listed", example);
+        }
+
+        let (status, html) = get("/about?__hugs_nominify=1&__hugs_delay=1").await;
+        assert_eq!(status, 200);
+        assert!(html.contains("<title>About | Golden</title>"));
+    }
+
+    #[actix_web::test]
+    async fn test_pages_win_over_static_files_like_the_build() {
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(fixture_state().await))
+                .service(page),
+        )
+        .await;
+
+        // The fixture has a stale about/index.html next to about.md
+        for uri in ["/about", "/about/index.html"] {
+            let res = test::call_service(&app, test::TestRequest::get().uri(uri).to_request()).await;
+            let html = String::from_utf8(test::read_body(res).await.to_vec()).unwrap();
+            assert!(!html.contains("Stale export"), "{} should serve the rendered page", uri);
+            assert!(html.contains("<title>About | Golden</title>"), "{} should serve the rendered page", uri);
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_excluded_files_are_missing_from_dev_and_build() {
+        let state = fixture_state().await;
+        let app = test::init_service(App::new().app_data(web::Data::new(Arc::clone(&state))).service(page)).await;
+        let status = |uri: &'static str| {
+            let app = &app;
+            async move { test::call_service(app, test::TestRequest::get().uri(uri).to_request()).await.status() }
+        };
+
+        let site_path = fixture_site_path(&state).await;
+        let output = tempfile::tempdir().unwrap();
+        crate::build::run_build(site_path, output.path().join("dist"), crate::build::BuildOptions::default()).await.unwrap();
+        let built = |relative: &str| output.path().join("dist").join(relative).exists();
+
+        // Excluded by default, and site configuration
+        for (uri, relative) in [("/scripts/deploy.sh", "scripts/deploy.sh"), ("/config.toml", "config.toml")] {
+            assert_eq!(status(uri).await, 404, "{} shouldn't be served", uri);
+            assert!(!built(relative), "{} shouldn't be copied", relative);
+        }
+        // Asking again doesn't warn again until the site reloads
+        assert_eq!(status("/scripts/deploy.sh").await, 404);
+        let snapshot = state.snapshot().await;
+        let warned = snapshot.app_data().unwrap().excluded_requests.lock().unwrap().clone();
+        assert_eq!(warned, HashSet::from([String::from("scripts/deploy.sh")]));
+
+        assert_eq!(status("/notes/ideas.txt").await, 200);
+        assert!(built("notes/ideas.txt"));
+
+        // Markdown still renders as pages and is never served raw
+        assert_eq!(status("/blog/first-post").await, 200);
+        assert_eq!(status("/blog/first-post.md").await, 404);
+        assert!(built("blog/first-post/index.html"));
+        assert!(!built("blog/first-post.md"));
+    }
+
+    #[actix_web::test]
+    async fn test_missing_assets_are_noted_with_the_page_that_asked() {
+        let state = fixture_state().await;
+        let app = test::init_service(App::new().app_data(web::Data::new(Arc::clone(&state))).service(page)).await;
+        let get = |uri: &'static str, referer: &'static str| {
+            let app = &app;
+            async move {
+                let req = test::TestRequest::get().uri(uri).insert_header((header::REFERER, referer)).to_request();
+                let res = test::call_service(app, req).await;
+                (res.status(), test::read_body(res).await)
+            }
+        };
+        let noted = || -> Vec<(String, String)> {
+            let missing = state.missing_assets.lock().unwrap();
+            missing.entries().iter().map(|entry| (entry.page.clone(), entry.asset.clone())).collect()
+        };
+
+        assert_eq!(get("/img/hero.png", "http://localhost:8080/blog/first-post").await.0, 404);
+        assert_eq!(get("/img/other.png", "https://elsewhere.example/post").await.0, 404);
+        assert_eq!(get("/blog/no-such-post", "http://localhost:8080/").await.0, 404);
+        assert_eq!(noted(), [(String::from("/blog/first-post"), String::from("/img/hero.png"))]);
+
+        state.missing_assets.lock().unwrap().record("/about", "/assets/logo.svg");
+        assert_eq!(get("/assets/logo.svg", "http://localhost:8080/about").await.0, 200);
+        assert_eq!(noted().len(), 1, "a file that's served again isn't missing");
+
+        let mut app_data = AppData::load(fixture_site_path(&state).await, "dev").await.unwrap();
+        app_data.config.dev.debug_tools = true;
+        state.replace_site(SiteState::Ready(app_data)).await;
+        let (status, body) = get("/__hugs/api", "").await;
+        assert_eq!(status, 200);
+        let api: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            api["missing_assets"],
+            serde_json::json!([{ "page": "/blog/first-post", "asset": "/img/hero.png", "requests": 1 }])
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_render_stats_count_each_page() {
+        let state = fixture_state().await;
+        let mut app_data = AppData::load(fixture_site_path(&state).await, "dev").await.unwrap();
+        app_data.config.dev.debug_tools = true;
+        state.replace_site(SiteState::Ready(app_data)).await;
+        let app = test::init_service(App::new().app_data(web::Data::new(Arc::clone(&state))).service(page)).await;
+        let get = |uri: &'static str| {
+            let app = &app;
+            async move {
+                let res = test::call_service(app, test::TestRequest::get().uri(uri).to_request()).await;
+                let status = res.status();
+                (status, String::from_utf8(test::read_body(res).await.to_vec()).unwrap())
+            }
+        };
+
+        for _ in 0..3 {
+            assert_eq!(get("/about").await.0, 200);
+        }
+        assert_eq!(get("/blog/first-post").await.0, 200);
+
+        let (status, body) = get("/__hugs/api/stats").await;
+        assert_eq!(status, 200);
+        let stats: serde_json::Value = serde_json::from_str(&body).unwrap();
+        let about = stats["pages"].as_array().unwrap().iter().find(|entry| entry["url"] == "/about").unwrap();
+        assert_eq!((&about["renders"], &about["cache_hits"]), (&serde_json::json!(1), &serde_json::json!(2)));
+        for phase in ["last_render_ms", "template_ms", "markdown_ms", "highlight_ms", "minify_ms"] {
+            assert!(about[phase].is_f64(), "{phase} should be a number of milliseconds");
+        }
+        assert!(about["template_ms"].as_f64() > Some(0.0), "templates take some time");
+        assert_eq!(stats["pages"].as_array().unwrap().len(), 2);
+        assert_eq!(stats["reloads"], 0);
+
+        let (status, html) = get("/__hugs/stats").await;
+        assert_eq!(status, 200);
+        assert!(html.contains("<a href=\"/about\">/about</a>") && html.contains("/blog/first-post"), "{html}");
+    }
+
+    #[actix_web::test]
+    async fn test_records_what_each_page_read() {
+        let state = fixture_state().await;
+        let app = test::init_service(App::new().app_data(web::Data::new(Arc::clone(&state))).service(page)).await;
+
+        for uri in ["/", "/about", "/tags/rust"] {
+            let res = test::call_service(&app, test::TestRequest::get().uri(uri).to_request()).await;
+            assert_eq!(res.status(), 200, "{} should render", uri);
+        }
+
+        let site = state.snapshot().await;
+        let SiteState::Ready(app_data) = site.as_ref() else {
+            panic!("the fixture site should load");
+        };
+        let page_deps = app_data.lock_page_deps().clone();
+        let files = |url: &str| -> Vec<String> {
+            page_deps[url].files.iter().map(|path| path.display().to_string()).collect()
+        };
+
+        // root.jinja cache-busts theme.css and highlight.css (generated from config.toml)
+        assert_eq!(files("/"), ["_/theme.css", "config.toml", "index.md"]);
+        assert!(page_deps["/"].pages, "the home page lists posts with pages()");
+        assert!(page_deps["/"].macros.is_empty());
+
+        assert_eq!(files("/about"), ["_/theme.css", "about.md", "config.toml"]);
+        assert!(!page_deps["/about"].pages);
+        assert!(page_deps["/about"].macros.contains("note"));
+
+        assert!(files("/tags/rust").contains(&String::from("tags/[tag].md")));
+        assert!(page_deps["/tags/rust"].pages, "a dynamic page's values come from pages()");
+    }
+
+    #[actix_web::test]
+    async fn test_reload_keeps_pages_the_change_did_not_touch() {
+        let state = fixture_state().await;
+        let app = test::init_service(App::new().app_data(web::Data::new(Arc::clone(&state))).service(page)).await;
+
+        for uri in ["/", "/about"] {
+            test::call_service(&app, test::TestRequest::get().uri(uri).to_request()).await;
+        }
+
+        let site_path = fixture_site_path(&state).await;
+        let reload = || async { AppData::load(site_path.clone(), "dev").await.unwrap() };
+        let cached = |data: &AppData, url: &str| state.cached_page(url, data).is_some();
+
+        // A new post changes what pages() returns on the home page, but not the about page
+        let new_data = reload().await;
+        keep_unaffected_pages(&state, &new_data, &[PathBuf::from("blog/third-post.md")]).await;
+        assert!(!cached(&new_data, "/"));
+        assert!(cached(&new_data, "/about"));
+        assert!(new_data.lock_page_deps().contains_key("/about"));
+
+        // A macro only changes the pages that use it
+        state.replace_site(SiteState::Ready(new_data)).await;
+        for uri in ["/", "/about"] {
+            test::call_service(&app, test::TestRequest::get().uri(uri).to_request()).await;
+        }
+        let macro_data = reload().await;
+        keep_unaffected_pages(&state, &macro_data, &[PathBuf::from("_/macros/note.md")]).await;
+        assert!(cached(&macro_data, "/"));
+        assert!(!cached(&macro_data, "/about"));
+
+        // Anything else under _/ may change every page
+        state.replace_site(SiteState::Ready(macro_data)).await;
+        let newer_data = reload().await;
+        keep_unaffected_pages(&state, &newer_data, &[PathBuf::from("_/nav.md")]).await;
+        assert!(!cached(&newer_data, "/about"));
+    }
+
+    #[actix_web::test]
+    async fn test_requests_racing_a_reload_see_one_site() {
+        let site = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(site.path().join("_")).unwrap();
+        for (file, content) in [
+            ("config.toml", "[dev]\ndebug_tools = true\n"),
+            ("_/header.md", "# Old header"),
+            ("_/nav.md", "[Home](/)"),
+            ("_/footer.md", "Bye"),
+            ("_/theme.css", "body {}"),
+            ("index.md", "---\ntitle: Home\n---\nHello"),
+        ] {
+            std::fs::write(site.path().join(file), content).unwrap();
+        }
+        let load = || AppData::load(site.path().to_path_buf(), "dev");
+        let old_data = load().await.unwrap();
+        std::fs::write(site.path().join("_/header.md"), "# New header").unwrap();
+        let new_data = load().await.unwrap();
+
+        let (reload_tx, _) = broadcast::channel(16);
+        let state = Arc::new(DevAppState {
+            site: RwLock::new(Arc::new(SiteState::Ready(old_data))),
+            reload_tx,
+            minify_config: MinifyConfig::new(false),
+            render_cache: Mutex::new(RenderCache::default()),
+            verbose: false,
+            dev_state: Mutex::new(DevState::default()),
+            state_site_path: None,
+            missing_assets: Mutex::new(MissingAssets::default()),
+            slow_pages: Mutex::new(SlowPages::default()),
+            render_stats: Mutex::new(RenderStats::default()),
+        });
+        let app = test::init_service(App::new().app_data(web::Data::new(Arc::clone(&state))).service(page)).await;
+        let get = |uri: &'static str| {
+            let app = &app;
+            async move {
+                let res = test::call_service(app, test::TestRequest::get().uri(uri).to_request()).await;
+                let status = res.status();
+                (status, String::from_utf8(test::read_body(res).await.to_vec()).unwrap())
+            }
+        };
+        // The clock only moves when every task is waiting on it, so the swap always lands while
+        // the slow request sleeps, after it took its snapshot, however busy the machine is
+        tokio::time::pause();
+        // Swap the site while a slow render is waiting to start
+        let swap_midway = |next: SiteState| {
+            let state = Arc::clone(&state);
+            async move {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                state.replace_site(next).await;
+            }
+        };
+
+        // A reload that fails doesn't turn a request on the old site into an error page
+        let broken = HugsError::DevForcedStatus { status: 500.into() };
+        let ((status, html), ()) = tokio::join!(get("/?__hugs_delay=300"), swap_midway(SiteState::Failed(broken)));
+        assert_eq!(status, 200);
+        assert!(html.contains("Old header"));
+        assert_eq!(get("/").await.0, 500, "requests after the swap see the error");
+
+        // A reload that works leaves the slow request wholly on the failed site, and the next one
+        // wholly on the new one
+        let ((status, _), ()) = tokio::join!(get("/?__hugs_delay=300"), swap_midway(SiteState::Ready(new_data)));
+        assert_eq!(status, 500);
+        let (status, html) = get("/").await;
+        assert_eq!(status, 200);
+        assert!(html.contains("New header") && !html.contains("Old header"));
+    }
+
+    #[actix_web::test]
+    async fn test_renders_past_the_timeout_are_given_up_on() {
+        assert_eq!(within(Duration::from_secs(5), async { 42 }).await, Some(42));
+        let slow = within(Duration::from_millis(20), async { std::thread::sleep(Duration::from_millis(200)) });
+        assert_eq!(slow.await, None);
+    }
+
+    #[actix_web::test]
+    async fn test_timed_out_pages_wait_for_a_retry_or_reload() {
+        let state = fixture_state().await;
+        let app = test::init_service(App::new().app_data(web::Data::new(Arc::clone(&state))).service(page)).await;
+        let get = |uri: &'static str| {
+            let app = &app;
+            async move {
+                let res = test::call_service(app, test::TestRequest::get().uri(uri).to_request()).await;
+                let location = res.headers().get(header::LOCATION).map(|location| location.to_str().unwrap().to_string());
+                (res.status(), location, String::from_utf8(test::read_body(res).await.to_vec()).unwrap())
+            }
+        };
+        let generation = match state.snapshot().await.as_ref() {
+            SiteState::Ready(app_data) => app_data.generation,
+            SiteState::Stale { .. } | SiteState::Failed(_) => panic!("the fixture site should load"),
+        };
+        state.slow_pages.lock().unwrap().timed_out("/about", generation);
+        state.slow_pages.lock().unwrap().timed_out("/about", generation);
+
+        let (status, _, html) = get("/about").await;
+        assert_eq!(status, 503);
+        assert!(html.contains("render_timeout_secs") && html.contains("2 times in a row"), "{html}");
+        assert!(html.contains(r#"href="/__hugs/retry?url=%2Fabout""#), "{html}");
+        assert_eq!(get("/notes/ideas.txt").await.0, 200, "the rest of the site is served as usual");
+
+        let (status, location, _) = get("/__hugs/retry?url=%2Fabout").await;
+        assert_eq!((status.as_u16(), location.as_deref()), (302, Some("/about")));
+        let (status, _, html) = get("/about").await;
+        assert_eq!(status, 200);
+        assert!(html.contains("<title>About | Golden</title>"));
+        assert_eq!(get("/about").await.0, 200, "a page that renders in time is cleared");
+
+        // The retry link only ever goes somewhere on the site
+        assert_eq!(get("/__hugs/retry?url=%2F%2Fexample.com").await.1.as_deref(), Some("/"));
+    }
+
+    #[actix_web::test]
+    async fn test_failed_reloads_keep_serving_the_last_good_site() {
+        let state = fixture_state().await;
+        let app = test::init_service(
+            App::new().app_data(web::Data::new(Arc::clone(&state))).service(theme).service(page),
+        )
+        .await;
+        let get = |uri: &'static str| {
+            let app = &app;
+            async move {
+                let res = test::call_service(app, test::TestRequest::get().uri(uri).to_request()).await;
+                let status = res.status();
+                (status, String::from_utf8(test::read_body(res).await.to_vec()).unwrap())
+            }
+        };
+        let broken = || HugsError::DevForcedStatus { status: 500.into() };
+        let loaded_at = Local::now().with_time(chrono::NaiveTime::from_hms_opt(14, 2, 0).unwrap()).unwrap();
+
+        // Healthy: pages as they are
+        let (status, html) = get("/about").await;
+        assert_eq!(status, 200);
+        assert!(!html.contains("hugs-stale"), "{html}");
+
+        // A reload that kept the last nav that worked says which file to fix
+        let mut kept_nav = state.snapshot().await.app_data().unwrap().clone();
+        kept_nav.chrome_errors = vec![HugsError::MarkdownParse { file: "_/nav.md".into(), reason: "unclosed".into() }];
+        state.replace_site(SiteState::Ready(kept_nav)).await;
+        let (status, html) = get("/about").await;
+        assert_eq!(status, 200);
+        assert!(html.contains("last version of this that worked") && html.contains("_/nav.md"), "{html}");
+        assert_eq!(html.matches("hugs-stale").count(), 1, "{html}");
+
+        // A reload fails: the last good site is still served, saying so on every page
+        let registry = state.snapshot().await.app_data().unwrap().cache_bust_registry.clone();
+        assert!(!registry.entries().is_empty(), "rendering pages hashed their assets");
+        let stale = state.snapshot().await.after_failed_reload(broken(), loaded_at);
+        assert!(registry.entries().is_empty(), "hashes from before the edit are forgotten");
+        state.replace_site(stale).await;
+        for _ in 0..2 {
+            let (status, html) = get("/about").await;
+            assert_eq!(status, 200);
+            assert!(html.contains("<title>About | Golden</title>"), "{html}");
+            assert!(html.contains("last good version, from 14:02") && html.contains("500"), "{html}");
+            assert_eq!(html.matches("hugs-stale").count(), 1, "a cached page gets the bar once: {html}");
+        }
+        assert_eq!(get("/theme.css").await.0, 200);
+
+        // Failing again keeps the time the site last loaded
+        let staler = state.snapshot().await.after_failed_reload(broken(), Local::now());
+        assert!(matches!(&staler, SiteState::Stale { loaded_at: at, .. } if *at == loaded_at));
+
+        // Never loaded: the error everywhere
+        let never = SiteState::Failed(broken()).after_failed_reload(broken(), Local::now());
+        state.replace_site(never).await;
+        assert_eq!(get("/about").await.0, 500);
+        assert_eq!(get("/theme.css").await.0, 500);
+    }
+
+    #[actix_web::test]
+    async fn test_failed_sites_answer_each_route_in_its_own_type() {
+        let state = fixture_state().await;
+        state.replace_site(SiteState::Failed(HugsError::DevForcedStatus { status: 500.into() })).await;
+        let app = test::init_service(
             App::new()
-                .app_data(web::Data::new(Arc::clone(&state_for_server)))
-                .service(live_reload_ws)
+                .app_data(web::Data::new(Arc::clone(&state)))
                 .service(theme)
                 .service(theme_hashed)
                 .service(sitemap)
-                .service(page)
-        })
-        .bind(("127.0.0.1", port))
-        .map_err(|e| HugsError::port_bind(path, port, e))?;
+                .service(page),
+        )
+        .await;
+
+        for (uri, content_type) in [
+            ("/", "text/html"),
+            ("/about", "text/html"),
+            ("/theme.css", "text/plain"),
+            ("/theme.1e092a90.css", "text/plain"),
+            ("/sitemap.xml", "text/plain"),
+            ("/scripts/app.js", "text/plain"),
+            ("/search.json", "text/plain"),
+        ] {
+            let res = test::call_service(&app, test::TestRequest::get().uri(uri).to_request()).await;
+            assert_eq!(res.status(), 500, "{}", uri);
+            let header = res.headers().get(header::CONTENT_TYPE).unwrap().to_str().unwrap().to_string();
+            assert!(header.starts_with(content_type), "{} was sent as {}", uri, header);
+
+            let body = String::from_utf8(test::read_body(res).await.to_vec()).unwrap();
+            if content_type == "text/plain" {
+                // One line saying what went wrong, and where to find the rest
+                let mut lines = body.lines();
+                let summary = lines.next().unwrap();
+                assert_eq!(summary, "I answered with status 500 because the URL asked me to [hugs::dev::forced_status]");
+                assert!(lines.next().unwrap().contains("terminal"), "{}", body);
+                assert!(!body.contains('<'), "{}", body);
+            } else {
+                assert!(body.contains("<html") && body.contains("__hugs_status"), "{}", body);
+            }
+        }
+    }
 
-        Ok((server.run(), port))
-    } else {
-        let port: u16 = DEFAULT_PORT;
+    #[actix_web::test]
+    async fn test_pages_load_the_reload_script_by_src() {
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(fixture_state().await))
+                .service(reload_js)
+                .service(page),
+        )
+        .await;
+
+        let res = test::call_service(&app, test::TestRequest::get().uri("/about").to_request()).await;
+        let html = String::from_utf8(test::read_body(res).await.to_vec()).unwrap();
+        assert!(html.contains(LIVE_RELOAD_SCRIPT));
+        assert!(!html.contains("new WebSocket"), "the script shouldn't be inline");
+
+        let res = test::call_service(&app, test::TestRequest::get().uri("/__hugs/reload.js").to_request()).await;
+        assert_eq!(res.status(), 200);
+        assert_eq!(res.headers().get("content-type").unwrap(), "application/javascript; charset=utf-8");
+        assert_eq!(test::read_body(res).await, LIVE_RELOAD_JS);
+
+        assert!(LIVE_RELOAD_HASH.starts_with("'sha256-") && LIVE_RELOAD_HASH.ends_with("='"));
+        assert_eq!(LIVE_RELOAD_HASH.len(), "'sha256-'".len() + 44);
+    }
 
-        // Default port: try subsequent ports until one is available
-        for attempt in 0..MAX_PORT_RETRIES {
-            let try_port = match port.checked_add(attempt) {
-                Some(p) => p,
-                None => break, // Port overflow, stop trying
-            };
+    async fn state_with_cors(cors: &[&str], cors_pages: bool) -> Arc<DevAppState> {
+        let state = fixture_state().await;
+        let mut app_data = AppData::load(fixture_site_path(&state).await, "dev").await.unwrap();
+        app_data.config.dev.cors = cors.iter().map(|origin| origin.to_string()).collect();
+        app_data.config.dev.cors_pages = cors_pages;
+        state.replace_site(SiteState::Ready(app_data)).await;
+        state
+    }
 
-            let state_for_server = Arc::clone(&state);
-            match HttpServer::new(move || {
-                App::new()
-                    .app_data(web::Data::new(Arc::clone(&state_for_server)))
-                    .service(live_reload_ws)
-                    .service(theme)
-                    .service(theme_hashed)
-                    .service(sitemap)
-                    .service(page)
-            })
-            .bind(("127.0.0.1", try_port))
-            {
-                Ok(server) => {
-                    return Ok((server.run(), try_port));
-                }
-                Err(_) => {
-                    // Try next port
-                    continue;
-                }
-            }
-        }
+    fn allow_origin(res: &actix_web::dev::ServiceResponse) -> Option<&str> {
+        res.headers().get(header::ACCESS_CONTROL_ALLOW_ORIGIN).and_then(|value| value.to_str().ok())
+    }
 
-        // All retries exhausted
-        let end_port = port.saturating_add(MAX_PORT_RETRIES - 1);
-        Err(HugsError::NoAvailablePort {
-            start_port: port.into(),
-            end_port: end_port.into(),
-        })
+    #[actix_web::test]
+    async fn test_cors_preflight_and_cross_origin_get() {
+        let state = state_with_cors(&["http://localhost:5173"], false).await;
+        let app = test::init_service(
+            App::new().app_data(web::Data::new(state)).wrap(from_fn(cors)).service(page),
+        )
+        .await;
+        let from = |req: test::TestRequest, origin: &str| req.insert_header((header::ORIGIN, origin.to_string())).to_request();
+
+        let preflight = test::TestRequest::default()
+            .method(Method::OPTIONS)
+            .uri("/assets/notes%20%231.txt")
+            .insert_header((header::ACCESS_CONTROL_REQUEST_METHOD, "GET"))
+            .insert_header((header::ACCESS_CONTROL_REQUEST_HEADERS, "x-requested-with"));
+        let res = test::call_service(&app, from(preflight, "http://localhost:5173")).await;
+        assert_eq!(res.status(), 204);
+        assert_eq!(allow_origin(&res), Some("http://localhost:5173"));
+        assert_eq!(res.headers().get(header::ACCESS_CONTROL_ALLOW_HEADERS).unwrap(), "x-requested-with");
+
+        let file = test::TestRequest::get().uri("/assets/notes%20%231.txt");
+        let res = test::call_service(&app, from(file, "http://localhost:5173")).await;
+        assert_eq!(res.status(), 200);
+        assert_eq!(allow_origin(&res), Some("http://localhost:5173"));
+
+        // HTML pages stay same-origin, and so does everything for origins that aren't listed
+        let about = test::TestRequest::get().uri("/about");
+        let res = test::call_service(&app, from(about, "http://localhost:5173")).await;
+        assert_eq!(res.status(), 200);
+        assert_eq!(allow_origin(&res), None);
+
+        let file = test::TestRequest::get().uri("/assets/notes%20%231.txt");
+        let res = test::call_service(&app, from(file, "http://localhost:3000")).await;
+        assert_eq!(allow_origin(&res), None);
+    }
+
+    #[actix_web::test]
+    async fn test_cors_any_origin_and_pages() {
+        let state = state_with_cors(&["*"], true).await;
+        let app = test::init_service(
+            App::new().app_data(web::Data::new(state)).wrap(from_fn(cors)).service(page),
+        )
+        .await;
+
+        let about = test::TestRequest::get().uri("/about").insert_header((header::ORIGIN, "http://127.0.0.1:8000"));
+        let res = test::call_service(&app, about.to_request()).await;
+        assert_eq!(allow_origin(&res), Some("*"));
+
+        // Without dev.cors there are no CORS headers and preflights aren't answered
+        let state = state_with_cors(&[], false).await;
+        let app = test::init_service(
+            App::new().app_data(web::Data::new(state)).wrap(from_fn(cors)).service(page),
+        )
+        .await;
+        let preflight = test::TestRequest::default()
+            .method(Method::OPTIONS)
+            .uri("/about")
+            .insert_header((header::ORIGIN, "http://localhost:5173"))
+            .insert_header((header::ACCESS_CONTROL_REQUEST_METHOD, "GET"));
+        let res = test::call_service(&app, preflight.to_request()).await;
+        assert_ne!(res.status(), 204);
+        assert_eq!(allow_origin(&res), None);
     }
 }
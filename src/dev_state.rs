@@ -0,0 +1,115 @@
+//! `_/.hugs-state.json`: what `hugs dev` remembers about a site between runs.
+//!
+//! I keep the port the last run listened on, so a bookmarked localhost URL keeps working, and the
+//! pages you looked at most recently, so `hugs dev --open` can take you back to where you were.
+//! The file is only a convenience: if it's missing, unreadable or from some other version, I start
+//! from scratch without a word. Keep it out of git; it's different on every machine.
+
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::console;
+
+/// Where the state lives, relative to the site root
+pub const STATE_FILE: &str = "_/.hugs-state.json";
+
+/// How many recently viewed pages I remember
+const RECENT_PAGES: usize = 10;
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct DevState {
+    /// Port the last `hugs dev` run listened on
+    #[serde(default)]
+    pub port: Option<u16>,
+    /// Page URLs viewed most recently, newest first
+    #[serde(default)]
+    pub recent_pages: Vec<String>,
+}
+
+impl DevState {
+    /// The site's saved state, or a fresh one when there's none I can use
+    pub fn load(site_path: &Path) -> Self {
+        let Ok(json) = std::fs::read_to_string(state_path(site_path)) else {
+            return Self::default();
+        };
+        let mut state: Self = serde_json::from_str(&json).unwrap_or_default();
+        state.port = state.port.filter(|&port| port != 0);
+        state.recent_pages.retain(|url| url.starts_with('/'));
+        state.recent_pages.truncate(RECENT_PAGES);
+        state
+    }
+
+    /// Write the state back. It's only a convenience, so failing to is a warning.
+    pub fn save(&self, site_path: &Path) {
+        let path = state_path(site_path);
+        let written = path
+            .parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .and_then(|_| std::fs::write(&path, serde_json::to_string_pretty(self).unwrap_or_default()));
+
+        if let Err(e) = written {
+            console::warn(format!("couldn't save {}: {}", STATE_FILE, e));
+        }
+    }
+
+    /// Put `url` at the front of the recent pages. Returns whether that changed anything.
+    pub fn record_page(&mut self, url: &str) -> bool {
+        if self.recent_pages.first().is_some_and(|last| last == url) {
+            return false;
+        }
+
+        self.recent_pages.retain(|recent| recent != url);
+        self.recent_pages.insert(0, url.to_string());
+        self.recent_pages.truncate(RECENT_PAGES);
+        true
+    }
+}
+
+fn state_path(site_path: &Path) -> PathBuf {
+    site_path.join(STATE_FILE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_state_round_trips() {
+        let site = tempfile::tempdir().unwrap();
+        let mut state = DevState { port: Some(8081), ..Default::default() };
+        state.record_page("/blog/hello");
+        state.save(site.path());
+
+        assert_eq!(DevState::load(site.path()), state);
+    }
+
+    #[test]
+    fn test_unusable_state_is_ignored() {
+        let site = tempfile::tempdir().unwrap();
+        assert_eq!(DevState::load(site.path()), DevState::default());
+
+        std::fs::create_dir(site.path().join("_")).unwrap();
+        std::fs::write(site.path().join(STATE_FILE), "{ not json").unwrap();
+        assert_eq!(DevState::load(site.path()), DevState::default());
+
+        std::fs::write(site.path().join(STATE_FILE), r#"{"port": 0, "recent_pages": ["/about", "http://elsewhere"], "theme": "dark"}"#).unwrap();
+        assert_eq!(
+            DevState::load(site.path()),
+            DevState { port: None, recent_pages: vec!["/about".to_string()] }
+        );
+    }
+
+    #[test]
+    fn test_recent_pages_keep_the_newest_first() {
+        let mut state = DevState::default();
+        for i in 0..RECENT_PAGES + 2 {
+            assert!(state.record_page(&format!("/p{}", i)));
+        }
+        assert!(state.record_page("/p5"));
+        assert!(!state.record_page("/p5"));
+
+        assert_eq!(state.recent_pages.len(), RECENT_PAGES);
+        assert_eq!(state.recent_pages[..3], ["/p5", "/p11", "/p10"]);
+    }
+}
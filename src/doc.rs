@@ -1,29 +1,54 @@
 use std::path::PathBuf;
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
 
-use actix_web::{App, HttpResponse, HttpServer, get, http::header::ContentType, web};
+use actix_web::{App, HttpResponse, get, http::header::ContentType, web};
 use include_dir::{Dir, include_dir};
 use owo_colors::OwoColorize;
+use serde::Deserialize;
 use tokio::fs;
 
 use crate::console;
-use crate::error::{HugsError, Result, StyledPath, StyledNum};
+use crate::dates::DateParser;
+use crate::deps::DepRecorder;
+use crate::doc_search::SearchIndex;
+use crate::error::{render_error_html, HugsError, Result, StyledPath};
 use crate::minify::{minify_css_content, minify_html_content, MinifyConfig};
-use crate::run::{
-    render_notfound_page, render_page_html, resolve_path_to_doc,
-    try_serve_static_file, AppData,
-};
+use crate::run::{escape_html, render_requested_page, route_request, AppData, PageResponse, Route};
+use crate::server::bind_with_retry;
 use crate::sitemap::generate_sitemap;
 
 /// The tutorial site directory embedded at compile time
 static DOCS_DIR: Dir<'_> = include_dir!("$CARGO_MANIFEST_DIR/tutorial-site");
 
-/// Maximum number of port retry attempts before giving up
-const MAX_PORT_RETRIES: u16 = 50;
+/// Where `--check-update` asks for the newest release
+const LATEST_RELEASE_URL: &str = "https://api.github.com/repos/AndrewBastin/hugs/releases/latest";
 
 pub struct DocAppState {
     pub app_data: AppData,
     pub minify_config: MinifyConfig,
+    pub search_index: SearchIndex,
+    /// The newest release's tag, once `--check-update` has found one newer than this build
+    pub newer_release: OnceLock<String>,
+}
+
+impl DocAppState {
+    pub fn new(app_data: AppData, minify_config: MinifyConfig) -> Self {
+        let search_index = SearchIndex::new(&app_data);
+        Self { app_data, minify_config, search_index, newer_release: OnceLock::new() }
+    }
+}
+
+#[derive(Deserialize)]
+struct SearchQuery {
+    #[serde(default)]
+    q: String,
+}
+
+/// Pages matching `?q=`, as JSON, for the tutorial's search page
+#[get("/__docs/search")]
+async fn search(query: web::Query<SearchQuery>, state: web::Data<Arc<DocAppState>>) -> HttpResponse {
+    HttpResponse::Ok().json(state.search_index.search(&query.q))
 }
 
 #[get("/theme.css")]
@@ -44,7 +69,7 @@ async fn theme_hashed(state: web::Data<Arc<DocAppState>>) -> HttpResponse {
 
 #[get("/sitemap.xml")]
 async fn sitemap(state: web::Data<Arc<DocAppState>>) -> HttpResponse {
-    match generate_sitemap(&state.app_data.pages, &state.app_data.config.site) {
+    match generate_sitemap(&state.app_data.pages, &state.app_data.config.site, &DateParser::new(&state.app_data.config), state.app_data.sitemap_template.as_deref(), None) {
         Ok(xml) => HttpResponse::Ok()
             .content_type(ContentType::xml())
             .body(xml),
@@ -55,48 +80,78 @@ async fn sitemap(state: web::Data<Arc<DocAppState>>) -> HttpResponse {
 
 #[get("/{tail:.*}")]
 async fn page(path: web::Path<String>, state: web::Data<Arc<DocAppState>>) -> HttpResponse {
-    let path_str = path.trim_end_matches('/');
+    let app_data = &state.app_data;
+    let path_str = match route_request(path.trim_end_matches('/'), app_data).await {
+        Route::Page(path_str) => path_str,
+        Route::File(response) => return response,
+    };
 
-    if let Some(response) = try_serve_static_file(path_str, &state.app_data).await {
-        return response;
+    // No live reload script for doc server
+    let banner = doc_banner(state.newer_release.get().map(String::as_str));
+    match render_requested_page(path_str, app_data, "", &DepRecorder::default()).await {
+        Ok(PageResponse::Page(html_out)) => HttpResponse::Ok()
+            .content_type(ContentType::html())
+            .body(minify_html_content(&with_banner(&html_out, &banner), &state.minify_config)),
+        Ok(PageResponse::Redirect(url)) => HttpResponse::Found()
+            .insert_header((actix_web::http::header::LOCATION, url))
+            .finish(),
+        Ok(PageResponse::NotFound(Some(html))) => HttpResponse::NotFound()
+            .content_type(ContentType::html())
+            .body(minify_html_content(&with_banner(&html, &banner), &state.minify_config)),
+        Ok(PageResponse::NotFound(None)) => HttpResponse::NotFound()
+            .body("Not Found"),
+        Err(e) => HttpResponse::InternalServerError()
+            .content_type(ContentType::html())
+            .body(render_error_html(&e, "")),
     }
+}
 
-    match resolve_path_to_doc(path_str, &state.app_data).await {
-        Ok(Some((frontmatter, doc_html, resolvable_path, frontmatter_json))) => {
-            match render_page_html(
-                &frontmatter,
-                &frontmatter_json,
-                &doc_html,
-                &resolvable_path,
-                &state.app_data,
-                "", // No live reload script for doc server
-            ) {
-                Ok(html_out) => {
-                    let final_html = minify_html_content(&html_out, &state.minify_config);
-                    HttpResponse::Ok()
-                        .content_type(ContentType::html())
-                        .body(final_html)
-                }
-                Err(_) => HttpResponse::InternalServerError()
-                    .body("Render error"),
-            }
-        }
-        Ok(None) => {
-            if let Some(html) = render_notfound_page(&state.app_data, "").await {
-                let final_html = minify_html_content(&html, &state.minify_config);
-                HttpResponse::NotFound()
-                    .content_type(ContentType::html())
-                    .body(final_html)
-            } else {
-                HttpResponse::NotFound()
-                    .body("Not Found")
-            }
-        }
-        Err(_) => HttpResponse::InternalServerError()
-            .body("Error processing page"),
+/// The bar at the top of every doc page: which Hugs the docs are for, a search box, and a newer
+/// release when `--check-update` found one
+fn doc_banner(newer_release: Option<&str>) -> String {
+    let newer = newer_release
+        .map(|tag| format!(" · <strong>{} is out</strong>", escape_html(tag)))
+        .unwrap_or_default();
+    format!(
+        "<div class=\"hugs-doc-banner\" style=\"display:flex;gap:1em;align-items:center;justify-content:center;\
+        padding:.4em 1em;font-size:.85em;border-bottom:1px solid rgba(127,127,127,.3)\">\
+        <span>Docs for Hugs v{}{}</span>\
+        <form action=\"/search\" role=\"search\" style=\"margin:0\">\
+        <input type=\"search\" name=\"q\" placeholder=\"Search the docs\" aria-label=\"Search the docs\"></form></div>",
+        env!("CARGO_PKG_VERSION"),
+        newer
+    )
+}
+
+/// `html` with `banner` at the start of its `<body>`
+fn with_banner(html: &str, banner: &str) -> String {
+    let body_start = html.find("<body").and_then(|start| html[start..].find('>').map(|end| start + end + 1));
+    match body_start {
+        Some(at) => format!("{}{}{}", &html[..at], banner, &html[at..]),
+        None => html.to_string(),
     }
 }
 
+/// Whether version `candidate` (like `v0.3.0`) is newer than `current` (like `0.2.0`)
+fn is_newer(candidate: &str, current: &str) -> bool {
+    let parts = |version: &str| -> Option<Vec<u64>> {
+        let version = version.trim().trim_start_matches('v');
+        let version = version.split(['-', '+']).next()?;
+        version.split('.').map(|part| part.parse().ok()).collect()
+    };
+    matches!((parts(candidate), parts(current)), (Some(candidate), Some(current)) if candidate > current)
+}
+
+/// The newest release's tag, or nothing when it can't be found out: no network, or an answer I
+/// don't understand. Never fails the doc server.
+fn latest_release() -> Option<String> {
+    let agent: ureq::Agent = ureq::Agent::config_builder().timeout_global(Some(Duration::from_secs(5))).build().into();
+    let mut response = agent.get(LATEST_RELEASE_URL).header("Accept", "application/vnd.github+json").call().ok()?;
+    let body = response.body_mut().read_to_string().ok()?;
+    let release: serde_json::Value = serde_json::from_str(&body).ok()?;
+    release.get("tag_name")?.as_str().map(str::to_string)
+}
+
 /// Dump embedded docs to a folder (temp dir if path is None)
 pub async fn dump_docs(path: Option<PathBuf>) -> Result<()> {
     let target_path = match path {
@@ -125,7 +180,7 @@ pub async fn dump_docs(path: Option<PathBuf>) -> Result<()> {
     Ok(())
 }
 
-pub async fn run_doc_server(port: Option<u16>, no_open: bool) -> Result<()> {
+pub async fn run_doc_server(port: Option<u16>, no_open: bool, check_update: bool) -> Result<()> {
     console::status("Starting", "documentation server");
 
     let temp_dir = extract_docs_to_temp().await?;
@@ -136,17 +191,40 @@ pub async fn run_doc_server(port: Option<u16>, no_open: bool) -> Result<()> {
     // Load site data
     let docs_path_display = docs_path.clone();
     let app_data = AppData::load(docs_path, "doc").await?;
-    let minify_config = MinifyConfig::new(app_data.config.build.minify);
+    let minify_config = MinifyConfig::from_build(&app_data.config.build);
 
-    let state = Arc::new(DocAppState {
-        app_data,
-        minify_config,
-    });
+    let state = Arc::new(DocAppState::new(app_data, minify_config));
+
+    // Only with --check-update, and in the background, so the docs never wait on the network
+    if check_update {
+        let state = Arc::clone(&state);
+        tokio::task::spawn_blocking(move || {
+            if let Some(tag) = latest_release().filter(|tag| is_newer(tag, env!("CARGO_PKG_VERSION"))) {
+                console::status("Update", format!("Hugs {} is out, you have v{}", tag, env!("CARGO_PKG_VERSION")));
+                let _ = state.newer_release.set(tag);
+            }
+        });
+    }
 
     // Find available port
     let default_port = port.unwrap_or(8888);
     let port_explicit = port.is_some();
-    let (server, actual_port) = try_bind_server(Arc::clone(&state), default_port, port_explicit)?;
+    let server_state = Arc::clone(&state);
+    let (server, actual_port) = bind_with_retry(
+        move || {
+            App::new()
+                .app_data(web::Data::new(Arc::clone(&server_state)))
+                .service(theme)
+                .service(theme_hashed)
+                .service(sitemap)
+                .service(search)
+                .service(page)
+        },
+        "127.0.0.1",
+        default_port,
+        port_explicit,
+        "hugs doc",
+    )?;
 
     let url = format!("http://127.0.0.1:{}", actual_port);
 
@@ -224,73 +302,56 @@ async fn extract_dir(dir: &Dir<'_>, target: &PathBuf) -> Result<()> {
     Ok(())
 }
 
-/// Attempt to bind to a port, retrying with incrementing ports if port was not explicitly specified
-fn try_bind_server(
-    state: Arc<DocAppState>,
-    port: u16,
-    port_explicit: bool,
-) -> Result<(actix_web::dev::Server, u16)> {
-    if port_explicit {
-        let state_for_server = Arc::clone(&state);
-        let server = HttpServer::new(move || {
-            App::new()
-                .app_data(web::Data::new(Arc::clone(&state_for_server)))
-                .service(theme)
-                .service(theme_hashed)
-                .service(sitemap)
-                .service(page)
-        })
-        .bind(("127.0.0.1", port))
-        .map_err(|e| HugsError::PortBind {
-            port: StyledNum(port),
-            src: miette::NamedSource::new(
-                "command".to_string(),
-                format!("hugs doc --port {}", port),
-            ),
-            span: miette::SourceSpan::new(
-                (17).into(), // position of port number
-                port.to_string().len().into(),
-            ),
-            help_text: format!(
-                "Port {} is already in use. Try a different port with: {}",
-                port.bold(),
-                format!("hugs doc --port {}", port.saturating_add(1)).cyan()
-            ),
-            cause: e,
-        })?;
-
-        Ok((server.run(), port))
-    } else {
-        for attempt in 0..MAX_PORT_RETRIES {
-            let try_port = match port.checked_add(attempt) {
-                Some(p) => p,
-                None => break,
-            };
-
-            let state_for_server = Arc::clone(&state);
-            match HttpServer::new(move || {
-                App::new()
-                    .app_data(web::Data::new(Arc::clone(&state_for_server)))
-                    .service(theme)
-                    .service(theme_hashed)
-                    .service(sitemap)
-                    .service(page)
-            })
-            .bind(("127.0.0.1", try_port))
-            {
-                Ok(server) => {
-                    return Ok((server.run(), try_port));
-                }
-                Err(_) => {
-                    continue;
-                }
-            }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test;
+
+    #[actix_web::test]
+    async fn test_doc_server_renders_every_tutorial_page() {
+        let docs = extract_docs_to_temp().await.unwrap();
+        let app_data = AppData::load(docs.path().to_path_buf(), "doc").await.unwrap();
+        let minify_config = MinifyConfig::from_build(&app_data.config.build);
+        let urls: Vec<String> = app_data.pages.iter().map(|info| info.url.to_string()).collect();
+        assert!(
+            app_data.pages.iter().any(|info| crate::run::DynamicContext::from_page_info(info).is_some()),
+            "the tutorial should have dynamic pages"
+        );
+
+        let state = Arc::new(DocAppState::new(app_data, minify_config));
+        let app = test::init_service(App::new().app_data(web::Data::new(state)).service(search).service(page)).await;
+
+        for url in &urls {
+            let uri = crate::url::encode_url_path(url);
+            let res = test::call_service(&app, test::TestRequest::get().uri(&uri).to_request()).await;
+            assert_eq!(res.status(), 200, "{} should render under hugs doc", url);
         }
 
-        let end_port = port.saturating_add(MAX_PORT_RETRIES - 1);
-        Err(HugsError::NoAvailablePort {
-            start_port: port.into(),
-            end_port: end_port.into(),
-        })
+        let res = test::call_service(&app, test::TestRequest::get().uri("/no-such-page").to_request()).await;
+        assert_eq!(res.status(), 404);
+
+        let res = test::call_service(&app, test::TestRequest::get().uri("/about").to_request()).await;
+        let html = String::from_utf8(test::read_body(res).await.to_vec()).unwrap();
+        assert!(html.contains(&format!("Docs for Hugs v{}", env!("CARGO_PKG_VERSION"))), "pages should have the banner");
+
+        let req = test::TestRequest::get().uri("/__docs/search?q=cache_bust").to_request();
+        let hits: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        let hits = hits.as_array().unwrap();
+        assert!(!hits.is_empty(), "the tutorial explains cache_bust()");
+        assert!(hits.iter().all(|hit| hit["url"].is_string() && hit["snippet"].is_string()));
+    }
+
+    #[actix_web::test]
+    async fn test_banner_and_versions() {
+        let html = with_banner("<html><body hg-path=\"about\"><p>Hi</p></body></html>", "<div>banner</div>");
+        assert_eq!(html, "<html><body hg-path=\"about\"><div>banner</div><p>Hi</p></body></html>");
+        assert!(doc_banner(Some("v9.0.0")).contains("v9.0.0 is out"));
+
+        assert!(is_newer("v0.3.0", "0.2.0"));
+        assert!(is_newer("1.0.0", "0.10.2"));
+        assert!(is_newer("v0.2.10", "0.2.9"));
+        assert!(!is_newer("v0.2.0", "0.2.0"));
+        assert!(!is_newer("v0.1.9-beta", "0.2.0"));
+        assert!(!is_newer("nightly", "0.2.0"));
     }
 }
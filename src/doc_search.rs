@@ -0,0 +1,180 @@
+//! Search for `hugs doc`: the embedded tutorial is small, so every page's text is kept in memory
+//! and a query just looks for its words in each one, ranking title matches above body matches.
+
+use std::sync::LazyLock;
+
+use regex::Regex;
+use serde::Serialize;
+
+use crate::run::{AppData, DynamicContext, markdown_plain_text};
+
+/// Characters of page text shown around the first match
+const SNIPPET_CHARS: usize = 160;
+
+/// Most results one search returns
+pub const MAX_RESULTS: usize = 20;
+
+/// Template tags like `{% raw %}` and `{# comments #}`, which aren't part of what a page says
+static TEMPLATE_TAG: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?s)\{%.*?%\}|\{#.*?#\}").unwrap());
+
+struct SearchDoc {
+    url: String,
+    title: String,
+    /// Whitespace collapsed, for snippets
+    text: String,
+    title_lower: String,
+    description_lower: String,
+    text_lower: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SearchHit {
+    pub url: String,
+    pub title: String,
+    pub snippet: String,
+    pub score: u32,
+}
+
+pub struct SearchIndex {
+    docs: Vec<SearchDoc>,
+}
+
+/// Lowercased words, spelled the way page text is indexed, so `cache_bust` finds `cache_bust()`
+fn normalize(text: &str) -> String {
+    markdown_plain_text(&TEMPLATE_TAG.replace_all(text, " ")).split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+impl SearchIndex {
+    /// Index the site's pages from their markdown. Dynamic pages are left out, since they're
+    /// generated listings of the other pages.
+    pub fn new(app_data: &AppData) -> Self {
+        let docs = app_data
+            .pages
+            .iter()
+            .filter(|page| DynamicContext::from_page_info(page).is_none())
+            .filter_map(|page| {
+                let source = app_data.page_sources.get(&page.file_path)?;
+                let field = |key: &str| page.frontmatter.get(key).and_then(|v| v.as_str()).unwrap_or("").to_string();
+                let title = field("title");
+                let text = normalize(source.body());
+                Some(SearchDoc {
+                    url: page.url.to_string(),
+                    title_lower: normalize(&title).to_lowercase(),
+                    description_lower: normalize(&field("description")).to_lowercase(),
+                    text_lower: text.to_lowercase(),
+                    title,
+                    text,
+                })
+            })
+            .collect();
+
+        Self { docs }
+    }
+
+    /// Pages with every word of `query` somewhere in their title, description or text, best first
+    pub fn search(&self, query: &str) -> Vec<SearchHit> {
+        let phrase = normalize(query).to_lowercase();
+        let terms: Vec<&str> = phrase.split_whitespace().collect();
+        if terms.is_empty() {
+            return Vec::new();
+        }
+
+        let mut hits: Vec<SearchHit> = self
+            .docs
+            .iter()
+            .filter_map(|doc| {
+                let mut score = 0;
+                for term in &terms {
+                    let in_title = doc.title_lower.contains(term);
+                    let in_description = doc.description_lower.contains(term);
+                    let in_text = doc.text_lower.matches(term).count().min(5) as u32;
+                    if !in_title && !in_description && in_text == 0 {
+                        return None;
+                    }
+                    score += 10 * in_title as u32 + 3 * in_description as u32 + in_text;
+                }
+                if terms.len() > 1 {
+                    score += 10 * doc.title_lower.contains(&phrase) as u32 + 5 * doc.text_lower.contains(&phrase) as u32;
+                }
+
+                Some(SearchHit { url: doc.url.clone(), title: doc.title.clone(), snippet: snippet(doc, terms[0]), score })
+            })
+            .collect();
+
+        hits.sort_by(|a, b| b.score.cmp(&a.score).then_with(|| a.title.cmp(&b.title)));
+        hits.truncate(MAX_RESULTS);
+        hits
+    }
+}
+
+/// Some of the page's text around the first `term`, or its beginning when only the title matched
+fn snippet(doc: &SearchDoc, term: &str) -> String {
+    // Lowercasing can change byte lengths, so find the match by characters
+    let chars: Vec<char> = doc.text.chars().collect();
+    let start = doc
+        .text_lower
+        .find(term)
+        .map(|byte| doc.text_lower[..byte].chars().count())
+        .unwrap_or(0)
+        .min(chars.len());
+    let from = start.saturating_sub(SNIPPET_CHARS / 3);
+    let to = (from + SNIPPET_CHARS).min(chars.len());
+
+    let mut snippet: String = chars[from..to].iter().collect();
+    if from > 0 {
+        snippet.insert(0, '…');
+    }
+    if to < chars.len() {
+        snippet.push('…');
+    }
+    snippet
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn doc(url: &str, title: &str, description: &str, body: &str) -> SearchDoc {
+        let text = normalize(body);
+        SearchDoc {
+            url: url.to_string(),
+            title: title.to_string(),
+            title_lower: normalize(title).to_lowercase(),
+            description_lower: normalize(description).to_lowercase(),
+            text_lower: text.to_lowercase(),
+            text,
+        }
+    }
+
+    #[test]
+    fn test_titles_rank_first_and_every_word_must_match() {
+        let index = SearchIndex {
+            docs: vec![
+                doc("/blog/assets", "Assets", "Images and files", "Call {% raw %}`cache_bust()`{% endraw %} on **theme.css** to bust caches."),
+                doc("/blog/cache-busting", "Cache busting", "", "Browsers keep old files around."),
+                doc("/blog/feeds", "Feeds", "", "RSS and Atom, nothing about caching here."),
+            ],
+        };
+
+        let urls = |query: &str| index.search(query).into_iter().map(|hit| hit.url).collect::<Vec<_>>();
+        assert_eq!(urls("cache"), ["/blog/cache-busting", "/blog/assets"]);
+        assert_eq!(urls("rss"), ["/blog/feeds"]);
+        assert_eq!(urls("cache_bust"), ["/blog/assets"]);
+        assert_eq!(urls("theme.css"), ["/blog/assets"]);
+        assert_eq!(urls("files caches"), ["/blog/assets"]);
+        assert!(urls("   ").is_empty());
+        assert!(urls("nowhere").is_empty());
+
+        let hit = &index.search("theme")[0];
+        assert_eq!(hit.snippet, "Call cache_bust on theme.css to bust caches.");
+    }
+
+    #[test]
+    fn test_snippets_are_cut_around_the_match() {
+        let body = format!("{} needle {}", "é ".repeat(200), "x ".repeat(200));
+        let hit = &SearchIndex { docs: vec![doc("/long", "Long", "", &body)] }.search("needle")[0];
+        assert!(hit.snippet.starts_with('…') && hit.snippet.ends_with('…'), "{}", hit.snippet);
+        assert!(hit.snippet.contains("needle"));
+        assert_eq!(hit.snippet.chars().count(), SNIPPET_CHARS + 2);
+    }
+}
@@ -0,0 +1,215 @@
+//! Near-duplicate pages, for `[build] duplicate_detection`.
+//!
+//! Each page's content, as plain text, is cut into overlapping runs of five words, and a MinHash
+//! signature of those runs estimates how much any two pages share. Rather than comparing every
+//! pair of pages, the signatures are split into bands and only pages that land in the same bucket
+//! for some band are compared, which keeps this linear in the number of pages.
+//!
+//! Pages that say they're related aren't reported: two with the same `series`, or two instances
+//! of the same dynamic page.
+
+use std::collections::{BTreeSet, HashMap};
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+use serde_yaml::Value as YamlValue;
+
+use crate::error::HugsError;
+use crate::run::PageInfo;
+
+/// Words in each shingle
+const SHINGLE_WORDS: usize = 5;
+
+/// Signature length, which sets how precise the similarity estimate is
+const SIGNATURE_LEN: usize = 128;
+
+/// Signature values hashed together for one LSH bucket. With 32 bands of 4, pages that are 60%
+/// alike share a bucket 98% of the time, and pages 20% alike rarely do.
+const BAND_ROWS: usize = 4;
+
+/// What duplicate detection knows about one page
+pub struct PageFingerprint {
+    url: String,
+    file_path: String,
+    series: Option<YamlValue>,
+    signature: [u64; SIGNATURE_LEN],
+}
+
+impl PageFingerprint {
+    /// Fingerprint a page from its rendered content. Pages without any words get none.
+    pub fn new(page: &PageInfo, content_html: &str) -> Option<Self> {
+        let words = plain_words(content_html);
+        if words.is_empty() {
+            return None;
+        }
+
+        let mut signature = [u64::MAX; SIGNATURE_LEN];
+        for shingle in words.windows(SHINGLE_WORDS.min(words.len())) {
+            let mut hasher = DefaultHasher::new();
+            shingle.hash(&mut hasher);
+            let shingle_hash = hasher.finish();
+
+            for (seed, min) in signature.iter_mut().enumerate() {
+                *min = (*min).min(mix(shingle_hash ^ (seed as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)));
+            }
+        }
+
+        Some(Self {
+            url: page.url.to_string(),
+            file_path: page.file_path.clone(),
+            series: page.frontmatter.get("series").filter(|series| !series.is_null()).cloned(),
+            signature,
+        })
+    }
+
+    /// The estimated share of shingles the two pages have in common
+    fn similarity(&self, other: &Self) -> f64 {
+        let same = self.signature.iter().zip(&other.signature).filter(|(a, b)| a == b).count();
+        same as f64 / SIGNATURE_LEN as f64
+    }
+
+    /// Whether the pages already say how they relate to each other
+    fn related(&self, other: &Self) -> bool {
+        self.file_path == other.file_path || self.series.is_some() && self.series == other.series
+    }
+}
+
+/// A warning for each pair of pages at least `threshold` alike, most alike first
+pub fn find_duplicates(pages: &[PageFingerprint], threshold: f64) -> Vec<HugsError> {
+    let mut candidates = BTreeSet::new();
+    for band in 0..SIGNATURE_LEN / BAND_ROWS {
+        let mut buckets: HashMap<&[u64], Vec<usize>> = HashMap::new();
+        for (index, page) in pages.iter().enumerate() {
+            let rows = &page.signature[band * BAND_ROWS..(band + 1) * BAND_ROWS];
+            buckets.entry(rows).or_default().push(index);
+        }
+
+        for bucket in buckets.values() {
+            for (i, &a) in bucket.iter().enumerate() {
+                for &b in &bucket[i + 1..] {
+                    candidates.insert((a, b));
+                }
+            }
+        }
+    }
+
+    let mut duplicates: Vec<(f64, &PageFingerprint, &PageFingerprint)> = candidates
+        .into_iter()
+        .map(|(a, b)| (&pages[a], &pages[b]))
+        .filter(|(a, b)| !a.related(b))
+        .map(|(a, b)| {
+            let (a, b) = if a.url <= b.url { (a, b) } else { (b, a) };
+            (a.similarity(b), a, b)
+        })
+        .filter(|(similarity, _, _)| *similarity >= threshold)
+        .collect();
+    duplicates.sort_by(|x, y| y.0.total_cmp(&x.0).then_with(|| x.1.url.cmp(&y.1.url)).then_with(|| x.2.url.cmp(&y.2.url)));
+
+    duplicates
+        .into_iter()
+        .map(|(similarity, page, other)| HugsError::DuplicateContent {
+            page: page.url.clone().into(),
+            other: other.url.clone().into(),
+            similarity: format!("{:.0}%", similarity * 100.0),
+        })
+        .collect()
+}
+
+/// The lowercased words of some HTML, leaving out the tags
+fn plain_words(html: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_tag = false;
+
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' => in_tag = false,
+            c if !in_tag && c.is_alphanumeric() => word.extend(c.to_lowercase()),
+            _ => {}
+        }
+        if !c.is_alphanumeric() && !word.is_empty() {
+            words.push(std::mem::take(&mut word));
+        }
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+/// Spread a hash's bits out, so each seed gives an independent-looking permutation (SplitMix64)
+fn mix(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    x ^ (x >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::url::SiteUrl;
+
+    const POST: &str = "<p>Rust makes it easy to write fast and reliable software. The borrow checker catches \
+        whole classes of bugs before your program ever runs, and cargo builds, tests and publishes your \
+        crates with one tool. Once you get used to ownership, lifetimes stop being scary and start being \
+        a way to say what you mean.</p>";
+
+    fn fingerprint(url: &str, frontmatter: &str, html: &str) -> PageFingerprint {
+        let page = PageInfo {
+            url: SiteUrl::parse(url),
+            file_path: format!("{}.md", url.trim_matches('/')),
+            frontmatter: serde_yaml::from_str(frontmatter).unwrap(),
+        };
+        PageFingerprint::new(&page, html).unwrap()
+    }
+
+    #[test]
+    fn test_copied_post_is_reported_once() {
+        let tweaked = POST.replace("reliable", "dependable");
+        let pages = [
+            fingerprint("/blog/rust", "title: Rust", POST),
+            fingerprint("/about", "title: About", "<p>I write about programming, gardening and whatever else I'm into this week.</p>"),
+            fingerprint("/blog/rust-copy", "title: Rust again", &tweaked),
+        ];
+
+        let warnings = find_duplicates(&pages, 0.7);
+        assert_eq!(warnings.len(), 1);
+        let HugsError::DuplicateContent { page, other, similarity } = &warnings[0] else {
+            panic!("expected a DuplicateContent warning");
+        };
+        assert_eq!((page.0.as_str(), other.0.as_str()), ("/blog/rust", "/blog/rust-copy"));
+        assert!(similarity.ends_with('%') && similarity != "100%", "{similarity}");
+
+        assert!(find_duplicates(&pages, 1.0).is_empty());
+    }
+
+    #[test]
+    fn test_related_pages_are_not_duplicates() {
+        // Every page's canonical link is its own URL, so a `canonical` key doesn't relate two pages
+        let pages = [
+            fingerprint("/blog/rust", "title: Rust", POST),
+            fingerprint("/mirror/rust", "title: Rust\ncanonical: https://example.com/blog/rust/", POST),
+        ];
+        assert_eq!(find_duplicates(&pages, 0.9).len(), 1);
+
+        let pages = [
+            fingerprint("/guide/part-1", "title: One\nseries: Guide", POST),
+            fingerprint("/guide/part-2", "title: Two\nseries: Guide", POST),
+        ];
+        assert!(find_duplicates(&pages, 0.9).is_empty());
+
+        let pages = [
+            fingerprint("/guide/part-1", "title: One\nseries: Guide", POST),
+            fingerprint("/other/part-1", "title: One\nseries: Other", POST),
+        ];
+        assert_eq!(find_duplicates(&pages, 0.9).len(), 1);
+    }
+
+    #[test]
+    fn test_plain_words_skip_markup() {
+        assert_eq!(
+            plain_words(r#"<h1 class="big">Hello,</h1><p>wörld of <a href="/x">Friends</a></p>"#),
+            ["hello", "wörld", "of", "friends"]
+        );
+    }
+}
@@ -4,6 +4,9 @@ use std::path::Path;
 use miette::{Diagnostic, NamedSource, SourceSpan};
 use thiserror::Error;
 
+use crate::run::TemplateError;
+use crate::template_help::{HelpBody, HelpRequest, HelpSection, HelpVariable, TemplateHelp};
+
 // ANSI color codes for styled error output
 const BOLD_CYAN: &str = "\x1b[1;36m";
 const YELLOW: &str = "\x1b[33m";
@@ -116,6 +119,17 @@ pub enum HugsError {
         cause: std::io::Error,
     },
 
+    #[error("I can't use {folder} as the `paths.{key}` folder, because {reason}")]
+    #[diagnostic(
+        code(hugs::config::paths),
+        help("`paths.content` and `paths.static` name folders inside the site, like `content` and `static`.\nLeave them out to keep pages and static files at the site root.")
+    )]
+    PathsFolder {
+        key: &'static str,
+        folder: StyledPath,
+        reason: &'static str,
+    },
+
     // === Frontmatter Errors ===
     #[error("I couldn't parse the frontmatter in {file}")]
     #[diagnostic(
@@ -144,7 +158,7 @@ pub enum HugsError {
         #[label("{reason}")]
         span: SourceSpan,
         reason: String,
-        help_text: String,
+        help_text: TemplateHelp,
     },
 
     #[error("I couldn't create the template context")]
@@ -204,6 +218,17 @@ pub enum HugsError {
         suggestion: String,
     },
 
+    #[error("I expected your site's {styled_type} to be a file, but {path} is a directory", styled_type = StyledName::from(*file_type))]
+    #[diagnostic(
+        code(hugs::file::required_is_dir),
+        help("{suggestion}")
+    )]
+    RequiredFileIsDirectory {
+        file_type: &'static str,
+        path: StyledPath,
+        suggestion: String,
+    },
+
     // === Feed Errors ===
     #[error("I need a title to generate the {feed_name} feed")]
     #[diagnostic(
@@ -219,6 +244,81 @@ pub enum HugsError {
     )]
     FeedMissingUrl { feed_name: StyledName },
 
+    #[error("I don't know which pages go in the {feed_name} feed")]
+    #[diagnostic(
+        code(hugs::feed::source),
+        help("Give the feed either a `source`, the URL its pages are under, or a `collection` from [collections] in config.toml, but not both.")
+    )]
+    FeedSource { feed_name: StyledName },
+
+    #[error("The {feed_name} feed takes its pages from the {collection} collection, but there's no such collection")]
+    #[diagnostic(code(hugs::feed::unknown_collection))]
+    FeedUnknownCollection {
+        feed_name: StyledName,
+        collection: StyledName,
+        #[help]
+        help_text: String,
+    },
+
+    #[error("The {feed_name} feed can't be written to {filename}")]
+    #[diagnostic(
+        code(hugs::feed::output_name),
+        help("Feeds are written at the top of the output folder, so `output_rss` and `output_atom` take a plain file name like \"rss.xml\", without folders or `..`.")
+    )]
+    FeedOutputName {
+        feed_name: StyledName,
+        filename: StyledPath,
+        #[source_code]
+        src: NamedSource<String>,
+        #[label("{reason}")]
+        span: SourceSpan,
+        reason: String,
+    },
+
+    #[error("I couldn't find the item template for the {feed_name} feed")]
+    #[diagnostic(
+        code(hugs::feed::item_template_missing),
+        help("`item_template` in config.toml points at {path}, but there's no file there. Paths are relative to your site's `_` folder.")
+    )]
+    FeedItemTemplateMissing { feed_name: StyledName, path: StyledPath },
+
+    #[error("The {feed_name} feed's item template can't be at {path}")]
+    #[diagnostic(
+        code(hugs::feed::item_template_path),
+        help("{reason}. `item_template` is a path inside your site's `_` folder, like \"feed-item.md\", without `..` or a leading `/`.")
+    )]
+    FeedItemTemplatePath { feed_name: StyledName, path: StyledPath, reason: String },
+
+    #[error("I couldn't render {file}, the item template for the {feed_name} feed")]
+    #[diagnostic(
+        code(hugs::feed::item_template),
+        help("{help_text}")
+    )]
+    FeedItemTemplate {
+        feed_name: StyledName,
+        file: StyledPath,
+        #[source_code]
+        src: NamedSource<String>,
+        #[label("{reason}")]
+        span: SourceSpan,
+        reason: String,
+        help_text: TemplateHelp,
+    },
+
+    #[error("The {feed_name} feed's item template failed for {page_url}, so I used its plain summary")]
+    #[diagnostic(code(hugs::feed::item_template_failed), severity(warning))]
+    FeedItemTemplateFailed {
+        feed_name: StyledName,
+        page_url: StyledPath,
+        #[source_code]
+        src: NamedSource<String>,
+        #[label("{reason}")]
+        span: SourceSpan,
+        reason: String,
+        #[help]
+        help_text: TemplateHelp,
+    },
+
     // === Sitemap Errors ===
     #[error("I need a base URL to generate the {name}", name = StyledName::from("sitemap"))]
     #[diagnostic(
@@ -231,6 +331,24 @@ pub enum HugsError {
     #[diagnostic(code(hugs::sitemap::template))]
     SitemapTemplate { reason: String },
 
+    #[error("I didn't write {output}, because it isn't well-formed XML: {reason}")]
+    #[diagnostic(code(hugs::build::malformed_xml), severity(warning))]
+    MalformedXml {
+        output: StyledName,
+        reason: String,
+        #[help]
+        help_text: String,
+    },
+
+    #[error("{output} has relative URLs, which break when it's served for a missing page deep in the site: {urls}")]
+    #[diagnostic(code(hugs::build::notfound_relative_urls), severity(warning))]
+    NotFoundRelativeUrls {
+        output: StyledName,
+        urls: String,
+        #[help]
+        help_text: String,
+    },
+
     // === Server Errors ===
     #[error("I couldn't start the server on port {port}")]
     #[diagnostic(code(hugs::server::port_bind))]
@@ -247,13 +365,28 @@ pub enum HugsError {
     },
 
     #[error("I couldn't find an available port after trying ports {start_port} through {end_port}")]
-    #[diagnostic(
-        code(hugs::server::no_available_port),
-        help("All ports in the range are in use. Try specifying a different starting port:\n\n    hugs dev <path> --port 9000")
-    )]
+    #[diagnostic(code(hugs::server::no_available_port))]
     NoAvailablePort {
         start_port: StyledNum<u16>,
         end_port: StyledNum<u16>,
+        #[help]
+        help_text: String,
+    },
+
+    #[error("I answered with status {status} because the URL asked me to")]
+    #[diagnostic(
+        code(hugs::dev::forced_status),
+        help("The request has ?__hugs_status set, which dev.debug_tools lets through. Remove it from the URL to see the page.")
+    )]
+    DevForcedStatus { status: StyledNum<u16> },
+
+    #[error("{url} took more than {secs} seconds to render, so I stopped waiting for it")]
+    #[diagnostic(code(hugs::dev::render_timeout))]
+    DevRenderTimeout {
+        url: StyledName,
+        secs: StyledNum<u64>,
+        #[help]
+        help_text: String,
     },
 
     #[error("I couldn't start the file watcher")]
@@ -289,6 +422,27 @@ pub enum HugsError {
     )]
     PathInvalidUtf8 { path: StyledPath },
 
+    #[error("{page} and {other} both end up at the URL {url}")]
+    #[diagnostic(
+        code(hugs::path::url_collision),
+        help("With `url_transliteration = \"ascii\"`, I spell every URL in plain ASCII, and these two came out the same. Rename one of them so their URLs differ.")
+    )]
+    UrlCollision { url: StyledPath, page: String, other: String },
+
+    #[error("{file} lists the alias {alias}, which I can't write a redirect for")]
+    #[diagnostic(
+        code(hugs::path::invalid_alias),
+        help("{reason} An alias is an old URL on this site, like `/old-post` or `/2019/hello`.")
+    )]
+    InvalidAlias { file: StyledPath, alias: String, reason: String },
+
+    #[error("{file} sets first_is_index, but {other} is already the page at {url}")]
+    #[diagnostic(
+        code(hugs::path::dynamic_index_conflict),
+        help("With `first_is_index: true`, the first value's page is the folder's index. Move what the other page says into this one and delete it, or drop `first_is_index` so the first value keeps its own URL.")
+    )]
+    DynamicIndexConflict { file: StyledPath, other: StyledPath, url: StyledPath },
+
     // === Markdown Errors ===
     #[error("I couldn't parse the markdown in {file}")]
     #[diagnostic(
@@ -335,6 +489,25 @@ pub enum HugsError {
         span: SourceSpan,
         /// The value the expression resolved to (if it evaluated but wasn't an array)
         resolved_value: Option<String>,
+        help_text: TemplateHelp,
+    },
+
+    #[error("I can't use {value:?} from `{param_name}` in {file} as part of a URL")]
+    #[diagnostic(
+        code(hugs::dynamic::invalid_value),
+        help("{help_text}")
+    )]
+    DynamicParamInvalid {
+        file: StyledPath,
+        param_name: StyledName,
+        value: String,
+        /// The expression or list the value came from
+        expression: String,
+        reason: String,
+        #[source_code]
+        src: Option<NamedSource<String>>,
+        #[label("{reason}")]
+        span: SourceSpan,
         help_text: String,
     },
 
@@ -359,6 +532,159 @@ pub enum HugsError {
         name: StyledName,
     },
 
+    #[error("I couldn't compile the {name} macro in {file}")]
+    #[diagnostic(
+        code(hugs::macros::syntax),
+        help("Here are some things to check:\n- Are all your {{{{ braces }}}} and {{% blocks %}} properly closed?\n- Are strings properly quoted?\n- Is every `{{% call %}}` closed with `{{% endcall %}}`?")
+    )]
+    MacroSyntax {
+        name: StyledName,
+        file: StyledPath,
+        #[source_code]
+        src: NamedSource<String>,
+        #[label("{reason}")]
+        span: SourceSpan,
+        reason: String,
+    },
+
+    #[error("The {name} macro left broken HTML in {file}")]
+    #[diagnostic(code(hugs::macros::broken_output))]
+    MacroBrokenOutput {
+        name: StyledName,
+        file: StyledPath,
+        #[help]
+        help_text: String,
+    },
+
+    #[error("{file} uses main_content, which is what it makes")]
+    #[diagnostic(
+        code(hugs::content_template::main_content),
+        help("A content template wraps the page's body, `{{{{ content }}}}`, and what it renders becomes `main_content` for the page layout. Use `{{{{ content }}}}` here instead.")
+    )]
+    ContentTemplateUsesMainContent {
+        file: StyledPath,
+        #[source_code]
+        src: NamedSource<String>,
+        #[label("main_content isn't made yet")]
+        span: SourceSpan,
+    },
+
+    // === Template Function Errors ===
+    #[error("I can't use {name} as a template function {what} name in {file}", file = StyledPath::from("config.toml"))]
+    #[diagnostic(
+        code(hugs::config::function_name),
+        help("Function and parameter names must start with a letter or underscore and contain only letters, numbers, and underscores.\n\nExample:\n[template.functions]\nexcerpt_url = {{ params = [\"p\"], body = \"{{{{ p.url }}}}#excerpt\" }}")
+    )]
+    TemplateFunctionInvalidName {
+        name: StyledName,
+        what: &'static str,
+        #[source_code]
+        src: NamedSource<String>,
+        #[label("not a valid identifier")]
+        span: SourceSpan,
+    },
+
+    #[error("The template function {name} in {file} clashes with {existing}", file = StyledPath::from("config.toml"))]
+    #[diagnostic(
+        code(hugs::config::function_collision),
+        help("Template functions share a namespace with macros and built-in functions, so one of them would be hidden. Pick a different name for this function.")
+    )]
+    TemplateFunctionCollision {
+        name: StyledName,
+        existing: String,
+        #[source_code]
+        src: NamedSource<String>,
+        #[label("this name is already taken")]
+        span: SourceSpan,
+    },
+
+    #[error("I couldn't compile the template function {name} in {file}", file = StyledPath::from("config.toml"))]
+    #[diagnostic(
+        code(hugs::config::function_syntax),
+        help("The `body` of a template function is a Jinja snippet, like the inside of a macro.\n\nHere are some things to check:\n- Are all your {{{{ braces }}}} and {{% blocks %}} properly closed?\n- Are strings properly quoted?\n- Are the variables you use listed in `params`?")
+    )]
+    TemplateFunctionSyntax {
+        name: StyledName,
+        #[source_code]
+        src: NamedSource<String>,
+        #[label("{reason}")]
+        span: SourceSpan,
+        reason: String,
+    },
+
+    #[error("I don't know the syntax highlighting theme {theme}")]
+    #[diagnostic(code(hugs::config::unknown_highlight_theme))]
+    UnknownHighlightTheme {
+        theme: StyledName,
+        #[source_code]
+        src: NamedSource<String>,
+        #[label("this theme")]
+        span: Option<SourceSpan>,
+        #[help]
+        help_text: String,
+    },
+
+    #[error("The highlighting alias {alias} points at {target}, a language I don't know")]
+    #[diagnostic(
+        code(hugs::config::unknown_highlight_alias),
+        help("An alias needs a language I can highlight, like `bash`, `json` or `text`. Check the spelling, or leave the alias out.")
+    )]
+    UnknownHighlightAlias {
+        alias: StyledName,
+        target: StyledName,
+        #[source_code]
+        src: NamedSource<String>,
+        #[label("this language")]
+        span: Option<SourceSpan>,
+    },
+
+    #[error("I couldn't load the syntax highlighting registry")]
+    #[diagnostic(
+        code(hugs::highlight::registry),
+        help("The languages and themes are built into Hugs, so this is a bug in me rather than your site: {reason}")
+    )]
+    HighlightRegistry { reason: String },
+
+    #[error("I couldn't render the {name} in {file}", name = StyledName::from("title_template"), file = StyledPath::from("config.toml"))]
+    #[diagnostic(
+        code(hugs::config::title_template),
+        help("`title_template` can use {{{{ title }}}} (the page's title) and {{{{ site.title }}}}.\n\nFor example: title_template = \"{{{{ title }}}} | {{{{ site.title }}}}\"")
+    )]
+    TitleTemplate {
+        #[source_code]
+        src: NamedSource<String>,
+        #[label("{reason}")]
+        span: SourceSpan,
+        reason: String,
+    },
+
+    #[error("The {name} didn't render for the page titled {title:?}: {reason}", name = StyledName::from("title_template"))]
+    #[diagnostic(
+        code(hugs::config::title_template_page),
+        severity(warning),
+        help("I used the page's plain title instead. The template rendered for a sample title when the site loaded, so something about this title trips it up.")
+    )]
+    TitleTemplatePage { title: String, reason: String },
+
+    // === Protected Page Errors ===
+    #[error("The protected page {file} doesn't have a password")]
+    #[diagnostic(
+        code(hugs::protected::missing_password),
+        help("Pages with `protected: true` need a passphrase to encrypt their content with. Add one to the frontmatter, ideally from an environment variable so it stays out of your repository:\n\n---\ntitle: Meeting Notes\nprotected: true\npassword: env:NOTES_PASSWORD\n---")
+    )]
+    ProtectedMissingPassword { file: StyledPath },
+
+    #[error("The protected page {file} reads its password from {var}, but that environment variable isn't set")]
+    #[diagnostic(
+        code(hugs::protected::password_env),
+        help("Set the variable when building, for example:\n\n    {var}=correct-horse-battery-staple hugs build")
+    )]
+    ProtectedPasswordEnv { file: StyledPath, var: StyledName },
+
+    #[error("I couldn't encrypt the protected page {file}: {reason}")]
+    #[diagnostic(code(hugs::protected::encrypt))]
+    ProtectedEncrypt { file: StyledPath, reason: String },
+
     // === Build Errors ===
     #[error("I couldn't resolve the page at URL {url}")]
     #[diagnostic(
@@ -367,6 +693,222 @@ pub enum HugsError {
     )]
     PageResolve { url: StyledPath, file_path: StyledPath },
 
+    #[error("The page at {old_url} moved to {new_url}")]
+    #[diagnostic(code(hugs::build::page_url_changed), severity(warning))]
+    PageUrlChanged {
+        old_url: StyledPath,
+        new_url: StyledPath,
+        #[help]
+        help_text: String,
+    },
+
+    #[error("The page {page} and {other} both want to be written to {path}")]
+    #[diagnostic(
+        code(hugs::build::output_collision),
+        severity(warning),
+        help("I kept the page and skipped {other}. Rendered pages, and the redirects at their aliases, always win over static and generated files at the same path, in both `hugs build` and `hugs dev`. Rename or move one of them to keep both.")
+    )]
+    OutputCollision {
+        path: StyledPath,
+        page: StyledPath,
+        other: String,
+    },
+
+    #[error("I couldn't find a page at {url}")]
+    #[diagnostic(
+        code(hugs::build::single_file_page),
+        help("`--single-file` takes the URL a page is served at, like `/` or `/blog/first-post`.")
+    )]
+    SingleFilePageNotFound { url: StyledPath },
+
+    #[error("I couldn't find a page at {target}")]
+    #[diagnostic(code(hugs::render::page_not_found))]
+    RenderPageNotFound {
+        target: StyledPath,
+        #[help]
+        help_text: String,
+    },
+
+    #[error("I need the site's URL to write a single-file page")]
+    #[diagnostic(
+        code(hugs::build::single_file_url),
+        help("Links to other pages and assets I don't embed have to point at the live site. Set `url` in the [site] section of config.toml.")
+    )]
+    SingleFileNeedsSiteUrl,
+
+    #[error("I couldn't read the asset manifest at {path}")]
+    #[diagnostic(
+        code(hugs::build::asset_manifest),
+        help("`--reuse-asset-manifest` takes the asset-manifest.json an earlier `hugs build` wrote. This one didn't parse: {reason}")
+    )]
+    AssetManifestParse { path: StyledPath, reason: String },
+
+    #[error("I couldn't minify {path}, so I published it as it is")]
+    #[diagnostic(
+        code(hugs::build::asset_not_minified),
+        severity(warning),
+        help("{reason}. If it's fine in the browser, it may use syntax I don't understand; set `js = false` or `svg = false` under [build.minify] to stop trying.")
+    )]
+    AssetNotMinified { path: StyledPath, reason: String },
+
+    #[error("I kept {path} as a link instead of embedding it")]
+    #[diagnostic(code(hugs::build::asset_not_inlined), severity(warning))]
+    AssetNotInlined {
+        path: StyledPath,
+        #[help]
+        help_text: String,
+    },
+
+    #[error("{count} code {} had unknown languages: {languages}", if count.0 == 1 { "block" } else { "blocks" })]
+    #[diagnostic(
+        code(hugs::build::unknown_code_language),
+        severity(warning),
+        help("I showed them as plain text, or as the language they looked like with `autodetect` on. Check the spelling of the language after the opening ```, use `text` for blocks that shouldn't be highlighted, or name the language I should use for one under `[build.syntax_highlighting.aliases]`.")
+    )]
+    UnknownCodeLanguages {
+        count: StyledNum<usize>,
+        languages: String,
+    },
+
+    #[error("{url} is over its weight budget")]
+    #[diagnostic(code(hugs::build::page_over_budget), severity(warning))]
+    PageOverBudget {
+        url: StyledPath,
+        #[help]
+        help_text: String,
+    },
+
+    #[error("Some assets count toward more than one page over budget")]
+    #[diagnostic(code(hugs::build::shared_over_budget), severity(warning))]
+    SharedAssetsOverBudget {
+        #[help]
+        help_text: String,
+    },
+
+    #[error("{page} and {other} are {similarity} the same")]
+    #[diagnostic(
+        code(hugs::build::duplicate_content),
+        severity(warning),
+        help("Pages this alike compete with each other in search results. If one is a leftover copy, delete it. If both should stay as parts of one whole, give them the same `series:` in their frontmatter.")
+    )]
+    DuplicateContent {
+        page: StyledPath,
+        other: StyledPath,
+        similarity: String,
+    },
+
+    #[error("Nothing links to {url}")]
+    #[diagnostic(
+        code(hugs::build::orphan_page),
+        severity(warning),
+        help("{file} isn't linked from any other page or from _/nav.md, so visitors and search engines can't find it. Link to it from somewhere, or set `orphan_ok: true` in its frontmatter if it's meant to be found another way.")
+    )]
+    OrphanPage { url: StyledPath, file: StyledPath },
+
+    #[error("Nothing links to {count} of the {total} pages {file} makes")]
+    #[diagnostic(code(hugs::build::dynamic_orphans), severity(warning))]
+    DynamicOrphans {
+        file: StyledPath,
+        count: String,
+        total: String,
+        #[help]
+        help_text: String,
+    },
+
+    #[error("{pages} not changed in over {age}")]
+    #[diagnostic(code(hugs::build::stale_pages), severity(warning))]
+    StalePages {
+        pages: String,
+        age: String,
+        #[help]
+        help_text: String,
+    },
+
+    #[error("Some pages link {stylesheets} without cache_bust()")]
+    #[diagnostic(code(hugs::build::unbusted_stylesheet), severity(warning))]
+    UnbustedStylesheets {
+        stylesheets: String,
+        #[help]
+        help_text: String,
+    },
+
+    #[error("_headers has {count} rules, but {provider} only reads the first {limit}")]
+    #[diagnostic(
+        code(hugs::build::too_many_header_rules),
+        severity(warning),
+        help("Pages past the limit don't get their preload headers. Turn `auto_preload_headers` off in the [hosting] section of config.toml and preload for every page with one `/*` rule of your own, or trim the site's own _headers.")
+    )]
+    TooManyHeaderRules {
+        count: StyledNum<usize>,
+        limit: usize,
+        provider: &'static str,
+    },
+
+    #[error("I couldn't fetch {url}, so it has no integrity attribute")]
+    #[diagnostic(
+        code(hugs::sri::fetch),
+        severity(warning),
+        help("{reason}. I left its tags as they were, and I'll try again on the next build. Once it's fetched, its hash is pinned in sri-lock.toml.")
+    )]
+    SriFetchFailed { url: StyledPath, reason: String },
+
+    #[error("{url} changed since its hash was pinned in {file}", file = StyledPath::from("sri-lock.toml"))]
+    #[diagnostic(
+        code(hugs::sri::changed),
+        help("It was pinned as {pinned}, but now it's {fetched}, so browsers would refuse to load it. If you expected the change, delete its line from sri-lock.toml and build again. If you didn't, find out why before you publish.")
+    )]
+    SriChanged {
+        url: StyledPath,
+        pinned: String,
+        fetched: String,
+    },
+
+    #[error("I couldn't parse {path}")]
+    #[diagnostic(
+        code(hugs::sri::lock),
+        help("`hugs build` writes it with the hashes of external files when `[sri] enabled = true`. Fix it, or delete it to fetch every file again: {reason}")
+    )]
+    SriLockParse { path: StyledPath, reason: String },
+
+    #[error("{path} has uncommitted changes in {count} files")]
+    #[diagnostic(
+        code(hugs::migrate::dirty),
+        help("I'm about to rewrite a lot of files, and that's easiest to review (and undo) on its own. Commit or stash your changes first, try it with --dry-run, or pass --allow-dirty if you're sure.")
+    )]
+    MigrateDirtyTree { path: StyledPath, count: StyledNum<usize> },
+
+    #[error("`{prefix}` isn't a path on your site")]
+    #[diagnostic(
+        code(hugs::migrate::prefix),
+        help("--from and --to are URL paths starting with `/`, like `hugs migrate links --from /old-blog --to /blog`.")
+    )]
+    MigratePrefix { prefix: String },
+
+    #[error("Pages that failed to build: {count}")]
+    #[diagnostic(
+        code(hugs::build::pages_failed),
+        help("Each page's error is above. I wrote the rest of the site, but left the failed pages out (or in as placeholders, with `failed_pages = \"placeholder\"`).")
+    )]
+    PagesFailed { count: StyledNum<usize> },
+
+    #[error("Pages over their weight budget: {count}")]
+    #[diagnostic(
+        code(hugs::build::budgets_exceeded),
+        help("The warnings above show what each page loads. Slim the pages down, raise the limits in the [budgets] section of config.toml, give heavy pages their own `budget:` in frontmatter, or set `fail = false` to only warn.")
+    )]
+    BudgetsExceeded { count: StyledNum<usize> },
+
+    #[error("The build had {count} {}, and strict mode counts them as errors", if count.0 == 1 { "warning" } else { "warnings" })]
+    #[diagnostic(
+        code(hugs::build::strict),
+        help("I still wrote everything, so you can look at the output. Fix the warnings above, or drop --strict and `strict = true` from the [build] section of config.toml to let them through.")
+    )]
+    StrictWarnings { count: StyledNum<usize> },
+
+    #[error("I ran into a problem generating a redirect page")]
+    #[diagnostic(code(hugs::build::redirect_template))]
+    RedirectTemplate { reason: String },
+
     #[error("A background task failed: {reason}")]
     #[diagnostic(
         code(hugs::build::task_join),
@@ -378,10 +920,17 @@ pub enum HugsError {
     #[error("I can't create a site at {path} because the directory is not empty")]
     #[diagnostic(
         code(hugs::new::dir_not_empty),
-        help("Choose an empty directory or a path that doesn't exist yet.")
+        help("Choose an empty directory or a path that doesn't exist yet. To add just the files it's missing to a site you started, use `hugs new --into` instead.")
     )]
     DirNotEmpty { path: StyledPath },
 
+    #[error("There's no site folder at {path} to add files to")]
+    #[diagnostic(
+        code(hugs::new::into_missing),
+        help("`--into` fills in the files a site you already started is missing. To start a new one there, drop `--into`: `hugs new {path}`.")
+    )]
+    NewIntoMissing { path: StyledPath },
+
     #[error("I couldn't read your input: {cause}")]
     #[diagnostic(code(hugs::new::input_error))]
     InputError { cause: String },
@@ -403,6 +952,26 @@ pub enum HugsError {
         cause: std::io::Error,
     },
 
+    // === Clean Errors ===
+    #[error("I won't delete {path}: {reason}")]
+    #[diagnostic(
+        code(hugs::clean::unsafe_output),
+        help("Point --output at a folder of its own, like dist. If you really mean it, add --force and I'll ask before deleting anything.")
+    )]
+    UnsafeOutputDir { path: StyledPath, reason: String },
+
+    #[error("I left {path} alone")]
+    #[diagnostic(code(hugs::clean::declined))]
+    CleanDeclined { path: StyledPath },
+
+    #[error("I couldn't delete the output directory at {path}")]
+    #[diagnostic(code(hugs::clean::remove_dir))]
+    RemoveDir {
+        path: StyledPath,
+        #[source]
+        cause: std::io::Error,
+    },
+
     // === Server Runtime Errors ===
     #[error("The server encountered an error")]
     #[diagnostic(code(hugs::server::runtime))]
@@ -421,6 +990,15 @@ pub enum HugsError {
         #[source]
         cause: std::io::Error,
     },
+
+    // === Shell Integration Errors ===
+    #[error("I couldn't write the {what}")]
+    #[diagnostic(code(hugs::shell::write))]
+    ShellOutput {
+        what: &'static str,
+        #[source]
+        cause: std::io::Error,
+    },
 }
 
 pub type Result<T> = std::result::Result<T, HugsError>;
@@ -532,6 +1110,36 @@ impl HugsError {
         }
     }
 
+    /// Create an error for a feed's item template that can't render at all (e.g. a syntax error)
+    pub fn feed_item_template(feed_name: &str, file: &str, content: &str, error: &TemplateError) -> Self {
+        HugsError::FeedItemTemplate {
+            feed_name: feed_name.into(),
+            file: file.into(),
+            src: NamedSource::new(file, content.to_string()),
+            span: extract_template_span(&error.error, content, error.macro_prefix_bytes, error.macro_prefix_lines),
+            reason: format_template_error_reason(&error.error),
+            help_text: template_error_help(&error.error, &error.hints),
+        }
+    }
+
+    /// Create a warning for a feed's item template that failed on a single page
+    pub fn feed_item_template_failed(
+        feed_name: &str,
+        page_url: &str,
+        file: &str,
+        content: &str,
+        error: &TemplateError,
+    ) -> Self {
+        HugsError::FeedItemTemplateFailed {
+            feed_name: feed_name.into(),
+            page_url: page_url.into(),
+            src: NamedSource::new(file, content.to_string()),
+            span: extract_template_span(&error.error, content, error.macro_prefix_bytes, error.macro_prefix_lines),
+            reason: format_template_error_reason(&error.error),
+            help_text: template_error_help(&error.error, &error.hints),
+        }
+    }
+
     /// Create a template render error for frontmatter with proper file location
     ///
     /// This shows the actual source file and highlights the correct line in frontmatter
@@ -581,14 +1189,13 @@ impl HugsError {
         }
     }
 
-    /// Create a port bind error with command source and highlighted port
-    pub fn port_bind(path: &Path, port: u16, cause: std::io::Error) -> Self {
+    /// Create a port bind error with command source and highlighted port.
+    /// `command` is how the server was started, without the `--port` flag.
+    pub fn port_bind(command: &str, port: u16, cause: std::io::Error) -> Self {
         use owo_colors::OwoColorize;
 
-        let command = format!("hugs dev {} --port {}", path.display(), port);
-        let port_str = port.to_string();
-        let port_start = command.rfind(&port_str).unwrap_or(0);
-        let span = SourceSpan::new(port_start.into(), port_str.len().into());
+        let port_start = command.len() + " --port ".len();
+        let span = SourceSpan::new(port_start.into(), port.to_string().len());
 
         let alt_port = port.checked_add(1).unwrap_or(8081);
         let help_text = format!(
@@ -596,18 +1203,32 @@ impl HugsError {
             1. Try a different port: {}\n\n  \
             2. Omit {} to let me find an available port automatically",
             port.bold(),
-            format!("hugs dev <path> --port {}", alt_port).cyan(),
+            format!("{} --port {}", command, alt_port).cyan(),
             "--port".cyan().bold()
         );
 
         HugsError::PortBind {
             port: port.into(),
-            src: NamedSource::new("command".to_string(), command),
+            src: NamedSource::new("command", format!("{} --port {}", command, port)),
             span,
             help_text,
             cause,
         }
     }
+
+    /// Create the error for when every port from `start_port` to `end_port` is taken
+    pub fn no_available_port(command: &str, start_port: u16, end_port: u16) -> Self {
+        use owo_colors::OwoColorize;
+
+        HugsError::NoAvailablePort {
+            start_port: start_port.into(),
+            end_port: end_port.into(),
+            help_text: format!(
+                "All ports in the range are in use. Try specifying a different starting port:\n\n    {}",
+                format!("{} --port 9000", command).cyan()
+            ),
+        }
+    }
 }
 
 /// Extract source span from MiniJinja error, adjusting for macro prefix
@@ -667,18 +1288,10 @@ fn extract_template_span(
 /// Format a clean error message from MiniJinja error
 /// Uses detail() for cleaner messages when available
 fn format_template_error_reason(error: &minijinja::Error) -> String {
-    use crate::run::{HELP_MARKER_FILTER, HELP_MARKER_FUNCTION, HELP_MARKER_TEST};
-
     // detail() provides a cleaner message without the full context
     if let Some(detail) = error.detail() {
-        if detail.starts_with(HELP_MARKER_FUNCTION) {
-            return "you asked for help here".to_string();
-        }
-        if detail.starts_with(HELP_MARKER_FILTER) {
-            return "you asked for filter help here".to_string();
-        }
-        if detail.starts_with(HELP_MARKER_TEST) {
-            return "you asked for test help here".to_string();
+        if let Some(request) = HelpRequest::parse(detail) {
+            return request.reason().to_string();
         }
         return detail.to_string();
     }
@@ -728,7 +1341,7 @@ impl TemplateHints {
             // Output formatting
             "format", "indent", "pprint", "tojson",
             // Hugs custom filters
-            "datefmt", "flatten", "help",
+            "datefmt", "flatten", "spaceless", "regex_replace", "help",
         ].into_iter().map(String::from).collect();
 
         // MiniJinja builtin tests (from minijinja 2.x documentation)
@@ -739,7 +1352,7 @@ impl TemplateHints {
             "even", "false", "filter", "float", "ge", "gt", "in", "integer",
             "iterable", "le", "lower", "lt", "mapping", "ne", "none", "number",
             "odd", "safe", "sameas", "sequence", "startingwith", "string",
-            "test", "true", "undefined", "upper",
+            "test", "true", "undefined", "upper", "matches",
         ].into_iter().map(String::from).collect();
 
         // Variables from PageContent struct (our code, so we know these)
@@ -789,7 +1402,7 @@ fn edit_distance(a: &str, b: &str) -> usize {
 }
 
 /// Find the best fuzzy match from a list of candidates
-fn find_best_match<'a>(name: &str, candidates: &'a [String]) -> Option<&'a str> {
+pub(crate) fn find_best_match<'a>(name: &str, candidates: &'a [String]) -> Option<&'a str> {
     let name_lower = name.to_lowercase();
     let max_distance = (name.len() / 2).max(2);
 
@@ -839,238 +1452,100 @@ fn extract_identifier(detail: &str) -> Option<&str> {
         }
     }
 
-    // MiniJinja also uses: "NAME is unknown" or "NAME is undefined" (without type prefix)
-    if let Some(pos) = detail.find(" is unknown") {
-        let before = &detail[..pos];
-        // Get the last word before " is unknown"
-        if let Some(start) = before.rfind(' ') {
-            return Some(&before[start + 1..]);
-        } else if !before.is_empty() {
-            return Some(before);
-        }
-    }
-    if let Some(pos) = detail.find(" is undefined") {
-        let before = &detail[..pos];
-        if let Some(start) = before.rfind(' ') {
-            return Some(&before[start + 1..]);
-        } else if !before.is_empty() {
-            return Some(before);
-        }
-    }
-
-    None
-}
-
-/// Parse help marker to extract kind and value (value is base64 encoded)
-fn parse_help_value_marker(detail: &str, prefix: &str) -> (String, String) {
-    use base64::{Engine, engine::general_purpose::STANDARD};
-    
-    let rest = detail.strip_prefix(prefix).unwrap_or("");
-    let mut kind = String::new();
-    let mut value_b64 = String::new();
-
-    for part in rest.split(':') {
-        if let Some(k) = part.strip_prefix("kind=") {
-            kind = k.to_string();
-        } else if let Some(v) = part.strip_prefix("value=") {
-            value_b64 = v.to_string();
-        }
-    }
-
-    let value = STANDARD
-        .decode(&value_b64)
-        .ok()
-        .and_then(|b| String::from_utf8(b).ok())
-        .unwrap_or_else(|| "?".to_string());
-
-    (kind, value)
-}
-
-/// Parse help function marker to extract variables
-/// A variable with its name and value representation
-struct VariableInfo {
-    name: String,
-    value: String,
-}
-
-fn parse_help_function_marker(detail: &str, prefix: &str) -> Vec<VariableInfo> {
-    use base64::{Engine, engine::general_purpose::STANDARD};
-    
-    let rest = detail.strip_prefix(prefix).unwrap_or("");
-    
-    // Find "variables=" and take everything after it
-    let vars = if let Some(pos) = rest.find("variables=") {
-        &rest[pos + "variables=".len()..]
-    } else {
-        return Vec::new();
-    };
-    
-    if vars.is_empty() {
-        return Vec::new();
-    }
-    
-    // Parse base64-encoded name:value pairs separated by commas
-    let mut result = Vec::new();
-    for entry in vars.split(',') {
-        if let Some((name_b64, value_b64)) = entry.split_once(':') {
-            let name = STANDARD
-                .decode(name_b64)
-                .ok()
-                .and_then(|b| String::from_utf8(b).ok())
-                .unwrap_or_else(|| "?".to_string());
-            let value = STANDARD
-                .decode(value_b64)
-                .ok()
-                .and_then(|b| String::from_utf8(b).ok())
-                .unwrap_or_else(|| "?".to_string());
-            result.push(VariableInfo { name, value });
-        }
-    }
-    
-    result
-}
-
-/// Wrap a list of items into lines with a maximum character width
-fn wrap_items_to_lines(items: &[String], max_width: usize) -> String {
-    use owo_colors::OwoColorize;
-
-    let mut result = String::new();
-    let mut current_line = String::from("  ");
-    let mut current_len = 2; // Account for leading indent
-
-    for (i, item) in items.iter().enumerate() {
-        let separator = if i > 0 { ", " } else { "" };
-        let addition_len = separator.len() + item.len();
-
-        if current_len + addition_len > max_width && current_len > 2 {
-            result.push_str(&current_line);
-            result.push('\n');
-            current_line = format!("  {}", item.bright_yellow());
-            current_len = 2 + item.len();
-        } else {
-            current_line.push_str(separator);
-            current_line.push_str(&item.bright_yellow().to_string());
-            current_len += addition_len;
-        }
-    }
-
-    if current_len > 2 {
-        result.push_str(&current_line);
-        result.push('\n');
-    }
-
-    result
-}
-
-/// Format help message for the `help()` function - shows everything
-fn format_full_help(context_vars: &[VariableInfo], hints: &TemplateHints) -> String {
-    use owo_colors::OwoColorize;
-
-    let mut help = String::new();
-
-    help.push_str("Variables you can use:\n");
-    if context_vars.is_empty() {
-        help.push_str("  I couldn't find any variables in this context.\n");
-    } else {
-        for var in context_vars {
-            help.push_str(&format!(
-                "  {} = {}\n",
-                var.name.bright_yellow(),
-                var.value.truecolor(255, 165, 0) // Orange
-            ));
+    // MiniJinja also uses: "NAME is unknown" or "NAME is undefined" (without type prefix)
+    if let Some(pos) = detail.find(" is unknown") {
+        let before = &detail[..pos];
+        // Get the last word before " is unknown"
+        if let Some(start) = before.rfind(' ') {
+            return Some(&before[start + 1..]);
+        } else if !before.is_empty() {
+            return Some(before);
         }
     }
-
-    help.push_str("\nFunctions you can call:\n");
-    if hints.functions.is_empty() {
-        help.push_str("  No custom functions are available.\n");
-    } else {
-        let funcs: Vec<String> = hints.functions.iter().map(|f| format!("{}()", f)).collect();
-        help.push_str(&wrap_items_to_lines(&funcs, 50));
+    if let Some(pos) = detail.find(" is undefined") {
+        let before = &detail[..pos];
+        if let Some(start) = before.rfind(' ') {
+            return Some(&before[start + 1..]);
+        } else if !before.is_empty() {
+            return Some(before);
+        }
     }
 
-    help.push_str("\nFilters you can apply:\n");
-    if hints.filters.is_empty() {
-        help.push_str("  No filters are available.\n");
-    } else {
-        help.push_str(&wrap_items_to_lines(&hints.filters, 50));
-    }
+    None
+}
 
-    help.push_str("\nTests you can use:\n");
-    if hints.tests.is_empty() {
-        help.push_str("  No tests are available.\n");
+/// Help for the `help()` function - shows everything
+fn full_help(variables: Vec<HelpVariable>, hints: &TemplateHints) -> TemplateHelp {
+    let variables = if variables.is_empty() {
+        HelpBody::Lines(vec!["I couldn't find any variables in this context.".to_string()])
     } else {
-        help.push_str(&wrap_items_to_lines(&hints.tests, 50));
-    }
+        HelpBody::Variables(variables)
+    };
+    let functions = hints.functions.iter().map(|f| format!("{}()", f)).collect();
 
+    let mut sections = vec![
+        HelpSection::new("Variables you can use", variables),
+        HelpSection::items("Functions you can call", functions, "No custom functions are available."),
+        HelpSection::items("Filters you can apply", hints.filters.clone(), "No filters are available."),
+        HelpSection::items("Tests you can use", hints.tests.clone(), "No tests are available."),
+    ];
     if !hints.macros.is_empty() {
-        help.push_str("\nMacros you've defined:\n");
-        help.push_str(&wrap_items_to_lines(&hints.macros, 50));
+        sections.push(HelpSection::new("Macros you've defined", HelpBody::Items(hints.macros.clone())));
     }
-
-    help
+    sections.push(HelpSection::new(
+        "Trimming whitespace",
+        HelpBody::Lines(vec![
+            "`{%-` and `-%}` drop the whitespace before or after a tag, e.g. `{%- if draft -%}`".to_string(),
+            "Set trim_blocks/lstrip_blocks under [template] in config.toml to do it for every block".to_string(),
+        ]),
+    ));
+    sections.push(HelpSection::new(
+        "Escaping",
+        HelpBody::Lines(vec![
+            "I escape text once when a template outputs it, so frontmatter like `title: Ben & Jerry's` is safe anywhere".to_string(),
+            format!("`{}` is already HTML and goes out as-is", crate::run::HTML_CONTEXT_FIELDS.join(", ")),
+            "For HTML of your own in frontmatter, write `{{ value | safe }}`".to_string(),
+        ]),
+    ));
+
+    TemplateHelp::Sections(sections)
 }
 
-/// Format help message for the `| help` filter - shows value info and filters
-fn format_filter_help(kind: &str, value: &str, hints: &TemplateHints) -> String {
-    use owo_colors::OwoColorize;
-
-    let mut help = format!(
-        "You're filtering a {} with value:\n    {}\n",
-        kind.yellow().bold(),
-        value.bright_yellow()
-    );
-
-    help.push_str("\nFilters you can apply:\n");
-    if hints.filters.is_empty() {
-        help.push_str("  I couldn't find any filters.\n");
-    } else {
-        help.push_str(&wrap_items_to_lines(&hints.filters, 50));
-    }
-
-    help
+/// Help for the `| help` filter (`verb` "filtering") or the `is help` test ("testing") - shows the
+/// value and what can be done with it
+fn value_help(verb: &str, kind: &str, value: String, list: HelpSection) -> TemplateHelp {
+    TemplateHelp::Sections(vec![
+        HelpSection::new(format!("You're {} a `{}` with value", verb, kind), HelpBody::Value(value)),
+        list,
+    ])
 }
 
-/// Format help message for the `is help` test - shows value info and tests
-fn format_test_help(kind: &str, value: &str, hints: &TemplateHints) -> String {
-    use owo_colors::OwoColorize;
-
-    let mut help = format!(
-        "You're testing a {} with value:\n    {}\n",
-        kind.yellow().bold(),
-        value.bright_yellow()
-    );
+/// Generate contextual help text based on the error kind
+fn template_error_help(error: &minijinja::Error, hints: &TemplateHints) -> TemplateHelp {
+    let detail = error.detail().unwrap_or_default();
 
-    help.push_str("\nTests you can use:\n");
-    if hints.tests.is_empty() {
-        help.push_str("  I couldn't find any tests.\n");
-    } else {
-        help.push_str(&wrap_items_to_lines(&hints.tests, 50));
+    match HelpRequest::parse(detail) {
+        Some(HelpRequest::Everything { variables }) => return full_help(variables, hints),
+        Some(HelpRequest::Filter { kind, value }) => {
+            let filters = HelpSection::items("Filters you can apply", hints.filters.clone(), "I couldn't find any filters.");
+            return value_help("filtering", &kind, value, filters);
+        }
+        Some(HelpRequest::Test { kind, value }) => {
+            let tests = HelpSection::items("Tests you can use", hints.tests.clone(), "I couldn't find any tests.");
+            return value_help("testing", &kind, value, tests);
+        }
+        None => {}
     }
 
-    help
+    TemplateHelp::Text(template_error_advice(error, detail, hints))
 }
 
-/// Generate contextual help text based on the error kind
-fn template_error_help(error: &minijinja::Error, hints: &TemplateHints) -> String {
-    use crate::run::{HELP_MARKER_FILTER, HELP_MARKER_FUNCTION, HELP_MARKER_TEST};
+/// Advice for a template error that isn't a help request
+fn template_error_advice(error: &minijinja::Error, detail: &str, hints: &TemplateHints) -> String {
     use minijinja::ErrorKind;
 
-    let detail = error.detail().unwrap_or_default();
-
-    if detail.starts_with(HELP_MARKER_FUNCTION) {
-        let context_vars = parse_help_function_marker(detail, HELP_MARKER_FUNCTION);
-        return format_full_help(&context_vars, hints);
-    }
-
-    if detail.starts_with(HELP_MARKER_FILTER) {
-        let (kind, value) = parse_help_value_marker(detail, HELP_MARKER_FILTER);
-        return format_filter_help(&kind, &value, hints);
-    }
-
-    if detail.starts_with(HELP_MARKER_TEST) {
-        let (kind, value) = parse_help_value_marker(detail, HELP_MARKER_TEST);
-        return format_test_help(&kind, &value, hints);
+    if detail.starts_with(crate::template_regex::INVALID_PATTERN_PREFIX) {
+        return crate::template_regex::invalid_pattern_help(detail);
     }
 
     let identifier = extract_identifier(detail);
@@ -1265,97 +1740,260 @@ impl<T> HugsResultExt<T> for std::result::Result<T, std::io::Error> {
     }
 }
 
+/// `text` without the color codes StyledPath and friends add
+pub fn strip_ansi(text: &str) -> String {
+    let mut plain = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(escape) = rest.find("\x1b[") {
+        plain.push_str(&rest[..escape]);
+        rest = &rest[escape..];
+        rest = &rest[rest.find('m').map_or(rest.len(), |end| end + 1)..];
+    }
+    plain.push_str(rest);
+    plain
+}
+
+/// A HugsError on one line without colors, with its code, like
+/// `I couldn't find the file at _/nav.md [hugs::file::not_found]`
+pub fn error_summary(error: &HugsError) -> String {
+    let plain = strip_ansi(&error.to_string()).split_whitespace().collect::<Vec<_>>().join(" ");
+    match error.code() {
+        Some(code) => format!("{} [{}]", plain, code),
+        None => plain,
+    }
+}
+
+/// A HugsError with its help left out, for when the error page shows the help itself
+#[derive(Debug, Error)]
+#[error(transparent)]
+struct WithoutHelp(HugsError);
+
+impl Diagnostic for WithoutHelp {
+    fn code<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        self.0.code()
+    }
+
+    fn severity(&self) -> Option<miette::Severity> {
+        self.0.severity()
+    }
+
+    fn url<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        self.0.url()
+    }
+
+    fn source_code(&self) -> Option<&dyn miette::SourceCode> {
+        self.0.source_code()
+    }
+
+    fn labels(&self) -> Option<Box<dyn Iterator<Item = miette::LabeledSpan> + '_>> {
+        self.0.labels()
+    }
+
+    fn related<'a>(&'a self) -> Option<Box<dyn Iterator<Item = &'a dyn Diagnostic> + 'a>> {
+        self.0.related()
+    }
+
+    fn diagnostic_source(&self) -> Option<&dyn Diagnostic> {
+        self.0.diagnostic_source()
+    }
+}
+
+impl HugsError {
+    /// The help of a template error, which can be more than text
+    fn template_help(&self) -> Option<&TemplateHelp> {
+        match self {
+            HugsError::TemplateRender { help_text, .. }
+            | HugsError::FeedItemTemplate { help_text, .. }
+            | HugsError::FeedItemTemplateFailed { help_text, .. }
+            | HugsError::DynamicExprEval { help_text, .. } => Some(help_text),
+            _ => None,
+        }
+    }
+}
+
 /// Render a HugsError as HTML for in-browser display during development
 pub fn render_error_html(error: &HugsError, dev_script: &str) -> String {
     use std::fmt::Write;
 
     let mut html = String::new();
 
+    // Help with lists in it is shown as HTML below the report, where the lists can flow to fit
+    let help_html = error.template_help().and_then(TemplateHelp::to_html);
+
     // Use miette's debug output which includes the fancy formatting
-    let error_text = format!("{:?}", miette::Report::new_boxed(Box::new(error.clone())));
+    let error_text = match help_html {
+        Some(_) => format!("{:?}", miette::Report::new(WithoutHelp(error.clone()))),
+        None => format!("{:?}", miette::Report::new_boxed(Box::new(error.clone()))),
+    };
 
     // Convert ANSI escape codes to styled HTML spans
-    let escaped = ansi_to_html::convert(&error_text)
-        .unwrap_or_else(|_| {
-            // Fallback: escape HTML manually if conversion fails
-            error_text
-                .replace('&', "&amp;")
-                .replace('<', "&lt;")
-                .replace('>', "&gt;")
-        })
-        .replace('\n', "<br>");
+    let escaped = ansi_to_html::convert(&error_text).unwrap_or_else(|_| {
+        // Fallback: escape HTML manually if conversion fails
+        crate::run::escape_html(&error_text)
+    });
 
     write!(
         html,
-        r#"<!DOCTYPE html>
+        r##"<!DOCTYPE html>
 <html lang="en">
 <head>
     <meta charset="UTF-8">
     <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <meta name="color-scheme" content="light dark">
     <title>Error - Hugs</title>
     <style>
+        :root {{
+            --page: #f6f6f9;
+            --surface: #ffffff;
+            --text: #1f2330;
+            --muted: #4c5266;
+            --accent: #b3153a;
+            --help-border: #006c8f;
+            --code-bg: #ebedf3;
+            --focus: #1a5fd0;
+        }}
+        @media (prefers-color-scheme: dark) {{
+            :root {{
+                --page: #1a1a2e;
+                --surface: #16213e;
+                --text: #eeeeee;
+                --muted: #b8bdd0;
+                --accent: #ff6b86;
+                --help-border: #00d9ff;
+                --code-bg: #0f3460;
+                --focus: #8ab4ff;
+            }}
+        }}
         body {{
-            font-family: 'SF Mono', 'Menlo', 'Monaco', 'Consolas', monospace;
-            background-color: #1a1a2e;
-            color: #eee;
-            padding: 2rem;
+            font-family: system-ui, -apple-system, 'Segoe UI', sans-serif;
+            background-color: var(--page);
+            color: var(--text);
+            padding: 2rem 1rem;
             margin: 0;
             line-height: 1.6;
         }}
-        .error-container {{
+        code, pre {{
+            font-family: 'SF Mono', 'Menlo', 'Monaco', 'Consolas', monospace;
+        }}
+        :focus-visible {{
+            outline: 3px solid var(--focus);
+            outline-offset: 2px;
+        }}
+        .skip-link {{
+            position: absolute;
+            left: 1rem;
+            top: -3rem;
+            padding: 0.5rem 1rem;
+            background: var(--surface);
+            color: var(--text);
+            border-radius: 4px;
+        }}
+        .skip-link:focus {{
+            top: 1rem;
+        }}
+        header, main {{
             max-width: 900px;
             margin: 0 auto;
-            background: #16213e;
-            border-radius: 8px;
-            padding: 2rem;
-            border-left: 4px solid #e94560;
         }}
-        .error-header {{
+        header {{
             display: flex;
             justify-content: space-between;
             align-items: center;
-            margin-bottom: 1rem;
+            color: var(--accent);
+        }}
+        h1 {{
+            font-size: 1.2rem;
+            margin: 0 0 1rem;
         }}
         .error-face {{
             font-size: 1.5rem;
-            color: #e94560;
         }}
-        .error-title {{
-            color: #e94560;
-            font-size: 1.2rem;
-            display: flex;
-            align-items: center;
-            gap: 0.5rem;
+        main {{
+            background: var(--surface);
+            border-radius: 8px;
+            padding: 1.5rem;
+            border-left: 4px solid var(--accent);
         }}
-        .error-content {{
-            white-space: pre-wrap;
+        /* The report keeps its terminal colors, so it stays dark in either scheme */
+        .report {{
+            --red: #ff6b6b; --green: #7ee787; --yellow: #e3b341; --blue: #79c0ff;
+            --magenta: #d2a8ff; --cyan: #56d4dd; --white: #eeeeee; --black: #6e7681;
+            --bright-red: #ffa198; --bright-green: #aff5b4; --bright-yellow: #f8e3a1; --bright-blue: #a5d6ff;
+            --bright-magenta: #e2c5ff; --bright-cyan: #b3f0ff; --bright-white: #ffffff; --bright-black: #8b949e;
+            margin: 0;
+            padding: 1rem;
+            background: #10131c;
+            color: #eeeeee;
+            border-radius: 4px;
             font-size: 0.9rem;
+            white-space: pre-wrap;
             overflow-x: auto;
         }}
-        .help-text {{
+        .help {{
             margin-top: 1.5rem;
-            padding: 1rem;
-            background: #0f3460;
-            border-radius: 4px;
-            border-left: 3px solid #00d9ff;
+            padding-left: 1rem;
+            border-left: 3px solid var(--help-border);
+        }}
+        .help h2 {{
+            font-size: 1.1rem;
+            margin: 0;
+        }}
+        .help h3 {{
+            font-size: 1rem;
+            margin: 1rem 0 0.5rem;
+        }}
+        .help p {{
+            margin: 0.25rem 0;
+            color: var(--muted);
+        }}
+        .help code {{
+            background: var(--code-bg);
+            border-radius: 3px;
+            padding: 0 0.3em;
+        }}
+        .help-items {{
+            display: flex;
+            flex-wrap: wrap;
+            gap: 0.4rem;
+            list-style: none;
+            margin: 0;
+            padding: 0;
+        }}
+        .help-variables {{
+            display: grid;
+            grid-template-columns: max-content 1fr;
+            gap: 0.25rem 1rem;
+            margin: 0;
+        }}
+        .help-variables div {{
+            display: contents;
+        }}
+        .help-variables dd {{
+            margin: 0;
+            overflow-wrap: anywhere;
+        }}
+        .help-value {{
+            margin: 0;
+            white-space: pre-wrap;
+            overflow-wrap: anywhere;
         }}
     </style>
 </head>
 <body>
-    <div class="error-container">
-        <div class="error-header">
-            <div class="error-title">
-                <span>✕</span>
-                <span>Something went wrong</span>
-            </div>
-            <div class="error-face">(╥﹏╥)</div>
-        </div>
-        <div class="error-content">{}</div>
-    </div>
+    <a class="skip-link" href="#error">Skip to the error</a>
+    <header>
+        <h1><span aria-hidden="true">✕ </span>Something went wrong</h1>
+        <div class="error-face" aria-hidden="true">(╥﹏╥)</div>
+    </header>
+    <main id="error" tabindex="-1">
+        <pre class="report" tabindex="0" aria-label="Error report">{}</pre>
+        {}
+    </main>
     {}
 </body>
-</html>"#,
+</html>"##,
         escaped,
+        help_html.unwrap_or_default(),
         dev_script
     )
     .unwrap();
@@ -1376,6 +2014,11 @@ impl Clone for HugsError {
                 path: path.clone(),
                 cause: std::io::Error::new(cause.kind(), cause.to_string()),
             },
+            HugsError::PathsFolder { key, folder, reason } => HugsError::PathsFolder {
+                key,
+                folder: folder.clone(),
+                reason,
+            },
             HugsError::FrontmatterParse { file, src, span, reason } => HugsError::FrontmatterParse {
                 file: file.clone(),
                 src: NamedSource::new(src.name().to_string(), src.inner().clone()),
@@ -1415,16 +2058,75 @@ impl Clone for HugsError {
                     suggestion: suggestion.clone(),
                 }
             }
+            HugsError::RequiredFileIsDirectory { file_type, path, suggestion } => {
+                HugsError::RequiredFileIsDirectory {
+                    file_type,
+                    path: path.clone(),
+                    suggestion: suggestion.clone(),
+                }
+            }
             HugsError::FeedMissingTitle { feed_name } => {
                 HugsError::FeedMissingTitle { feed_name: feed_name.clone() }
             }
             HugsError::FeedMissingUrl { feed_name } => {
                 HugsError::FeedMissingUrl { feed_name: feed_name.clone() }
             }
+            HugsError::FeedSource { feed_name } => HugsError::FeedSource { feed_name: feed_name.clone() },
+            HugsError::FeedUnknownCollection { feed_name, collection, help_text } => HugsError::FeedUnknownCollection {
+                feed_name: feed_name.clone(),
+                collection: collection.clone(),
+                help_text: help_text.clone(),
+            },
+            HugsError::FeedOutputName { feed_name, filename, src, span, reason } => HugsError::FeedOutputName {
+                feed_name: feed_name.clone(),
+                filename: filename.clone(),
+                src: NamedSource::new(src.name(), src.inner().clone()),
+                span: *span,
+                reason: reason.clone(),
+            },
+            HugsError::FeedItemTemplateMissing { feed_name, path } => HugsError::FeedItemTemplateMissing {
+                feed_name: feed_name.clone(),
+                path: path.clone(),
+            },
+            HugsError::FeedItemTemplatePath { feed_name, path, reason } => HugsError::FeedItemTemplatePath {
+                feed_name: feed_name.clone(),
+                path: path.clone(),
+                reason: reason.clone(),
+            },
+            HugsError::FeedItemTemplate { feed_name, file, src, span, reason, help_text } => {
+                HugsError::FeedItemTemplate {
+                    feed_name: feed_name.clone(),
+                    file: file.clone(),
+                    src: NamedSource::new(src.name(), src.inner().clone()),
+                    span: *span,
+                    reason: reason.clone(),
+                    help_text: help_text.clone(),
+                }
+            }
+            HugsError::FeedItemTemplateFailed { feed_name, page_url, src, span, reason, help_text } => {
+                HugsError::FeedItemTemplateFailed {
+                    feed_name: feed_name.clone(),
+                    page_url: page_url.clone(),
+                    src: NamedSource::new(src.name(), src.inner().clone()),
+                    span: *span,
+                    reason: reason.clone(),
+                    help_text: help_text.clone(),
+                }
+            }
             HugsError::SitemapMissingUrl => HugsError::SitemapMissingUrl,
             HugsError::SitemapTemplate { reason } => {
                 HugsError::SitemapTemplate { reason: reason.clone() }
             }
+            HugsError::MalformedXml { output, reason, help_text } => HugsError::MalformedXml {
+                output: output.clone(),
+                reason: reason.clone(),
+                help_text: help_text.clone(),
+            },
+            HugsError::NotFoundRelativeUrls { output, urls, help_text } => HugsError::NotFoundRelativeUrls {
+                output: output.clone(),
+                urls: urls.clone(),
+                help_text: help_text.clone(),
+            },
             HugsError::PortBind { port, src, span, help_text, cause } => HugsError::PortBind {
                 port: StyledNum(port.0),
                 src: NamedSource::new(src.name().to_string(), src.inner().clone()),
@@ -1432,9 +2134,18 @@ impl Clone for HugsError {
                 help_text: help_text.clone(),
                 cause: std::io::Error::new(cause.kind(), cause.to_string()),
             },
-            HugsError::NoAvailablePort { start_port, end_port } => HugsError::NoAvailablePort {
+            HugsError::NoAvailablePort { start_port, end_port, help_text } => HugsError::NoAvailablePort {
                 start_port: StyledNum(start_port.0),
                 end_port: StyledNum(end_port.0),
+                help_text: help_text.clone(),
+            },
+            HugsError::DevForcedStatus { status } => HugsError::DevForcedStatus {
+                status: StyledNum(status.0),
+            },
+            HugsError::DevRenderTimeout { url, secs, help_text } => HugsError::DevRenderTimeout {
+                url: url.clone(),
+                secs: StyledNum(secs.0),
+                help_text: help_text.clone(),
             },
             HugsError::WatcherInit { cause } => HugsError::WatcherInit {
                 cause: notify::Error::generic(&cause.to_string()),
@@ -1450,6 +2161,21 @@ impl Clone for HugsError {
             HugsError::PathInvalidUtf8 { path } => {
                 HugsError::PathInvalidUtf8 { path: path.clone() }
             }
+            HugsError::UrlCollision { url, page, other } => HugsError::UrlCollision {
+                url: url.clone(),
+                page: page.clone(),
+                other: other.clone(),
+            },
+            HugsError::InvalidAlias { file, alias, reason } => HugsError::InvalidAlias {
+                file: file.clone(),
+                alias: alias.clone(),
+                reason: reason.clone(),
+            },
+            HugsError::DynamicIndexConflict { file, other, url } => HugsError::DynamicIndexConflict {
+                file: file.clone(),
+                other: other.clone(),
+                url: url.clone(),
+            },
             HugsError::MarkdownParse { file, reason } => HugsError::MarkdownParse {
                 file: file.clone(),
                 reason: reason.clone(),
@@ -1473,6 +2199,18 @@ impl Clone for HugsError {
                 resolved_value: resolved_value.clone(),
                 help_text: help_text.clone(),
             },
+            HugsError::DynamicParamInvalid { file, param_name, value, expression, reason, src, span, help_text } => {
+                HugsError::DynamicParamInvalid {
+                    file: file.clone(),
+                    param_name: param_name.clone(),
+                    value: value.clone(),
+                    expression: expression.clone(),
+                    reason: reason.clone(),
+                    src: src.as_ref().map(|s| NamedSource::new(s.name(), s.inner().clone())),
+                    span: *span,
+                    help_text: help_text.clone(),
+                }
+            }
             HugsError::MacroParse { file, reason } => HugsError::MacroParse {
                 file: file.clone(),
                 reason: reason.clone(),
@@ -1481,16 +2219,183 @@ impl Clone for HugsError {
                 path: path.clone(),
                 name: name.clone(),
             },
+            HugsError::MacroSyntax { name, file, src, span, reason } => HugsError::MacroSyntax {
+                name: name.clone(),
+                file: file.clone(),
+                src: NamedSource::new(src.name(), src.inner().clone()),
+                span: *span,
+                reason: reason.clone(),
+            },
+            HugsError::MacroBrokenOutput { name, file, help_text } => HugsError::MacroBrokenOutput {
+                name: name.clone(),
+                file: file.clone(),
+                help_text: help_text.clone(),
+            },
+            HugsError::ContentTemplateUsesMainContent { file, src, span } => HugsError::ContentTemplateUsesMainContent {
+                file: file.clone(),
+                src: NamedSource::new(src.name(), src.inner().clone()),
+                span: *span,
+            },
+            HugsError::TemplateFunctionInvalidName { name, what, src, span } => HugsError::TemplateFunctionInvalidName {
+                name: name.clone(),
+                what,
+                src: NamedSource::new(src.name(), src.inner().clone()),
+                span: *span,
+            },
+            HugsError::TemplateFunctionCollision { name, existing, src, span } => HugsError::TemplateFunctionCollision {
+                name: name.clone(),
+                existing: existing.clone(),
+                src: NamedSource::new(src.name(), src.inner().clone()),
+                span: *span,
+            },
+            HugsError::TemplateFunctionSyntax { name, src, span, reason } => HugsError::TemplateFunctionSyntax {
+                name: name.clone(),
+                src: NamedSource::new(src.name(), src.inner().clone()),
+                span: *span,
+                reason: reason.clone(),
+            },
+            HugsError::UnknownHighlightTheme { theme, src, span, help_text } => HugsError::UnknownHighlightTheme {
+                theme: theme.clone(),
+                src: NamedSource::new(src.name(), src.inner().clone()),
+                span: *span,
+                help_text: help_text.clone(),
+            },
+            HugsError::UnknownHighlightAlias { alias, target, src, span } => HugsError::UnknownHighlightAlias {
+                alias: alias.clone(),
+                target: target.clone(),
+                src: NamedSource::new(src.name(), src.inner().clone()),
+                span: *span,
+            },
+            HugsError::HighlightRegistry { reason } => HugsError::HighlightRegistry {
+                reason: reason.clone(),
+            },
+            HugsError::TitleTemplatePage { title, reason } => HugsError::TitleTemplatePage {
+                title: title.clone(),
+                reason: reason.clone(),
+            },
+            HugsError::TitleTemplate { src, span, reason } => HugsError::TitleTemplate {
+                src: NamedSource::new(src.name(), src.inner().clone()),
+                span: *span,
+                reason: reason.clone(),
+            },
+            HugsError::ProtectedMissingPassword { file } => HugsError::ProtectedMissingPassword {
+                file: file.clone(),
+            },
+            HugsError::ProtectedPasswordEnv { file, var } => HugsError::ProtectedPasswordEnv {
+                file: file.clone(),
+                var: var.clone(),
+            },
+            HugsError::ProtectedEncrypt { file, reason } => HugsError::ProtectedEncrypt {
+                file: file.clone(),
+                reason: reason.clone(),
+            },
             HugsError::PageResolve { url, file_path } => HugsError::PageResolve {
                 url: url.clone(),
                 file_path: file_path.clone(),
             },
+            HugsError::PageUrlChanged { old_url, new_url, help_text } => HugsError::PageUrlChanged {
+                old_url: old_url.clone(),
+                new_url: new_url.clone(),
+                help_text: help_text.clone(),
+            },
+            HugsError::OutputCollision { path, page, other } => HugsError::OutputCollision {
+                path: path.clone(),
+                page: page.clone(),
+                other: other.clone(),
+            },
+            HugsError::SingleFilePageNotFound { url } => HugsError::SingleFilePageNotFound { url: url.clone() },
+            HugsError::RenderPageNotFound { target, help_text } => HugsError::RenderPageNotFound {
+                target: target.clone(),
+                help_text: help_text.clone(),
+            },
+            HugsError::SingleFileNeedsSiteUrl => HugsError::SingleFileNeedsSiteUrl,
+            HugsError::AssetManifestParse { path, reason } => HugsError::AssetManifestParse {
+                path: path.clone(),
+                reason: reason.clone(),
+            },
+            HugsError::AssetNotInlined { path, help_text } => HugsError::AssetNotInlined {
+                path: path.clone(),
+                help_text: help_text.clone(),
+            },
+            HugsError::UnknownCodeLanguages { count, languages } => HugsError::UnknownCodeLanguages {
+                count: StyledNum(count.0),
+                languages: languages.clone(),
+            },
+            HugsError::PageOverBudget { url, help_text } => HugsError::PageOverBudget {
+                url: url.clone(),
+                help_text: help_text.clone(),
+            },
+            HugsError::SharedAssetsOverBudget { help_text } => HugsError::SharedAssetsOverBudget {
+                help_text: help_text.clone(),
+            },
+            HugsError::DuplicateContent { page, other, similarity } => HugsError::DuplicateContent {
+                page: page.clone(),
+                other: other.clone(),
+                similarity: similarity.clone(),
+            },
+            HugsError::OrphanPage { url, file } => HugsError::OrphanPage {
+                url: url.clone(),
+                file: file.clone(),
+            },
+            HugsError::AssetNotMinified { path, reason } => HugsError::AssetNotMinified {
+                path: path.clone(),
+                reason: reason.clone(),
+            },
+            HugsError::StalePages { pages, age, help_text } => HugsError::StalePages {
+                pages: pages.clone(),
+                age: age.clone(),
+                help_text: help_text.clone(),
+            },
+            HugsError::DynamicOrphans { file, count, total, help_text } => HugsError::DynamicOrphans {
+                file: file.clone(),
+                count: count.clone(),
+                total: total.clone(),
+                help_text: help_text.clone(),
+            },
+            HugsError::UnbustedStylesheets { stylesheets, help_text } => HugsError::UnbustedStylesheets {
+                stylesheets: stylesheets.clone(),
+                help_text: help_text.clone(),
+            },
+            HugsError::TooManyHeaderRules { count, limit, provider } => HugsError::TooManyHeaderRules {
+                count: StyledNum(count.0),
+                limit: *limit,
+                provider,
+            },
+            HugsError::SriFetchFailed { url, reason } => HugsError::SriFetchFailed {
+                url: url.clone(),
+                reason: reason.clone(),
+            },
+            HugsError::SriChanged { url, pinned, fetched } => HugsError::SriChanged {
+                url: url.clone(),
+                pinned: pinned.clone(),
+                fetched: fetched.clone(),
+            },
+            HugsError::SriLockParse { path, reason } => HugsError::SriLockParse {
+                path: path.clone(),
+                reason: reason.clone(),
+            },
+            HugsError::MigrateDirtyTree { path, count } => HugsError::MigrateDirtyTree {
+                path: path.clone(),
+                count: StyledNum(count.0),
+            },
+            HugsError::MigratePrefix { prefix } => HugsError::MigratePrefix {
+                prefix: prefix.clone(),
+            },
+            HugsError::PagesFailed { count } => HugsError::PagesFailed { count: StyledNum(count.0) },
+            HugsError::BudgetsExceeded { count } => HugsError::BudgetsExceeded { count: StyledNum(count.0) },
+            HugsError::StrictWarnings { count } => HugsError::StrictWarnings { count: StyledNum(count.0) },
+            HugsError::RedirectTemplate { reason } => HugsError::RedirectTemplate {
+                reason: reason.clone(),
+            },
             HugsError::TaskJoin { reason } => HugsError::TaskJoin {
                 reason: reason.clone(),
             },
             HugsError::DirNotEmpty { path } => HugsError::DirNotEmpty {
                 path: path.clone(),
             },
+            HugsError::NewIntoMissing { path } => HugsError::NewIntoMissing {
+                path: path.clone(),
+            },
             HugsError::InputError { cause } => HugsError::InputError {
                 cause: cause.clone(),
             },
@@ -1498,6 +2403,15 @@ impl Clone for HugsError {
                 path: path.clone(),
                 cause: std::io::Error::new(cause.kind(), cause.to_string()),
             },
+            HugsError::UnsafeOutputDir { path, reason } => HugsError::UnsafeOutputDir {
+                path: path.clone(),
+                reason: reason.clone(),
+            },
+            HugsError::CleanDeclined { path } => HugsError::CleanDeclined { path: path.clone() },
+            HugsError::RemoveDir { path, cause } => HugsError::RemoveDir {
+                path: path.clone(),
+                cause: std::io::Error::new(cause.kind(), cause.to_string()),
+            },
             HugsError::CopyFile { src, dest, cause } => HugsError::CopyFile {
                 src: src.clone(),
                 dest: dest.clone(),
@@ -1509,6 +2423,53 @@ impl Clone for HugsError {
             HugsError::DocTempDir { cause } => HugsError::DocTempDir {
                 cause: std::io::Error::new(cause.kind(), cause.to_string()),
             },
+            HugsError::ShellOutput { what, cause } => HugsError::ShellOutput {
+                what,
+                cause: std::io::Error::new(cause.kind(), cause.to_string()),
+            },
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render_error(template: &str) -> HugsError {
+        let mut env = minijinja::Environment::new();
+        env.add_filter("help", |value: minijinja::Value| -> std::result::Result<String, minijinja::Error> {
+            Err(minijinja::Error::new(
+                minijinja::ErrorKind::InvalidOperation,
+                crate::template_help::value_marker(
+                    crate::template_help::HELP_MARKER_FILTER,
+                    &format!("{:?}", value.kind()),
+                    &format!("{:?}", value),
+                ),
+            ))
+        });
+        let error = env.render_str(template, minijinja::context! { title => "<Hi>" }).unwrap_err();
+        HugsError::template_render_named("page.md", template, &error, &TemplateHints::from_environment(&env), 0, 0)
+    }
+
+    #[test]
+    fn test_error_page_lists_help_outside_the_report() {
+        let html = render_error_html(&render_error("{{ title | help }}"), "<script></script>");
+
+        assert!(html.contains(r##"<a class="skip-link" href="#error">"##), "{html}");
+        assert!(html.contains("prefers-color-scheme: dark"), "{html}");
+        let (report, help) = html.split_once("</pre>").unwrap();
+        assert!(report.contains("you asked for filter help here"), "{report}");
+        assert!(!report.contains("Filters you can apply"), "{report}");
+        assert!(help.contains("<pre class=\"help-value\"><code>&quot;&lt;Hi&gt;&quot;</code></pre>"), "{help}");
+        assert!(help.contains("<li><code>selectattr</code></li>"), "{help}");
+        assert!(help.find("</main>").unwrap() < help.find("<script>").unwrap());
+    }
+
+    #[test]
+    fn test_error_page_keeps_text_help_in_the_report() {
+        let html = render_error_html(&render_error("{{ title | nope }}"), "");
+        let (report, rest) = html.split_once("</pre>").unwrap();
+        assert!(report.contains("I don&#39;t recognize this filter"), "{report}");
+        assert!(!rest.contains("class=\"help\""), "{rest}");
+    }
+}
@@ -1,32 +1,176 @@
-use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Range;
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
 use rss::{ChannelBuilder, GuidBuilder, ItemBuilder};
 
 use crate::console;
-use crate::config::{FeedConfig, SiteMetadata};
+use crate::collections::feed_source_pages;
+use crate::config::{FeedConfig, SiteConfig, SiteMetadata};
+use crate::dates::{DateParser, extract_dates};
+use crate::deps::DepRecorder;
 use crate::error::{HugsError, Result};
-use crate::run::PageInfo;
+use crate::run::{AppData, DynamicContext, PageInfo, RenderContext, render_template, resolve_dynamic_doc, resolve_path_to_doc};
+use crate::url::{BaseUrl, SiteUrl};
+use crate::xml_check::xml_text;
+
+/// Check every feed's output file names when the site loads: each must be a plain file name,
+/// and two feeds writing the same file means one of them is lost, which gets a warning.
+/// Item templates are checked here too, since they must stay inside the `_` folder.
+pub fn validate_feed_outputs(config: &SiteConfig) -> Result<()> {
+    for (index, feed) in config.feeds.iter().enumerate() {
+        if let Some(path) = &feed.item_template
+            && let Some(reason) = item_template_problem(path)
+        {
+            return Err(HugsError::FeedItemTemplatePath {
+                feed_name: feed.name.as_str().into(),
+                path: path.as_str().into(),
+                reason: reason.to_string(),
+            });
+        }
+
+        for (output, atom) in [(&feed.output_rss, false), (&feed.output_atom, true)] {
+            let Some(filename) = output else {
+                continue;
+            };
+
+            if let Some(reason) = output_name_problem(filename) {
+                let span = config
+                    .feed_output_span(index, atom)
+                    .map_or_else(|| miette::SourceSpan::from((0_usize, 1_usize)), |range| miette::SourceSpan::new(range.start.into(), range.len().max(1)));
+                return Err(HugsError::FeedOutputName {
+                    feed_name: feed.name.as_str().into(),
+                    filename: filename.as_str().into(),
+                    src: miette::NamedSource::new("config.toml", config.source.clone()),
+                    span,
+                    reason: reason.to_string(),
+                });
+            }
+        }
+    }
+
+    for warning in shared_outputs(config) {
+        console::warn(warning);
+    }
+    Ok(())
+}
+
+/// A warning for each file two feed outputs would both write. A paginated feed writes
+/// `rss-page2.xml` and so on after `rss.xml`, so those count too. Feeds are written in order,
+/// so the later one is what's left.
+fn shared_outputs(config: &SiteConfig) -> Vec<String> {
+    let outputs: Vec<(&FeedConfig, &str)> = config
+        .feeds
+        .iter()
+        .flat_map(|feed| [&feed.output_rss, &feed.output_atom].into_iter().flatten().map(move |output| (feed, output.as_str())))
+        .collect();
+
+    let mut warnings = Vec::new();
+    for (index, &(first, first_output)) in outputs.iter().enumerate() {
+        for &(second, second_output) in &outputs[index + 1..] {
+            let shared = if first_output == second_output {
+                first_output
+            } else if first.paginate && page_number(second_output, first_output).is_some() {
+                second_output
+            } else if second.paginate && page_number(first_output, second_output).is_some() {
+                first_output
+            } else {
+                continue;
+            };
+
+            warnings.push(if first.name == second.name {
+                format!("the {} feed writes both its RSS and Atom to {}, so only the Atom one is kept", first.name, shared)
+            } else {
+                format!("the {} and {} feeds both write {}, so only the {} feed is kept", first.name, second.name, shared, second.name)
+            });
+        }
+    }
+    warnings
+}
+
+/// Which page of a paginated feed whose first file is `output` writes `filename`, from the second on
+fn page_number(filename: &str, output: &str) -> Option<usize> {
+    let pattern = page_filename(output, 0);
+    let (before, after) = pattern.rsplit_once("-page0")?;
+    let number = filename.strip_prefix(before)?.strip_prefix("-page")?.strip_suffix(after)?.parse().ok()?;
+    (number >= 2 && page_filename(output, number) == filename).then_some(number)
+}
+
+/// What's wrong with `filename` as a feed's output file, if anything
+fn output_name_problem(filename: &str) -> Option<&'static str> {
+    if filename.trim().is_empty() {
+        Some("this is empty")
+    } else if filename.split(['/', '\\']).any(|segment| segment == "..") {
+        Some("this leaves the output folder")
+    } else if filename.contains(['/', '\\']) {
+        Some("this is in a folder")
+    } else {
+        None
+    }
+}
+
+/// What's wrong with `path` as a feed's `item_template`, if anything
+fn item_template_problem(path: &str) -> Option<&'static str> {
+    if path.trim().is_empty() {
+        Some("This is empty")
+    } else if path.starts_with(['/', '\\']) || Path::new(path).is_absolute() || path.contains(':') {
+        Some("This is an absolute path")
+    } else if path.split(['/', '\\']).any(|segment| segment == "..") {
+        Some("This leaves the `_` folder")
+    } else {
+        None
+    }
+}
 
 /// Represents a page ready for feed inclusion
 pub struct FeedItem {
     pub title: String,
     pub url: String,
     pub date: Option<DateTime<Utc>>,
+    pub updated: Option<DateTime<Utc>>,
     pub summary: Option<String>,
+    /// HTML body of the item, from the feed's item template or the full page
+    pub content: Option<String>,
     pub author: Option<String>,
+    /// From the page's `tags` and `categories`, up to the feed's `category_limit`
+    pub categories: Vec<String>,
+    /// Full URL of the page of each category that has one in `category_pages`
+    pub category_urls: BTreeMap<String, String>,
+    pub page: PageInfo,
 }
 
-/// Extract feed items from pages matching the source filter
+/// Extract feed items from the feed's source pages or collection, along with any page that sets
+/// `feed: true`. A page's own `feed: false` leaves it out even when it's under the source.
 pub fn collect_feed_items(
     pages: &[PageInfo],
     feed_config: &FeedConfig,
-    site_metadata: &SiteMetadata,
+    config: &SiteConfig,
 ) -> Vec<FeedItem> {
-    let base_url = site_metadata.url.as_deref().unwrap_or("");
+    let site_metadata = &config.site;
+    let dates = DateParser::new(config);
 
-    let mut items: Vec<FeedItem> = pages
+    let category_pages = category_pages(pages, feed_config, config);
+    let source = feed_source_pages(feed_config, config, pages);
+    let forced = pages
         .iter()
-        .filter(|page| matches_source(&page.url, &feed_config.source))
-        .filter_map(|page| page_to_feed_item(page, base_url, site_metadata))
+        .filter(|page| feed_override(page) == Some(true) && !source.iter().any(|p| p.url == page.url));
+    let mut items: Vec<FeedItem> = source
+        .iter()
+        .copied()
+        .filter(|page| feed_override(page) != Some(false))
+        .chain(forced)
+        .filter(|page| !is_protected(page))
+        .filter_map(|page| page_to_feed_item(page, site_metadata, &dates))
+        .map(|mut item| {
+            item.categories.truncate(feed_config.category_limit);
+            item.category_urls = item
+                .categories
+                .iter()
+                .filter_map(|term| Some((term.clone(), site_metadata.absolute_url(category_pages.get(term)?))))
+                .collect();
+            item
+        })
         .collect();
 
     // Sort by date descending (most recent first)
@@ -37,104 +181,288 @@ pub fn collect_feed_items(
         (None, None) => std::cmp::Ordering::Equal,
     });
 
-    // Apply limit
-    items.truncate(feed_config.limit);
+    // Apply limit; paginated feeds keep everything and spread it over pages instead
+    if !feed_config.paginate {
+        items.truncate(feed_config.limit);
+    }
 
     items
 }
 
-/// Check if a page URL matches the feed source filter
-fn matches_source(page_url: &str, source: &str) -> bool {
-    let index_url = if source.ends_with('/') {
-        source.to_string()
-    } else {
-        format!("{}/", source)
+/// The pages `category_pages` makes, by the tag each one is for
+fn category_pages<'a>(pages: &'a [PageInfo], feed_config: &FeedConfig, config: &SiteConfig) -> HashMap<String, &'a SiteUrl> {
+    let Some(file) = feed_config.category_pages.as_deref().map(|file| Path::new(file.trim_start_matches('/'))) else {
+        return HashMap::new();
     };
 
-    page_url.starts_with(source) && page_url != index_url
+    pages
+        .iter()
+        .filter(|page| {
+            let page_file = Path::new(&page.file_path);
+            page_file == file || config.paths.url_path(page_file) == file
+        })
+        .filter_map(|page| Some((yaml_term(&DynamicContext::from_page_info(page)?.param_value)?, &page.url)))
+        .collect()
+}
+
+/// A tag or category written as a string, number or boolean
+fn yaml_term(value: &serde_yaml::Value) -> Option<String> {
+    let term = match value {
+        serde_yaml::Value::String(s) => s.trim().to_string(),
+        serde_yaml::Value::Number(n) => n.to_string(),
+        serde_yaml::Value::Bool(b) => b.to_string(),
+        _ => return None,
+    };
+    (!term.is_empty()).then_some(term)
+}
+
+/// The page's `tags` and then its `categories`, each a single value or a list, without repeats
+fn page_categories(frontmatter: &serde_yaml::Value) -> Vec<String> {
+    let mut categories: Vec<String> = Vec::new();
+    for key in ["tags", "categories"] {
+        let terms = match frontmatter.get(key) {
+            Some(serde_yaml::Value::Sequence(values)) => values.iter().filter_map(yaml_term).collect(),
+            Some(value) => yaml_term(value).into_iter().collect(),
+            None => Vec::new(),
+        };
+        for term in terms {
+            if !categories.contains(&term) {
+                categories.push(term);
+            }
+        }
+    }
+    categories
+}
+
+/// One file of a feed, and the files before and after it
+#[derive(Debug, Clone, PartialEq)]
+pub struct FeedPage {
+    /// Output path, e.g. `rss-page2.xml`
+    pub filename: String,
+    /// Which of the feed's items go in this file
+    pub items: Range<usize>,
+    pub prev: Option<String>,
+    pub next: Option<String>,
+}
+
+/// Split `item_count` items into the files written for a feed whose first file is `output`.
+/// Feeds that don't paginate are a single file, as are paginated ones with no items.
+pub fn feed_pages(output: &str, item_count: usize, feed_config: &FeedConfig) -> Vec<FeedPage> {
+    let per_page = if feed_config.paginate { feed_config.limit.max(1) } else { item_count.max(1) };
+    let page_count = item_count.div_ceil(per_page).max(1);
+
+    (1..=page_count)
+        .map(|number| FeedPage {
+            filename: page_filename(output, number),
+            items: (number - 1) * per_page..(number * per_page).min(item_count),
+            prev: (number > 1).then(|| page_filename(output, number - 1)),
+            next: (number < page_count).then(|| page_filename(output, number + 1)),
+        })
+        .collect()
+}
+
+/// `rss.xml` for the first page, then `rss-page2.xml`, `rss-page3.xml`, ...
+fn page_filename(output: &str, number: usize) -> String {
+    if number == 1 {
+        return output.to_string();
+    }
+
+    let name_start = output.rfind('/').map_or(0, |i| i + 1);
+    match output[name_start..].rfind('.').filter(|&dot| dot > 0) {
+        Some(dot) => {
+            let (stem, extension) = output.split_at(name_start + dot);
+            format!("{}-page{}{}", stem, number, extension)
+        }
+        None => format!("{}-page{}", output, number),
+    }
+}
+
+/// Links between the pages of a paginated feed, as absolute URLs. Every page links to itself,
+/// and to the pages on either side.
+fn paging_links(page: &FeedPage, base_url: &BaseUrl, paginated: bool) -> Vec<atom_syndication::Link> {
+    if !paginated {
+        return Vec::new();
+    }
+
+    let url = |filename: &str| base_url.absolute_url(&SiteUrl::parse(filename));
+    let link = |rel: &str, filename: &str| atom_syndication::Link {
+        href: url(filename),
+        rel: rel.to_string(),
+        ..Default::default()
+    };
+
+    let mut links = vec![link("self", &page.filename)];
+    if let Some(prev) = &page.prev {
+        links.push(link("previous", prev));
+    }
+    if let Some(next) = &page.next {
+        links.push(link("next", next));
+    }
+    links
+}
+
+/// What the page's `feed` frontmatter says about being in feeds, which beats the feed's source
+fn feed_override(page: &PageInfo) -> Option<bool> {
+    page.frontmatter.get("feed").and_then(|v| v.as_bool())
+}
+
+/// Protected pages are only readable with a passphrase, so they never appear in feeds
+fn is_protected(page: &PageInfo) -> bool {
+    page.frontmatter
+        .get("protected")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false)
 }
 
 /// Convert a PageInfo to a FeedItem
 fn page_to_feed_item(
     page: &PageInfo,
-    base_url: &str,
     site_metadata: &SiteMetadata,
+    dates: &DateParser,
 ) -> Option<FeedItem> {
     let title = page
         .frontmatter
         .get("title")
         .and_then(|v| v.as_str())
-        .unwrap_or("Untitled")
-        .to_string();
+        .unwrap_or("Untitled");
 
-    let full_url = format!("{}{}", base_url.trim_end_matches('/'), &page.url);
+    let full_url = site_metadata.absolute_url(&page.url);
 
-    let date = extract_date_from_frontmatter(&page.frontmatter);
+    let page_dates = extract_dates(&page.frontmatter, dates);
 
     let summary = page
         .frontmatter
         .get("description")
         .or_else(|| page.frontmatter.get("summary"))
         .or_else(|| page.frontmatter.get("excerpt"))
-        .and_then(|v| v.as_str())
-        .map(|s| s.to_string());
+        .and_then(|v| v.as_str());
 
     let author = page
         .frontmatter
         .get("author")
         .and_then(|v| v.as_str())
-        .map(|s| s.to_string())
-        .or_else(|| site_metadata.author.clone());
+        .or(site_metadata.author.as_deref());
 
+    // A control character pasted into frontmatter would make the whole feed invalid XML
     Some(FeedItem {
-        title,
+        title: xml_text(title).into_owned(),
         url: full_url,
-        date,
-        summary,
-        author,
+        date: page_dates.published,
+        updated: page_dates.updated,
+        summary: summary.map(|summary| xml_text(summary).into_owned()),
+        content: None,
+        author: author.map(|author| xml_text(author).into_owned()),
+        categories: page_categories(&page.frontmatter).iter().map(|term| xml_text(term).into_owned()).collect(),
+        category_urls: BTreeMap::new(),
+        page: page.clone(),
     })
 }
 
-/// Try to extract and parse a date from frontmatter
-pub fn extract_date_from_frontmatter(frontmatter: &serde_yaml::Value) -> Option<DateTime<Utc>> {
-    let date_str = frontmatter
-        .get("date")
-        .or_else(|| frontmatter.get("published"))
-        .or_else(|| frontmatter.get("created"))
-        .or_else(|| frontmatter.get("pubDate"))
-        .and_then(|v| v.as_str())?;
+/// Fill in each item's `content` from the feed's `item_template` and/or the page's rendered HTML.
+/// Items whose template fails to render keep their plain summary, and come back as warnings, each
+/// with the URL of the page it was for.
+pub async fn render_item_contents(
+    items: &mut [FeedItem],
+    feed_config: &FeedConfig,
+    app_data: &AppData,
+) -> Result<Vec<(String, HugsError)>> {
+    let template = match &feed_config.item_template {
+        Some(path) => Some(load_item_template(path, feed_config, app_data).await?),
+        None => None,
+    };
 
-    parse_date_string(date_str)
-}
+    if template.is_none() && !feed_config.full_content {
+        return Ok(Vec::new());
+    }
 
-/// Parse a date string in various common formats
-fn parse_date_string(s: &str) -> Option<DateTime<Utc>> {
-    // ISO 8601 / RFC 3339 (2024-01-15T10:30:00Z)
-    if let Ok(dt) = DateTime::parse_from_rfc3339(s) {
-        return Some(dt.with_timezone(&Utc));
+    let cache_bust = app_data.cache_bust_function();
+    let mut warnings = Vec::new();
+
+    for item in items.iter_mut() {
+        let page_html = if feed_config.full_content {
+            Some(render_page_body(&item.page, app_data).await?)
+        } else {
+            None
+        };
+
+        let Some((file, source)) = &template else {
+            item.content = page_html.map(|html| xml_text(&html).into_owned());
+            continue;
+        };
+
+        let mut ctx = serde_json::to_value(&item.page.frontmatter).unwrap_or_default();
+        if let serde_json::Value::Object(map) = &mut ctx {
+            map.insert("url".to_string(), item.url.clone().into());
+            map.insert("summary".to_string(), item.summary.clone().into());
+            if let Some(html) = page_html {
+                map.insert("content".to_string(), html.into());
+            }
+        }
+
+        let deps = DepRecorder::default();
+        let render = RenderContext { cache_bust: Some(&cache_bust), ..app_data.render_context(&deps) };
+        match render_template(source, &ctx, &render) {
+            Ok(html) => item.content = Some(xml_text(html.trim()).into_owned()),
+            // A syntax error breaks every item, so there's no point carrying on
+            Err(e) if e.error.kind() == minijinja::ErrorKind::SyntaxError => {
+                return Err(HugsError::feed_item_template(&feed_config.name, file, source, &e));
+            }
+            Err(e) => warnings.push((
+                item.page.url.to_string(),
+                HugsError::feed_item_template_failed(&feed_config.name, item.page.url.as_str(), file, source, &e),
+            )),
+        }
     }
 
-    // YYYY-MM-DD (2024-01-15)
-    if let Ok(nd) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
-        let ndt = nd.and_hms_opt(0, 0, 0)?;
-        return Some(DateTime::from_naive_utc_and_offset(ndt, Utc));
+    Ok(warnings)
+}
+
+/// Read an `item_template`, returning its `_/`-relative display name and contents
+async fn load_item_template(
+    path: &str,
+    feed_config: &FeedConfig,
+    app_data: &AppData,
+) -> Result<(String, String)> {
+    let relative = format!("_/{}", path.trim_start_matches("_/"));
+    let full_path = app_data.site_path.join(&relative);
+
+    if !full_path.is_file() {
+        return Err(HugsError::FeedItemTemplateMissing {
+            feed_name: feed_config.name.clone().into(),
+            path: relative.into(),
+        });
     }
 
-    // YYYY-MM-DD HH:MM:SS (2024-01-15 10:30:00)
-    if let Ok(ndt) = NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S") {
-        return Some(DateTime::from_naive_utc_and_offset(ndt, Utc));
+    let source = tokio::fs::read_to_string(&full_path)
+        .await
+        .map_err(|e| HugsError::FileRead {
+            path: full_path.into(),
+            cause: e,
+        })?;
+
+    Ok((relative, source))
+}
+
+/// The page's own rendered HTML, without the site's header, nav and footer
+async fn render_page_body(page: &PageInfo, app_data: &AppData) -> Result<String> {
+    if let Some(ctx) = DynamicContext::from_page_info(page) {
+        let (_, doc_html, _, _) = resolve_dynamic_doc(&page.file_path, &ctx, app_data, &DepRecorder::default()).await?;
+        return Ok(doc_html);
     }
 
-    console::warn(format!(
-        "couldn't parse date '{}'. Supported: YYYY-MM-DD, YYYY-MM-DDTHH:MM:SSZ, YYYY-MM-DD HH:MM:SS",
-        s
-    ));
-    None
+    let (_, doc_html, _, _) = resolve_path_to_doc(page.url.as_str().trim_start_matches('/'), app_data, &DepRecorder::default())
+        .await?
+        .ok_or_else(|| HugsError::PageResolve {
+            url: page.url.to_string().into(),
+            file_path: page.file_path.clone().into(),
+        })?;
+    Ok(doc_html)
 }
 
-/// Generate RSS 2.0 feed XML
+/// Generate RSS 2.0 feed XML for one page of a feed
 pub fn generate_rss(
     items: &[FeedItem],
+    page: &FeedPage,
     feed_config: &FeedConfig,
     site_metadata: &SiteMetadata,
 ) -> Result<String> {
@@ -160,7 +488,7 @@ pub fn generate_rss(
         .cloned()
         .unwrap_or_default();
 
-    let rss_items: Vec<rss::Item> = items
+    let rss_items: Vec<rss::Item> = items[page.items.clone()]
         .iter()
         .map(|item| {
             let mut builder = ItemBuilder::default();
@@ -177,37 +505,47 @@ pub fn generate_rss(
                 builder.pub_date(Some(date.to_rfc2822()));
             }
 
-            if let Some(summary) = &item.summary {
-                builder.description(Some(summary.clone()));
+            if let Some(description) = item.content.as_ref().or(item.summary.as_ref()) {
+                builder.description(Some(description.clone()));
             }
 
             if let Some(author) = &item.author {
                 builder.author(Some(author.clone()));
             }
 
+            builder.categories(
+                item.categories
+                    .iter()
+                    .map(|term| rss::Category { name: term.clone(), domain: item.category_urls.get(term).cloned() })
+                    .collect::<Vec<_>>(),
+            );
+
             builder.build()
         })
         .collect();
 
+    let links = paging_links(page, base_url, feed_config.paginate);
     let channel = ChannelBuilder::default()
         .title(title.clone())
-        .link(base_url.clone())
+        .link(base_url.to_string())
         .description(description)
         .language(Some(site_metadata.language.clone()))
         .generator(Some("Hugs Static Site Generator".to_string()))
+        .atom_ext((!links.is_empty()).then_some(rss::extension::atom::AtomExtension { links }))
         .items(rss_items)
         .build();
 
     Ok(channel.to_string())
 }
 
-/// Generate Atom feed XML
+/// Generate Atom feed XML for one page of a feed
 pub fn generate_atom(
     items: &[FeedItem],
+    page: &FeedPage,
     feed_config: &FeedConfig,
     site_metadata: &SiteMetadata,
 ) -> Result<String> {
-    use atom_syndication::{Entry, Feed, Generator, Link, Person, Text};
+    use atom_syndication::{Category, Content, Entry, Feed, Generator, Link, Person, Text};
 
     let title = feed_config
         .title
@@ -224,7 +562,7 @@ pub fn generate_atom(
             feed_name: feed_config.name.clone().into(),
         })?;
 
-    let entries: Vec<Entry> = items
+    let entries: Vec<Entry> = items[page.items.clone()]
         .iter()
         .map(|item| {
             let mut entry = Entry::default();
@@ -236,16 +574,28 @@ pub fn generate_atom(
                 ..Default::default()
             }]);
 
-            if let Some(date) = &item.date {
-                entry.set_updated(*date);
+            if let Some(updated) = item.updated.or(item.date) {
+                entry.set_updated(updated);
             } else {
                 entry.set_updated(Utc::now());
             }
 
+            if let Some(date) = &item.date {
+                entry.set_published(Some((*date).into()));
+            }
+
             if let Some(summary) = &item.summary {
                 entry.set_summary(Some(Text::plain(summary)));
             }
 
+            if let Some(content) = &item.content {
+                entry.set_content(Some(Content {
+                    value: Some(content.clone()),
+                    content_type: Some("html".to_string()),
+                    ..Default::default()
+                }));
+            }
+
             if let Some(author) = &item.author {
                 entry.set_authors(vec![Person {
                     name: author.clone(),
@@ -253,19 +603,34 @@ pub fn generate_atom(
                 }]);
             }
 
+            entry.set_categories(
+                item.categories
+                    .iter()
+                    .map(|term| Category { term: term.clone(), scheme: item.category_urls.get(term).cloned(), label: None })
+                    .collect::<Vec<_>>(),
+            );
+
             entry
         })
         .collect();
 
     let mut feed = Feed::default();
     feed.set_title(Text::plain(title));
-    feed.set_id(base_url);
-    feed.set_links(vec![Link {
-        href: base_url.clone(),
+    feed.set_id(base_url.to_string());
+    let mut links = vec![Link {
+        href: base_url.to_string(),
         rel: "alternate".to_string(),
         ..Default::default()
-    }]);
-    feed.set_updated(Utc::now());
+    }];
+    links.extend(paging_links(page, base_url, feed_config.paginate));
+    feed.set_links(links);
+    // The feed was last updated when its newest entry was, which keeps rebuilds reproducible
+    let updated = items[page.items.clone()]
+        .iter()
+        .filter_map(|item| item.updated.or(item.date))
+        .max()
+        .unwrap_or_else(Utc::now);
+    feed.set_updated(updated);
     feed.set_generator(Some(Generator {
         value: "Hugs Static Site Generator".to_string(),
         ..Default::default()
@@ -274,3 +639,301 @@ pub fn generate_atom(
 
     Ok(feed.to_string())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::url::SiteUrl;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_feed_output_names() {
+        let config = |outputs: &str| {
+            let source = format!("[[feeds]]\nname = \"blog\"\nsource = \"/blog\"\n{}", outputs);
+            let mut config: SiteConfig = toml::from_str(&source).unwrap();
+            config.source = source;
+            config
+        };
+
+        assert!(validate_feed_outputs(&config("output_rss = \"rss.xml\"\noutput_atom = \"atom.xml\"\n")).is_ok());
+        // Sharing a file is only a warning
+        assert!(validate_feed_outputs(&config("output_rss = \"feed.xml\"\noutput_atom = \"feed.xml\"\n")).is_ok());
+
+        for (filename, reason) in [("../rss.xml", "leaves the output folder"), ("feeds/rss.xml", "in a folder"), ("", "empty")] {
+            let config = config(&format!("output_atom = \"{}\"\n", filename));
+            let Err(HugsError::FeedOutputName { reason: got, span, .. }) = validate_feed_outputs(&config) else {
+                panic!("{filename:?} should be rejected");
+            };
+            assert!(got.contains(reason), "{filename:?}: {got}");
+            assert_eq!(&config.source[span.offset()..span.offset() + span.len()], format!("\"{}\"", filename));
+        }
+    }
+
+    #[test]
+    fn test_shared_outputs_include_every_page_of_a_feed() {
+        let shared = |feeds: &str| shared_outputs(&toml::from_str(feeds).unwrap());
+        let feed = |name: &str, rest: &str| format!("[[feeds]]\nname = \"{}\"\nsource = \"/{}\"\n{}\n", name, name, rest);
+
+        assert_eq!(
+            shared(&feed("blog", "output_rss = \"feed.xml\"\noutput_atom = \"feed.xml\"")),
+            ["the blog feed writes both its RSS and Atom to feed.xml, so only the Atom one is kept"]
+        );
+        assert_eq!(
+            shared(&(feed("blog", "output_rss = \"rss.xml\"\npaginate = true") + &feed("news", "output_rss = \"rss-page3.xml\""))),
+            ["the blog and news feeds both write rss-page3.xml, so only the news feed is kept"]
+        );
+        assert_eq!(
+            shared(&(feed("news", "output_atom = \"blog-page2.atom\"") + &feed("blog", "output_atom = \"blog.atom\"\npaginate = true"))),
+            ["the news and blog feeds both write blog-page2.atom, so only the blog feed is kept"]
+        );
+
+        // Without pagination there's only the first file, and page numbers start at 2
+        assert!(shared(&(feed("blog", "output_rss = \"rss.xml\"") + &feed("news", "output_rss = \"rss-page2.xml\""))).is_empty());
+        for filename in ["rss-page1.xml", "rss-page02.xml", "rss-page2.atom", "rss-pagex.xml"] {
+            assert_eq!(page_number(filename, "rss.xml"), None, "{filename}");
+        }
+        assert_eq!(page_number("feed-page12", "feed"), Some(12));
+    }
+
+    #[test]
+    fn test_item_template_paths() {
+        let config = |path: &str| {
+            let source = format!("[[feeds]]\nname = \"blog\"\nsource = \"/blog\"\nitem_template = '{}'\n", path);
+            let mut config: SiteConfig = toml::from_str(&source).unwrap();
+            config.source = source;
+            config
+        };
+
+        assert!(validate_feed_outputs(&config("feed-item.md")).is_ok());
+        assert!(validate_feed_outputs(&config("_/feeds/item.md")).is_ok());
+
+        for (path, reason) in [
+            ("../secrets.md", "leaves"),
+            ("feeds/../../secrets.md", "leaves"),
+            ("..\\secrets.md", "leaves"),
+            ("/etc/passwd", "absolute"),
+            ("C:\\notes.md", "absolute"),
+            ("", "empty"),
+        ] {
+            let Err(HugsError::FeedItemTemplatePath { reason: got, .. }) = validate_feed_outputs(&config(path)) else {
+                panic!("{path:?} should be rejected");
+            };
+            assert!(got.contains(reason), "{path:?}: {got}");
+        }
+    }
+
+    fn item(n: usize) -> FeedItem {
+        let url = format!("/blog/post-{}", n);
+        FeedItem {
+            title: format!("Post {}", n),
+            url: format!("https://example.com{}", url),
+            date: DateTime::from_timestamp(1_700_000_000 - n as i64 * 86_400, 0),
+            updated: None,
+            summary: None,
+            content: None,
+            author: None,
+            categories: Vec::new(),
+            category_urls: BTreeMap::new(),
+            page: PageInfo {
+                url: SiteUrl::parse(&url),
+                file_path: format!("blog/post-{}.md", n),
+                frontmatter: serde_yaml::Value::Null,
+            },
+        }
+    }
+
+    /// (self, previous, next) hrefs of each page
+    fn chain(links: &[Vec<atom_syndication::Link>]) -> Vec<(String, Option<String>, Option<String>)> {
+        links
+            .iter()
+            .map(|links| {
+                let href = |rel: &str| links.iter().find(|l| l.rel == rel).map(|l| l.href.clone());
+                (href("self").expect("every page links to itself"), href("previous"), href("next"))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_page_filenames() {
+        assert_eq!(page_filename("rss.xml", 1), "rss.xml");
+        assert_eq!(page_filename("rss.xml", 2), "rss-page2.xml");
+        assert_eq!(page_filename("feeds/blog.atom", 3), "feeds/blog-page3.atom");
+        assert_eq!(page_filename("v1.0/feed", 2), "v1.0/feed-page2");
+    }
+
+    #[test]
+    fn test_paginated_feeds_link_into_a_chain() {
+        let feed_config: FeedConfig =
+            toml::from_str("name = \"blog\"\nsource = \"/blog\"\nlimit = 2\npaginate = true").unwrap();
+        let site: SiteMetadata = toml::from_str("title = \"Site\"\nurl = \"https://example.com/\"").unwrap();
+        let items: Vec<FeedItem> = (1..=5).map(item).collect();
+
+        let pages = feed_pages("rss.xml", items.len(), &feed_config);
+        let ranges: Vec<_> = pages.iter().map(|p| p.items.clone()).collect();
+        assert_eq!(ranges, vec![0..2, 2..4, 4..5]);
+
+        let rss_links: Vec<_> = pages
+            .iter()
+            .map(|page| {
+                let xml = generate_rss(&items, page, &feed_config, &site).unwrap();
+                let channel = rss::Channel::from_str(&xml).unwrap();
+                assert_eq!(channel.items().len(), page.items.len());
+                channel.atom_ext().unwrap().links().to_vec()
+            })
+            .collect();
+        let atom_links: Vec<_> = pages
+            .iter()
+            .map(|page| {
+                let xml = generate_atom(&items, page, &feed_config, &site).unwrap();
+                atom_syndication::Feed::from_str(&xml).unwrap().links().to_vec()
+            })
+            .collect();
+
+        for chain in [chain(&rss_links), chain(&atom_links)] {
+            let selves: Vec<&str> = chain.iter().map(|(this, _, _)| this.as_str()).collect();
+            assert_eq!(
+                selves,
+                vec!["https://example.com/rss.xml", "https://example.com/rss-page2.xml", "https://example.com/rss-page3.xml"]
+            );
+            for pair in chain.windows(2) {
+                assert_eq!(pair[0].2.as_ref(), Some(&pair[1].0));
+                assert_eq!(pair[1].1.as_ref(), Some(&pair[0].0));
+            }
+            assert_eq!(chain[0].1, None);
+            assert_eq!(chain.last().unwrap().2, None);
+        }
+    }
+
+    fn tagged_page(url: &str, file_path: &str, frontmatter: &str) -> PageInfo {
+        PageInfo { url: SiteUrl::parse(url), file_path: file_path.to_string(), frontmatter: serde_yaml::from_str(frontmatter).unwrap() }
+    }
+
+    #[test]
+    fn test_page_categories_take_single_values_and_lists() {
+        let categories = |frontmatter: &str| page_categories(&serde_yaml::from_str(frontmatter).unwrap());
+
+        assert_eq!(categories("tags: rust"), ["rust"]);
+        assert_eq!(categories("tags: [rust, web]\ncategories: [web, 2024, ' ']"), ["rust", "web", "2024"]);
+        assert_eq!(categories("categories: Notes"), ["Notes"]);
+        assert!(categories("title: Untagged").is_empty());
+    }
+
+    #[test]
+    fn test_items_have_categories_in_both_formats() {
+        let config: SiteConfig = toml::from_str(
+            "[site]\ntitle = \"Site\"\nurl = \"https://example.com\"\n\n[[feeds]]\nname = \"blog\"\nsource = \"/blog\"\ncategory_limit = 3\ncategory_pages = \"blog/tags/[tag].md\"",
+        )
+        .unwrap();
+        let feed_config = &config.feeds[0];
+        let pages = vec![
+            tagged_page("/blog/post", "blog/post.md", "title: Post\ntags: [rust, web, cli, extra]"),
+            tagged_page("/blog/note", "blog/note.md", "title: Note\ntags: rust"),
+            tagged_page("/blog/tags/rust", "blog/tags/[tag].md", "tag: rust"),
+        ];
+
+        let items: Vec<FeedItem> = collect_feed_items(&pages, feed_config, &config)
+            .into_iter()
+            .filter(|item| item.page.file_path != "blog/tags/[tag].md")
+            .collect();
+        assert_eq!(items[0].categories, ["rust", "web", "cli"]);
+        assert_eq!(items[1].categories, ["rust"]);
+
+        let page = &feed_pages("rss.xml", items.len(), feed_config)[0];
+        let rss = rss::Channel::from_str(&generate_rss(&items, page, feed_config, &config.site).unwrap()).unwrap();
+        let rss_categories: Vec<(&str, Option<&str>)> =
+            rss.items()[0].categories().iter().map(|c| (c.name(), c.domain())).collect();
+        assert_eq!(rss_categories, [("rust", Some("https://example.com/blog/tags/rust")), ("web", None), ("cli", None)]);
+
+        let atom = atom_syndication::Feed::from_str(&generate_atom(&items, page, feed_config, &config.site).unwrap()).unwrap();
+        let atom_categories: Vec<(&str, Option<&str>)> =
+            atom.entries()[1].categories().iter().map(|c| (c.term(), c.scheme())).collect();
+        assert_eq!(atom_categories, [("rust", Some("https://example.com/blog/tags/rust"))]);
+    }
+
+    #[test]
+    fn test_site_under_a_path_agrees_on_absolute_urls() {
+        let config: SiteConfig = toml::from_str(
+            "[site]\ntitle = \"Site\"\nurl = \"https://example.com/blog/\"\n\n[[feeds]]\nname = \"posts\"\nsource = \"/posts\"",
+        )
+        .unwrap();
+        let feed_config = &config.feeds[0];
+        let pages = vec![tagged_page("/posts/hello", "posts/hello.md", "title: Hello")];
+
+        let seo = crate::run::build_seo_context(&serde_yaml::from_str("title: Hello").unwrap(), &pages[0].url, &config.site, &Default::default());
+        assert_eq!(seo.canonical_url, "https://example.com/blog/posts/hello");
+        assert_eq!(seo.og_url, seo.canonical_url);
+
+        let dates = DateParser::new(&config);
+        let sitemap = crate::sitemap::generate_sitemap(&pages, &config.site, &dates, None, None).unwrap();
+        assert!(sitemap.contains("<loc>https://example.com/blog/posts/hello/</loc>"), "{sitemap}");
+
+        let items = collect_feed_items(&pages, feed_config, &config);
+        let page = &feed_pages("rss.xml", items.len(), feed_config)[0];
+        let rss = rss::Channel::from_str(&generate_rss(&items, page, feed_config, &config.site).unwrap()).unwrap();
+        assert_eq!(rss.link(), "https://example.com/blog");
+        assert_eq!(rss.items()[0].link(), Some(seo.canonical_url.as_str()));
+
+        let atom = atom_syndication::Feed::from_str(&generate_atom(&items, page, feed_config, &config.site).unwrap()).unwrap();
+        assert_eq!(atom.id(), "https://example.com/blog");
+        assert_eq!(atom.entries()[0].id(), seo.canonical_url);
+    }
+
+    #[test]
+    fn test_frontmatter_beats_the_feed_source() {
+        let config: SiteConfig = toml::from_str(
+            "[site]\ntitle = \"Site\"\nurl = \"https://example.com\"\n\n[[feeds]]\nname = \"blog\"\nsource = \"/blog\"",
+        )
+        .unwrap();
+        let pages = vec![
+            tagged_page("/blog/post", "blog/post.md", "title: Post\ndate: 2024-03-01"),
+            tagged_page("/blog/kept", "blog/kept.md", "title: Kept\nfeed: true\ndate: 2024-02-01"),
+            tagged_page("/blog/colophon", "blog/colophon.md", "title: Colophon\nfeed: false"),
+            tagged_page("/talks/launch", "talks/launch.md", "title: Launch\nfeed: true\ndate: 2024-01-01"),
+            tagged_page("/about", "about.md", "title: About"),
+            tagged_page("/secret", "secret.md", "title: Secret\nfeed: true\nprotected: true"),
+        ];
+
+        let titles: Vec<String> =
+            collect_feed_items(&pages, &config.feeds[0], &config).into_iter().map(|item| item.title).collect();
+        assert_eq!(titles, ["Post", "Kept", "Launch"]);
+    }
+
+    #[test]
+    fn test_control_characters_are_left_out_of_feeds() {
+        let config: SiteConfig = toml::from_str(
+            "[site]\ntitle = \"Site\"\nurl = \"https://example.com\"\n\n[[feeds]]\nname = \"blog\"\nsource = \"/blog\"",
+        )
+        .unwrap();
+        let feed_config = &config.feeds[0];
+        let pages = vec![tagged_page(
+            "/blog/post",
+            "blog/post.md",
+            "title: \"Back\\bspace\"\ndescription: \"Bell\\a\"\ntags: [\"r\\bust\"]",
+        )];
+
+        let items = collect_feed_items(&pages, feed_config, &config);
+        assert_eq!((items[0].title.as_str(), items[0].summary.as_deref()), ("Backspace", Some("Bell")));
+        let page = &feed_pages("rss.xml", items.len(), feed_config)[0];
+        for xml in [
+            generate_rss(&items, page, feed_config, &config.site).unwrap(),
+            generate_atom(&items, page, feed_config, &config.site).unwrap(),
+        ] {
+            assert_eq!(crate::xml_check::check_well_formed(&xml), None, "{xml}");
+            assert!(!xml.contains('\u{8}') && xml.contains("Backspace") && xml.contains("rust"), "{xml}");
+        }
+        let rss = rss::Channel::from_str(&generate_rss(&items, page, feed_config, &config.site).unwrap()).unwrap();
+        assert_eq!(rss.items()[0].title(), Some("Backspace"));
+    }
+
+    #[test]
+    fn test_unpaginated_feeds_are_one_file_without_paging_links() {
+        let feed_config: FeedConfig = toml::from_str("name = \"blog\"\nsource = \"/blog\"\nlimit = 2").unwrap();
+        let site: SiteMetadata = toml::from_str("title = \"Site\"\nurl = \"https://example.com\"").unwrap();
+        let items: Vec<FeedItem> = (1..=2).map(item).collect();
+
+        let pages = feed_pages("feed.xml", items.len(), &feed_config);
+        assert_eq!(pages.len(), 1);
+        let xml = generate_rss(&items, &pages[0], &feed_config, &site).unwrap();
+        assert!(rss::Channel::from_str(&xml).unwrap().atom_ext().is_none());
+    }
+}
@@ -0,0 +1,292 @@
+//! Pages that haven't changed in a while, for `[build] freshness_report` and
+//! `hugs build --report freshness`.
+//!
+//! A page's age comes from its `updated` date, or its `date` if it was never updated, as the scan
+//! parsed them. When the site is in a git repo, the last commit to the page's file counts too, and
+//! whichever of the two is later wins. Pages with none of these can't be placed and are left out. Each stale page goes in the
+//! oldest of `[build] freshness_buckets` it's past, so a page three years old is listed under
+//! "2 years" and not again under "6 months". A page that doesn't go stale, like a license or a
+//! glossary, can say `evergreen: true` in its frontmatter.
+//!
+//! A dynamic page is listed once for its `[param].md` file, since all its pages share the file's
+//! dates and the file is what needs updating.
+
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::process::Command;
+
+use chrono::{DateTime, Months, Utc};
+use serde_json::json;
+
+use crate::error::HugsError;
+use crate::run::{DynamicContext, PageInfo};
+
+/// A page past one of the thresholds
+pub struct StalePage<'a> {
+    /// The page's URL, or none for a dynamic page, which makes many
+    pub url: Option<String>,
+    pub file: &'a str,
+    /// When the page last changed
+    pub last_modified: DateTime<Utc>,
+}
+
+/// The pages past one threshold and not the next
+pub struct StaleBucket<'a> {
+    pub months: u32,
+    /// Oldest first
+    pub pages: Vec<StalePage<'a>>,
+}
+
+/// When each file under `site_path` was last committed, by its path from the site with `/` between
+/// the parts. Empty when the site isn't in a git repo, or git can't be run.
+pub fn git_commit_dates(site_path: &Path) -> HashMap<String, DateTime<Utc>> {
+    let mut dates = HashMap::new();
+    let output = Command::new("git")
+        .args(["-c", "core.quotePath=false", "log", "--format=%x00%ct", "--name-only", "--relative", "--", "."])
+        .current_dir(site_path)
+        .output();
+    let Some(output) = output.ok().filter(|output| output.status.success()) else {
+        return dates;
+    };
+
+    // Newest commit first, so the first time a file comes up is the last time it changed
+    let mut committed = None;
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        if let Some(timestamp) = line.strip_prefix('\0') {
+            committed = timestamp.parse().ok().and_then(|seconds| DateTime::from_timestamp(seconds, 0));
+        } else if let Some(committed) = committed
+            && !line.is_empty()
+        {
+            dates.entry(line.to_string()).or_insert(committed);
+        }
+    }
+    dates
+}
+
+/// Pages last changed more than each of `buckets` months before `now`, oldest bucket first and
+/// leaving out the empty ones. `commit_dates` are from [`git_commit_dates`].
+pub fn find_stale_pages<'a>(
+    pages: &'a [PageInfo],
+    commit_dates: &HashMap<String, DateTime<Utc>>,
+    buckets: &[u32],
+    now: DateTime<Utc>,
+) -> Vec<StaleBucket<'a>> {
+    let mut months: Vec<u32> = buckets.iter().copied().filter(|&months| months > 0).collect();
+    months.sort_unstable_by(|a, b| b.cmp(a));
+    months.dedup();
+
+    let mut groups: Vec<StaleBucket> = months.iter().map(|&months| StaleBucket { months, pages: Vec::new() }).collect();
+    let mut seen_files: HashSet<&str> = HashSet::new();
+    for page in pages {
+        if evergreen(page) || !seen_files.insert(page.file_path.as_str()) {
+            continue;
+        }
+        let committed = commit_dates.get(&page.file_path).copied();
+        let Some(last_modified) = last_modified(page).max(committed) else { continue };
+        let Some(group) = groups.iter_mut().find(|group| {
+            now.checked_sub_months(Months::new(group.months)).is_some_and(|cutoff| last_modified < cutoff)
+        }) else {
+            continue;
+        };
+
+        let url = DynamicContext::from_page_info(page).is_none().then(|| page.url.to_string());
+        group.pages.push(StalePage { url, file: &page.file_path, last_modified });
+    }
+
+    for group in &mut groups {
+        group.pages.sort_by(|a, b| a.last_modified.cmp(&b.last_modified).then(a.file.cmp(b.file)));
+    }
+    groups.retain(|group| !group.pages.is_empty());
+    groups
+}
+
+/// Whether the page says it doesn't go out of date
+fn evergreen(page: &PageInfo) -> bool {
+    page.frontmatter.get("evergreen").and_then(|value| value.as_bool()).unwrap_or(false)
+}
+
+/// When the page says it last changed, from the dates the scan parsed
+fn last_modified(page: &PageInfo) -> Option<DateTime<Utc>> {
+    ["updated_parsed", "date_parsed"]
+        .iter()
+        .find_map(|key| page.frontmatter.get(key).and_then(|value| value.as_str()))
+        .and_then(|date| DateTime::parse_from_rfc3339(date).ok())
+        .map(|date| date.with_timezone(&Utc))
+}
+
+/// A threshold as it reads in a sentence, like "6 months" or "2 years"
+pub fn age_label(months: u32) -> String {
+    match (months % 12, months / 12) {
+        (0, 1) => String::from("1 year"),
+        (0, years) => format!("{} years", years),
+        (_, _) if months == 1 => String::from("1 month"),
+        _ => format!("{} months", months),
+    }
+}
+
+/// Pages named in a warning before the rest are just counted
+const MAX_LISTED_PAGES: usize = 10;
+
+/// A warning for each bucket with stale pages in it
+pub fn stale_page_warnings(groups: &[StaleBucket]) -> Vec<HugsError> {
+    groups
+        .iter()
+        .map(|group| {
+            let names: Vec<String> = group
+                .pages
+                .iter()
+                .map(|page| format!("{} ({})", page.file, page.last_modified.format("%Y-%m-%d")))
+                .collect();
+            let mut listed = names[..names.len().min(MAX_LISTED_PAGES)].join(", ");
+            if names.len() > MAX_LISTED_PAGES {
+                listed.push_str(&format!(" and {} more", names.len() - MAX_LISTED_PAGES));
+            }
+
+            let count = group.pages.len();
+            HugsError::StalePages {
+                pages: format!("{} {}", count, if count == 1 { "page" } else { "pages" }),
+                age: age_label(group.months),
+                help_text: format!(
+                    "Last changed: {}. Check they're still right and set `updated:` in their frontmatter, or `evergreen: true` if they don't go out of date.",
+                    listed
+                ),
+            }
+        })
+        .collect()
+}
+
+/// The stale pages as JSON entries, for `hugs build --format json`
+pub fn stale_pages_json(groups: &[StaleBucket]) -> serde_json::Value {
+    groups
+        .iter()
+        .map(|group| {
+            let pages: Vec<serde_json::Value> = group
+                .pages
+                .iter()
+                .map(|page| json!({ "url": page.url, "file": page.file, "last_modified": page.last_modified.format("%Y-%m-%d").to_string() }))
+                .collect();
+            json!({ "older_than": age_label(group.months), "months": group.months, "pages": pages })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::url::SiteUrl;
+
+    fn page(url: &str, file: &str, frontmatter: &str) -> PageInfo {
+        PageInfo { url: SiteUrl::parse(url), file_path: file.to_string(), frontmatter: serde_yaml::from_str(frontmatter).unwrap() }
+    }
+
+    fn files<'a>(group: &StaleBucket<'a>) -> Vec<&'a str> {
+        group.pages.iter().map(|page| page.file).collect()
+    }
+
+    #[test]
+    fn test_pages_go_in_the_oldest_bucket_they_are_past() {
+        let now = DateTime::parse_from_rfc3339("2026-10-16T12:00:00Z").unwrap().with_timezone(&Utc);
+        let tag = |value: &str| {
+            page(
+                &format!("/tags/{}", value),
+                "tags/[tag].md",
+                &format!("tag: {}\ndate_parsed: 2023-01-01T00:00:00+00:00", value),
+            )
+        };
+        let pages = vec![
+            page("/fresh", "fresh.md", "date_parsed: 2026-09-01T00:00:00+00:00"),
+            page("/months", "months.md", "date_parsed: 2026-03-01T00:00:00+00:00"),
+            page("/year", "year.md", "date_parsed: 2025-06-01T00:00:00+00:00"),
+            // Updating an old page makes it fresh again
+            page("/updated", "updated.md", "date_parsed: 2019-01-01T00:00:00+00:00\nupdated_parsed: 2026-10-01T00:00:00+00:00"),
+            page("/ancient", "ancient.md", "date_parsed: 2018-05-05T00:00:00+00:00"),
+            page("/old", "old.md", "date_parsed: 2022-02-02T00:00:00+00:00"),
+            page("/license", "license.md", "date_parsed: 2015-01-01T00:00:00+00:00\nevergreen: true"),
+            page("/undated", "undated.md", "title: Undated"),
+            tag("rust"),
+            tag("web"),
+        ];
+
+        let groups = find_stale_pages(&pages, &HashMap::new(), &[6, 12, 24], now);
+        let summary: Vec<(u32, Vec<&str>)> = groups.iter().map(|group| (group.months, files(group))).collect();
+        assert_eq!(
+            summary,
+            vec![(24, vec!["ancient.md", "old.md", "tags/[tag].md"]), (12, vec!["year.md"]), (6, vec!["months.md"])]
+        );
+        assert_eq!(groups[0].pages[0].url.as_deref(), Some("/ancient"));
+        assert_eq!(groups[0].pages[2].url, None);
+
+        // Buckets may come in any order, and the empty ones are left out
+        let groups = find_stale_pages(&pages, &HashMap::new(), &[120, 12, 12], now);
+        assert_eq!(groups.len(), 1);
+        assert_eq!(files(&groups[0]), ["ancient.md", "old.md", "tags/[tag].md", "year.md"]);
+    }
+
+    #[test]
+    fn test_commits_count_as_changes() {
+        let repo = tempfile::tempdir().unwrap();
+        let site = repo.path().join("site");
+        std::fs::create_dir_all(site.join("docs")).unwrap();
+        assert!(git_commit_dates(&site).is_empty());
+
+        let git = |args: &[&str], date: &str| {
+            let output = Command::new("git")
+                .args(["-c", "user.name=Test", "-c", "user.email=test@example.com"])
+                .args(args)
+                .env("GIT_AUTHOR_DATE", date)
+                .env("GIT_COMMITTER_DATE", date)
+                .current_dir(repo.path())
+                .output();
+            output.map(|output| output.status.success()).unwrap_or(false)
+        };
+        if !git(&["init", "--quiet"], "") {
+            // No git on this machine, so there's nothing to check against
+            return;
+        }
+        let commit = |file: &str, date: &str| {
+            std::fs::write(repo.path().join(file), date).unwrap();
+            assert!(git(&["add", "--all"], date));
+            assert!(git(&["commit", "--quiet", "--message", file], date));
+        };
+        commit("site/docs/setup.md", "2020-01-01T00:00:00Z");
+        commit("site/docs/über.md", "2021-01-01T00:00:00Z");
+        commit("site/docs/setup.md", "2026-10-01T00:00:00Z");
+        commit("README.md", "2026-10-02T00:00:00Z");
+
+        let dates = git_commit_dates(&site);
+        let day = |date: &str| DateTime::parse_from_rfc3339(date).unwrap().with_timezone(&Utc);
+        assert_eq!(dates.len(), 2);
+        assert_eq!(dates["docs/setup.md"], day("2026-10-01T00:00:00Z"));
+        assert_eq!(dates["docs/über.md"], day("2021-01-01T00:00:00Z"));
+
+        // The later of the frontmatter and the last commit wins
+        let pages = vec![
+            page("/docs/setup", "docs/setup.md", "date_parsed: 2019-01-01T00:00:00+00:00"),
+            page("/docs/uber", "docs/über.md", "title: Undated"),
+            page("/docs/new", "docs/new.md", "date_parsed: 2019-01-01T00:00:00+00:00"),
+            page("/docs/later", "docs/later.md", "updated_parsed: 2026-10-01T00:00:00+00:00"),
+        ];
+        let groups = find_stale_pages(&pages, &dates, &[12], day("2026-10-16T00:00:00Z"));
+        assert_eq!(files(&groups[0]), ["docs/new.md", "docs/über.md"]);
+        assert_eq!(groups[0].pages[1].last_modified, day("2021-01-01T00:00:00Z"));
+    }
+
+    #[test]
+    fn test_reports_name_the_age_of_each_bucket() {
+        assert_eq!(age_label(6), "6 months");
+        assert_eq!(age_label(12), "1 year");
+        assert_eq!(age_label(24), "2 years");
+        assert_eq!(age_label(18), "18 months");
+        assert_eq!(age_label(1), "1 month");
+
+        let now = DateTime::parse_from_rfc3339("2026-10-16T00:00:00Z").unwrap().with_timezone(&Utc);
+        let pages = vec![page("/old", "old.md", "date_parsed: 2020-01-01T00:00:00+00:00")];
+        let groups = find_stale_pages(&pages, &HashMap::new(), &[24], now);
+        assert_eq!(
+            stale_pages_json(&groups),
+            json!([{ "older_than": "2 years", "months": 24, "pages": [{ "url": "/old", "file": "old.md", "last_modified": "2020-01-01" }] }])
+        );
+        let warning = &stale_page_warnings(&groups)[0];
+        assert_eq!(warning.to_string(), "1 page not changed in over 2 years");
+    }
+}
@@ -0,0 +1,103 @@
+//! `[build] respect_gitignore`: files git ignores stay out of the site, as pages and as static
+//! files, in `hugs dev` and `hugs build` alike, and changing one doesn't reload `hugs dev`.
+//!
+//! What's ignored comes from walking the site with the `ignore` crate, so `.gitignore` files in
+//! any folder (and above the site, in the rest of the repository), `.git/info/exclude` and git's
+//! global excludes all count, the way git reads them. The site doesn't have to be a repository.
+//!
+//! `_/` is never ignored. Its files are how the site is put together, and a site that ignores
+//! generated CSS there still needs it loaded. This composes with `[assets] exclude`: a file has
+//! to get past both to be published.
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use walkdir::WalkDir;
+
+/// The files and folders in a site that git ignores
+#[derive(Debug, Clone, Default)]
+pub struct GitIgnored {
+    /// Absolute paths. A folder here covers everything in it, including files made after the scan.
+    paths: Arc<HashSet<PathBuf>>,
+}
+
+impl GitIgnored {
+    /// Find what git ignores in the site at `site_path`
+    pub fn scan(site_path: &Path) -> Self {
+        let mut visible: HashSet<PathBuf> = HashSet::new();
+        let walker = ignore::WalkBuilder::new(site_path)
+            .hidden(false)
+            .ignore(false)
+            .parents(true)
+            .require_git(false)
+            .filter_entry(|entry| entry.file_name() != ".git")
+            .build();
+        visible.extend(walker.filter_map(|entry| entry.ok()).map(|entry| entry.into_path()));
+
+        let mut paths = HashSet::new();
+        let mut entries = WalkDir::new(site_path).min_depth(1).into_iter();
+        while let Some(Ok(entry)) = entries.next() {
+            let top_level_site_folder = entry.depth() == 1 && entry.file_name() == "_";
+            if top_level_site_folder || entry.file_name() == ".git" {
+                if entry.file_type().is_dir() {
+                    entries.skip_current_dir();
+                }
+                continue;
+            }
+            if !visible.contains(entry.path()) {
+                // Everything inside an ignored folder is ignored with it
+                if entry.file_type().is_dir() {
+                    entries.skip_current_dir();
+                }
+                paths.insert(entry.into_path());
+            }
+        }
+        Self { paths: Arc::new(paths) }
+    }
+
+    /// Whether git ignores `path`, or a folder it's in
+    pub fn contains(&self, path: &Path) -> bool {
+        !self.paths.is_empty() && path.ancestors().any(|ancestor| self.paths.contains(ancestor))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nested_gitignores_and_the_site_folder() {
+        let site = tempfile::tempdir().unwrap();
+        for (file, content) in [
+            (".gitignore", "*.draft.md\ntmp/\n_/generated.css\n"),
+            ("index.md", ""),
+            ("post.draft.md", ""),
+            ("tmp/cache.json", ""),
+            ("blog/.gitignore", "private.md\n!keep.draft.md\n"),
+            ("blog/private.md", ""),
+            ("blog/public.md", ""),
+            ("blog/keep.draft.md", ""),
+            ("docs/private.md", ""),
+            ("_/generated.css", ""),
+            (".well-known/security.txt", ""),
+        ] {
+            let path = site.path().join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        }
+
+        let ignored = GitIgnored::scan(site.path());
+        let is_ignored = |file: &str| ignored.contains(&site.path().join(file));
+        for file in ["post.draft.md", "tmp/cache.json", "tmp/made-later.json", "blog/private.md"] {
+            assert!(is_ignored(file), "{file} should be ignored");
+        }
+        // A folder's .gitignore only covers that folder, and can let a file back in
+        for file in ["index.md", "blog/public.md", "blog/keep.draft.md", "docs/private.md", ".well-known/security.txt"] {
+            assert!(!is_ignored(file), "{file} shouldn't be ignored");
+        }
+        assert!(!is_ignored("_/generated.css"));
+
+        assert!(!GitIgnored::default().contains(&site.path().join("post.draft.md")));
+    }
+}
@@ -0,0 +1,257 @@
+//! `[hosting] auto_preload_headers`: a rule in `_headers` for every page, preloading the
+//! stylesheets it links and the fonts those stylesheets (or the page itself) load. Hosts that send
+//! early hints, like Cloudflare Pages, pass these on before the page is even ready, so the browser
+//! can start fetching them right away.
+//!
+//! A `_headers` file among the site's static files is published as usual, and the page rules are
+//! added after it.
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+use crate::assets::{AssetRoots, skip_reason};
+use crate::config::{AssetsConfig, HostingProvider};
+use crate::error::{HugsError, Result};
+use crate::inline::stylesheet_fonts;
+use crate::output_scan::ScannedPage;
+use crate::text_output::write_text_output_atomically;
+
+/// File name of the headers file, at the root of the output
+pub const HEADERS_FILE: &str = "_headers";
+
+/// Preloads in one page's rule. Past this, the browser is better off finding the rest in the page.
+const MAX_PRELOADS_PER_PAGE: usize = 8;
+
+/// Heads the rules I add, so they're easy to tell from the site's own
+const GENERATED_COMMENT: &str = "# Preloads for each page, added by hugs";
+
+#[derive(Debug, Clone, PartialEq)]
+enum Preload {
+    Style(String),
+    Font(String),
+}
+
+impl Preload {
+    fn header(&self) -> String {
+        match self {
+            Preload::Style(path) => format!("Link: <{}>; rel=preload; as=style", path),
+            // Fonts are always fetched in CORS mode, so the preload has to be too or it's wasted
+            Preload::Font(path) => format!("Link: <{}>; rel=preload; as=font; crossorigin", path),
+        }
+    }
+}
+
+/// The preloads of each page that loads any stylesheets or fonts, by URL. Stylesheets are read
+/// from `output_path` for the fonts they load.
+fn preload_rules(scanned: &[ScannedPage], output_path: &Path) -> BTreeMap<String, Vec<Preload>> {
+    let mut fonts_of: HashMap<&str, Vec<String>> = HashMap::new();
+    let mut rules: BTreeMap<String, Vec<Preload>> = BTreeMap::new();
+
+    for scan in scanned {
+        let mut preloads: Vec<Preload> = Vec::new();
+        for stylesheet in &scan.stylesheets {
+            preloads.push(Preload::Style(stylesheet.clone()));
+        }
+        for stylesheet in &scan.stylesheets {
+            let fonts = fonts_of.entry(stylesheet.as_str()).or_insert_with(|| {
+                std::fs::read_to_string(output_path.join(stylesheet.trim_start_matches('/')))
+                    .map(|css| stylesheet_fonts(&css, stylesheet))
+                    .unwrap_or_default()
+            });
+            preloads.extend(fonts.iter().cloned().map(Preload::Font));
+        }
+        preloads.extend(scan.preloaded_fonts.iter().cloned().map(Preload::Font));
+
+        let rule = rules.entry(scan.page.url.to_string()).or_default();
+        for preload in preloads {
+            if rule.len() < MAX_PRELOADS_PER_PAGE && !rule.contains(&preload) {
+                rule.push(preload);
+            }
+        }
+    }
+
+    rules.retain(|_, preloads| !preloads.is_empty());
+    rules
+}
+
+/// The site's own `_headers` (if any) followed by a rule for each page
+fn render_headers(existing: Option<&str>, rules: &BTreeMap<String, Vec<Preload>>) -> String {
+    let mut out = String::new();
+    if let Some(existing) = existing.map(str::trim_end).filter(|existing| !existing.is_empty()) {
+        out.push_str(existing);
+        out.push_str("\n\n");
+    }
+
+    out.push_str(GENERATED_COMMENT);
+    out.push('\n');
+    for (path, preloads) in rules {
+        out.push_str(path);
+        out.push('\n');
+        for preload in preloads {
+            out.push_str("  ");
+            out.push_str(&preload.header());
+            out.push('\n');
+        }
+    }
+    out
+}
+
+/// Path rules in a `_headers` file: unindented lines that aren't comments
+fn count_rules(headers: &str) -> usize {
+    headers
+        .lines()
+        .filter(|line| !line.is_empty() && !line.starts_with([' ', '\t', '#']))
+        .count()
+}
+
+/// Write `_headers` with a preload rule for each scanned page, after the site's own `_headers`.
+/// Returns a warning when the file has more rules than `provider` reads.
+pub fn write_preload_headers(
+    scanned: &[ScannedPage],
+    asset_roots: &AssetRoots,
+    output_path: &Path,
+    assets: &AssetsConfig,
+    provider: HostingProvider,
+    normalize: bool,
+) -> Result<Option<HugsError>> {
+    let existing = if skip_reason(Path::new(HEADERS_FILE), assets).is_none() {
+        std::fs::read_to_string(asset_roots.path(Path::new(HEADERS_FILE))).ok()
+    } else {
+        None
+    };
+    let rules = preload_rules(scanned, output_path);
+    let headers = render_headers(existing.as_deref(), &rules);
+
+    write_text_output_atomically(&output_path.join(HEADERS_FILE), &headers, normalize)?;
+
+    let count = count_rules(&headers);
+    Ok(provider
+        .header_rule_limit()
+        .filter(|limit| count > *limit)
+        .map(|limit| HugsError::TooManyHeaderRules {
+            count: count.into(),
+            limit,
+            provider: provider.name(),
+        }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output_scan::scan_pages;
+    use crate::run::PageInfo;
+    use crate::url::SiteUrl;
+
+    fn page(url: &str) -> PageInfo {
+        PageInfo {
+            url: SiteUrl::parse(url),
+            file_path: format!("{}.md", url.trim_matches('/')),
+            frontmatter: serde_yaml::Value::Null,
+        }
+    }
+
+    fn write(output: &Path, path: &str, contents: &str) {
+        let file = output.join(path);
+        std::fs::create_dir_all(file.parent().unwrap()).unwrap();
+        std::fs::write(file, contents).unwrap();
+    }
+
+    fn site() -> tempfile::TempDir {
+        let output = tempfile::tempdir().unwrap();
+        write(
+            output.path(),
+            "theme.1e092a90.css",
+            r#"@font-face { src: url("fonts/inter.woff2") format("woff2"), url(/fonts/inter.woff2); } body { background: url(/bg.png) }"#,
+        );
+        write(output.path(), "code.css", "pre { font-family: monospace }");
+        let head = r#"<link rel="stylesheet" href="/theme.1e092a90.css"><link rel="stylesheet" href="https://cdn.example/x.css">"#;
+        write(output.path(), "index.html", head);
+        write(
+            output.path(),
+            "blog/post/index.html",
+            &format!(r#"{head}<link rel=stylesheet href="../../code.css"><link rel="stylesheet" href="/theme.1e092a90.css"><link rel="preload" as="font" href="/fonts/inter.woff2" crossorigin><link rel="preload" as="font" href="/fonts/mono.woff2" crossorigin>"#),
+        );
+        write(output.path(), "plain/index.html", "<p>No styles</p>");
+        output
+    }
+
+    #[test]
+    fn test_each_page_preloads_its_stylesheets_and_fonts_once() {
+        let output = site();
+        let pages = [page("/"), page("/blog/post"), page("/plain")];
+        let rules = preload_rules(&scan_pages(&pages, output.path()), output.path());
+
+        assert_eq!(rules.keys().collect::<Vec<_>>(), ["/", "/blog/post"]);
+        assert_eq!(
+            render_headers(None, &rules),
+            "# Preloads for each page, added by hugs
+/
+  Link: </theme.1e092a90.css>; rel=preload; as=style
+  Link: </fonts/inter.woff2>; rel=preload; as=font; crossorigin
+/blog/post
+  Link: </theme.1e092a90.css>; rel=preload; as=style
+  Link: </code.css>; rel=preload; as=style
+  Link: </fonts/inter.woff2>; rel=preload; as=font; crossorigin
+  Link: </fonts/mono.woff2>; rel=preload; as=font; crossorigin
+"
+        );
+    }
+
+    #[test]
+    fn test_preloads_are_capped_per_page() {
+        let output = tempfile::tempdir().unwrap();
+        let links: String = (0..20).map(|i| format!(r#"<link rel="stylesheet" href="/s{i}.css">"#)).collect();
+        write(output.path(), "index.html", &links);
+
+        let pages = [page("/")];
+        let rules = preload_rules(&scan_pages(&pages, output.path()), output.path());
+        assert_eq!(rules["/"].len(), MAX_PRELOADS_PER_PAGE);
+        assert_eq!(rules["/"][0], Preload::Style("/s0.css".to_string()));
+    }
+
+    #[test]
+    fn test_rules_follow_the_sites_own_headers() {
+        let site_dir = tempfile::tempdir().unwrap();
+        let output = site();
+        write(site_dir.path(), HEADERS_FILE, "/*\n  X-Frame-Options: DENY\n\n# Old assets\n/old/*\n  Cache-Control: no-store\n\n\n");
+        let pages = [page("/")];
+
+        let warning = write_preload_headers(
+            &scan_pages(&pages, output.path()),
+            &AssetRoots::whole_site(site_dir.path()),
+            output.path(),
+            &AssetsConfig::default(),
+            HostingProvider::Cloudflare,
+            false,
+        )
+        .unwrap();
+        assert!(warning.is_none());
+
+        let headers = std::fs::read_to_string(output.path().join(HEADERS_FILE)).unwrap();
+        assert!(
+            headers.starts_with("/*\n  X-Frame-Options: DENY\n\n# Old assets\n/old/*\n  Cache-Control: no-store\n\n# Preloads for each page, added by hugs\n/\n  Link: </theme.1e092a90.css>"),
+            "{headers}"
+        );
+        assert_eq!(count_rules(&headers), 3);
+    }
+
+    #[test]
+    fn test_rules_past_the_providers_limit_are_reported() {
+        let site = tempfile::tempdir().unwrap();
+        let output = tempfile::tempdir().unwrap();
+        let pages: Vec<PageInfo> = (0..120).map(|i| page(&format!("/p{i}"))).collect();
+        for i in 0..120 {
+            write(output.path(), &format!("p{i}/index.html"), r#"<link rel="stylesheet" href="/theme.css">"#);
+        }
+        let scanned = scan_pages(&pages, output.path());
+        let write_for = |provider| {
+            write_preload_headers(&scanned, &AssetRoots::whole_site(site.path()), output.path(), &AssetsConfig::default(), provider, false).unwrap()
+        };
+
+        let Some(HugsError::TooManyHeaderRules { count, limit, provider }) = write_for(HostingProvider::Cloudflare) else {
+            panic!("expected a warning");
+        };
+        assert_eq!((count.0, limit, provider), (120, 100, "Cloudflare Pages"));
+        assert!(write_for(HostingProvider::Netlify).is_none());
+    }
+}
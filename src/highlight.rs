@@ -1,30 +1,148 @@
 //! Syntax highlighting for code blocks using giallo.
+//!
+//! Highlighted blocks are kept in a process-wide cache keyed by a hash of their language, theme
+//! and code, so a snippet repeated across pages (say, by a macro) is highlighted once per build.
+//! Being content-addressed, the cache stays valid across `hugs dev` reloads and is kept.
+//!
+//! A fence's language is looked up after `[build.syntax_highlighting.aliases]` and my own aliases
+//! for names giallo doesn't know, like `golang`. With `autodetect` on, a block in a language I
+//! still don't know is highlighted as whatever its shebang line or its JSON or XML shape says.
 
-use std::sync::OnceLock;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, PoisonError};
 
-use giallo::{HighlightOptions, HtmlRenderer, Registry, RenderOptions, ThemeVariant};
-use regex::Regex;
+use giallo::{HighlightOptions, HtmlRenderer, PLAIN_GRAMMAR_NAME, Registry, RenderOptions, ThemeVariant};
+use rayon::prelude::*;
+use sha2::{Digest, Sha256};
+
+use crate::config::SyntaxHighlightConfig;
+use crate::error::{HugsError, Result};
 
 /// Global registry - loaded once at startup
 static REGISTRY: OnceLock<Registry> = OnceLock::new();
 
-/// Regex for finding code blocks in HTML
-static CODE_BLOCK_RE: OnceLock<Regex> = OnceLock::new();
+/// Highlighted blocks, shared by every page and every reload
+static CACHE: OnceLock<Mutex<HighlightCache>> = OnceLock::new();
+
+/// Blocks served from the cache instead of highlighted again
+static CACHE_HITS: AtomicUsize = AtomicUsize::new(0);
+
+/// Most highlighted HTML the cache holds, in bytes, before it forgets the oldest blocks
+const CACHE_LIMIT_BYTES: usize = 64 * 1024 * 1024;
+
+/// Themes in giallo's built-in registry, which can't list them itself
+const BUILTIN_THEMES: &[&str] = &[
+    "andromeeda",
+    "aurora-x",
+    "ayu-dark",
+    "catppuccin-frappe",
+    "catppuccin-latte",
+    "catppuccin-macchiato",
+    "catppuccin-mocha",
+    "dark-plus",
+    "dracula",
+    "dracula-soft",
+    "everforest-dark",
+    "everforest-light",
+    "github-dark",
+    "github-dark-default",
+    "github-dark-dimmed",
+    "github-dark-high-contrast",
+    "github-light",
+    "github-light-default",
+    "github-light-high-contrast",
+    "gruvbox-dark-hard",
+    "gruvbox-dark-medium",
+    "gruvbox-dark-soft",
+    "gruvbox-light-hard",
+    "gruvbox-light-medium",
+    "gruvbox-light-soft",
+    "houston",
+    "kanagawa-dragon",
+    "kanagawa-lotus",
+    "kanagawa-wave",
+    "laserwave",
+    "light-plus",
+    "material-theme",
+    "material-theme-darker",
+    "material-theme-lighter",
+    "material-theme-ocean",
+    "material-theme-palenight",
+    "min-dark",
+    "min-light",
+    "monokai",
+    "night-owl",
+    "nord",
+    "one-dark-pro",
+    "one-light",
+    "plastic",
+    "poimandres",
+    "red",
+    "rose-pine",
+    "rose-pine-dawn",
+    "rose-pine-moon",
+    "slack-dark",
+    "slack-ochin",
+    "snazzy-light",
+    "solarized-dark",
+    "solarized-light",
+    "synthwave-84",
+    "tokyo-night",
+    "vesper",
+    "vitesse-black",
+    "vitesse-dark",
+    "vitesse-light",];
+
+/// Fence languages giallo doesn't know under that name, and the grammar to use for each.
+/// `[build.syntax_highlighting.aliases]` go over these.
+const BUILTIN_ALIASES: &[(&str, &str)] = &[
+    ("atom", "xml"),
+    ("bazel", "python"),
+    ("bzl", "python"),
+    ("containerfile", "dockerfile"),
+    ("docker-compose", "yaml"),
+    ("env", "dotenv"),
+    ("ex", "elixir"),
+    ("exs", "elixir"),
+    ("golang", "go"),
+    ("gradle", "groovy"),
+    ("htm", "html"),
+    ("jinja2", "jinja"),
+    ("nasm", "asm"),
+    ("ndjson", "jsonl"),
+    ("node", "javascript"),
+    ("patch", "diff"),
+    ("plaintext", "text"),
+    ("plist", "xml"),
+    ("psm1", "powershell"),
+    ("pwsh", "powershell"),
+    ("rss", "xml"),
+    ("starlark", "python"),
+    ("svg", "xml"),
+    ("xhtml", "html"),
+    ("xslt", "xsl"),
+];
 
 /// Initialize the syntax highlighting registry.
-/// This should be called once at application startup.
-pub fn init_registry() {
-    REGISTRY.get_or_init(|| {
-        let mut registry = Registry::builtin().expect("Failed to load syntax highlighting registry");
-        registry.link_grammars();
-        registry
-    });
-    CODE_BLOCK_RE.get_or_init(|| {
-        // Match <pre><code class="language-X">...</code></pre>
-        // The (?s) flag makes . match newlines
-        Regex::new(r#"(?s)<pre><code class="language-([^"]+)">(.+?)</code></pre>"#)
-            .expect("Invalid regex pattern")
-    });
+/// This should be called once at application startup; later calls do nothing.
+pub fn init_registry() -> Result<()> {
+    if REGISTRY.get().is_some() {
+        return Ok(());
+    }
+
+    let registry = load_registry().map_err(|e| HugsError::HighlightRegistry { reason: e.to_string() })?;
+    // Another load may have finished first, and its registry is just as good
+    let _ = REGISTRY.set(registry);
+    Ok(())
+}
+
+fn load_registry() -> std::result::Result<Registry, giallo::Error> {
+    let mut registry = Registry::builtin()?;
+    // Blocks without a language are highlighted as plain text, so they get the theme's wrapper too
+    registry.add_plain_grammar(&["text", "txt"])?;
+    registry.link_grammars();
+    Ok(registry)
 }
 
 /// Get the registry, panics if not initialized
@@ -34,25 +152,221 @@ fn registry() -> &'static Registry {
         .expect("Syntax highlighting registry not initialized. Call init_registry() first.")
 }
 
-/// Get the code block regex
-fn code_block_regex() -> &'static Regex {
-    CODE_BLOCK_RE.get().expect("Code block regex not initialized")
+/// Longest character reference `html_decode` looks for, `&` and `;` included, like `&#x10FFFF;`
+const MAX_REFERENCE_LEN: usize = 10;
+
+/// Decode the character references in escaped HTML text: the ones markdown writes (`&amp;`,
+/// `&lt;`, `&gt;`, `&quot;`), `&apos;`, and numeric ones like `&#39;` and `&#x3C;`. It's a single
+/// pass, so the `&` a reference decodes to never starts another one: `&amp;lt;` is `&lt;`, not `<`.
+/// Anything else, like a lone `&` or `&copy;`, is left as it is.
+pub(crate) fn html_decode(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        match decode_reference(rest) {
+            Some((c, len)) => {
+                out.push(c);
+                rest = &rest[len..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+
+    out.push_str(rest);
+    out
+}
+
+/// The character the reference at the start of `s` stands for, and the reference's length
+fn decode_reference(s: &str) -> Option<(char, usize)> {
+    let end = s.bytes().take(MAX_REFERENCE_LEN).position(|b| b == b';')?;
+    let c = match &s[1..end] {
+        "amp" => '&',
+        "lt" => '<',
+        "gt" => '>',
+        "quot" => '"',
+        "apos" => '\'',
+        name => {
+            let number = name.strip_prefix('#')?;
+            let code = match number.strip_prefix(['x', 'X']) {
+                Some(hex) if !hex.is_empty() && hex.bytes().all(|b| b.is_ascii_hexdigit()) => u32::from_str_radix(hex, 16).ok()?,
+                None if !number.is_empty() && number.bytes().all(|b| b.is_ascii_digit()) => number.parse().ok()?,
+                _ => return None,
+            };
+            char::from_u32(code).filter(|c| *c != '\0')?
+        }
+    };
+    Some((c, end + 1))
 }
 
-/// HTML-decode common entities that markdown encoders produce
-fn html_decode(s: &str) -> String {
-    s.replace("&lt;", "<")
-        .replace("&gt;", ">")
-        .replace("&amp;", "&")
-        .replace("&quot;", "\"")
-        .replace("&#39;", "'")
+/// Whether `theme` names a syntax highlighting theme I can use
+pub fn has_theme(theme: &str) -> bool {
+    registry().contains_theme(theme)
 }
 
-/// Highlight a single code block
+/// Names of the syntax highlighting themes I can use
+pub fn theme_names() -> Vec<String> {
+    let registry = registry();
+    BUILTIN_THEMES
+        .iter()
+        .filter(|theme| registry.contains_theme(theme))
+        .map(|theme| theme.to_string())
+        .collect()
+}
+
+/// Languages that code blocks asked for but I don't know, with how many blocks asked for each.
+/// Pages render in parallel, so this is shared and filled in as they go.
+#[derive(Debug, Default)]
+pub struct UnknownLanguages(Mutex<BTreeMap<String, usize>>);
+
+impl UnknownLanguages {
+    pub fn record(&self, languages: Vec<String>) {
+        if languages.is_empty() {
+            return;
+        }
+        let mut counts = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        for lang in languages {
+            *counts.entry(lang).or_default() += 1;
+        }
+    }
+
+    /// The number of code blocks with an unknown language, and the languages, if there were any
+    pub fn summary(&self) -> Option<(usize, Vec<String>)> {
+        let counts = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        if counts.is_empty() {
+            return None;
+        }
+        Some((counts.values().sum(), counts.keys().cloned().collect()))
+    }
+}
+
+/// Hash of what decides a block's highlighted HTML
+type CacheKey = [u8; 32];
+
+/// Highlighted blocks by content, oldest forgotten first once they pass `CACHE_LIMIT_BYTES`
+#[derive(Default)]
+struct HighlightCache {
+    blocks: HashMap<CacheKey, Arc<str>>,
+    order: VecDeque<CacheKey>,
+    bytes: usize,
+}
+
+impl HighlightCache {
+    fn key(code: &str, lang: &str, theme: &str) -> CacheKey {
+        let mut hasher = Sha256::new();
+        for part in [lang, theme, code] {
+            hasher.update(part.len().to_le_bytes());
+            hasher.update(part);
+        }
+        hasher.finalize().into()
+    }
+
+    fn insert(&mut self, key: CacheKey, html: Arc<str>) {
+        if self.blocks.contains_key(&key) || html.len() > CACHE_LIMIT_BYTES {
+            return;
+        }
+        self.bytes += html.len();
+        self.blocks.insert(key, html);
+        self.order.push_back(key);
+
+        while self.bytes > CACHE_LIMIT_BYTES {
+            let Some(oldest) = self.order.pop_front() else {
+                break;
+            };
+            if let Some(forgotten) = self.blocks.remove(&oldest) {
+                self.bytes -= forgotten.len();
+            }
+        }
+    }
+}
+
+fn cache() -> &'static Mutex<HighlightCache> {
+    CACHE.get_or_init(Mutex::default)
+}
+
+/// How many blocks have come from the cache so far
+#[cfg(test)]
+fn cache_hits() -> usize {
+    CACHE_HITS.load(Ordering::Relaxed)
+}
+
+/// Highlight a code block, or take it from the cache when the same code was highlighted before
+fn highlight_cached(code: &str, lang: &str, theme: &str) -> Option<Arc<str>> {
+    let key = HighlightCache::key(code, lang, theme);
+    if let Some(html) = cache().lock().unwrap_or_else(PoisonError::into_inner).blocks.get(&key) {
+        CACHE_HITS.fetch_add(1, Ordering::Relaxed);
+        return Some(Arc::clone(html));
+    }
+
+    // Highlighting happens outside the lock, so other blocks aren't held up. Two threads may
+    // both highlight the same new block; the second result is just dropped.
+    let html: Arc<str> = highlight_code(code, lang, theme)?.into();
+    cache().lock().unwrap_or_else(PoisonError::into_inner).insert(key, Arc::clone(&html));
+    Some(html)
+}
+
+/// Whether I have a grammar called `name`, not counting aliases
+pub fn has_grammar(name: &str) -> bool {
+    registry().contains_grammar(name)
+}
+
+/// The grammar for a block labelled `lang`, after the site's aliases and mine, if I know it.
+/// A language I have a grammar for is never aliased to another.
+pub fn resolve_language<'a>(lang: &'a str, aliases: &'a BTreeMap<String, String>) -> Option<&'a str> {
+    if has_grammar(lang) {
+        return Some(lang);
+    }
+    let name = aliases
+        .iter()
+        .find(|(alias, _)| alias.eq_ignore_ascii_case(lang))
+        .map(|(_, name)| name.as_str())
+        .or_else(|| BUILTIN_ALIASES.iter().find(|(alias, _)| alias.eq_ignore_ascii_case(lang)).map(|(_, name)| *name))
+        .unwrap_or(lang);
+    has_grammar(name).then_some(name)
+}
+
+/// A guess at the grammar for `code`, from the program its shebang line runs, or from it being
+/// a whole JSON value or XML document
+fn detect_language<'a>(code: &'a str, aliases: &'a BTreeMap<String, String>) -> Option<&'a str> {
+    if let Some(program) = shebang_program(code) {
+        return resolve_language(program, aliases);
+    }
+
+    let code = code.trim();
+    if code.starts_with(['{', '[']) && serde_json::from_str::<serde::de::IgnoredAny>(code).is_ok() {
+        return Some("json");
+    }
+    if code.starts_with('<') && code.ends_with('>') {
+        let first_line = code.lines().next().unwrap_or_default().to_ascii_lowercase();
+        let html = first_line.starts_with("<!doctype html") || first_line.starts_with("<html");
+        return Some(if html { "html" } else { "xml" });
+    }
+    None
+}
+
+/// The program a `#!` line runs, without its folder or version, so `#!/usr/bin/env python3`
+/// runs `python`
+fn shebang_program(code: &str) -> Option<&str> {
+    let line = code.lines().next()?.strip_prefix("#!")?;
+    let mut words = line.split_whitespace();
+    let mut program = words.next()?.rsplit('/').next()?;
+    if program == "env" {
+        // Past env's own flags and variables, like `env -S VAR=1 deno`
+        program = words.find(|word| !word.starts_with('-') && !word.contains('='))?.rsplit('/').next()?;
+    }
+    Some(program.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.')).filter(|program| !program.is_empty())
+}
+
+/// Highlight a single code block. Languages I don't know are highlighted as plain text.
 fn highlight_code(code: &str, lang: &str, theme: &str) -> Option<String> {
     let registry = registry();
 
-    let options = HighlightOptions::new(lang, ThemeVariant::Single(theme));
+    let options = HighlightOptions::new(lang, ThemeVariant::Single(theme)).fallback_to_plain(true);
 
     // Try to highlight with the specified language
     let highlighted = registry.highlight(code, &options).ok()?;
@@ -61,21 +375,165 @@ fn highlight_code(code: &str, lang: &str, theme: &str) -> Option<String> {
     Some(renderer.render(&highlighted, &render_options))
 }
 
+/// A `<pre><code>` block in rendered HTML
+#[derive(Debug, PartialEq)]
+struct CodeBlock<'a> {
+    /// Byte range of the whole block, from `<pre>` to `</pre>`
+    start: usize,
+    end: usize,
+    /// Language from a `language-X` class on the `<code>` tag
+    lang: Option<&'a str>,
+    /// Still-escaped contents of the `<code>` element
+    code: &'a str,
+}
+
+/// Find the code blocks the markdown renderer produced: a bare `<pre>` holding a single `<code>`.
+/// Blocks whose `<pre>` already carries attributes (e.g. ones I highlighted before) are left alone.
+fn find_code_blocks(html: &str) -> Vec<CodeBlock<'_>> {
+    // ASCII lowercasing keeps byte offsets, so positions found here index `html` too
+    let lower = html.to_ascii_lowercase();
+    let mut blocks = Vec::new();
+    let mut pos = 0;
+
+    while let Some(found) = lower[pos..].find("<pre>") {
+        let start = pos + found;
+        pos = start + "<pre>".len();
+
+        let after_pre = skip_whitespace(&lower, pos);
+        let Some(code_tag_end) = open_tag_end(&lower, after_pre, "code") else {
+            continue;
+        };
+        let attributes = &html[after_pre + "<code".len()..code_tag_end - 1];
+        let content_start = code_tag_end;
+
+        let Some((content_end, close_end)) = find_closing_code(&lower, content_start) else {
+            continue;
+        };
+
+        let after_code = skip_whitespace(&lower, close_end);
+        if !lower[after_code..].starts_with("</pre>") {
+            continue;
+        }
+        let end = after_code + "</pre>".len();
+
+        blocks.push(CodeBlock {
+            start,
+            end,
+            lang: language_class(attributes),
+            code: &html[content_start..content_end],
+        });
+        pos = end;
+    }
+
+    blocks
+}
+
+fn skip_whitespace(s: &str, pos: usize) -> usize {
+    pos + s[pos..].len() - s[pos..].trim_start().len()
+}
+
+/// If an opening `<tag ...>` starts at `pos`, the offset just past its `>`
+fn open_tag_end(lower: &str, pos: usize, tag: &str) -> Option<usize> {
+    let rest = lower[pos..].strip_prefix('<')?.strip_prefix(tag)?;
+    if !rest.starts_with(|c: char| c == '>' || c.is_ascii_whitespace()) {
+        return None;
+    }
+    let close = rest.find('>')?;
+    Some(pos + 1 + tag.len() + close + 1)
+}
+
+/// Find the `</code>` matching a `<code>` whose contents start at `pos`, counting nested
+/// `<code>` elements. Returns where the contents end and where the closing tag ends.
+fn find_closing_code(lower: &str, pos: usize) -> Option<(usize, usize)> {
+    let mut depth = 1;
+    let mut cursor = pos;
+
+    loop {
+        let next_close = cursor + lower[cursor..].find("</code")?;
+
+        if let Some(next_open) = lower[cursor..next_close].find("<code").map(|found| cursor + found) {
+            if open_tag_end(lower, next_open, "code").is_some() {
+                depth += 1;
+            }
+            cursor = next_open + "<code".len();
+            continue;
+        }
+
+        depth -= 1;
+        let close_end = next_close + lower[next_close..].find('>')? + 1;
+        if depth == 0 {
+            return Some((next_close, close_end));
+        }
+        cursor = close_end;
+    }
+}
+
+/// The `X` of a `language-X` class among a tag's attributes
+fn language_class(attributes: &str) -> Option<&str> {
+    let class_start = attributes.find("class=")? + "class=".len();
+    let quote = attributes[class_start..].chars().next().filter(|c| *c == '"' || *c == '\'')?;
+    let value = &attributes[class_start + 1..];
+    let value = &value[..value.find(quote)?];
+
+    value
+        .split_ascii_whitespace()
+        .find_map(|class| class.strip_prefix("language-"))
+        .filter(|lang| !lang.is_empty())
+}
+
 /// Process HTML and highlight all code blocks.
-/// Returns the HTML with code blocks syntax-highlighted.
-pub fn highlight_code_blocks(html: &str, theme: &str) -> String {
-    let re = code_block_regex();
+/// Returns the HTML with code blocks syntax-highlighted, and the language of each block I didn't
+/// know even after aliasing, which fell back to plain text or a detected language.
+/// Pages with at least `config.parallel_blocks` blocks have them highlighted in parallel.
+pub fn highlight_code_blocks(html: &str, config: &SyntaxHighlightConfig) -> (String, Vec<String>) {
+    let blocks = find_code_blocks(html);
+    let unknown = blocks
+        .iter()
+        .map(|block| block.lang.unwrap_or(PLAIN_GRAMMAR_NAME))
+        .filter(|lang| resolve_language(lang, &config.aliases).is_none())
+        .map(|lang| lang.to_lowercase())
+        .collect();
+
+    let result = replace_code_blocks(html, &blocks, config.parallel_blocks, |code, lang| {
+        let lang = resolve_language(lang, &config.aliases)
+            .or_else(|| config.autodetect.then(|| detect_language(code, &config.aliases)).flatten())
+            .unwrap_or(lang);
+        highlight_cached(code, lang, &config.theme)
+    });
+    (result, unknown)
+}
+
+/// Replace each of `html`'s `blocks` with what `highlight` makes of its decoded code and language,
+/// in parallel when there are at least `parallel_over` of them. The code is decoded once, for the
+/// highlighter, which escapes it again. A block `highlight` gives up on keeps its original,
+/// still-escaped HTML, so nothing is ever decoded twice.
+fn replace_code_blocks(
+    html: &str,
+    blocks: &[CodeBlock],
+    parallel_over: usize,
+    highlight: impl Fn(&str, &str) -> Option<Arc<str>> + Sync,
+) -> String {
+    let highlight_block = |block: &CodeBlock| highlight(&html_decode(block.code), block.lang.unwrap_or(PLAIN_GRAMMAR_NAME));
+    let highlighted: Vec<Option<Arc<str>>> = if blocks.len() >= parallel_over {
+        blocks.par_iter().map(highlight_block).collect()
+    } else {
+        blocks.iter().map(highlight_block).collect()
+    };
 
-    re.replace_all(html, |caps: &regex::Captures| {
-        let lang = &caps[1];
-        let code = html_decode(&caps[2]);
+    let mut result = String::with_capacity(html.len());
+    let mut last = 0;
 
-        match highlight_code(&code, lang, theme) {
-            Some(highlighted) => highlighted,
-            None => caps[0].to_string(), // Fall back to original on error
+    for (block, highlighted) in blocks.iter().zip(highlighted) {
+        result.push_str(&html[last..block.start]);
+        match highlighted {
+            Some(highlighted) => result.push_str(&highlighted),
+            None => result.push_str(&html[block.start..block.end]),
         }
-    })
-    .to_string()
+        last = block.end;
+    }
+
+    result.push_str(&html[last..]);
+    result
 }
 
 /// Generate CSS for syntax highlighting theme.
@@ -84,3 +542,264 @@ pub fn generate_theme_css(theme: &str) -> String {
     // The second argument is the CSS class prefix
     registry.generate_css(theme, "").unwrap_or_default()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::minify::{MinifyConfig, minify_html_content};
+
+    const THEME: &str = "one-dark-pro";
+
+    /// What code snippets are made of: references, things that nearly are, and what they decode to
+    const CODE_PIECES: &[&str] = &[
+        "&amp;", "&lt;", "&gt;", "&quot;", "&#39;", "&apos;", "&#x3C;", "&#60;", "&amp;lt;", "&amp;amp;", "&copy;",
+        "&#;", "&#xZZ;", "&", ";", "#", "lt", "amp", "<", ">", "\"", "'", "x", " ", "\n",
+    ];
+
+    /// The same for prose, leaving out `<`, which would start raw HTML there
+    const PROSE_PIECES: &[&str] = &[
+        "&amp;", "&lt;", "&gt;", "&quot;", "&#39;", "&#x3C;", "&#60;", "&amp;lt;", "&amp;amp;", "&copy;", "&#;", "&",
+        ";", "#", "lt", "amp", "\"", "'", "x", " ",
+    ];
+
+    fn config() -> SyntaxHighlightConfig {
+        SyntaxHighlightConfig { theme: THEME.to_string(), ..SyntaxHighlightConfig::default() }
+    }
+
+    fn highlight(markdown: &str) -> String {
+        init_registry().unwrap();
+        let html = markdown::to_html(markdown);
+        highlight_code_blocks(&html, &config()).0
+    }
+
+    #[test]
+    fn test_code_containing_closing_tags() {
+        let html = highlight("```html\n<pre><code>hi</code></pre>\n```\n\nAfter");
+
+        assert_eq!(html.matches("<pre class=\"giallo").count(), 1, "{}", html);
+        assert!(html.contains("data-lang=\"html\""));
+        // The closing tags inside the block stay escaped, and the text after it is untouched
+        assert!(!html.contains("<pre><code>"));
+        assert!(html.ends_with("<p>After</p>"));
+    }
+
+    #[test]
+    fn test_consecutive_and_unlabelled_blocks() {
+        let html = highlight("```rust\nfn a() {}\n```\n```\nplain text\n```\n```rust\nfn b() {}\n```");
+
+        assert_eq!(html.matches("<pre class=\"giallo").count(), 3, "{}", html);
+        assert_eq!(html.matches("data-lang=\"rust\"").count(), 2);
+        assert!(html.contains(&format!("data-lang=\"{}\"", PLAIN_GRAMMAR_NAME)));
+    }
+
+    #[test]
+    fn test_finds_blocks_regardless_of_attribute_order_or_nesting() {
+        let html = "<PRE><code data-line=\"1\" class='block language-rust'>fn x() {}</code>\n</PRE>\
+                    <pre><code class=\"language-html\"><pre><code>&lt;b&gt;</code></pre></code></pre>\
+                    <pre class=\"giallo\"><code>already done</code></pre>";
+
+        let blocks = find_code_blocks(html);
+        assert_eq!(blocks.len(), 2);
+        assert_eq!((blocks[0].lang, blocks[0].code), (Some("rust"), "fn x() {}"));
+        assert_eq!((blocks[1].lang, blocks[1].code), (Some("html"), "<pre><code>&lt;b&gt;</code></pre>"));
+        assert_eq!(blocks[1].end, html.find("<pre class=").unwrap());
+    }
+
+    #[test]
+    fn test_unknown_languages_fall_back_to_plain_and_are_counted() {
+        init_registry().unwrap();
+        let html = markdown::to_html("```rust\nfn a() {}\n```\n```rsut\nx\n```\n```\nplain\n```\n```Rsut\ny\n```\n```zzz\nz\n```");
+        let (html, unknown) = highlight_code_blocks(&html, &config());
+
+        assert_eq!(html.matches("<pre class=\"giallo").count(), 5, "{}", html);
+        assert_eq!(unknown, vec!["rsut", "rsut", "zzz"]);
+
+        let languages = UnknownLanguages::default();
+        languages.record(unknown);
+        languages.record(Vec::new());
+        assert_eq!(languages.summary(), Some((3, vec!["rsut".to_string(), "zzz".to_string()])));
+    }
+
+    #[test]
+    fn test_aliases_name_languages_i_know() {
+        init_registry().unwrap();
+        let aliases = BTreeMap::from([("Rsut".to_string(), "rust".to_string()), ("svg".to_string(), "html".to_string())]);
+        let aliased = SyntaxHighlightConfig { aliases, ..config() };
+        let html = markdown::to_html("```golang\nfunc main() {}\n```\n```rsut\nfn a() {}\n```\n```svg\n<svg/>\n```\n```bsh\nls\n```");
+        let (html, unknown) = highlight_code_blocks(&html, &aliased);
+
+        assert!(html.contains("data-lang=\"go\"") && html.contains("data-lang=\"rust\""), "{}", html);
+        // The site's aliases go over mine
+        assert!(html.contains("data-lang=\"html\"") && !html.contains("data-lang=\"xml\""), "{}", html);
+        assert_eq!(unknown, vec!["bsh"]);
+
+        let broken = SyntaxHighlightConfig { aliases: BTreeMap::from([("shl".to_string(), "bsh".to_string())]), ..config() };
+        assert_eq!(highlight_code_blocks(&markdown::to_html("```shl\nls\n```"), &broken).1, vec!["shl"]);
+
+        // A language I know keeps its own grammar
+        let overriding = BTreeMap::from([("rust".to_string(), "python".to_string())]);
+        assert_eq!(resolve_language("rust", &overriding), Some("rust"));
+    }
+
+    #[test]
+    fn test_shebangs_and_shapes_give_the_language_away() {
+        init_registry().unwrap();
+        assert_eq!(shebang_program("#!/usr/bin/env python3\nprint()"), Some("python"));
+        assert_eq!(shebang_program("#!/usr/bin/env -S VAR=1 node --harmony"), Some("node"));
+        assert_eq!(shebang_program("#!/bin/bash -e"), Some("bash"));
+        assert_eq!(shebang_program("# not a shebang"), None);
+
+        let aliases = BTreeMap::new();
+        assert_eq!(detect_language("#!/usr/bin/env node\nconsole.log(1)", &aliases), Some("javascript"));
+        assert_eq!(detect_language("  {\"a\": [1, 2]}\n", &aliases), Some("json"));
+        assert_eq!(detect_language("{ not json }", &aliases), None);
+        assert_eq!(detect_language("<?xml version=\"1.0\"?>\n<feed/>", &aliases), Some("xml"));
+        assert_eq!(detect_language("<!DOCTYPE html>\n<html></html>", &aliases), Some("html"));
+        assert_eq!(detect_language("just words", &aliases), None);
+
+        let markdown = "```script\n#!/usr/bin/env python3\nprint(\"hi\")\n```\n```data\n{\"a\": 1}\n```";
+        let html = markdown::to_html(markdown);
+        let (detected, unknown) = highlight_code_blocks(&html, &SyntaxHighlightConfig { autodetect: true, ..config() });
+        assert!(detected.contains("data-lang=\"python\"") && detected.contains("data-lang=\"json\""), "{}", detected);
+        // They're still reported, so the site can add an alias
+        assert_eq!(unknown, vec!["script", "data"]);
+
+        let plain = highlight_code_blocks(&html, &config()).0;
+        assert_eq!(plain.matches(&format!("data-lang=\"{}\"", PLAIN_GRAMMAR_NAME)).count(), 2, "{}", plain);
+    }
+
+    #[test]
+    fn test_known_languages_are_left_as_they_are() {
+        init_registry().unwrap();
+        let html = markdown::to_html("```bash\n#!/usr/bin/env python3\necho hi\n```\n```rust\nfn a() {}\n```");
+        let autodetect = SyntaxHighlightConfig { autodetect: true, ..config() };
+        let (highlighted, unknown) = highlight_code_blocks(&html, &autodetect);
+
+        assert_eq!(highlighted, highlight_code_blocks(&html, &config()).0);
+        assert!(highlighted.contains("data-lang=\"shellscript\"") && !highlighted.contains("data-lang=\"python\""), "{}", highlighted);
+        assert!(unknown.is_empty());
+    }
+
+    /// A few hundred snippets strung together from `pieces`, the same ones every run
+    fn snippets(pieces: &[&str]) -> Vec<String> {
+        let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as usize
+        };
+        (0..300)
+            .map(|_| {
+                let len = 1 + next() % 12;
+                (0..len).map(|_| pieces[next() % pieces.len()]).collect()
+            })
+            .collect()
+    }
+
+    /// The text a browser shows for some HTML: tags dropped, references decoded. Each run of text
+    /// between tags is decoded on its own, since a reference can't span a tag.
+    fn text(html: &str) -> String {
+        let mut out = String::new();
+        let mut rest = html;
+        while let Some(open) = rest.find('<') {
+            out.push_str(&html_decode(&rest[..open]));
+            rest = &rest[open..];
+            rest = &rest[rest.find('>').map_or(rest.len(), |close| close + 1)..];
+        }
+        out.push_str(&html_decode(rest));
+        out
+    }
+
+    #[test]
+    fn test_html_decode_is_a_single_pass() {
+        assert_eq!(html_decode("&amp;lt; &amp;quot; &amp;#39; &amp;amp;"), "&lt; &quot; &#39; &amp;");
+        assert_eq!(html_decode("&lt;b&gt; &quot;&apos;&#39;&#x3C;&#X3e;"), "<b> \"''<>");
+        assert_eq!(html_decode("a & b; &copy; &#; &#xZZ; &#0; &#+5; &"), "a & b; &copy; &#; &#xZZ; &#0; &#+5; &");
+    }
+
+    #[test]
+    fn test_references_in_code_decode_once() {
+        let html = highlight("```rust\nlet s = '&quot;' + \"&amp;lt;\" + &#39;\n```");
+        assert_eq!(text(&html), "let s = '&quot;' + \"&amp;lt;\" + &#39;");
+    }
+
+    #[test]
+    fn test_blocks_that_fail_to_highlight_keep_their_escaped_html() {
+        let html = markdown::to_html("```rust\n'&quot;' &lt; &#39;\n```");
+        assert_eq!(replace_code_blocks(&html, &find_code_blocks(&html), usize::MAX, |_, _| None), html);
+        assert!(html.contains("&amp;quot;"), "{}", html);
+    }
+
+    #[test]
+    fn test_code_survives_markdown_highlighting_and_minifying() {
+        init_registry().unwrap();
+        let minify = MinifyConfig::new(true);
+
+        for snippet in snippets(CODE_PIECES) {
+            let markdown = format!("```rust\n{}\n```", snippet);
+            let highlighted = highlight(&markdown);
+            let minified = minify_html_content(&highlighted, &minify);
+
+            // Both what dev serves and what the build writes show the code exactly as written
+            for html in [&highlighted, &minified] {
+                assert_eq!(text(html).trim_end_matches('\n'), snippet.trim_end_matches('\n'), "{:?} came out as {}", snippet, html);
+            }
+        }
+    }
+
+    #[test]
+    fn test_prose_survives_highlighting_and_minifying() {
+        init_registry().unwrap();
+        let minify = MinifyConfig::new(true);
+        let words = |text: String| text.split_whitespace().collect::<Vec<_>>().join(" ");
+
+        for snippet in snippets(PROSE_PIECES) {
+            let html = markdown::to_html(&format!("Prose {}", snippet));
+            let highlighted = highlight_code_blocks(&html, &config()).0;
+            let minified = minify_html_content(&highlighted, &minify);
+
+            assert_eq!(highlighted, html);
+            assert_eq!(words(text(&minified)), words(text(&html)), "{:?} came out as {}", snippet, minified);
+        }
+    }
+
+    #[test]
+    fn test_repeated_blocks_are_highlighted_once() {
+        init_registry().unwrap();
+        // Unique to this test, so the first block is never in the cache already
+        let snippet = "fn repeated_in_the_cache_test() -> u32 { 200 }";
+        let markdown = format!("```rust\n{}\n```\n\n", snippet).repeat(200);
+        let html = markdown::to_html(&markdown);
+
+        let hits_before = cache_hits();
+        let sequential = highlight_code_blocks(&html, &SyntaxHighlightConfig { parallel_blocks: usize::MAX, ..config() }).0;
+        // Other tests use the cache at the same time, so only a lower bound is certain
+        assert!(cache_hits() - hits_before >= 199, "repeated blocks should come from the cache");
+
+        let blocks: Vec<&str> = sequential.split("<pre class=\"giallo").skip(1).collect();
+        assert_eq!(blocks.len(), 200);
+        assert!(blocks.iter().all(|block| *block == blocks[0]));
+
+        // In parallel, every block is in its place just the same
+        let parallel = highlight_code_blocks(&html, &SyntaxHighlightConfig { parallel_blocks: 2, ..config() }).0;
+        assert_eq!(parallel, sequential);
+    }
+
+    #[test]
+    fn test_cache_forgets_the_oldest_blocks_over_its_limit() {
+        let mut cache = HighlightCache::default();
+        let block = |c: char| -> Arc<str> { c.to_string().repeat(CACHE_LIMIT_BYTES / 2).into() };
+        let keys: Vec<CacheKey> = ["a", "b", "c"].iter().map(|code| HighlightCache::key(code, "rust", THEME)).collect();
+
+        cache.insert(keys[0], block('a'));
+        cache.insert(keys[1], block('b'));
+        assert_eq!(cache.blocks.len(), 2);
+        cache.insert(keys[2], block('c'));
+
+        assert!(!cache.blocks.contains_key(&keys[0]));
+        assert!(cache.blocks.contains_key(&keys[1]) && cache.blocks.contains_key(&keys[2]));
+        assert_eq!(cache.bytes, CACHE_LIMIT_BYTES);
+        assert_ne!(keys[0], HighlightCache::key("a", "rust", "dracula"));
+    }
+}
@@ -0,0 +1,165 @@
+//! `image_size(path=...)`: an image's width and height in pixels, for the `width` and `height` of
+//! its `<img>`, so the page doesn't jump about as images load.
+//!
+//! I read them from the file's header. Big images and many pages make that add up, so the sizes
+//! are kept in the build cache, keyed by the file's path, length and modification time.
+
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+use minijinja::value::{Kwargs, Value};
+use minijinja::{Error, ErrorKind};
+
+use crate::assets::AssetRoots;
+use crate::build_cache::BuildCache;
+use crate::deps::DepRecorder;
+use crate::run::is_plain_relative;
+
+/// Create the `image_size` function for the site's images. Every file it reads is recorded into
+/// `deps`.
+pub fn image_size_fn(
+    asset_roots: &AssetRoots,
+    cache: &BuildCache,
+    deps: &DepRecorder,
+) -> impl Fn(Kwargs) -> std::result::Result<Value, Error> + Send + Sync + 'static {
+    let asset_roots = asset_roots.clone();
+    let cache = cache.clone();
+    let deps = deps.clone();
+
+    move |kwargs: Kwargs| {
+        let path: String = kwargs.get("path")?;
+        kwargs.assert_all_used()?;
+
+        let relative = Path::new(path.trim_start_matches('/'));
+        if !is_plain_relative(relative) {
+            return Err(Error::new(
+                ErrorKind::InvalidOperation,
+                format!("image_size only reads files inside the site, and {:?} isn't", path),
+            ));
+        }
+        deps.record_file(asset_roots.file(relative));
+
+        let file = asset_roots.path(relative);
+        let unreadable = |e: std::io::Error| Error::new(ErrorKind::InvalidOperation, format!("couldn't read {}: {}", path, e));
+        let metadata = std::fs::metadata(&file).map_err(unreadable)?;
+        let modified = metadata.modified().ok().and_then(|time| time.duration_since(UNIX_EPOCH).ok()).map(|age| age.as_nanos());
+
+        let key = (relative.to_string_lossy(), metadata.len(), modified);
+        let (width, height) = cache.try_get_or_compute("image_size", &key, || {
+            let bytes = std::fs::read(&file).map_err(unreadable)?;
+            dimensions(&bytes).ok_or_else(|| {
+                Error::new(
+                    ErrorKind::InvalidOperation,
+                    format!("image_size can't tell how big {} is. It reads PNG, JPEG, GIF and WebP images.", path),
+                )
+            })
+        })?;
+        Ok(minijinja::context! { width => width, height => height })
+    }
+}
+
+/// Width and height of a PNG, JPEG, GIF or WebP image, from its header
+fn dimensions(bytes: &[u8]) -> Option<(u32, u32)> {
+    let be16 = |at: usize| Some(u16::from_be_bytes(bytes.get(at..at + 2)?.try_into().ok()?) as u32);
+    let le16 = |at: usize| Some(u16::from_le_bytes(bytes.get(at..at + 2)?.try_into().ok()?) as u32);
+    let be32 = |at: usize| Some(u32::from_be_bytes(bytes.get(at..at + 4)?.try_into().ok()?));
+    let le24 = |at: usize| Some(u32::from_le_bytes([*bytes.get(at)?, *bytes.get(at + 1)?, *bytes.get(at + 2)?, 0]));
+
+    if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        return Some((be32(16)?, be32(20)?));
+    }
+    if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+        return Some((le16(6)?, le16(8)?));
+    }
+    if bytes.starts_with(b"RIFF") && bytes.get(8..12) == Some(&b"WEBP"[..]) {
+        return match bytes.get(12..16)? {
+            b"VP8 " => Some((le16(26)? & 0x3fff, le16(28)? & 0x3fff)),
+            b"VP8L" => {
+                let bits = u32::from_le_bytes(bytes.get(21..25)?.try_into().ok()?);
+                Some(((bits & 0x3fff) + 1, ((bits >> 14) & 0x3fff) + 1))
+            }
+            b"VP8X" => Some((le24(24)? + 1, le24(27)? + 1)),
+            _ => None,
+        };
+    }
+    if bytes.starts_with(&[0xff, 0xd8]) {
+        // Walk the segments to the frame header, which follows any metadata and thumbnails
+        let mut at = 2;
+        while *bytes.get(at)? == 0xff {
+            let marker = *bytes.get(at + 1)?;
+            let is_frame = matches!(marker, 0xc0..=0xcf) && !matches!(marker, 0xc4 | 0xc8 | 0xcc);
+            if is_frame {
+                return Some((be16(at + 7)?, be16(at + 5)?));
+            }
+            at += 2 + be16(at + 2)? as usize;
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut bytes = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+        bytes.extend(width.to_be_bytes());
+        bytes.extend(height.to_be_bytes());
+        bytes.extend([8, 6, 0, 0, 0]);
+        bytes
+    }
+
+    #[test]
+    fn test_reads_each_format() {
+        assert_eq!(dimensions(&png(640, 480)), Some((640, 480)));
+        assert_eq!(dimensions(b"GIF89a\x20\x03\x58\x02\0\0"), Some((800, 600)));
+
+        // A JPEG with an APP0 segment before its baseline frame header
+        let jpeg = [
+            &[0xff, 0xd8, 0xff, 0xe0, 0x00, 0x04, 0x4a, 0x46][..],
+            &[0xff, 0xc0, 0x00, 0x11, 0x08, 0x01, 0x2c, 0x01, 0x90, 0x03],
+        ]
+        .concat();
+        assert_eq!(dimensions(&jpeg), Some((400, 300)));
+
+        let mut webp = b"RIFF\0\0\0\0WEBPVP8X\x0a\0\0\0\0\0\0\0".to_vec();
+        webp.extend([0x7f, 0x07, 0x00, 0x37, 0x04, 0x00]);
+        assert_eq!(dimensions(&webp), Some((1920, 1080)));
+
+        let mut lossless = b"RIFF\0\0\0\0WEBPVP8L\0\0\0\0\x2f".to_vec();
+        lossless.extend((99 | (49 << 14) as u32).to_le_bytes());
+        assert_eq!(dimensions(&lossless), Some((100, 50)));
+
+        assert_eq!(dimensions(b"<svg></svg>"), None);
+        assert_eq!(dimensions(b"\x89PNG\r\n\x1a\n"), None);
+    }
+
+    #[test]
+    fn test_sizes_are_kept_between_builds() {
+        let site = tempfile::tempdir().unwrap();
+        std::fs::create_dir(site.path().join("img")).unwrap();
+        std::fs::write(site.path().join("img/cat.png"), png(120, 80)).unwrap();
+        let roots = AssetRoots::whole_site(site.path());
+
+        let render = |cache: &BuildCache, template: &str| {
+            let mut env = minijinja::Environment::new();
+            env.add_function("image_size", image_size_fn(&roots, cache, &DepRecorder::default()));
+            env.render_str(template, ())
+        };
+
+        let first = BuildCache::open(site.path());
+        let template = r#"{% set size = image_size(path="/img/cat.png") %}{{ size.width }}x{{ size.height }}"#;
+        assert_eq!(render(&first, template).unwrap(), "120x80");
+        assert_eq!(render(&first, template).unwrap(), "120x80");
+        assert_eq!(first.stats(), (1, 1));
+
+        let second = BuildCache::open(site.path());
+        assert_eq!(render(&second, template).unwrap(), "120x80");
+        assert_eq!(second.stats(), (1, 0));
+
+        let missing = render(&second, r#"{{ image_size(path="img/dog.png") }}"#).unwrap_err();
+        assert!(missing.to_string().contains("couldn't read img/dog.png"), "{missing}");
+        let outside = render(&second, r#"{{ image_size(path="../secret.png") }}"#).unwrap_err();
+        assert!(outside.to_string().contains("only reads files inside the site"), "{outside}");
+    }
+}
@@ -0,0 +1,548 @@
+//! Self-contained single-page output for `hugs build --single-file`.
+//!
+//! After a page renders as usual, this pass walks its tags: local stylesheets become `<style>`
+//! blocks, local images become `data:` URIs (up to `build.single_file_image_limit_kb`), and every
+//! other local link points at the live site through `site.url`. The result opens anywhere.
+
+use std::path::{Component, Path, PathBuf};
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use percent_encoding::percent_decode_str;
+
+use crate::assets::skip_reason;
+use crate::error::{HugsError, Result};
+use crate::run::AppData;
+
+/// Extensions of the font files `stylesheet_fonts` picks out
+const FONT_EXTENSIONS: &[&str] = &["woff2", "woff", "ttf", "otf"];
+
+/// Attributes holding a single URL that should point at the live site
+pub(crate) const URL_ATTRIBUTES: &[&str] = &["href", "src", "action", "poster", "cite", "data"];
+
+/// Inline a rendered page's local stylesheets and images and make its other local links absolute.
+/// Returns the new HTML along with warnings about assets I had to leave as links.
+pub fn inline_assets(html: &str, page_url: &str, app_data: &AppData) -> Result<(String, Vec<HugsError>)> {
+    // Paths here are spelled as the page wrote them, already encoded, so they go after the base as they are
+    let site_url = app_data.config.site.url.as_ref().ok_or(HugsError::SingleFileNeedsSiteUrl)?.to_string();
+
+    let inliner = Inliner {
+        site_url: &site_url,
+        page_url,
+        app_data,
+        image_limit: app_data.config.build.single_file_image_limit_kb * 1024,
+    };
+    let mut warnings = Vec::new();
+    let html = rewrite_start_tags(html, |tag| inliner.rewrite(tag, &mut warnings));
+    Ok((html, warnings))
+}
+
+struct Inliner<'a> {
+    site_url: &'a str,
+    page_url: &'a str,
+    app_data: &'a AppData,
+    image_limit: u64,
+}
+
+impl Inliner<'_> {
+    fn rewrite(&self, tag: &StartTag, warnings: &mut Vec<HugsError>) -> Option<String> {
+        if is_stylesheet(tag)
+            && let Some(path) = tag.attr("href").and_then(|href| local_path(self.page_url, href))
+            && let Some(css) = self.stylesheet(&path)
+        {
+            let css = absolutize_css_urls(&css, &path, self.site_url);
+            return Some(format!("<style>{}</style>", css.replace("</style", "<\\/style")));
+        }
+
+        let mut tag = tag.clone();
+        let mut changed = false;
+
+        for (name, value) in tag.attrs.iter_mut() {
+            let Some(raw) = value.as_deref() else {
+                continue;
+            };
+
+            let new_value = if tag.name == "img" && name == "src" {
+                local_path(self.page_url, raw).map(|path| self.image(&path, raw, warnings))
+            } else if name == "srcset" {
+                Some(self.absolutize_srcset(raw))
+            } else if URL_ATTRIBUTES.contains(&name.as_str()) {
+                local_path(self.page_url, raw).map(|path| self.absolute(&path, raw))
+            } else {
+                None
+            };
+
+            if let Some(new_value) = new_value {
+                *value = Some(new_value);
+                changed = true;
+            }
+        }
+
+        changed.then(|| tag.to_html())
+    }
+
+    /// The contents of a local stylesheet, including the generated theme and highlight CSS
+    fn stylesheet(&self, path: &str) -> Option<String> {
+        let original = self
+            .app_data
+            .cache_bust_registry
+            .entries()
+            .into_iter()
+            .find(|(_, hashed)| hashed == path)
+            .map(|(original, _)| original)
+            .unwrap_or_else(|| path.to_string());
+
+        match original.as_str() {
+            "/theme.css" => Some(self.app_data.theme_css.clone()),
+            "/highlight.css" => Some(self.app_data.highlight_css.clone()),
+            _ => std::fs::read_to_string(self.site_file(&original)?).ok(),
+        }
+    }
+
+    /// A `data:` URI for a local image, or its absolute URL when it's missing or over the limit
+    fn image(&self, path: &str, raw: &str, warnings: &mut Vec<HugsError>) -> String {
+        let Some(file) = self.site_file(path) else {
+            warnings.push(HugsError::AssetNotInlined {
+                path: path.into(),
+                help_text: String::from("I couldn't find this image in the site, so I pointed it at the live site instead."),
+            });
+            return self.absolute(path, raw);
+        };
+
+        let size = file.metadata().map(|m| m.len()).unwrap_or(u64::MAX);
+        if size > self.image_limit {
+            warnings.push(HugsError::AssetNotInlined {
+                path: path.into(),
+                help_text: format!(
+                    "It's {} KB, over the {} KB limit, so I pointed it at the live site instead. Raise `single_file_image_limit_kb` in the [build] section of config.toml to embed it.",
+                    size.div_ceil(1024),
+                    self.image_limit / 1024
+                ),
+            });
+            return self.absolute(path, raw);
+        }
+
+        match std::fs::read(&file) {
+            Ok(bytes) => {
+                let mime = mime_guess::from_path(&file).first_or_octet_stream();
+                format!("data:{};base64,{}", mime, STANDARD.encode(bytes))
+            }
+            Err(_) => self.absolute(path, raw),
+        }
+    }
+
+    /// The site file a root-relative URL path is served from, if it's published at all
+    fn site_file(&self, path: &str) -> Option<PathBuf> {
+        let decoded = percent_decode_str(path.trim_start_matches('/')).decode_utf8().ok()?;
+        let relative = Path::new(decoded.as_ref());
+        if relative.components().any(|c| !matches!(c, Component::Normal(_)))
+            || skip_reason(relative, &self.app_data.config.assets).is_some()
+        {
+            return None;
+        }
+
+        let file = self.app_data.asset_roots.path(relative);
+        file.is_file().then_some(file)
+    }
+
+    /// `path` on the live site, keeping the query and fragment from the original attribute value
+    fn absolute(&self, path: &str, raw: &str) -> String {
+        let suffix = raw.find(['?', '#']).map(|i| &raw[i..]).unwrap_or("");
+        format!("{}{}{}", self.site_url, path, suffix)
+    }
+
+    fn absolutize_srcset(&self, srcset: &str) -> String {
+        srcset
+            .split(',')
+            .map(|candidate| {
+                let candidate = candidate.trim();
+                let (url, descriptor) = candidate.split_once(char::is_whitespace).unwrap_or((candidate, ""));
+                let url = match local_path(self.page_url, url) {
+                    Some(path) => self.absolute(&path, url),
+                    None => url.to_string(),
+                };
+                if descriptor.is_empty() { url } else { format!("{} {}", url, descriptor.trim()) }
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// The local stylesheets, scripts and images a rendered page at `page_url` loads, as root-relative
+/// paths without repeats. Links to other pages aren't included, and neither are `srcset`
+/// candidates, since the browser only fetches one of them.
+pub(crate) fn page_subresources(html: &str, page_url: &str) -> Vec<String> {
+    let mut paths: Vec<String> = Vec::new();
+    rewrite_start_tags(html, |tag| {
+        let url = match tag.name.as_str() {
+            "link" if is_stylesheet(tag) => tag.attr("href"),
+            "script" | "img" => tag.attr("src"),
+            "video" => tag.attr("poster"),
+            _ => None,
+        };
+        if let Some(path) = url.and_then(|url| local_path(page_url, url))
+            && !paths.contains(&path)
+        {
+            paths.push(path);
+        }
+        None
+    });
+    paths
+}
+
+/// Where the links (`<a href>`) in a rendered page at `page_url` go, as decoded root-relative
+/// paths without repeats. Links to other sites and to a spot on the same page are left out.
+pub(crate) fn page_links(html: &str, page_url: &str) -> Vec<String> {
+    let mut paths: Vec<String> = Vec::new();
+    rewrite_start_tags(html, |tag| {
+        if tag.name == "a"
+            && let Some(path) = tag.attr("href").and_then(|href| local_path(page_url, href))
+        {
+            let path = percent_decode_str(&path).decode_utf8_lossy().into_owned();
+            if !paths.contains(&path) {
+                paths.push(path);
+            }
+        }
+        None
+    });
+    paths
+}
+
+/// The local stylesheets a rendered page at `page_url` links and the fonts it preloads with
+/// `<link rel="preload" as="font">`, as root-relative paths without repeats
+pub(crate) fn page_stylesheets_and_fonts(html: &str, page_url: &str) -> (Vec<String>, Vec<String>) {
+    let mut stylesheets: Vec<String> = Vec::new();
+    let mut fonts: Vec<String> = Vec::new();
+    rewrite_start_tags(html, |tag| {
+        let list = if is_stylesheet(tag) {
+            &mut stylesheets
+        } else if tag.name == "link"
+            && tag.attr("rel").is_some_and(|rel| rel.split_ascii_whitespace().any(|r| r.eq_ignore_ascii_case("preload")))
+            && tag.attr("as").is_some_and(|kind| kind.eq_ignore_ascii_case("font"))
+        {
+            &mut fonts
+        } else {
+            return None;
+        };
+        if let Some(path) = tag.attr("href").and_then(|href| local_path(page_url, href))
+            && !list.contains(&path)
+        {
+            list.push(path);
+        }
+        None
+    });
+    (stylesheets, fonts)
+}
+
+/// The local font files a stylesheet served at `css_path` loads through `url(...)`, as
+/// root-relative paths without repeats
+pub(crate) fn stylesheet_fonts(css: &str, css_path: &str) -> Vec<String> {
+    let mut fonts: Vec<String> = Vec::new();
+    let mut rest = css;
+
+    while let Some(start) = rest.find("url(") {
+        let after = &rest[start + "url(".len()..];
+        let Some(end) = after.find(')') else {
+            break;
+        };
+        let inner = after[..end].trim();
+        let url = inner.trim_matches(|c| c == '"' || c == '\'');
+        if let Some(path) = local_path(css_path, url)
+            && FONT_EXTENSIONS.iter().any(|ext| path.rsplit_once('.').is_some_and(|(_, e)| e.eq_ignore_ascii_case(ext)))
+            && !fonts.contains(&path)
+        {
+            fonts.push(path);
+        }
+        rest = &after[end..];
+    }
+    fonts
+}
+
+fn is_stylesheet(tag: &StartTag) -> bool {
+    tag.name == "link"
+        && tag.attr("rel").is_some_and(|rel| rel.split_ascii_whitespace().any(|r| r.eq_ignore_ascii_case("stylesheet")))
+}
+
+/// The root-relative path a local URL in a page at `page_url` points to, without its query or
+/// fragment. External URLs, `data:` URIs and same-page fragments aren't local.
+pub(crate) fn local_path(page_url: &str, url: &str) -> Option<String> {
+    let url = url.trim().replace("&amp;", "&");
+    if url.is_empty() || url.starts_with('#') || url.starts_with("//") || has_scheme(&url) {
+        return None;
+    }
+
+    let path = &url[..url.find(['?', '#']).unwrap_or(url.len())];
+    let joined = if path.starts_with('/') {
+        path.to_string()
+    } else {
+        // Relative to the page's directory, the way a browser resolves it
+        let dir = &page_url[..page_url.rfind('/').map(|i| i + 1).unwrap_or(0)];
+        format!("{}{}", if dir.is_empty() { "/" } else { dir }, path)
+    };
+
+    let mut segments: Vec<&str> = Vec::new();
+    for segment in joined.split('/').skip(1) {
+        match segment {
+            "." => {}
+            ".." => {
+                segments.pop();
+            }
+            _ => segments.push(segment),
+        }
+    }
+    // A trailing `.` or `..` still points at a directory
+    let trailing_slash = joined.ends_with("/.") || joined.ends_with("/..");
+    Some(format!("/{}{}", segments.join("/"), if trailing_slash { "/" } else { "" }))
+}
+
+pub(crate) fn has_scheme(url: &str) -> bool {
+    match url.find(':') {
+        Some(colon) => {
+            let scheme = &url[..colon];
+            scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+                && scheme.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+        }
+        None => false,
+    }
+}
+
+/// Point the local `url(...)` references in a stylesheet served at `css_path` at the live site
+fn absolutize_css_urls(css: &str, css_path: &str, site_url: &str) -> String {
+    let mut out = String::with_capacity(css.len());
+    let mut rest = css;
+
+    while let Some(start) = rest.find("url(") {
+        let (before, after) = rest.split_at(start + "url(".len());
+        out.push_str(before);
+
+        let Some(end) = after.find(')') else {
+            rest = after;
+            break;
+        };
+        let inner = after[..end].trim();
+        let quote = inner.chars().next().filter(|c| *c == '"' || *c == '\'');
+        let url = quote.map_or(inner, |q| inner.trim_matches(q));
+
+        match local_path(css_path, url) {
+            Some(path) => {
+                let suffix = url.find(['?', '#']).map(|i| &url[i..]).unwrap_or("");
+                out.push_str(&format!("\"{}{}{}\"", site_url, path, suffix));
+            }
+            None => out.push_str(&after[..end]),
+        }
+        rest = &after[end..];
+    }
+
+    out.push_str(rest);
+    out
+}
+
+/// An HTML start tag, with attribute names lowercased and values kept as written
+#[derive(Clone, Debug)]
+pub(crate) struct StartTag {
+    pub name: String,
+    attrs: Vec<(String, Option<String>)>,
+    pub self_closing: bool,
+}
+
+impl StartTag {
+    pub(crate) fn attr(&self, name: &str) -> Option<&str> {
+        self.attrs.iter().find(|(n, _)| n == name).and_then(|(_, v)| v.as_deref())
+    }
+
+    /// Whether the tag has the attribute, with or without a value
+    pub(crate) fn has_attr(&self, name: &str) -> bool {
+        self.attrs.iter().any(|(n, _)| n == name)
+    }
+
+    /// Change the attribute's value, if the tag has it
+    pub(crate) fn set_attr(&mut self, name: &str, value: String) {
+        if let Some((_, old)) = self.attrs.iter_mut().find(|(n, _)| n == name) {
+            *old = Some(value);
+        }
+    }
+
+    /// Add the attribute at the end
+    pub(crate) fn push_attr(&mut self, name: &str, value: &str) {
+        self.attrs.push((name.to_string(), Some(value.to_string())));
+    }
+
+    pub(crate) fn to_html(&self) -> String {
+        let mut html = format!("<{}", self.name);
+        for (name, value) in &self.attrs {
+            match value {
+                Some(value) if value.contains('"') => html.push_str(&format!(" {}='{}'", name, value)),
+                Some(value) => html.push_str(&format!(" {}=\"{}\"", name, value)),
+                None => html.push_str(&format!(" {}", name)),
+            }
+        }
+        html.push_str(if self.self_closing { " />" } else { ">" });
+        html
+    }
+}
+
+/// Copy `html`, letting `rewrite` replace start tags. Comments and the contents of `<script>`
+/// and `<style>` are copied as they are.
+pub(crate) fn rewrite_start_tags(html: &str, mut rewrite: impl FnMut(&StartTag) -> Option<String>) -> String {
+    let lower = html.to_ascii_lowercase();
+    let mut out = String::with_capacity(html.len());
+    let mut pos = 0;
+
+    while let Some(found) = html[pos..].find('<') {
+        let start = pos + found;
+        out.push_str(&html[pos..start]);
+
+        if html[start..].starts_with("<!--") {
+            let end = html[start..].find("-->").map_or(html.len(), |i| start + i + 3);
+            out.push_str(&html[start..end]);
+            pos = end;
+            continue;
+        }
+
+        let Some((tag, end)) = parse_start_tag(html, start) else {
+            out.push('<');
+            pos = start + 1;
+            continue;
+        };
+
+        match rewrite(&tag) {
+            Some(replacement) => out.push_str(&replacement),
+            None => out.push_str(&html[start..end]),
+        }
+        pos = end;
+
+        // Raw text elements: skip to their closing tag without looking for tags inside
+        if matches!(tag.name.as_str(), "script" | "style") && !tag.self_closing {
+            let close = format!("</{}", tag.name);
+            let raw_end = lower[pos..].find(&close).map_or(html.len(), |i| pos + i);
+            out.push_str(&html[pos..raw_end]);
+            pos = raw_end;
+        }
+    }
+
+    out.push_str(&html[pos..]);
+    out
+}
+
+/// Parse the start tag at `start`, returning it and the offset just past its `>`
+pub(crate) fn parse_start_tag(html: &str, start: usize) -> Option<(StartTag, usize)> {
+    let bytes = html.as_bytes();
+    let mut i = start + 1;
+    if !bytes.get(i)?.is_ascii_alphabetic() {
+        return None;
+    }
+
+    let name_start = i;
+    while i < bytes.len() && !bytes[i].is_ascii_whitespace() && bytes[i] != b'>' && bytes[i] != b'/' {
+        i += 1;
+    }
+    let name = html[name_start..i].to_ascii_lowercase();
+
+    let mut attrs = Vec::new();
+    let mut self_closing = false;
+    loop {
+        while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+            i += 1;
+        }
+        match bytes.get(i)? {
+            b'>' => return Some((StartTag { name, attrs, self_closing }, i + 1)),
+            b'/' => {
+                self_closing = true;
+                i += 1;
+                continue;
+            }
+            _ => {}
+        }
+
+        let attr_start = i;
+        while i < bytes.len() && !bytes[i].is_ascii_whitespace() && !matches!(bytes[i], b'=' | b'>' | b'/') {
+            i += 1;
+        }
+        let attr_name = html[attr_start..i].to_ascii_lowercase();
+
+        while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+            i += 1;
+        }
+        if bytes.get(i) != Some(&b'=') {
+            attrs.push((attr_name, None));
+            continue;
+        }
+        i += 1;
+        while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+            i += 1;
+        }
+
+        let value = match bytes.get(i)? {
+            quote @ (b'"' | b'\'') => {
+                let value_start = i + 1;
+                let value_end = value_start + html[value_start..].find(*quote as char)?;
+                i = value_end + 1;
+                &html[value_start..value_end]
+            }
+            _ => {
+                let value_start = i;
+                while i < bytes.len() && !bytes[i].is_ascii_whitespace() && bytes[i] != b'>' {
+                    i += 1;
+                }
+                &html[value_start..i]
+            }
+        };
+        attrs.push((attr_name, Some(value.to_string())));
+        self_closing = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::build::render_page;
+
+    #[test]
+    fn test_local_path() {
+        assert_eq!(local_path("/blog/first-post", "second-post").as_deref(), Some("/blog/second-post"));
+        assert_eq!(local_path("/blog/", "../about?x=1#top").as_deref(), Some("/about"));
+        assert_eq!(local_path("/about", "/assets/logo.svg").as_deref(), Some("/assets/logo.svg"));
+        assert_eq!(local_path("/about", "./").as_deref(), Some("/"));
+        for external in ["https://example.com/x", "//cdn.example/x", "mailto:hi@example.com", "#top", "data:image/png;base64,AA"] {
+            assert_eq!(local_path("/about", external), None, "{}", external);
+        }
+    }
+
+    async fn single_file(url: &str, image_limit_kb: u64) -> (String, Vec<HugsError>) {
+        let site_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/basic/site");
+        let mut app_data = AppData::load(site_path, "build").await.unwrap();
+        app_data.config.build.single_file_image_limit_kb = image_limit_kb;
+
+        let page = app_data.pages.iter().find(|p| p.url == url).unwrap().clone();
+        let html = render_page(&page, &app_data).await.unwrap();
+        inline_assets(&html, url, &app_data).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_single_file_has_no_root_relative_references() {
+        let (html, warnings) = single_file("/about", 512).await;
+
+        assert!(warnings.is_empty());
+        for attr in ["href", "src", "srcset", "action"] {
+            for quote in ['"', '\''] {
+                let pattern = format!("{}={}/", attr, quote);
+                assert!(!html.contains(&pattern), "found {}", pattern);
+            }
+        }
+        assert!(!html.contains("url(/"));
+        assert!(!html.contains("rel=\"stylesheet\""), "stylesheets should be inlined");
+
+        assert!(html.contains("<style>"));
+        assert!(html.contains("src=\"data:image/svg+xml;base64,"));
+        assert!(html.contains("href=\"https://golden.example/blog/#latest\""));
+        assert!(html.contains("href=\"https://golden.example/assets/caf%C3%A9.svg\""));
+    }
+
+    #[tokio::test]
+    async fn test_images_over_the_limit_stay_links() {
+        let (html, warnings) = single_file("/about", 0).await;
+
+        assert!(html.contains("src=\"https://golden.example/assets/logo.svg\""));
+        assert_eq!(warnings.len(), 1);
+        assert!(matches!(&warnings[0], HugsError::AssetNotInlined { .. }));
+    }
+}
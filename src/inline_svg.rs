@@ -0,0 +1,206 @@
+//! `inline_svg(path=...)`: an SVG file's markup, put straight into the page, so CSS can style it
+//! (`fill: currentColor` and the like), which it can't do through `<img>`.
+//!
+//! Files are read once per load of the site and kept, since the same icon tends to show up on
+//! every page.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, LazyLock, Mutex, PoisonError};
+
+use minijinja::value::{Kwargs, Value};
+use minijinja::{Error, ErrorKind};
+use regex::Regex;
+
+use crate::assets::AssetRoots;
+use crate::build_cache::BuildCache;
+use crate::computed_svg::ComputedSvgs;
+use crate::deps::DepRecorder;
+use crate::image_size::image_size_fn;
+use crate::run::{escape_html, is_plain_relative};
+
+/// The opening tag of the root element
+static SVG_START: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"<svg\b(?:[^>"']|"[^"]*"|'[^']*')*>"#).unwrap());
+
+/// One `name="value"` attribute in a tag
+static ATTRIBUTE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"\s+([A-Za-z_:][-A-Za-z0-9_:.]*)\s*=\s*("[^"]*"|'[^']*')"#).unwrap());
+
+#[derive(Clone)]
+pub struct SvgInliner {
+    asset_roots: AssetRoots,
+    /// Files read so far, by URL path
+    files: Arc<Mutex<HashMap<PathBuf, Arc<str>>>>,
+    /// `qr()` and `avatar()`, dressed the same way and kept for as long
+    pub computed: ComputedSvgs,
+}
+
+impl SvgInliner {
+    pub fn new(asset_roots: AssetRoots) -> Self {
+        Self {
+            asset_roots,
+            files: Arc::default(),
+            computed: ComputedSvgs::default(),
+        }
+    }
+
+    /// Create the `image_size` function, reading images from the same folders as SVGs
+    pub fn image_size_fn(&self, cache: &BuildCache, deps: &DepRecorder) -> impl Fn(Kwargs) -> std::result::Result<Value, Error> + Send + Sync + 'static {
+        image_size_fn(&self.asset_roots, cache, deps)
+    }
+
+    /// Create a minijinja-compatible function that inlines SVGs from this site. Every file it
+    /// reads is recorded into `deps`.
+    pub fn to_minijinja_fn(&self, deps: &DepRecorder) -> impl Fn(Kwargs) -> std::result::Result<Value, Error> + Send + Sync + 'static {
+        let inliner = self.clone();
+        let deps = deps.clone();
+
+        move |kwargs: Kwargs| {
+            let path: String = kwargs.get("path")?;
+            let class: Option<String> = kwargs.get("class")?;
+            let label: Option<String> = kwargs.get("label")?;
+            let strip_size: Option<bool> = kwargs.get("strip_size")?;
+            kwargs.assert_all_used()?;
+
+            let relative = Path::new(path.trim_start_matches('/'));
+            if !is_plain_relative(relative) {
+                return Err(Error::new(
+                    ErrorKind::InvalidOperation,
+                    format!("inline_svg only reads files inside the site, and {:?} isn't", path),
+                ));
+            }
+            deps.record_file(inliner.asset_roots.file(relative));
+
+            let svg = inliner.read(relative)?;
+            let markup = dress(&svg, class.as_deref(), label.as_deref(), strip_size.unwrap_or(false))
+                .ok_or_else(|| Error::new(ErrorKind::InvalidOperation, format!("{} has no <svg> element", path)))?;
+            Ok(Value::from_safe_string(markup))
+        }
+    }
+
+    fn read(&self, relative: &Path) -> std::result::Result<Arc<str>, Error> {
+        if let Some(svg) = self.files.lock().unwrap_or_else(PoisonError::into_inner).get(relative) {
+            return Ok(Arc::clone(svg));
+        }
+
+        let svg: Arc<str> = std::fs::read_to_string(self.asset_roots.path(relative))
+            .map_err(|e| {
+                Error::new(ErrorKind::InvalidOperation, format!("couldn't read /{}: {}", relative.display(), e))
+            })?
+            .into();
+        self.files
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(relative.to_path_buf(), Arc::clone(&svg));
+        Ok(svg)
+    }
+}
+
+/// The `<svg>` element from a file, without the XML prolog, doctype or comments before it, with
+/// `class` added and labelled for screen readers (or hidden from them when there's no `label`)
+pub(crate) fn dress(svg: &str, class: Option<&str>, label: Option<&str>, strip_size: bool) -> Option<String> {
+    let start = SVG_START.find(svg)?;
+    let tag = start.as_str();
+    let (attributes, self_closing) = match tag.strip_suffix("/>") {
+        Some(rest) => (&rest["<svg".len()..], true),
+        None => (&tag["<svg".len()..tag.len() - 1], false),
+    };
+
+    let mut kept = String::new();
+    let mut had_class = false;
+    let has = |name: &str| ATTRIBUTE.captures_iter(attributes).any(|caps| caps[1].eq_ignore_ascii_case(name));
+    let has_role = has("role");
+    let has_aria = has("aria-label") || has("aria-labelledby") || has("aria-hidden");
+
+    for caps in ATTRIBUTE.captures_iter(attributes) {
+        let name = &caps[1];
+        let quoted = &caps[2];
+        if strip_size && (name.eq_ignore_ascii_case("width") || name.eq_ignore_ascii_case("height")) {
+            continue;
+        }
+        match class {
+            Some(class) if name.eq_ignore_ascii_case("class") => {
+                had_class = true;
+                let quote = &quoted[..1];
+                let value = &quoted[1..quoted.len() - 1];
+                kept.push_str(&format!(" class={}{} {}{}", quote, value, escape_html(class), quote));
+            }
+            _ => kept.push_str(&format!(" {}={}", name, quoted)),
+        }
+    }
+
+    if let Some(class) = class
+        && !had_class
+    {
+        kept.push_str(&format!(" class=\"{}\"", escape_html(class)));
+    }
+    if !has_aria {
+        match label {
+            Some(label) => {
+                if !has_role {
+                    kept.push_str(" role=\"img\"");
+                }
+                kept.push_str(&format!(" aria-label=\"{}\"", escape_html(label)));
+            }
+            None => kept.push_str(" aria-hidden=\"true\" focusable=\"false\""),
+        }
+    }
+
+    let rest = &svg[start.end()..];
+    let close = if self_closing { "/>" } else { ">" };
+    Some(format!("<svg{}{}{}", kept, close, rest.trim_end()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ARROW: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE svg PUBLIC "-//W3C//DTD SVG 1.1//EN" "http://www.w3.org/Graphics/SVG/1.1/DTD/svg11.dtd">
+<!-- exported from a drawing app -->
+<svg xmlns="http://www.w3.org/2000/svg" width="24" height="24" viewBox="0 0 24 24" class='glyph'>
+  <path d="M5 12h14"/>
+</svg>
+"#;
+
+    #[test]
+    fn test_prolog_goes_and_attributes_are_added() {
+        assert_eq!(
+            dress(ARROW, Some("icon"), None, false).unwrap(),
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"24\" height=\"24\" viewBox=\"0 0 24 24\" class='glyph icon' \
+            aria-hidden=\"true\" focusable=\"false\">\n  <path d=\"M5 12h14\"/>\n</svg>"
+        );
+        assert_eq!(
+            dress(ARROW, Some("a \"b\""), Some("Next page"), true).unwrap(),
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"0 0 24 24\" class='glyph a &quot;b&quot;' \
+            role=\"img\" aria-label=\"Next page\">\n  <path d=\"M5 12h14\"/>\n</svg>"
+        );
+        assert_eq!(dress("<p>not an svg</p>", None, None, false), None);
+    }
+
+    #[test]
+    fn test_reads_once_and_stays_inside_the_site() {
+        let site = tempfile::tempdir().unwrap();
+        std::fs::create_dir(site.path().join("icons")).unwrap();
+        std::fs::write(site.path().join("icons/arrow.svg"), ARROW).unwrap();
+
+        let deps = DepRecorder::default();
+        let mut env = minijinja::Environment::new();
+        env.add_function("inline_svg", SvgInliner::new(AssetRoots::whole_site(site.path())).to_minijinja_fn(&deps));
+        let render = |template: &str| env.render_str(template, ());
+
+        let first = render(r#"{{ inline_svg(path="/icons/arrow.svg", class="icon") }}"#).unwrap();
+        assert!(first.starts_with("<svg xmlns="), "{first}");
+        assert!(deps.snapshot().files.contains(Path::new("icons/arrow.svg")));
+
+        // Served from memory now, within this load of the site
+        std::fs::remove_file(site.path().join("icons/arrow.svg")).unwrap();
+        assert_eq!(render(r#"{{ inline_svg(path="/icons/arrow.svg", class="icon") }}"#).unwrap(), first);
+
+        let missing = render(r#"{{ inline_svg(path="/icons/missing.svg") }}"#).unwrap_err();
+        assert!(missing.to_string().contains("couldn't read /icons/missing.svg"), "{missing}");
+        let outside = render(r#"{{ inline_svg(path="../secret.svg") }}"#).unwrap_err();
+        assert!(outside.to_string().contains("inside the site"), "{outside}");
+    }
+}
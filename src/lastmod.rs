@@ -0,0 +1,200 @@
+//! `.hugs-cache/lastmod.json`: when each page's HTML last changed, for the sitemap's `<lastmod>` on
+//! pages whose frontmatter has no date.
+//!
+//! Every build hashes each page it writes and compares the hash with the one the previous build
+//! recorded, kept beside the site with the rest of `.hugs-cache/` rather than in the output, which
+//! is published and often starts out empty. A page whose HTML changed
+//! gets the build's time as its last change. Without a previous record there's no telling what
+//! changed, so that build only records hashes, and the sitemap leaves `<lastmod>` out for those
+//! pages rather than claim they all changed just now.
+
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::build_cache::CACHE_DIR;
+
+/// File name of the history, in the site's `.hugs-cache/`
+const LASTMOD_FILE: &str = "lastmod.json";
+
+/// Where builds before the history moved left it, at the root of the output directory
+const OLD_LASTMOD_FILE: &str = ".hugs-lastmod.json";
+
+/// Each page's HTML hash and when it last changed, by URL
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct LastmodHistory {
+    pages: BTreeMap<String, PageHistory>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct PageHistory {
+    hash: String,
+    first_seen: DateTime<Utc>,
+    /// When a build last saw the HTML change, unset until one has
+    last_changed: Option<DateTime<Utc>>,
+}
+
+impl LastmodHistory {
+    /// Where a build of the site at `site_path` keeps its history
+    pub fn path(site_path: &Path) -> PathBuf {
+        site_path.join(CACHE_DIR).join(LASTMOD_FILE)
+    }
+
+    /// Read the history left by the previous build, if there is one I can use, falling back to
+    /// the copy an older version of hugs left in the output directory
+    pub async fn load_previous(site_path: &Path, output_path: &Path) -> Option<Self> {
+        let content = match tokio::fs::read_to_string(Self::path(site_path)).await {
+            Ok(content) => content,
+            Err(_) => tokio::fs::read_to_string(output_path.join(OLD_LASTMOD_FILE)).await.ok()?,
+        };
+        serde_json::from_str(&content).ok()
+    }
+
+    /// This build's history, from each page's URL and HTML hash. Pages that are gone are dropped.
+    pub fn update(previous: Option<&Self>, hashes: impl IntoIterator<Item = (String, String)>, now: DateTime<Utc>) -> Self {
+        let pages = hashes
+            .into_iter()
+            .map(|(url, hash)| {
+                let page = match previous.map(|previous| previous.pages.get(&url)) {
+                    // No earlier build to compare with
+                    None => PageHistory { hash, first_seen: now, last_changed: None },
+                    // A page that's new since the last build
+                    Some(None) => PageHistory { hash, first_seen: now, last_changed: Some(now) },
+                    Some(Some(seen)) if seen.hash == hash => seen.clone(),
+                    Some(Some(seen)) => PageHistory { hash, first_seen: seen.first_seen, last_changed: Some(now) },
+                };
+                (url, page)
+            })
+            .collect();
+
+        Self { pages }
+    }
+
+    /// When the page at `url` last changed, if a build has seen it change
+    pub fn last_changed(&self, url: &str) -> Option<DateTime<Utc>> {
+        self.pages.get(url)?.last_changed
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap_or_default()
+    }
+}
+
+/// Hash a page's HTML as written
+pub fn hash_html(html: &[u8]) -> String {
+    hex::encode(Sha256::digest(html))
+}
+
+/// Passes a page through to `inner`, hashing and counting it on the way, so a page streamed to
+/// disk gets the same hash as [`hash_html`] without being read back
+pub struct HashingWriter<W> {
+    inner: W,
+    hasher: Sha256,
+    bytes: u64,
+}
+
+impl<W: Write> HashingWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self { inner, hasher: Sha256::new(), bytes: 0 }
+    }
+
+    /// The writer back, with how many bytes went through and their hash
+    pub fn finish(self) -> (W, u64, String) {
+        (self.inner, self.bytes, hex::encode(self.hasher.finalize()))
+    }
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        self.bytes += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(day: u32) -> DateTime<Utc> {
+        format!("2024-05-{:02}T12:00:00Z", day).parse().unwrap()
+    }
+
+    fn hashes(pages: &[(&str, &str)]) -> Vec<(String, String)> {
+        pages.iter().map(|(url, html)| (url.to_string(), hash_html(html.as_bytes()))).collect()
+    }
+
+    #[test]
+    fn test_only_changed_pages_move() {
+        let site = [("/", "<h1>Home</h1>"), ("/about", "<p>About</p>")];
+
+        // The first build can't tell what changed, so it claims nothing
+        let first = LastmodHistory::update(None, hashes(&site), at(1));
+        assert_eq!(first.last_changed("/"), None);
+        assert_eq!(first.last_changed("/about"), None);
+
+        // Nothing changed: nothing moves
+        let second = LastmodHistory::update(Some(&first), hashes(&site), at(2));
+        assert_eq!(second, first);
+
+        // One page changed and one is new
+        let third = LastmodHistory::update(
+            Some(&second),
+            hashes(&[("/", "<h1>Home</h1>"), ("/about", "<p>About us</p>"), ("/new", "<p>Hi</p>")]),
+            at(3),
+        );
+        assert_eq!(third.last_changed("/"), None);
+        assert_eq!(third.last_changed("/about"), Some(at(3)));
+        assert_eq!(third.last_changed("/new"), Some(at(3)));
+        assert_eq!(third.pages["/about"].first_seen, at(1));
+
+        // Later unchanged builds keep the recorded change
+        let fourth = LastmodHistory::update(Some(&third), hashes(&[("/about", "<p>About us</p>")]), at(9));
+        assert_eq!(fourth.last_changed("/about"), Some(at(3)));
+        assert_eq!(fourth.last_changed("/new"), None);
+    }
+
+    #[test]
+    fn test_streamed_pages_hash_like_written_ones() {
+        let mut out = HashingWriter::new(Vec::new());
+        out.write_all(b"<h1>Home</h1>").unwrap();
+        out.write_all(b"<p>Hi</p>").unwrap();
+
+        let (html, bytes, hash) = out.finish();
+        assert_eq!(html, b"<h1>Home</h1><p>Hi</p>");
+        assert_eq!(bytes, html.len() as u64);
+        assert_eq!(hash, hash_html(&html));
+    }
+
+    #[tokio::test]
+    async fn test_history_is_read_from_the_cache_then_the_old_output_file() {
+        let site = tempfile::tempdir().unwrap();
+        let output = site.path().join("dist");
+        assert_eq!(LastmodHistory::load_previous(site.path(), &output).await, None);
+
+        let old = LastmodHistory::update(None, hashes(&[("/", "old")]), at(1));
+        std::fs::create_dir_all(&output).unwrap();
+        std::fs::write(output.join(OLD_LASTMOD_FILE), old.to_json()).unwrap();
+        assert_eq!(LastmodHistory::load_previous(site.path(), &output).await, Some(old));
+
+        let current = LastmodHistory::update(None, hashes(&[("/", "new")]), at(2));
+        std::fs::create_dir_all(site.path().join(CACHE_DIR)).unwrap();
+        std::fs::write(LastmodHistory::path(site.path()), current.to_json()).unwrap();
+        assert_eq!(LastmodHistory::load_previous(site.path(), &output).await, Some(current));
+    }
+
+    #[test]
+    fn test_history_round_trips() {
+        let history = LastmodHistory::update(Some(&LastmodHistory::default()), hashes(&[("/", "x")]), at(4));
+        assert_eq!(serde_json::from_str::<LastmodHistory>(&history.to_json()).unwrap(), history);
+    }
+}
@@ -0,0 +1,179 @@
+//! Naming the macro behind broken page output.
+//!
+//! Macro output is pasted into a page before the markdown pass, so a macro that leaves an element
+//! open garbles the page without any error pointing at it. `AppData` keeps a second copy of the
+//! macros template whose bodies are wrapped in marker characters. When a page's HTML comes out
+//! broken, I render it again with that copy and look for the macro output the problem came from.
+
+use std::collections::HashMap;
+use std::ops::Range;
+
+use crate::error::{HugsError, Result};
+use crate::pretty::unclosed_elements;
+
+/// Starts a macro's output, followed by its name and `NAME_END`
+const START: char = '\u{E000}';
+const NAME_END: char = '\u{E001}';
+/// Ends the innermost macro output that's still open
+const END: char = '\u{E002}';
+
+/// A macro body that marks where its output starts and ends
+pub fn traced_body(name: &str, body: &str) -> String {
+    format!("{START}{name}{NAME_END}\n{body}\n{END}")
+}
+
+/// The traced macros template, and where each macro is defined
+#[derive(Clone, Debug, Default)]
+pub struct MacroTrace {
+    pub template: String,
+    /// Site-relative source file per macro name
+    pub files: HashMap<String, String>,
+}
+
+/// Where one macro call's output ended up in the page
+#[derive(Debug, PartialEq)]
+struct MacroOutput {
+    name: String,
+    range: Range<usize>,
+}
+
+impl MacroTrace {
+    /// Check a page's converted HTML, naming the macro responsible when the markdown couldn't be
+    /// converted or an element was left open. `render_traced` renders the page's template with the
+    /// given macros template; it's only called when something is wrong.
+    pub fn check(
+        &self,
+        converted: std::result::Result<String, markdown::message::Message>,
+        source_name: &str,
+        render_traced: impl FnOnce(&str) -> Option<String>,
+    ) -> Result<String> {
+        if self.files.is_empty() {
+            return converted.map_err(|message| HugsError::MarkdownParse {
+                file: source_name.into(),
+                reason: message.to_string(),
+            });
+        }
+
+        match converted {
+            Ok(html) => {
+                let unclosed = unclosed_elements(&html);
+                if unclosed.is_empty() {
+                    return Ok(html);
+                }
+
+                let Some((text, outputs)) = render_traced(&self.template).map(|traced| strip_markers(&traced)) else {
+                    return Ok(html);
+                };
+                // Innermost calls come last, so search from the end
+                let culprit = outputs.iter().rev().find_map(|output| {
+                    unclosed_elements(&text[output.range.clone()])
+                        .into_iter()
+                        .find(|element| unclosed.contains(element))
+                        .map(|element| (output, element))
+                });
+
+                match culprit {
+                    Some((output, element)) => Err(self.error(
+                        &output.name,
+                        source_name,
+                        format!(
+                            "The {} macro in {} opens a <{}> element without closing it. Close it inside the macro, or use {{% call %}} with caller() to wrap content.",
+                            output.name,
+                            self.file(&output.name),
+                            element
+                        ),
+                    )),
+                    // Left open by the page itself, which isn't my business here
+                    None => Ok(html),
+                }
+            }
+            Err(message) => {
+                let offset = match message.place.as_deref() {
+                    Some(markdown::message::Place::Point(point)) => Some(point.offset),
+                    Some(markdown::message::Place::Position(position)) => Some(position.start.offset),
+                    None => None,
+                };
+                let culprit = offset.zip(render_traced(&self.template)).and_then(|(offset, traced)| {
+                    let (_, outputs) = strip_markers(&traced);
+                    outputs.into_iter().rev().find(|output| output.range.contains(&offset))
+                });
+
+                match culprit {
+                    Some(output) => Err(self.error(
+                        &output.name,
+                        source_name,
+                        format!(
+                            "I couldn't convert the markdown: {}\n\nThe problem is in the output of the {} macro in {}.",
+                            message,
+                            output.name,
+                            self.file(&output.name)
+                        ),
+                    )),
+                    None => Err(HugsError::MarkdownParse {
+                        file: source_name.into(),
+                        reason: message.to_string(),
+                    }),
+                }
+            }
+        }
+    }
+
+    fn file(&self, name: &str) -> &str {
+        self.files.get(name).map_or("_/macros/", String::as_str)
+    }
+
+    fn error(&self, name: &str, source_name: &str, help_text: String) -> HugsError {
+        HugsError::MacroBrokenOutput {
+            name: name.to_string().into(),
+            file: source_name.into(),
+            help_text,
+        }
+    }
+}
+
+/// Remove the markers from a traced render, returning the plain text and where each macro call's
+/// output sits in it, in the order the calls started
+fn strip_markers(traced: &str) -> (String, Vec<MacroOutput>) {
+    let mut text = String::with_capacity(traced.len());
+    let mut outputs: Vec<MacroOutput> = Vec::new();
+    let mut open = Vec::new();
+    let mut chars = traced.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            START => {
+                let name: String = chars.by_ref().take_while(|&c| c != NAME_END).collect();
+                open.push(outputs.len());
+                outputs.push(MacroOutput { name, range: text.len()..text.len() });
+            }
+            END => {
+                if let Some(index) = open.pop() {
+                    outputs[index].range.end = text.len();
+                }
+            }
+            _ => text.push(c),
+        }
+    }
+
+    // A call cut short keeps going to the end
+    for index in open {
+        outputs[index].range.end = text.len();
+    }
+
+    (text, outputs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strip_markers() {
+        let traced = format!("a {} b {} c", traced_body("outer", &format!("x {} y", traced_body("inner", "z"))), traced_body("last", "w"));
+        let (text, outputs) = strip_markers(&traced);
+
+        assert_eq!(text, "a \nx \nz\n y\n b \nw\n c");
+        let spans: Vec<(&str, &str)> = outputs.iter().map(|o| (o.name.as_str(), &text[o.range.clone()])).collect();
+        assert_eq!(spans, vec![("outer", "\nx \nz\n y\n"), ("inner", "\nz\n"), ("last", "\nw\n")]);
+    }
+}
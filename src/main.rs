@@ -1,19 +1,72 @@
 use std::path::PathBuf;
 
-use clap::{Parser, Subcommand};
+use clap::{CommandFactory, Parser, Subcommand, ValueHint};
 
+mod asset_manifest;
+mod asset_minify;
+mod assets;
+mod auto_list;
+mod budgets;
 mod build;
+mod build_cache;
+mod clean;
+mod code_languages;
+mod collections;
+mod computed_svg;
 mod config;
+mod config_show;
 mod console;
+mod content_templates;
+mod cors;
+mod csp;
+mod dates;
+mod deps;
 mod dev;
+mod dev_state;
+mod duplicates;
 mod doc;
+mod doc_search;
 mod error;
 mod feed;
+mod freshness;
+mod gitignore;
+mod headers;
 mod highlight;
+mod image_size;
+mod inline;
+mod inline_svg;
+mod lastmod;
+mod macro_trace;
+mod mem_stats;
+mod migrate;
 mod minify;
+mod missing_assets;
 mod new;
+mod notfound;
+mod orphans;
+mod output_scan;
+mod page_tokens;
+mod pretty;
+mod protect;
+mod readtime;
+mod redirects;
+mod reload_hooks;
+mod render;
+mod render_cache;
+mod render_stats;
 mod run;
+mod server;
+mod shell;
 mod sitemap;
+mod slow_pages;
+mod sri;
+mod template_help;
+mod template_regex;
+mod text_output;
+mod timings;
+mod upgrade;
+mod url;
+mod xml_check;
 
 #[derive(Parser, Debug)]
 #[command(
@@ -37,28 +90,149 @@ enum Command {
     /// I'll run a development server with live reloading
     Dev {
         /// Path to the site directory (defaults to current directory)
-        #[arg(default_value = ".")]
+        #[arg(default_value = ".", value_hint = ValueHint::DirPath)]
         path: PathBuf,
 
-        /// Port to run on (if specified, I'll fail when unavailable; otherwise I'll retry)
+        /// Port to run on (if specified, I'll fail when unavailable; otherwise I'll start from the port I used last time)
         #[arg(short, long)]
         port: Option<u16>,
+
+        /// Open the browser at the page you were last looking at
+        #[arg(long)]
+        open: bool,
+
+        /// Tell you more about what I'm doing, like which pages come from the render cache
+        #[arg(short, long)]
+        verbose: bool,
     },
     /// I'll build your static site
     Build {
         /// Path to the site directory (defaults to current directory)
-        #[arg(default_value = ".")]
+        #[arg(default_value = ".", value_hint = ValueHint::DirPath)]
         path: PathBuf,
 
         /// Output directory for the built site
-        #[arg(short, long, default_value = "dist")]
+        #[arg(short, long, default_value = "dist", value_hint = ValueHint::DirPath)]
+        output: PathBuf,
+
+        /// Write just the page at URL to FILE, with its styles and images embedded
+        #[arg(long, num_args = 2, value_names = ["URL", "FILE"], conflicts_with = "output")]
+        single_file: Option<Vec<String>>,
+
+        /// Keep the cache-busted names from an earlier build's asset-manifest.json for files that haven't changed
+        #[arg(long, value_name = "PATH", conflicts_with = "single_file", value_hint = ValueHint::FilePath)]
+        reuse_asset_manifest: Option<PathBuf>,
+
+        /// Print how much memory the build took at its peak and which pages came out biggest
+        #[arg(long)]
+        mem_stats: bool,
+
+        /// Clean the output directory even if it holds your site (I'll ask first)
+        #[arg(long, conflicts_with = "single_file")]
+        force: bool,
+
+        /// Fail the build if it has any warnings (I'll still write everything and report them all)
+        #[arg(long, conflicts_with = "single_file")]
+        strict: bool,
+
+        /// Carry on past pages that fail to render, and report them all at the end
+        #[arg(long, conflicts_with = "single_file")]
+        keep_going: bool,
+
+        /// Run these checks too, as if they were on in `[build]`
+        #[arg(long, value_enum, value_delimiter = ',', conflicts_with = "single_file")]
+        report: Vec<crate::build::Report>,
+
+        /// How to report what checks like `orphan_report` found: as warnings, or as JSON on stdout
+        #[arg(long, value_enum, default_value = "text", conflicts_with = "single_file")]
+        format: crate::build::ReportFormat,
+
+        /// Work everything out again, without reading or writing `.hugs-cache/`
+        #[arg(long, conflicts_with = "single_file")]
+        no_cache: bool,
+
+        /// Show every warning in full, instead of the same warning once for all the pages it's on
+        #[arg(short, long, conflicts_with = "single_file")]
+        verbose: bool,
+    },
+    /// I'll render one page and print it, the way `hugs build` would write it
+    #[command(after_help = "For example, `hugs render /blog/tag/rust --raw | tidy` or `hugs render about.md`")]
+    Render {
+        /// URL of the page, like `/blog/first-post`, or the path to its markdown file
+        target: String,
+
+        /// Path to the site directory (defaults to current directory)
+        #[arg(long, default_value = ".", value_hint = ValueHint::DirPath)]
+        site: PathBuf,
+
+        /// Print only the page's content, after Jinja and markdown, without the templates around it
+        #[arg(long, conflicts_with = "full")]
+        raw: bool,
+
+        /// Print the whole page through the root template (what I do unless you say --raw)
+        #[arg(long)]
+        full: bool,
+    },
+    /// I'll remove your built site
+    Clean {
+        /// Path to the site directory (defaults to current directory)
+        #[arg(default_value = ".", value_hint = ValueHint::DirPath)]
+        path: PathBuf,
+
+        /// Output directory to remove
+        #[arg(short, long, default_value = "dist", value_hint = ValueHint::DirPath)]
         output: PathBuf,
+
+        /// Remove it even if it holds your site (I'll ask first)
+        #[arg(long)]
+        force: bool,
+
+        /// Remove the site's `.hugs-cache/` too, so the next build works everything out again
+        #[arg(long)]
+        cache: bool,
     },
     /// I'll create a new Hugs site for you
-    #[command(after_help = "If you don't provide a name, I'll ask you for one!")]
+    #[command(after_help = "If you don't provide a name, I'll ask you for one! To fill in what a site you started by hand is missing, try `hugs new --into my-site --only _/,config.toml`.")]
     New {
         /// Name for your new site folder (I'll create it in the current directory)
+        #[arg(value_hint = ValueHint::DirPath, conflicts_with = "into")]
         name: Option<PathBuf>,
+
+        /// What to start from
+        #[arg(long, value_enum, default_value = "tutorial")]
+        template: crate::new::SiteTemplate,
+
+        /// Add the template's files to a site you already have, leaving every file that's there alone
+        #[arg(long, value_name = "SITE", value_hint = ValueHint::DirPath)]
+        into: Option<PathBuf>,
+
+        /// With --into, only add files under these paths, like `_/,config.toml`
+        #[arg(long, value_delimiter = ',', requires = "into")]
+        only: Vec<String>,
+
+        /// List the files I'd create without creating anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// I'll show you your site's settings
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+    /// I'll update your pages after you move things around
+    Migrate {
+        #[command(subcommand)]
+        command: MigrateCommand,
+    },
+    /// I'll update your site for changes in how this version of Hugs expects it to be laid out
+    Upgrade {
+        /// Path to the site directory (defaults to current directory)
+        #[arg(default_value = ".", value_hint = ValueHint::DirPath)]
+        path: PathBuf,
+
+        /// Show what would change without changing anything
+        #[arg(long)]
+        dry_run: bool,
     },
     /// I'll open the Hugs documentation in your browser
     Doc {
@@ -71,11 +245,68 @@ enum Command {
         no_open: bool,
 
         /// I'll extract docs to a folder and print the path (useful for giving LLMs context)
-        #[arg(long, num_args = 0..=1)]
+        #[arg(long, num_args = 0..=1, value_hint = ValueHint::DirPath)]
         dump: Option<Option<PathBuf>>,
+
+        /// I'll check online whether a newer Hugs is out, and say so on the docs
+        #[arg(long)]
+        check_update: bool,
+    },
+    /// I'll print a tab completion script for your shell
+    #[command(after_help = "For example, add `source <(hugs completions bash)` to your ~/.bashrc")]
+    Completions {
+        /// Shell to complete commands in
+        shell: clap_complete::Shell,
     },
+    /// I'll print my man page (try `hugs manpage > hugs.1`)
+    Manpage,
 }
 
+#[derive(Subcommand, Debug)]
+enum ConfigCommand {
+    /// I'll print every setting a build would use, defaults included, and where each one came from
+    Show {
+        /// Path to the site directory (defaults to current directory)
+        #[arg(default_value = ".", value_hint = ValueHint::DirPath)]
+        path: PathBuf,
+
+        /// How to print the settings
+        #[arg(long, value_enum, default_value = "toml")]
+        format: crate::config_show::ConfigFormat,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum MigrateCommand {
+    /// I'll point every link to URLs under one path at another, in your pages, `_/` and `[migrate] link_fields`
+    #[command(after_help = "For example, `hugs migrate links --from /posts --to /blog --dry-run`")]
+    Links {
+        /// Path to the site directory (defaults to current directory)
+        #[arg(default_value = ".", value_hint = ValueHint::DirPath)]
+        path: PathBuf,
+
+        /// The old URL path, like `/posts`
+        #[arg(long)]
+        from: String,
+
+        /// The new URL path, like `/blog`
+        #[arg(long)]
+        to: String,
+
+        /// Show what would change without changing anything
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Rewrite even when git says the site has uncommitted changes
+        #[arg(long)]
+        allow_dirty: bool,
+    },
+}
+
+/// Exit code of a `hugs build --strict` that wrote everything but had warnings. Other errors exit
+/// with 1.
+const STRICT_EXIT_CODE: i32 = 2;
+
 #[tokio::main]
 async fn main() -> miette::Result<()> {
     miette::set_hook(Box::new(|_| {
@@ -94,22 +325,67 @@ async fn main() -> miette::Result<()> {
     let args = Args::parse();
 
     match args.command {
-        Command::Dev { path, port } => {
-            crate::dev::run_dev_server(path, port).await?;
+        Command::Dev { path, port, open, verbose } => {
+            crate::dev::run_dev_server(path, port, open, verbose).await?;
+        }
+        Command::Build { path, output, single_file, reuse_asset_manifest, mem_stats, force, strict, keep_going, report, format, no_cache, verbose } => {
+            if let Some([url, file]) = single_file.as_deref() {
+                crate::build::run_single_file_build(path, url, PathBuf::from(file)).await?;
+            } else {
+                let options = crate::build::BuildOptions {
+                    reuse_asset_manifest,
+                    mem_stats,
+                    force,
+                    strict,
+                    keep_going,
+                    reports: report,
+                    format,
+                    use_cache: !no_cache,
+                    verbose,
+                };
+                match crate::build::run_build(path, output, options).await {
+                    // The build finished, so CI can tell "warnings failed it" from "it broke"
+                    Err(e @ crate::error::HugsError::StrictWarnings { .. }) => {
+                        eprintln!("{:?}", miette::Report::new(e));
+                        std::process::exit(STRICT_EXIT_CODE);
+                    }
+                    result => result?,
+                }
+            }
+        }
+        Command::Render { target, site, raw, full: _ } => {
+            let output = if raw { crate::render::RenderOutput::Raw } else { crate::render::RenderOutput::Full };
+            crate::render::run_render(site, &target, output).await?;
         }
-        Command::Build { path, output } => {
-            crate::build::run_build(path, output).await?;
+        Command::Clean { path, output, force, cache } => {
+            crate::clean::run_clean(path, output, force, cache).await?;
         }
-        Command::New { name } => {
-            crate::new::create_site(name).await?;
+        Command::New { name, template, into, only, dry_run } => match into {
+            Some(site) => crate::new::add_to_site(site, template, &only, dry_run).await?,
+            None => crate::new::create_site(name, template, dry_run).await?,
+        },
+        Command::Config { command: ConfigCommand::Show { path, format } } => {
+            crate::config_show::run_config_show(path, format).await?;
         }
-        Command::Doc { port, no_open, dump } => {
+        Command::Migrate { command: MigrateCommand::Links { path, from, to, dry_run, allow_dirty } } => {
+            crate::migrate::run_migrate_links(path, &from, &to, dry_run, allow_dirty).await?;
+        }
+        Command::Upgrade { path, dry_run } => {
+            crate::upgrade::run_upgrade(path, dry_run).await?;
+        }
+        Command::Doc { port, no_open, dump, check_update } => {
             if let Some(maybe_path) = dump {
                 crate::doc::dump_docs(maybe_path).await?;
             } else {
-                crate::doc::run_doc_server(port, no_open).await?;
+                crate::doc::run_doc_server(port, no_open, check_update).await?;
             }
         }
+        Command::Completions { shell } => {
+            crate::shell::write_completions(shell, Args::command(), &mut std::io::stdout())?;
+        }
+        Command::Manpage => {
+            crate::shell::write_manpage(Args::command(), &mut std::io::stdout())?;
+        }
     }
 
     Ok(())
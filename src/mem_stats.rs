@@ -0,0 +1,91 @@
+//! `hugs build --mem-stats`: how much memory the build needed and which pages were the biggest,
+//! so a page that suddenly balloons (and the build with it) shows up before it hurts.
+
+use crate::console;
+
+/// How many of the biggest pages I list
+const LARGEST_PAGES: usize = 5;
+
+/// A page the build wrote
+#[derive(Debug, Clone)]
+pub struct WrittenPage {
+    pub url: String,
+    /// Size of the page's HTML file
+    pub bytes: u64,
+    /// Written as it rendered, without minification
+    pub streamed: bool,
+    /// Hash of the page's HTML, for noticing when it changes between builds
+    pub hash: String,
+}
+
+/// The most memory this process has held at once, where the platform can tell me
+pub fn peak_rss_bytes() -> Option<u64> {
+    #[cfg(unix)]
+    {
+        let mut usage = std::mem::MaybeUninit::<libc::rusage>::zeroed();
+        // SAFETY: getrusage only writes to the struct it's given
+        if unsafe { libc::getrusage(libc::RUSAGE_SELF, usage.as_mut_ptr()) } != 0 {
+            return None;
+        }
+        // SAFETY: getrusage succeeded, so the struct is filled in
+        let max_rss = u64::try_from(unsafe { usage.assume_init() }.ru_maxrss).ok()?;
+
+        // Linux and the BSDs count kilobytes, macOS counts bytes
+        if cfg!(target_os = "macos") { Some(max_rss) } else { Some(max_rss * 1024) }
+    }
+    #[cfg(not(unix))]
+    {
+        None
+    }
+}
+
+/// Print the peak memory use and the biggest pages in `pages`
+pub fn print_mem_stats(pages: &[WrittenPage]) {
+    match peak_rss_bytes() {
+        Some(bytes) => console::status("Memory", format!("peak RSS {}", format_size(bytes))),
+        None => console::status("Memory", "peak RSS isn't available on this platform"),
+    }
+
+    for page in largest_pages(pages) {
+        let streamed = if page.streamed { " (streamed)" } else { "" };
+        console::status("Page", format!("{} {}{}", format_size(page.bytes), page.url, streamed));
+    }
+}
+
+/// The biggest pages, biggest first
+fn largest_pages(pages: &[WrittenPage]) -> Vec<&WrittenPage> {
+    let mut largest: Vec<&WrittenPage> = pages.iter().collect();
+    largest.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.url.cmp(&b.url)));
+    largest.truncate(LARGEST_PAGES);
+    largest
+}
+
+/// `bytes` in KB, or MB once it's that big
+pub fn format_size(bytes: u64) -> String {
+    if bytes >= 1024 * 1024 {
+        format!("{:.1} MB", bytes as f64 / (1024.0 * 1024.0))
+    } else {
+        format!("{} KB", bytes.div_ceil(1024))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_largest_pages_come_first() {
+        let pages: Vec<WrittenPage> = (0..8)
+            .map(|i| WrittenPage { url: format!("/p{}", i), bytes: i * 100, streamed: false, hash: String::new() })
+            .collect();
+
+        let urls: Vec<&str> = largest_pages(&pages).iter().map(|p| p.url.as_str()).collect();
+        assert_eq!(urls, ["/p7", "/p6", "/p5", "/p4", "/p3"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_peak_rss_is_reported() {
+        assert!(peak_rss_bytes().is_some_and(|bytes| bytes > 0));
+    }
+}
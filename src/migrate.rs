@@ -0,0 +1,481 @@
+//! `hugs migrate links`: after moving a section of the site, point every link to its old URLs at
+//! the new ones. I rewrite markdown links, images and link definitions in the content folder and
+//! in `_/`, plus the frontmatter fields in `[migrate] link_fields`, and leave code, external URLs
+//! and relative links alone.
+//!
+//! Only link targets change, never the lines around them, so each file keeps its line count and
+//! a dry run can show the changes line by line.
+
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use markdown::mdast::Node;
+use owo_colors::OwoColorize;
+use walkdir::WalkDir;
+
+use crate::config::SiteConfig;
+use crate::console;
+use crate::error::{HugsError, HugsResultExt, Result, StyledNum};
+use crate::text_output::write_atomically;
+use crate::url::relative_path_to_url_string;
+
+/// A replacement of part of a file
+#[derive(Debug, Clone, PartialEq)]
+struct Edit {
+    range: Range<usize>,
+    replacement: String,
+}
+
+/// Rewrite links starting with `from` to start with `to` in every page and template of the site at
+/// `site_path`. With `dry_run`, print what would change instead.
+pub async fn run_migrate_links(site_path: PathBuf, from: &str, to: &str, dry_run: bool, allow_dirty: bool) -> Result<()> {
+    for prefix in [from, to] {
+        if !is_site_path(prefix) || prefix.contains(char::is_whitespace) {
+            return Err(HugsError::MigratePrefix { prefix: prefix.to_string() });
+        }
+    }
+
+    let config = SiteConfig::load(&site_path).await?;
+    if !dry_run
+        && !allow_dirty
+        && let Some(count) = uncommitted_files(&site_path).filter(|count| *count > 0)
+    {
+        return Err(HugsError::MigrateDirtyTree { path: (&site_path).into(), count: StyledNum(count) });
+    }
+
+    let mut files_touched = 0;
+    let mut links_rewritten = 0;
+    for file in markdown_files(&site_path, config.paths.content_dir()) {
+        let text = tokio::fs::read_to_string(&file).await.with_file_read(&file)?;
+        let edits = link_edits(&text, &config.migrate.link_fields, from, to);
+        if edits.is_empty() {
+            continue;
+        }
+
+        let rewritten = apply_edits(&text, &edits);
+        let relative = relative_path_to_url_string(file.strip_prefix(&site_path).unwrap_or(&file));
+        if dry_run {
+            print_diff(&relative, &text, &rewritten);
+        } else {
+            write_atomically(&file, &rewritten)?;
+        }
+        files_touched += 1;
+        links_rewritten += edits.len();
+    }
+
+    let verb = if dry_run { "Would rewrite" } else { "Rewrote" };
+    console::status(verb, format!("{} links in {} files, from {} to {}", links_rewritten, files_touched, from, to));
+    Ok(())
+}
+
+/// Whether `prefix` is a path on the site, like `/blog`, rather than a relative or external URL
+fn is_site_path(prefix: &str) -> bool {
+    prefix.starts_with('/') && !prefix.starts_with("//")
+}
+
+/// `target` with its `from` prefix swapped for `to`, when it's a link on this site under `from`.
+/// Whole path segments are compared, so `/blog` doesn't match `/blogroll`.
+fn rewrite_target(target: &str, from: &str, to: &str) -> Option<String> {
+    if !is_site_path(target) {
+        return None;
+    }
+    let rest = target.strip_prefix(from.trim_end_matches('/'))?;
+    if !(rest.is_empty() || rest.starts_with(['/', '?', '#'])) {
+        return None;
+    }
+
+    let rewritten = format!("{}{}", to.trim_end_matches('/'), rest);
+    let rewritten = if rewritten.is_empty() || rewritten.starts_with(['?', '#']) { format!("/{}", rewritten) } else { rewritten };
+    (rewritten != target).then_some(rewritten)
+}
+
+/// The edits that rewrite links from `from` to `to` in a page file: in its frontmatter `fields`
+/// and in the links of its body
+fn link_edits(text: &str, fields: &[String], from: &str, to: &str) -> Vec<Edit> {
+    let body_start = frontmatter_end(text);
+    let mut edits = frontmatter_edits(&text[..body_start], fields, from, to);
+    edits.extend(body_edits(&text[body_start..], from, to).into_iter().map(|edit| Edit {
+        range: edit.range.start + body_start..edit.range.end + body_start,
+        replacement: edit.replacement,
+    }));
+    edits
+}
+
+/// Where the body starts: after the closing `---` of the frontmatter, or at 0 without one
+fn frontmatter_end(text: &str) -> usize {
+    let mut offset = 0;
+    for (i, line) in text.split_inclusive('\n').enumerate() {
+        offset += line.len();
+        let fence = line.trim_end() == "---";
+        if i == 0 && !fence {
+            return 0;
+        }
+        if i > 0 && fence {
+            return offset;
+        }
+    }
+    // Frontmatter that's never closed isn't frontmatter
+    0
+}
+
+/// Edits to the values of `fields` in frontmatter: `image: /old/a.png`, quoted or not, or the items
+/// of a list under the field. Nested fields and `[a, b]` lists are left alone.
+fn frontmatter_edits(frontmatter: &str, fields: &[String], from: &str, to: &str) -> Vec<Edit> {
+    let mut edits = Vec::new();
+    let mut in_field = false;
+    let mut offset = 0;
+
+    for line in frontmatter.split_inclusive('\n') {
+        let start = offset;
+        offset += line.len();
+        let content = line.trim_end();
+
+        let indent = content.len() - content.trim_start().len();
+        let value_start = if !content.starts_with([' ', '\t', '-']) {
+            let Some(colon) = content.find(':') else {
+                in_field = false;
+                continue;
+            };
+            in_field = fields.iter().any(|field| field == content[..colon].trim().trim_matches(['"', '\'']));
+            colon + 1
+        } else if content[indent..].starts_with('-') {
+            indent + 1
+        } else {
+            continue;
+        };
+
+        if !in_field {
+            continue;
+        }
+        if let Some((range, target)) = scalar_range(&content[value_start..])
+            && let Some(replacement) = rewrite_target(target, from, to)
+        {
+            edits.push(Edit { range: start + value_start + range.start..start + value_start + range.end, replacement });
+        }
+    }
+
+    edits
+}
+
+/// Where the plain or quoted YAML scalar in `value` (what follows `key:` or `-`) is, and the scalar
+fn scalar_range(value: &str) -> Option<(Range<usize>, &str)> {
+    let trimmed = value.trim_start();
+    let start = value.len() - trimmed.len();
+
+    match trimmed.chars().next()? {
+        quote @ ('"' | '\'') => {
+            let end = trimmed[1..].find(quote)? + 1;
+            Some((start + 1..start + end, &trimmed[1..end]))
+        }
+        _ => {
+            let end = trimmed.find(" #").unwrap_or(trimmed.len());
+            let scalar = trimmed[..end].trim_end();
+            Some((start..start + scalar.len(), scalar))
+        }
+    }
+}
+
+/// Edits to the targets of links, images and link definitions in a markdown body. Code blocks and
+/// inline code aren't parsed as links, so they're never touched.
+fn body_edits(body: &str, from: &str, to: &str) -> Vec<Edit> {
+    let Ok(tree) = markdown::to_mdast(body, &markdown::ParseOptions::gfm()) else {
+        return Vec::new();
+    };
+
+    let mut edits = Vec::new();
+    let mut stack = vec![&tree];
+    while let Some(node) = stack.pop() {
+        if let Some(children) = node.children() {
+            stack.extend(children);
+        }
+
+        // The target follows the text's `](` in a link or image, and the label's `]:` in a definition
+        let marker = match node {
+            Node::Link(_) | Node::Image(_) => "](",
+            Node::Definition(_) => "]:",
+            _ => continue,
+        };
+        let Some(position) = node.position() else { continue };
+        let span = &body[position.start.offset..position.end.offset];
+        let found = if marker == "](" { span.rfind(marker) } else { span.find(marker) };
+        let Some(found) = found else { continue };
+
+        let target_start = position.start.offset + found + marker.len();
+        if let Some(range) = destination_range(&body[target_start..position.end.offset]) {
+            let range = range.start + target_start..range.end + target_start;
+            if let Some(replacement) = rewrite_target(&body[range.clone()], from, to) {
+                edits.push(Edit { range, replacement });
+            }
+        }
+    }
+
+    edits
+}
+
+/// Where the link destination is at the start of `text`: inside `<...>`, or up to whitespace or
+/// the `)` closing the link
+fn destination_range(text: &str) -> Option<Range<usize>> {
+    let start = text.len() - text.trim_start().len();
+    let rest = &text[start..];
+
+    if let Some(inner) = rest.strip_prefix('<') {
+        let end = inner.find('>')?;
+        return Some(start + 1..start + 1 + end);
+    }
+
+    let mut depth = 0;
+    let mut end = rest.len();
+    for (i, c) in rest.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' if depth == 0 => {
+                end = i;
+                break;
+            }
+            ')' => depth -= 1,
+            c if c.is_whitespace() => {
+                end = i;
+                break;
+            }
+            _ => {}
+        }
+    }
+    (end > 0).then_some(start..start + end)
+}
+
+fn apply_edits(text: &str, edits: &[Edit]) -> String {
+    let mut edits: Vec<&Edit> = edits.iter().collect();
+    edits.sort_by_key(|edit| edit.range.start);
+
+    let mut out = String::with_capacity(text.len());
+    let mut pos = 0;
+    for edit in edits {
+        out.push_str(&text[pos..edit.range.start]);
+        out.push_str(&edit.replacement);
+        pos = edit.range.end;
+    }
+    out.push_str(&text[pos..]);
+    out
+}
+
+/// The markdown files of the site: pages in the content folder, and templates in `_/`. Hidden
+/// folders like `.git` are skipped.
+fn markdown_files(site_path: &Path, content_dir: &Path) -> Vec<PathBuf> {
+    let content = site_path.join(content_dir);
+    let mut roots = vec![content.clone()];
+    let templates = site_path.join("_");
+    if !templates.starts_with(&content) {
+        roots.push(templates);
+    }
+
+    let mut files: Vec<PathBuf> = roots
+        .iter()
+        .flat_map(|root| {
+            WalkDir::new(root)
+                .into_iter()
+                .filter_entry(|e| e.depth() == 0 || !e.file_name().to_string_lossy().starts_with('.'))
+                .filter_map(|e| e.ok())
+                .filter(|e| e.file_type().is_file() && e.path().extension().is_some_and(|ext| ext == "md"))
+                .map(|e| e.into_path())
+        })
+        .collect();
+    files.sort();
+    files
+}
+
+/// The lines of `file` that changed: each one removed from `before` and each one added in
+/// `after`, numbered as they are in their own version
+pub fn print_diff(file: &str, before: &str, after: &str) {
+    let before: Vec<&str> = before.lines().collect();
+    let after: Vec<&str> = after.lines().collect();
+    // How many lines the rest of each version has in common, from each pair of starting lines
+    let mut common = vec![vec![0usize; after.len() + 1]; before.len() + 1];
+    for old in (0..before.len()).rev() {
+        for new in (0..after.len()).rev() {
+            common[old][new] = if before[old] == after[new] {
+                common[old + 1][new + 1] + 1
+            } else {
+                common[old + 1][new].max(common[old][new + 1])
+            };
+        }
+    }
+
+    println!("{}", file.bold());
+    let (mut old, mut new) = (0, 0);
+    while old < before.len() || new < after.len() {
+        if old < before.len() && new < after.len() && before[old] == after[new] {
+            old += 1;
+            new += 1;
+        } else if old < before.len() && (new == after.len() || common[old + 1][new] >= common[old][new + 1]) {
+            println!("{:>5} {}", old + 1, format!("- {}", before[old]).red());
+            old += 1;
+        } else {
+            println!("{:>5} {}", new + 1, format!("+ {}", after[new]).green());
+            new += 1;
+        }
+    }
+    println!();
+}
+
+/// How many files under `site_path` git reports as changed or untracked, or `None` when the site
+/// isn't in a git repository or git can't tell
+fn uncommitted_files(site_path: &Path) -> Option<usize> {
+    let site_path = site_path.canonicalize().ok()?;
+    site_path.ancestors().find(|dir| dir.join(".git").exists())?;
+
+    let output = Command::new("git").args(["status", "--porcelain", "--untracked-files=all", "--", "."]).current_dir(&site_path).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).lines().count())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn migrate(text: &str) -> String {
+        apply_edits(text, &link_edits(text, &[String::from("image"), String::from("related")], "/old", "/new/"))
+    }
+
+    #[test]
+    fn test_targets_under_the_prefix_are_rewritten() {
+        for (target, rewritten) in [
+            ("/old", Some("/new")),
+            ("/old/", Some("/new/")),
+            ("/old/post?x=1#top", Some("/new/post?x=1#top")),
+            ("/old#top", Some("/new#top")),
+            ("/oldies/post", None),
+            ("/other/old/post", None),
+            ("old/post", None),
+            ("//old/post", None),
+            ("https://example.com/old/post", None),
+        ] {
+            assert_eq!(rewrite_target(target, "/old", "/new/").as_deref(), rewritten, "{target}");
+        }
+        assert_eq!(rewrite_target("/old/a", "/old/", "/").as_deref(), Some("/a"));
+        assert_eq!(rewrite_target("/old", "/old", "/").as_deref(), Some("/"));
+        assert_eq!(rewrite_target("/a", "/", "/docs").as_deref(), Some("/docs/a"));
+    }
+
+    #[test]
+    fn test_links_images_and_definitions_are_rewritten_but_not_code() {
+        let text = "---
+title: Post
+image: /old/cover.png
+related:
+  - \"/old/a\"
+  - /other/b
+description: See /old/a
+---
+A [post](/old/a \"Title\"), [another](</old/b c>) and [ref][1].
+[![Cover](/old/cover.png)](/old/c)
+[outside](https://example.com/old/a) and [prefix](/oldies)
+
+```md
+[code](/old/a)
+```
+
+Inline `[code](/old/a)`.
+
+[1]: /old/d
+";
+        let expected = "---
+title: Post
+image: /new/cover.png
+related:
+  - \"/new/a\"
+  - /other/b
+description: See /old/a
+---
+A [post](/new/a \"Title\"), [another](</new/b c>) and [ref][1].
+[![Cover](/new/cover.png)](/new/c)
+[outside](https://example.com/old/a) and [prefix](/oldies)
+
+```md
+[code](/old/a)
+```
+
+Inline `[code](/old/a)`.
+
+[1]: /new/d
+";
+        assert_eq!(migrate(text), expected);
+        assert_eq!(link_edits(text, &[String::from("image"), String::from("related")], "/old", "/new").len(), 7);
+    }
+
+    #[test]
+    fn test_pages_without_frontmatter_are_all_body() {
+        assert_eq!(migrate("[Home](/)\n[Old](/old/x)\n"), "[Home](/)\n[Old](/new/x)\n");
+        assert_eq!(frontmatter_end("---\ntitle: a\n---\nbody"), 17);
+        assert_eq!(frontmatter_end("--- not frontmatter\n"), 0);
+    }
+
+    #[tokio::test]
+    async fn test_files_are_rewritten_in_place() {
+        let site = tempfile::tempdir().unwrap();
+        let write = |file: &str, content: &str| {
+            let path = site.path().join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        };
+        write("config.toml", "[site]\ntitle = \"Site\"");
+        write("_/nav.md", "[Blog](/old/)");
+        write("blog/post.md", "---\ntitle: Post\n---\n[Next](/old/next)");
+        write(".hidden/notes.md", "[Next](/old/next)");
+
+        run_migrate_links(site.path().to_path_buf(), "/old", "/blog", true, false).await.unwrap();
+        assert_eq!(std::fs::read_to_string(site.path().join("_/nav.md")).unwrap(), "[Blog](/old/)");
+
+        run_migrate_links(site.path().to_path_buf(), "/old", "/blog", false, true).await.unwrap();
+        assert_eq!(std::fs::read_to_string(site.path().join("_/nav.md")).unwrap(), "[Blog](/blog/)");
+        assert_eq!(std::fs::read_to_string(site.path().join("blog/post.md")).unwrap(), "---\ntitle: Post\n---\n[Next](/blog/next)");
+        assert_eq!(std::fs::read_to_string(site.path().join(".hidden/notes.md")).unwrap(), "[Next](/old/next)");
+
+        let err = run_migrate_links(site.path().to_path_buf(), "old", "/blog", true, false).await.unwrap_err();
+        assert!(matches!(err, HugsError::MigratePrefix { prefix } if prefix == "old"));
+    }
+
+    #[tokio::test]
+    async fn test_dirty_site_refuses_to_migrate() {
+        let repo = tempfile::tempdir().unwrap();
+        let site = repo.path().join("site");
+        std::fs::create_dir_all(&site).unwrap();
+        std::fs::write(site.join("config.toml"), "[site]\ntitle = \"Site\"").unwrap();
+        std::fs::write(site.join("index.md"), "[Blog](/old/)").unwrap();
+        assert_eq!(uncommitted_files(&site), None);
+
+        let git = |args: &[&str]| {
+            let output = Command::new("git")
+                .args(["-c", "user.name=Test", "-c", "user.email=test@example.com"])
+                .args(args)
+                .current_dir(repo.path())
+                .output();
+            output.map(|output| output.status.success()).unwrap_or(false)
+        };
+        if !git(&["init", "--quiet"]) {
+            // No git on this machine, so there's nothing to check against
+            return;
+        }
+        assert_eq!(uncommitted_files(&site), Some(2));
+
+        assert!(git(&["add", "--all"]));
+        assert!(git(&["commit", "--quiet", "--message", "Initial"]));
+        assert_eq!(uncommitted_files(&site), Some(0));
+
+        // Changes outside the site don't count against it
+        std::fs::write(repo.path().join("README.md"), "Notes").unwrap();
+        assert_eq!(uncommitted_files(&site), Some(0));
+
+        std::fs::write(site.join("index.md"), "[Blog](/old/posts/)").unwrap();
+        assert_eq!(uncommitted_files(&site), Some(1));
+        let err = run_migrate_links(site.clone(), "/old", "/blog", false, false).await.unwrap_err();
+        assert!(matches!(err, HugsError::MigrateDirtyTree { .. }));
+        assert_eq!(std::fs::read_to_string(site.join("index.md")).unwrap(), "[Blog](/old/posts/)");
+
+        run_migrate_links(site.clone(), "/old", "/blog", true, false).await.unwrap();
+        run_migrate_links(site.clone(), "/old", "/blog", false, true).await.unwrap();
+        assert_eq!(std::fs::read_to_string(site.join("index.md")).unwrap(), "[Blog](/blog/posts/)");
+    }
+}
@@ -1,21 +1,36 @@
 use minify_html::{minify, Cfg};
 
+use crate::config::{BuildConfig, HtmlOutput};
+use crate::pretty::prettify_html;
+
 /// Configuration for minification
 #[derive(Debug, Clone, Copy)]
 pub struct MinifyConfig {
+    /// Whether CSS is minified
     pub enabled: bool,
+    pub html_output: HtmlOutput,
 }
 
 impl MinifyConfig {
     pub fn new(enabled: bool) -> Self {
-        Self { enabled }
+        let html_output = if enabled { HtmlOutput::Minify } else { HtmlOutput::Raw };
+        Self { enabled, html_output }
+    }
+
+    pub fn from_build(build: &BuildConfig) -> Self {
+        Self {
+            enabled: build.minify.css(),
+            html_output: build.html_output(),
+        }
     }
 }
 
-/// Minify HTML content
+/// Minify HTML content, or pretty-print it when `html_output = "pretty"`
 pub fn minify_html_content(html: &str, config: &MinifyConfig) -> String {
-    if !config.enabled {
-        return html.to_string();
+    match config.html_output {
+        HtmlOutput::Minify => {}
+        HtmlOutput::Pretty => return prettify_html(html),
+        HtmlOutput::Raw => return html.to_string(),
     }
 
     let cfg = Cfg {
@@ -28,6 +43,14 @@ pub fn minify_html_content(html: &str, config: &MinifyConfig) -> String {
     String::from_utf8(minified).unwrap_or_else(|_| html.to_string())
 }
 
+/// `minify_html_content` for a page that's no longer needed, which raw output hands straight back
+pub fn minify_html_owned(html: String, config: &MinifyConfig) -> String {
+    match config.html_output {
+        HtmlOutput::Raw => html,
+        _ => minify_html_content(&html, config),
+    }
+}
+
 /// Minify CSS content
 pub fn minify_css_content(css: &str, config: &MinifyConfig) -> String {
     if !config.enabled {
@@ -0,0 +1,150 @@
+//! Files that pages ask `hugs dev` for but that aren't there yet, like the hero image of a post
+//! you're still writing. The browser only shows those as a 404 in the network tab, so I note
+//! each one with the page that asked for it (from the `Referer` header) and list them in the
+//! terminal, and at `/__hugs/api` with `dev.debug_tools` on.
+//!
+//! A file drops off the list once it's served, and the whole list is forgotten when the site
+//! reloads, since the change may well have added the files or removed the references.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use percent_encoding::percent_decode_str;
+use serde::Serialize;
+
+/// Most missing files I remember at once; past this the oldest are forgotten
+const MAX_ENTRIES: usize = 200;
+
+/// Extensions of requests for pages rather than files
+const PAGE_EXTENSIONS: &[&str] = &["html", "htm"];
+
+/// A missing file and the page that asked for it
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MissingAsset {
+    pub page: String,
+    pub asset: String,
+    /// How many times it's been asked for
+    pub requests: u32,
+    /// Whether it's been listed in the terminal
+    #[serde(skip)]
+    reported: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct MissingAssets {
+    /// Oldest first
+    entries: Vec<MissingAsset>,
+    /// Whether a report is already waiting to be printed
+    pub report_scheduled: bool,
+}
+
+impl MissingAssets {
+    /// Note that `page` asked for `asset`, which isn't there. Returns whether it's new.
+    pub fn record(&mut self, page: &str, asset: &str) -> bool {
+        if let Some(entry) = self.entries.iter_mut().find(|entry| entry.page == page && entry.asset == asset) {
+            entry.requests += 1;
+            return false;
+        }
+
+        if self.entries.len() == MAX_ENTRIES {
+            self.entries.remove(0);
+        }
+        self.entries.push(MissingAsset { page: page.to_string(), asset: asset.to_string(), requests: 1, reported: false });
+        true
+    }
+
+    /// `asset` was served, so no page is missing it any more
+    pub fn resolved(&mut self, asset: &str) {
+        self.entries.retain(|entry| entry.asset != asset);
+    }
+
+    /// Forget everything, when the site reloads
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    pub fn entries(&self) -> &[MissingAsset] {
+        &self.entries
+    }
+
+    /// The missing files not listed in the terminal yet, by page, marking them as listed
+    pub fn take_unreported(&mut self) -> BTreeMap<String, Vec<String>> {
+        let mut by_page: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for entry in self.entries.iter_mut().filter(|entry| !entry.reported) {
+            entry.reported = true;
+            by_page.entry(entry.page.clone()).or_default().push(entry.asset.clone());
+        }
+        by_page
+    }
+}
+
+/// Whether a request for `path` is for a file rather than a page: it has an extension, it
+/// isn't `.html`, and it's not a folder
+pub fn is_asset_path(path: &str) -> bool {
+    !path.ends_with('/')
+        && Path::new(path)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| !PAGE_EXTENSIONS.iter().any(|page| ext.eq_ignore_ascii_case(page)))
+}
+
+/// The path of the page in a `Referer` header, when it's a page of this server at `host`.
+/// Pages on other origins are none of my business.
+pub fn referring_page(referer: &str, host: &str) -> Option<String> {
+    let rest = referer.strip_prefix("http://").or_else(|| referer.strip_prefix("https://"))?;
+    let (authority, path) = rest.find('/').map_or((rest, "/"), |slash| rest.split_at(slash));
+    if !authority.eq_ignore_ascii_case(host) {
+        return None;
+    }
+    let path = &path[..path.find(['?', '#']).unwrap_or(path.len())];
+    Some(percent_decode_str(path).decode_utf8_lossy().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_referring_pages_are_on_this_server() {
+        let host = "127.0.0.1:8080";
+        assert_eq!(referring_page("http://127.0.0.1:8080/blog/x?draft=1#top", host).as_deref(), Some("/blog/x"));
+        assert_eq!(referring_page("http://127.0.0.1:8080", host).as_deref(), Some("/"));
+        assert_eq!(referring_page("http://127.0.0.1:8080/caf%C3%A9", host).as_deref(), Some("/café"));
+        for other in ["http://127.0.0.1:9090/blog/x", "https://example.com/127.0.0.1:8080/", "about:blank", ""] {
+            assert_eq!(referring_page(other, host), None, "{other}");
+        }
+    }
+
+    #[test]
+    fn test_asset_paths_have_a_file_extension() {
+        for asset in ["img/hero.png", "fonts/inter.woff2", "theme.css", "data.JSON"] {
+            assert!(is_asset_path(asset), "{asset}");
+        }
+        for page in ["blog/x", "blog/", "about.html", "v1.0/"] {
+            assert!(!is_asset_path(page), "{page}");
+        }
+    }
+
+    #[test]
+    fn test_entries_are_bounded_and_cleared_when_resolved() {
+        let mut missing = MissingAssets::default();
+        assert!(missing.record("/blog/x", "/img/hero.png"));
+        assert!(!missing.record("/blog/x", "/img/hero.png"));
+        assert!(missing.record("/blog/y", "/img/hero.png"));
+        assert!(missing.record("/blog/y", "/img/other.png"));
+        assert_eq!(missing.entries()[0].requests, 2);
+
+        let unreported = missing.take_unreported();
+        assert_eq!(unreported["/blog/y"], ["/img/hero.png", "/img/other.png"]);
+        assert!(missing.take_unreported().is_empty());
+
+        missing.resolved("/img/hero.png");
+        assert_eq!(missing.entries().len(), 1);
+
+        for i in 0..MAX_ENTRIES + 5 {
+            missing.record("/", &format!("/{i}.png"));
+        }
+        assert_eq!(missing.entries().len(), MAX_ENTRIES);
+        assert_eq!(missing.entries()[0].asset, "/5.png");
+    }
+}
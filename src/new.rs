@@ -1,19 +1,52 @@
 use std::borrow::Cow;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use dialoguer::{theme::ColorfulTheme, Input};
-use include_dir::{include_dir, Dir};
+use include_dir::{include_dir, Dir, File};
 use owo_colors::OwoColorize;
 use tokio::fs;
+use tokio::io::AsyncWriteExt;
 
 use crate::console;
 use crate::error::{HugsError, Result, StyledPath};
 
-/// The template directory embedded at compile time
-static TEMPLATE_DIR: Dir<'_> = include_dir!("$CARGO_MANIFEST_DIR/tutorial-site");
+/// The template directories embedded at compile time
+static TUTORIAL_DIR: Dir<'_> = include_dir!("$CARGO_MANIFEST_DIR/tutorial-site");
+static SPLIT_DIR: Dir<'_> = include_dir!("$CARGO_MANIFEST_DIR/split-site");
 
-/// Create a new Hugs site at the given path
-pub async fn create_site(name: Option<PathBuf>) -> Result<()> {
+/// Files the split template takes from the tutorial instead of keeping its own copy
+const SHARED_FILES: &[&str] = &["_/theme.css"];
+
+/// What a new site starts out as
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum SiteTemplate {
+    /// The Hugs tutorial, with pages and templates side by side
+    Tutorial,
+    /// A small site with pages in `content/`, files in `static/` and templates in `_/`
+    Split,
+}
+
+impl SiteTemplate {
+    fn dir(self) -> &'static Dir<'static> {
+        match self {
+            SiteTemplate::Tutorial => &TUTORIAL_DIR,
+            SiteTemplate::Split => &SPLIT_DIR,
+        }
+    }
+
+    /// Every file the template writes, sorted by path
+    fn files(self) -> Vec<&'static File<'static>> {
+        let mut files = template_files(self.dir());
+        if self == SiteTemplate::Split {
+            files.extend(SHARED_FILES.iter().filter_map(|path| TUTORIAL_DIR.get_file(path)));
+            files.sort_by_key(|file| file.path());
+        }
+        files
+    }
+}
+
+/// Create a new Hugs site at the given path. With `dry_run`, list what I'd create instead.
+pub async fn create_site(name: Option<PathBuf>, template: SiteTemplate, dry_run: bool) -> Result<()> {
     let path = match name {
         Some(p) => p,
         None => {
@@ -46,9 +79,18 @@ pub async fn create_site(name: Option<PathBuf>) -> Result<()> {
         }
     }
 
+    let files = template.files();
+    if dry_run {
+        let plan = plan_extraction(&files, &path, &[]);
+        print_plan(&plan);
+        return Ok(());
+    }
+
     console::status("Creating", format!("new site at {}", path.display()));
 
-    extract_dir(&TEMPLATE_DIR, &path).await?;
+    for file in &files {
+        write_new_file(&path.join(file.path()), file.contents()).await?;
+    }
 
     // Print success message
     let path_display = path.display().to_string();
@@ -68,6 +110,119 @@ pub async fn create_site(name: Option<PathBuf>) -> Result<()> {
     Ok(())
 }
 
+/// Add the template's files that `site` doesn't have yet, limited to those under `only` (like
+/// `_/` or `config.toml`) when it's not empty. Files already there are never touched.
+pub async fn add_to_site(site: PathBuf, template: SiteTemplate, only: &[String], dry_run: bool) -> Result<()> {
+    if !site.is_dir() {
+        return Err(HugsError::NewIntoMissing { path: StyledPath::from(&site) });
+    }
+
+    let files = template.files();
+    for prefix in only {
+        if !files.iter().any(|file| is_selected(file.path(), std::slice::from_ref(prefix))) {
+            console::warn(format!("the template has nothing at `{}`, so I'm not adding anything for it", prefix));
+        }
+    }
+
+    let mut plan = plan_extraction(&files, &site, only);
+    if dry_run {
+        print_plan(&plan);
+        return Ok(());
+    }
+
+    let mut created = Vec::new();
+    for relative in plan.create {
+        let file = files.iter().find(|file| file.path() == relative).expect("planned files come from the template");
+        // Something may have appeared since the plan; it still wins
+        if write_new_file(&site.join(&relative), file.contents()).await? {
+            console::status("Created", relative.display());
+            created.push(relative);
+        } else {
+            plan.skip.push(relative);
+        }
+    }
+
+    console::status(
+        "Finished",
+        format!("added {} files to {}, and left {} that were already there", created.len(), site.display(), plan.skip.len()),
+    );
+    Ok(())
+}
+
+/// What putting a template's files into a folder does with each of them
+#[derive(Debug, Default, PartialEq)]
+struct Extraction {
+    /// Files to write, relative to the folder
+    create: Vec<PathBuf>,
+    /// Files left alone, because the folder already has something at their path
+    skip: Vec<PathBuf>,
+}
+
+/// Every file of an embedded template, relative to its root, in path order
+fn template_files(dir: &'static Dir<'static>) -> Vec<&'static File<'static>> {
+    let mut files: Vec<&File> = dir.files().collect();
+    for subdir in dir.dirs() {
+        files.extend(template_files(subdir));
+    }
+    files.sort_by_key(|file| file.path());
+    files
+}
+
+/// Whether `file` (relative to the template) is under one of `only`: `_/` covers everything in
+/// `_`, and `config.toml` just that file. Nothing in `only` means every file.
+fn is_selected(file: &Path, only: &[String]) -> bool {
+    only.is_empty()
+        || only.iter().any(|prefix| {
+            let prefix = prefix.trim_start_matches("./").trim_end_matches('/');
+            !prefix.is_empty() && file.starts_with(prefix)
+        })
+}
+
+/// Which of `files` would be written into `target`, and which are already there
+fn plan_extraction(files: &[&File], target: &Path, only: &[String]) -> Extraction {
+    let mut plan = Extraction::default();
+    for file in files.iter().filter(|file| is_selected(file.path(), only)) {
+        let relative = file.path().to_path_buf();
+        if target.join(&relative).symlink_metadata().is_ok() {
+            plan.skip.push(relative);
+        } else {
+            plan.create.push(relative);
+        }
+    }
+    plan
+}
+
+fn print_plan(plan: &Extraction) {
+    for file in &plan.create {
+        console::status_cyan("Would create", file.display());
+    }
+    console::status_cyan(
+        "Dry run",
+        format!("I'd create {} files, and leave {} that are already there", plan.create.len(), plan.skip.len()),
+    );
+}
+
+/// Write a file that doesn't exist yet, creating its folder. Returns false, without writing, when
+/// something is already at `path`.
+async fn write_new_file(path: &Path, contents: &[u8]) -> Result<bool> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).await.map_err(|e| HugsError::CreateDir {
+            path: StyledPath::from(parent),
+            cause: e,
+        })?;
+    }
+
+    let write_error = |e| HugsError::FileWrite { path: StyledPath::from(path), cause: e };
+    let mut file = match fs::OpenOptions::new().write(true).create_new(true).open(path).await {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => return Ok(false),
+        Err(e) => return Err(write_error(e)),
+    };
+    file.write_all(contents).await.map_err(write_error)?;
+    file.flush().await.map_err(write_error)?;
+    Ok(true)
+}
+
 /// Quote a string for shell usage if it contains special characters
 fn shell_quote(s: &str) -> Cow<'_, str> {
     let needs_quoting = s.is_empty()
@@ -89,34 +244,63 @@ fn shell_quote(s: &str) -> Cow<'_, str> {
     }
 }
 
-/// Recursively extract an embedded directory to the filesystem
-async fn extract_dir(dir: &Dir<'_>, target: &PathBuf) -> Result<()> {
-    // Create the target directory
-    fs::create_dir_all(target)
-        .await
-        .map_err(|e| HugsError::CreateDir {
-            path: StyledPath::from(target),
-            cause: e,
-        })?;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    // Process all entries
-    for entry in dir.entries() {
-        match entry {
-            include_dir::DirEntry::Dir(subdir) => {
-                let subdir_path = target.join(subdir.path().file_name().unwrap());
-                Box::pin(extract_dir(subdir, &subdir_path)).await?;
-            }
-            include_dir::DirEntry::File(file) => {
-                let file_path = target.join(file.path().file_name().unwrap());
-                fs::write(&file_path, file.contents())
-                    .await
-                    .map_err(|e| HugsError::FileWrite {
-                        path: StyledPath::from(&file_path),
-                        cause: e,
-                    })?;
-            }
-        }
+    fn write(site: &Path, file: &str, content: &str) {
+        let path = site.join(file);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
     }
 
-    Ok(())
+    #[test]
+    fn test_only_missing_files_under_the_prefixes_are_planned() {
+        let site = tempfile::tempdir().unwrap();
+        write(site.path(), "config.toml", "[site]\ntitle = \"Mine\"");
+        write(site.path(), "_/nav.md", "[Home](/)");
+        let only = [String::from("_/"), String::from("config.toml")];
+
+        let plan = plan_extraction(&SiteTemplate::Tutorial.files(), site.path(), &only);
+        assert_eq!(plan.skip, [PathBuf::from("_/nav.md"), PathBuf::from("config.toml")]);
+        assert!(plan.create.contains(&PathBuf::from("_/header.md")), "{:?}", plan.create);
+        assert!(plan.create.iter().any(|file| file.starts_with("_/macros")), "{:?}", plan.create);
+        assert!(plan.create.iter().all(|file| file.starts_with("_")), "{:?}", plan.create);
+
+        assert!(is_selected(Path::new("_/macros/note.md"), &[String::from("./_")]));
+        assert!(!is_selected(Path::new("config.toml.example"), &[String::from("config.toml")]));
+        assert!(is_selected(Path::new("index.md"), &[]));
+    }
+
+    #[tokio::test]
+    async fn test_adding_to_a_site_never_changes_its_files() {
+        let site = tempfile::tempdir().unwrap();
+        write(site.path(), "config.toml", "[site]\ntitle = \"Mine\"");
+        write(site.path(), "index.md", "---\ntitle: Mine\n---\nMy own home page");
+        write(site.path(), "_/footer.md", "My footer");
+        let existing = ["config.toml", "index.md", "_/footer.md"].map(|file| {
+            let path = site.path().join(file);
+            (path.clone(), std::fs::read(&path).unwrap(), std::fs::metadata(&path).unwrap().modified().unwrap())
+        });
+
+        add_to_site(site.path().to_path_buf(), SiteTemplate::Split, &[], true).await.unwrap();
+        assert!(!site.path().join("_/header.md").exists(), "a dry run wrote files");
+
+        add_to_site(site.path().to_path_buf(), SiteTemplate::Split, &[], false).await.unwrap();
+        for (path, content, modified) in &existing {
+            assert_eq!(&std::fs::read(path).unwrap(), content, "{}", path.display());
+            assert_eq!(&std::fs::metadata(path).unwrap().modified().unwrap(), modified, "{}", path.display());
+        }
+        assert!(site.path().join("_/header.md").is_file() && site.path().join("content/about.md").is_file());
+        assert_eq!(std::fs::read(site.path().join("_/theme.css")).unwrap(), TUTORIAL_DIR.get_file("_/theme.css").unwrap().contents());
+
+        // Running it again finds nothing left to add
+        let plan = plan_extraction(&SiteTemplate::Split.files(), site.path(), &[]);
+        assert!(plan.create.is_empty(), "{:?}", plan.create);
+        assert!(!write_new_file(&site.path().join("index.md"), b"replaced").await.unwrap());
+        assert_eq!(std::fs::read(site.path().join("index.md")).unwrap(), existing[1].1);
+
+        let missing = add_to_site(site.path().join("nope"), SiteTemplate::Split, &[], false).await.unwrap_err();
+        assert!(matches!(missing, HugsError::NewIntoMissing { .. }));
+    }
 }
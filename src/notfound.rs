@@ -0,0 +1,75 @@
+//! Links in the 404 page, which hosts serve for whatever path was asked for.
+//!
+//! A relative link in `[404].md`, like `[home](index)`, would resolve against `/a/b/c/missing`
+//! wherever `<base>` doesn't reach, so I point the page's own links at the site root as it
+//! renders, the way `base: "/"` reads them. The header, nav, footer and `head_extra` are shared
+//! with every other page, so those I leave be, and `hugs build` warns about any relative URL
+//! they leave in `404.html`.
+
+use crate::inline::{StartTag, URL_ATTRIBUTES, has_scheme, local_path, rewrite_start_tags};
+
+/// Whether `url` resolves against the page it's on
+fn is_relative(url: &str) -> bool {
+    let url = url.trim();
+    !url.is_empty() && !url.starts_with(['/', '#']) && !has_scheme(url)
+}
+
+/// `url` from the site root, keeping its query and fragment, if it's relative
+fn root_absolute(url: &str) -> Option<String> {
+    if !is_relative(url) {
+        return None;
+    }
+    let path = local_path("/", url)?;
+    let suffix = url.find(['?', '#']).map(|i| &url[i..]).unwrap_or("");
+    Some(format!("{}{}", path, suffix.trim_end()))
+}
+
+/// `html` with its relative links and sources pointed at the site root
+pub fn absolutize_links(html: &str) -> String {
+    rewrite_start_tags(html, |tag| {
+        let mut tag: StartTag = tag.clone();
+        let mut changed = false;
+        for name in URL_ATTRIBUTES {
+            if let Some(url) = tag.attr(name).and_then(root_absolute) {
+                tag.set_attr(name, url);
+                changed = true;
+            }
+        }
+        changed.then(|| tag.to_html())
+    })
+}
+
+/// The relative `href` and `src` values in a rendered 404 page, without repeats
+pub fn relative_urls(html: &str) -> Vec<String> {
+    let mut urls: Vec<String> = Vec::new();
+    rewrite_start_tags(html, |tag| {
+        if tag.name == "base" {
+            return None;
+        }
+        for url in [tag.attr("href"), tag.attr("src")].into_iter().flatten() {
+            if is_relative(url) && !urls.iter().any(|seen| seen == url) {
+                urls.push(url.to_string());
+            }
+        }
+        None
+    });
+    urls
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_relative_links_start_at_the_root() {
+        let html = r##"<p><a href="index">home</a> <a href="blog/../about?x=1#team">about</a> <a href="/blog">blog</a> <a href="#top">top</a> <a href="https://example.com/a">out</a> <a href="mailto:me@example.com">me</a> <img src="img/cat.png" alt="cat"></p>"##;
+        assert_eq!(
+            absolutize_links(html),
+            r##"<p><a href="/index">home</a> <a href="/about?x=1#team">about</a> <a href="/blog">blog</a> <a href="#top">top</a> <a href="https://example.com/a">out</a> <a href="mailto:me@example.com">me</a> <img src="/img/cat.png" alt="cat"></p>"##
+        );
+
+        let page = r#"<base href="/"><link rel="stylesheet" href="style.css"><a href="/">home</a><a href="docs">docs</a><a href="docs">docs</a><script src="//cdn.example.com/x.js"></script>"#;
+        assert_eq!(relative_urls(page), ["style.css", "docs"]);
+        assert!(relative_urls(&absolutize_links(page)).is_empty());
+    }
+}
@@ -0,0 +1,212 @@
+//! Pages nothing links to, for `[build] orphan_report` and `[build] dynamic_orphan_report`.
+//!
+//! Links are read from the written pages rather than their markdown, so a directory index that
+//! lists its pages with a `pages()` loop links to every one of them. A page is an orphan when no
+//! other page links to it and `_/nav.md` doesn't either. The home page is never one, and a page
+//! that's meant to be found some other way can say `orphan_ok: true` in its frontmatter.
+//!
+//! Orphans made by a dynamic page are reported apart, one group per `[param].md` file, since the
+//! fix there is usually to make fewer pages rather than to link to them.
+
+use std::collections::{BTreeMap, HashSet};
+
+use serde_json::json;
+
+use crate::error::HugsError;
+use crate::inline::page_links;
+use crate::output_scan::ScannedPage;
+use crate::run::{DynamicContext, PageInfo, page_for_request};
+
+/// Written pages that no other page or the nav links to, in URL order
+pub fn find_orphans<'a>(scanned: &[ScannedPage<'a>], pages: &[PageInfo], nav_html: &str) -> Vec<&'a PageInfo> {
+    let mut linked: HashSet<&str> = HashSet::new();
+    for scan in scanned {
+        for link in &scan.links {
+            if let Some(target) = page_for_request(pages, link).filter(|target| target.url != scan.page.url) {
+                linked.insert(target.url.as_str());
+            }
+        }
+    }
+    for link in page_links(nav_html, "/") {
+        if let Some(target) = page_for_request(pages, &link) {
+            linked.insert(target.url.as_str());
+        }
+    }
+
+    let mut orphans: Vec<&PageInfo> = scanned
+        .iter()
+        .map(|scan| scan.page)
+        .filter(|page| page.url != "/" && !linked.contains(page.url.as_str()) && !orphan_ok(page))
+        .collect();
+    orphans.sort_by(|a, b| a.url.cmp(&b.url));
+    orphans
+}
+
+/// Whether the page says it's fine for nothing to link to it
+fn orphan_ok(page: &PageInfo) -> bool {
+    page.frontmatter.get("orphan_ok").and_then(|value| value.as_bool()).unwrap_or(false)
+}
+
+/// Orphans made by one dynamic page
+pub struct DynamicOrphans<'a> {
+    /// The dynamic page's file, like `tags/[tag].md`
+    pub file: &'a str,
+    /// How many pages the file makes in all
+    pub total: usize,
+    pub orphans: Vec<&'a PageInfo>,
+}
+
+/// Split `orphans` into the static pages and the ones dynamic pages made, grouped by their file
+pub fn split_dynamic_orphans<'a>(orphans: Vec<&'a PageInfo>, pages: &[PageInfo]) -> (Vec<&'a PageInfo>, Vec<DynamicOrphans<'a>>) {
+    let (dynamic, plain): (Vec<&PageInfo>, Vec<&PageInfo>) =
+        orphans.into_iter().partition(|page| DynamicContext::from_page_info(page).is_some());
+
+    let mut by_file: BTreeMap<&str, Vec<&PageInfo>> = BTreeMap::new();
+    for page in dynamic {
+        by_file.entry(page.file_path.as_str()).or_default().push(page);
+    }
+    let groups = by_file
+        .into_iter()
+        .map(|(file, orphans)| DynamicOrphans {
+            file,
+            total: pages.iter().filter(|page| page.file_path == file).count(),
+            orphans,
+        })
+        .collect();
+
+    (plain, groups)
+}
+
+/// URLs named in a dynamic orphan warning before the rest are just counted
+const MAX_LISTED_URLS: usize = 5;
+
+/// A warning for each dynamic page that made orphans
+pub fn dynamic_orphan_warnings(groups: &[DynamicOrphans]) -> Vec<HugsError> {
+    groups
+        .iter()
+        .map(|group| {
+            let urls: Vec<&str> = group.orphans.iter().map(|page| page.url.as_str()).collect();
+            let mut listed = urls[..urls.len().min(MAX_LISTED_URLS)].join(", ");
+            if urls.len() > MAX_LISTED_URLS {
+                listed.push_str(&format!(" and {} more", urls.len() - MAX_LISTED_URLS));
+            }
+
+            HugsError::DynamicOrphans {
+                file: group.file.into(),
+                count: group.orphans.len().to_string(),
+                total: group.total.to_string(),
+                help_text: format!(
+                    "Nothing links to {}. If nothing should, change the values in {}'s frontmatter so I don't make these pages at all. If they're meant to be found another way, set `orphan_ok: true` in its frontmatter.",
+                    listed, group.file
+                ),
+            }
+        })
+        .collect()
+}
+
+/// The dynamic orphans as JSON entries, for `hugs build --format json`
+pub fn dynamic_orphans_json(groups: &[DynamicOrphans]) -> serde_json::Value {
+    groups
+        .iter()
+        .map(|group| {
+            let urls: Vec<&str> = group.orphans.iter().map(|page| page.url.as_str()).collect();
+            json!({ "file": group.file, "pages": group.total, "orphans": urls })
+        })
+        .collect()
+}
+
+/// A warning for each orphan
+pub fn orphan_warnings(orphans: &[&PageInfo]) -> Vec<HugsError> {
+    orphans
+        .iter()
+        .map(|page| HugsError::OrphanPage {
+            url: page.url.to_string().into(),
+            file: page.file_path.as_str().into(),
+        })
+        .collect()
+}
+
+/// The orphans as JSON entries, for `hugs build --format json`
+pub fn orphans_json(orphans: &[&PageInfo]) -> serde_json::Value {
+    orphans.iter().map(|page| json!({ "url": page.url, "file": page.file_path })).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::url::SiteUrl;
+
+    fn page(url: &str, frontmatter: &str) -> PageInfo {
+        PageInfo {
+            url: SiteUrl::parse(url),
+            file_path: format!("{}.md", url.trim_matches('/')),
+            frontmatter: serde_yaml::from_str(frontmatter).unwrap(),
+        }
+    }
+
+    fn scanned<'a>(page: &'a PageInfo, links: &[&str]) -> ScannedPage<'a> {
+        ScannedPage {
+            page,
+            html_len: 0,
+            subresources: Vec::new(),
+            links: links.iter().map(|s| s.to_string()).collect(),
+            stylesheets: Vec::new(),
+            preloaded_fonts: Vec::new(),
+        }
+    }
+
+    fn urls(orphans: &[&PageInfo]) -> Vec<String> {
+        orphans.iter().map(|page| page.url.to_string()).collect()
+    }
+
+    #[test]
+    fn test_pages_without_inbound_links_are_orphans() {
+        let pages = vec![
+            page("/", "title: Home"),
+            page("/blog/", "title: Blog"),
+            page("/blog/post", "title: Post"),
+            page("/lonely", "title: Lonely"),
+            page("/self", "title: Self"),
+            page("/secret", "orphan_ok: true"),
+            page("/about", "title: About"),
+        ];
+        let scans: Vec<ScannedPage> = vec![
+            scanned(&pages[0], &["/blog/", "/assets/logo.svg"]),
+            scanned(&pages[1], &["/blog/post/index.html"]),
+            scanned(&pages[2], &["/blog"]),
+            scanned(&pages[3], &["/"]),
+            // Linking to yourself doesn't count
+            scanned(&pages[4], &["/self"]),
+            scanned(&pages[5], &[]),
+            scanned(&pages[6], &[]),
+        ];
+
+        assert_eq!(urls(&find_orphans(&scans, &pages, r#"<a href="/about/">About</a>"#)), ["/lonely", "/self"]);
+    }
+
+    #[test]
+    fn test_dynamic_orphans_are_grouped_by_file() {
+        let tag = |value: &str| PageInfo {
+            url: SiteUrl::parse(&format!("/tags/{}", value)),
+            file_path: String::from("tags/[tag].md"),
+            frontmatter: serde_yaml::from_str(&format!("tag: {}", value)).unwrap(),
+        };
+        let pages = vec![page("/lonely", "title: Lonely"), tag("rust"), tag("web"), tag("go")];
+        let orphans: Vec<&PageInfo> = vec![&pages[0], &pages[2], &pages[3]];
+
+        let (plain, groups) = split_dynamic_orphans(orphans, &pages);
+        assert_eq!(urls(&plain), ["/lonely"]);
+        assert_eq!(groups.len(), 1);
+        assert_eq!((groups[0].file, groups[0].total, urls(&groups[0].orphans)), ("tags/[tag].md", 3, vec![String::from("/tags/web"), String::from("/tags/go")]));
+        assert_eq!(
+            dynamic_orphans_json(&groups),
+            json!([{ "file": "tags/[tag].md", "pages": 3, "orphans": ["/tags/web", "/tags/go"] }])
+        );
+    }
+
+    #[test]
+    fn test_orphans_as_json() {
+        let lonely = page("/lonely", "title: Lonely");
+        assert_eq!(orphans_json(&[&lonely]), json!([{ "url": "/lonely", "file": "lonely.md" }]));
+    }
+}
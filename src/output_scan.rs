@@ -0,0 +1,144 @@
+//! Reading the written pages back after `hugs build`.
+//!
+//! The checks that care about what each page loads or links to (budgets, stylesheets linked
+//! without `cache_bust()`, and orphan pages) share one pass over the output: every page's HTML is
+//! read and its local stylesheets, scripts, images, preloaded fonts and links are pulled out once.
+
+use std::path::Path;
+
+use crate::error::HugsError;
+use crate::inline::{page_links, page_stylesheets_and_fonts, page_subresources};
+use crate::run::{CacheBustRegistry, PageInfo};
+
+/// Built-in stylesheets that should be linked through `cache_bust()`
+const BUSTABLE_STYLESHEETS: &[&str] = &["/theme.css", "/highlight.css"];
+
+/// Pages named in the stylesheet warning before the rest are just counted
+const MAX_LISTED_PAGES: usize = 5;
+
+/// A written page and what it loads
+pub struct ScannedPage<'a> {
+    pub page: &'a PageInfo,
+    /// Size of the written HTML, in bytes
+    pub html_len: u64,
+    /// Local stylesheets, scripts and images the page loads, as root-relative paths
+    pub subresources: Vec<String>,
+    /// Local pages and files the page links to, as decoded root-relative paths
+    pub links: Vec<String>,
+    /// Local stylesheets the page links, as root-relative paths
+    pub stylesheets: Vec<String>,
+    /// Local fonts the page preloads itself, as root-relative paths
+    pub preloaded_fonts: Vec<String>,
+}
+
+/// Read back every page written to `output_path`. Pages that weren't written are left out.
+pub fn scan_pages<'a>(pages: &'a [PageInfo], output_path: &Path) -> Vec<ScannedPage<'a>> {
+    pages
+        .iter()
+        .filter_map(|page| {
+            let html = std::fs::read_to_string(output_path.join(page.url.output_path())).ok()?;
+            let (stylesheets, preloaded_fonts) = page_stylesheets_and_fonts(&html, page.url.as_str());
+            Some(ScannedPage {
+                page,
+                html_len: html.len() as u64,
+                subresources: page_subresources(&html, page.url.as_str()),
+                links: page_links(&html, page.url.as_str()),
+                stylesheets,
+                preloaded_fonts,
+            })
+        })
+        .collect()
+}
+
+/// One warning for every page that links `/theme.css` or `/highlight.css` directly rather than
+/// through `cache_bust()`. Those keep the same URL when they change, so CDNs and browsers can hold
+/// on to a stale copy.
+pub fn check_unbusted_stylesheets(scanned: &[ScannedPage], registry: &CacheBustRegistry) -> Option<HugsError> {
+    let hashed: Vec<String> = registry.entries().into_values().collect();
+    let is_unbusted = |path: &String| BUSTABLE_STYLESHEETS.contains(&path.as_str()) && !hashed.contains(path);
+
+    let mut stylesheets: Vec<&str> = Vec::new();
+    let mut pages: Vec<&str> = Vec::new();
+    for scan in scanned {
+        let unbusted: Vec<&String> = scan.subresources.iter().filter(|path| is_unbusted(path)).collect();
+        if unbusted.is_empty() {
+            continue;
+        }
+        for path in unbusted {
+            if !stylesheets.contains(&path.as_str()) {
+                stylesheets.push(path);
+            }
+        }
+        pages.push(scan.page.url.as_str());
+    }
+
+    if pages.is_empty() {
+        return None;
+    }
+    stylesheets.sort();
+
+    let mut listed = pages[..pages.len().min(MAX_LISTED_PAGES)].join(", ");
+    if pages.len() > MAX_LISTED_PAGES {
+        listed.push_str(&format!(" and {} more", pages.len() - MAX_LISTED_PAGES));
+    }
+    let calls: Vec<String> = stylesheets
+        .iter()
+        .map(|path| format!("{{{{ cache_bust(path=\"{}\") }}}}", path))
+        .collect();
+
+    Some(HugsError::UnbustedStylesheets {
+        stylesheets: stylesheets.join(" and "),
+        help_text: format!(
+            "Its URL stays the same when it changes, so CDNs and browsers can keep serving an old copy. Link it as {} instead.\n\nPages: {}\n\nIf your host sends short cache headers on purpose, set `warn_unbusted_css = false` in the [build] section of config.toml.",
+            calls.join(" and "),
+            listed
+        ),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::url::SiteUrl;
+
+    fn scanned<'a>(page: &'a PageInfo, subresources: &[&str]) -> ScannedPage<'a> {
+        ScannedPage {
+            page,
+            html_len: 0,
+            subresources: subresources.iter().map(|s| s.to_string()).collect(),
+            links: Vec::new(),
+            stylesheets: Vec::new(),
+            preloaded_fonts: Vec::new(),
+        }
+    }
+
+    fn page(url: &str) -> PageInfo {
+        PageInfo {
+            url: SiteUrl::parse(url),
+            file_path: String::new(),
+            frontmatter: serde_yaml::Value::Null,
+        }
+    }
+
+    #[test]
+    fn test_unbusted_stylesheets_are_reported_once() {
+        let registry = CacheBustRegistry::new();
+        let pages: Vec<PageInfo> = (0..8).map(|i| page(&format!("/p{}", i))).collect();
+        let mut scans: Vec<ScannedPage> = pages.iter().map(|p| scanned(p, &["/theme.css", "/app.js"])).collect();
+        scans[0].subresources.push("/highlight.css".to_string());
+
+        let Some(HugsError::UnbustedStylesheets { stylesheets, help_text }) = check_unbusted_stylesheets(&scans, &registry) else {
+            panic!("expected a warning");
+        };
+        assert_eq!(stylesheets, "/highlight.css and /theme.css");
+        assert!(help_text.contains("Pages: /p0, /p1, /p2, /p3, /p4 and 3 more"), "{help_text}");
+        assert!(help_text.contains("{{ cache_bust(path=\"/theme.css\") }}"), "{help_text}");
+    }
+
+    #[test]
+    fn test_busted_stylesheets_pass() {
+        let registry = CacheBustRegistry::new();
+        let home = page("/");
+        assert!(check_unbusted_stylesheets(&[scanned(&home, &["/theme.1e092a90.css", "/styles/theme.css"])], &registry).is_none());
+    }
+}
@@ -0,0 +1,127 @@
+//! Page tokens: placeholders the header, nav and footer can hold for things about the page
+//! they're shown on.
+//!
+//! Those parts are rendered once for the whole site, so they can't see the current page. Instead,
+//! `{{ page_token("source") }}` puts `%%HUGS_PAGE_SOURCE%%` in the rendered HTML, and I swap it for
+//! the page's value as each page is finished. Page bodies and `_content.md` get the same swap, so
+//! a token means the same thing wherever it's written. It's a plain substitution: there's no way
+//! to branch on a token's value.
+
+use std::borrow::Cow;
+use std::sync::LazyLock;
+
+use regex::{Captures, Regex};
+
+use crate::run::escape_html;
+use crate::url::encode_url_path;
+
+/// Token names, with the placeholder each one puts in the HTML
+const TOKENS: [(&str, &str); 3] = [
+    ("url", "%%HUGS_PAGE_URL%%"),
+    ("source", "%%HUGS_PAGE_SOURCE%%"),
+    ("title", "%%HUGS_PAGE_TITLE%%"),
+];
+
+/// What every placeholder has in it, to skip HTML without any quickly
+const MARKER: &str = "HUGS_PAGE_";
+
+/// A placeholder, including the forms markdown leaves in a link's URL, where it percent-encodes
+/// some of the `%`s
+static PLACEHOLDER: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new("(?:%25|%){2}HUGS_PAGE_(URL|SOURCE|TITLE)(?:%25|%){2}").unwrap());
+
+/// The values a page gives its tokens
+pub struct PageTokens<'a> {
+    /// The page's URL, like `/blog/hello`
+    pub url: &'a str,
+    /// The page's file, relative to the site root, like `blog/hello.md`
+    pub source: &'a str,
+    pub title: &'a str,
+}
+
+impl PageTokens<'_> {
+    /// `html` with every placeholder replaced. Values are escaped so they're safe in both text and
+    /// attribute values, and percent-encoded too where markdown put the placeholder in a URL.
+    pub fn substitute<'h>(&self, html: &'h str) -> Cow<'h, str> {
+        if !html.contains(MARKER) {
+            return Cow::Borrowed(html);
+        }
+
+        PLACEHOLDER.replace_all(html, |caps: &Captures| {
+            let value = match &caps[1] {
+                "URL" => self.url,
+                "SOURCE" => self.source,
+                _ => self.title,
+            };
+            if caps[0].contains("%25") {
+                escape_html(&encode_url_path(value))
+            } else {
+                escape_html(value)
+            }
+        })
+    }
+
+    /// [`substitute`](Self::substitute) for HTML that's already owned, without copying it when
+    /// there's nothing to replace
+    pub fn substitute_owned(&self, html: String) -> String {
+        if !html.contains(MARKER) {
+            return html;
+        }
+        self.substitute(&html).into_owned()
+    }
+}
+
+/// `page_token(name)`: the placeholder for one of the page tokens
+pub fn page_token(name: &str) -> std::result::Result<String, minijinja::Error> {
+    TOKENS
+        .iter()
+        .find(|(token, _)| *token == name)
+        .map(|(_, placeholder)| placeholder.to_string())
+        .ok_or_else(|| {
+            let names: Vec<&str> = TOKENS.iter().map(|(token, _)| *token).collect();
+            minijinja::Error::new(
+                minijinja::ErrorKind::InvalidOperation,
+                format!("there's no page token called {:?}; try one of {}", name, names.join(", ")),
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAGE: PageTokens = PageTokens {
+        url: "/blog/über",
+        source: "blog/über.md",
+        title: "Fish & \"Chips\" <3",
+    };
+
+    #[test]
+    fn test_values_are_escaped_for_text_and_attributes() {
+        let html = format!(
+            r#"<a href="/x" title="{}">{}</a>"#,
+            page_token("title").unwrap(),
+            page_token("title").unwrap()
+        );
+        assert_eq!(
+            PAGE.substitute(&html),
+            r#"<a href="/x" title="Fish &amp; &quot;Chips&quot; &lt;3">Fish &amp; &quot;Chips&quot; &lt;3</a>"#
+        );
+    }
+
+    #[test]
+    fn test_tokens_in_markdown_links() {
+        let markdown = format!("[Edit](https://github.com/me/site/edit/main/{})", page_token("source").unwrap());
+        let html = markdown::to_html(&markdown);
+        assert_eq!(
+            PAGE.substitute(&html),
+            r#"<p><a href="https://github.com/me/site/edit/main/blog/%C3%BCber.md">Edit</a></p>"#
+        );
+    }
+
+    #[test]
+    fn test_html_without_tokens_is_untouched() {
+        assert!(matches!(PAGE.substitute("<nav>Home</nav>"), Cow::Borrowed(_)));
+        assert!(page_token("path").is_err());
+    }
+}
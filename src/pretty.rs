@@ -0,0 +1,477 @@
+//! Diff-friendly HTML formatting for `build.html_output = "pretty"`.
+//!
+//! Block elements go on their own lines, indented by nesting depth, and the inline content
+//! between them is joined onto one line with its whitespace collapsed. `<pre>`, `<code>`,
+//! `<script>`, `<style>` and `<textarea>` are copied exactly as written. The layout depends only
+//! on the tags, never on the template's whitespace, so formatting the output again changes nothing.
+
+use crate::inline::parse_start_tag;
+
+const INDENT: &str = "  ";
+
+/// Elements that start a new line and close an open `<p>`
+const BLOCK_ELEMENTS: &[&str] = &[
+    "address", "article", "aside", "base", "blockquote", "body", "caption", "col", "colgroup", "dd",
+    "details", "dialog", "div", "dl", "dt", "fieldset", "figcaption", "figure", "footer", "form", "h1",
+    "h2", "h3", "h4", "h5", "h6", "head", "header", "hgroup", "hr", "html", "legend", "li", "link",
+    "main", "menu", "meta", "nav", "noscript", "ol", "optgroup", "option", "p", "pre", "search",
+    "section", "summary", "table", "tbody", "td", "tfoot", "th", "thead", "title", "tr", "ul",
+];
+
+/// Elements that never have contents or an end tag
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track", "wbr",
+];
+
+/// Elements whose end tag may be left out
+const OPTIONAL_END_ELEMENTS: &[&str] = &[
+    "body", "caption", "colgroup", "dd", "dt", "head", "html", "li", "optgroup", "option", "p", "tbody",
+    "td", "tfoot", "th", "thead", "tr",
+];
+
+/// Elements whose contents are copied as written
+const VERBATIM_ELEMENTS: &[&str] = &["code", "pre", "script", "style", "textarea"];
+
+/// Pretty-print `html`, one block element per line
+pub fn prettify_html(html: &str) -> String {
+    let mut out = String::with_capacity(html.len() + html.len() / 4);
+    write_children(&parse(html), 0, &mut out);
+    out
+}
+
+/// Names of the elements in `html` that are opened but never closed, in document order
+pub fn unclosed_elements(html: &str) -> Vec<String> {
+    fn collect(nodes: &[Node], unclosed: &mut Vec<String>) {
+        for node in nodes {
+            if let Node::Element { start_tag, name, children, end_tag } = node {
+                let needs_end = !VOID_ELEMENTS.contains(&name.as_str())
+                    && !OPTIONAL_END_ELEMENTS.contains(&name.as_str())
+                    && !start_tag.ends_with("/>");
+                if end_tag.is_none() && needs_end {
+                    unclosed.push(name.clone());
+                }
+                collect(children, unclosed);
+            }
+        }
+    }
+
+    let mut unclosed = Vec::new();
+    collect(&parse(html), &mut unclosed);
+    unclosed
+}
+
+#[derive(Debug)]
+enum Node {
+    Element {
+        /// The start tag with its whitespace normalized
+        start_tag: String,
+        name: String,
+        children: Vec<Node>,
+        /// `None` when the element was closed implicitly
+        end_tag: Option<String>,
+    },
+    /// Copied as written: comments, doctypes, stray end tags and verbatim elements
+    Verbatim { html: String, block: bool },
+    Text(String),
+}
+
+impl Node {
+    /// Whether this node can share a line with its neighbours
+    fn is_inline(&self) -> bool {
+        match self {
+            Node::Element { name, children, .. } => !is_block(name) && children.iter().all(Node::is_inline),
+            Node::Verbatim { block, .. } => !block,
+            Node::Text(_) => true,
+        }
+    }
+}
+
+fn is_block(name: &str) -> bool {
+    BLOCK_ELEMENTS.contains(&name)
+}
+
+/// An element whose end tag hasn't been seen yet
+struct OpenElement {
+    start_tag: String,
+    name: String,
+    children: Vec<Node>,
+}
+
+/// Builds the node tree, closing elements the way browsers do when end tags are left out
+#[derive(Default)]
+struct TreeBuilder {
+    root: Vec<Node>,
+    open: Vec<OpenElement>,
+}
+
+impl TreeBuilder {
+    fn push(&mut self, node: Node) {
+        match self.open.last_mut() {
+            Some(parent) => parent.children.push(node),
+            None => self.root.push(node),
+        }
+    }
+
+    fn close_top(&mut self, end_tag: Option<String>) {
+        if let Some(element) = self.open.pop() {
+            self.push(Node::Element {
+                start_tag: element.start_tag,
+                name: element.name,
+                children: element.children,
+                end_tag,
+            });
+        }
+    }
+
+    /// Close the open elements that a `name` start tag ends
+    fn close_implied(&mut self, name: &str) {
+        while let Some(top) = self.open.last() {
+            let closes = match top.name.as_str() {
+                "p" => is_block(name),
+                "li" => name == "li",
+                "dt" | "dd" => matches!(name, "dt" | "dd"),
+                "td" | "th" => matches!(name, "td" | "th" | "tr"),
+                "tr" => name == "tr",
+                "option" => matches!(name, "option" | "optgroup"),
+                _ => false,
+            };
+            if !closes {
+                break;
+            }
+            self.close_top(None);
+        }
+    }
+
+    /// Close `name` and anything left open inside it. Returns false when it isn't open.
+    fn close(&mut self, name: &str, end_tag: String) -> bool {
+        let Some(index) = self.open.iter().rposition(|element| element.name == name) else {
+            return false;
+        };
+        while self.open.len() > index + 1 {
+            self.close_top(None);
+        }
+        self.close_top(Some(end_tag));
+        true
+    }
+
+    fn finish(mut self) -> Vec<Node> {
+        while !self.open.is_empty() {
+            self.close_top(None);
+        }
+        self.root
+    }
+}
+
+fn parse(html: &str) -> Vec<Node> {
+    let lower = html.to_ascii_lowercase();
+    let mut tree = TreeBuilder::default();
+    let mut pos = 0;
+
+    while let Some(found) = html[pos..].find('<') {
+        let start = pos + found;
+        if start > pos {
+            tree.push(Node::Text(html[pos..start].to_string()));
+        }
+        let rest = &html[start..];
+
+        if rest.starts_with("<!--") {
+            let end = rest.find("-->").map_or(html.len(), |i| start + i + 3);
+            tree.push(Node::Verbatim { html: html[start..end].to_string(), block: false });
+            pos = end;
+        } else if rest.starts_with("<!") || rest.starts_with("<?") {
+            let end = rest.find('>').map_or(html.len(), |i| start + i + 1);
+            tree.push(Node::Verbatim { html: html[start..end].to_string(), block: true });
+            pos = end;
+        } else if let Some(name) = rest.strip_prefix("</").filter(|r| r.starts_with(|c: char| c.is_ascii_alphabetic())) {
+            let name_len = name.find(|c: char| c.is_ascii_whitespace() || c == '>').unwrap_or(name.len());
+            let name = name[..name_len].to_ascii_lowercase();
+            let end = rest.find('>').map_or(html.len(), |i| start + i + 1);
+            let end_tag = normalize_tag(&html[start..end]);
+            if !tree.close(&name, end_tag.clone()) {
+                tree.push(Node::Verbatim { html: end_tag, block: false });
+            }
+            pos = end;
+        } else if let Some((tag, mut end)) = parse_start_tag(html, start) {
+            tree.close_implied(&tag.name);
+
+            if VERBATIM_ELEMENTS.contains(&tag.name.as_str()) && !tag.self_closing {
+                end = verbatim_end(&lower, end, &tag.name);
+                let block = matches!(tag.name.as_str(), "pre" | "script" | "style");
+                tree.push(Node::Verbatim { html: html[start..end].to_string(), block });
+            } else if VOID_ELEMENTS.contains(&tag.name.as_str()) || tag.self_closing {
+                tree.push(Node::Element {
+                    start_tag: normalize_tag(&html[start..end]),
+                    name: tag.name,
+                    children: Vec::new(),
+                    end_tag: None,
+                });
+            } else {
+                tree.open.push(OpenElement {
+                    start_tag: normalize_tag(&html[start..end]),
+                    name: tag.name,
+                    children: Vec::new(),
+                });
+            }
+            pos = end;
+        } else {
+            tree.push(Node::Text(String::from("<")));
+            pos = start + 1;
+        }
+    }
+
+    if pos < html.len() {
+        tree.push(Node::Text(html[pos..].to_string()));
+    }
+    tree.finish()
+}
+
+/// Where a verbatim element whose contents start at `pos` ends, just past its end tag.
+/// `<script>`, `<style>` and `<textarea>` end at the first end tag; `<pre>` and `<code>` can nest.
+fn verbatim_end(lower: &str, pos: usize, name: &str) -> usize {
+    let open = format!("<{}", name);
+    let close = format!("</{}", name);
+    let nests = matches!(name, "pre" | "code");
+    let mut depth = 1;
+    let mut cursor = pos;
+
+    loop {
+        let Some(next_close) = lower[cursor..].find(&close).map(|i| cursor + i) else {
+            return lower.len();
+        };
+
+        if nests
+            && let Some(next_open) = lower[cursor..next_close].find(&open).map(|i| cursor + i)
+        {
+            if lower[next_open + open.len()..].starts_with(|c: char| c == '>' || c.is_ascii_whitespace()) {
+                depth += 1;
+            }
+            cursor = next_open + open.len();
+            continue;
+        }
+
+        let end = lower[next_close..].find('>').map_or(lower.len(), |i| next_close + i + 1);
+        depth -= 1;
+        if depth == 0 {
+            return end;
+        }
+        cursor = end;
+    }
+}
+
+/// Collapse whitespace between attributes to single spaces, leaving quoted values alone
+fn normalize_tag(tag: &str) -> String {
+    let mut out = String::with_capacity(tag.len());
+    let mut quote = None;
+    let mut pending_space = false;
+
+    for c in tag.chars() {
+        match quote {
+            Some(q) => {
+                out.push(c);
+                if c == q {
+                    quote = None;
+                }
+            }
+            None if c.is_ascii_whitespace() => pending_space = true,
+            None => {
+                if pending_space && c != '>' {
+                    out.push(' ');
+                }
+                pending_space = false;
+                if c == '"' || c == '\'' {
+                    quote = Some(c);
+                }
+                out.push(c);
+            }
+        }
+    }
+
+    out
+}
+
+/// Write `nodes` as lines at `depth`, joining runs of inline nodes onto one line
+fn write_children(nodes: &[Node], depth: usize, out: &mut String) {
+    let mut run = Vec::new();
+
+    for node in nodes {
+        if node.is_inline() {
+            run.push(node);
+            continue;
+        }
+        write_line(&inline_html(&run), depth, out);
+        run.clear();
+
+        match node {
+            Node::Element { start_tag, children, end_tag, .. } => {
+                if children.iter().all(Node::is_inline) {
+                    let children: Vec<&Node> = children.iter().collect();
+                    let line = format!("{}{}{}", start_tag, inline_html(&children), end_tag.as_deref().unwrap_or(""));
+                    write_line(&line, depth, out);
+                } else {
+                    write_line(start_tag, depth, out);
+                    write_children(children, depth + 1, out);
+                    write_line(end_tag.as_deref().unwrap_or(""), depth, out);
+                }
+            }
+            Node::Verbatim { html, .. } => write_line(html, depth, out),
+            Node::Text(_) => unreachable!("text is always inline"),
+        }
+    }
+
+    write_line(&inline_html(&run), depth, out);
+}
+
+fn write_line(line: &str, depth: usize, out: &mut String) {
+    if line.is_empty() {
+        return;
+    }
+    for _ in 0..depth {
+        out.push_str(INDENT);
+    }
+    out.push_str(line);
+    out.push('\n');
+}
+
+/// Inline nodes as one line, with each whitespace run in their text collapsed to a single space
+/// and none at either end
+fn inline_html(nodes: &[&Node]) -> String {
+    let mut line = InlineLine::default();
+    for node in nodes {
+        line.node(node);
+    }
+    line.html
+}
+
+#[derive(Default)]
+struct InlineLine {
+    html: String,
+    pending_space: bool,
+}
+
+impl InlineLine {
+    fn node(&mut self, node: &Node) {
+        match node {
+            Node::Element { start_tag, children, end_tag, .. } => {
+                self.raw(start_tag);
+                for child in children {
+                    self.node(child);
+                }
+                if let Some(end_tag) = end_tag {
+                    self.raw(end_tag);
+                }
+            }
+            Node::Verbatim { html, .. } => self.raw(html),
+            Node::Text(text) => {
+                for c in text.chars() {
+                    if c.is_ascii_whitespace() {
+                        self.pending_space = true;
+                    } else {
+                        self.raw(c.encode_utf8(&mut [0; 4]));
+                    }
+                }
+            }
+        }
+    }
+
+    fn raw(&mut self, html: &str) {
+        if self.pending_space && !self.html.is_empty() {
+            self.html.push(' ');
+        }
+        self.pending_space = false;
+        self.html.push_str(html);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAGE: &str = r#"<!DOCTYPE html>
+<html lang="en"><head>
+<meta charset="utf-8">
+    <title>  Hello  </title>
+<style>
+  body  { margin: 0 }
+</style>
+</head>
+<body>
+<nav><a href="/">Home</a>   <a href="/about">About</a></nav>
+<main    class="content"   id='main' >
+<h1>Hello,
+    <em>world</em>!</h1>
+<p>One
+paragraph <!-- note --> here.
+<p>Implied close
+<ul><li>One<li>Two <code>a  b</code></ul>
+<pre><code class="language-rust">fn main() {
+    println!("hi");
+}
+</code></pre>
+<script>if (a < b) { go(); }</script>
+</main>
+</body>
+</html>
+"#;
+
+    #[test]
+    fn test_prettify_layout() {
+        let expected = r#"<!DOCTYPE html>
+<html lang="en">
+  <head>
+    <meta charset="utf-8">
+    <title>Hello</title>
+    <style>
+  body  { margin: 0 }
+</style>
+  </head>
+  <body>
+    <nav><a href="/">Home</a> <a href="/about">About</a></nav>
+    <main class="content" id='main'>
+      <h1>Hello, <em>world</em>!</h1>
+      <p>One paragraph <!-- note --> here.
+      <p>Implied close
+      <ul>
+        <li>One
+        <li>Two <code>a  b</code>
+      </ul>
+      <pre><code class="language-rust">fn main() {
+    println!("hi");
+}
+</code></pre>
+      <script>if (a < b) { go(); }</script>
+    </main>
+  </body>
+</html>
+"#;
+        assert_eq!(prettify_html(PAGE), expected);
+    }
+
+    #[test]
+    fn test_prettify_is_idempotent() {
+        let inputs = [
+            PAGE,
+            "<div><span>a</span><div>b</div>c</div>",
+            "<p>stray</span> end <b>unclosed",
+            "<svg viewBox=\"0 0 1 1\"><path d=\"M0 0\"/></svg><br/>text",
+            "<pre>outer <pre>inner</pre> still outer</pre><p>after",
+            "a < b and <3",
+        ];
+
+        for input in inputs {
+            let once = prettify_html(input);
+            assert_eq!(prettify_html(&once), once, "formatting twice changed:\n{}", once);
+        }
+    }
+
+    #[test]
+    fn test_unclosed_elements() {
+        assert_eq!(unclosed_elements("<div><p>a<ul><li>b</ul><br><img /><span>c"), vec!["div", "span"]);
+        assert!(unclosed_elements("<div><pre><div></pre></div>").is_empty());
+    }
+
+    #[test]
+    fn test_normalize_tag() {
+        assert_eq!(normalize_tag("<a\n  href=\"x  y\"\n  title='a > b' >"), "<a href=\"x  y\" title='a > b'>");
+        assert_eq!(normalize_tag("<br />"), "<br />");
+    }
+}
@@ -0,0 +1,256 @@
+//! Password-protected pages.
+//!
+//! Pages with `protected: true` in their frontmatter have their rendered content
+//! encrypted at build time with AES-256-GCM. The key is derived from the page's
+//! passphrase with Argon2id. Browsers can't do Argon2 through WebCrypto, so the inline
+//! script in `templates/protected.jinja` carries a small implementation of it and hands
+//! the key to WebCrypto for the decryption. This is the only JavaScript Hugs ships to
+//! production pages.
+//!
+//! The salt and nonce aren't random: the salt comes from the page's file and the nonce from
+//! the key and the content. Rebuilding an unchanged page gives the same bytes, so the
+//! lastmod history and the build manifest only see it change when its content does, and
+//! different content never reuses a nonce under the same key.
+
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::{Engine, engine::general_purpose::STANDARD};
+use minijinja::{Environment, context};
+use sha2::{Digest, Sha256};
+
+use crate::error::{HugsError, Result};
+use crate::run::ContentFrontmatter;
+
+const PROTECTED_TEMPLATE: &str = include_str!("templates/protected.jinja");
+
+/// How hard the content key is to derive, in KiB of memory and passes over it
+#[derive(Debug, Clone, Copy)]
+struct KeyCost {
+    memory_kib: u32,
+    passes: u32,
+}
+
+/// OWASP's recommendation for Argon2id with one lane: 19 MiB and two passes
+const KEY_COST: KeyCost = KeyCost { memory_kib: 19_456, passes: 2 };
+
+/// Prefix for passphrases read from environment variables (e.g. `env:NOTES_PASSWORD`)
+const ENV_PREFIX: &str = "env:";
+
+/// Shown above protected pages in the dev server, where content is rendered unencrypted
+const DEV_BANNER: &str = r#"<div class="hugs-protected-banner" style="padding: 0.5rem 1rem; margin-bottom: 1rem; border-left: 4px solid #e9a545; background: rgba(233, 165, 69, 0.15);">🔒 This page is protected. I'm showing it unlocked because this is the dev server; the built site asks readers for the passphrase.</div>"#;
+
+/// Encrypt a protected page's content for the built site.
+/// Returns the content unchanged when the page isn't protected.
+pub fn protect_for_build(frontmatter: &ContentFrontmatter, content_html: String, file: &str) -> Result<String> {
+    if !frontmatter.protected {
+        return Ok(content_html);
+    }
+
+    let passphrase = resolve_passphrase(frontmatter.password.as_deref(), file)?;
+    encrypt_content(&content_html, &passphrase, KEY_COST, file)
+}
+
+/// Mark a protected page's content as such for the dev server, leaving it readable.
+/// Returns the content unchanged when the page isn't protected.
+pub fn protect_for_dev(frontmatter: &ContentFrontmatter, content_html: String) -> String {
+    if !frontmatter.protected {
+        return content_html;
+    }
+
+    format!("{}\n{}", DEV_BANNER, content_html)
+}
+
+/// Look up the passphrase for a page, following `env:` references
+fn resolve_passphrase(password: Option<&str>, file: &str) -> Result<String> {
+    let password = password
+        .filter(|p| !p.is_empty())
+        .ok_or_else(|| HugsError::ProtectedMissingPassword { file: file.into() })?;
+
+    match password.strip_prefix(ENV_PREFIX) {
+        Some(var) => std::env::var(var)
+            .ok()
+            .filter(|p| !p.is_empty())
+            .ok_or_else(|| HugsError::ProtectedPasswordEnv {
+                file: file.into(),
+                var: var.into(),
+            }),
+        None => Ok(password.to_string()),
+    }
+}
+
+/// Derive a 256-bit AES key from a passphrase with Argon2id
+fn derive_key(passphrase: &str, salt: &[u8], cost: KeyCost) -> std::result::Result<[u8; 32], argon2::Error> {
+    let params = Params::new(cost.memory_kib, cost.passes, 1, Some(32))?;
+    let mut key = [0u8; 32];
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params).hash_password_into(passphrase.as_bytes(), salt, &mut key)?;
+    Ok(key)
+}
+
+/// The salt for a page's key, the same on every build of that page
+fn page_salt(file: &str) -> [u8; 16] {
+    let digest = Sha256::new().chain_update(b"hugs protected page salt\0").chain_update(file).finalize();
+    digest[..16].try_into().expect("SHA-256 is longer than 16 bytes")
+}
+
+/// The nonce for encrypting `html` under `key`. It only repeats when the content does, so an
+/// unchanged page encrypts to the same bytes and changed content always gets a new nonce.
+fn content_nonce(key: &[u8; 32], html: &str) -> [u8; 12] {
+    let digest = Sha256::new().chain_update(key).chain_update(html).finalize();
+    digest[..12].try_into().expect("SHA-256 is longer than 12 bytes")
+}
+
+/// Encrypt HTML and wrap it in the unlock form
+fn encrypt_content(html: &str, passphrase: &str, cost: KeyCost, file: &str) -> Result<String> {
+    let encrypt_error = |reason: String| HugsError::ProtectedEncrypt {
+        file: file.into(),
+        reason,
+    };
+
+    let salt = page_salt(file);
+    let key = derive_key(passphrase, &salt, cost).map_err(|e| encrypt_error(e.to_string()))?;
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key));
+    let nonce = content_nonce(&key, html);
+    let ciphertext = cipher
+        .encrypt(Nonce::from_slice(&nonce), html.as_bytes())
+        .map_err(|e| encrypt_error(e.to_string()))?;
+
+    let mut env = Environment::new();
+    env.add_template("protected", PROTECTED_TEMPLATE)
+        .and_then(|_| env.get_template("protected"))
+        .and_then(|tmpl| {
+            tmpl.render(context! {
+                salt => STANDARD.encode(salt),
+                iv => STANDARD.encode(nonce),
+                memory => cost.memory_kib,
+                passes => cost.passes,
+                ciphertext => STANDARD.encode(ciphertext),
+            })
+        })
+        .map_err(|e| encrypt_error(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_COST: KeyCost = KeyCost { memory_kib: 64, passes: 1 };
+
+    fn decrypt_box(html: &str, passphrase: &str) -> Option<String> {
+        let attr = |name: &str| -> Option<Vec<u8>> {
+            let start = html.find(&format!("data-{}=\"", name))? + name.len() + 7;
+            let end = start + html[start..].find('"')?;
+            STANDARD.decode(&html[start..end]).ok()
+        };
+
+        let key = derive_key(passphrase, &attr("salt")?, TEST_COST).ok()?;
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key));
+        let iv = attr("iv")?;
+        let plain = cipher
+            .decrypt(aes_gcm::Nonce::from_slice(&iv), attr("ciphertext")?.as_slice())
+            .ok()?;
+        String::from_utf8(plain).ok()
+    }
+
+    #[test]
+    fn test_encrypted_content_round_trips_only_with_passphrase() {
+        let html = encrypt_content("<p>Meeting notes</p>", "hunter2", TEST_COST, "notes.md").unwrap();
+
+        assert!(!html.contains("Meeting notes"), "plaintext must not leak into the page");
+        assert!(!html.contains("\n\n"), "blank lines would break the HTML block in markdown");
+        assert_eq!(decrypt_box(&html, "hunter2").as_deref(), Some("<p>Meeting notes</p>"));
+        assert_eq!(decrypt_box(&html, "wrong"), None);
+    }
+
+    #[test]
+    fn test_unchanged_pages_encrypt_the_same_every_build() {
+        let encrypt = |html: &str, file: &str| encrypt_content(html, "hunter2", TEST_COST, file).unwrap();
+        let iv = |html: &str| html.split("data-iv=\"").nth(1).unwrap().split('"').next().unwrap().to_string();
+
+        let first = encrypt("<p>Meeting notes</p>", "notes.md");
+        assert_eq!(first, encrypt("<p>Meeting notes</p>", "notes.md"));
+
+        let edited = encrypt("<p>Meeting notes, edited</p>", "notes.md");
+        assert_ne!(iv(&first), iv(&edited), "new content must never reuse a nonce");
+        assert_ne!(first, encrypt("<p>Meeting notes</p>", "other.md"));
+    }
+
+    /// Two passes, so the second pass's data-dependent indexing is covered too
+    const VECTOR_COST: KeyCost = KeyCost { memory_kib: 64, passes: 2 };
+
+    // protected.jinja cites this vector. If it changes, the page script has to change with it.
+    #[test]
+    fn test_key_derivation_matches_the_pinned_vector() {
+        let key = derive_key("hunter2 ☃", b"hugs test vector", VECTOR_COST).unwrap();
+        assert_eq!(hex::encode(key), "0a759a39a7f17b65a90c9c037761f6b76ca45e618a6ab13f15b7fe5c26b248a3");
+    }
+
+    /// Run the unlock script from `html` under node against a stand-in for the page, submitting
+    /// `passphrase`. What it puts in the page's place, or `None` if it said the passphrase was
+    /// wrong. Skips, with `Err`, when node isn't installed.
+    fn unlock_with_node(html: &str, passphrase: &str) -> std::result::Result<Option<String>, ()> {
+        let attr = |name: &str| {
+            let start = html.find(&format!("data-{}=\"", name)).unwrap() + name.len() + 7;
+            html[start..start + html[start..].find('"').unwrap()].to_string()
+        };
+        let script = html.split("<script>").nth(1).unwrap().split("</script>").next().unwrap();
+        let dataset = serde_json::json!({
+            "salt": attr("salt"),
+            "iv": attr("iv"),
+            "memory": attr("memory"),
+            "passes": attr("passes"),
+            "ciphertext": attr("ciphertext"),
+        });
+        let harness = format!(
+            r#"globalThis.crypto ??= require("node:crypto").webcrypto;
+            let submit;
+            const input = {{ value: {passphrase} }};
+            const form = {{ addEventListener: (_, handler) => submit = handler, querySelector: () => input }};
+            const error = {{ set hidden(hidden) {{ if (!hidden) process.stdout.write("WRONG"); }} }};
+            const box = {{
+                dataset: {dataset},
+                querySelector: (selector) => selector === "form" ? form : error,
+                set outerHTML(html) {{ process.stdout.write("UNLOCKED" + html); }},
+            }};
+            globalThis.document = {{ querySelectorAll: () => [box] }};
+            {script}
+            submit({{ preventDefault() {{}} }});"#,
+            passphrase = serde_json::Value::from(passphrase),
+        );
+
+        let output = std::process::Command::new("node").arg("-e").arg(harness).output().map_err(|_| ())?;
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        match stdout.strip_prefix("UNLOCKED") {
+            Some(unlocked) => Ok(Some(unlocked.to_string())),
+            None => {
+                assert_eq!(stdout, "WRONG");
+                Ok(None)
+            }
+        }
+    }
+
+    #[test]
+    fn test_page_script_unlocks_what_the_build_encrypts() {
+        let html = encrypt_content("<p>Meeting notes ☃</p>", "hunter2 ☃", VECTOR_COST, "notes.md").unwrap();
+        let Ok(unlocked) = unlock_with_node(&html, "hunter2 ☃") else {
+            // No node on this machine, so there's nothing to check against
+            return;
+        };
+        assert_eq!(unlocked.as_deref(), Some("<p>Meeting notes ☃</p>"));
+        assert_eq!(unlock_with_node(&html, "hunter2"), Ok(None));
+    }
+
+    #[test]
+    fn test_passphrase_from_env_requires_variable() {
+        assert_eq!(resolve_passphrase(Some("literal"), "a.md").unwrap(), "literal");
+        assert!(matches!(
+            resolve_passphrase(Some("env:HUGS_TEST_PASSPHRASE_THAT_IS_UNSET"), "a.md"),
+            Err(HugsError::ProtectedPasswordEnv { .. })
+        ));
+        assert!(matches!(
+            resolve_passphrase(None, "a.md"),
+            Err(HugsError::ProtectedMissingPassword { .. })
+        ));
+    }
+}
@@ -0,0 +1,239 @@
+//! Reading time for `readtime()` and list items. Most languages put spaces between words, so I
+//! count words; Chinese and Japanese don't, so on pages in a CJK language I count characters
+//! instead, and words too for the Latin-script text mixed in with them.
+
+use std::fmt;
+use std::sync::Arc;
+
+use minijinja::Value;
+use minijinja::value::{Enumerator, Object};
+use serde::{Deserialize, Serialize};
+
+use crate::build_cache::BuildCache;
+use crate::config::BuildConfig;
+use crate::run::{markdown_plain_text, strip_code_blocks};
+
+/// How fast people read, from `[build]`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReadingSpeed {
+    pub words_per_minute: u32,
+    pub chars_per_minute: u32,
+}
+
+impl ReadingSpeed {
+    pub fn from_config(build: &BuildConfig) -> Self {
+        Self { words_per_minute: build.reading_speed, chars_per_minute: build.cjk_reading_speed }
+    }
+}
+
+/// What a reading time was counted in, so templates can say "1,200 characters" rather than "words"
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum CountMethod {
+    Words,
+    Characters,
+    /// CJK characters plus the words between them
+    Mixed,
+}
+
+impl CountMethod {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CountMethod::Words => "words",
+            CountMethod::Characters => "characters",
+            CountMethod::Mixed => "mixed",
+        }
+    }
+}
+
+/// A page's estimated reading time. In templates it prints as its minutes, and has `minutes`,
+/// `words`, `characters` and `method` attributes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReadingTime {
+    /// At least 1
+    pub minutes: u32,
+    pub words: usize,
+    /// CJK characters, which are only counted on pages in a CJK language
+    pub characters: usize,
+    pub method: CountMethod,
+}
+
+impl Object for ReadingTime {
+    fn get_value(self: &Arc<Self>, key: &Value) -> Option<Value> {
+        match key.as_str()? {
+            "minutes" => Some(Value::from(self.minutes)),
+            "words" => Some(Value::from(self.words)),
+            "characters" => Some(Value::from(self.characters)),
+            "method" => Some(Value::from(self.method.as_str())),
+            _ => None,
+        }
+    }
+
+    fn enumerate(self: &Arc<Self>) -> Enumerator {
+        Enumerator::Str(&["minutes", "words", "characters", "method"])
+    }
+
+    fn render(self: &Arc<Self>, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.minutes)
+    }
+}
+
+/// Whether `lang`, a language tag like `ja` or `zh-Hant`, is Chinese, Japanese or Korean
+pub fn is_cjk_language(lang: &str) -> bool {
+    let primary = lang.split(['-', '_']).next().unwrap_or("").to_ascii_lowercase();
+    matches!(primary.as_str(), "ja" | "zh" | "ko")
+}
+
+/// Han, kana and Hangul: the characters a CJK reading speed is measured in
+fn is_cjk_char(c: char) -> bool {
+    matches!(c,
+        '\u{3040}'..='\u{30FF}'     // Hiragana, Katakana
+        | '\u{31F0}'..='\u{31FF}'   // Katakana phonetic extensions
+        | '\u{3400}'..='\u{4DBF}'   // CJK extension A
+        | '\u{4E00}'..='\u{9FFF}'   // CJK unified ideographs
+        | '\u{F900}'..='\u{FAFF}'   // CJK compatibility ideographs
+        | '\u{FF66}'..='\u{FF9F}'   // Half-width katakana
+        | '\u{1100}'..='\u{11FF}'   // Hangul jamo
+        | '\u{3130}'..='\u{318F}'   // Hangul compatibility jamo
+        | '\u{AC00}'..='\u{D7AF}'   // Hangul syllables
+        | '\u{20000}'..='\u{2FA1F}' // CJK extensions B onwards
+    )
+}
+
+/// Estimated reading time of markdown `text` on a page in language `lang`, ignoring code blocks.
+/// Counting reads through the whole page, so the counts are kept in `cache`.
+pub fn reading_time(text: &str, lang: &str, speed: ReadingSpeed, cache: &BuildCache) -> ReadingTime {
+    let cjk = is_cjk_language(lang);
+    let counts = cache.get_or_compute("readtime", &(cjk, text), || count(text, cjk));
+    let Counts { words, characters, method } = counts;
+    let minutes = words as f64 / speed.words_per_minute.max(1) as f64
+        + characters as f64 / speed.chars_per_minute.max(1) as f64;
+    ReadingTime { minutes: (minutes.ceil() as u32).max(1), words, characters, method }
+}
+
+/// Words, CJK characters, and which of them a page was counted in
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+struct Counts {
+    words: usize,
+    characters: usize,
+    method: CountMethod,
+}
+
+fn count(text: &str, cjk: bool) -> Counts {
+    let plain = markdown_plain_text(&strip_code_blocks(text));
+
+    if !cjk {
+        return Counts { words: plain.split_whitespace().count(), characters: 0, method: CountMethod::Words };
+    }
+
+    let characters = plain.chars().filter(|&c| is_cjk_char(c)).count();
+    // CJK characters and punctuation separate the words of Latin text mixed in with them
+    let words = plain
+        .split(|c: char| c.is_whitespace() || is_cjk_char(c) || (!c.is_ascii() && !c.is_alphanumeric()))
+        .filter(|word| word.chars().any(char::is_alphanumeric))
+        .count();
+    let method = match (characters, words) {
+        (0, _) => CountMethod::Words,
+        (_, 0) => CountMethod::Characters,
+        _ => CountMethod::Mixed,
+    };
+    Counts { words, characters, method }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SPEED: ReadingSpeed = ReadingSpeed { words_per_minute: 200, chars_per_minute: 500 };
+
+    /// Reading time without a cache
+    fn reading_time(text: &str, lang: &str, speed: ReadingSpeed) -> ReadingTime {
+        super::reading_time(text, lang, speed, &BuildCache::default())
+    }
+
+    #[test]
+    fn test_recognises_cjk_language_tags() {
+        for lang in ["ja", "ja-JP", "zh", "zh-Hant-TW", "ZH_cn", "ko-KR"] {
+            assert!(is_cjk_language(lang), "{lang}");
+        }
+        for lang in ["en-us", "", "jav", "de", "kok"] {
+            assert!(!is_cjk_language(lang), "{lang}");
+        }
+    }
+
+    #[test]
+    fn test_japanese_is_counted_in_characters() {
+        // 22 characters, punctuation aside
+        let sentence = "今日は東京で新しい本を買いました。とても楽しい。\n";
+        let text = format!("# 日記\n\n{}", sentence.repeat(50));
+        let time = reading_time(&text, "ja", SPEED);
+
+        assert_eq!(time.method, CountMethod::Characters);
+        assert_eq!(time.words, 0);
+        assert_eq!(time.characters, 2 + 22 * 50);
+        assert_eq!(time.minutes, 3);
+
+        // Counted in words, each line would be a single word
+        assert_eq!(reading_time(&text, "en", SPEED).minutes, 1);
+    }
+
+    #[test]
+    fn test_mixed_pages_count_characters_and_words() {
+        let text = "RustとTypeScriptの**型システム**を比べます。Hello world, again.\n\n```rust\nfn main() {}\n```\n";
+        let time = reading_time(text, "ja-JP", SPEED);
+
+        assert_eq!(time.method, CountMethod::Mixed);
+        assert_eq!(time.characters, "とのシステムを比べます".chars().count() + 1);
+        assert_eq!(time.words, 5);
+    }
+
+    #[test]
+    fn test_combined_minutes_add_both_measures() {
+        let text = format!("{}{}", "字".repeat(500), " word".repeat(200));
+        let time = reading_time(&text, "zh", SPEED);
+        assert_eq!((time.characters, time.words, time.minutes), (500, 200, 2));
+    }
+
+    #[test]
+    fn test_cjk_language_pages_in_latin_script_count_words() {
+        let time = reading_time("Only English here, on a page marked Japanese.", "ja", SPEED);
+        assert_eq!((time.method, time.words, time.characters), (CountMethod::Words, 8, 0));
+    }
+
+    #[test]
+    fn test_snake_case_identifiers_are_one_word() {
+        let text = "Call read_config_file with the parse_toml_value helper, or _emphasise_ it.";
+        assert_eq!(reading_time(text, "en", SPEED).words, 9);
+    }
+
+    #[test]
+    fn test_renders_as_minutes_with_attributes() {
+        let time = reading_time(&"言葉".repeat(600), "ja", SPEED);
+        let mut env = minijinja::Environment::new();
+        env.add_global("t", Value::from_object(time));
+
+        let rendered = env
+            .render_str("{{ t }} min ({{ t.characters }} {{ t.method }}, {{ t.minutes * 2 }})", ())
+            .unwrap();
+        assert_eq!(rendered, "3 min (1200 characters, 6)");
+    }
+
+    #[test]
+    fn test_counts_are_kept_between_builds() {
+        let site = tempfile::tempdir().unwrap();
+        let text = "Some words to count, **twice** over.";
+
+        let first = BuildCache::open(site.path());
+        assert_eq!(super::reading_time(text, "en", SPEED, &first), reading_time(text, "en", SPEED));
+        assert_eq!(first.stats(), (0, 1));
+
+        // The reading speed only changes the minutes, so the count is still good
+        let second = BuildCache::open(site.path());
+        let slow = ReadingSpeed { words_per_minute: 1, chars_per_minute: 1 };
+        assert_eq!(super::reading_time(text, "en-GB", slow, &second).minutes, 6);
+        assert_eq!(second.stats(), (1, 0));
+
+        // A CJK page is counted another way
+        super::reading_time(text, "ja", SPEED, &second);
+        assert_eq!(second.stats(), (1, 1));
+    }
+}
@@ -0,0 +1,283 @@
+//! Moved pages: `aliases` redirects and URL change detection between builds.
+//!
+//! Every build writes a small manifest (`.hugs-cache/manifest.json`) next to the
+//! site, recording each page's URL and a hash of its markdown body. On the next
+//! build, a page whose body matches a page that disappeared is assumed to have
+//! been moved, and I warn about the old URL unless an alias already covers it.
+//! The manifest names the site's source files, so it stays out of the output.
+
+use std::collections::HashSet;
+use std::path::{Component, Path, PathBuf};
+
+use minijinja::{Environment, context};
+use serde::{Deserialize, Serialize};
+use serde_yaml::Value as YamlValue;
+use sha2::{Digest, Sha256};
+
+use crate::config::SiteMetadata;
+use crate::build_cache::CACHE_DIR;
+use crate::error::{HugsError, Result};
+use crate::run::{AppData, DynamicContext, PageInfo};
+use crate::url::SiteUrl;
+
+/// File name of the build manifest, in the site's `.hugs-cache/`
+const MANIFEST_FILE: &str = "manifest.json";
+
+/// Where builds before the manifest moved left it, at the root of the output directory
+const OLD_MANIFEST_FILE: &str = ".hugs-manifest.json";
+
+const REDIRECT_TEMPLATE: &str = include_str!("templates/redirect.jinja");
+
+/// URLs and body hashes of the pages emitted by a build
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct BuildManifest {
+    pages: Vec<ManifestPage>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ManifestPage {
+    url: String,
+    file_path: String,
+    body_hash: String,
+}
+
+impl BuildManifest {
+    /// Where a build of the site at `site_path` keeps its manifest
+    pub fn path(site_path: &Path) -> PathBuf {
+        site_path.join(CACHE_DIR).join(MANIFEST_FILE)
+    }
+
+    /// Read the manifest left by the previous build, if there is one, falling back to the copy
+    /// an older version of hugs left in the output directory
+    pub async fn load_previous(site_path: &Path, output_path: &Path) -> Option<Self> {
+        let content = match tokio::fs::read_to_string(Self::path(site_path)).await {
+            Ok(content) => content,
+            Err(_) => tokio::fs::read_to_string(output_path.join(OLD_MANIFEST_FILE)).await.ok()?,
+        };
+        serde_json::from_str(&content).ok()
+    }
+
+    /// Record the static pages of this build. Dynamic pages share one body across
+    /// all their URLs, so they can't be told apart by content and are left out.
+    pub async fn collect(app_data: &AppData) -> Self {
+        let mut manifest_pages = Vec::new();
+
+        for page in app_data.pages.iter() {
+            if DynamicContext::from_page_info(page).is_some() {
+                continue;
+            }
+
+            let Ok(source) = app_data.page_source(&page.file_path).await else {
+                continue;
+            };
+
+            manifest_pages.push(ManifestPage {
+                url: page.url.to_string(),
+                file_path: page.file_path.clone(),
+                body_hash: hash_body(source.body()),
+            });
+        }
+
+        Self { pages: manifest_pages }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap_or_default()
+    }
+}
+
+/// Hash a page's markdown body. Frontmatter is left out, so metadata edits don't count as new
+/// content.
+fn hash_body(body: &str) -> String {
+    // An empty body says nothing about where a page went
+    let body = body.trim();
+    if body.is_empty() {
+        return String::new();
+    }
+
+    hex::encode(Sha256::digest(body.as_bytes()))
+}
+
+/// Compare two builds and warn about pages that moved to a new URL without an alias for the old one
+pub fn detect_moved_pages(previous: &BuildManifest, current: &BuildManifest, pages: &[PageInfo]) -> Vec<HugsError> {
+    let previous_urls: HashSet<&str> = previous.pages.iter().map(|p| p.url.as_str()).collect();
+    let current_urls: HashSet<&str> = current.pages.iter().map(|p| p.url.as_str()).collect();
+
+    let mut warnings = Vec::new();
+
+    for old in &previous.pages {
+        if old.body_hash.is_empty() || current_urls.contains(old.url.as_str()) {
+            continue;
+        }
+
+        // Only a page at a URL that's new in this build can be where the old one went
+        let candidates: Vec<&ManifestPage> = current
+            .pages
+            .iter()
+            .filter(|p| p.body_hash == old.body_hash && !previous_urls.contains(p.url.as_str()))
+            .collect();
+
+        let [moved] = candidates.as_slice() else {
+            continue;
+        };
+
+        let already_aliased = pages
+            .iter()
+            .find(|p| p.url == moved.url)
+            .is_some_and(|p| page_aliases(&p.frontmatter).iter().any(|a| SiteUrl::parse(a).same_page(&old.url)));
+
+        if !already_aliased {
+            warnings.push(HugsError::PageUrlChanged {
+                old_url: old.url.clone().into(),
+                new_url: moved.url.clone().into(),
+                help_text: format!(
+                    "Links to the old URL will break. If that matters, add it to the frontmatter of {} and I'll redirect it:\n\naliases:\n  - {}",
+                    moved.file_path, old.url
+                ),
+            });
+        }
+    }
+
+    warnings
+}
+
+/// Old URLs a page should be reachable from, normalized to start with `/`
+pub fn page_aliases(frontmatter: &YamlValue) -> Vec<String> {
+    let aliases = match frontmatter.get("aliases") {
+        Some(YamlValue::Sequence(seq)) => seq.iter().filter_map(|v| v.as_str()).collect(),
+        Some(YamlValue::String(s)) => vec![s.as_str()],
+        _ => Vec::new(),
+    };
+
+    aliases
+        .into_iter()
+        .map(|a| format!("/{}", a.trim_start_matches('/')))
+        .collect()
+}
+
+/// Fail on an alias that isn't a path on the site: one that climbs out of it with `..`, or names
+/// a place on this computer or another site. Its redirect would be written there.
+pub fn check_aliases(pages: &[PageInfo]) -> Result<()> {
+    for page in pages {
+        for alias in page_aliases(&page.frontmatter) {
+            if let Some(reason) = alias_problem(&alias) {
+                return Err(HugsError::InvalidAlias {
+                    file: page.file_path.as_str().into(),
+                    alias,
+                    reason: reason.to_string(),
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Why an alias (already starting with `/`) can't be written into the output, if it can't
+fn alias_problem(alias: &str) -> Option<&'static str> {
+    if alias.contains("://") {
+        return Some("It's a whole URL, and I only write redirects on this site.");
+    }
+    if alias.split(['/', '\\']).any(|segment| segment == "..") {
+        return Some("`..` would put its redirect outside the output folder.");
+    }
+    let relative = Path::new(alias.trim_start_matches('/'));
+    if !relative.components().all(|component| matches!(component, Component::Normal(_) | Component::CurDir)) {
+        return Some("It's a path on this computer, not on the site.");
+    }
+    None
+}
+
+/// Find the page that lists `url` among its aliases
+pub fn find_alias_target<'a>(pages: &'a [PageInfo], url: &str) -> Option<&'a PageInfo> {
+    pages
+        .iter()
+        .find(|page| page_aliases(&page.frontmatter).iter().any(|a| SiteUrl::parse(a).same_page(url)))
+}
+
+/// Render the HTML page written at an alias, sending visitors on to the page's real URL
+pub fn render_redirect_page(target_url: &SiteUrl, site: &SiteMetadata) -> Result<String> {
+    let target = site.absolute_url(target_url);
+
+    let mut env = Environment::new();
+    env.add_template("redirect", REDIRECT_TEMPLATE)
+        .and_then(|_| env.get_template("redirect"))
+        .and_then(|tmpl| tmpl.render(context! { target => target, language => site.language }))
+        .map_err(|e| HugsError::RedirectTemplate { reason: e.to_string() })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::run::PageSource;
+
+    fn page(url: &str, frontmatter: &str) -> PageInfo {
+        PageInfo {
+            url: SiteUrl::parse(url),
+            file_path: format!("{}.md", url.trim_matches('/')),
+            frontmatter: serde_yaml::from_str(frontmatter).unwrap(),
+        }
+    }
+
+    fn manifest(entries: &[(&str, &str)]) -> BuildManifest {
+        BuildManifest {
+            pages: entries
+                .iter()
+                .map(|(url, body)| ManifestPage {
+                    url: url.to_string(),
+                    file_path: format!("{}.md", url.trim_matches('/')),
+                    body_hash: hash_body(body),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_moved_page_warns_unless_aliased() {
+        let previous = manifest(&[("/blog/rust-tips", "Some tips"), ("/about", "About me")]);
+        let current = manifest(&[("/blog/rust-tips-2024", "Some tips"), ("/about", "About me")]);
+
+        let pages = vec![page("/blog/rust-tips-2024", "title: Tips"), page("/about", "title: About")];
+        let warnings = detect_moved_pages(&previous, &current, &pages);
+        assert_eq!(warnings.len(), 1);
+        assert!(matches!(
+            &warnings[0],
+            HugsError::PageUrlChanged { old_url, new_url, .. }
+                if old_url.0 == "/blog/rust-tips" && new_url.0 == "/blog/rust-tips-2024"
+        ));
+
+        let pages = vec![
+            page("/blog/rust-tips-2024", "title: Tips\naliases:\n  - blog/rust-tips/"),
+            page("/about", "title: About"),
+        ];
+        assert!(detect_moved_pages(&previous, &current, &pages).is_empty());
+    }
+
+    #[test]
+    fn test_new_and_edited_pages_are_not_moves() {
+        // A brand new page, and a removed page whose content changed on its way out
+        let previous = manifest(&[("/old", "Old words")]);
+        let current = manifest(&[("/new", "New words"), ("/fresh", "Fresh")]);
+        assert!(detect_moved_pages(&previous, &current, &[]).is_empty());
+
+        // Frontmatter edits don't change the body hash
+        let body_of = |text: &str| PageSource::parse(text.to_string()).unwrap().body().to_string();
+        assert_eq!(
+            hash_body(&body_of("---\ntitle: A\n---\nBody")),
+            hash_body(&body_of("---\ntitle: B\ndate: 2024-01-01\n---\nBody"))
+        );
+    }
+
+    #[test]
+    fn test_aliases_stay_inside_the_site() {
+        let pages = |alias: &str| vec![page("/post", &format!("aliases: ['{}']", alias))];
+        for fine in ["/old-post", "2019/hello/", "old.html", "./old"] {
+            assert!(check_aliases(&pages(fine)).is_ok(), "{fine} should be allowed");
+        }
+        for outside in ["../../etc/x", "/blog/../../x", "..\\x", "https://example.com/old"] {
+            assert!(
+                matches!(check_aliases(&pages(outside)), Err(HugsError::InvalidAlias { alias, .. }) if alias.ends_with(outside.trim_start_matches('/'))),
+                "{outside} should be rejected"
+            );
+        }
+    }
+}
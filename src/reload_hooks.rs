@@ -0,0 +1,133 @@
+//! `dev.on_reload`: commands `hugs dev` runs after it reloads the site, before it tells the
+//! browser, so a generated file like Tailwind's CSS is up to date when the page comes back.
+//!
+//! A command gets the changed paths in `HUGS_CHANGED`, one per line, relative to the site. Files
+//! it writes should be listed in `dev.on_reload_outputs`, so writing them doesn't set off another
+//! reload, and another run of the command after that.
+
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, BufReader};
+use tokio::process::Command;
+
+use crate::assets::pattern_matches;
+use crate::config::DevConfig;
+use crate::console;
+use crate::url::relative_path_to_url_string;
+
+/// The environment variable holding the changed paths
+pub const CHANGED_ENV: &str = "HUGS_CHANGED";
+
+/// Whether `relative` (a changed path inside the site) is something an `on_reload` command writes
+pub fn is_hook_output(dev: &DevConfig, relative: &Path) -> bool {
+    let path = relative_path_to_url_string(relative);
+    dev.on_reload_outputs.iter().any(|pattern| pattern_matches(pattern, &path))
+}
+
+/// Run each `on_reload` command in turn from the site folder. One that fails or runs past the
+/// timeout gets a warning and the reload carries on.
+pub async fn run_on_reload(dev: &DevConfig, site_path: &Path, changed: &[PathBuf]) {
+    let changed = changed.iter().map(|path| relative_path_to_url_string(path)).collect::<Vec<_>>().join("\n");
+    let timeout = Duration::from_secs(dev.on_reload_timeout_secs);
+
+    for command in &dev.on_reload {
+        console::status_cyan("Running", command);
+        if let Err(reason) = run_command(command, site_path, &changed, timeout).await {
+            console::warn(format!("on_reload command `{}` {}", command, reason));
+        }
+    }
+}
+
+async fn run_command(command: &str, site_path: &Path, changed: &str, timeout: Duration) -> Result<(), String> {
+    let mut child = shell(command)
+        .current_dir(site_path)
+        .env(CHANGED_ENV, changed)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| format!("couldn't start: {}", e))?;
+
+    let stdout = child.stdout.take().map(|out| tokio::spawn(print_lines(BufReader::new(out))));
+    let stderr = child.stderr.take().map(|err| tokio::spawn(print_lines(BufReader::new(err))));
+
+    let status = match tokio::time::timeout(timeout, child.wait()).await {
+        Ok(status) => status.map_err(|e| format!("couldn't finish: {}", e))?,
+        Err(_) => {
+            let _ = child.kill().await;
+            return Err(format!("took longer than {}s, so I stopped it", timeout.as_secs()));
+        }
+    };
+    for printer in [stdout, stderr].into_iter().flatten() {
+        let _ = printer.await;
+    }
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("failed ({})", status))
+    }
+}
+
+/// The command as the platform's shell runs it, so pipes and `&&` work
+fn shell(command: &str) -> Command {
+    if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C").arg(command);
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.arg("-c").arg(command);
+        shell
+    }
+}
+
+/// Print a command's output as it comes, each line marked as the command's
+async fn print_lines(reader: impl AsyncBufRead + Unpin) {
+    let mut lines = reader.lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        console::status_cyan("on_reload", line);
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    fn dev(on_reload: &[&str], timeout_secs: u64) -> DevConfig {
+        DevConfig {
+            on_reload: on_reload.iter().map(|command| command.to_string()).collect(),
+            on_reload_timeout_secs: timeout_secs,
+            on_reload_outputs: vec![String::from("assets/tailwind.css"), String::from("gen/**")],
+            ..DevConfig::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_commands_get_the_changed_paths() {
+        let site = tempfile::tempdir().unwrap();
+        let changed = [PathBuf::from("blog/post.md"), PathBuf::from("_/theme.css")];
+        run_on_reload(&dev(&["printf '%s' \"$HUGS_CHANGED\" > seen.txt", "exit 3"], 5), site.path(), &changed).await;
+        assert_eq!(std::fs::read_to_string(site.path().join("seen.txt")).unwrap(), "blog/post.md\n_/theme.css");
+    }
+
+    #[tokio::test]
+    async fn test_slow_commands_are_stopped() {
+        let site = tempfile::tempdir().unwrap();
+        let result = run_command("sleep 5", site.path(), "", Duration::from_millis(100)).await;
+        assert!(result.unwrap_err().contains("took longer"));
+        assert!(run_command("exit 2", site.path(), "", Duration::from_secs(5)).await.is_err());
+        assert!(run_command("true", site.path(), "", Duration::from_secs(5)).await.is_ok());
+    }
+
+    #[test]
+    fn test_declared_outputs_are_ignored() {
+        let dev = dev(&[], 30);
+        assert!(is_hook_output(&dev, Path::new("assets/tailwind.css")));
+        assert!(is_hook_output(&dev, Path::new("gen/icons/a.svg")));
+        assert!(!is_hook_output(&dev, Path::new("assets/site.css")));
+    }
+}
@@ -0,0 +1,124 @@
+//! `hugs render`: one page of the site printed to stdout, either the whole page the way
+//! `hugs build` writes it or just its content. Pages are looked up with `resolve_page`, like the
+//! build and the servers do, so dynamic pages work by URL too.
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::build::render_page;
+use crate::deps::DepRecorder;
+use crate::error::{HugsError, Result};
+use crate::minify::{MinifyConfig, minify_html_content};
+use crate::protect::protect_for_build;
+use crate::redirects::find_alias_target;
+use crate::run::{AppData, page_for_request, resolve_page};
+use crate::url::relative_path_to_url_string;
+
+/// How much of the page `hugs render` prints
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RenderOutput {
+    /// The page's content as HTML, after Jinja and markdown, without any template around it
+    Raw,
+    /// The whole page through the root template, as `hugs build` writes it
+    Full,
+}
+
+/// Print the page at `target`, a URL like `/blog/tag/rust` or a page's markdown file
+pub async fn run_render(site_path: PathBuf, target: &str, output: RenderOutput) -> Result<()> {
+    let app_data = AppData::load(site_path, "render --site").await?;
+    let html = render_target(target, output, &app_data).await?;
+
+    // A reader that stops early, like `head`, has all it wanted
+    match writeln!(std::io::stdout(), "{}", html.trim_end()) {
+        Err(e) if e.kind() != std::io::ErrorKind::BrokenPipe => Err(HugsError::ShellOutput { what: "page", cause: e }),
+        _ => Ok(()),
+    }
+}
+
+/// The HTML `hugs render` prints for `target`. A protected page is encrypted either way, just as
+/// the build publishes it.
+async fn render_target(target: &str, output: RenderOutput, app_data: &AppData) -> Result<String> {
+    let request_path = request_path(target, app_data)?;
+    let page = page_for_request(&app_data.pages, &request_path).ok_or_else(|| page_not_found(target, app_data))?;
+
+    Ok(match output {
+        RenderOutput::Raw => {
+            let resolved = resolve_page(&request_path, app_data, &DepRecorder::default())
+                .await?
+                .ok_or_else(|| page_not_found(target, app_data))?;
+            protect_for_build(&resolved.frontmatter, resolved.content, &page.file_path)?
+        }
+        RenderOutput::Full => {
+            let html = render_page(page, app_data).await?;
+            minify_html_content(&html, &MinifyConfig::from_build(&app_data.config.build))
+        }
+    })
+}
+
+/// The request path (no leading or trailing slash) of the page `target` names. Anything ending in
+/// `.md` is a page's file, relative to the site or the current directory; the rest are URLs.
+fn request_path(target: &str, app_data: &AppData) -> Result<String> {
+    if !target.ends_with(".md") {
+        return Ok(target.trim_matches('/').to_string());
+    }
+
+    let file = Path::new(target);
+    let relative = file.strip_prefix(&app_data.site_path).unwrap_or(file);
+    let relative = relative_path_to_url_string(relative);
+    let pages: Vec<_> = app_data.pages.iter().filter(|page| page.file_path == relative).collect();
+
+    match pages.as_slice() {
+        [] => Err(page_not_found(target, app_data)),
+        [page] => Ok(page.url.as_str().trim_matches('/').to_string()),
+        [page, ..] => Err(HugsError::RenderPageNotFound {
+            target: target.into(),
+            help_text: format!(
+                "{} is a dynamic page that makes {} pages. Give me the URL of the one you want, like {}.",
+                relative,
+                pages.len(),
+                page.url
+            ),
+        }),
+    }
+}
+
+fn page_not_found(target: &str, app_data: &AppData) -> HugsError {
+    let url = format!("/{}", target.trim_matches('/'));
+    let help_text = match find_alias_target(&app_data.pages, &url) {
+        Some(page) => format!("{} is an old URL of {}, which is the page I'd redirect you to.", url, page.url),
+        None => String::from(
+            "`hugs render` takes the URL a page is served at, like `/` or `/blog/first-post`, or the path to its markdown file.",
+        ),
+    };
+    HugsError::RenderPageNotFound { target: target.into(), help_text }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_protected_pages_stay_encrypted() {
+        let site = tempfile::tempdir().unwrap();
+        for (file, content) in [
+            ("_/header.md", "# Site"),
+            ("_/nav.md", "[Home](/)"),
+            ("_/footer.md", "Bye"),
+            ("_/theme.css", "body {}"),
+            ("index.md", "---\ntitle: Home\n---\nWelcome"),
+            ("notes.md", "---\ntitle: Notes\nprotected: true\npassword: hunter2\n---\nThe secret plan"),
+        ] {
+            let path = site.path().join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        }
+        let app_data = AppData::load(site.path().to_path_buf(), "render --site").await.unwrap();
+
+        for output in [RenderOutput::Raw, RenderOutput::Full] {
+            let html = render_target("/notes", output, &app_data).await.unwrap();
+            assert!(!html.contains("The secret plan") && html.contains("data-ciphertext"), "{output:?}: {html}");
+        }
+        let raw = render_target("index.md", RenderOutput::Raw, &app_data).await.unwrap();
+        assert_eq!(raw.trim(), "<p>Welcome</p>");
+    }
+}
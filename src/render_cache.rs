@@ -0,0 +1,140 @@
+//! In-memory cache of rendered pages for the dev server.
+//!
+//! Every `AppData` load gets a new generation id, and any change the watcher sees
+//! triggers a reload. A cached page is valid for the generation it was rendered under,
+//! and the watcher carries over the pages a change couldn't have affected.
+
+use std::collections::HashMap;
+
+/// Rendered HTML per URL, bounded by a least-recently-used entry cap
+#[derive(Debug, Default)]
+pub struct RenderCache {
+    generation: u64,
+    capacity: usize,
+    entries: HashMap<String, CachedPage>,
+    /// Monotonic counter used to order entries by last use
+    clock: u64,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+#[derive(Debug)]
+struct CachedPage {
+    html: String,
+    last_used: u64,
+}
+
+impl RenderCache {
+    /// Look up a page rendered under `generation`, dropping everything if the site has reloaded
+    /// since without the pages being carried over
+    pub fn get(&mut self, url: &str, generation: u64, capacity: usize) -> Option<String> {
+        if generation > self.generation {
+            self.entries.clear();
+            self.generation = generation;
+        }
+        self.capacity = capacity;
+        self.clock += 1;
+
+        // Requests still holding site data older than the cache's never hit
+        let entry = if generation == self.generation { self.entries.get_mut(url) } else { None };
+        match entry {
+            Some(page) => {
+                page.last_used = self.clock;
+                self.hits += 1;
+                Some(page.html.clone())
+            }
+            None => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    /// Move the cache on to a reloaded site's `generation`, keeping only the pages `keep` accepts
+    pub fn carry_over(&mut self, generation: u64, keep: impl Fn(&str) -> bool) {
+        self.entries.retain(|url, _| keep(url));
+        self.generation = generation;
+    }
+
+    /// Store a page rendered under `generation`, evicting the least recently used page when full
+    pub fn insert(&mut self, url: &str, generation: u64, html: String) {
+        // The site reloaded while this page was rendering, so it may already be stale
+        if generation != self.generation || self.capacity == 0 {
+            return;
+        }
+
+        if !self.entries.contains_key(url) && self.entries.len() >= self.capacity {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, page)| page.last_used)
+                .map(|(url, _)| url.clone());
+            if let Some(oldest) = oldest {
+                self.entries.remove(&oldest);
+            }
+        }
+
+        self.clock += 1;
+        self.entries.insert(
+            url.to_string(),
+            CachedPage {
+                html,
+                last_used: self.clock,
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let mut cache = RenderCache::default();
+
+        for url in ["/a", "/b"] {
+            assert_eq!(cache.get(url, 1, 2), None);
+            cache.insert(url, 1, format!("<p>{}</p>", url));
+        }
+
+        // Touch /a so /b becomes the oldest
+        assert_eq!(cache.get("/a", 1, 2).as_deref(), Some("<p>/a</p>"));
+        assert_eq!(cache.get("/c", 1, 2), None);
+        cache.insert("/c", 1, String::from("<p>/c</p>"));
+
+        assert!(cache.get("/a", 1, 2).is_some());
+        assert!(cache.get("/b", 1, 2).is_none());
+        assert!(cache.get("/c", 1, 2).is_some());
+        assert_eq!((cache.hits, cache.misses), (3, 4));
+    }
+
+    #[test]
+    fn test_new_generation_invalidates() {
+        let mut cache = RenderCache::default();
+        cache.get("/a", 1, 8);
+        cache.insert("/a", 1, String::from("old"));
+
+        assert_eq!(cache.get("/a", 2, 8), None);
+
+        // A render that started before the reload must not land in the new generation
+        cache.insert("/a", 1, String::from("old"));
+        assert_eq!(cache.get("/a", 2, 8), None);
+    }
+
+    #[test]
+    fn test_carry_over_keeps_unaffected_pages() {
+        let mut cache = RenderCache::default();
+        for url in ["/a", "/b"] {
+            cache.get(url, 1, 8);
+            cache.insert(url, 1, format!("<p>{}</p>", url));
+        }
+
+        cache.carry_over(2, |url| url != "/b");
+
+        // Requests still on the old site data miss rather than clearing the carried pages
+        assert_eq!(cache.get("/a", 1, 8), None);
+        assert_eq!(cache.get("/a", 2, 8).as_deref(), Some("<p>/a</p>"));
+        assert_eq!(cache.get("/b", 2, 8), None);
+    }
+}
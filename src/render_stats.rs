@@ -0,0 +1,114 @@
+//! How long `hugs dev` takes to render each page, and to load the site, for `/__hugs/stats`.
+//!
+//! Kept per URL from startup, across reloads, so a page can be watched getting faster or slower
+//! as the site changes. The number of pages is capped, dropping the one asked for longest ago.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::timings::{RenderTimings, as_millis};
+
+/// Most pages I keep statistics for
+const MAX_PAGES: usize = 1000;
+
+#[derive(Debug, Default)]
+pub struct RenderStats {
+    pages: HashMap<String, PageStats>,
+    /// Monotonic counter used to order pages by last request
+    clock: u64,
+    /// Times the watcher has loaded the site again, whether or not it worked
+    pub reloads: u64,
+    /// How long loading the site took most recently, at startup or on a reload
+    pub last_load: Option<Duration>,
+}
+
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct PageStats {
+    /// Times the page was rendered, not counting requests the render cache answered
+    pub renders: u64,
+    pub cache_hits: u64,
+    #[serde(rename = "last_render_ms", serialize_with = "as_millis")]
+    pub last_render: Duration,
+    /// Where the time went in the last render
+    #[serde(flatten)]
+    pub last_timings: RenderTimings,
+    #[serde(skip)]
+    last_used: u64,
+}
+
+impl RenderStats {
+    /// Record that `url` rendered in `total`, of which `timings` went on each phase
+    pub fn rendered(&mut self, url: &str, total: Duration, timings: RenderTimings) {
+        let page = self.page(url);
+        page.renders += 1;
+        page.last_render = total;
+        page.last_timings = timings;
+    }
+
+    /// Record that the render cache answered a request for `url`
+    pub fn cache_hit(&mut self, url: &str) {
+        self.page(url).cache_hits += 1;
+    }
+
+    /// Record a load of the site that took `duration`; `reload` when the watcher started it
+    pub fn loaded(&mut self, duration: Duration, reload: bool) {
+        self.last_load = Some(duration);
+        if reload {
+            self.reloads += 1;
+        }
+    }
+
+    /// Every page's statistics, slowest last render first
+    pub fn pages(&self) -> Vec<(&str, &PageStats)> {
+        let mut pages: Vec<(&str, &PageStats)> = self.pages.iter().map(|(url, page)| (url.as_str(), page)).collect();
+        pages.sort_by(|(a_url, a), (b_url, b)| b.last_render.cmp(&a.last_render).then(a_url.cmp(b_url)));
+        pages
+    }
+
+    /// The statistics for `url`, making room for them when it's new
+    fn page(&mut self, url: &str) -> &mut PageStats {
+        if !self.pages.contains_key(url) && self.pages.len() >= MAX_PAGES {
+            let oldest = self.pages.iter().min_by_key(|(_, page)| page.last_used).map(|(url, _)| url.clone());
+            if let Some(oldest) = oldest {
+                self.pages.remove(&oldest);
+            }
+        }
+
+        self.clock += 1;
+        let page = self.pages.entry(url.to_string()).or_default();
+        page.last_used = self.clock;
+        page
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stats_count_renders_and_hits_and_stay_bounded() {
+        let mut stats = RenderStats::default();
+        let timings = RenderTimings { template: Duration::from_millis(3), ..RenderTimings::default() };
+        stats.rendered("/slow", Duration::from_millis(40), timings);
+        stats.rendered("/fast", Duration::from_millis(5), RenderTimings::default());
+        stats.rendered("/fast", Duration::from_millis(2), RenderTimings::default());
+        stats.cache_hit("/fast");
+
+        let pages = stats.pages();
+        assert_eq!(pages.iter().map(|(url, _)| *url).collect::<Vec<_>>(), ["/slow", "/fast"]);
+        assert_eq!((pages[1].1.renders, pages[1].1.cache_hits, pages[1].1.last_render), (2, 1, Duration::from_millis(2)));
+        assert_eq!(pages[0].1.last_timings, timings);
+
+        for n in 0..MAX_PAGES {
+            stats.rendered(&format!("/page-{}", n), Duration::ZERO, RenderTimings::default());
+        }
+        assert_eq!(stats.pages.len(), MAX_PAGES);
+        assert!(!stats.pages.contains_key("/slow") && stats.pages.contains_key("/page-0"));
+
+        stats.loaded(Duration::from_millis(120), false);
+        stats.loaded(Duration::from_millis(80), true);
+        assert_eq!((stats.reloads, stats.last_load), (1, Some(Duration::from_millis(80))));
+    }
+}
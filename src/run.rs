@@ -1,20 +1,45 @@
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::cell::Cell;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::path::{Path, PathBuf};
-use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
 
 use actix_web::{HttpResponse, http::header::ContentType};
 use serde::{Deserialize, Serialize};
 use serde_json;
 use serde_yaml::Value as YamlValue;
 use sha2::{Sha256, Digest};
-use chrono::{DateTime, Locale, NaiveDate, NaiveDateTime, Utc};
-use minijinja::{Environment, State, Value};
+use chrono::Locale;
+use minijinja::{Environment, State, UndefinedBehavior, Value};
 use tokio::task::JoinSet;
 use walkdir::WalkDir;
 
-use crate::config::SiteConfig;
+use crate::asset_minify::AssetMinifier;
+use crate::auto_list::{AutoListOptions, render_auto_list};
+use crate::assets::{AssetRoots, SkipReason, skip_reason};
+use crate::code_languages::{CodeLanguages, fence_languages};
+use crate::content_templates::{ContentTemplates, body_prints_content, is_section_template};
+use crate::config::{ParamCharset, SiteConfig, TemplateConfig, UrlStyle, UrlTransliteration};
+use crate::build_cache::BuildCache;
 use crate::console;
+use crate::dates::{DateParser, PageDates, extract_dates};
+use crate::deps::{DepRecorder, RenderDeps};
 use crate::error::{HugsError, HugsResultExt, Result, TemplateHints};
+use crate::gitignore::GitIgnored;
+use crate::highlight::UnknownLanguages;
+use crate::inline_svg::SvgInliner;
+use crate::macro_trace::{MacroTrace, traced_body};
+use crate::mem_stats::format_size;
+use crate::notfound::absolutize_links;
+use crate::page_tokens::{PageTokens, page_token};
+use crate::protect::protect_for_dev;
+use crate::readtime::{ReadingSpeed, ReadingTime, reading_time};
+use crate::redirects::find_alias_target;
+use crate::template_help::{self, HELP_MARKER_FILTER, HELP_MARKER_TEST, HelpBody, HelpRequest, HelpSection, HelpVariable, TemplateHelp};
+use crate::template_regex::{matches_test, regex_replace_filter};
+use crate::timings::{self, Phase};
+use crate::url::{SiteUrl, relative_path_to_url_string, without_html_suffix};
 
 /// Create markdown options (can't be static due to non-Send callback fields)
 fn markdown_options() -> markdown::Options {
@@ -34,43 +59,27 @@ fn markdown_options() -> markdown::Options {
 fn markdown_to_html(
     body: &str,
     config: &crate::config::SyntaxHighlightConfig,
-) -> std::result::Result<String, String> {
-    let html = markdown::to_html_with_options(body, &markdown_options())
-        .map_err(|e| e.to_string())?;
+    unknown_languages: &UnknownLanguages,
+) -> std::result::Result<String, markdown::message::Message> {
+    let html = timings::phase(Phase::Markdown, || markdown::to_html_with_options(body, &markdown_options()))?;
 
     if config.enabled {
-        Ok(crate::highlight::highlight_code_blocks(&html, &config.theme))
+        let (html, unknown) = timings::phase(Phase::Highlight, || crate::highlight::highlight_code_blocks(&html, config));
+        unknown_languages.record(unknown);
+        Ok(html)
     } else {
         Ok(html)
     }
 }
 
-/// Create a `pages` function for minijinja that returns all pages, optionally filtered by URL prefix
+/// Create a `pages` function for minijinja that returns all pages, optionally filtered by URL prefix,
+/// sorted and limited
 fn create_pages_function(
     pages: Arc<Vec<PageInfo>>,
 ) -> impl Fn(minijinja::value::Kwargs) -> std::result::Result<Value, minijinja::Error> + Send + Sync + 'static {
     move |kwargs: minijinja::value::Kwargs| {
-        // If `within` arg is provided, filter by URL prefix
-        let within: Option<String> = kwargs.get("within")?;
-        if let Some(prefix) = within {
-            // The index URL for the directory is the prefix with a trailing slash
-            let index_url = if prefix.ends_with('/') {
-                prefix.clone()
-            } else {
-                format!("{}/", prefix)
-            };
-            let filtered: Vec<&PageInfo> = pages
-                .iter()
-                .filter(|page| {
-                    // Include pages within the prefix, but exclude the directory index
-                    page.url.starts_with(&prefix) && page.url != index_url
-                })
-                .collect();
-
-            Ok(Value::from_serialize(&filtered))
-        } else {
-            Ok(Value::from_serialize(&*pages))
-        }
+        let query = crate::config::PageQuery::from_kwargs(&kwargs)?;
+        Ok(Value::from_serialize(query.select(&pages)))
     }
 }
 
@@ -78,25 +87,86 @@ fn create_pages_function(
 /// Maps original path (e.g., "/theme.css") to hashed path (e.g., "/theme.a1b2c3f4.css")
 #[derive(Default, Clone)]
 pub struct CacheBustRegistry {
-    entries: Arc<Mutex<HashMap<String, String>>>,
+    entries: Arc<Mutex<CacheBustEntries>>,
+}
+
+/// A cache-busted copy of a file
+#[derive(Debug, Clone, PartialEq)]
+pub struct CacheBustEntry {
+    /// Path the copy is served at, e.g. "/theme.a1b2c3f4.css"
+    pub hashed: String,
+    /// Full SHA-256 of the contents, in hex
+    pub hash: String,
+}
+
+#[derive(Default)]
+struct CacheBustEntries {
+    current: HashMap<String, CacheBustEntry>,
+    /// Names from an earlier build's asset manifest, reused while the contents still match
+    reusable: HashMap<String, CacheBustEntry>,
 }
 
 impl CacheBustRegistry {
     pub fn new() -> Self {
         Self {
-            entries: Arc::new(Mutex::new(HashMap::new())),
+            entries: Arc::new(Mutex::new(CacheBustEntries::default())),
         }
     }
 
-    pub fn entries(&self) -> HashMap<String, String> {
-        self.entries.lock().unwrap().clone()
+    /// Lock the entries, recovering them if a render task panicked while holding the lock.
+    /// Every update is a single insert, so the map is never left half-written.
+    fn lock(&self) -> MutexGuard<'_, CacheBustEntries> {
+        self.entries.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// The cache-busted name of each file, sorted by original path, so whatever is written from
+    /// them comes out in the same order however the pages raced to register
+    pub fn entries(&self) -> BTreeMap<String, String> {
+        self.lock()
+            .current
+            .iter()
+            .map(|(original, entry)| (original.clone(), entry.hashed.clone()))
+            .collect()
+    }
+
+    /// The entries with their content hashes, for the asset manifest
+    pub fn entries_with_hashes(&self) -> BTreeMap<String, CacheBustEntry> {
+        self.lock().current.iter().map(|(original, entry)| (original.clone(), entry.clone())).collect()
+    }
+
+    /// Snapshot the entries and clear the registry, so stale hashes aren't reused
+    pub fn take(&self) -> HashMap<String, String> {
+        std::mem::take(&mut self.lock().current)
+            .into_iter()
+            .map(|(original, entry)| (original, entry.hashed))
+            .collect()
+    }
+
+    /// Offer names from an earlier build. Each is only reused if the file's contents still hash
+    /// the same; otherwise it gets a fresh name.
+    pub fn seed(&self, reusable: HashMap<String, CacheBustEntry>) {
+        self.lock().reusable = reusable;
+    }
+
+    fn get(&self, original: &str) -> Option<String> {
+        self.lock().current.get(original).map(|entry| entry.hashed.clone())
     }
 
-    fn insert(&self, original: &str, hashed: &str) {
-        self.entries
-            .lock()
-            .unwrap()
-            .insert(original.to_string(), hashed.to_string());
+    /// Name the cache-busted copy of `original`, whose contents hash to `hash`, and register it.
+    /// Pages rendering at once may both get here for the same file; the first keeps its entry.
+    fn register(&self, original: &str, hash: String) -> String {
+        let mut entries = self.lock();
+        if let Some(existing) = entries.current.get(original) {
+            return existing.hashed.clone();
+        }
+
+        let hashed = match entries.reusable.get(original) {
+            Some(previous) if previous.hash == hash => previous.hashed.clone(),
+            // First 8 hex chars (4 bytes) go in the name
+            _ => insert_hash_into_path(original, &hash[..8]),
+        };
+        entries.current.insert(original.to_string(), CacheBustEntry { hashed: hashed.clone(), hash });
+        hashed
     }
 }
 
@@ -104,33 +174,45 @@ impl CacheBustRegistry {
 /// Usage in templates: {{ cache_bust(path="/theme.css") }} -> "/theme.a1b2c3f4.css"
 #[derive(Clone)]
 pub struct CacheBustFunction {
-    site_path: PathBuf,
+    asset_roots: AssetRoots,
     theme_css: String,
     highlight_css: String,
     registry: CacheBustRegistry,
+    /// Minifies the scripts and SVGs the build publishes, so their names follow what's served
+    minifier: Option<AssetMinifier>,
 }
 
 impl CacheBustFunction {
     pub fn new(
-        site_path: PathBuf,
+        asset_roots: AssetRoots,
         theme_css: String,
         highlight_css: String,
         registry: CacheBustRegistry,
     ) -> Self {
         Self {
-            site_path,
+            asset_roots,
             theme_css,
             highlight_css,
             registry,
+            minifier: None,
         }
     }
 
-    /// Create a minijinja-compatible function from this cache bust configuration
-    pub fn to_minijinja_fn(&self) -> impl Fn(minijinja::value::Kwargs) -> std::result::Result<String, minijinja::Error> + Send + Sync + 'static {
-        let site_path = self.site_path.clone();
+    /// Name the files `minifier` minifies for their minified contents
+    pub fn with_minifier(mut self, minifier: Option<AssetMinifier>) -> Self {
+        self.minifier = minifier;
+        self
+    }
+
+    /// Create a minijinja-compatible function from this cache bust configuration.
+    /// Every file it's asked about is recorded into `deps`.
+    pub fn to_minijinja_fn(&self, deps: &DepRecorder) -> impl Fn(minijinja::value::Kwargs) -> std::result::Result<String, minijinja::Error> + Send + Sync + 'static {
+        let asset_roots = self.asset_roots.clone();
         let theme_css = self.theme_css.clone();
         let highlight_css = self.highlight_css.clone();
         let registry = self.registry.clone();
+        let minifier = self.minifier.clone();
+        let deps = deps.clone();
 
         move |kwargs: minijinja::value::Kwargs| {
             let path: Option<String> = kwargs.get("path")?;
@@ -140,51 +222,64 @@ impl CacheBustFunction {
                     "cache_bust requires 'path' argument",
                 )
             })?;
+            kwargs.assert_all_used()?;
+            let relative = Path::new(path.trim_start_matches('/'));
+            deps.record_file(match path.as_str() {
+                "/theme.css" => PathBuf::from("_/theme.css"),
+                "/highlight.css" => PathBuf::from("config.toml"),
+                _ => asset_roots.file(relative),
+            });
+
             // Check if already computed
-            {
-                let entries = registry.entries.lock().unwrap();
-                if let Some(hashed) = entries.get(&path) {
-                    return Ok(hashed.clone());
-                }
+            if let Some(hashed) = registry.get(&path) {
+                return Ok(hashed);
             }
 
-            // Get content (special case for theme.css and highlight.css which are pre-loaded)
-            let content = if path == "/theme.css" {
-                theme_css.as_bytes().to_vec()
+            // Hash the content (special case for theme.css and highlight.css which are pre-loaded)
+            let hash = if path == "/theme.css" {
+                compute_content_hash(theme_css.as_bytes())
             } else if path == "/highlight.css" {
-                highlight_css.as_bytes().to_vec()
+                compute_content_hash(highlight_css.as_bytes())
             } else {
-                let file_path = if path.starts_with('/') {
-                    site_path.join(&path[1..])
-                } else {
-                    site_path.join(&path)
-                };
-                std::fs::read(&file_path).map_err(|e| {
+                let cannot_read = |e: std::io::Error| {
                     minijinja::Error::new(
                         minijinja::ErrorKind::InvalidOperation,
                         format!("cache_bust: cannot read file '{}': {}", path, e),
                     )
-                })?
+                };
+                let file = asset_roots.path(relative);
+                match &minifier {
+                    Some(minifier) if minifier.minifies(relative) => {
+                        let content = std::fs::read(&file).map_err(cannot_read)?;
+                        match minifier.minify(relative, &content) {
+                            Some(Ok(minified)) => compute_content_hash(minified.as_bytes()),
+                            // The build publishes it as it is, and warns then
+                            _ => compute_content_hash(&content),
+                        }
+                    }
+                    _ => compute_file_hash(&file).map_err(cannot_read)?,
+                }
             };
 
-            // Compute hash (first 8 hex chars of SHA-256)
-            let hash = compute_content_hash(&content);
-            let hashed_path = insert_hash_into_path(&path, &hash);
-
             // Register for build phase
-            registry.insert(&path, &hashed_path);
-
-            Ok(hashed_path)
+            Ok(registry.register(&path, hash))
         }
     }
 }
 
-/// Compute SHA-256 hash and return first 8 hex characters
+/// Compute the SHA-256 hash of `content` as hex
 fn compute_content_hash(content: &[u8]) -> String {
     let mut hasher = Sha256::new();
     hasher.update(content);
-    let result = hasher.finalize();
-    hex::encode(&result[..4]) // 4 bytes = 8 hex chars
+    hex::encode(hasher.finalize())
+}
+
+/// Compute the SHA-256 hash of the file at `path` as hex, a buffer at a time, so fingerprinting
+/// a video doesn't read the whole thing into memory
+fn compute_file_hash(path: &Path) -> std::io::Result<String> {
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut std::fs::File::open(path)?, &mut hasher)?;
+    Ok(hex::encode(hasher.finalize()))
 }
 
 /// Insert hash into path before extension: /theme.css -> /theme.a1b2c3f4.css
@@ -198,6 +293,53 @@ fn insert_hash_into_path(path: &str, hash: &str) -> String {
 
 pub const ROOT_TEMPL: &'static str = include_str!("templates/root.jinja");
 
+/// Context fields holding HTML I rendered (or that config.toml supplies as HTML). Templates
+/// output them as-is; every other string is escaped once when a template outputs it.
+pub const HTML_CONTEXT_FIELDS: &[&str] = &["content", "main_content", "header", "footer", "nav", "head_extra", "dev_script"];
+
+/// A template's context with its `HTML_CONTEXT_FIELDS` marked safe, so autoescaping leaves them be
+fn template_context<T: serde::Serialize>(ctx: T) -> Value {
+    let ctx = Value::from_serialize(ctx);
+    let html: Vec<(&str, Value)> = HTML_CONTEXT_FIELDS
+        .iter()
+        .filter_map(|&field| {
+            let value = ctx.get_attr(field).ok()?;
+            Some((field, Value::from_safe_string(value.as_str()?.to_string())))
+        })
+        .collect();
+
+    // Later maps win
+    minijinja::value::merge_maps([ctx, Value::from_iter(html)])
+}
+
+/// Writes template output, escaping `&`, `<`, `>`, `"` and `'` in anything not marked safe.
+/// MiniJinja's own escaping also turns `/` into `&#x2f;`, which would mangle every URL a template
+/// writes into an attribute.
+fn html_formatter(out: &mut minijinja::Output, state: &State, value: &Value) -> std::result::Result<(), minijinja::Error> {
+    if value.is_safe() || state.auto_escape() != minijinja::AutoEscape::Html {
+        return minijinja::escape_formatter(out, state, value);
+    }
+
+    let text = value.as_str().map(String::from).unwrap_or_else(|| value.to_string());
+    out.write_str(&escape_html(&text)).map_err(minijinja::Error::from)
+}
+
+/// Escape text for HTML element content and quoted attribute values
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
 /// Error type that includes both the MiniJinja error and template hints for suggestions
 pub struct TemplateError {
     pub error: minijinja::Error,
@@ -208,11 +350,6 @@ pub struct TemplateError {
     pub macro_prefix_lines: usize,
 }
 
-/// Help marker prefixes used to identify help requests in error messages
-pub const HELP_MARKER_FUNCTION: &str = "__hugs_help_function__";
-pub const HELP_MARKER_FILTER: &str = "__hugs_help_filter__";
-pub const HELP_MARKER_TEST: &str = "__hugs_help_test__";
-
 /// MiniJinja builtin filters (from minijinja 2.x documentation)
 /// https://docs.rs/minijinja/latest/minijinja/filters/
 const BUILTIN_FILTERS: &[&str] = &[
@@ -233,7 +370,7 @@ const BUILTIN_FILTERS: &[&str] = &[
     // Output formatting
     "format", "indent", "pprint", "tojson",
     // Hugs custom filters
-    "datefmt", "help",
+    "datefmt", "regex_replace", "help",
 ];
 
 /// MiniJinja builtin tests (from minijinja 2.x documentation)
@@ -243,43 +380,15 @@ const BUILTIN_TESTS: &[&str] = &[
     "even", "false", "filter", "float", "ge", "gt", "in", "integer",
     "iterable", "le", "lower", "lt", "mapping", "ne", "none", "number",
     "odd", "safe", "sameas", "sequence", "startingwith", "string",
-    "test", "true", "undefined", "upper", "help",
+    "test", "true", "undefined", "upper", "matches", "help",
 ];
 
-/// Wrap a list of items into lines with a max width
-fn wrap_items_to_lines(items: &[&str], max_width: usize) -> String {
-    let mut result = String::new();
-    let mut current_line = String::from("  ");
-
-    for (i, item) in items.iter().enumerate() {
-        let separator = if i == 0 { "" } else { ", " };
-        let with_sep = format!("{}{}", separator, item);
-
-        if current_line.len() + with_sep.len() > max_width && current_line.len() > 2 {
-            result.push_str(&current_line);
-            result.push('\n');
-            current_line = format!("  {}", item);
-        } else {
-            current_line.push_str(&with_sep);
-        }
-    }
-
-    if !current_line.trim().is_empty() {
-        result.push_str(&current_line);
-        result.push('\n');
-    }
-
-    result
-}
-
 /// Create the `help` function for minijinja
 /// Usage: {{ help() }} - shows all available variables, functions, filters, tests, macros
 fn create_help_function(
     function_names: Vec<String>,
 ) -> impl Fn(&State) -> std::result::Result<Value, minijinja::Error> + Send + Sync + 'static {
     move |state: &State| {
-        use base64::{Engine, engine::general_purpose::STANDARD};
-        
         // Collect variables with their values, sorted alphabetically
         let mut names: Vec<String> = state
             .known_variables()
@@ -287,34 +396,23 @@ fn create_help_function(
             .map(|c| c.into_owned())
             .collect();
         names.sort();
-        
-        let var_entries: Vec<String> = names
+
+        let variables: Vec<HelpVariable> = names
             .into_iter()
-            .filter_map(|name| {
-                // Filter out registered functions (they appear in known_variables but aren't variables)
-                if function_names.contains(&name) {
-                    return None;
-                }
-                let value_repr = state
+            // Filter out registered functions (they appear in known_variables but aren't variables)
+            .filter(|name| !function_names.contains(name))
+            .map(|name| {
+                let value = state
                     .lookup(&name)
                     .map(|v| format!("{:?}", v))
                     .unwrap_or_else(|| "?".to_string());
-                // Encode name and value as base64 to handle all special characters
-                let name_b64 = STANDARD.encode(&name);
-                let value_b64 = STANDARD.encode(&value_repr);
-                Some(format!("{}:{}", name_b64, value_b64))
+                HelpVariable { name, value }
             })
             .collect();
 
-        let msg = format!(
-            "{}:variables={}",
-            HELP_MARKER_FUNCTION,
-            var_entries.join(",")
-        );
-
         Err(minijinja::Error::new(
             minijinja::ErrorKind::InvalidOperation,
-            msg,
+            template_help::function_marker(&variables),
         ))
     }
 }
@@ -322,19 +420,8 @@ fn create_help_function(
 /// Create the `help` filter for minijinja
 /// Usage: {{ value | help }} - shows the value's type/content and applicable filters
 fn create_help_filter() -> impl Fn(&State, Value) -> std::result::Result<Value, minijinja::Error> + Send + Sync + 'static {
-    use base64::{Engine, engine::general_purpose::STANDARD};
-    
     |_state: &State, value: Value| {
-        let value_kind = format!("{:?}", value.kind());
-        let value_repr = format!("{:?}", value);
-        let value_b64 = STANDARD.encode(&value_repr);
-
-        let msg = format!(
-            "{}:kind={}:value={}",
-            HELP_MARKER_FILTER,
-            value_kind,
-            value_b64
-        );
+        let msg = template_help::value_marker(HELP_MARKER_FILTER, &format!("{:?}", value.kind()), &format!("{:?}", value));
 
         Err(minijinja::Error::new(
             minijinja::ErrorKind::InvalidOperation,
@@ -346,19 +433,8 @@ fn create_help_filter() -> impl Fn(&State, Value) -> std::result::Result<Value,
 /// Create the `help` test for minijinja
 /// Usage: {% if value is help %} - shows the value's type/content and applicable tests
 fn create_help_test() -> impl Fn(&State, Value) -> std::result::Result<bool, minijinja::Error> + Send + Sync + 'static {
-    use base64::{Engine, engine::general_purpose::STANDARD};
-    
     |_state: &State, value: Value| {
-        let value_kind = format!("{:?}", value.kind());
-        let value_repr = format!("{:?}", value);
-        let value_b64 = STANDARD.encode(&value_repr);
-
-        let msg = format!(
-            "{}:kind={}:value={}",
-            HELP_MARKER_TEST,
-            value_kind,
-            value_b64
-        );
+        let msg = template_help::value_marker(HELP_MARKER_TEST, &format!("{:?}", value.kind()), &format!("{:?}", value));
 
         Err(minijinja::Error::new(
             minijinja::ErrorKind::InvalidOperation,
@@ -368,15 +444,32 @@ fn create_help_test() -> impl Fn(&State, Value) -> std::result::Result<bool, min
 }
 
 /// Create the `readtime` function for minijinja
-/// Usage: {{ readtime(text) }} - returns estimated reading time in minutes for the given markdown text
+/// Usage: {{ readtime(text) }} - returns estimated reading time in minutes for the given markdown text,
+/// counted in characters when the page's `lang` (or the site's language) is Chinese, Japanese or Korean
 fn create_readtime_function(
-    reading_speed: u32,
-) -> impl Fn(String) -> std::result::Result<u32, minijinja::Error> + Send + Sync + 'static {
-    move |text: String| {
-        let word_count = count_words_in_markdown(&text);
-        let minutes = (word_count as f64 / reading_speed as f64).ceil() as u32;
-        Ok(minutes.max(1))
-    }
+    speed: ReadingSpeed,
+    default_language: String,
+    cache: BuildCache,
+) -> impl Fn(&State, String) -> std::result::Result<u32, minijinja::Error> + Send + Sync + 'static {
+    move |state: &State, text: String| Ok(page_reading_time(state, &text, speed, &default_language, &cache).minutes)
+}
+
+/// Create the `readtime_detail` function for minijinja
+/// Usage: {% set time = readtime_detail(text) %} - the same estimate as `readtime()`, with the
+/// `minutes`, `words`, `characters` and `method` it came from
+fn create_readtime_detail_function(
+    speed: ReadingSpeed,
+    default_language: String,
+    cache: BuildCache,
+) -> impl Fn(&State, String) -> std::result::Result<Value, minijinja::Error> + Send + Sync + 'static {
+    move |state: &State, text: String| Ok(Value::from_object(page_reading_time(state, &text, speed, &default_language, &cache)))
+}
+
+/// Reading time of `text` in the language of the page being rendered
+fn page_reading_time(state: &State, text: &str, speed: ReadingSpeed, default_language: &str, cache: &BuildCache) -> ReadingTime {
+    let page_lang = state.lookup("lang");
+    let lang = page_lang.as_ref().and_then(Value::as_str).unwrap_or(default_language);
+    reading_time(text, lang, speed, cache)
 }
 
 /// Parse a locale string into a chrono Locale.
@@ -386,35 +479,6 @@ fn parse_locale(s: &str) -> Option<Locale> {
     Locale::try_from(normalized.as_str()).ok()
 }
 
-/// Parse a date string into a DateTime<Utc>.
-/// Supports: ISO 8601/RFC 3339, YYYY-MM-DD, YYYY-MM-DD HH:MM:SS
-fn parse_date_string_for_filter(s: &str) -> std::result::Result<DateTime<Utc>, minijinja::Error> {
-    // ISO 8601 / RFC 3339 (2024-01-15T10:30:00Z)
-    if let Ok(dt) = DateTime::parse_from_rfc3339(s) {
-        return Ok(dt.with_timezone(&Utc));
-    }
-
-    // YYYY-MM-DD (2024-01-15)
-    if let Ok(nd) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
-        if let Some(ndt) = nd.and_hms_opt(0, 0, 0) {
-            return Ok(DateTime::from_naive_utc_and_offset(ndt, Utc));
-        }
-    }
-
-    // YYYY-MM-DD HH:MM:SS (2024-01-15 10:30:00)
-    if let Ok(ndt) = NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S") {
-        return Ok(DateTime::from_naive_utc_and_offset(ndt, Utc));
-    }
-
-    Err(minijinja::Error::new(
-        minijinja::ErrorKind::InvalidOperation,
-        format!(
-            "datefmt: couldn't parse date '{}'. Supported formats: YYYY-MM-DD, YYYY-MM-DDTHH:MM:SSZ, YYYY-MM-DD HH:MM:SS",
-            s
-        ),
-    ))
-}
-
 /// Create the `datefmt` filter for locale-aware date formatting.
 ///
 /// Usage in templates:
@@ -422,6 +486,7 @@ fn parse_date_string_for_filter(s: &str) -> std::result::Result<DateTime<Utc>, m
 ///   {{ page.date | datefmt("%A, %d %B %Y", locale="fr_FR") }}
 fn create_datefmt_filter(
     default_locale: String,
+    dates: DateParser,
 ) -> impl Fn(&State, Value, String, minijinja::value::Kwargs) -> std::result::Result<String, minijinja::Error>
        + Send
        + Sync
@@ -439,9 +504,17 @@ fn create_datefmt_filter(
             None => default_locale_parsed,
         };
 
-        // Parse the date value
+        // Parse the date value, showing it in the site's timezone
         let datetime = match value.as_str() {
-            Some(s) => parse_date_string_for_filter(s)?,
+            Some(s) => match dates.parse(s) {
+                Some(datetime) => dates.local(datetime),
+                None => {
+                    return Err(minijinja::Error::new(
+                        minijinja::ErrorKind::InvalidOperation,
+                        format!("datefmt: {}", dates.unparseable(s)),
+                    ))
+                }
+            },
             None => {
                 return Err(minijinja::Error::new(
                     minijinja::ErrorKind::InvalidOperation,
@@ -489,15 +562,89 @@ fn create_flatten_filter(
     }
 }
 
-/// Count words in markdown content, stripping HTML tags and markdown syntax
-fn count_words_in_markdown(text: &str) -> usize {
-    let without_code_blocks = strip_code_blocks(text);
-    let without_html = strip_html_tags(&without_code_blocks);
-    let without_markdown = strip_markdown_syntax(&without_html);
-    without_markdown.split_whitespace().count()
+/// The spaceless filter drops whitespace between HTML tags, leaving text and
+/// anything inside `<pre>`, `<code>`, `<textarea>`, `<script>` or `<style>` alone.
+///
+/// Usage in templates:
+///   {{ nav_html | spaceless }}
+///   {% filter spaceless %}<ul>
+///     <li>One</li>
+///   </ul>{% endfilter %}  -> <ul><li>One</li></ul>
+fn spaceless_filter(value: Value) -> Value {
+    let html = value.as_str().map(String::from).unwrap_or_else(|| value.to_string());
+    let collapsed = collapse_whitespace_between_tags(&html);
+    if value.is_safe() {
+        Value::from_safe_string(collapsed)
+    } else {
+        Value::from(collapsed)
+    }
+}
+
+/// Elements whose whitespace is part of their content
+const WHITESPACE_SENSITIVE_TAGS: &[&str] = &["pre", "code", "textarea"];
+
+/// Elements whose content isn't HTML, so a `<` in it doesn't start a tag
+const RAW_TEXT_TAGS: &[&str] = &["script", "style"];
+
+fn collapse_whitespace_between_tags(html: &str) -> String {
+    let mut result = String::with_capacity(html.len());
+    let mut preserve_depth = 0usize;
+    let mut rest = html;
+
+    while let Some(tag_start) = rest.find('<') {
+        let (text, tail) = rest.split_at(tag_start);
+        let between_tags = result.ends_with('>') && text.trim().is_empty();
+        if preserve_depth > 0 || !between_tags {
+            result.push_str(text);
+        }
+
+        let tag_end = tail.find('>').map_or(tail.len(), |i| i + 1);
+        let tag = &tail[..tag_end];
+
+        let (closing, name_start) = match tag.strip_prefix("</") {
+            Some(name) => (true, name),
+            None => (false, &tag[1..]),
+        };
+        let name: String = name_start
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric())
+            .collect::<String>()
+            .to_ascii_lowercase();
+        if WHITESPACE_SENSITIVE_TAGS.contains(&name.as_str()) {
+            if closing {
+                preserve_depth = preserve_depth.saturating_sub(1);
+            } else if !tag.ends_with("/>") {
+                preserve_depth += 1;
+            }
+        }
+
+        result.push_str(tag);
+        rest = &tail[tag_end..];
+
+        // Everything up to the closing tag is kept as it is
+        if !closing && !tag.ends_with("/>") && RAW_TEXT_TAGS.contains(&name.as_str()) {
+            let closing_tag = format!("</{}", name);
+            let content_end = rest
+                .as_bytes()
+                .windows(closing_tag.len())
+                .position(|window| window.eq_ignore_ascii_case(closing_tag.as_bytes()))
+                .unwrap_or(rest.len());
+            result.push_str(&rest[..content_end]);
+            rest = &rest[content_end..];
+        }
+    }
+
+    result.push_str(rest);
+    result
+}
+
+/// Markdown without its HTML tags and markdown syntax, leaving the words
+pub fn markdown_plain_text(text: &str) -> String {
+    strip_markdown_syntax(&strip_html_tags(text))
 }
 
-fn strip_code_blocks(text: &str) -> String {
+/// Markdown without its fenced code blocks
+pub(crate) fn strip_code_blocks(text: &str) -> String {
     let mut result = String::new();
     let mut in_code_block = false;
 
@@ -536,7 +683,7 @@ fn strip_markdown_syntax(text: &str) -> String {
     result = result.replace("__", "");
     result = result.replace("~~", "");
     result = result.replace('*', "");
-    result = result.replace('_', " ");
+    result = strip_underscore_emphasis(&result);
     result = result.replace('#', "");
     result = result.replace('>', "");
     result = result.replace('[', "");
@@ -547,26 +694,87 @@ fn strip_markdown_syntax(text: &str) -> String {
     result
 }
 
+/// Drop the `_` of `_emphasis_`, keeping the ones inside words like `snake_case`
+fn strip_underscore_emphasis(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    chars
+        .iter()
+        .enumerate()
+        .filter(|&(i, &c)| {
+            c != '_'
+                || (i > 0 && chars[i - 1].is_alphanumeric() && chars.get(i + 1).is_some_and(|next| next.is_alphanumeric()))
+        })
+        .map(|(_, &c)| c)
+        .collect()
+}
+
+/// What a template is rendered against: the site's pages and config, the functions' shared state,
+/// and where to record what the render reads
+#[derive(Clone, Copy)]
+pub struct RenderContext<'a> {
+    pub pages: &'a Arc<Vec<PageInfo>>,
+    pub cache_bust: Option<&'a CacheBustFunction>,
+    pub svg: Option<&'a SvgInliner>,
+    /// Results kept between builds, for `image_size()` and `readtime()`
+    pub cache: &'a BuildCache,
+    pub code_languages: &'a CodeLanguages,
+    pub macros_template: &'a str,
+    pub config: &'a SiteConfig,
+    pub mode: RenderMode,
+    pub deps: &'a DepRecorder,
+}
+
 /// Create a configured template environment with custom functions
-fn create_template_env(
-    pages: &Arc<Vec<PageInfo>>,
-    cache_bust: Option<&CacheBustFunction>,
-    reading_speed: u32,
-    default_language: &str,
-) -> (Environment<'static>, TemplateHints) {
+fn create_template_env(render: &RenderContext<'_>, template_config: &TemplateConfig) -> (Environment<'static>, TemplateHints) {
     let mut env = Environment::new();
-    env.add_function("pages", create_pages_function(Arc::clone(pages)));
-    env.add_function("readtime", create_readtime_function(reading_speed));
+    env.set_auto_escape_callback(|_| minijinja::AutoEscape::Html);
+    env.set_formatter(html_formatter);
+    let RenderContext { pages, cache_bust, svg, cache, code_languages, config, mode, deps, .. } = *render;
+    let reading_speed = ReadingSpeed::from_config(&config.build);
+    let default_language = &config.site.language;
+    env.set_trim_blocks(template_config.trim_blocks);
+    env.set_lstrip_blocks(template_config.lstrip_blocks);
+    env.add_global("hugs", minijinja::context! { mode => mode, dev => mode == RenderMode::Dev });
+    let pages_fn = create_pages_function(Arc::clone(pages));
+    let pages_deps = deps.clone();
+    env.add_function("pages", move |kwargs: minijinja::value::Kwargs| {
+        pages_deps.record_pages();
+        pages_fn(kwargs)
+    });
+    let collection_pages = Arc::clone(pages);
+    let collections = config.collections.clone();
+    let collection_deps = deps.clone();
+    env.add_function("collection", move |name: String| {
+        collection_deps.record_pages();
+        crate::collections::collection_function(&collections, &collection_pages, &name)
+    });
+    env.add_function("readtime", create_readtime_function(reading_speed, default_language.to_string(), cache.clone()));
+    env.add_function("readtime_detail", create_readtime_detail_function(reading_speed, default_language.to_string(), cache.clone()));
+    env.add_function("page_token", |name: String| page_token(&name));
     if let Some(cb) = cache_bust {
-        env.add_function("cache_bust", cb.to_minijinja_fn());
+        env.add_function("cache_bust", cb.to_minijinja_fn(deps));
+    }
+    if let Some(svg) = svg {
+        env.add_function("inline_svg", svg.to_minijinja_fn(deps));
+        env.add_function("qr", svg.computed.qr_fn());
+        env.add_function("avatar", svg.computed.avatar_fn());
+        env.add_function("image_size", svg.image_size_fn(cache, deps));
     }
+    env.add_function("code_languages", code_languages.to_minijinja_fn(deps));
 
     // Add the datefmt filter with the site's default locale
-    env.add_filter("datefmt", create_datefmt_filter(default_language.to_string()));
+    env.add_filter("datefmt", create_datefmt_filter(default_language.to_string(), DateParser::new(config)));
 
     // Add the flatten filter for flattening nested sequences
     env.add_filter("flatten", create_flatten_filter());
 
+    // Add the spaceless filter for dropping whitespace between HTML tags
+    env.add_filter("spaceless", spaceless_filter);
+
+    // Add the regex test and filter
+    env.add_test("matches", matches_test);
+    env.add_filter("regex_replace", regex_replace_filter);
+
     // Collect function names before adding help (includes builtins + our functions)
     let mut function_names: Vec<String> = env.globals().map(|(name, _)| name.to_string()).collect();
     function_names.push("help".to_string()); // include help itself
@@ -600,19 +808,60 @@ fn extract_macro_names(macros_template: &str) -> Vec<String> {
     names
 }
 
+/// How many renders can be under way at once on a thread, counting one a template starts from
+/// inside another. More than this is a loop, like an include that reaches back into the content
+/// template that included it.
+const MAX_RENDER_DEPTH: usize = 8;
+
+thread_local! {
+    static RENDER_DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+/// One level of render depth, held for as long as a render is under way
+struct RenderDepth;
+
+impl RenderDepth {
+    fn enter() -> std::result::Result<Self, minijinja::Error> {
+        RENDER_DEPTH.with(|depth| {
+            if depth.get() >= MAX_RENDER_DEPTH {
+                return Err(minijinja::Error::new(
+                    minijinja::ErrorKind::InvalidOperation,
+                    format!("templates are rendering each other more than {} deep, which is probably a loop", MAX_RENDER_DEPTH),
+                ));
+            }
+            depth.set(depth.get() + 1);
+            Ok(RenderDepth)
+        })
+    }
+}
+
+impl Drop for RenderDepth {
+    fn drop(&mut self) {
+        RENDER_DEPTH.with(|depth| depth.set(depth.get() - 1));
+    }
+}
+
 pub fn render_template<T: serde::Serialize>(
     template: &str,
     ctx: T,
-    pages: &Arc<Vec<PageInfo>>,
-    cache_bust: Option<&CacheBustFunction>,
-    macros_template: &str,
-    reading_speed: u32,
-    default_language: &str,
+    render: &RenderContext<'_>,
 ) -> std::result::Result<String, TemplateError> {
-    let (mut env, hints) = create_template_env(pages, cache_bust, reading_speed, default_language);
+    render_template_each(template, [ctx], render).map(|mut rendered| rendered.remove(0))
+}
+
+/// Render `template` once for each of `ctxs`, setting the environment up only once
+pub fn render_template_each<T: serde::Serialize>(
+    template: &str,
+    ctxs: impl IntoIterator<Item = T>,
+    render: &RenderContext<'_>,
+) -> std::result::Result<Vec<String>, TemplateError> {
+    let _timer = timings::PhaseTimer::start(Phase::Template);
+    let (mut env, hints) = create_template_env(render, &render.config.template);
+    let macros_template = render.macros_template;
 
     // Extract macro names and add them to hints for error suggestions
     let macro_names = extract_macro_names(macros_template);
+    record_macro_uses(template, macros_template, &macro_names, render.deps);
     let hints = hints.with_macros(macro_names);
 
     // Calculate macro prefix metrics for error position adjustment
@@ -631,9 +880,46 @@ pub fn render_template<T: serde::Serialize>(
     };
 
     let make_err = |e| TemplateError { error: e, hints: hints.clone(), macro_prefix_bytes, macro_prefix_lines };
+    let _depth = RenderDepth::enter().map_err(make_err)?;
     env.add_template("template", &full_template).map_err(make_err)?;
     let tmpl = env.get_template("template").map_err(make_err)?;
-    tmpl.render(ctx).map_err(|e| TemplateError { error: e, hints, macro_prefix_bytes, macro_prefix_lines })
+    ctxs.into_iter()
+        .map(|ctx| tmpl.render(template_context(ctx)).map_err(make_err))
+        .collect()
+}
+
+/// Record which of `macro_names` a template refers to, and the macros those call in turn. Macro
+/// calls can't be intercepted like functions, so this looks at the names the templates use instead.
+fn record_macro_uses(template: &str, macros_template: &str, macro_names: &[String], deps: &DepRecorder) {
+    if macro_names.is_empty() {
+        return;
+    }
+    let env = Environment::new();
+    let names_used = |source: &str| {
+        env.template_from_str(source)
+            .map(|tmpl| tmpl.undeclared_variables(false))
+            .unwrap_or_default()
+    };
+    let mut pending: Vec<String> = names_used(template).into_iter().filter(|name| macro_names.contains(name)).collect();
+    if pending.is_empty() {
+        return;
+    }
+
+    // What each macro's own body uses
+    let calls: HashMap<String, HashSet<String>> = macros_template
+        .split_inclusive("{% endmacro %}")
+        .filter_map(|definition| Some((extract_macro_names(definition).into_iter().next()?, names_used(definition))))
+        .collect();
+    let mut recorded = HashSet::new();
+    while let Some(name) = pending.pop() {
+        if !macro_names.contains(&name) || !recorded.insert(name.clone()) {
+            continue;
+        }
+        deps.record_macro(&name);
+        if let Some(called) = calls.get(&name) {
+            pending.extend(called.iter().cloned());
+        }
+    }
 }
 
 /// Render using the root template
@@ -641,8 +927,34 @@ pub fn render_root_template<T: serde::Serialize>(
     app_data: &AppData,
     ctx: T,
     cache_bust: &CacheBustFunction,
+    deps: &DepRecorder,
 ) -> std::result::Result<String, TemplateError> {
-    let (mut env, hints) = create_template_env(&app_data.pages, Some(cache_bust), app_data.config.build.reading_speed, &app_data.config.site.language);
+    with_root_template(app_data, cache_bust, deps, |tmpl| tmpl.render(template_context(ctx)))
+}
+
+/// Render using the root template, writing the page to `out` as it renders instead of
+/// collecting it into a string
+pub fn render_root_template_to<T: serde::Serialize, W: std::io::Write>(
+    app_data: &AppData,
+    ctx: T,
+    cache_bust: &CacheBustFunction,
+    deps: &DepRecorder,
+    out: W,
+) -> std::result::Result<(), TemplateError> {
+    with_root_template(app_data, cache_bust, deps, |tmpl| tmpl.render_captured_to(template_context(ctx), out).map(|_| ()))
+}
+
+/// Set up the root template with the site's macros and hand it to `render`
+fn with_root_template<R>(
+    app_data: &AppData,
+    cache_bust: &CacheBustFunction,
+    deps: &DepRecorder,
+    render: impl FnOnce(&minijinja::Template<'_, '_>) -> std::result::Result<R, minijinja::Error>,
+) -> std::result::Result<R, TemplateError> {
+    let _timer = timings::PhaseTimer::start(Phase::Template);
+    // root.jinja is laid out for the default whitespace handling, so `[template]` trimming only applies to site templates
+    let context = RenderContext { cache_bust: Some(cache_bust), ..app_data.render_context(deps) };
+    let (mut env, hints) = create_template_env(&context, &TemplateConfig::default());
 
     // Extract macro names and add them to hints for error suggestions
     let macro_names = extract_macro_names(&app_data.macros_template);
@@ -664,21 +976,19 @@ pub fn render_root_template<T: serde::Serialize>(
     };
 
     let make_err = |e| TemplateError { error: e, hints: hints.clone(), macro_prefix_bytes, macro_prefix_lines };
+    let _depth = RenderDepth::enter().map_err(make_err)?;
     env.add_template("root", &full_root_template).map_err(make_err)?;
     let tmpl = env.get_template("root").map_err(make_err)?;
-    tmpl.render(ctx).map_err(|e| TemplateError { error: e, hints, macro_prefix_bytes, macro_prefix_lines })
+    render(&tmpl).map_err(|e| TemplateError { error: e, hints, macro_prefix_bytes, macro_prefix_lines })
 }
 
 fn parse_md(
     content_jinja_md: &str,
     page_content: &PageContent<'_>,
-    pages: &Arc<Vec<PageInfo>>,
     source_name: &str,
-    macros_template: &str,
-    reading_speed: u32,
-    default_language: &str,
+    render: &RenderContext<'_>,
 ) -> Result<String> {
-    let content_md = render_template(content_jinja_md, page_content, pages, None, macros_template, reading_speed, default_language)
+    let content_md = render_template(content_jinja_md, page_content, render)
         .map_err(|e| HugsError::template_render_named(
             source_name,
             content_jinja_md,
@@ -688,7 +998,7 @@ fn parse_md(
             e.macro_prefix_lines,
         ))?;
 
-    markdown::to_html_with_options(&content_md, &markdown_options()).map_err(|e| HugsError::MarkdownParse {
+    timings::phase(Phase::Markdown, || markdown::to_html_with_options(&content_md, &markdown_options())).map_err(|e| HugsError::MarkdownParse {
         file: source_name.into(),
         reason: e.to_string(),
     })
@@ -698,10 +1008,20 @@ fn parse_md(
 pub struct AppData {
     pub site_path: PathBuf,
 
+    /// Where static files are published from: `[paths] static`, and `[paths] content` for files
+    /// kept next to pages
+    pub asset_roots: AssetRoots,
+
+    /// What git ignores, with `[build] respect_gitignore`
+    pub git_ignored: GitIgnored,
+
     pub header_html: String,
     pub footer_html: String,
     pub nav_html: String,
 
+    /// Why the header, footer or nav didn't render on a reload, which kept its last good HTML
+    pub chrome_errors: Vec<HugsError>,
+
     pub theme_css: String,
 
     /// All pages including expanded dynamic pages
@@ -710,31 +1030,137 @@ pub struct AppData {
     /// Dynamic page definitions (for dev server pattern matching)
     pub dynamic_defs: Arc<Vec<DynamicPageDef>>,
 
+    /// Page files as the scan read them, by path relative to the site root
+    pub page_sources: HashMap<String, Arc<PageSource>>,
+
+    /// The languages of the site's code blocks, for `code_languages()`
+    pub code_languages: CodeLanguages,
+
+    /// Whether rendering uses `page_sources` or reads page files again
+    pub source_reads: SourceReads,
+
+    /// Whether pages are rendered for `hugs dev` or to publish
+    pub mode: RenderMode,
+
+    /// Minifies the scripts and SVGs a build publishes, when `[build.minify]` asks for it
+    pub asset_minifier: Option<AssetMinifier>,
+
     pub notfound_page: Option<PathBuf>,
 
     pub config: SiteConfig,
 
     pub cache_bust_registry: CacheBustRegistry,
 
+    /// SVG files `inline_svg()` has read, shared by every render from this load
+    pub svg_inliner: SvgInliner,
+
+    /// Results kept between builds, for `image_size()` and `readtime()`. Off unless `hugs build`
+    /// opens it.
+    pub build_cache: BuildCache,
+
     /// Pre-generated CSS for syntax highlighting
     pub highlight_css: String,
 
     /// Pre-built template containing all macro definitions from _/macros/
     pub macros_template: String,
 
-    /// Content template from _/content.md (defaults to "{{ content }}")
-    pub content_template: String,
+    /// Macros template that marks each macro's output, for naming the macro behind broken HTML
+    pub macro_trace: MacroTrace,
+
+    /// Content templates from _/content.md and the `_content.md` files in content folders
+    pub content_templates: ContentTemplates,
+
+    /// Template for each entry of an `auto_list` listing, from _/list-item.md if there is one
+    pub list_item_template: Option<String>,
+
+    /// Template for sitemap.xml, from _/sitemap.jinja if there is one
+    pub sitemap_template: Option<String>,
+
+    /// Unique id for this load of the site; anything rendered from an older generation is stale
+    pub generation: u64,
+
+    /// What the header, footer and nav read, which every page embeds
+    pub shared_deps: RenderDeps,
+
+    /// What each page's last render read, by URL
+    pub page_deps: Arc<Mutex<HashMap<String, RenderDeps>>>,
+
+    /// Excluded files `hugs dev` has said it won't serve since the site loaded, so each is only
+    /// warned about once per reload
+    pub excluded_requests: Arc<Mutex<HashSet<String>>>,
+
+    /// Code block languages I didn't know and highlighted as plain text, for the build summary
+    pub unknown_languages: Arc<UnknownLanguages>,
+
+    /// Pages `title_template` failed for, for the build summary
+    pub title_failures: Arc<TitleFailures>,
 }
 
+/// Source of `AppData::generation` ids
+static NEXT_GENERATION: AtomicU64 = AtomicU64::new(1);
+
 impl AppData {
     /// Create a CacheBustFunction configured for this site
     pub fn cache_bust_function(&self) -> CacheBustFunction {
         CacheBustFunction::new(
-            self.site_path.clone(),
+            self.asset_roots.clone(),
             self.theme_css.clone(),
             self.highlight_css.clone(),
             self.cache_bust_registry.clone(),
         )
+        .with_minifier(self.asset_minifier.clone())
+    }
+
+    /// What page bodies and content templates render against, recording into `deps`
+    pub fn render_context<'a>(&'a self, deps: &'a DepRecorder) -> RenderContext<'a> {
+        RenderContext {
+            pages: &self.pages,
+            cache_bust: None,
+            svg: Some(&self.svg_inliner),
+            cache: &self.build_cache,
+            code_languages: &self.code_languages,
+            macros_template: &self.macros_template,
+            config: &self.config,
+            mode: self.mode,
+            deps,
+        }
+    }
+
+    /// The page file at `relative_path` (relative to the site root), as the scan read it or as it
+    /// is now, depending on `source_reads`. Files the scan didn't read are read now.
+    pub async fn page_source(&self, relative_path: &str) -> Result<Arc<PageSource>> {
+        if self.source_reads == SourceReads::Scanned
+            && let Some(source) = self.page_sources.get(relative_path)
+        {
+            return Ok(Arc::clone(source));
+        }
+
+        let full_path = self.site_path.join(relative_path);
+        let text = tokio::fs::read_to_string(&full_path).await.with_file_read(&full_path)?;
+        PageSource::parse(text).map(Arc::new).map_err(|(text, e)| HugsError::FrontmatterParse {
+            file: relative_path.into(),
+            src: miette::NamedSource::new(relative_path, text),
+            span: miette::SourceSpan::from((0_usize, 1_usize)),
+            reason: format!("Failed to parse frontmatter as YAML: {}", e),
+        })
+    }
+
+    /// Whether there's a page file at `relative_path`, going by the scan unless reads are fresh
+    fn has_page_file(&self, relative_path: &str) -> bool {
+        let path = self.site_path.join(relative_path);
+        !self.git_ignored.contains(&path)
+            && ((self.source_reads == SourceReads::Scanned && self.page_sources.contains_key(relative_path)) || path.exists())
+    }
+
+    /// Remember what a page's render read, including what the parts shared by every page read
+    pub fn record_page_deps(&self, url: &str, deps: RenderDeps) {
+        let mut all = self.shared_deps.clone();
+        all.merge(&deps);
+        self.lock_page_deps().insert(url.to_string(), all);
+    }
+
+    pub fn lock_page_deps(&self) -> MutexGuard<'_, HashMap<String, RenderDeps>> {
+        self.page_deps.lock().unwrap_or_else(PoisonError::into_inner)
     }
 }
 
@@ -743,6 +1169,10 @@ async fn read_required_file(
     file_type: &'static str,
     relative_path: &str,
 ) -> Result<String> {
+    if path.is_dir() {
+        return Err(not_a_file_error(file_type, relative_path));
+    }
+
     tokio::fs::read_to_string(path).await.map_err(|e| {
         if e.kind() == std::io::ErrorKind::NotFound {
             HugsError::RequiredFileMissing {
@@ -762,8 +1192,47 @@ async fn read_required_file(
     })
 }
 
+fn not_a_file_error(file_type: &'static str, relative_path: &str) -> HugsError {
+    HugsError::RequiredFileIsDirectory {
+        file_type,
+        path: relative_path.into(),
+        suggestion: format!(
+            "`{}` should be a plain file, not a folder. Remove the folder and create a file with that name instead.",
+            relative_path
+        ),
+    }
+}
+
+/// Warn about site files that exist but have nothing in them, unless `build.allow_empty` lists them
+fn warn_empty_site_files(files: &[(&str, &str)], config: &SiteConfig) {
+    let empty: Vec<&str> = files
+        .iter()
+        .filter(|(path, content)| content.trim().is_empty() && !config.build.allow_empty.iter().any(|a| a == path))
+        .map(|(path, _)| *path)
+        .collect();
+
+    if !empty.is_empty() {
+        console::warn(format!(
+            "{} {} empty. If that's on purpose, list {} under `allow_empty` in the [build] section of config.toml",
+            empty.join(", "),
+            if empty.len() == 1 { "is" } else { "are" },
+            if empty.len() == 1 { "it" } else { "them" },
+        ));
+    }
+}
+
 impl AppData {
     pub async fn load(site_path: PathBuf, command: &str) -> Result<AppData> {
+        Self::load_after(site_path, command, None).await
+    }
+
+    /// Load the site again after a change. When the header, footer or nav doesn't render, the
+    /// `previous` load's HTML for it stays in place, and the error goes in `chrome_errors`.
+    pub async fn reload(site_path: PathBuf, command: &str, previous: &AppData) -> Result<AppData> {
+        Self::load_after(site_path, command, Some(previous)).await
+    }
+
+    async fn load_after(site_path: PathBuf, command: &str, previous: Option<&AppData>) -> Result<AppData> {
         // Check if this looks like a valid Hugs site
         let underscore_dir = site_path.join("_");
         if !site_path.is_dir() || !underscore_dir.is_dir() {
@@ -779,23 +1248,64 @@ impl AppData {
         let nav_path = site_path.join("_/nav.md");
         let theme_path = site_path.join("_/theme.css");
         let content_template_path = site_path.join("_/content.md");
+        let list_item_path = site_path.join("_/list-item.md");
+        let sitemap_template_path = site_path.join("_/sitemap.jinja");
 
         let header_md = read_required_file(&header_path, "header", "_/header.md").await?;
         let footer_md = read_required_file(&footer_path, "footer", "_/footer.md").await?;
         let nav_md = read_required_file(&nav_path, "navigation", "_/nav.md").await?;
         let theme_css = read_required_file(&theme_path, "theme stylesheet", "_/theme.css").await?;
-        let content_template = if content_template_path.exists() {
-            tokio::fs::read_to_string(&content_template_path).await.map_err(|e| HugsError::FileRead {
+        let custom_content_template = if content_template_path.is_dir() {
+            return Err(not_a_file_error("content template", "_/content.md"));
+        } else if content_template_path.exists() {
+            Some(tokio::fs::read_to_string(&content_template_path).await.map_err(|e| HugsError::FileRead {
                 path: content_template_path.clone().into(),
                 cause: e,
-            })?
+            })?)
+        } else {
+            None
+        };
+        let list_item_template = if list_item_path.is_dir() {
+            return Err(not_a_file_error("list item template", "_/list-item.md"));
+        } else if list_item_path.exists() {
+            Some(tokio::fs::read_to_string(&list_item_path).await.map_err(|e| HugsError::FileRead {
+                path: list_item_path.clone().into(),
+                cause: e,
+            })?)
+        } else {
+            None
+        };
+        let sitemap_template = if sitemap_template_path.is_dir() {
+            return Err(not_a_file_error("sitemap template", "_/sitemap.jinja"));
+        } else if sitemap_template_path.exists() {
+            Some(tokio::fs::read_to_string(&sitemap_template_path).await.map_err(|e| HugsError::FileRead {
+                path: sitemap_template_path.clone().into(),
+                cause: e,
+            })?)
         } else {
-            String::from("{{ content }}")
+            None
         };
         let config = SiteConfig::load(&site_path).await?;
+        config.paths.check(&site_path)?;
+        validate_title_template(&config)?;
+        crate::feed::validate_feed_outputs(&config)?;
+        if let Some(template) = &sitemap_template {
+            crate::sitemap::validate_sitemap_template(template, &config.site)?;
+        }
+
+        warn_empty_site_files(
+            &[
+                ("_/header.md", &header_md),
+                ("_/footer.md", &footer_md),
+                ("_/nav.md", &nav_md),
+                ("_/theme.css", &theme_css),
+            ],
+            &config,
+        );
 
         // Initialize syntax highlighting registry and generate CSS
-        crate::highlight::init_registry();
+        crate::highlight::init_registry()?;
+        validate_highlighting(&config)?;
         let highlight_css = if config.build.syntax_highlighting.enabled {
             crate::highlight::generate_theme_css(&config.build.syntax_highlighting.theme)
         } else {
@@ -804,26 +1314,73 @@ impl AppData {
 
         // Load macros from _/macros/ directory
         let macros = load_macros(&site_path).await?;
-        let macros_template = build_macros_template(&macros);
+        let mut macros_template = build_macros_template(&macros, false);
+
+        // Compile `[template.functions]` from config.toml into macros, so they're callable everywhere macros are
+        let template_functions = build_template_functions(&config, &macros, &site_path)?;
+        macros_template.push_str(&template_functions);
+
+        let macro_trace = MacroTrace {
+            template: build_macros_template(&macros, true) + &template_functions,
+            files: macros
+                .iter()
+                .map(|m| {
+                    let file = m.source_path.strip_prefix(&site_path).unwrap_or(&m.source_path);
+                    (m.name.clone(), relative_path_to_url_string(file))
+                })
+                .collect(),
+        };
+
+        let mode = RenderMode::for_command(command);
 
         // Phase 1: Scan pages and collect static pages + raw dynamic definitions
-        let raw_scan_result = scan_pages_raw(&site_path).await?;
+        let transliteration = config.site.url_transliteration;
+        let git_ignored = match config.build.respect_gitignore {
+            true => GitIgnored::scan(&site_path),
+            false => GitIgnored::default(),
+        };
+        let raw_scan_result = scan_pages_raw(&site_path, &config, &git_ignored).await?;
 
         // Create initial pages Arc with just static pages (for dynamic param evaluation)
         let static_pages = Arc::new(raw_scan_result.static_pages.clone());
 
         // Phase 2: Evaluate dynamic page parameters (now pages() is available)
-        let dynamic_defs = evaluate_dynamic_defs(raw_scan_result.raw_dynamic_defs, &static_pages)?;
+        let dynamic_defs = evaluate_dynamic_defs(raw_scan_result.raw_dynamic_defs, &static_pages, config.build.dynamic_param_chars)?;
+        check_dynamic_index_conflicts(&dynamic_defs, &static_pages, transliteration)?;
 
         // Expand dynamic pages into concrete pages
-        let expanded_pages = expand_dynamic_pages(&dynamic_defs);
+        let expanded_pages = expand_dynamic_pages(&dynamic_defs, transliteration, config.build.url_style, &config.build.private_frontmatter_keys);
 
         // Combine static and expanded pages
+        let page_sources = raw_scan_result.sources;
         let mut all_pages = raw_scan_result.static_pages;
         all_pages.extend(expanded_pages);
+        if transliteration == UrlTransliteration::Ascii {
+            check_url_collisions(&all_pages)?;
+        }
+        crate::collections::check_collections(&config, &all_pages)?;
+        crate::redirects::check_aliases(&all_pages)?;
+        let page_files = all_pages.iter().map(|page| page.file_path.as_str());
+        let content_templates = ContentTemplates::load(&site_path, custom_content_template, page_files).await?;
+
+        // Macros can read the calling page's frontmatter, so any page's keys are fair game
+        let page_keys: BTreeSet<String> = all_pages
+            .iter()
+            .map(|page| &page.frontmatter)
+            .chain(page_sources.values().map(|source| &source.frontmatter))
+            .filter_map(YamlValue::as_mapping)
+            .flat_map(|map| map.keys().filter_map(YamlValue::as_str).map(str::to_string))
+            .collect();
+        for warning in check_macros(&macros, &config, &site_path, &page_keys)? {
+            console::warn(warning);
+        }
 
         let pages = Arc::new(all_pages);
         let dynamic_defs = Arc::new(dynamic_defs);
+        let aliases = &config.build.syntax_highlighting.aliases;
+        let code_languages = CodeLanguages::collect(&raw_scan_result.fences, &pages, |name| {
+            crate::highlight::resolve_language(name, aliases).map(String::from)
+        });
 
         let initial_page_content = PageContent {
             title: "",
@@ -834,19 +1391,51 @@ impl AppData {
             main_content: "",
             path_class: "",
             base: "/",
+            is_notfound: false,
             dev_script: "",
             seo: SeoContext::default(),
             syntax_highlighting_enabled: false,
             head_extra: "",
         };
 
-        let reading_speed = config.build.reading_speed;
-        let default_language = &config.site.language;
-        let header_html = parse_md(&header_md, &initial_page_content, &pages, "_/header.md", &macros_template, reading_speed, default_language)?;
-        let footer_html = parse_md(&footer_md, &initial_page_content, &pages, "_/footer.md", &macros_template, reading_speed, default_language)?;
-        let nav_html = parse_md(&nav_md, &initial_page_content, &pages, "_/nav.md", &macros_template, reading_speed, default_language)?;
+        let asset_roots = AssetRoots::new(&site_path, &config.paths);
+        // `hugs dev` serves files as they are
+        let asset_minifier = match mode {
+            RenderMode::Build => site_asset_minifier(&config, &asset_roots, &theme_css),
+            RenderMode::Dev => None,
+        };
+        let svg_inliner = SvgInliner::new(asset_roots.clone());
+        let shared_deps = DepRecorder::default();
+        let mut chrome_errors = Vec::new();
+        let mut render_chrome = |markdown: &str, file: &str, last_good: fn(&AppData) -> &String| {
+            let render = RenderContext {
+                pages: &pages,
+                cache_bust: None,
+                svg: Some(&svg_inliner),
+                cache: &BuildCache::default(),
+                code_languages: &code_languages,
+                macros_template: &macros_template,
+                config: &config,
+                mode,
+                deps: &shared_deps,
+            };
+            match parse_md(markdown, &initial_page_content, file, &render) {
+                Ok(html) => Ok(html),
+                // A half-finished edit shouldn't take the whole site down once it has loaded
+                Err(e) => match previous {
+                    Some(previous) => {
+                        chrome_errors.push(e);
+                        Ok(last_good(previous).clone())
+                    }
+                    None => Err(e),
+                },
+            }
+        };
+        let header_html = render_chrome(&header_md, "_/header.md", |data| &data.header_html)?;
+        let footer_html = render_chrome(&footer_md, "_/footer.md", |data| &data.footer_html)?;
+        let nav_html = render_chrome(&nav_md, "_/nav.md", |data| &data.nav_html)?;
 
-        let notfound_path = site_path.join("[404].md");
+        let notfound_path = site_path.join(config.paths.content_dir()).join("[404].md");
         let notfound_page = if notfound_path.exists() {
             Some(notfound_path)
         } else {
@@ -855,18 +1444,37 @@ impl AppData {
 
         Ok(AppData {
             site_path,
+            asset_roots,
+            git_ignored,
             header_html,
             footer_html,
             nav_html,
+            chrome_errors,
             theme_css,
             pages,
             dynamic_defs,
+            page_sources,
+            code_languages,
+            source_reads: SourceReads::default(),
+            mode,
+            asset_minifier,
             notfound_page,
             config,
             cache_bust_registry: CacheBustRegistry::new(),
+            svg_inliner,
+            build_cache: BuildCache::default(),
             highlight_css,
             macros_template,
-            content_template,
+            macro_trace,
+            content_templates,
+            list_item_template,
+            sitemap_template,
+            generation: NEXT_GENERATION.fetch_add(1, Ordering::Relaxed),
+            shared_deps: shared_deps.snapshot(),
+            page_deps: Arc::default(),
+            excluded_requests: Arc::default(),
+            unknown_languages: Arc::default(),
+            title_failures: Arc::new(TitleFailures::new(mode)),
         })
     }
 }
@@ -877,10 +1485,23 @@ pub struct ContentFrontmatter {
     pub description: Option<String>,
     pub author: Option<String>,
     pub image: Option<String>,
+    /// Encrypt this page's content at build time (see `crate::protect`)
+    #[serde(default)]
+    pub protected: bool,
+    /// Passphrase for protected pages, either literal or `env:VAR_NAME`
+    pub password: Option<String>,
+    /// Extra meta tags for this page, merged over `[site.meta]`
+    #[serde(default)]
+    pub meta: BTreeMap<String, String>,
+    /// `auto_list` and its settings, for directory index pages
+    #[serde(flatten)]
+    pub list: AutoListOptions,
 }
 
 #[derive(Serialize, Default, Clone)]
 pub struct SeoContext {
+    /// Page title after applying the site's `title_template`
+    pub title: String,
     pub description: Option<String>,
     pub author: Option<String>,
     pub canonical_url: String,
@@ -895,51 +1516,183 @@ pub struct SeoContext {
     pub twitter_description: Option<String>,
     pub twitter_image: Option<String>,
     pub twitter_handle: Option<String>,
+    /// Custom meta tags as (name, content); names prefixed with `property:` render as `property=`
+    pub extra_meta: Vec<(String, String)>,
 }
 
-/// Render a page title using the site's title template, if configured.
-/// Returns the original title if no template is set or if rendering fails.
-fn render_title_template(
-    page_title: &str,
-    site: &crate::config::SiteMetadata,
-) -> String {
+/// Pages `title_template` failed for, by title, with why. Pages render in parallel, so this is
+/// shared and filled in as they go. The build lists them with its warnings; `hugs dev` prints each
+/// one as it happens, since it has no summary to put them in.
+#[derive(Debug, Default)]
+pub struct TitleFailures {
+    failures: Mutex<BTreeMap<String, String>>,
+    print: bool,
+}
+
+impl TitleFailures {
+    pub fn new(mode: RenderMode) -> Self {
+        Self { failures: Mutex::default(), print: mode == RenderMode::Dev }
+    }
+
+    fn record(&self, page_title: &str, e: &minijinja::Error) {
+        let reason = title_template_error_reason(e);
+        let mut failures = self.failures.lock().unwrap_or_else(PoisonError::into_inner);
+        if failures.insert(page_title.to_string(), reason.clone()).is_none() && self.print {
+            console::warn(HugsError::TitleTemplatePage { title: page_title.to_string(), reason }.to_string());
+        }
+    }
+
+    /// A warning for each page title the template failed for
+    pub fn warnings(&self) -> Vec<HugsError> {
+        let failures = self.failures.lock().unwrap_or_else(PoisonError::into_inner);
+        failures
+            .iter()
+            .map(|(title, reason)| HugsError::TitleTemplatePage { title: title.clone(), reason: reason.clone() })
+            .collect()
+    }
+}
+
+/// Render a page title using the site's title template, if configured.
+/// Falls back to the original title if the template fails for this page, noting it in `failures`.
+fn render_title_template(
+    page_title: &str,
+    site: &crate::config::SiteMetadata,
+    failures: &TitleFailures,
+) -> String {
     match &site.title_template {
         Some(template) => {
-            let mut env = Environment::new();
-            if let Err(_) = env.add_template("title", template) {
-                return page_title.to_string();
-            }
+            let site_title = site.title.as_deref().unwrap_or("");
+            try_render_title_template(template, page_title, site_title).unwrap_or_else(|e| {
+                failures.record(page_title, &e);
+                page_title.to_string()
+            })
+        }
+        None => page_title.to_string(),
+    }
+}
 
-            let tmpl = match env.get_template("title") {
-                Ok(t) => t,
-                Err(_) => return page_title.to_string(),
-            };
+/// Render `title_template` with a page title. Undefined variables are errors,
+/// so a typo like `{{ titel }}` doesn't quietly render as an empty string.
+fn try_render_title_template(
+    template: &str,
+    page_title: &str,
+    site_title: &str,
+) -> std::result::Result<String, minijinja::Error> {
+    let mut env = Environment::new();
+    env.set_undefined_behavior(UndefinedBehavior::Strict);
+
+    let ctx = minijinja::context! {
+        title => page_title,
+        site => minijinja::context! {
+            title => site_title
+        }
+    };
+
+    env.render_str(template, ctx)
+}
+
+/// Check `title_template` once at load time by rendering it with a sample title,
+/// pointing any error at the template in config.toml
+pub fn validate_title_template(config: &SiteConfig) -> Result<()> {
+    let Some(template) = &config.site.title_template else {
+        return Ok(());
+    };
+    let site_title = config.site.title.as_deref().unwrap_or("");
+
+    let Err(e) = try_render_title_template(template, "Sample Page", site_title) else {
+        return Ok(());
+    };
 
-            let ctx = minijinja::context! {
-                title => page_title,
-                site => minijinja::context! {
-                    title => site.title.as_deref().unwrap_or("")
+    let span = match config.title_template_span() {
+        Some(value_span) => {
+            let start = toml_string_content_start(&config.source, &value_span);
+            match e.range() {
+                Some(range) => {
+                    let last = value_span.end.saturating_sub(1);
+                    let err_start = (start + range.start).min(last);
+                    let err_end = (start + range.end).min(value_span.end);
+                    miette::SourceSpan::new(err_start.into(), err_end.saturating_sub(err_start).max(1))
                 }
-            };
+                None => miette::SourceSpan::new(value_span.start.into(), value_span.len().max(1)),
+            }
+        }
+        None => miette::SourceSpan::from((0_usize, 1_usize)),
+    };
+
+    Err(HugsError::TitleTemplate {
+        src: miette::NamedSource::new("config.toml", config.source.clone()),
+        span,
+        reason: title_template_error_reason(&e),
+    })
+}
+
+/// Check the syntax highlighting theme and aliases name things I know, pointing at the one in
+/// config.toml that doesn't. A misspelled theme would otherwise leave code blocks unstyled with
+/// no hint why.
+pub fn validate_highlighting(config: &SiteConfig) -> Result<()> {
+    let highlighting = &config.build.syntax_highlighting;
+    if !highlighting.enabled {
+        return Ok(());
+    }
+    if let Some(error) = unknown_highlight_alias(config) {
+        return Err(error);
+    }
+    if crate::highlight::has_theme(&highlighting.theme) {
+        return Ok(());
+    }
 
-            tmpl.render(ctx).unwrap_or_else(|_| page_title.to_string())
+    let themes = crate::highlight::theme_names();
+    let mut help_text = match crate::error::find_best_match(&highlighting.theme, &themes) {
+        Some(suggestion) => format!("Did you mean `{}`?\n\n", suggestion),
+        None => String::new(),
+    };
+    help_text.push_str(&format!("Themes I know: {}", themes.join(", ")));
+
+    Err(HugsError::UnknownHighlightTheme {
+        theme: highlighting.theme.as_str().into(),
+        src: miette::NamedSource::new("config.toml", config.source.clone()),
+        span: config
+            .highlight_theme_span()
+            .map(|range| miette::SourceSpan::new(range.start.into(), range.len().max(1))),
+        help_text,
+    })
+}
+
+/// The first `[build.syntax_highlighting.aliases]` entry that points at a language I don't know.
+/// Aliases for a language I already have a grammar for get a warning, since it keeps that grammar.
+fn unknown_highlight_alias(config: &SiteConfig) -> Option<HugsError> {
+    for (alias, target) in &config.build.syntax_highlighting.aliases {
+        if crate::highlight::has_grammar(alias) {
+            crate::console::warn(format!(
+                "I already know `{}`, so I'm ignoring its alias to `{}` under [build.syntax_highlighting.aliases]",
+                alias, target
+            ));
+        } else if !crate::highlight::has_grammar(target) {
+            return Some(HugsError::UnknownHighlightAlias {
+                alias: alias.as_str().into(),
+                target: target.as_str().into(),
+                src: miette::NamedSource::new("config.toml", config.source.clone()),
+                span: config
+                    .highlight_alias_span(alias)
+                    .map(|range| miette::SourceSpan::new(range.start.into(), range.len().max(1))),
+            });
         }
-        None => page_title.to_string(),
     }
+    None
+}
+
+/// Short description of a title_template error, without minijinja's `(in <string>:1)` suffix
+fn title_template_error_reason(e: &minijinja::Error) -> String {
+    e.detail().map(str::to_string).unwrap_or_else(|| e.kind().to_string())
 }
 
 pub fn build_seo_context(
     frontmatter: &ContentFrontmatter,
-    page_url: &str,
+    page_url: &SiteUrl,
     site: &crate::config::SiteMetadata,
+    title_failures: &TitleFailures,
 ) -> SeoContext {
-    let base_url = site.url.as_deref().unwrap_or("").trim_end_matches('/');
-    let page_url_clean = page_url.trim_end_matches('/');
-    let canonical_url = if page_url_clean.is_empty() {
-        format!("{}/", base_url)
-    } else {
-        format!("{}{}", base_url, page_url_clean)
-    };
+    let canonical_url = site.absolute_url(&page_url.without_trailing_slash());
 
     let description = frontmatter.description.clone().or_else(|| site.description.clone());
     let author = frontmatter.author.clone().or_else(|| site.author.clone());
@@ -952,7 +1705,7 @@ pub fn build_seo_context(
             if img.starts_with("http") {
                 img.clone()
             } else {
-                format!("{}{}", base_url.trim_end_matches('/'), img)
+                site.absolute_url(&SiteUrl::parse(img))
             }
         });
 
@@ -962,9 +1715,10 @@ pub fn build_seo_context(
         "summary".to_string()
     };
 
-    let rendered_title = render_title_template(&frontmatter.title, site);
+    let rendered_title = render_title_template(&frontmatter.title, site, title_failures);
 
-    SeoContext {
+    let mut seo = SeoContext {
+        title: rendered_title.clone(),
         description: description.clone(),
         author,
         canonical_url: canonical_url.clone(),
@@ -979,17 +1733,130 @@ pub fn build_seo_context(
         twitter_description: description,
         twitter_image: image,
         twitter_handle: site.twitter_handle.clone(),
+        extra_meta: Vec::new(),
+    };
+
+    // Page meta wins over site meta; tags Hugs already emits are overridden in place
+    let mut meta = site.meta.clone();
+    meta.extend(frontmatter.meta.clone());
+    for (key, content) in meta {
+        if !seo.override_tag(&key, &content) {
+            seo.extra_meta.push((key, content));
+        }
+    }
+
+    seo
+}
+
+impl SeoContext {
+    /// Replace the value of a tag the root template already emits.
+    /// Returns false if `key` isn't one of those tags.
+    fn override_tag(&mut self, key: &str, content: &str) -> bool {
+        let content = content.to_string();
+        match key.strip_prefix("property:").unwrap_or(key) {
+            "description" => self.description = Some(content),
+            "author" => self.author = Some(content),
+            "og:title" => self.og_title = content,
+            "og:description" => self.og_description = Some(content),
+            "og:url" => self.og_url = content,
+            "og:type" => self.og_type = content,
+            "og:image" => self.og_image = Some(content),
+            "og:site_name" => self.og_site_name = Some(content),
+            "twitter:card" => self.twitter_card = content,
+            "twitter:title" => self.twitter_title = content,
+            "twitter:description" => self.twitter_description = Some(content),
+            "twitter:image" => self.twitter_image = Some(content),
+            "twitter:site" => self.twitter_handle = Some(content),
+            _ => return false,
+        }
+        true
     }
 }
 
 #[derive(Clone, Serialize)]
 pub struct PageInfo {
-    pub url: String,
+    pub url: SiteUrl,
     pub file_path: String,
     #[serde(flatten)]
     pub frontmatter: YamlValue,
 }
 
+/// A page file as the scan read it. Rendering uses this instead of the file on disk, so the page
+/// and its listing in `pages()` come from the same version even if the file is saved mid-build.
+#[derive(Debug)]
+pub struct PageSource {
+    /// The whole file, for pointing errors at
+    pub text: String,
+    /// The frontmatter as written, before the scan adds parsed dates or drops passwords
+    pub frontmatter: YamlValue,
+    /// Where the body starts in `text`
+    body_start: usize,
+}
+
+impl PageSource {
+    /// Split a page file into its frontmatter and body. On failure, the text comes back with the error.
+    pub fn parse(text: String) -> std::result::Result<Self, (String, markdown_frontmatter::Error)> {
+        match markdown_frontmatter::parse::<YamlValue>(&text) {
+            Ok((frontmatter, body)) => {
+                let body_start = text.len() - body.len();
+                Ok(Self { text, frontmatter, body_start })
+            }
+            Err(e) => Err((text, e)),
+        }
+    }
+
+    /// The page after its frontmatter
+    pub fn body(&self) -> &str {
+        &self.text[self.body_start..]
+    }
+
+    /// The frontmatter fields Hugs itself uses
+    fn content_frontmatter(&self, relative_path: &str) -> Result<ContentFrontmatter> {
+        serde_yaml::from_value(self.frontmatter.clone()).map_err(|e| self.frontmatter_error(relative_path, e))
+    }
+
+    fn frontmatter_error(&self, relative_path: &str, e: serde_yaml::Error) -> HugsError {
+        HugsError::FrontmatterParse {
+            file: relative_path.into(),
+            src: miette::NamedSource::new(relative_path, self.text.clone()),
+            span: miette::SourceSpan::from((0_usize, 1_usize)),
+            reason: format!(
+                "I couldn't parse the frontmatter. Make sure you have a valid `title` field. Error: {}",
+                e
+            ),
+        }
+    }
+}
+
+/// What pages are rendered for, which templates can check as `hugs.dev` and `hugs.mode`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RenderMode {
+    /// `hugs dev`, for you to look at while you work
+    Dev,
+    /// `hugs build`, and everything else that shows pages the way they'll be published
+    #[default]
+    Build,
+}
+
+impl RenderMode {
+    /// The mode for a `command` as `AppData::load` is given it. Only the dev server renders
+    /// pages for you alone.
+    pub fn for_command(command: &str) -> Self {
+        if command == "dev" { RenderMode::Dev } else { RenderMode::Build }
+    }
+}
+
+/// Where rendering gets page files from
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum SourceReads {
+    /// What the scan read when the site was loaded, so the whole build sees one version of each file
+    #[default]
+    Scanned,
+    /// The file as it is right now, so `hugs dev` shows an edit even before the site reloads
+    Fresh,
+}
+
 /// Dynamic page template before expansion (e.g., `[slug].md`)
 #[derive(Clone)]
 pub struct DynamicPageDef {
@@ -997,10 +1864,31 @@ pub struct DynamicPageDef {
     pub param_name: String,
     /// The source file path relative to site root (e.g., "blog/[slug].md")
     pub source_path: PathBuf,
+    /// The source file path within the content folder, which its URLs come from
+    pub url_path: PathBuf,
     /// The evaluated parameter values
     pub param_values: Vec<YamlValue>,
     /// The raw frontmatter for this dynamic page
     pub frontmatter: YamlValue,
+    /// `first_is_index`: the first value's page is the folder's index, at `/blog/` rather than
+    /// `/blog/1`, like the first page of a paginated list
+    pub first_is_index: bool,
+}
+
+impl DynamicPageDef {
+    /// The page the first value makes, when it's the folder's index
+    fn index_value(&self) -> Option<&YamlValue> {
+        self.param_values.first().filter(|_| self.first_is_index)
+    }
+
+    /// The URL of the page for `value`
+    fn value_url(&self, value: &YamlValue, transliteration: UrlTransliteration) -> SiteUrl {
+        if self.index_value() == Some(value) {
+            dynamic_index_url(&self.url_path, transliteration)
+        } else {
+            generate_dynamic_url(&self.url_path, &self.param_name, value, transliteration)
+        }
+    }
 }
 
 /// Raw dynamic page definition before parameter evaluation
@@ -1009,6 +1897,7 @@ pub struct DynamicPageDef {
 struct RawDynamicPageDef {
     param_name: String,
     source_path: PathBuf,
+    url_path: PathBuf,
     frontmatter: YamlValue,
     /// Full file content for error reporting with source spans
     file_content: String,
@@ -1023,9 +1912,10 @@ pub struct MacroDefinition {
     pub params: Vec<MacroParam>,
     /// The raw body content (markdown/HTML/Jinja template)
     pub body: String,
-    /// Source file path for error reporting (kept for future use)
-    #[allow(dead_code)]
+    /// Source file path for error reporting
     pub source_path: PathBuf,
+    /// The whole file, for pointing errors at
+    pub source: String,
 }
 
 /// A single macro parameter with its default value
@@ -1041,6 +1931,10 @@ pub struct MacroParam {
 struct RawScanResult {
     static_pages: Vec<PageInfo>,
     raw_dynamic_defs: Vec<RawDynamicPageDef>,
+    /// Every page file that parsed, by path relative to the site root
+    sources: HashMap<String, Arc<PageSource>>,
+    /// The languages of each page file's code blocks, by path relative to the site root
+    fences: HashMap<String, Vec<String>>,
 }
 
 /// Context for rendering a dynamic page - contains the parameter name and value
@@ -1131,6 +2025,111 @@ fn extract_param_name(filename: &str) -> Option<String> {
         .map(String::from)
 }
 
+/// Longest value a dynamic expression's help shows in full
+const HELP_VALUE_MAX_CHARS: usize = 500;
+
+/// `value`, cut short with how much was left out when it's too long to read in a terminal
+fn truncate_for_help(value: &str) -> Cow<'_, str> {
+    match value.char_indices().nth(HELP_VALUE_MAX_CHARS) {
+        Some((cut, _)) => Cow::Owned(format!("{}… ({} more chars)", &value[..cut], value[cut..].chars().count())),
+        None => Cow::Borrowed(value),
+    }
+}
+
+/// The closing note of help in a dynamic page's expression
+fn dynamic_routes_note() -> HelpSection {
+    HelpSection::note(&[
+        "I'm trying to determine the routes for this dynamic page.",
+        "Make sure it produces an array of values.",
+    ])
+}
+
+/// Help for `| help` (`verb` "filtering") or `is help` ("testing") in a dynamic page's expression
+fn dynamic_value_help(verb: &str, kind: &str, value: &str, list: HelpSection) -> TemplateHelp {
+    TemplateHelp::Sections(vec![
+        HelpSection::new(
+            format!("You're {} a `{}` with value", verb, kind),
+            HelpBody::Value(truncate_for_help(value).into_owned()),
+        ),
+        list,
+        dynamic_routes_note(),
+    ])
+}
+
+/// Where the value of the top-level frontmatter `key` is written in a page file: the whole scalar,
+/// quotes included, across every line of a multi-line or block (`>`, `|`) one. `None` when the key
+/// isn't there, or its value is a list or mapping rather than a scalar.
+fn frontmatter_value_span(file_content: &str, key: &str) -> Option<miette::SourceSpan> {
+    let mut lines = Vec::new();
+    let mut offset = 0;
+    for line in file_content.split_inclusive('\n') {
+        lines.push((offset, line.trim_end_matches(['\n', '\r'])));
+        offset += line.len();
+    }
+
+    // Only look between the opening `---` and the closing one
+    if lines.first()?.1.trim_end() != "---" {
+        return None;
+    }
+    let close = lines.iter().skip(1).position(|(_, line)| matches!(line.trim_end(), "---" | "...")).map(|i| i + 1);
+    let frontmatter_end = close.map_or(file_content.len(), |i| lines[i].0);
+    let lines = &lines[1..close.unwrap_or(lines.len())];
+
+    let prefix = format!("{}:", key);
+    let index = lines.iter().position(|(_, line)| line.starts_with(&prefix))?;
+    let (line_start, line) = lines[index];
+    let after_colon = &line[prefix.len()..];
+    let value = after_colon.trim_start();
+    let value_start = line_start + prefix.len() + (after_colon.len() - value.len());
+
+    // More-indented lines carry a block scalar, or a plain scalar folded over several lines
+    let continuation: Vec<(usize, &str)> = lines[index + 1..]
+        .iter()
+        .take_while(|(_, line)| line.trim().is_empty() || line.starts_with([' ', '\t']))
+        .filter(|(_, line)| !line.trim().is_empty())
+        .copied()
+        .collect();
+    let line_end = |(start, line): (usize, &str)| start + line.trim_end().len();
+
+    let (start, end) = match value.chars().next()? {
+        quote @ ('"' | '\'') => {
+            let rest = &file_content[value_start + 1..frontmatter_end];
+            let mut chars = rest.char_indices();
+            let mut closing = None;
+            while let Some((i, c)) = chars.next() {
+                match c {
+                    '\\' if quote == '"' => {
+                        chars.next();
+                    }
+                    // In single quotes, `''` is an escaped quote
+                    '\'' if quote == '\'' && rest[i + 1..].starts_with('\'') => {
+                        chars.next();
+                    }
+                    c if c == quote => {
+                        closing = Some(i);
+                        break;
+                    }
+                    _ => {}
+                }
+            }
+            (value_start, value_start + 1 + closing? + 1)
+        }
+        '>' | '|' => {
+            let first = *continuation.first()?;
+            let indent = first.1.len() - first.1.trim_start().len();
+            (first.0 + indent, line_end(*continuation.last()?))
+        }
+        '[' | '{' | '#' => return None,
+        _ => {
+            let text = value.split(" #").next().unwrap_or(value).trim_end();
+            let end = continuation.last().map_or(value_start + text.len(), |last| line_end(*last));
+            (value_start, end)
+        }
+    };
+
+    Some(miette::SourceSpan::new(start.into(), end - start))
+}
+
 /// Evaluate parameter values from frontmatter with access to pages() and other helpers.
 /// This is the enhanced version that provides helper functions in the evaluation context.
 fn evaluate_param_values_with_pages(
@@ -1139,142 +2138,63 @@ fn evaluate_param_values_with_pages(
     source_path: &Path,
     pages: &Arc<Vec<PageInfo>>,
     file_content: &str,
+    param_chars: ParamCharset,
 ) -> Result<Vec<YamlValue>> {
     use miette::{NamedSource, SourceSpan};
 
     // Helper to find the span of the param expression in the file content
     let find_param_span = |expr: &str| -> SourceSpan {
-        // Look for the pattern "param_name: " or "param_name:" followed by the expression
-        let search_patterns = [
-            format!("{}: \"{}\"", param_name, expr),
-            format!("{}:\"{}\"", param_name, expr),
-            format!("{}: '{}'", param_name, expr),
-            format!("{}:'{}'", param_name, expr),
-        ];
-
-        for pattern in &search_patterns {
-            if let Some(pos) = file_content.find(pattern) {
-                // Point to the expression part (after "param_name: ")
-                let expr_start = pos + param_name.len() + 2; // +2 for ": " or ":"
-                return SourceSpan::new(expr_start.into(), (pattern.len() - param_name.len() - 2).into());
-            }
-        }
-
-        // Fallback: try to find just the expression string
-        if let Some(pos) = file_content.find(expr) {
-            return SourceSpan::new(pos.into(), expr.len().into());
-        }
-
-        // Last resort: point to start of file
-        SourceSpan::new(0_usize.into(), 1_usize.into())
-    };
-
-    // Helper to parse help filter/test marker and extract kind/value
-    let parse_help_marker = |detail: &str, marker: &str| -> Option<(String, String)> {
-        use base64::{Engine, engine::general_purpose::STANDARD};
-
-        let rest = detail.strip_prefix(marker)?;
-        let mut kind = String::new();
-        let mut value_b64 = String::new();
-
-        for part in rest.split(':') {
-            if let Some(k) = part.strip_prefix("kind=") {
-                kind = k.to_string();
-            } else if let Some(v) = part.strip_prefix("value=") {
-                value_b64 = v.to_string();
-            }
-        }
-
-        let value = STANDARD
-            .decode(&value_b64)
-            .ok()
-            .and_then(|b| String::from_utf8(b).ok())
-            .unwrap_or_else(|| "?".to_string());
-
-        Some((kind, value))
+        frontmatter_value_span(file_content, param_name)
+            // Fallback: try to find just the expression string
+            .or_else(|| file_content.find(expr).map(|pos| SourceSpan::new(pos.into(), expr.len())))
+            // Last resort: point to start of file
+            .unwrap_or_else(|| SourceSpan::new(0_usize.into(), 1_usize))
     };
 
     // Helper to create the error with all fields
     let make_error = |expr: &str, reason: String, resolved_value: Option<String>| -> HugsError {
         let span = find_param_span(expr);
+        let builtin = |names: &[&str]| names.iter().map(|name| name.to_string()).collect::<Vec<_>>();
 
         // Check if this is a help request - if so, provide specialized help
         // Use the same span labels as template errors
-        let (display_reason, help_text, resolved) = if reason.starts_with(HELP_MARKER_FILTER) {
-            // Filter help: "you asked for filter help here"
-            if let Some((kind, value)) = parse_help_marker(&reason, HELP_MARKER_FILTER) {
-                use owo_colors::OwoColorize;
-                let friendly_reason = "you asked for filter help here".to_string();
-                let filters_list = wrap_items_to_lines(BUILTIN_FILTERS, 60);
-                let help = format!(
-                    "You're filtering a {} with value:\n    {}\n\n\
-                     Filters you can apply:\n{}\n\
-                     I'm trying to determine the routes for this dynamic page.\n\
-                     Make sure it produces an array of values.",
-                    kind.yellow().bold(),
-                    value.bright_yellow(),
-                    filters_list
-                );
-                (friendly_reason, help, Some(value))
-            } else {
-                // Fallback if parsing fails
-                let help = format!(
-                    "The expression `{}` failed to evaluate.{}\n\nI'm trying to determine the routes for this dynamic page.\nMake sure it produces an array of values.\n\nCommon functions:\n- range(end=5) -> [0, 1, 2, 3, 4]\n- range(start=1, end=6) -> [1, 2, 3, 4, 5]\n- pages(within='/blog') | map(attribute='slug') | list",
-                    expr,
-                    resolved_value.as_ref().map(|v| format!("\n\nThe expression resolved to:\n{}", v)).unwrap_or_default()
-                );
-                (reason, help, resolved_value)
-            }
-        } else if reason.starts_with(HELP_MARKER_TEST) {
-            // Test help: "you asked for test help here"
-            if let Some((kind, value)) = parse_help_marker(&reason, HELP_MARKER_TEST) {
-                use owo_colors::OwoColorize;
-                let friendly_reason = "you asked for test help here".to_string();
-                let tests_list = wrap_items_to_lines(BUILTIN_TESTS, 60);
-                let help = format!(
-                    "You're testing a {} with value:\n    {}\n\n\
-                     Tests you can use:\n{}\n\
-                     I'm trying to determine the routes for this dynamic page.\n\
-                     Make sure it produces an array of values.",
-                    kind.yellow().bold(),
-                    value.bright_yellow(),
-                    tests_list
-                );
-                (friendly_reason, help, Some(value))
-            } else {
+        let request = HelpRequest::parse(&reason);
+        let friendly_reason = request.as_ref().map(|request| request.reason().to_string());
+        let (display_reason, help_text, resolved) = match request {
+            Some(HelpRequest::Filter { kind, value }) => {
+                let filters = HelpSection::new("Filters you can apply", HelpBody::Items(builtin(BUILTIN_FILTERS)));
+                let help = dynamic_value_help("filtering", &kind, &value, filters);
+                (friendly_reason.unwrap_or(reason), help, Some(value))
+            }
+            Some(HelpRequest::Test { kind, value }) => {
+                let tests = HelpSection::new("Tests you can use", HelpBody::Items(builtin(BUILTIN_TESTS)));
+                let help = dynamic_value_help("testing", &kind, &value, tests);
+                (friendly_reason.unwrap_or(reason), help, Some(value))
+            }
+            Some(HelpRequest::Everything { .. }) => {
+                let help = TemplateHelp::Sections(vec![
+                    HelpSection::new(
+                        "Variables you can use",
+                        HelpBody::Lines(vec![
+                            "In dynamic page expressions, no variables are pre-defined.".to_string(),
+                            "Use `pages()` to get page data.".to_string(),
+                        ]),
+                    ),
+                    HelpSection::new("Functions you can call", HelpBody::Items(builtin(&["pages()", "help()"]))),
+                    HelpSection::new("Filters you can apply", HelpBody::Items(builtin(BUILTIN_FILTERS))),
+                    HelpSection::new("Tests you can use", HelpBody::Items(builtin(BUILTIN_TESTS))),
+                    dynamic_routes_note(),
+                ]);
+                (friendly_reason.unwrap_or(reason), help, None)
+            }
+            None => {
                 let help = format!(
                     "The expression `{}` failed to evaluate.{}\n\nI'm trying to determine the routes for this dynamic page.\nMake sure it produces an array of values.\n\nCommon functions:\n- range(end=5) -> [0, 1, 2, 3, 4]\n- range(start=1, end=6) -> [1, 2, 3, 4, 5]\n- pages(within='/blog') | map(attribute='slug') | list",
                     expr,
-                    resolved_value.as_ref().map(|v| format!("\n\nThe expression resolved to:\n{}", v)).unwrap_or_default()
+                    resolved_value.as_ref().map(|v| format!("\n\nThe expression resolved to:\n{}", truncate_for_help(v))).unwrap_or_default()
                 );
-                (reason, help, resolved_value)
-            }
-        } else if reason.starts_with(HELP_MARKER_FUNCTION) {
-            // Function help: "you asked for help here"
-            let friendly_reason = "you asked for help here".to_string();
-            let filters_list = wrap_items_to_lines(BUILTIN_FILTERS, 60);
-            let tests_list = wrap_items_to_lines(BUILTIN_TESTS, 60);
-            let help = format!(
-                "Variables you can use:\n\
-                 In dynamic page expressions, no variables are pre-defined.\n\
-                 Use pages() to get page data.\n\n\
-                 Functions you can call:\n\
-                 pages(), help()\n\n\
-                 Filters you can apply:\n{}\n\
-                 Tests you can use:\n{}\n\
-                 I'm trying to determine the routes for this dynamic page.\n\
-                 Make sure it produces an array of values.",
-                filters_list,
-                tests_list
-            );
-            (friendly_reason, help, None)
-        } else {
-            let help = format!(
-                "The expression `{}` failed to evaluate.{}\n\nI'm trying to determine the routes for this dynamic page.\nMake sure it produces an array of values.\n\nCommon functions:\n- range(end=5) -> [0, 1, 2, 3, 4]\n- range(start=1, end=6) -> [1, 2, 3, 4, 5]\n- pages(within='/blog') | map(attribute='slug') | list",
-                expr,
-                resolved_value.as_ref().map(|v| format!("\n\nThe expression resolved to:\n{}", v)).unwrap_or_default()
-            );
-            (reason, help, resolved_value)
+                (reason, help.into(), resolved_value)
+            }
         };
 
         HugsError::DynamicExprEval {
@@ -1304,9 +2224,9 @@ fn evaluate_param_values_with_pages(
             param_name: param_name.into(),
         })?;
 
-    match param_value {
+    let (values, expression) = match param_value {
         // Direct array: page_no: [1, 2, 3]
-        YamlValue::Sequence(seq) => Ok(seq.clone()),
+        YamlValue::Sequence(seq) => (seq.clone(), None),
 
         // Jinja expression: page_no: "{{ range(end=5) }}" or page_no: "range(end=5)"
         YamlValue::String(expr) => {
@@ -1325,6 +2245,10 @@ fn evaluate_param_values_with_pages(
             // Add the flatten filter for flattening nested sequences
             env.add_filter("flatten", create_flatten_filter());
 
+            // Add the regex test and filter
+            env.add_test("matches", matches_test);
+            env.add_filter("regex_replace", regex_replace_filter);
+
             // Add the help test for debugging
             env.add_test("help", create_help_test());
 
@@ -1383,15 +2307,81 @@ fn evaluate_param_values_with_pages(
                 })
                 .collect();
 
-            Ok(values)
+            (values, Some(expr.as_str()))
+        }
+
+        _ => {
+            return Err(HugsError::DynamicParamParse {
+                file: source_path.display().to_string().into(),
+                param_name: param_name.into(),
+                reason: "Parameter value must be an array or a Jinja expression string".into(),
+            });
         }
+    };
+
+    // Values become URL segments and output paths, so anything that could break out of them is rejected
+    for value in &values {
+        let Some(reason) = invalid_param_value_reason(value, param_chars) else {
+            continue;
+        };
+
+        let value_str = yaml_value_to_string(value);
+        let (span, expression) = match expression {
+            Some(expr) => (find_param_span(expr), expr.to_string()),
+            None => {
+                // Point at the value inside the literal list
+                let list_start = file_content.find(&format!("{}:", param_name)).unwrap_or(0);
+                let span = file_content[list_start..]
+                    .find(value_str.as_str())
+                    .filter(|_| !value_str.is_empty())
+                    .map(|pos| SourceSpan::new((list_start + pos).into(), value_str.len()))
+                    .unwrap_or_else(|| SourceSpan::new(list_start.into(), param_name.len()));
+                (span, serde_yaml::to_string(param_value).unwrap_or_default().trim().to_string())
+            }
+        };
 
-        _ => Err(HugsError::DynamicParamParse {
+        return Err(HugsError::DynamicParamInvalid {
             file: source_path.display().to_string().into(),
             param_name: param_name.into(),
-            reason: "Parameter value must be an array or a Jinja expression string".into(),
-        }),
+            value: value_str,
+            help_text: format!(
+                "Each value of `{}` becomes part of a page URL and an output path, so it can't contain `/`, `\\`, `?`, `#` or `%`, be empty, or be `.` or `..`.\n\nThe values came from:\n    {}\n\nFilter the odd ones out, or turn them into slugs first (e.g. `| lower | replace(\"/\", \"-\")`).",
+                param_name, expression
+            ),
+            expression,
+            reason,
+            src: Some(NamedSource::new(source_path.display().to_string(), file_content.to_string())),
+            span,
+        });
+    }
+
+    Ok(values)
+}
+
+/// Why a dynamic param value can't be used as a URL segment, or `None` if it can
+fn invalid_param_value_reason(value: &YamlValue, param_chars: ParamCharset) -> Option<String> {
+    let value = match value {
+        YamlValue::String(s) => s.clone(),
+        YamlValue::Number(_) | YamlValue::Bool(_) => return None,
+        _ => return Some("this value is a list or a map, not a string or number".to_string()),
+    };
+
+    if value.is_empty() {
+        return Some("this value is empty".to_string());
     }
+    if value == "." || value == ".." {
+        return Some(format!("`{}` would point at a different folder", value));
+    }
+    if let Some(c) = value.chars().find(|c| matches!(c, '/' | '\\' | '?' | '#' | '%') || c.is_control()) {
+        return Some(format!("this value contains {:?}", c));
+    }
+    if param_chars == ParamCharset::Ascii
+        && let Some(c) = value.chars().find(|c| !(c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | '~')))
+    {
+        return Some(format!("{:?} isn't allowed with dynamic_param_chars = \"ascii\"", c));
+    }
+
+    None
 }
 
 /// Format error message for dynamic expression evaluation, including available functions
@@ -1422,6 +2412,7 @@ fn render_frontmatter_values(
     dynamic_ctx: &DynamicContext,
     pages: &Arc<Vec<PageInfo>>,
     language: &str,
+    dates: &DateParser,
     source_file: &str,
     source_content: &str,
 ) -> Result<YamlValue> {
@@ -1436,7 +2427,7 @@ fn render_frontmatter_values(
     env.add_function("pages", create_pages_function(Arc::clone(pages)));
 
     // Add the datefmt filter
-    env.add_filter("datefmt", create_datefmt_filter(language.to_string()));
+    env.add_filter("datefmt", create_datefmt_filter(language.to_string(), dates.clone()));
 
     // Add the help filter (same as in page templates)
     env.add_filter("help", create_help_filter());
@@ -1444,6 +2435,10 @@ fn render_frontmatter_values(
     // Add the flatten filter for flattening nested sequences
     env.add_filter("flatten", create_flatten_filter());
 
+    // Add the regex test and filter
+    env.add_test("matches", matches_test);
+    env.add_filter("regex_replace", regex_replace_filter);
+
     let mut rendered_mapping = serde_yaml::Mapping::new();
 
     for (key, value) in mapping {
@@ -1548,32 +2543,68 @@ fn yaml_value_to_string(value: &YamlValue) -> String {
 }
 
 /// Generate URL for a dynamic page instance
-fn generate_dynamic_url(source_path: &Path, param_name: &str, value: &YamlValue) -> String {
-    let path_str = source_path.with_extension("").to_string_lossy().to_string();
+fn generate_dynamic_url(
+    source_path: &Path,
+    param_name: &str,
+    value: &YamlValue,
+    transliteration: UrlTransliteration,
+) -> SiteUrl {
+    let path_str = relative_path_to_url_string(&source_path.with_extension(""));
     let placeholder = format!("[{}]", param_name);
     let value_str = yaml_value_to_string(value);
 
-    let replaced = path_str.replace(&placeholder, &value_str);
+    SiteUrl::from_segments(&path_str.replace(&placeholder, &value_str), transliteration)
+}
 
-    if replaced == "index" {
-        String::from("/")
-    } else if replaced.ends_with("/index") {
-        format!("/{}/", replaced.strip_suffix("/index").unwrap())
-    } else {
-        format!("/{}", replaced)
+/// The URL of the folder a dynamic page is in, where `first_is_index` puts its first value's page
+fn dynamic_index_url(source_path: &Path, transliteration: UrlTransliteration) -> SiteUrl {
+    let index = source_path.with_file_name("index");
+    SiteUrl::from_segments(&relative_path_to_url_string(&index), transliteration)
+}
+
+/// Fail when a `first_is_index` dynamic page's first value would take the place of a page that's
+/// already its folder's index: an `index.md`, or another such dynamic page
+fn check_dynamic_index_conflicts(
+    dynamic_defs: &[DynamicPageDef],
+    static_pages: &[PageInfo],
+    transliteration: UrlTransliteration,
+) -> Result<()> {
+    let mut claimed: HashMap<SiteUrl, &Path> = HashMap::new();
+    for def in dynamic_defs.iter().filter(|def| def.index_value().is_some()) {
+        let url = dynamic_index_url(&def.url_path, transliteration);
+        let other = static_pages
+            .iter()
+            .find(|page| page.url.same_page(url.as_str()))
+            .map(|page| page.file_path.clone())
+            .or_else(|| claimed.get(&url).map(|other| relative_path_to_url_string(other)));
+        if let Some(other) = other {
+            return Err(HugsError::DynamicIndexConflict {
+                file: def.source_path.clone().into(),
+                other: other.into(),
+                url: url.to_string().into(),
+            });
+        }
+        claimed.insert(url, &def.source_path);
     }
+    Ok(())
 }
 
 /// Expand dynamic page definitions into concrete PageInfo entries
-fn expand_dynamic_pages(dynamic_defs: &[DynamicPageDef]) -> Vec<PageInfo> {
+fn expand_dynamic_pages(
+    dynamic_defs: &[DynamicPageDef],
+    transliteration: UrlTransliteration,
+    url_style: UrlStyle,
+    private_keys: &[String],
+) -> Vec<PageInfo> {
     let mut expanded = Vec::new();
 
     for def in dynamic_defs {
         for value in &def.param_values {
-            let url = generate_dynamic_url(&def.source_path, &def.param_name, value);
+            let url = def.value_url(value, transliteration).in_style(url_style);
 
             // Create a copy of frontmatter with the parameter value set
             let mut frontmatter = def.frontmatter.clone();
+            remove_private_keys(&mut frontmatter, private_keys);
             if let YamlValue::Mapping(ref mut map) = frontmatter {
                 map.insert(
                     YamlValue::String(def.param_name.clone()),
@@ -1583,7 +2614,7 @@ fn expand_dynamic_pages(dynamic_defs: &[DynamicPageDef]) -> Vec<PageInfo> {
 
             expanded.push(PageInfo {
                 url,
-                file_path: def.source_path.to_string_lossy().to_string(),
+                file_path: relative_path_to_url_string(&def.source_path),
                 frontmatter,
             });
         }
@@ -1592,6 +2623,37 @@ fn expand_dynamic_pages(dynamic_defs: &[DynamicPageDef]) -> Vec<PageInfo> {
     expanded
 }
 
+/// Drop the frontmatter keys other pages shouldn't see: any starting with `_`, and the site's
+/// `private_frontmatter_keys`
+fn remove_private_keys(frontmatter: &mut YamlValue, private_keys: &[String]) {
+    if let YamlValue::Mapping(map) = frontmatter {
+        map.retain(|key, _| {
+            key.as_str().is_none_or(|key| !key.starts_with('_') && !private_keys.iter().any(|private| private == key))
+        });
+    }
+}
+
+/// Fail when two pages came out at the same URL, which ASCII transliteration can do to names that
+/// differed only in their accents
+fn check_url_collisions(pages: &[PageInfo]) -> Result<()> {
+    let describe = |page: &PageInfo| match DynamicContext::from_page_info(page) {
+        Some(ctx) => format!("{} ({} = {})", page.file_path, ctx.param_name, yaml_value_to_string(&ctx.param_value)),
+        None => page.file_path.clone(),
+    };
+
+    let mut seen: HashMap<&str, &PageInfo> = HashMap::new();
+    for page in pages {
+        if let Some(other) = seen.insert(page.url.as_str(), page) {
+            return Err(HugsError::UrlCollision {
+                url: page.url.to_string().into(),
+                page: describe(other),
+                other: describe(page),
+            });
+        }
+    }
+    Ok(())
+}
+
 /// Check if a string is a valid identifier for macro names
 fn is_valid_identifier(s: &str) -> bool {
     let mut chars = s.chars();
@@ -1679,6 +2741,7 @@ fn parse_macro_file(path: &Path, content: &str) -> Result<MacroDefinition> {
         params,
         body: body.to_string(),
         source_path: path.to_path_buf(),
+        source: content.to_string(),
     })
 }
 
@@ -1711,8 +2774,9 @@ async fn load_macros(site_path: &PathBuf) -> Result<Vec<MacroDefinition>> {
     Ok(macros)
 }
 
-/// Build a combined template string containing all macro definitions
-fn build_macros_template(macros: &[MacroDefinition]) -> String {
+/// Build a combined template string containing all macro definitions.
+/// With `traced`, each macro's output is wrapped in markers (see `crate::macro_trace`).
+fn build_macros_template(macros: &[MacroDefinition], traced: bool) -> String {
     let mut template = String::new();
 
     for macro_def in macros {
@@ -1724,114 +2788,467 @@ fn build_macros_template(macros: &[MacroDefinition]) -> String {
             .collect::<Vec<_>>()
             .join(", ");
 
+        let body = if traced {
+            traced_body(&macro_def.name, macro_def.body.trim())
+        } else {
+            format!("\n{}\n", macro_def.body.trim())
+        };
         template.push_str(&format!(
-            "{{% macro {}({}) %}}\n{}\n{{% endmacro %}}\n\n",
+            "{{% macro {}({}) %}}{}{{% endmacro %}}\n\n",
             macro_def.name,
             params_str,
-            macro_def.body.trim()
+            body
         ));
     }
 
     template
 }
 
-pub fn convert_file_path_to_url(path: &Path) -> String {
-    let path_str = path.with_extension("").to_string_lossy().to_string();
+/// Names a macro body can use without declaring them, besides the site's globals and macros and
+/// the keys in pages' frontmatter: its own implicit ones, and what a page's context has
+const MACRO_IMPLICIT_NAMES: &[&str] = &[
+    "caller", "content", "varargs", "kwargs", "site", "page", "path_class", "base", "is_notfound", "seo",
+];
 
-    if path_str == "index" {
-        // Root index.md -> /
-        String::from("/")
-    } else if path_str.ends_with("/index") {
-        // Directory index.md -> /path/to/dir/ (with trailing slash for correct relative URL resolution)
-        let dir_path = path_str.strip_suffix("/index").unwrap_or(&path_str);
-        format!("/{}/", dir_path)
-    } else {
-        // Regular file -> /path/to/file
-        format!("/{}", path_str)
-    }
-}
+/// Compile each macro on its own, so a syntax error points into the macro's file rather than
+/// into whichever page first calls it. Returns warnings about names a macro uses that nothing
+/// declares, with the closest parameter or known name when one looks like a typo of it, and
+/// about parameters it never uses. `page_keys` are the frontmatter keys of every page, which a
+/// macro can read from the page calling it.
+fn check_macros(
+    macros: &[MacroDefinition],
+    config: &SiteConfig,
+    site_path: &Path,
+    page_keys: &BTreeSet<String>,
+) -> Result<Vec<String>> {
+    use miette::{NamedSource, SourceSpan};
 
-/// Intermediate result for parsing a single page file
-enum ParsedPage {
-    Static(PageInfo),
-    RawDynamic(RawDynamicPageDef),
-}
+    let mut warnings = Vec::new();
+    if macros.is_empty() {
+        return Ok(warnings);
+    }
 
-/// Phase 1: Scan all pages, collecting static pages and raw dynamic definitions
-/// Dynamic parameter expressions are NOT evaluated here (they need pages to be available)
-async fn scan_pages_raw(site_path: &PathBuf) -> Result<RawScanResult> {
-    // 1. Collect paths synchronously (fast - just directory walking)
-    let paths: Vec<(PathBuf, PathBuf)> = WalkDir::new(site_path)
-        .into_iter()
-        .filter_map(|e| e.ok())
-        .filter(|e| e.file_type().is_file())
-        .filter(|e| e.path().extension().is_some_and(|ext| ext == "md"))
-        .filter_map(|entry| {
-            let path = entry.path();
-            let relative_path = path.strip_prefix(site_path).ok()?;
+    let mut known = builtin_function_names();
+    known.extend(macros.iter().map(|m| m.name.clone()));
+    known.extend(config.template.functions.keys().map(|name| name.get_ref().clone()));
+    known.extend(MACRO_IMPLICIT_NAMES.iter().map(|name| name.to_string()));
+    known.extend(page_keys.iter().cloned());
 
-            // Skip _ directory and [404].md
-            if relative_path.starts_with("_") {
-                return None;
-            }
-            if relative_path.to_string_lossy() == "[404].md" {
-                return None;
+    for macro_def in macros {
+        let file = macro_def.source_path.strip_prefix(site_path).unwrap_or(&macro_def.source_path);
+        let file_name = relative_path_to_url_string(file);
+        let params: Vec<String> = macro_def.params.iter().map(|p| p.name.clone()).collect();
+
+        let env = Environment::new();
+        let body = match env.template_from_str(&macro_def.body) {
+            Ok(body) => body,
+            Err(e) => {
+                // The body is the end of the file, after the frontmatter
+                let body_start = macro_def.source.len().saturating_sub(macro_def.body.len());
+                let span = match e.range() {
+                    Some(range) => {
+                        let end = (body_start + range.end).min(macro_def.source.len());
+                        let start = (body_start + range.start).min(end).min(macro_def.source.len().saturating_sub(1));
+                        SourceSpan::new(start.into(), (end - start).max(1))
+                    }
+                    None => SourceSpan::new(body_start.into(), macro_def.body.len().max(1)),
+                };
+                return Err(HugsError::MacroSyntax {
+                    name: macro_def.name.as_str().into(),
+                    file: file.into(),
+                    src: NamedSource::new(file_name, macro_def.source.clone()),
+                    span,
+                    reason: e.detail().map(|d| d.to_string()).unwrap_or_else(|| e.to_string()),
+                });
             }
+        };
 
-            Some((path.to_owned(), relative_path.to_owned()))
-        })
-        .collect();
+        let used = body.undeclared_variables(false);
+        let mut unknown: Vec<&String> = used.iter().filter(|name| !params.contains(name) && !known.contains(name)).collect();
+        unknown.sort();
+        for name in unknown {
+            let warning = if let Some(param) = crate::error::find_best_match(name, &params) {
+                format!("the {} macro uses `{}`, which it doesn't declare; did you mean its `{}` parameter?", macro_def.name, name, param)
+            } else if let Some(similar) = crate::error::find_best_match(name, &known) {
+                format!("the {} macro uses `{}`, which it doesn't declare; did you mean `{}`?", macro_def.name, name, similar)
+            } else {
+                format!(
+                    "the {} macro uses `{}`, which isn't one of its parameters, a template function or any page's frontmatter",
+                    macro_def.name, name
+                )
+            };
+            warnings.push(warning);
+        }
 
-    // 2. Read and parse files in parallel
-    let mut join_set: JoinSet<Option<Result<ParsedPage>>> = JoinSet::new();
+        for param in params.iter().filter(|param| !used.contains(*param)) {
+            warnings.push(format!("the {} macro declares `{}` in {} but never uses it", macro_def.name, param, file_name));
+        }
+    }
 
-    for (path, relative_path) in paths {
-        join_set.spawn(async move {
-            let content = match tokio::fs::read_to_string(&path).await {
-                Ok(c) => c,
-                Err(e) => {
-                    console::warn(format!(
-                        "couldn't read {}: {}, skipping",
-                        relative_path.display(),
-                        e
-                    ));
-                    return None;
-                }
-            };
+    Ok(warnings)
+}
 
-            let frontmatter = match markdown_frontmatter::parse::<YamlValue>(&content) {
-                Ok((fm, _body)) => fm,
+/// Names of the functions every page template can call (MiniJinja builtins + Hugs functions)
+fn builtin_function_names() -> Vec<String> {
+    let cache_bust = CacheBustFunction::new(
+        AssetRoots::whole_site(Path::new("")),
+        String::new(),
+        String::new(),
+        CacheBustRegistry::new(),
+    );
+    let svg = SvgInliner::new(AssetRoots::whole_site(Path::new("")));
+    let render = RenderContext {
+        pages: &Arc::new(Vec::new()),
+        cache_bust: Some(&cache_bust),
+        svg: Some(&svg),
+        cache: &BuildCache::default(),
+        code_languages: &CodeLanguages::default(),
+        macros_template: "",
+        config: &SiteConfig::default(),
+        mode: RenderMode::Build,
+        deps: &DepRecorder::default(),
+    };
+    let (env, _) = create_template_env(&render, &TemplateConfig::default());
+    env.globals().map(|(name, _)| name.to_string()).collect()
+}
+
+/// Byte offset where a TOML string's contents begin, skipping the opening delimiter
+fn toml_string_content_start(source: &str, span: &std::ops::Range<usize>) -> usize {
+    let raw = source.get(span.clone()).unwrap_or("");
+    let delimiter_len = if raw.starts_with("\"\"\"") || raw.starts_with("'''") {
+        // TOML trims a newline immediately following the opening delimiter
+        let rest = &raw[3..];
+        if rest.starts_with("\r\n") {
+            5
+        } else if rest.starts_with('\n') {
+            4
+        } else {
+            3
+        }
+    } else {
+        1
+    };
+    (span.start + delimiter_len).min(span.end)
+}
+
+/// Compile the `[template.functions]` table from config.toml into macro definitions.
+///
+/// Each entry becomes a one-line macro appended to the macros template, e.g.
+/// `excerpt_url = { params = ["p"], body = "{{ p.url }}#excerpt" }` turns into
+/// `{% macro excerpt_url(p) %}{{ p.url }}#excerpt{% endmacro %}`.
+fn build_template_functions(
+    config: &SiteConfig,
+    macros: &[MacroDefinition],
+    site_path: &Path,
+) -> Result<String> {
+    use miette::{NamedSource, SourceSpan};
+
+    let functions = &config.template.functions;
+    if functions.is_empty() {
+        return Ok(String::new());
+    }
+
+    let named_source = || NamedSource::new("config.toml", config.source.clone());
+    let to_span = |range: std::ops::Range<usize>| {
+        SourceSpan::new(range.start.into(), range.len().max(1))
+    };
+    let builtin_names = builtin_function_names();
+
+    let mut template = String::new();
+
+    for (name, function) in functions {
+        if !is_valid_identifier(name.get_ref()) {
+            return Err(HugsError::TemplateFunctionInvalidName {
+                name: name.get_ref().as_str().into(),
+                what: "function",
+                src: named_source(),
+                span: to_span(name.span()),
+            });
+        }
+
+        if let Some(param) = function.params.iter().find(|p| !is_valid_identifier(p.get_ref())) {
+            return Err(HugsError::TemplateFunctionInvalidName {
+                name: param.get_ref().as_str().into(),
+                what: "parameter",
+                src: named_source(),
+                span: to_span(param.span()),
+            });
+        }
+
+        // Functions share a namespace with macros and builtins - don't let one silently hide another
+        let existing = if let Some(macro_def) = macros.iter().find(|m| &m.name == name.get_ref()) {
+            let macro_path = macro_def
+                .source_path
+                .strip_prefix(site_path)
+                .unwrap_or(&macro_def.source_path);
+            Some(format!("the macro defined in {}", macro_path.display()))
+        } else if builtin_names.iter().any(|b| b == name.get_ref()) {
+            Some(String::from("a built-in function"))
+        } else {
+            None
+        };
+
+        if let Some(existing) = existing {
+            return Err(HugsError::TemplateFunctionCollision {
+                name: name.get_ref().as_str().into(),
+                existing,
+                src: named_source(),
+                span: to_span(name.span()),
+            });
+        }
+
+        let params_str = function
+            .params
+            .iter()
+            .map(|p| p.get_ref().as_str())
+            .collect::<Vec<_>>()
+            .join(", ");
+        let macro_open = format!("{{% macro {}({}) %}}", name.get_ref(), params_str);
+        let definition = format!("{}{}{{% endmacro %}}\n", macro_open, function.body.get_ref());
+
+        // Compile each function on its own so syntax errors can point into config.toml
+        let mut env = Environment::new();
+        if let Err(e) = env.add_template("function", &definition) {
+            let body_span = function.body.span();
+            let body_start = toml_string_content_start(&config.source, &body_span);
+            let span = match e.range() {
+                Some(range) if range.start >= macro_open.len() => {
+                    // Errors past the body (e.g. an unclosed tag) point at the closing quote
+                    let last = body_span.end.saturating_sub(1);
+                    let start = (body_start + range.start - macro_open.len()).min(last);
+                    let end = (body_start + range.end.saturating_sub(macro_open.len())).min(body_span.end);
+                    to_span(start..end)
+                }
+                _ => to_span(body_span),
+            };
+
+            return Err(HugsError::TemplateFunctionSyntax {
+                name: name.get_ref().as_str().into(),
+                src: named_source(),
+                span,
+                reason: e.detail().map(|d| d.to_string()).unwrap_or_else(|| e.to_string()),
+            });
+        }
+
+        template.push_str(&definition);
+    }
+
+    Ok(template)
+}
+
+/// The page served for a request path (without leading slash), matching either the page's URL
+/// or the file it's written to. Pages take precedence over static and generated files at the
+/// same path, in both build and dev.
+pub fn page_for_request<'a>(pages: &'a [PageInfo], path: &str) -> Option<&'a PageInfo> {
+    let path = path.trim_matches('/');
+    pages
+        .iter()
+        .find(|page| page.url.same_page(path) || page.url.output_path() == Path::new(path))
+}
+
+/// The page whose URL in the other `url_style` is `path`, so `/about` and `/about.html` both
+/// reach `about.md` in `hugs dev` whichever the site uses. The build only writes one of them.
+fn page_in_any_style<'a>(pages: &'a [PageInfo], path: &str) -> Option<&'a PageInfo> {
+    pages.iter().find(|page| page.url.same_page_in_any_style(path))
+}
+
+/// The minifier for `[build.minify]`, knowing the ids the site's stylesheets refer to
+fn site_asset_minifier(config: &SiteConfig, asset_roots: &AssetRoots, theme_css: &str) -> Option<AssetMinifier> {
+    if !config.build.minify.svg() && !config.build.minify.js() {
+        return None;
+    }
+    let stylesheets: Vec<String> = asset_roots
+        .files()
+        .into_iter()
+        .filter(|(_, relative)| relative.extension().is_some_and(|ext| ext == "css"))
+        .filter_map(|(path, _)| std::fs::read_to_string(path).ok())
+        .collect();
+    AssetMinifier::new(config.build.minify, std::iter::once(theme_css).chain(stylesheets.iter().map(String::as_str)))
+}
+
+/// Add a page's parsed `date` and `updated` values to its frontmatter as ISO 8601 strings
+/// (`date_parsed`, `updated_parsed`), so templates and `datefmt` don't have to re-parse them
+fn insert_parsed_dates(frontmatter: &mut YamlValue, dates: &DateParser) -> PageDates {
+    let page_dates = extract_dates(frontmatter, dates);
+
+    if let YamlValue::Mapping(map) = frontmatter {
+        for (key, date) in [("date_parsed", page_dates.published), ("updated_parsed", page_dates.updated)] {
+            if let Some(date) = date {
+                map.insert(YamlValue::String(key.to_string()), YamlValue::String(dates.local(date).to_rfc3339()));
+            }
+        }
+    }
+
+    page_dates
+}
+
+/// Intermediate result for parsing a single page file
+enum ParsedPage {
+    Static(PageInfo),
+    RawDynamic(RawDynamicPageDef),
+}
+
+/// A parsed page file, with its source when the frontmatter parsed
+type ScannedPage = (ParsedPage, Option<Arc<PageSource>>, Vec<String>);
+
+/// Phase 1: Scan all pages, collecting static pages and raw dynamic definitions
+/// Dynamic parameter expressions are NOT evaluated here (they need pages to be available)
+async fn scan_pages_raw(site_path: &PathBuf, config: &SiteConfig, git_ignored: &GitIgnored) -> Result<RawScanResult> {
+    let (transliteration, url_style) = (config.site.url_transliteration, config.build.url_style);
+    let private_keys: Arc<[String]> = config.build.private_frontmatter_keys.clone().into();
+    let max_page_bytes = config.build.max_page_size_kb.saturating_mul(1024);
+    let dates = Arc::new(DateParser::new(config));
+
+    let paths_config = &config.paths;
+    let content_path = site_path.join(paths_config.content_dir());
+    // A static folder inside the content folder holds files to publish as they are, even markdown
+    let static_path = site_path.join(paths_config.static_dir());
+    let skip_static = static_path != content_path && static_path.starts_with(&content_path);
+
+    // 1. Collect paths synchronously (fast - just directory walking)
+    let paths: Vec<(PathBuf, PathBuf, PathBuf)> = WalkDir::new(&content_path)
+        .into_iter()
+        .filter_entry(|e| {
+            let is_static_folder = skip_static && e.path() == static_path;
+            !is_static_folder && !git_ignored.contains(e.path())
+        })
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .filter(|e| e.path().extension().is_some_and(|ext| ext == "md"))
+        .filter_map(|entry| {
+            let path = entry.path();
+            let relative_path = path.strip_prefix(site_path).ok()?;
+            let url_path = paths_config.url_path(relative_path);
+
+            // Skip _ directory and [404].md
+            if url_path.starts_with("_") {
+                return None;
+            }
+            if url_path.to_string_lossy() == "[404].md" {
+                return None;
+            }
+            // Content templates for a folder wrap its pages rather than being one
+            if is_section_template(relative_path) {
+                return None;
+            }
+
+            Some((path.to_owned(), relative_path.to_owned(), url_path.to_owned()))
+        })
+        .collect();
+
+    // 2. Read and parse files in parallel
+    let mut join_set: JoinSet<Option<Result<ScannedPage>>> = JoinSet::new();
+
+    for (path, relative_path, url_path) in paths {
+        let private_keys = Arc::clone(&private_keys);
+        let dates = Arc::clone(&dates);
+        join_set.spawn(async move {
+            // Check the size first, so a stray log file named .md isn't read into memory
+            if let Ok(metadata) = tokio::fs::metadata(&path).await
+                && metadata.len() > max_page_bytes
+            {
+                console::warn(format!(
+                    "{} is {}, over `max_page_size_kb` ({}), skipping. If it really is a page, raise `[build] max_page_size_kb`.",
+                    relative_path.display(),
+                    format_size(metadata.len()),
+                    format_size(max_page_bytes)
+                ));
+                return None;
+            }
+
+            let content = match tokio::fs::read_to_string(&path).await {
+                Ok(c) => c,
                 Err(e) => {
+                    console::warn(format!(
+                        "couldn't read {}: {}, skipping",
+                        relative_path.display(),
+                        e
+                    ));
+                    return None;
+                }
+            };
+
+            let fences = fence_languages(&content);
+
+            // Rendering reports a file that doesn't parse properly, by reading it again then
+            let (mut frontmatter, source) = match PageSource::parse(content) {
+                Ok(source) => (source.frontmatter.clone(), Ok(Arc::new(source))),
+                Err((content, e)) => {
                     console::warn(format!(
                         "couldn't parse frontmatter in {}: {}, using empty metadata",
                         relative_path.display(),
                         e
                     ));
-                    YamlValue::Mapping(serde_yaml::Mapping::new())
+                    (YamlValue::Mapping(serde_yaml::Mapping::new()), Err(content))
                 }
             };
 
+            if let Ok(source) = &source
+                && let Some(name) = body_prints_content(source.body())
+            {
+                console::warn(format!(
+                    "{} prints {{{{ {} }}}}, which is always empty in a page body. The body is what becomes `content`, so print it from `_/content.md` instead.",
+                    relative_path.display(),
+                    name
+                ));
+            }
+
+            // Keep protected page passwords out of pages() and feeds
+            if let YamlValue::Mapping(ref mut map) = frontmatter {
+                map.remove("password");
+            }
+            // ...and private keys, though a dynamic page needs them all to find its values
+            if !is_dynamic_page(&relative_path) {
+                remove_private_keys(&mut frontmatter, &private_keys);
+            }
+
+            let page_dates = insert_parsed_dates(&mut frontmatter, &dates);
+            if let (Some(published), Some(updated)) = (page_dates.published, page_dates.updated)
+                && updated < published
+            {
+                console::warn(format!(
+                    "{} was updated ({}) before it was published ({}), check its dates",
+                    relative_path.display(),
+                    dates.local(updated).format("%Y-%m-%d"),
+                    dates.local(published).format("%Y-%m-%d")
+                ));
+            }
+
             // Check if this is a dynamic page
             if is_dynamic_page(&relative_path) {
                 let filename = relative_path.file_name()?.to_str()?;
                 let param_name = extract_param_name(filename)?;
 
+                let file_content = match &source {
+                    Ok(source) => source.text.clone(),
+                    Err(content) => content.clone(),
+                };
+
                 // Don't evaluate parameter values yet - we need pages to be available first
-                Some(Ok(ParsedPage::RawDynamic(RawDynamicPageDef {
-                    param_name,
-                    source_path: relative_path,
-                    frontmatter,
-                    file_content: content,
-                })))
+                Some(Ok((
+                    ParsedPage::RawDynamic(RawDynamicPageDef {
+                        param_name,
+                        source_path: relative_path,
+                        url_path,
+                        frontmatter,
+                        file_content,
+                    }),
+                    source.ok(),
+                    fences,
+                )))
             } else {
-                let url = convert_file_path_to_url(&relative_path);
-                let file_path = relative_path.to_string_lossy().to_string();
-
-                Some(Ok(ParsedPage::Static(PageInfo {
-                    url,
-                    file_path,
-                    frontmatter,
-                })))
+                let url = SiteUrl::from_file_path(&url_path, transliteration).in_style(url_style);
+                let file_path = relative_path_to_url_string(&relative_path);
+
+                Some(Ok((
+                    ParsedPage::Static(PageInfo {
+                        url,
+                        file_path,
+                        frontmatter,
+                    }),
+                    source.ok(),
+                    fences,
+                )))
             }
         });
     }
@@ -1839,19 +3256,42 @@ async fn scan_pages_raw(site_path: &PathBuf) -> Result<RawScanResult> {
     // 3. Collect results
     let mut static_pages = Vec::new();
     let mut raw_dynamic_defs = Vec::new();
+    let mut sources = HashMap::new();
+    let mut all_fences = HashMap::new();
 
     while let Some(result) = join_set.join_next().await {
         if let Ok(Some(parsed_result)) = result {
-            match parsed_result? {
-                ParsedPage::Static(page_info) => static_pages.push(page_info),
-                ParsedPage::RawDynamic(def) => raw_dynamic_defs.push(def),
+            let (parsed, source, fences) = parsed_result?;
+            let file_path = match parsed {
+                ParsedPage::Static(page_info) => {
+                    let file_path = page_info.file_path.clone();
+                    static_pages.push(page_info);
+                    file_path
+                }
+                ParsedPage::RawDynamic(def) => {
+                    let file_path = relative_path_to_url_string(&def.source_path);
+                    raw_dynamic_defs.push(def);
+                    file_path
+                }
+            };
+            if !fences.is_empty() {
+                all_fences.insert(file_path.clone(), fences);
+            }
+            if let Some(source) = source {
+                sources.insert(file_path, source);
             }
         }
     }
 
+    // Files finish parsing in arbitrary order; sort so pages() and build output are deterministic
+    static_pages.sort_by(|a, b| a.url.cmp(&b.url));
+    raw_dynamic_defs.sort_by(|a, b| a.source_path.cmp(&b.source_path));
+
     Ok(RawScanResult {
         static_pages,
         raw_dynamic_defs,
+        sources,
+        fences: all_fences,
     })
 }
 
@@ -1859,6 +3299,7 @@ async fn scan_pages_raw(site_path: &PathBuf) -> Result<RawScanResult> {
 fn evaluate_dynamic_defs(
     raw_defs: Vec<RawDynamicPageDef>,
     pages: &Arc<Vec<PageInfo>>,
+    param_chars: ParamCharset,
 ) -> Result<Vec<DynamicPageDef>> {
     let mut evaluated_defs = Vec::new();
 
@@ -1869,12 +3310,15 @@ fn evaluate_dynamic_defs(
             &raw_def.source_path,
             pages,
             &raw_def.file_content,
+            param_chars,
         )?;
 
         evaluated_defs.push(DynamicPageDef {
             param_name: raw_def.param_name,
             source_path: raw_def.source_path,
+            url_path: raw_def.url_path,
             param_values,
+            first_is_index: raw_def.frontmatter.get("first_is_index").and_then(YamlValue::as_bool).unwrap_or(false),
             frontmatter: raw_def.frontmatter,
         });
     }
@@ -1892,6 +3336,8 @@ pub struct PageContent<'a> {
     pub main_content: &'a str,
     pub path_class: &'a str,
     pub base: &'a str,
+    /// Whether this is the 404 page, which hosts serve for any path
+    pub is_notfound: bool,
     pub dev_script: &'a str,
     pub seo: SeoContext,
     pub syntax_highlighting_enabled: bool,
@@ -1909,26 +3355,41 @@ pub struct PageContent<'a> {
 pub async fn resolve_path_to_doc(
     path: &str,
     app_data: &AppData,
+    deps: &DepRecorder,
 ) -> Result<Option<(ContentFrontmatter, String, PathBuf, serde_json::Value)>> {
+    // `blog/` and `blog` are the same page; a trailing slash would otherwise end up in its URL
+    let path = path.trim_matches('/');
     let resolvable_path = {
         let check_path = if path.is_empty() { "index" } else { path };
 
-        let mut possible_path = app_data.site_path.join(format!("{}.md", check_path));
+        let paths = &app_data.config.paths;
+        // Only a file whose URL, spelled the way the build spells it, is the one asked for
+        let serves_path = |file: &str| {
+            app_data.has_page_file(file)
+                && SiteUrl::from_file_path(paths.url_path(Path::new(file)), app_data.config.site.url_transliteration).same_page(path)
+        };
+        let mut possible_path = paths.page_file(&format!("{}.md", check_path));
 
-        if possible_path.exists() {
-            Some(possible_path)
+        if serves_path(&possible_path) {
+            Some(app_data.site_path.join(possible_path))
         } else if check_path != "index" {
-            possible_path = app_data.site_path.join(format!("{}/index.md", check_path));
+            possible_path = paths.page_file(&format!("{}/index.md", check_path));
 
-            if possible_path.exists() {
-                Some(possible_path)
+            if serves_path(&possible_path) {
+                Some(app_data.site_path.join(possible_path))
             } else {
                 None
             }
         } else {
             None
         }
-    };
+    }
+    // A transliterated URL doesn't spell out its file's name
+    .or_else(|| {
+        page_for_request(&app_data.pages, path)
+            .filter(|page| DynamicContext::from_page_info(page).is_none() && app_data.has_page_file(&page.file_path))
+            .map(|page| app_data.site_path.join(&page.file_path))
+    });
 
     let resolvable_path = match resolvable_path {
         Some(p) => p,
@@ -1939,36 +3400,17 @@ pub async fn resolve_path_to_doc(
         .strip_prefix(&app_data.site_path)
         .unwrap_or(&resolvable_path);
     let relative_path_str = relative_path.display().to_string();
+    deps.record_file(relative_path);
 
-    let doc_content_jinja = tokio::fs::read_to_string(&resolvable_path)
-        .await
-        .with_file_read(&resolvable_path)?;
+    let source = app_data.page_source(&relative_path_to_url_string(relative_path)).await?;
+    let raw_body = source.body();
 
     let path_class = convert_path_to_class(&resolvable_path, app_data)?;
 
-    // Parse frontmatter FIRST from raw content so it's available to the page body
-    let (frontmatter, raw_body) =
-        markdown_frontmatter::parse::<ContentFrontmatter>(&doc_content_jinja).map_err(|e| {
-            HugsError::FrontmatterParse {
-                file: relative_path_str.clone().into(),
-                src: miette::NamedSource::new(relative_path_str.clone(), doc_content_jinja.clone()),
-                span: miette::SourceSpan::from((0_usize, 1_usize)),
-                reason: format!(
-                    "I couldn't parse the frontmatter. Make sure you have a valid `title` field. Error: {}",
-                    e
-                ),
-            }
-        })?;
-
-    let (raw_frontmatter, _) =
-        markdown_frontmatter::parse::<YamlValue>(&doc_content_jinja).map_err(|e| {
-            HugsError::FrontmatterParse {
-                file: relative_path_str.clone().into(),
-                src: miette::NamedSource::new(relative_path_str.clone(), doc_content_jinja.clone()),
-                span: miette::SourceSpan::from((0_usize, 1_usize)),
-                reason: format!("Failed to parse frontmatter as YAML: {}", e),
-            }
-        })?;
+    // Frontmatter comes first, so it's available to the page body
+    let frontmatter = source.content_frontmatter(&relative_path_str)?;
+    let mut raw_frontmatter = source.frontmatter.clone();
+    insert_parsed_dates(&mut raw_frontmatter, &DateParser::new(&app_data.config));
     let frontmatter_json = yaml_to_json_value(&raw_frontmatter);
 
     // Create merged context: PageContent fields + frontmatter fields
@@ -1982,6 +3424,7 @@ pub async fn resolve_path_to_doc(
         main_content: "",
         path_class: &path_class,
         base: "/",
+        is_notfound: false,
         dev_script: "",
         seo: SeoContext::default(),
         syntax_highlighting_enabled: false,
@@ -2000,7 +3443,7 @@ pub async fn resolve_path_to_doc(
     }
 
     // Render only the body (not frontmatter) with the merged context
-    let body = render_template(raw_body, &context, &app_data.pages, None, &app_data.macros_template, app_data.config.build.reading_speed, &app_data.config.site.language)
+    let mut body = render_template(raw_body, &context, &app_data.render_context(deps))
         .map_err(|e| HugsError::template_render(
             &resolvable_path,
             raw_body,
@@ -2010,11 +3453,24 @@ pub async fn resolve_path_to_doc(
             e.macro_prefix_lines,
         ))?;
 
-    let doc_html = markdown_to_html(&body, &app_data.config.build.syntax_highlighting)
-        .map_err(|reason| HugsError::MarkdownParse {
-            file: relative_path_str.into(),
-            reason,
-        })?;
+    // A directory index can list the pages in its directory after its own content
+    if frontmatter.list.auto_list {
+        if resolvable_path.file_name().is_some_and(|name| name == "index.md") {
+            let index_url = SiteUrl::from_file_path(app_data.config.paths.url_path(relative_path), app_data.config.site.url_transliteration);
+            body.push_str("\n\n");
+            body.push_str(&render_auto_list(index_url.as_str(), &frontmatter.list, app_data, deps).await?);
+        } else {
+            console::warn(format!("{} sets auto_list, which only works on index.md pages", relative_path_str));
+        }
+    }
+
+    let doc_html = app_data.macro_trace.check(
+        markdown_to_html(&body, &app_data.config.build.syntax_highlighting, &app_data.unknown_languages),
+        &relative_path_str,
+        |traced_macros| {
+            render_template(raw_body, &context, &RenderContext { macros_template: traced_macros, ..app_data.render_context(&DepRecorder::default()) }).ok()
+        },
+    )?;
 
     Ok(Some((frontmatter, doc_html, resolvable_path, frontmatter_json)))
 }
@@ -2027,14 +3483,18 @@ pub async fn resolve_dynamic_doc(
     source_file_path: &str,
     dynamic_ctx: &DynamicContext,
     app_data: &AppData,
+    deps: &DepRecorder,
 ) -> Result<(ContentFrontmatter, String, PathBuf, serde_json::Value)> {
     let resolvable_path = app_data.site_path.join(source_file_path);
+    // Which values the page exists for usually comes from pages(), so any page can add or remove it
+    deps.record_file(source_file_path);
+    deps.record_pages();
 
     let relative_path_str = source_file_path.to_string();
 
-    let doc_content_jinja = tokio::fs::read_to_string(&resolvable_path)
-        .await
-        .with_file_read(&resolvable_path)?;
+    let source = app_data.page_source(source_file_path).await?;
+    let doc_content_jinja = &source.text;
+    let raw_body = source.body();
 
     // For dynamic pages, use the param value in the path class (not the [param] placeholder)
     let value_str = yaml_value_to_string(&dynamic_ctx.param_value);
@@ -2044,43 +3504,25 @@ pub async fn resolve_dynamic_doc(
         .replace(&format!("[{}]", dynamic_ctx.param_name), &value_str)
         .replace('/', " ");
 
-    // Parse frontmatter as raw YAML first
-    let (raw_frontmatter, raw_body) =
-        markdown_frontmatter::parse::<YamlValue>(&doc_content_jinja).map_err(|e| {
-            HugsError::FrontmatterParse {
-                file: relative_path_str.clone().into(),
-                src: miette::NamedSource::new(relative_path_str.clone(), doc_content_jinja.clone()),
-                span: miette::SourceSpan::from((0_usize, 1_usize)),
-                reason: format!("Failed to parse frontmatter as YAML: {}", e),
-            }
-        })?;
-
     // Render template expressions in frontmatter values (e.g., `title: "{{ tag | title }}"`)
-    let rendered_frontmatter = render_frontmatter_values(
-        &raw_frontmatter,
+    let dates = DateParser::new(&app_data.config);
+    let mut rendered_frontmatter = render_frontmatter_values(
+        &source.frontmatter,
         dynamic_ctx,
         &app_data.pages,
         &app_data.config.site.language,
+        &dates,
         &relative_path_str,
-        &doc_content_jinja,
+        doc_content_jinja,
     )?;
 
     // Convert rendered frontmatter to JSON for template context
+    insert_parsed_dates(&mut rendered_frontmatter, &dates);
     let frontmatter_json = yaml_to_json_value(&rendered_frontmatter);
 
     // Deserialize rendered frontmatter into ContentFrontmatter
     let frontmatter: ContentFrontmatter = serde_yaml::from_value(rendered_frontmatter.clone())
-        .map_err(|e| {
-            HugsError::FrontmatterParse {
-                file: relative_path_str.clone().into(),
-                src: miette::NamedSource::new(relative_path_str.clone(), doc_content_jinja.clone()),
-                span: miette::SourceSpan::from((0_usize, 1_usize)),
-                reason: format!(
-                    "I couldn't parse the frontmatter. Make sure you have a valid `title` field. Error: {}",
-                    e
-                ),
-            }
-        })?;
+        .map_err(|e| source.frontmatter_error(&relative_path_str, e))?;
 
     // Create merged context: PageContent fields + frontmatter fields + dynamic parameter
     let head_extra = app_data.config.site.head_extra.as_deref().unwrap_or("");
@@ -2093,6 +3535,7 @@ pub async fn resolve_dynamic_doc(
         main_content: "",
         path_class: &path_class,
         base: "/",
+        is_notfound: false,
         dev_script: "",
         seo: SeoContext::default(),
         syntax_highlighting_enabled: false,
@@ -2117,7 +3560,7 @@ pub async fn resolve_dynamic_doc(
     }
 
     // Render only the body (not frontmatter) with the merged context
-    let body = render_template(raw_body, &context, &app_data.pages, None, &app_data.macros_template, app_data.config.build.reading_speed, &app_data.config.site.language)
+    let body = render_template(raw_body, &context, &app_data.render_context(deps))
         .map_err(|e| HugsError::template_render(
             &resolvable_path,
             raw_body,
@@ -2127,11 +3570,13 @@ pub async fn resolve_dynamic_doc(
             e.macro_prefix_lines,
         ))?;
 
-    let doc_html = markdown_to_html(&body, &app_data.config.build.syntax_highlighting)
-        .map_err(|reason| HugsError::MarkdownParse {
-            file: relative_path_str.into(),
-            reason,
-        })?;
+    let doc_html = app_data.macro_trace.check(
+        markdown_to_html(&body, &app_data.config.build.syntax_highlighting, &app_data.unknown_languages),
+        &relative_path_str,
+        |traced_macros| {
+            render_template(raw_body, &context, &RenderContext { macros_template: traced_macros, ..app_data.render_context(&DepRecorder::default()) }).ok()
+        },
+    )?;
 
     Ok((frontmatter, doc_html, resolvable_path, frontmatter_json))
 }
@@ -2139,11 +3584,14 @@ pub async fn resolve_dynamic_doc(
 pub async fn render_notfound_page(app_data: &AppData, dev_script: &str) -> Option<String> {
     let notfound_path = app_data.notfound_page.as_ref()?;
 
-    let doc_content_jinja = tokio::fs::read_to_string(notfound_path).await.ok()?;
+    let relative_path = relative_path_to_url_string(notfound_path.strip_prefix(&app_data.site_path).unwrap_or(notfound_path));
+    let source = app_data.page_source(&relative_path).await.ok()?;
+    let raw_body = source.body();
 
-    // Parse frontmatter FIRST from raw content so it's available to the page body
-    let (frontmatter, raw_body) = markdown_frontmatter::parse::<ContentFrontmatter>(&doc_content_jinja).ok()?;
-    let (raw_frontmatter, _) = markdown_frontmatter::parse::<YamlValue>(&doc_content_jinja).ok()?;
+    // Frontmatter comes first, so it's available to the page body
+    let frontmatter = source.content_frontmatter(&relative_path).ok()?;
+    let mut raw_frontmatter = source.frontmatter.clone();
+    insert_parsed_dates(&mut raw_frontmatter, &DateParser::new(&app_data.config));
     let frontmatter_json = yaml_to_json_value(&raw_frontmatter);
 
     // Create merged context: PageContent fields + frontmatter fields
@@ -2157,6 +3605,7 @@ pub async fn render_notfound_page(app_data: &AppData, dev_script: &str) -> Optio
         main_content: "",
         path_class: "notfound",
         base: "/",
+        is_notfound: true,
         dev_script: "",
         seo: SeoContext::default(),
         syntax_highlighting_enabled: false,
@@ -2173,48 +3622,38 @@ pub async fn render_notfound_page(app_data: &AppData, dev_script: &str) -> Optio
     }
 
     // Render only the body (not frontmatter) with the merged context
-    let body = render_template(raw_body, &context, &app_data.pages, None, &app_data.macros_template, app_data.config.build.reading_speed, &app_data.config.site.language).ok()?;
-
-    let doc_html = markdown_to_html(&body, &app_data.config.build.syntax_highlighting).ok()?;
+    let deps = DepRecorder::default();
+    let body = render_template(raw_body, &context, &app_data.render_context(&deps)).ok()?;
 
-    let seo = build_seo_context(&frontmatter, "/404", &app_data.config.site);
-    let rendered_title = render_title_template(&frontmatter.title, &app_data.config.site);
+    // Hosts serve the page for any path, so its links can't be relative to where it is
+    let doc_html = markdown_to_html(&body, &app_data.config.build.syntax_highlighting, &app_data.unknown_languages).ok()?;
+    let doc_html = absolutize_links(&doc_html);
 
-    let mut content_ctx = if let serde_json::Value::Object(map) = &frontmatter_json {
-        serde_json::Value::Object(map.clone())
-    } else {
-        serde_json::Value::Object(serde_json::Map::new())
-    };
+    let seo = build_seo_context(&frontmatter, &SiteUrl::parse("/404"), &app_data.config.site, &app_data.title_failures);
+    let rendered_title = seo.title.clone();
 
-    if let serde_json::Value::Object(ref mut map) = content_ctx {
-        map.insert("content".to_string(), serde_json::Value::String(doc_html.clone()));
-        map.insert("path_class".to_string(), serde_json::Value::String("notfound".to_string()));
-        map.insert("base".to_string(), serde_json::Value::String("/".to_string()));
-        map.insert("seo".to_string(), serde_json::to_value(&seo).unwrap_or(serde_json::Value::Null));
-    }
+    let content_ctx = content_template_context(&frontmatter_json, &doc_html, "notfound", "/", true, &seo);
 
-    let content_template_rendered = render_template(
-        &app_data.content_template,
-        &content_ctx,
-        &app_data.pages,
-        None,
-        &app_data.macros_template,
-        app_data.config.build.reading_speed,
-        &app_data.config.site.language,
-    ).ok()?;
+    let content_template = app_data.content_templates.for_page(&relative_path);
+    let content_template_rendered = render_template(&content_template.template, &content_ctx, &app_data.render_context(&deps)).ok()?;
 
-    let main_content_html = markdown::to_html_with_options(&content_template_rendered, &markdown_options()).ok()?;
+    let main_content_html = timings::phase(Phase::Markdown, || markdown::to_html_with_options(&content_template_rendered, &markdown_options())).ok()?;
+    let main_content_html = absolutize_links(&main_content_html);
 
     let head_extra_val = app_data.config.site.head_extra.as_deref().unwrap_or("");
+    let tokens = PageTokens { url: "/404", source: &relative_path, title: &frontmatter.title };
+    let doc_html = tokens.substitute_owned(doc_html);
+    let main_content_html = tokens.substitute_owned(main_content_html);
     let content = PageContent {
         title: &rendered_title,
-        header: &app_data.header_html,
-        footer: &app_data.footer_html,
-        nav: &app_data.nav_html,
+        header: &tokens.substitute(&app_data.header_html),
+        footer: &tokens.substitute(&app_data.footer_html),
+        nav: &tokens.substitute(&app_data.nav_html),
         content: &doc_html,
         main_content: &main_content_html,
         path_class: "notfound",
         base: "/",
+        is_notfound: true,
         dev_script,
         seo,
         syntax_highlighting_enabled: app_data.config.build.syntax_highlighting.enabled,
@@ -2222,70 +3661,227 @@ pub async fn render_notfound_page(app_data: &AppData, dev_script: &str) -> Optio
     };
 
     let cache_bust = app_data.cache_bust_function();
-    let html_out = render_root_template(app_data, &content, &cache_bust).ok()?;
+    let html_out = render_root_template(app_data, &content, &cache_bust, &deps).ok()?;
 
     Some(html_out)
 }
 
-pub async fn try_serve_static_file(path: &str, app_data: &AppData) -> Option<HttpResponse> {
-    // Don't serve files from the _ directory as static assets
-    if path.starts_with("_/") || path.starts_with("_") {
-        return None;
+/// How a server answers a request path (trailing slashes trimmed) before rendering anything
+pub enum Route<'a> {
+    /// Render the page at this path
+    Page(&'a str),
+    /// Send a static file
+    File(HttpResponse),
+}
+
+/// Route a `hugs dev` or `hugs doc` request. A page wins over a static file at the same path,
+/// like in `hugs build`, and asking for the file a page is written to (about/index.html) also
+/// gets the page. A page's URL in the other `url_style` only gets it when there's no static
+/// file there, since the build would publish that file.
+pub async fn route_request<'a>(path: &'a str, app_data: &'a AppData) -> Route<'a> {
+    if let Some(claimed) = page_for_request(&app_data.pages, path) {
+        return Route::Page(claimed.url.as_str().trim_matches('/'));
     }
+    if let Some(response) = try_serve_static_file(path, app_data).await {
+        return Route::File(response);
+    }
+    match page_in_any_style(&app_data.pages, path) {
+        Some(page) => Route::Page(page.url.as_str().trim_matches('/')),
+        None => Route::Page(path),
+    }
+}
 
-    let file_path = app_data.site_path.join(path);
+/// What a server request for a page resolves to
+pub enum PageResponse {
+    /// The rendered page, before minification
+    Page(String),
+    /// An old URL from a page's `aliases`; holds the encoded URL to redirect to
+    Redirect(String),
+    /// Nothing here; holds the site's 404 page if it has one
+    NotFound(Option<String>),
+}
 
-    // Check if it's an actual file (not directory) and not a markdown file
-    if file_path.is_file() {
-        if let Some(ext) = file_path.extension() {
-            if ext == "md" {
-                return None; // Let markdown files be handled by the page renderer
-            }
-        }
+/// Where a resolved page came from, which decides its URL, `base` and `path_class`
+enum ResolvedSource {
+    /// A markdown file, by its full path
+    File(PathBuf),
+    /// An instance of a dynamic page, by the URL the build gives it
+    Dynamic(SiteUrl),
+}
 
-        // Read and serve the file
-        match tokio::fs::read(&file_path).await {
-            Ok(contents) => {
-                let mime_type = mime_guess::from_path(&file_path)
-                    .first_or_octet_stream();
+/// The page at a request path, rendered up to its own content
+pub struct ResolvedPage {
+    pub frontmatter: ContentFrontmatter,
+    pub frontmatter_json: serde_json::Value,
+    /// The page's content as HTML, without `_/content.md` or the root template around it
+    pub content: String,
+    source: ResolvedSource,
+}
 
-                Some(HttpResponse::Ok()
-                    .content_type(ContentType(mime_type))
-                    .body(contents))
+impl ResolvedPage {
+    /// Render the page up to its root template
+    pub fn prepare(self, app_data: &AppData, dev_script: &str, deps: &DepRecorder) -> Result<PreparedPage> {
+        match &self.source {
+            ResolvedSource::File(path) => {
+                prepare_page_html(&self.frontmatter, &self.frontmatter_json, self.content, path, app_data, dev_script, deps)
+            }
+            ResolvedSource::Dynamic(url) => {
+                prepare_dynamic_page_html(&self.frontmatter, &self.frontmatter_json, self.content, url, app_data, dev_script, deps)
             }
-            Err(_) => None,
         }
-    } else {
-        None
     }
 }
 
-pub fn convert_path_to_base(path: &PathBuf, app_data: &AppData) -> Result<String> {
-    let relative = path.strip_prefix(&app_data.site_path).map_err(|_| {
-        HugsError::PathStripPrefix {
-            path: path.into(),
-            base: (&app_data.site_path).into(),
+/// What `_/content.md` renders with: the page's frontmatter, and its rendered body as `content`.
+/// The body is HTML already, so it's marked safe for autoescaping to print it as it is.
+fn content_template_context(
+    frontmatter_json: &serde_json::Value,
+    doc_html: &str,
+    path_class: &str,
+    base: &str,
+    is_notfound: bool,
+    seo: &SeoContext,
+) -> Value {
+    minijinja::value::merge_maps([
+        Value::from_serialize(frontmatter_json),
+        minijinja::context! {
+            content => Value::from_safe_string(doc_html.to_string()),
+            path_class,
+            base,
+            is_notfound,
+            seo,
+        },
+    ])
+}
+
+/// Find the page at a request path (without leading slash) and render its content: a markdown
+/// page, then an instance of a dynamic page. `hugs build`, `hugs dev`, `hugs doc` and
+/// `hugs render` all look pages up here, so they agree on what's at a URL.
+pub async fn resolve_page(path: &str, app_data: &AppData, deps: &DepRecorder) -> Result<Option<ResolvedPage>> {
+    let path = without_html_suffix(path);
+    if let Some((frontmatter, content, resolvable_path, frontmatter_json)) =
+        resolve_path_to_doc(path, app_data, deps).await?
+    {
+        return Ok(Some(ResolvedPage { frontmatter, frontmatter_json, content, source: ResolvedSource::File(resolvable_path) }));
+    }
+
+    if let Some((source_path, dynamic_ctx)) = match_dynamic_page(path, app_data) {
+        let (frontmatter, content, _resolvable_path, frontmatter_json) =
+            resolve_dynamic_doc(&source_path, &dynamic_ctx, app_data, deps).await?;
+        // The URL the build gives the page, which keeps the trailing slash of an index page
+        let page_url = page_for_request(&app_data.pages, path)
+            .map(|page| page.url.clone())
+            .unwrap_or_else(|| SiteUrl::parse(path));
+        return Ok(Some(ResolvedPage { frontmatter, frontmatter_json, content, source: ResolvedSource::Dynamic(page_url) }));
+    }
+
+    Ok(None)
+}
+
+/// Render the page a `hugs dev` or `hugs doc` request asks for: a markdown page, then a dynamic
+/// page, then an alias redirect, then the 404 page
+pub async fn render_requested_page(
+    path: &str,
+    app_data: &AppData,
+    dev_script: &str,
+    deps: &DepRecorder,
+) -> Result<PageResponse> {
+    if let Some(mut page) = resolve_page(path, app_data, deps).await? {
+        page.content = protect_for_dev(&page.frontmatter, page.content);
+        return page.prepare(app_data, dev_script, deps)?.render(app_data).map(PageResponse::Page);
+    }
+
+    // An old URL listed in a page's `aliases` sends visitors to that page
+    if let Some(target) = find_alias_target(&app_data.pages, &format!("/{}", path)) {
+        return Ok(PageResponse::Redirect(target.url.encoded().into_owned()));
+    }
+
+    Ok(PageResponse::NotFound(render_notfound_page(app_data, dev_script).await))
+}
+
+/// The dynamic page instance at a URL path, as (source file, parameter). Each value's URL comes
+/// from [`DynamicPageDef::value_url`], like in the build, so dev serves exactly the URLs the build
+/// writes.
+fn match_dynamic_page(url_path: &str, app_data: &AppData) -> Option<(String, DynamicContext)> {
+    let transliteration = app_data.config.site.url_transliteration;
+
+    app_data.dynamic_defs.iter().find_map(|def| {
+        let value = def.param_values.iter().find(|value| def.value_url(value, transliteration).same_page(url_path))?;
+        Some((
+            relative_path_to_url_string(&def.source_path),
+            DynamicContext { param_name: def.param_name.clone(), param_value: value.clone() },
+        ))
+    })
+}
+
+/// Whether `relative` stays inside the directory it's joined to: no `..`, no root, no prefix
+pub fn is_plain_relative(relative: &Path) -> bool {
+    relative.components().all(|c| matches!(c, std::path::Component::Normal(_)))
+}
+
+pub async fn try_serve_static_file(path: &str, app_data: &AppData) -> Option<HttpResponse> {
+    // URLs only separate with `/`. On Windows a `\` would be a separator too, and `..\` could
+    // reach outside the site, so requests with one are never files.
+    let relative = Path::new(path);
+    if path.contains('\\') || !is_plain_relative(relative) {
+        return None;
+    }
+
+    let file_path = app_data.asset_roots.path(relative);
+    if !file_path.is_file() || app_data.git_ignored.contains(&file_path) {
+        return None;
+    }
+
+    // Serve exactly what `hugs build` would copy. Pages and `_/` are handled elsewhere
+    match skip_reason(relative, &app_data.config.assets) {
+        None => {}
+        Some(SkipReason::Excluded(pattern)) => {
+            if !app_data.excluded_requests.lock().unwrap_or_else(PoisonError::into_inner).insert(path.to_string()) {
+                return None;
+            }
+            console::warn(format!(
+                "not serving /{} because it matches `{}` in the [assets] exclude list, so `hugs build` won't copy it either",
+                path, pattern
+            ));
+            return None;
         }
-    })?;
+        Some(_) => return None,
+    }
 
-    // Get the parent directory of the file
-    if let Some(parent) = relative.parent() {
-        let parent_str = parent.to_string_lossy();
-        if parent_str.is_empty() {
-            Ok(String::from("/"))
-        } else {
-            Ok(format!("/{}/", parent_str))
+    // Read and serve the file
+    match tokio::fs::read(&file_path).await {
+        Ok(contents) => {
+            let mime_type = mime_guess::from_path(&file_path)
+                .first_or_octet_stream();
+
+            Some(HttpResponse::Ok()
+                .content_type(ContentType(mime_type))
+                .body(contents))
         }
-    } else {
-        Ok(String::from("/"))
+        Err(_) => None,
+    }
+}
+
+/// The `<base>` of the page at `url`: the folder its source file sits in, which is where relative
+/// links and images in it resolve from. Page URLs end in `/` exactly when they come from an
+/// `index.md` (static or dynamic), so `/docs/2/` gets `/docs/2/` while `/docs/page` gets `/docs/`,
+/// however the page is served.
+pub fn page_base(url: &str) -> String {
+    if url.ends_with('/') {
+        return url.to_string();
+    }
+    match url.rfind('/') {
+        Some(slash) => url[..=slash].to_string(),
+        None => String::from("/"),
     }
 }
 
 pub fn convert_path_to_class(path: &PathBuf, app_data: &AppData) -> Result<String> {
-    let relative = path.strip_prefix(&app_data.site_path).map_err(|_| {
+    let content_path = app_data.site_path.join(app_data.config.paths.content_dir());
+    let relative = path.strip_prefix(&content_path).map_err(|_| {
         HugsError::PathStripPrefix {
             path: path.into(),
-            base: (&app_data.site_path).into(),
+            base: (&content_path).into(),
         }
     })?;
 
@@ -2298,13 +3894,10 @@ pub fn convert_path_to_class(path: &PathBuf, app_data: &AppData) -> Result<Strin
         &without_ext
     };
 
-    let path_str = path_for_class
-        .to_str()
-        .ok_or_else(|| HugsError::PathInvalidUtf8 {
-            path: path.into(),
-        })?
-        .to_string()
-        .replace("/", " ");
+    if path_for_class.to_str().is_none() {
+        return Err(HugsError::PathInvalidUtf8 { path: path.into() });
+    }
+    let path_str = relative_path_to_url_string(path_for_class).replace('/', " ");
 
     // For root index.md, path_str will be empty - use "index" instead
     if path_str.is_empty() {
@@ -2314,142 +3907,227 @@ pub fn convert_path_to_class(path: &PathBuf, app_data: &AppData) -> Result<Strin
     }
 }
 
-/// Helper function to render a page to HTML
-pub fn render_page_html(
+/// Render a page up to its root template
+pub fn prepare_page_html(
     frontmatter: &ContentFrontmatter,
     frontmatter_json: &serde_json::Value,
-    doc_html: &str,
+    doc_html: String,
     resolvable_path: &PathBuf,
     app_data: &AppData,
     dev_script: &str,
-) -> Result<String> {
-    let base = convert_path_to_base(resolvable_path, app_data)?;
+    deps: &DepRecorder,
+) -> Result<PreparedPage> {
     let path_class = convert_path_to_class(resolvable_path, app_data)?;
-    let page_url = convert_file_path_to_url(
-        resolvable_path
-            .strip_prefix(&app_data.site_path)
-            .unwrap_or(resolvable_path),
-    );
-
-    render_page_html_internal(frontmatter, frontmatter_json, doc_html, &page_url, &path_class, &base, app_data, dev_script)
+    let relative_path = resolvable_path.strip_prefix(&app_data.site_path).unwrap_or(resolvable_path);
+    let page_url = SiteUrl::from_file_path(app_data.config.paths.url_path(relative_path), app_data.config.site.url_transliteration)
+        .in_style(app_data.config.build.url_style);
+    let base = page_base(page_url.as_str());
+    let source = relative_path_to_url_string(relative_path);
+
+    let location = PageLocation { url: page_url, source, path_class, base };
+    prepare_page_internal(frontmatter, frontmatter_json, doc_html, location, app_data, dev_script, deps)
 }
 
-/// Render a dynamic page to HTML with explicit URL (for proper SEO and path_class)
-pub fn render_dynamic_page_html(
+/// Render a dynamic page up to its root template
+pub fn prepare_dynamic_page_html(
     frontmatter: &ContentFrontmatter,
     frontmatter_json: &serde_json::Value,
-    doc_html: &str,
-    page_url: &str,
+    doc_html: String,
+    page_url: &SiteUrl,
     app_data: &AppData,
     dev_script: &str,
-) -> Result<String> {
+    deps: &DepRecorder,
+) -> Result<PreparedPage> {
     // Derive base and path_class from the resolved URL instead of file path
-    let url_path = page_url.trim_start_matches('/');
-    let base = if url_path.is_empty() || url_path == "/" {
-        String::from("/")
-    } else if let Some(parent) = std::path::Path::new(url_path).parent() {
-        let parent_str = parent.to_string_lossy();
-        if parent_str.is_empty() {
-            String::from("/")
-        } else {
-            format!("/{}/", parent_str)
-        }
-    } else {
-        String::from("/")
-    };
-
+    let base = page_base(page_url.as_str());
+    let url_path = page_url.as_str().trim_matches('/');
     let path_class = if url_path.is_empty() {
         String::from("index")
     } else {
         url_path.replace('/', " ")
     };
 
-    render_page_html_internal(frontmatter, frontmatter_json, doc_html, page_url, &path_class, &base, app_data, dev_script)
+    // Every instance of a dynamic page is in `pages`, so its file can be looked up by URL
+    let source = page_for_request(&app_data.pages, page_url.as_str()).map(|page| page.file_path.clone()).unwrap_or_default();
+
+    let location = PageLocation { url: page_url.clone(), source, path_class, base };
+    prepare_page_internal(frontmatter, frontmatter_json, doc_html, location, app_data, dev_script, deps)
 }
 
-/// Internal helper for rendering page HTML
-fn render_page_html_internal(
-    frontmatter: &ContentFrontmatter,
-    frontmatter_json: &serde_json::Value,
-    doc_html: &str,
-    page_url: &str,
-    path_class: &str,
-    base: &str,
-    app_data: &AppData,
-    dev_script: &str,
-) -> Result<String> {
-    let seo = build_seo_context(frontmatter, page_url, &app_data.config.site);
-    let rendered_title = render_title_template(&frontmatter.title, &app_data.config.site);
+/// A page rendered up to its root template, which is all that's left to do. The build writes big
+/// pages straight to disk from here, rather than holding the whole page in memory once more.
+pub struct PreparedPage {
+    url: SiteUrl,
+    /// The page's file, relative to the site root
+    source: String,
+    title: String,
+    /// The title from the frontmatter, before `title_template`
+    page_title: String,
+    content: String,
+    main_content: String,
+    path_class: String,
+    base: String,
+    dev_script: String,
+    seo: SeoContext,
+    /// What the page body read
+    body_deps: RenderDeps,
+    /// What the content and root templates read
+    deps: DepRecorder,
+}
 
-    let mut content_ctx = if let serde_json::Value::Object(map) = frontmatter_json {
-        serde_json::Value::Object(map.clone())
-    } else {
-        serde_json::Value::Object(serde_json::Map::new())
-    };
+impl PreparedPage {
+    /// Bytes of rendered content; the finished page is this plus the header, nav and footer
+    pub fn content_len(&self) -> usize {
+        self.main_content.len()
+    }
 
-    if let serde_json::Value::Object(ref mut map) = content_ctx {
-        map.insert("content".to_string(), serde_json::Value::String(doc_html.to_string()));
-        map.insert("path_class".to_string(), serde_json::Value::String(path_class.to_string()));
-        map.insert("base".to_string(), serde_json::Value::String(base.to_string()));
-        map.insert("seo".to_string(), serde_json::to_value(&seo).unwrap_or(serde_json::Value::Null));
+    /// The page's own content as HTML, without `_/content.md` around it
+    pub fn content(&self) -> &str {
+        &self.content
     }
 
-    let content_template_rendered = render_template(
-        &app_data.content_template,
-        &content_ctx,
-        &app_data.pages,
-        None,
-        &app_data.macros_template,
-        app_data.config.build.reading_speed,
-        &app_data.config.site.language,
-    )
-    .map_err(|e| HugsError::template_render_named(
-        "_/content.md",
-        &app_data.content_template,
-        &e.error,
-        &e.hints,
-        e.macro_prefix_bytes,
-        e.macro_prefix_lines,
-    ))?;
-
-    let main_content_html = markdown::to_html_with_options(&content_template_rendered, &markdown_options())
+    /// Finish the page as a string
+    pub fn render(self, app_data: &AppData) -> Result<String> {
+        let cache_bust = app_data.cache_bust_function();
+        let chrome = self.chrome(app_data);
+        let html = render_root_template(app_data, self.page_content(app_data, &chrome), &cache_bust, &self.deps)
+            .map_err(root_template_error)?;
+        self.record_deps(app_data);
+        Ok(html)
+    }
+
+    /// Finish the page straight into `out`
+    pub fn render_to(self, app_data: &AppData, out: impl std::io::Write) -> Result<()> {
+        let cache_bust = app_data.cache_bust_function();
+        let chrome = self.chrome(app_data);
+        render_root_template_to(app_data, self.page_content(app_data, &chrome), &cache_bust, &self.deps, out)
+            .map_err(root_template_error)?;
+        self.record_deps(app_data);
+        Ok(())
+    }
+
+    /// The header, nav and footer, with this page's values in their page tokens
+    fn chrome<'a>(&self, app_data: &'a AppData) -> [Cow<'a, str>; 3] {
+        let tokens = PageTokens { url: self.url.as_str(), source: &self.source, title: &self.page_title };
+        [&app_data.header_html, &app_data.nav_html, &app_data.footer_html].map(|html| tokens.substitute(html))
+    }
+
+    fn page_content<'a>(&'a self, app_data: &'a AppData, chrome: &'a [Cow<'a, str>; 3]) -> PageContent<'a> {
+        let [header, nav, footer] = chrome;
+        PageContent {
+            title: &self.title,
+            header,
+            footer,
+            nav,
+            content: &self.content,
+            main_content: &self.main_content,
+            path_class: &self.path_class,
+            base: &self.base,
+            is_notfound: false,
+            dev_script: &self.dev_script,
+            seo: self.seo.clone(),
+            syntax_highlighting_enabled: app_data.config.build.syntax_highlighting.enabled,
+            head_extra: app_data.config.site.head_extra.as_deref().unwrap_or(""),
+        }
+    }
+
+    /// Store what rendering the page read: its body plus the wrapping templates
+    fn record_deps(self, app_data: &AppData) {
+        let mut deps = self.body_deps;
+        deps.merge(&self.deps.snapshot());
+        app_data.record_page_deps(self.url.as_str(), deps);
+    }
+}
+
+fn root_template_error(e: TemplateError) -> HugsError {
+    HugsError::template_render_named("root.jinja", ROOT_TEMPL, &e.error, &e.hints, e.macro_prefix_bytes, e.macro_prefix_lines)
+}
+
+/// Where a page is: its URL, its file relative to the site root, and the class and base its
+/// templates get
+struct PageLocation {
+    url: SiteUrl,
+    source: String,
+    path_class: String,
+    base: String,
+}
+
+/// Render a page's content template, leaving the root template for later. `body_deps` holds what
+/// rendering the page body read.
+fn prepare_page_internal(
+    frontmatter: &ContentFrontmatter,
+    frontmatter_json: &serde_json::Value,
+    doc_html: String,
+    location: PageLocation,
+    app_data: &AppData,
+    dev_script: &str,
+    body_deps: &DepRecorder,
+) -> Result<PreparedPage> {
+    let PageLocation { url: page_url, source, path_class, base } = location;
+    let deps = DepRecorder::default();
+    let seo = build_seo_context(frontmatter, &page_url, &app_data.config.site, &app_data.title_failures);
+
+    let content_ctx = content_template_context(frontmatter_json, &doc_html, &path_class, &base, false, &seo);
+
+    // The nearest `_content.md` above the page, or `_/content.md`
+    let content_template = app_data.content_templates.for_page(&source);
+    let content_template_rendered = render_template(&content_template.template, &content_ctx, &app_data.render_context(&deps))
+    .map_err(|e| content_template.render_error(&e))?;
+    // The context holds a copy of the page, so let it go before the next one is made
+    drop(content_ctx);
+
+    let main_content = timings::phase(Phase::Markdown, || markdown::to_html_with_options(&content_template_rendered, &markdown_options()))
         .map_err(|e| HugsError::MarkdownParse {
-            file: "_/content.md".into(),
+            file: content_template.file.as_str().into(),
             reason: e.to_string(),
         })?;
 
-    let head_extra = app_data.config.site.head_extra.as_deref().unwrap_or("");
-    let content = PageContent {
-        title: &rendered_title,
-        header: &app_data.header_html,
-        footer: &app_data.footer_html,
-        nav: &app_data.nav_html,
-        content: doc_html,
-        main_content: &main_content_html,
+    let tokens = PageTokens { url: page_url.as_str(), source: &source, title: &frontmatter.title };
+    let content = tokens.substitute_owned(doc_html);
+    let main_content = tokens.substitute_owned(main_content);
+
+    Ok(PreparedPage {
+        url: page_url,
+        source,
+        title: seo.title.clone(),
+        page_title: frontmatter.title.clone(),
+        content,
+        main_content,
         path_class,
         base,
-        dev_script,
+        dev_script: dev_script.to_string(),
         seo,
-        syntax_highlighting_enabled: app_data.config.build.syntax_highlighting.enabled,
-        head_extra,
-    };
-
-    let cache_bust = app_data.cache_bust_function();
-    render_root_template(app_data, &content, &cache_bust)
-        .map_err(|e| HugsError::template_render_named(
-            "root.jinja",
-            ROOT_TEMPL,
-            &e.error,
-            &e.hints,
-            e.macro_prefix_bytes,
-            e.macro_prefix_lines,
-        ))
+        body_deps: body_deps.snapshot(),
+        deps,
+    })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    /// Render `template` with no site around it
+    fn render_bare<T: serde::Serialize>(
+        template: &str,
+        ctx: T,
+        config: &SiteConfig,
+        cache_bust: Option<&CacheBustFunction>,
+        macros_template: &str,
+    ) -> String {
+        let render = RenderContext {
+            pages: &Arc::new(vec![]),
+            cache_bust,
+            svg: None,
+            cache: &BuildCache::default(),
+            code_languages: &CodeLanguages::default(),
+            macros_template,
+            config,
+            mode: RenderMode::Build,
+            deps: &DepRecorder::default(),
+        };
+        render_template(template, ctx, &render).unwrap_or_else(|e| panic!("{}", e.error))
+    }
+
     #[test]
     fn test_parse_locale() {
         // Test underscore format
@@ -2466,25 +4144,231 @@ mod tests {
     }
 
     #[test]
-    fn test_parse_date_string() {
-        // YYYY-MM-DD format
-        assert!(parse_date_string_for_filter("2024-01-15").is_ok());
+    fn test_frontmatter_and_datefmt_read_the_same_dates() {
+        let config: SiteConfig = toml::from_str("[site]\ntimezone = \"+02:00\"\n[dates]\nformats = [\"%d/%m/%Y\"]").unwrap();
+        let dates = DateParser::new(&config);
+        let mut env = Environment::new();
+        env.add_filter("datefmt", create_datefmt_filter("en_US".to_string(), dates.clone()));
+        env.add_template("test", "{{ date | datefmt(\"%Y-%m-%d %H:%M\") }}").unwrap();
+        let tmpl = env.get_template("test").unwrap();
 
-        // ISO 8601 format
-        assert!(parse_date_string_for_filter("2024-01-15T10:30:00Z").is_ok());
+        for input in [
+            "2024-01-15",
+            "2024-01-15T10:30:00Z",
+            "2024-01-15 10:30:00",
+            "2024-01-15T10:30:00",
+            "Mon, 15 Jan 2024 10:30:00 +0000",
+            "15 Jan 2024",
+            "15/01/2024",
+            "invalid",
+            "01/15/2024",
+            "2024-01-15T25:00:00Z",
+        ] {
+            let frontmatter: YamlValue = serde_yaml::from_str(&format!("date: \"{}\"", input)).unwrap();
+            let published = extract_dates(&frontmatter, &dates).published;
+            let formatted = tmpl.render(minijinja::context! { date => input }).ok();
+            assert_eq!(formatted, published.map(|dt| dates.local(dt).format("%Y-%m-%d %H:%M").to_string()), "{input}");
+        }
 
-        // YYYY-MM-DD HH:MM:SS format
-        assert!(parse_date_string_for_filter("2024-01-15 10:30:00").is_ok());
+        // Dates without an offset show the day they were written for
+        assert_eq!(tmpl.render(minijinja::context! { date => "2024-01-15" }).unwrap(), "2024-01-15 00:00");
+        assert_eq!(tmpl.render(minijinja::context! { date => "2024-01-15T23:30:00Z" }).unwrap(), "2024-01-16 01:30");
+    }
 
-        // Invalid format
-        assert!(parse_date_string_for_filter("invalid").is_err());
-        assert!(parse_date_string_for_filter("15/01/2024").is_err());
+    fn cache_bust_env(registry: &CacheBustRegistry) -> Environment<'static> {
+        let cache_bust = CacheBustFunction::new(
+            AssetRoots::whole_site(Path::new("/nonexistent")),
+            String::from("body { color: red; }"),
+            String::from(".hl { color: blue; }"),
+            registry.clone(),
+        );
+        let mut env = Environment::new();
+        env.add_function("cache_bust", cache_bust.to_minijinja_fn(&DepRecorder::default()));
+        env
+    }
+
+    #[test]
+    fn test_cache_bust_registry_survives_panicking_render() {
+        let registry = CacheBustRegistry::new();
+
+        // A render task that panics while holding the lock poisons the mutex
+        let poisoned = registry.clone();
+        let result = std::thread::spawn(move || {
+            let _guard = poisoned.entries.lock().unwrap();
+            panic!("render task failed");
+        })
+        .join();
+        assert!(result.is_err());
+        assert!(registry.entries.is_poisoned());
+
+        // Later pages still render and register their assets
+        let env = cache_bust_env(&registry);
+        let rendered = env
+            .render_str(r#"{{ cache_bust(path="/theme.css") }}"#, ())
+            .unwrap();
+        assert!(rendered.starts_with("/theme.") && rendered.ends_with(".css"));
+        assert_eq!(registry.entries().get("/theme.css"), Some(&rendered));
+
+
+        let taken = registry.take();
+        assert_eq!(taken.len(), 1);
+        assert!(registry.entries().is_empty());
+    }
+
+    #[test]
+    fn test_cache_bust_registry_collects_entries_from_parallel_renders() {
+        let registry = CacheBustRegistry::new();
+        let site = tempfile::tempdir().unwrap();
+        for i in 0..16 {
+            std::fs::write(site.path().join(format!("asset{}.js", i)), format!("// {}", i)).unwrap();
+        }
+
+        // Each task gets its own clone, like render tasks sharing Arc<AppData> in build
+        let handles: Vec<_> = (0..16)
+            .map(|i| {
+                let cache_bust = CacheBustFunction::new(
+                    AssetRoots::whole_site(site.path()),
+                    String::new(),
+                    String::new(),
+                    registry.clone(),
+                );
+                std::thread::spawn(move || {
+                    let mut env = Environment::new();
+                    env.add_function("cache_bust", cache_bust.to_minijinja_fn(&DepRecorder::default()));
+                    env.render_str(&format!(r#"{{{{ cache_bust(path="/asset{}.js") }}}}"#, i), ())
+                        .unwrap()
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let entries = registry.entries();
+        assert_eq!(entries.len(), 16);
+        for i in 0..16 {
+            assert!(entries.contains_key(&format!("/asset{}.js", i)));
+        }
+    }
+
+    #[test]
+    fn test_cache_bust_hashes_big_files_a_buffer_at_a_time() {
+        let site = tempfile::tempdir().unwrap();
+        let content: Vec<u8> = (0..3 * 1024 * 1024 + 7).map(|i: u32| (i % 251) as u8).collect();
+        std::fs::write(site.path().join("video.webm"), &content).unwrap();
+
+        let expected = compute_content_hash(&content);
+        assert_eq!(compute_file_hash(&site.path().join("video.webm")).unwrap(), expected);
+
+        let registry = CacheBustRegistry::new();
+        let cache_bust = CacheBustFunction::new(AssetRoots::whole_site(site.path()), String::new(), String::new(), registry.clone());
+        let mut env = Environment::new();
+        env.add_function("cache_bust", cache_bust.to_minijinja_fn(&DepRecorder::default()));
+        let rendered = env.render_str(r#"{{ cache_bust(path="/video.webm") }}"#, ()).unwrap();
+        assert_eq!(rendered, format!("/video.{}.webm", &expected[..8]));
+        assert_eq!(registry.entries_with_hashes()["/video.webm"].hash, expected);
+    }
+
+    #[test]
+    fn test_cache_bust_rejects_unknown_arguments() {
+        let env = cache_bust_env(&CacheBustRegistry::new());
+        let err = env.render_str(r#"{{ cache_bust(path="/theme.css", fresh=true) }}"#, ()).unwrap_err();
+        assert!(err.to_string().contains("fresh"), "{err}");
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+    async fn test_pages_racing_to_cache_bust_the_same_assets_agree() {
+        let site = tempfile::tempdir().unwrap();
+        std::fs::create_dir(site.path().join("img")).unwrap();
+        std::fs::write(site.path().join("app.js"), "console.log(1)").unwrap();
+        std::fs::write(site.path().join("img/logo.svg"), "<svg></svg>").unwrap();
+        let template = r#"{{ cache_bust(path="/theme.css") }} {{ cache_bust(path="/app.js") }} {{ cache_bust(path="/img/logo.svg") }}"#;
+
+        // 100 pages at once, each with its own environment, all sharing the registry as in a build
+        let render_all = |registry: CacheBustRegistry| {
+            let cache_bust = CacheBustFunction::new(AssetRoots::whole_site(site.path()), String::from("body {}"), String::new(), registry);
+            async move {
+                let mut renders = tokio::task::JoinSet::new();
+                for _ in 0..100 {
+                    let cache_bust = cache_bust.clone();
+                    renders.spawn_blocking(move || {
+                        let mut env = Environment::new();
+                        env.add_function("cache_bust", cache_bust.to_minijinja_fn(&DepRecorder::default()));
+                        env.render_str(template, ()).unwrap()
+                    });
+                }
+                tokio::time::timeout(std::time::Duration::from_secs(30), renders.join_all()).await.expect("renders got stuck on the registry")
+            }
+        };
+
+        let registry = CacheBustRegistry::new();
+        let rendered = render_all(registry.clone()).await;
+        assert_eq!(rendered.len(), 100);
+        assert!(rendered.iter().all(|html| *html == rendered[0]), "pages disagree on the names");
+
+        let entries = registry.entries();
+        assert_eq!(entries.keys().collect::<Vec<_>>(), ["/app.js", "/img/logo.svg", "/theme.css"]);
+        assert_eq!(rendered[0], format!("{} {} {}", entries["/theme.css"], entries["/app.js"], entries["/img/logo.svg"]));
+
+        // Another build names them the same
+        let again = CacheBustRegistry::new();
+        render_all(again.clone()).await;
+        assert_eq!(again.entries_with_hashes(), registry.entries_with_hashes());
+    }
+
+    #[test]
+    fn test_seo_meta_merges_page_over_site() {
+        let site: crate::config::SiteMetadata = toml::from_str(
+            r#"
+            description = "Site description"
+            [meta]
+            google-site-verification = "abc123"
+            "fediverse:creator" = "@site@example.social"
+            "#,
+        )
+        .unwrap();
+        let frontmatter: ContentFrontmatter = serde_yaml::from_str(
+            "title: Post\nmeta:\n  fediverse:creator: \"@me@example.social\"\n  property:og:type: article\n  description: Page description",
+        )
+        .unwrap();
+
+        let seo = build_seo_context(&frontmatter, &SiteUrl::parse("/post"), &site, &TitleFailures::default());
+
+        assert_eq!(seo.og_type, "article");
+        assert_eq!(seo.description.as_deref(), Some("Page description"));
+        assert_eq!(
+            seo.extra_meta,
+            vec![
+                ("fediverse:creator".to_string(), "@me@example.social".to_string()),
+                ("google-site-verification".to_string(), "abc123".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_insert_parsed_dates() {
+        let mut frontmatter: YamlValue =
+            serde_yaml::from_str("title: Post\ndate: 2024-01-15\nupdated: 2024-03-02 09:30:00").unwrap();
+
+        let dates = insert_parsed_dates(&mut frontmatter, &DateParser::default());
+
+        assert_eq!(dates.last_modified(), dates.updated);
+        assert_eq!(frontmatter["date_parsed"].as_str(), Some("2024-01-15T00:00:00+00:00"));
+        assert_eq!(frontmatter["updated_parsed"].as_str(), Some("2024-03-02T09:30:00+00:00"));
+
+        // Pages that were never updated only get the published date
+        let mut frontmatter: YamlValue = serde_yaml::from_str("title: Post\ndate: 2024-01-15").unwrap();
+        let dates = insert_parsed_dates(&mut frontmatter, &DateParser::default());
+
+        assert_eq!(dates.last_modified(), dates.published);
+        assert!(frontmatter.get("updated_parsed").is_none());
     }
 
     #[test]
     fn test_datefmt_filter_basic() {
         let mut env = Environment::new();
-        env.add_filter("datefmt", create_datefmt_filter("en_US".to_string()));
+        env.add_filter("datefmt", create_datefmt_filter("en_US".to_string(), DateParser::default()));
         env.add_template("test", "{{ date | datefmt(\"%Y-%m-%d\") }}").unwrap();
 
         let tmpl = env.get_template("test").unwrap();
@@ -2495,7 +4379,7 @@ mod tests {
     #[test]
     fn test_datefmt_filter_localized() {
         let mut env = Environment::new();
-        env.add_filter("datefmt", create_datefmt_filter("en_US".to_string()));
+        env.add_filter("datefmt", create_datefmt_filter("en_US".to_string(), DateParser::default()));
         env.add_template("test", "{{ date | datefmt(\"%B\") }}").unwrap();
 
         let tmpl = env.get_template("test").unwrap();
@@ -2506,7 +4390,7 @@ mod tests {
     #[test]
     fn test_datefmt_filter_locale_override() {
         let mut env = Environment::new();
-        env.add_filter("datefmt", create_datefmt_filter("en_US".to_string()));
+        env.add_filter("datefmt", create_datefmt_filter("en_US".to_string(), DateParser::default()));
         env.add_template("test", "{{ date | datefmt(\"%B\", locale=\"fr_FR\") }}").unwrap();
 
         let tmpl = env.get_template("test").unwrap();
@@ -2560,22 +4444,22 @@ mod tests {
     #[test]
     fn test_dynamic_param_pages_function_available() {
         // Test that pages() function is available in dynamic parameter expressions
-        // This allows frontmatter like: slug: "{{ pages(within='/blog/') | map(attribute='url') }}"
+        // This allows frontmatter like: slug: "{{ pages(within='/blog/') | map(attribute='slug') }}"
         let pages = Arc::new(vec![
             PageInfo {
-                url: "/blog/post1".to_string(),
+                url: SiteUrl::parse("/blog/post1"),
                 file_path: "blog/post1.md".to_string(),
-                frontmatter: YamlValue::Mapping(serde_yaml::Mapping::new()),
+                frontmatter: serde_yaml::from_str("slug: post1").unwrap(),
             },
             PageInfo {
-                url: "/blog/post2".to_string(),
+                url: SiteUrl::parse("/blog/post2"),
                 file_path: "blog/post2.md".to_string(),
-                frontmatter: YamlValue::Mapping(serde_yaml::Mapping::new()),
+                frontmatter: serde_yaml::from_str("slug: post2").unwrap(),
             },
         ]);
 
-        // Expression using pages() to get URLs from blog posts
-        let expr = "{{ pages(within='/blog/') | map(attribute='url') }}";
+        // Expression using pages() to get slugs from blog posts (URLs would be rejected for their slashes)
+        let expr = "{{ pages(within='/blog/') | map(attribute='slug') }}";
         let file_content = format!(r#"---
 slug: "{}"
 ---
@@ -2594,14 +4478,15 @@ Content"#, expr);
             Path::new("test/[slug].md"),
             &pages,
             &file_content,
+            ParamCharset::default(),
         );
 
         assert!(result.is_ok(), "pages() should be available in frontmatter expressions: {:?}", result.err());
         let values = result.unwrap();
         // Should produce two URLs from the blog posts
         assert_eq!(values.len(), 2);
-        assert!(values.contains(&YamlValue::String("/blog/post1".to_string())));
-        assert!(values.contains(&YamlValue::String("/blog/post2".to_string())));
+        assert!(values.contains(&YamlValue::String("post1".to_string())));
+        assert!(values.contains(&YamlValue::String("post2".to_string())));
     }
 
     #[test]
@@ -2609,7 +4494,7 @@ Content"#, expr);
         // Test that errors from frontmatter evaluation include helpful environment info
         let pages = Arc::new(vec![
             PageInfo {
-                url: "/blog/post1".to_string(),
+                url: SiteUrl::parse("/blog/post1"),
                 file_path: "blog/post1.md".to_string(),
                 frontmatter: YamlValue::Mapping(serde_yaml::Mapping::new()),
             },
@@ -2635,6 +4520,7 @@ Content"#, expr);
             Path::new("test/[slug].md"),
             &pages,
             &file_content,
+            ParamCharset::default(),
         );
 
         assert!(result.is_err());
@@ -2682,6 +4568,7 @@ Content"#, expr);
             &dynamic_ctx,
             &pages,
             "en_US",
+            &DateParser::default(),
             "test.md",
             "---\ntitle: \"{{ tag | title }}\"\n---\n",
         );
@@ -2749,6 +4636,7 @@ Content"#, expr);
             &dynamic_ctx,
             &pages,
             "en_US",
+            &DateParser::default(),
             "test.md",
             "---\ntitle: \"{{ tag | title }}\"\norder: 42\n---\n",
         );
@@ -2798,6 +4686,7 @@ Content"#, expr);
             &dynamic_ctx,
             &pages,
             "en_US",
+            &DateParser::default(),
             "test.md",
             "---\ntitle: \"{{ tag | unknownfilter }}\"\n---\n",
         );
@@ -2808,6 +4697,7 @@ Content"#, expr);
         // Check that the error is a TemplateRender error, not TemplateContext
         match &err {
             HugsError::TemplateRender { help_text, .. } => {
+                let help_text = help_text.to_string();
                 // Should mention it's an unknown filter and suggest alternatives
                 assert!(
                     help_text.contains("filter") || help_text.contains("Filter"),
@@ -2852,6 +4742,7 @@ Content"#, expr);
             &dynamic_ctx,
             &pages,
             "en_US",
+            &DateParser::default(),
             "test.md",
             "---\ntitle: \"{{ tag | help }}\"\n---\n",
         );
@@ -2864,6 +4755,7 @@ Content"#, expr);
         // Check that we got a TemplateRender error with the help output
         match &err {
             HugsError::TemplateRender { reason, help_text, .. } => {
+                let help_text = help_text.to_string();
                 // The reason should indicate this is a help request
                 assert!(
                     reason.contains("you asked for filter help"),
@@ -2914,6 +4806,7 @@ Content"#, expr);
             &dynamic_ctx,
             &pages,
             "en_US",
+            &DateParser::default(),
             source_file,
             source_content,
         );
@@ -2960,7 +4853,7 @@ Content"#, expr);
         // Test that DynamicExprEval errors include source span pointing to the expression
         let pages = Arc::new(vec![
             PageInfo {
-                url: "/blog/post1".to_string(),
+                url: SiteUrl::parse("/blog/post1"),
                 file_path: "blog/post1.md".to_string(),
                 frontmatter: YamlValue::Mapping(serde_yaml::Mapping::new()),
             },
@@ -2987,6 +4880,7 @@ Content here"#;
             source_path,
             &pages,
             file_content,
+            ParamCharset::default(),
         );
 
         assert!(result.is_err(), "Expression with |help should fail as it throws an error");
@@ -2995,6 +4889,7 @@ Content here"#;
         // Check that the error includes source span information
         match &err {
             HugsError::DynamicExprEval { file, src, span, expression, help_text, .. } => {
+                let help_text = help_text.to_string();
                 // Should show the file path
                 let file_str = format!("{:?}", file);
                 assert!(
@@ -3063,6 +4958,7 @@ Content"#;
             source_path,
             &pages,
             file_content,
+            ParamCharset::default(),
         );
 
         assert!(result.is_err(), "Expression with unknown function should fail");
@@ -3101,7 +4997,7 @@ Content"#;
         // and produces helpful debug output (not "filter help is unknown")
         let pages = Arc::new(vec![
             PageInfo {
-                url: "/blog/post1".to_string(),
+                url: SiteUrl::parse("/blog/post1"),
                 file_path: "blog/post1.md".to_string(),
                 frontmatter: YamlValue::Mapping(serde_yaml::Mapping::new()),
             },
@@ -3127,6 +5023,7 @@ Content"#;
             source_path,
             &pages,
             file_content,
+            ParamCharset::default(),
         );
 
         // The help filter intentionally throws an error to display help info
@@ -3135,6 +5032,7 @@ Content"#;
 
         match &err {
             HugsError::DynamicExprEval { reason, help_text, resolved_value, .. } => {
+                let help_text = help_text.to_string();
                 // The reason (span label) should match template errors
                 assert!(
                     reason.contains("you asked for filter help here"),
@@ -3181,7 +5079,7 @@ Content"#;
         // and produces helpful debug output (not "test help is unknown")
         let pages = Arc::new(vec![
             PageInfo {
-                url: "/blog/post1".to_string(),
+                url: SiteUrl::parse("/blog/post1"),
                 file_path: "blog/post1.md".to_string(),
                 frontmatter: YamlValue::Mapping(serde_yaml::Mapping::new()),
             },
@@ -3208,6 +5106,7 @@ Content"#;
             source_path,
             &pages,
             file_content,
+            ParamCharset::default(),
         );
 
         // The help test intentionally throws an error to display help info
@@ -3216,6 +5115,7 @@ Content"#;
 
         match &err {
             HugsError::DynamicExprEval { reason, help_text, resolved_value, .. } => {
+                let help_text = help_text.to_string();
                 // The reason (span label) should match template errors
                 assert!(
                     reason.contains("you asked for test help here"),
@@ -3256,13 +5156,46 @@ Content"#;
         }
     }
 
+    #[test]
+    fn test_frontmatter_value_spans_cover_the_whole_expression() {
+        // Block scalar lines are indented under the key
+        let block = "{{ pages(within='/blog')\n    | map(attribute='tags') | flatten | unique }}";
+        // file, text the span should cover
+        let cases = [
+            ("---\ntag: \"{{ x }}\"\n---\nBody", "\"{{ x }}\""),
+            ("---\r\ntitle: T\r\ntag: '{{ \"it''s\" }}' # note\r\n---\r\n", "'{{ \"it''s\" }}'"),
+            ("---\ntag: \"{{ pages(within='/blog')\n  | map(attribute=\\\"tags\\\") }}\"\nother: 1\n---\n", "\"{{ pages(within='/blog')\n  | map(attribute=\\\"tags\\\") }}\""),
+            (&format!("---\ntag: >-\n  {}\n\nother: 1\n---\n", block), block),
+            (&format!("---\ntag: |\n  {}\n---\n", block), block),
+            ("---\ntag: rust # a comment\n---\n", "rust"),
+            ("---\ntag: a plain\n  value\nother: 1\n---\n", "a plain\n  value"),
+        ];
+
+        for (file, covered) in cases {
+            let span = frontmatter_value_span(file, "tag").unwrap_or_else(|| panic!("no span in {file:?}"));
+            assert_eq!(&file[span.offset()..span.offset() + span.len()], covered, "span in {file:?}");
+        }
+
+        assert!(frontmatter_value_span("---\ntitle: T\n---\ntag: \"{{ x }}\"\n", "tag").is_none());
+        assert!(frontmatter_value_span("---\nmytag: x\n---\n", "tag").is_none());
+        assert!(frontmatter_value_span("---\ntag:\n  - a\n---\n", "tag").is_none());
+        assert!(frontmatter_value_span("---\ntag: [a, b]\n---\n", "tag").is_none());
+    }
+
+    #[test]
+    fn test_long_values_are_truncated_in_help() {
+        assert_eq!(truncate_for_help("short"), "short");
+        let long = "é".repeat(HELP_VALUE_MAX_CHARS + 42);
+        assert_eq!(truncate_for_help(&long), format!("{}… (42 more chars)", "é".repeat(HELP_VALUE_MAX_CHARS)));
+    }
+
     #[test]
     fn test_dynamic_expr_help_function_is_recognized() {
         // Test that the help() function is recognized in dynamic page expressions
         // and produces helpful debug output (not "function help is unknown")
         let pages = Arc::new(vec![
             PageInfo {
-                url: "/blog/post1".to_string(),
+                url: SiteUrl::parse("/blog/post1"),
                 file_path: "blog/post1.md".to_string(),
                 frontmatter: YamlValue::Mapping(serde_yaml::Mapping::new()),
             },
@@ -3288,6 +5221,7 @@ Content"#;
             source_path,
             &pages,
             file_content,
+            ParamCharset::default(),
         );
 
         // The help function intentionally throws an error to display help info
@@ -3296,6 +5230,7 @@ Content"#;
 
         match &err {
             HugsError::DynamicExprEval { reason, help_text, .. } => {
+                let help_text = help_text.to_string();
                 // The reason (span label) should match template errors
                 assert!(
                     reason.contains("you asked for help here"),
@@ -3356,4 +5291,1240 @@ Content"#;
             }
         }
     }
+
+    fn parse_test_config(source: &str) -> SiteConfig {
+        let mut config: SiteConfig = toml::from_str(source).unwrap();
+        config.source = source.to_string();
+        config
+    }
+
+    #[test]
+    fn test_title_template_typo_points_into_config() {
+        let source = "[site]\ntitle = \"Site\"\ntitle_template = \"{{ titel }} | {{ site.title }}\"\n";
+        let config = parse_test_config(source);
+
+        match validate_title_template(&config) {
+            Err(HugsError::TitleTemplate { span, .. }) => {
+                assert_eq!(&source[span.offset()..span.offset() + span.len()], "titel");
+            }
+            _ => panic!("expected a TitleTemplate error"),
+        }
+
+        let config = parse_test_config("[site]\ntitle_template = \"{{ title }} | {{ site.title }}\"\n");
+        assert!(validate_title_template(&config).is_ok());
+        let failures = TitleFailures::default();
+        assert_eq!(render_title_template("About", &config.site, &failures), "About | ");
+        assert!(failures.warnings().is_empty());
+
+        // A page the template fails for keeps its plain title, and the build hears about it
+        let config = parse_test_config("[site]\ntitle_template = \"{{ title }} ({{ title[10] }})\"\n");
+        assert_eq!(render_title_template("About", &config.site, &failures), "About");
+        assert!(matches!(&failures.warnings()[..], [HugsError::TitleTemplatePage { title, .. }] if title == "About"));
+    }
+
+    #[test]
+    fn test_invalid_regex_points_at_the_call() {
+        let template = "<p>{{ title }}</p>\n{% if url is matches(\"^/blog/(?<=x)\") %}x{% endif %}";
+        let render = RenderContext {
+            pages: &Arc::new(vec![]),
+            cache_bust: None,
+            svg: None,
+            cache: &BuildCache::default(),
+            code_languages: &CodeLanguages::default(),
+            macros_template: "",
+            config: &SiteConfig::default(),
+            mode: RenderMode::Build,
+            deps: &DepRecorder::default(),
+        };
+        let Err(e) = render_template(template, minijinja::context! { title => "Hi", url => "/blog/" }, &render) else {
+            panic!("expected the pattern to be rejected");
+        };
+
+        let HugsError::TemplateRender { span, help_text, reason, .. } =
+            HugsError::template_render_named("page.md", template, &e.error, &e.hints, e.macro_prefix_bytes, e.macro_prefix_lines)
+        else {
+            unreachable!()
+        };
+        assert!(template[span.offset()..span.offset() + span.len()].contains("matches"), "{reason}");
+        assert!(reason.contains("look-around"), "{reason}");
+        assert!(help_text.to_string().contains("Lookahead and lookbehind"), "{help_text}");
+    }
+
+    #[test]
+    fn test_frontmatter_is_escaped_once_and_html_fields_are_not() {
+        let title = "Ben & Jerry's <script>alert(1)</script> 🍦";
+        let escaped = "Ben &amp; Jerry&#39;s &lt;script&gt;alert(1)&lt;/script&gt; 🍦";
+        let config = SiteConfig::default();
+        let render = |template: &str, ctx: Value, cache_bust: Option<&CacheBustFunction>| {
+            render_bare(template, ctx, &config, cache_bust, "")
+        };
+
+        let body = render("# {{ title }}\n\n{{ content }}", minijinja::context! { title, content => "<p>Body</p>" }, None);
+        assert_eq!(body, format!("# {escaped}\n\n<p>Body</p>"));
+
+        let frontmatter: ContentFrontmatter = serde_yaml::from_str(&format!("title: {:?}", title)).unwrap();
+        let seo = build_seo_context(&frontmatter, &SiteUrl::parse("/ice-cream"), &config.site, &TitleFailures::default());
+        let page = PageContent {
+            title: &seo.title.clone(),
+            header: "<h1>Site</h1>",
+            footer: "",
+            nav: "",
+            content: "",
+            main_content: "<p>Body &amp; more</p>",
+            path_class: "",
+            base: "/",
+            is_notfound: false,
+            dev_script: "",
+            seo,
+            syntax_highlighting_enabled: false,
+            head_extra: "",
+        };
+        let cache_bust = CacheBustFunction::new(AssetRoots::whole_site(Path::new("")), String::new(), String::new(), CacheBustRegistry::new());
+        let html = render(ROOT_TEMPL, Value::from_serialize(&page), Some(&cache_bust));
+
+        assert!(html.contains(&format!("<title>{escaped}</title>")), "{html}");
+        assert!(html.contains(&format!(r#"<meta property="og:title" content="{escaped}">"#)), "{html}");
+        assert!(html.contains(r#"<link rel="canonical" href="/ice-cream">"#), "{html}");
+        assert!(html.contains("<h1>Site</h1>") && html.contains("<p>Body &amp; more</p>"), "{html}");
+    }
+
+    #[tokio::test]
+    async fn test_readtime_is_a_number_and_readtime_detail_says_how() {
+        let (_site, app_data) = transliterated_site(&[(
+            "index.md",
+            "---\ntitle: Home\n---\n{{ readtime(\"a few words\") > 0 }}, {{ readtime(\"a few words\") + 1 }}, {{ readtime_detail(\"a few words\").method }} {{ readtime_detail(\"a few words\").words }}",
+        )])
+        .await;
+        let app_data = app_data.unwrap();
+
+        let html = crate::build::render_page(page_for_request(&app_data.pages, "/").unwrap(), &app_data).await.unwrap();
+        assert!(html.contains("True, 2, words 3"), "{html}");
+    }
+
+    #[tokio::test]
+    async fn test_ampersands_in_bodies_are_escaped_once() {
+        let (_site, app_data) = transliterated_site(&[
+            ("config.toml", "[build]\nminify = false\n"),
+            ("_/content.md", "<div class=\"body\">\n\n{{ content }}\n\n</div>"),
+            ("index.md", "---\ntitle: Ben & Jerry\n---\nBen & Jerry, by {{ title }}\n\n`a && b`\n\n[Ben & Jerry](/?a=1&b=2)"),
+        ])
+        .await;
+        let app_data = app_data.unwrap();
+
+        let html = crate::build::render_page(page_for_request(&app_data.pages, "/").unwrap(), &app_data).await.unwrap();
+        assert!(html.contains("<p>Ben &amp; Jerry, by Ben &amp; Jerry</p>"), "{html}");
+        assert!(html.contains("<code>a &amp;&amp; b</code>"), "{html}");
+        assert!(html.contains(r#"<a href="/?a=1&amp;b=2">Ben &amp; Jerry</a>"#), "{html}");
+        assert!(!html.contains("&amp;amp;"), "{html}");
+    }
+
+    #[test]
+    fn test_unknown_highlight_theme_suggests_a_known_one() {
+        crate::highlight::init_registry().unwrap();
+        let source = "[build.syntax_highlighting]\ntheme = \"one-drak-pro\"\n";
+        let config = parse_test_config(source);
+
+        match validate_highlighting(&config) {
+            Err(HugsError::UnknownHighlightTheme { span: Some(span), help_text, .. }) => {
+                assert_eq!(&source[span.offset()..span.offset() + span.len()], "\"one-drak-pro\"");
+                assert!(help_text.starts_with("Did you mean `one-dark-pro`?"), "{help_text}");
+                assert!(help_text.contains("github-light"), "{help_text}");
+            }
+            _ => panic!("expected an UnknownHighlightTheme error"),
+        }
+
+        let config = parse_test_config("[build.syntax_highlighting]\ntheme = \"One-Dark-Pro\"\n");
+        assert!(validate_highlighting(&config).is_ok());
+        let config = parse_test_config("[build.syntax_highlighting]\nenabled = false\ntheme = \"nope\"\n");
+        assert!(validate_highlighting(&config).is_ok());
+    }
+
+    #[test]
+    fn test_highlight_aliases_need_a_language_i_know() {
+        crate::highlight::init_registry().unwrap();
+        let source = "[build.syntax_highlighting.aliases]\nshell = \"bash\"\nmydata = \"jsno\"\n";
+        match validate_highlighting(&parse_test_config(source)) {
+            Err(HugsError::UnknownHighlightAlias { alias, span: Some(span), .. }) => {
+                assert_eq!(alias.0, "mydata");
+                assert_eq!(&source[span.offset()..span.offset() + span.len()], "\"jsno\"");
+            }
+            other => panic!("expected an UnknownHighlightAlias error, got {other:?}"),
+        }
+
+        let config = parse_test_config("[build.syntax_highlighting.aliases]\nshell = \"bash\"\nrust = \"python\"\n");
+        assert!(validate_highlighting(&config).is_ok());
+    }
+
+    #[test]
+    fn test_macro_uses_include_the_macros_they_call() {
+        let macros_template = "{% macro badge(text) %}\n<b>{{ text }}</b>\n{% endmacro %}\n\n\
+            {% macro card(title) %}\n{{ badge(title) }}\n{% endmacro %}\n\n\
+            {% macro note() %}\n{{ caller() }}\n{% endmacro %}\n\n";
+        let names = extract_macro_names(macros_template);
+        let used = |template: &str| {
+            let deps = DepRecorder::default();
+            record_macro_uses(template, macros_template, &names, &deps);
+            deps.snapshot().macros.into_iter().collect::<Vec<_>>()
+        };
+
+        assert_eq!(used("{{ card(title) }}"), ["badge", "card"]);
+        assert_eq!(used("{% call note() %}Hi{% endcall %}"), ["note"]);
+        assert!(used("{{ title }}").is_empty());
+    }
+
+    #[test]
+    fn test_template_functions_are_callable_like_macros() {
+        let config = parse_test_config(
+            "[template.functions]\nexcerpt_url = { params = [\"p\"], body = \"{{ p.url }}#excerpt\" }\n",
+        );
+
+        let macros_template = build_template_functions(&config, &[], Path::new("")).unwrap();
+        let result = render_bare(
+            "{{ excerpt_url(page) }}",
+            minijinja::context! { page => minijinja::context! { url => "/blog/hello" } },
+            &SiteConfig::default(),
+            None,
+            &macros_template,
+        );
+
+        assert_eq!(result.trim(), "/blog/hello#excerpt");
+    }
+
+    #[test]
+    fn test_template_functions_see_the_mode() {
+        let config = parse_test_config("[template.functions]
+banner = { body = \"{{ hugs.mode }} {% if hugs.dev %}draft{% endif %}\" }
+");
+        let macros_template = build_template_functions(&config, &[], Path::new("")).unwrap();
+
+        for (mode, expected) in [(RenderMode::Build, "build"), (RenderMode::Dev, "dev draft")] {
+            let render = RenderContext {
+                pages: &Arc::new(vec![]),
+                cache_bust: None,
+                svg: None,
+                cache: &BuildCache::default(),
+                code_languages: &CodeLanguages::default(),
+                macros_template: &macros_template,
+                config: &config,
+                mode,
+                deps: &DepRecorder::default(),
+            };
+            let result = render_template("{{ banner() }}", minijinja::context! {}, &render).unwrap_or_else(|e| panic!("{}", e.error));
+            assert_eq!(result.trim(), expected);
+        }
+    }
+
+    #[test]
+    fn test_template_function_errors_point_into_config() {
+        // Syntax errors are reported inside the body string
+        let source = "[template.functions]\nbroken = { body = \"{{ oops \" }\n";
+        let config = parse_test_config(source);
+        match build_template_functions(&config, &[], Path::new("")) {
+            Err(HugsError::TemplateFunctionSyntax { span, .. }) => {
+                let body_start = source.find("{{ oops").unwrap();
+                assert!(span.offset() >= body_start, "span should be inside the body string");
+                assert!(span.offset() <= source.rfind('"').unwrap());
+            }
+            other => panic!("Expected TemplateFunctionSyntax error, got: {:?}", other.err()),
+        }
+
+        // Names clashing with built-in functions are rejected
+        let config = parse_test_config("[template.functions]\npages = { body = \"hi\" }\n");
+        assert!(matches!(
+            build_template_functions(&config, &[], Path::new("")),
+            Err(HugsError::TemplateFunctionCollision { .. })
+        ));
+
+        // Names must be identifiers
+        let config = parse_test_config("[template.functions]\n\"my-fn\" = { body = \"hi\" }\n");
+        assert!(matches!(
+            build_template_functions(&config, &[], Path::new("")),
+            Err(HugsError::TemplateFunctionInvalidName { .. })
+        ));
+    }
+
+    #[test]
+    fn test_macros_are_checked_when_they_load() {
+        let site = Path::new("/site");
+        let parse = |file: &str, content: &str| parse_macro_file(&site.join("_/macros").join(file), content).unwrap();
+        let card = parse("card.md", "---\ntitle: \"\"\n---\n<h2>{{ titel }}</h2>{{ author }}{{ caller() }}{{ hugs.dev }}");
+        let badge = parse("badge.md", "---\nlabel: \"\"\ncolor: \"blue\"\n---\n<span>{{ label | upper }}</span>{{ card() }}{{ site }}");
+        let stray = parse("stray.md", "---\n---\n{{ sumary }}{{ page.url }}{{ wibble }}");
+        let broken = parse("broken.md", "---\nname: \"\"\n---\n<p>\n{% if name %}{{ name }}\n{% endfi %}\n");
+        let page_keys = BTreeSet::from([String::from("author"), String::from("summary")]);
+
+        let warnings = check_macros(&[card.clone(), badge.clone(), stray], &SiteConfig::default(), site, &page_keys).unwrap();
+        assert_eq!(
+            warnings,
+            [
+                "the card macro uses `titel`, which it doesn't declare; did you mean its `title` parameter?",
+                "the card macro declares `title` in _/macros/card.md but never uses it",
+                "the badge macro declares `color` in _/macros/badge.md but never uses it",
+                "the stray macro uses `sumary`, which it doesn't declare; did you mean `summary`?",
+                "the stray macro uses `wibble`, which isn't one of its parameters, a template function or any page's frontmatter",
+            ]
+        );
+
+        match check_macros(&[card, badge, broken], &SiteConfig::default(), site, &page_keys) {
+            Err(HugsError::MacroSyntax { name, file, src, span, .. }) => {
+                assert_eq!(name.0, "broken");
+                assert_eq!(file.0, "_/macros/broken.md");
+                assert_eq!(src.name(), "_/macros/broken.md");
+                let line = src.inner()[..span.offset()].matches('\n').count() + 1;
+                assert_eq!((line, &src.inner()[span.offset()..span.offset() + span.len()]), (6, "endfi"));
+            }
+            other => panic!("expected a MacroSyntax error, got: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_broken_macro_output_names_the_macro() {
+        let site = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(site.path().join("_/macros")).unwrap();
+        for (file, content) in [
+            ("_/header.md", "# Site"),
+            ("_/nav.md", "[Home](/)"),
+            ("_/footer.md", ""),
+            ("_/theme.css", ""),
+            ("_/macros/card.md", "---\ntitle: \"\"\n---\n<div class=\"card\">\n\n**{{ title }}**\n"),
+            ("_/macros/note.md", "<aside>{{ caller() }}</aside>"),
+            ("index.md", "---\ntitle: Home\n---\n{% call note() %}Hi{% endcall %}\n\n{{ card(title=\"One\") }}\n"),
+            ("ok.md", "---\ntitle: Fine\n---\n{% call note() %}Hi{% endcall %}\n"),
+        ] {
+            std::fs::write(site.path().join(file), content).unwrap();
+        }
+        let app_data = AppData::load(site.path().to_path_buf(), "build").await.unwrap();
+
+        match resolve_path_to_doc("", &app_data, &DepRecorder::default()).await {
+            Err(HugsError::MacroBrokenOutput { name, file, help_text }) => {
+                assert_eq!(name.0, "card");
+                assert_eq!(file.0, "index.md");
+                assert!(help_text.contains("_/macros/card.md") && help_text.contains("<div>"), "{}", help_text);
+            }
+            other => panic!("expected a MacroBrokenOutput error, got: {:?}", other.err()),
+        }
+
+        assert!(resolve_path_to_doc("ok", &app_data, &DepRecorder::default()).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_dev_and_build_give_pages_the_same_base() {
+        let site = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(site.path().join("_")).unwrap();
+        for (file, content) in [
+            ("_/header.md", "# Site"),
+            ("_/nav.md", "[Home](/)"),
+            ("_/footer.md", ""),
+            ("_/theme.css", "body {}"),
+            ("index.md", "---\ntitle: Home\n---\n![Diagram](img/diagram.png)"),
+            ("docs/[n].md", "---\ntitle: Part {{ n }}\nn: [1, 2]\n---\n![Diagram](img/diagram.png)"),
+            ("docs/static/index.md", "---\ntitle: Static\n---\n![Diagram](img/diagram.png)"),
+            ("docs/page.md", "---\ntitle: Page\n---\n![Diagram](img/diagram.png)"),
+            ("guides/[g].md", "---\ntitle: Guide\ng: [intro]\n---\n![Diagram](img/diagram.png)"),
+        ] {
+            let path = site.path().join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        }
+        let app_data = AppData::load(site.path().to_path_buf(), "dev").await.unwrap();
+        let base_of = |html: &str| {
+            let start = html.find("<base href=\"").expect("page has a <base>") + "<base href=\"".len();
+            html[start..start + html[start..].find('"').unwrap()].to_string()
+        };
+
+        let expected = [
+            ("/", "/"),
+            ("/docs/1", "/docs/"),
+            ("/docs/static/", "/docs/static/"),
+            ("/docs/page", "/docs/"),
+            ("/guides/intro", "/guides/"),
+        ];
+        for (url, base) in expected {
+            let page = page_for_request(&app_data.pages, url).unwrap_or_else(|| panic!("no page at {url}"));
+            let built = crate::build::render_page(page, &app_data).await.unwrap();
+
+            let request = url.trim_matches('/');
+            let Ok(PageResponse::Page(served)) = render_requested_page(request, &app_data, "", &DepRecorder::default()).await else {
+                panic!("dev didn't render {url}");
+            };
+
+            assert_eq!(base_of(&built), base, "build base for {url}");
+            assert_eq!(base_of(&served), base, "dev base for {url}");
+            assert!(built.contains(r#"src="img/diagram.png""#) && served.contains(r#"src="img/diagram.png""#));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_streamed_page_matches_the_rendered_one() {
+        let site = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(site.path().join("_")).unwrap();
+        let archive: String = (0..2000).map(|i| format!("- Entry {} & <more>\n", i)).collect();
+        for (file, content) in [
+            ("_/header.md", "# Site"),
+            ("_/nav.md", "[Home](/)"),
+            ("_/footer.md", "Bye"),
+            ("_/theme.css", "body {}"),
+            ("archive.md", &format!("---\ntitle: Archive\n---\n{}", archive)),
+        ] {
+            std::fs::write(site.path().join(file), content).unwrap();
+        }
+        let app_data = AppData::load(site.path().to_path_buf(), "build").await.unwrap();
+        let page = page_for_request(&app_data.pages, "/archive").unwrap();
+
+        let rendered = crate::build::prepare_page(page, &app_data).await.unwrap().render(&app_data).unwrap();
+        app_data.lock_page_deps().clear();
+
+        let mut streamed = Vec::new();
+        let prepared = crate::build::prepare_page(page, &app_data).await.unwrap();
+        assert!(prepared.content_len() > 2000 * "Entry".len());
+        prepared.render_to(&app_data, &mut streamed).unwrap();
+
+        assert_eq!(String::from_utf8(streamed).unwrap(), rendered);
+        assert!(app_data.lock_page_deps().contains_key("/archive"));
+    }
+
+    #[tokio::test]
+    async fn test_rendering_uses_the_files_the_scan_read() {
+        let site = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(site.path().join("_")).unwrap();
+        std::fs::create_dir_all(site.path().join("posts")).unwrap();
+        for (file, content) in [
+            ("_/header.md", "# Site"),
+            ("_/nav.md", "[Home](/)"),
+            ("_/footer.md", ""),
+            ("_/theme.css", "body {}"),
+            ("posts/[n].md", "---\ntitle: Part {{ n }}\nn: [1, 2]\n---\nPart {{ n }} as scanned"),
+        ] {
+            std::fs::write(site.path().join(file), content).unwrap();
+        }
+        for i in 0..1000 {
+            std::fs::write(site.path().join(format!("posts/p{}.md", i)), format!("---\ntitle: Post {}\n---\nScanned {}", i, i)).unwrap();
+        }
+        let mut app_data = AppData::load(site.path().to_path_buf(), "build").await.unwrap();
+        assert_eq!(app_data.page_sources.len(), 1001);
+
+        // With the page files gone, every page still renders from what the scan read
+        std::fs::remove_dir_all(site.path().join("posts")).unwrap();
+        for page in app_data.pages.iter() {
+            let html = crate::build::render_page(page, &app_data).await.unwrap();
+            assert!(html.contains("as scanned") || html.contains("Scanned"), "{}", page.url);
+        }
+
+        std::fs::create_dir(site.path().join("posts")).unwrap();
+        std::fs::write(site.path().join("posts/p7.md"), "---\ntitle: Post 7\n---\nEdited").unwrap();
+        app_data.source_reads = SourceReads::Fresh;
+        let (_, html, _, _) = resolve_path_to_doc("posts/p7", &app_data, &DepRecorder::default()).await.unwrap().unwrap();
+        assert!(html.contains("Edited"), "{}", html);
+    }
+
+    async fn transliterated_site(files: &[(&str, &str)]) -> (tempfile::TempDir, Result<AppData>) {
+        let site = tempfile::tempdir().unwrap();
+        for (file, content) in [
+            ("config.toml", "[site]\nurl_transliteration = \"ascii\"\n"),
+            ("_/header.md", "# Site"),
+            ("_/nav.md", "[Home](/)"),
+            ("_/footer.md", ""),
+            ("_/theme.css", "body {}"),
+        ]
+        .iter()
+        .chain(files)
+        {
+            let path = site.path().join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        }
+        let app_data = AppData::load(site.path().to_path_buf(), "dev").await;
+        (site, app_data)
+    }
+
+    #[tokio::test]
+    async fn test_ascii_urls_serve_their_pages() {
+        let (_site, app_data) = transliterated_site(&[
+            ("Über uns.md", "---\ntitle: Über uns\n---\nHallo"),
+            ("ブログ/最初.md", "---\ntitle: 最初\n---\nFirst"),
+            ("städte/[stadt].md", "---\ntitle: \"{{ stadt }}\"\nstadt: [\"Zürich\", \"Köln\"]\n---\nIn {{ stadt }}"),
+        ])
+        .await;
+        let app_data = app_data.unwrap();
+
+        let mut urls: Vec<&str> = app_data.pages.iter().map(|page| page.url.as_str()).collect();
+        urls.sort();
+        assert_eq!(urls, ["/burogu/zui-chu", "/stadte/koln", "/stadte/zurich", "/uber-uns"]);
+
+        for (request, text) in [("uber-uns", "Hallo"), ("burogu/zui-chu", "First"), ("stadte/zurich", "In Zürich")] {
+            let Ok(PageResponse::Page(html)) = render_requested_page(request, &app_data, "", &DepRecorder::default()).await else {
+                panic!("dev didn't render {request}");
+            };
+            assert!(html.contains(text), "{html}");
+        }
+        // The build doesn't write the names as they're spelled in the files, so neither does dev
+        for request in ["Über uns", "ブログ/最初", "städte/Zürich"] {
+            let response = render_requested_page(request, &app_data, "", &DepRecorder::default()).await;
+            assert!(matches!(response, Ok(PageResponse::NotFound(_))), "dev served {request}");
+        }
+
+        let page = page_for_request(&app_data.pages, "/uber-uns").unwrap();
+        let built = crate::build::render_page(page, &app_data).await.unwrap();
+        assert!(built.contains("<title>Über uns</title>") && built.contains("Hallo"), "{built}");
+    }
+
+    #[tokio::test]
+    async fn test_ascii_url_collisions_name_both_pages() {
+        let (_site, app_data) =
+            transliterated_site(&[("Café.md", "---\ntitle: Café\n---\n"), ("cafe.md", "---\ntitle: Cafe\n---\n")]).await;
+        match app_data {
+            Err(HugsError::UrlCollision { url, page, other }) => {
+                assert_eq!(url.0, "/cafe");
+                let mut names = [page, other];
+                names.sort();
+                assert_eq!(names, ["Café.md", "cafe.md"]);
+            }
+            other => panic!("expected a UrlCollision, got: {:?}", other.err()),
+        }
+
+        let (_site, app_data) = transliterated_site(&[("tags/[tag].md", "---\ntitle: Tag\ntag: [\"Ärger\", \"arger\"]\n---\n")]).await;
+        assert!(matches!(
+            app_data,
+            Err(HugsError::UrlCollision { page, other, .. }) if page.contains("tag = ") && other.contains("tag = ")
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_nav_page_tokens_fill_in_each_page() {
+        let site = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(site.path().join("_")).unwrap();
+        std::fs::create_dir_all(site.path().join("tags")).unwrap();
+        for (file, content) in [
+            ("_/header.md", "# Site"),
+            ("_/nav.md", "[Edit {{ page_token(\"title\") }}](https://github.com/me/site/edit/main/{{ page_token(\"source\") }})"),
+            ("_/footer.md", "You're on {{ page_token(\"url\") }}"),
+            ("_/theme.css", "body {}"),
+            ("about.md", "---\ntitle: About & more\n---\nHi from [{{ page_token(\"source\") }}](https://github.com/me/site/blob/main/{{ page_token(\"source\") }})"),
+            ("tags/[tag].md", "---\ntitle: \"Tag {{ tag }}\"\ntag: [rust]\n---\nTagged"),
+            ("[404].md", "---\ntitle: Lost\n---\nNope, {{ page_token(\"title\") }}"),
+        ] {
+            std::fs::write(site.path().join(file), content).unwrap();
+        }
+        let app_data = AppData::load(site.path().to_path_buf(), "build").await.unwrap();
+
+        let about = crate::build::render_page(page_for_request(&app_data.pages, "/about").unwrap(), &app_data).await.unwrap();
+        assert!(about.contains(r#"<a href="https://github.com/me/site/edit/main/about.md">Edit About &amp; more</a>"#), "{about}");
+        assert!(about.contains("You're on /about"), "{about}");
+        assert!(about.contains(r#"Hi from <a href="https://github.com/me/site/blob/main/about.md">about.md</a>"#), "{about}");
+
+        let tag = crate::build::render_page(page_for_request(&app_data.pages, "/tags/rust").unwrap(), &app_data).await.unwrap();
+        assert!(tag.contains(r#"<a href="https://github.com/me/site/edit/main/tags/%5Btag%5D.md">Edit Tag rust</a>"#), "{tag}");
+
+        let notfound = render_notfound_page(&app_data, "").await.unwrap();
+        assert!(notfound.contains("Edit Lost") && notfound.contains("Nope, Lost") && !notfound.contains("HUGS_PAGE_"), "{notfound}");
+    }
+
+    #[tokio::test]
+    async fn test_404_links_start_at_the_root() {
+        let (_site, app_data) = transliterated_site(&[
+            ("_/content.md", "{% if is_notfound %}<p>Missing:</p>{% endif %}\n\n{{ content }}\n\n[up](../index)"),
+            ("[404].md", "---\ntitle: Lost\n---\n[home](index) ![cat](img/cat.png) [docs](/docs#top){% if is_notfound %} lost{% endif %}"),
+            ("about.md", "---\ntitle: About\n---\nNot found: {{ is_notfound }}"),
+        ])
+        .await;
+        let app_data = app_data.unwrap();
+
+        let notfound = render_notfound_page(&app_data, "").await.unwrap();
+        for expected in ["<p>Missing:</p>", r#"<a href="/index">home</a>"#, r#"src="/img/cat.png""#, r#"<a href="/docs#top">docs</a>"#, " lost", r#"<a href="/index">up</a>"#] {
+            assert!(notfound.contains(expected), "{expected} in {notfound}");
+        }
+
+        let about = crate::build::render_page(page_for_request(&app_data.pages, "/about").unwrap(), &app_data).await.unwrap();
+        assert!(about.contains("Not found: False") && !about.contains("Missing:"), "{about}");
+    }
+
+    #[tokio::test]
+    async fn test_private_frontmatter_stays_on_its_page() {
+        let site = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(site.path().join("_")).unwrap();
+        std::fs::create_dir_all(site.path().join("blog")).unwrap();
+        for (file, content) in [
+            (
+                "config.toml",
+                "[site]\nurl = \"https://example.com\"\n[build]\nprivate_frontmatter_keys = [\"internal_notes\"]\n\
+                 [[feeds]]\nname = \"blog\"\nsource = \"/blog\"\noutput_rss = \"feed.xml\"\nitem_template = \"item.md\"\n",
+            ),
+            ("_/header.md", "# Site"),
+            ("_/nav.md", "[Home](/)"),
+            ("_/footer.md", ""),
+            ("_/theme.css", "body {}"),
+            ("_/item.md", "{{ title }}: {{ internal_notes }} {{ _todo }}"),
+            ("index.md", "---\ntitle: Home\n---\n{% for p in pages() %}{{ p.title }} {{ p.internal_notes }} {{ p._todo }} {% for key, value in p | items %}{{ key }}={{ value }} {% endfor %}\n{% endfor %}"),
+            ("blog/post.md", "---\ntitle: Post\ndate: 2024-01-01\ninternal_notes: NOTES-SECRET\n_todo: TODO-SECRET\n---\n{{ internal_notes }} {{ _todo }}"),
+            ("blog/[n].md", "---\ntitle: Part {{ n }}\nn: [1]\n_draft_of: DRAFT-SECRET\n---\n{{ _draft_of }}"),
+        ] {
+            std::fs::write(site.path().join(file), content).unwrap();
+        }
+
+        let app_data = AppData::load(site.path().to_path_buf(), "dev").await.unwrap();
+        let Ok(PageResponse::Page(post)) = render_requested_page("blog/post", &app_data, "", &DepRecorder::default()).await else {
+            panic!("dev didn't render the post");
+        };
+        assert!(post.contains("NOTES-SECRET TODO-SECRET"), "{post}");
+        let Ok(PageResponse::Page(part)) = render_requested_page("blog/1", &app_data, "", &DepRecorder::default()).await else {
+            panic!("dev didn't render the dynamic page");
+        };
+        assert!(part.contains("DRAFT-SECRET"), "{part}");
+        let Ok(PageResponse::Page(home)) = render_requested_page("", &app_data, "", &DepRecorder::default()).await else {
+            panic!("dev didn't render the home page");
+        };
+        assert!(home.contains("Post") && !home.contains("SECRET"), "{home}");
+
+        let output = tempfile::tempdir().unwrap();
+        crate::build::run_build(site.path().to_path_buf(), output.path().join("dist"), crate::build::BuildOptions::default()).await.unwrap();
+        for entry in walkdir::WalkDir::new(output.path()).into_iter().filter_map(|e| e.ok()).filter(|e| e.file_type().is_file()) {
+            let written = std::fs::read_to_string(entry.path()).unwrap_or_default();
+            let owner = ["blog/post/index.html", "blog/1/index.html"].iter().any(|owner| entry.path().ends_with(owner));
+            assert_eq!(written.contains("SECRET"), owner, "{}", entry.path().display());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_folder_content_templates() {
+        let site = tempfile::tempdir().unwrap();
+        for dir in ["_", "blog/2024", "docs", "drafts"] {
+            std::fs::create_dir_all(site.path().join(dir)).unwrap();
+        }
+        for (file, content) in [
+            ("config.toml", ""),
+            ("_/header.md", "# Site"),
+            ("_/nav.md", "[Home](/)"),
+            ("_/footer.md", "Footer"),
+            ("_/theme.css", "body {}"),
+            ("_/content.md", "SITE-WRAP {{ content }}"),
+            ("index.md", "---\ntitle: Home\n---\nHome body"),
+            ("blog/_content.md", "BLOG-WRAP by {{ author }} {{ content }}"),
+            ("blog/2024/recap.md", "---\ntitle: Recap\nauthor: Sam\n---\nRecap body"),
+            ("blog/[n].md", "---\ntitle: Part {{ n }}\nn: [1]\nauthor: Kim\n---\nPart body"),
+            ("docs/_content.md", "DOCS-WRAP {{ content | nope }}"),
+            ("docs/intro.md", "---\ntitle: Intro\n---\nIntro body"),
+            // No page is under it, so it's never read
+            ("drafts/_content.md", "{{ main_content }}"),
+        ] {
+            std::fs::write(site.path().join(file), content).unwrap();
+        }
+
+        let app_data = AppData::load(site.path().to_path_buf(), "dev").await.unwrap();
+        assert!(!app_data.pages.iter().any(|page| page.file_path.ends_with("_content.md")));
+
+        let render = |path: &'static str| {
+            let app_data = &app_data;
+            async move { render_requested_page(path, app_data, "", &DepRecorder::default()).await }
+        };
+        let Ok(PageResponse::Page(home)) = render("").await else {
+            panic!("the home page didn't render");
+        };
+        assert!(home.contains("SITE-WRAP") && !home.contains("BLOG-WRAP"), "{home}");
+        let Ok(PageResponse::Page(recap)) = render("blog/2024/recap").await else {
+            panic!("the post didn't render");
+        };
+        assert!(recap.contains("BLOG-WRAP by Sam") && !recap.contains("SITE-WRAP"), "{recap}");
+        let Ok(PageResponse::Page(part)) = render("blog/1").await else {
+            panic!("the dynamic page didn't render");
+        };
+        assert!(part.contains("BLOG-WRAP by Kim"), "{part}");
+
+        let Err(HugsError::TemplateRender { file, .. }) = render("docs/intro").await else {
+            panic!("docs/_content.md should have failed");
+        };
+        assert!(file.to_string().contains("docs/_content.md"), "{file}");
+    }
+
+    #[tokio::test]
+    async fn test_required_file_that_is_a_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let theme_path = dir.path().join("theme.css");
+        std::fs::create_dir(&theme_path).unwrap();
+
+        assert!(matches!(
+            read_required_file(&theme_path, "theme stylesheet", "_/theme.css").await,
+            Err(HugsError::RequiredFileIsDirectory { .. })
+        ));
+    }
+
+    #[test]
+    fn test_content_template_uses_content() {
+        use crate::content_templates::content_template_uses_content;
+
+        assert!(content_template_uses_content("{{ content }}"));
+        assert!(content_template_uses_content("<article>\n{{ content | safe }}\n</article>"));
+        assert!(content_template_uses_content("{% if title %}# {{ title }}{% endif %}\n{{content}}"));
+        assert!(!content_template_uses_content("# {{ title }}\n\nNothing else here"));
+    }
+
+    #[tokio::test]
+    async fn test_content_nests_one_way() {
+        let (_site, app_data) = transliterated_site(&[
+            ("_/content.md", "<div class=\"wrap\">\n\n{{ content }}\n\n</div>"),
+            ("index.md", "---\ntitle: Home\n---\nHOME-BODY"),
+            // A page can't print itself, so both are empty in its body
+            ("empty.md", "---\ntitle: Empty\n---\nBEFORE{{ content }}{{ main_content }}AFTER"),
+            ("twice/_content.md", "{{ content }}\n\n{{ content }}"),
+            ("twice/post.md", "---\ntitle: Post\n---\nTWICE-BODY"),
+        ])
+        .await;
+        let app_data = app_data.unwrap();
+        let render = |path: &'static str| {
+            let app_data = &app_data;
+            async move {
+                match render_requested_page(path, app_data, "", &DepRecorder::default()).await {
+                    Ok(PageResponse::Page(html)) => html,
+                    _ => panic!("{path} didn't render"),
+                }
+            }
+        };
+
+        // The body goes through the content template into the root template once
+        let home = render("").await;
+        assert_eq!(home.matches("HOME-BODY").count(), 1, "{home}");
+        assert!(home.contains("<div class=\"wrap\">"), "{home}");
+        let empty = render("empty").await;
+        assert!(empty.contains("BEFOREAFTER"), "{empty}");
+        // Printing content twice is allowed, with a warning, and does what it says
+        let post = render("twice/post").await;
+        assert_eq!(post.matches("TWICE-BODY").count(), 2, "{post}");
+
+        let (_site, app_data) = transliterated_site(&[
+            ("_/content.md", "{{ content }}\n\n{% if main_content %}{{ main_content }}{% endif %}"),
+            ("index.md", "---\ntitle: Home\n---\nHome"),
+        ])
+        .await;
+        let Err(HugsError::ContentTemplateUsesMainContent { file, span, .. }) = app_data else {
+            panic!("main_content in _/content.md should fail to load");
+        };
+        assert!(file.to_string().contains("_/content.md"), "{file}");
+        assert_eq!(span.offset(), 21);
+    }
+
+    #[tokio::test]
+    async fn test_gitignored_files_stay_out_of_the_site() {
+        let (site, app_data) = transliterated_site(&[
+            ("config.toml", "[build]\nrespect_gitignore = true\n"),
+            (".gitignore", "*.draft.md\ntmp/\n_/theme.css\n"),
+            ("_/theme.css", "body { color: rebeccapurple }"),
+            ("index.md", "---\ntitle: Home\n---\nHome"),
+            ("ideas.draft.md", "---\ntitle: Ideas\n---\nNot yet"),
+            ("tmp/scratch.md", "---\ntitle: Scratch\n---\nScratch"),
+            ("tmp/cache.json", "{}"),
+            ("blog/.gitignore", "secret.md\n"),
+            ("blog/secret.md", "---\ntitle: Secret\n---\nShh"),
+            ("blog/post.md", "---\ntitle: Post\n---\nPost"),
+            ("logo.txt", "logo"),
+        ])
+        .await;
+        let app_data = app_data.unwrap();
+
+        let mut urls: Vec<String> = app_data.pages.iter().map(|page| page.url.to_string()).collect();
+        urls.sort();
+        assert_eq!(urls, ["/", "/blog/post"]);
+        // `_/` is loaded even when it's ignored
+        assert!(app_data.theme_css.contains("rebeccapurple"));
+
+        // Dev serves neither ignored pages nor ignored files
+        for path in ["ideas.draft", "blog/secret", "tmp/scratch"] {
+            let response = render_requested_page(path, &app_data, "", &DepRecorder::default()).await;
+            assert!(matches!(response, Ok(PageResponse::NotFound(_))), "{path} shouldn't be served");
+        }
+        assert!(try_serve_static_file("tmp/cache.json", &app_data).await.is_none());
+        assert!(try_serve_static_file("logo.txt", &app_data).await.is_some());
+
+        let output = tempfile::tempdir().unwrap();
+        let dist = output.path().join("dist");
+        crate::build::run_build(site.path().to_path_buf(), dist.clone(), crate::build::BuildOptions::default()).await.unwrap();
+        assert!(dist.join("blog/post/index.html").exists() && dist.join("logo.txt").exists());
+        for built in ["ideas.draft/index.html", "blog/secret/index.html", "tmp"] {
+            assert!(!dist.join(built).exists(), "{built} shouldn't be built");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_url_styles_link_to_the_files_they_write() {
+        /// Every href on the built pages, and every URL in the sitemap and feed, on this site
+        fn link_targets(dist: &Path, pages: &[&str]) -> Vec<String> {
+            let mut targets = Vec::new();
+            let mut between = |text: &str, open: &str, close: &str| {
+                for (start, _) in text.match_indices(open) {
+                    let rest = &text[start + open.len()..];
+                    let target = &rest[..rest.find(close).unwrap()];
+                    targets.push(target.trim_start_matches("https://example.com").to_string());
+                }
+            };
+            for page in pages {
+                between(&std::fs::read_to_string(dist.join(page)).unwrap(), "href=\"", "\"");
+            }
+            between(&std::fs::read_to_string(dist.join("sitemap.xml")).unwrap(), "<loc>", "</loc>");
+            between(&std::fs::read_to_string(dist.join("rss.xml")).unwrap(), "<link>", "</link>");
+            targets.retain(|target| target.starts_with('/') && !target.ends_with(".css") && !target.ends_with(".xml"));
+            targets.sort();
+            targets.dedup();
+            targets
+        }
+
+        let mut built = Vec::new();
+        for (style, pages) in [
+            ("directory", ["index.html", "blog/index.html", "about/index.html", "legacy/index.html"]),
+            ("html", ["index.html", "blog/index.html", "about.html", "legacy.html"]),
+        ] {
+            let config = format!(
+                "[site]\ntitle = \"Site\"\nurl = \"https://example.com\"\n\n[build]\nminify = false\nurl_style = \"{style}\"\n\n[[feeds]]\nname = \"blog\"\nsource = \"/blog\"\noutput_rss = \"rss.xml\"\n"
+            );
+            let (site, app_data) = transliterated_site(&[
+                ("config.toml", &config),
+                ("index.md", "---\ntitle: Home\n---\n[About](/about) {% for page in pages() %}[{{ page.title }}]({{ page.url }}) {% endfor %}"),
+                ("about.md", "---\ntitle: About\naliases: [old-about]\n---\nAbout"),
+                ("blog/index.md", "---\ntitle: Blog\nauto_list: true\n---\nPosts"),
+                ("blog/first.md", "---\ntitle: First\ndate: 2024-01-01\n---\nFirst"),
+                ("tags/[tag].md", "---\ntitle: \"{{ tag }}\"\ntag: [rust]\n---\nTagged"),
+                // A page and a static file at the same `.html`, which only collide in the html style
+                ("legacy.md", "---\ntitle: Legacy\n---\nRendered"),
+                ("legacy.html", "<p>Exported</p>"),
+            ])
+            .await;
+            let app_data = app_data.unwrap();
+
+            // Dev serves pages at either form of their URL
+            for path in ["about", "about.html", "about/index.html", "tags/rust", "tags/rust.html"] {
+                let Route::Page(page_path) = route_request(path, &app_data).await else {
+                    panic!("{path} should be a page in the {style} style");
+                };
+                let response = render_requested_page(page_path, &app_data, "", &DepRecorder::default()).await;
+                assert!(matches!(response, Ok(PageResponse::Page(_))), "{path} should render in the {style} style");
+            }
+            let legacy_is_static = matches!(route_request("legacy.html", &app_data).await, Route::File(_));
+            assert_eq!(legacy_is_static, style == "directory", "legacy.html in the {style} style");
+            // The build only knows the page by its URL in the site's style
+            let other_style = if style == "html" { "about" } else { "about.html" };
+            assert!(page_for_request(&app_data.pages, other_style).is_none(), "{other_style} in the {style} style");
+
+            let output = tempfile::tempdir().unwrap();
+            let dist = output.path().join("dist");
+            crate::build::run_build(site.path().to_path_buf(), dist.clone(), crate::build::BuildOptions::default()).await.unwrap();
+
+            // The page wins the html style's collision, as it does in dev
+            let legacy = std::fs::read_to_string(dist.join("legacy.html")).unwrap();
+            assert_eq!(legacy.contains("Exported"), style == "directory", "legacy.html in the {style} style");
+            let redirect = if style == "html" { "old-about.html" } else { "old-about/index.html" };
+            assert!(dist.join(redirect).is_file(), "{redirect} in the {style} style");
+
+            // Directory indexes, and their canonical URLs without the slash, are index.html either way
+            let targets = link_targets(&dist, &pages);
+            for target in &targets {
+                let file = dist.join(target.trim_start_matches('/'));
+                let written = file.is_file() || file.join("index.html").is_file();
+                assert!(written || target == "/about", "{target} links to nothing in the {style} style");
+            }
+            built.push(targets);
+        }
+
+        // The same links, with every page that isn't an index (or `/about`, written by hand)
+        // turned into its `.html` file, and the sitemap's `/about/` with it
+        let (directory, html) = (&built[0], &built[1]);
+        assert!(directory.contains(&String::from("/blog/first")) && directory.contains(&String::from("/tags/rust/")), "{directory:?}");
+        let mut expected: Vec<String> = directory
+            .iter()
+            .map(|target| match ["/", "/about", "/blog", "/blog/"].contains(&target.as_str()) {
+                true => target.clone(),
+                false => format!("{}.html", target.trim_end_matches('/')),
+            })
+            .collect();
+        expected.sort();
+        expected.dedup();
+        assert_eq!(html, &expected);
+    }
+
+    #[tokio::test]
+    async fn test_code_languages_gather_every_pages_fences() {
+        let (_site, app_data) = transliterated_site(&[
+            ("config.toml", "[build.syntax_highlighting]\naliases = { conf = \"ini\" }\n"),
+            ("index.md", "---\ntitle: Home\n---\n{% for lang in code_languages() %}{{ lang.name }}={{ lang.blocks }}/{{ lang.pages | length }}/{{ lang.supported }};{% endfor %}"),
+            ("blog/one.md", "---\ntitle: One\n---\n```Rust\nfn a() {}\n```\n\n```rust\nfn b() {}\n```\n\n```rsut\nfn c() {}\n```\n"),
+            ("blog/two.md", "---\ntitle: Two\n---\n```golang\nfunc a() {}\n```\n\n```conf\na = 1\n```\n\n```\nplain\n```\n"),
+            ("tags/[tag].md", "---\ntitle: Tag\ntag: [a, b, c]\n---\n```rust\nfn tagged() {}\n```\n"),
+        ])
+        .await;
+        let app_data = app_data.unwrap();
+
+        let languages = &app_data.code_languages.languages;
+        let rust = &languages[0];
+        assert_eq!((rust.name.as_str(), rust.blocks), ("rust", 3));
+        // The dynamic page's block counts once, but each of its pages is listed
+        assert_eq!(rust.pages, ["/blog/one", "/tags/a", "/tags/b", "/tags/c"]);
+        let summary: Vec<(&str, usize, bool, Option<&str>)> =
+            languages.iter().map(|lang| (lang.name.as_str(), lang.blocks, lang.supported, lang.grammar.as_deref())).collect();
+        assert_eq!(
+            summary,
+            [
+                ("rust", 3, true, Some("rust")),
+                ("conf", 1, true, Some("ini")),
+                ("golang", 1, true, Some("go")),
+                ("rsut", 1, false, None),
+            ]
+        );
+
+        let Ok(PageResponse::Page(home)) = render_requested_page("", &app_data, "", &DepRecorder::default()).await else {
+            panic!("the home page should render");
+        };
+        assert!(home.contains("rust=3/4/True;conf=1/1/True;golang=1/1/True;rsut=1/1/False;"), "{home}");
+    }
+
+    #[test]
+    fn test_render_depth_stops_runaway_nesting() {
+        let held: Vec<RenderDepth> = (0..MAX_RENDER_DEPTH).map(|_| RenderDepth::enter().unwrap()).collect();
+        let Err(err) = RenderDepth::enter() else {
+            panic!("rendering past the limit should fail");
+        };
+        assert!(err.to_string().contains("more than 8 deep"), "{err}");
+
+        drop(held);
+        assert_eq!(RENDER_DEPTH.with(Cell::get), 0);
+        assert!(RenderDepth::enter().is_ok());
+    }
+
+    #[test]
+    fn test_spaceless_keeps_preformatted_content() {
+        let html = "<ul>\n  <li>One</li>\n  <li>Two words</li>\n</ul>\n<pre><code>fn main() {\n    <b>x</b> <i>y</i>\n}</code></pre>\n<p> text </p>";
+        assert_eq!(
+            collapse_whitespace_between_tags(html),
+            "<ul><li>One</li><li>Two words</li></ul><pre><code>fn main() {\n    <b>x</b> <i>y</i>\n}</code></pre><p> text </p>"
+        );
+
+        let html = "<div>\n<script>\nif (a < b) {\n  x = \"<p> </p>\";\n}\n</script>\n<STYLE>\np > a { }\n</STYLE>\n<p>after</p>\n</div>";
+        assert_eq!(
+            collapse_whitespace_between_tags(html),
+            "<div><script>\nif (a < b) {\n  x = \"<p> </p>\";\n}\n</script><STYLE>\np > a { }\n</STYLE><p>after</p></div>"
+        );
+    }
+
+    #[test]
+    fn test_whitespace_control_is_opt_in() {
+        let template = "<ul>\n  {% for i in [1, 2] %}\n  <li>{{ i }}</li>\n  {% endfor %}\n</ul>";
+        let render = |template_config: TemplateConfig| {
+            let config = SiteConfig { template: template_config, ..Default::default() };
+            render_bare(template, minijinja::context! {}, &config, None, "")
+        };
+
+        assert_eq!(
+            render(TemplateConfig::default()),
+            "<ul>\n  \n  <li>1</li>\n  \n  <li>2</li>\n  \n</ul>"
+        );
+
+        let trimmed = TemplateConfig {
+            trim_blocks: true,
+            lstrip_blocks: true,
+            ..Default::default()
+        };
+        assert_eq!(render(trimmed), "<ul>\n  <li>1</li>\n  <li>2</li>\n</ul>");
+    }
+
+    #[test]
+    fn test_dynamic_param_values_stay_inside_the_output_dir() {
+        let evaluate = |file_content: &str, param_chars: ParamCharset| {
+            let (frontmatter, _) = markdown_frontmatter::parse::<YamlValue>(file_content).unwrap();
+            evaluate_param_values_with_pages(
+                "tag",
+                &frontmatter,
+                Path::new("tags/[tag].md"),
+                &Arc::new(vec![]),
+                file_content,
+                param_chars,
+            )
+        };
+
+        // Values that would escape or break the URL are rejected, pointing at the value
+        for (content, bad) in [
+            ("---\ntag: [\"rust\", \"../../etc\"]\n---\n", "../../etc"),
+            ("---\ntag: [\"..\"]\n---\n", ".."),
+            ("---\ntag: [\"what?\"]\n---\n", "what?"),
+            ("---\ntag: \"{{ ['ok', 'a/b'] }}\"\n---\n", "a/b"),
+        ] {
+            match evaluate(content, ParamCharset::Unicode) {
+                Err(HugsError::DynamicParamInvalid { value, span, .. }) => {
+                    assert_eq!(value, bad);
+                    assert!(content[span.offset()..span.offset() + span.len()].contains(bad));
+                }
+                other => panic!("Expected DynamicParamInvalid for {:?}, got: {:?}", bad, other.err()),
+            }
+        }
+
+        // Awkward but harmless values are fine, and land in their own folder
+        let content = "---\ntag: [\"hello world\", \"café\", \"🎉\"]\n---\n";
+        let values = evaluate(content, ParamCharset::Unicode).unwrap();
+        for value in &values {
+            let url = generate_dynamic_url(Path::new("tags/[tag].md"), "tag", value, UrlTransliteration::Keep);
+            let output = url.output_path();
+            assert_eq!(output.components().count(), 3, "{} should be written to tags/<value>/index.html", url);
+            assert!(output.components().all(|c| matches!(c, std::path::Component::Normal(_))));
+        }
+
+        // ...unless the site asks for plain ASCII
+        assert!(matches!(
+            evaluate(content, ParamCharset::Ascii),
+            Err(HugsError::DynamicParamInvalid { value, .. }) if value == "hello world"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_first_is_index_puts_the_first_value_at_the_folder() {
+        let site = tempfile::tempdir().unwrap();
+        let write = |file: &str, content: &str| {
+            let path = site.path().join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        };
+        for (file, content) in [
+            ("config.toml", "[site]\nurl = \"https://example.com\""),
+            ("_/header.md", "# Site"),
+            ("_/nav.md", "[Home](/)"),
+            ("_/footer.md", ""),
+            ("_/theme.css", "body {}"),
+            ("[page_no].md", "---\ntitle: Page {{ page_no }}\npage_no: [1, 2, 3]\nfirst_is_index: true\n---\nHome page {{ page_no }}: {% for p in pages() %}{{ p.url }} {% endfor %}"),
+            ("blog/[page_no].md", "---\ntitle: Blog {{ page_no }}\npage_no: [1, 2]\nfirst_is_index: true\n---\nBlog page {{ page_no }}"),
+        ] {
+            write(file, content);
+        }
+
+        let app_data = AppData::load(site.path().to_path_buf(), "dev").await.unwrap();
+        let mut urls: Vec<&str> = app_data.pages.iter().map(|page| page.url.as_str()).collect();
+        urls.sort();
+        assert_eq!(urls, ["/", "/2", "/3", "/blog/", "/blog/2"]);
+
+        let render = |path: &'static str| {
+            let app_data = &app_data;
+            async move {
+                match render_requested_page(path, app_data, "", &DepRecorder::default()).await {
+                    Ok(PageResponse::Page(html)) => Some(html),
+                    _ => None,
+                }
+            }
+        };
+        let home = render("").await.expect("dev didn't render the root index");
+        assert!(home.contains("Home page 1:") && home.contains("/blog/ /blog/2"), "{home}");
+        assert!(render("2").await.unwrap().contains("Home page 2"));
+        assert!(render("blog/").await.unwrap().contains("Blog page 1"));
+        assert!(render("blog").await.unwrap().contains("Blog page 1"));
+        assert!(render("blog/2").await.unwrap().contains("Blog page 2"));
+        // The first value has no URL of its own
+        assert!(render("1").await.is_none() && render("blog/1").await.is_none());
+
+        let output = tempfile::tempdir().unwrap();
+        let dist = output.path().join("dist");
+        crate::build::run_build(site.path().to_path_buf(), dist.clone(), crate::build::BuildOptions::default()).await.unwrap();
+        assert!(std::fs::read_to_string(dist.join("blog/index.html")).unwrap().contains("Blog page 1"));
+        assert!(std::fs::read_to_string(dist.join("index.html")).unwrap().contains("Home page 1"));
+        assert!(!dist.join("1").exists() && !dist.join("blog/1").exists());
+        let sitemap = std::fs::read_to_string(dist.join("sitemap.xml")).unwrap();
+        assert!(sitemap.contains("<loc>https://example.com/blog/</loc>") && !sitemap.contains("/blog/1"), "{sitemap}");
+
+        // An index.md in the same folder is already the index
+        write("blog/index.md", "---\ntitle: Blog\n---\nAll posts");
+        match AppData::load(site.path().to_path_buf(), "dev").await {
+            Err(HugsError::DynamicIndexConflict { file, other, url }) => {
+                assert!(file.to_string().contains("blog/[page_no].md"));
+                assert!(other.to_string().contains("blog/index.md") && url.to_string().contains("/blog/"));
+            }
+            other => panic!("expected DynamicIndexConflict, got {:?}", other.err()),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_paths_become_slash_separated_urls_and_classes() {
+        let (site, app_data) = transliterated_site(&[
+            ("docs/guide/setup.md", "---\ntitle: Setup\n---\nSetup"),
+            ("docs/[v].md", "---\ntitle: \"{{ v }}\"\nv: [\"one\"]\n---\nVersion {{ v }}"),
+            ("files/a.txt", "a"),
+        ])
+        .await;
+        let app_data = app_data.unwrap();
+
+        // Built from components, so it's spelled with the platform's separator
+        let file: PathBuf = ["docs", "guide", "setup.md"].iter().collect();
+        assert_eq!(convert_path_to_class(&site.path().join(&file), &app_data).unwrap(), "docs guide setup");
+        let dynamic: PathBuf = ["docs", "[v].md"].iter().collect();
+        assert_eq!(generate_dynamic_url(&dynamic, "v", &YamlValue::String("one".into()), UrlTransliteration::Keep), "/docs/one");
+        assert_eq!(dynamic_index_url(&dynamic, UrlTransliteration::Keep), "/docs/");
+
+        let mut files: Vec<&str> = app_data.pages.iter().map(|page| page.file_path.as_str()).collect();
+        files.sort();
+        assert_eq!(files, ["docs/[v].md", "docs/guide/setup.md"]);
+        let (source, _) = match_dynamic_page("docs/one", &app_data).expect("no dynamic match");
+        assert_eq!(source, "docs/[v].md");
+
+        assert!(try_serve_static_file("files/a.txt", &app_data).await.is_some());
+        // Off Windows `\` is an ordinary character, so give each request a file spelled just like it:
+        // only the check on `\` itself keeps these from being served
+        #[cfg(unix)]
+        for file in ["files\\a.txt", "files\\..\\config.toml", "..\\secret"] {
+            std::fs::write(site.path().join(file), "a").unwrap();
+        }
+        for request in ["files\\a.txt", "files\\..\\config.toml", "..\\secret"] {
+            assert!(try_serve_static_file(request, &app_data).await.is_none(), "{request}");
+        }
+    }
+
+    #[cfg(windows)]
+    #[tokio::test]
+    async fn test_windows_separators_never_reach_urls() {
+        let (site, app_data) = transliterated_site(&[("docs/guide/setup.md", "---\ntitle: Setup\n---\nSetup")]).await;
+        let app_data = app_data.unwrap();
+
+        let file = site.path().join(r"docs\guide\setup.md");
+        assert_eq!(convert_path_to_class(&file, &app_data).unwrap(), "docs guide setup");
+        assert_eq!(generate_dynamic_url(Path::new(r"tags\[tag].md"), "tag", &YamlValue::String("rust".into()), UrlTransliteration::Keep), "/tags/rust");
+        assert_eq!(app_data.pages[0].file_path, "docs/guide/setup.md");
+        assert_eq!(app_data.pages[0].url, "/docs/guide/setup");
+    }
+
+    #[tokio::test]
+    async fn test_page_files_over_the_size_limit_are_skipped() {
+        let (site, _) = transliterated_site(&[("index.md", "# Home"), ("notes.md", "# Notes")]).await;
+        // Sparse, so it takes no room on disk and I never read it
+        let log = std::fs::File::create(site.path().join("chat-export.md")).unwrap();
+        log.set_len(11 * 1024 * 1024).unwrap();
+
+        let app_data = AppData::load(site.path().to_path_buf(), "dev").await.unwrap();
+        let urls: Vec<String> = app_data.pages.iter().map(|page| page.url.to_string()).collect();
+        assert_eq!(urls, ["/", "/notes"]);
+
+        // Raising the limit lets it through
+        std::fs::write(site.path().join("config.toml"), "[build]\nmax_page_size_kb = 12000\n").unwrap();
+        let app_data = AppData::load(site.path().to_path_buf(), "dev").await.unwrap();
+        assert_eq!(app_data.pages.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_reload_keeps_the_last_good_nav() {
+        let site = tempfile::tempdir().unwrap();
+        let write = |file: &str, content: &str| {
+            let path = site.path().join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        };
+        for (file, content) in [
+            ("_/header.md", "# Site"),
+            ("_/nav.md", "[Home](/) [Posts](/posts)"),
+            ("_/footer.md", "Bye"),
+            ("_/theme.css", "body {}"),
+            ("index.md", "---\ntitle: Home\n---\nHello"),
+        ] {
+            write(file, content);
+        }
+        let load = || AppData::load(site.path().to_path_buf(), "dev");
+        let reload = |previous| AppData::reload(site.path().to_path_buf(), "dev", previous);
+
+        let good = load().await.unwrap();
+        assert!(good.chrome_errors.is_empty());
+
+        // Saved halfway through an edit
+        write("_/nav.md", "[Home](/) [Posts](/posts) {{ pages( }}");
+        assert!(load().await.is_err(), "a first load still needs every part to render");
+        let broken = reload(&good).await.unwrap();
+        assert_eq!(broken.nav_html, good.nav_html);
+        assert_eq!(broken.header_html, good.header_html);
+        assert_eq!(broken.chrome_errors.len(), 1);
+        assert!(format!("{:?}", miette::Report::new(broken.chrome_errors[0].clone())).contains("_/nav.md"));
+
+        // Another file changes while the nav is still broken
+        write("index.md", "---\ntitle: Home\n---\nHello again");
+        write("_/footer.md", "See you");
+        let still_broken = reload(&broken).await.unwrap();
+        assert_eq!(still_broken.nav_html, good.nav_html);
+        assert!(still_broken.footer_html.contains("See you"));
+        assert_eq!(still_broken.chrome_errors.len(), 1);
+
+        write("_/nav.md", "[Home](/) [About](/about)");
+        let fixed = reload(&still_broken).await.unwrap();
+        assert!(fixed.nav_html.contains("About"), "{}", fixed.nav_html);
+        assert!(fixed.chrome_errors.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_inline_svg_in_pages_and_nav() {
+        let site = tempfile::tempdir().unwrap();
+        for (file, content) in [
+            ("_/header.md", "# Site"),
+            ("_/nav.md", "{{ inline_svg(path=\"/icons/logo.svg\", class=\"logo\") }} [Home](/)"),
+            ("_/footer.md", "Bye"),
+            ("_/theme.css", "body {}"),
+            ("icons/logo.svg", "<?xml version=\"1.0\"?>\n<svg viewBox=\"0 0 1 1\"><circle r=\"1\"/></svg>\n"),
+            ("index.md", "---\ntitle: Home\n---\nHi {{ inline_svg(path=\"icons/logo.svg\", label=\"Logo\") }}"),
+            ("broken.md", "---\ntitle: Broken\n---\nHi\n\n{{ inline_svg(path=\"/icons/gone.svg\") }}"),
+        ] {
+            let path = site.path().join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        }
+        let app_data = AppData::load(site.path().to_path_buf(), "build").await.unwrap();
+        assert!(app_data.nav_html.contains(r#"<svg viewBox="0 0 1 1" class="logo" aria-hidden="true" focusable="false"><circle"#), "{}", app_data.nav_html);
+
+        let deps = DepRecorder::default();
+        let (_, html, _, _) = resolve_path_to_doc("", &app_data, &deps).await.unwrap().unwrap();
+        assert!(html.contains(r#"Hi <svg viewBox="0 0 1 1" role="img" aria-label="Logo">"#), "{}", html);
+        assert!(!html.contains("<?xml"));
+        assert!(deps.snapshot().files.contains(Path::new("icons/logo.svg")));
+
+        let Err(HugsError::TemplateRender { span, reason, .. }) = resolve_path_to_doc("broken", &app_data, &DepRecorder::default()).await else {
+            panic!("a missing SVG should fail the page");
+        };
+        assert!(reason.contains("couldn't read /icons/gone.svg"), "{reason}");
+        assert!(span.offset() > 0, "the error should point at the call");
+    }
+
+    #[tokio::test]
+    async fn test_dev_only_content_is_left_out_of_builds() {
+        let (site, _) = transliterated_site(&[
+            ("_/nav.md", "[Home](/){% if hugs.dev %} [Scratch](/scratch){% endif %}"),
+            ("index.md", "---\ntitle: Home\n---\nPublished.\n\n{% if hugs.dev %}Experiment in progress.{% endif %}\n\nMode: {{ hugs.mode }}"),
+        ])
+        .await;
+
+        for (command, mode, dev) in [("dev", RenderMode::Dev, true), ("build", RenderMode::Build, false), ("doc", RenderMode::Build, false)] {
+            let app_data = AppData::load(site.path().to_path_buf(), command).await.unwrap();
+            assert_eq!(app_data.mode, mode);
+            assert_eq!(app_data.nav_html.contains("/scratch"), dev, "{command}: {}", app_data.nav_html);
+
+            let (_, html, _, _) = resolve_path_to_doc("", &app_data, &DepRecorder::default()).await.unwrap().unwrap();
+            assert!(html.contains("Published."), "{html}");
+            assert_eq!(html.contains("Experiment in progress."), dev, "{command}: {html}");
+            assert!(html.contains(&format!("Mode: {}", if dev { "dev" } else { "build" })), "{html}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_builds_publish_minified_scripts_and_svgs() {
+        let script = "// Say hi\nfunction greet(name) {\n    console.log('hi ' + name);\n}\n";
+        let (site, _) = transliterated_site(&[
+            ("config.toml", "[build.minify]\njs = true\nsvg = true\n"),
+            ("index.md", "---\ntitle: Home\n---\n# Home"),
+            ("js/app.js", script),
+            ("js/broken.js", "function (\n"),
+            ("img/logo.svg", "<svg>\n  <!-- logo -->\n  <g id=\"layer1\"/>\n</svg>\n"),
+        ])
+        .await;
+
+        let output = tempfile::tempdir().unwrap();
+        let dist = output.path().join("dist");
+        crate::build::run_build(site.path().to_path_buf(), dist.clone(), crate::build::BuildOptions::default()).await.unwrap();
+        let app = std::fs::read_to_string(dist.join("js/app.js")).unwrap();
+        assert!(app.len() < script.len() && !app.contains("Say hi"), "{app}");
+        assert_eq!(std::fs::read_to_string(dist.join("js/broken.js")).unwrap(), "function (\n");
+        assert_eq!(std::fs::read_to_string(dist.join("img/logo.svg")).unwrap(), "<svg><g id=\"layer1\"/></svg>");
+        // Pages are still minified as before
+        assert!(!std::fs::read_to_string(dist.join("index.html")).unwrap().contains("\n\n"));
+
+        // A cache-busted copy is named for what's published
+        let app_data = AppData::load(site.path().to_path_buf(), "build").await.unwrap();
+        let mut env = Environment::new();
+        env.add_function("cache_bust", app_data.cache_bust_function().to_minijinja_fn(&DepRecorder::default()));
+        let busted = env.render_str(r#"{{ cache_bust(path="/js/app.js") }}"#, ()).unwrap();
+        assert_eq!(busted, format!("/js/app.{}.js", &compute_content_hash(app.as_bytes())[..8]));
+
+        // `hugs dev` serves the files as they are
+        let app_data = AppData::load(site.path().to_path_buf(), "dev").await.unwrap();
+        assert!(app_data.asset_minifier.is_none());
+    }
 }
@@ -0,0 +1,143 @@
+//! Port binding shared by `hugs dev` and `hugs doc`.
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{Server, ServiceFactory, ServiceRequest, ServiceResponse};
+use actix_web::{App, HttpServer};
+
+use crate::error::{HugsError, Result};
+
+/// Maximum number of port retry attempts before giving up
+pub const MAX_PORT_RETRIES: u16 = 50;
+
+/// Bind the app built by `app_factory` to `host:port` and start it.
+///
+/// When `explicit` is set (the user passed `--port`), a port that's in use is an error. Otherwise
+/// I try the following ports until one is free. `command` is how the server was started, minus
+/// the `--port` flag, so errors can point at the right part of it.
+pub fn bind_with_retry<F, T, B>(
+    app_factory: F,
+    host: &str,
+    port: u16,
+    explicit: bool,
+    command: &str,
+) -> Result<(Server, u16)>
+where
+    F: Fn() -> App<T> + Send + Clone + 'static,
+    T: ServiceFactory<ServiceRequest, Config = (), Response = ServiceResponse<B>, Error = actix_web::Error, InitError = ()>
+        + 'static,
+    B: MessageBody + 'static,
+{
+    if explicit {
+        let server = HttpServer::new(app_factory)
+            .bind((host, port))
+            .map_err(|e| HugsError::port_bind(command, port, e))?;
+
+        return Ok((server.run(), port));
+    }
+
+    for attempt in 0..MAX_PORT_RETRIES {
+        let Some(try_port) = port.checked_add(attempt) else {
+            break; // Port overflow, stop trying
+        };
+
+        if let Ok(server) = HttpServer::new(app_factory.clone()).bind((host, try_port)) {
+            return Ok((server.run(), try_port));
+        }
+    }
+
+    Err(HugsError::no_available_port(command, port, port.saturating_add(MAX_PORT_RETRIES - 1)))
+}
+
+/// Like `bind_with_retry` without `--port`, but try `preferred` (say, the port the last run used)
+/// before anything else
+pub fn bind_preferring<F, T, B>(
+    app_factory: F,
+    host: &str,
+    preferred: Option<u16>,
+    port: u16,
+    command: &str,
+) -> Result<(Server, u16)>
+where
+    F: Fn() -> App<T> + Send + Clone + 'static,
+    T: ServiceFactory<ServiceRequest, Config = (), Response = ServiceResponse<B>, Error = actix_web::Error, InitError = ()>
+        + 'static,
+    B: MessageBody + 'static,
+{
+    if let Some(preferred) = preferred
+        && let Ok(server) = HttpServer::new(app_factory.clone()).bind((host, preferred))
+    {
+        return Ok((server.run(), preferred));
+    }
+
+    bind_with_retry(app_factory, host, port, false, command)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    use actix_web::{HttpResponse, web};
+
+    fn app() -> App<
+        impl ServiceFactory<
+            ServiceRequest,
+            Config = (),
+            Response = ServiceResponse<impl MessageBody>,
+            Error = actix_web::Error,
+            InitError = (),
+        >,
+    > {
+        App::new().route("/", web::get().to(HttpResponse::Ok))
+    }
+
+    #[actix_web::test]
+    async fn test_retry_skips_a_port_in_use() {
+        let taken = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let taken_port = taken.local_addr().unwrap().port();
+
+        let (server, port) = bind_with_retry(app, "127.0.0.1", taken_port, false, "hugs dev .").unwrap();
+        assert!(port > taken_port);
+
+        let handle = server.handle();
+        actix_web::rt::spawn(server);
+        handle.stop(false).await;
+    }
+
+    #[actix_web::test]
+    async fn test_preferred_port_is_tried_first() {
+        let taken = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let taken_port = taken.local_addr().unwrap().port();
+        let free_port = TcpListener::bind(("127.0.0.1", 0)).unwrap().local_addr().unwrap().port();
+
+        let (server, port) = bind_preferring(app, "127.0.0.1", Some(free_port), taken_port, "hugs dev .").unwrap();
+        assert_eq!(port, free_port);
+        let handle = server.handle();
+        actix_web::rt::spawn(server);
+        handle.stop(false).await;
+
+        let (server, port) = bind_preferring(app, "127.0.0.1", Some(taken_port), taken_port, "hugs dev .").unwrap();
+        assert!(port > taken_port);
+        let handle = server.handle();
+        actix_web::rt::spawn(server);
+        handle.stop(false).await;
+    }
+
+    #[actix_web::test]
+    async fn test_explicit_port_in_use_points_at_the_port() {
+        let taken = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let taken_port = taken.local_addr().unwrap().port();
+
+        for command in ["hugs doc", "hugs dev ./my-site"] {
+            let Err(HugsError::PortBind { src, span, .. }) =
+                bind_with_retry(app, "127.0.0.1", taken_port, true, command)
+            else {
+                panic!("expected a port bind error for {command}");
+            };
+
+            let source = src.inner();
+            assert_eq!(source, &format!("{command} --port {taken_port}"));
+            assert_eq!(&source[span.offset()..span.offset() + span.len()], taken_port.to_string());
+        }
+    }
+}
@@ -0,0 +1,58 @@
+//! `hugs completions` and `hugs manpage`: both are generated from the same clap definitions as the
+//! commands themselves, so they can't fall behind when a command or flag is added.
+
+use std::io::Write;
+
+use clap_complete::Shell;
+
+use crate::error::{HugsError, Result};
+
+/// Write the completion script for `shell` to `out`
+pub fn write_completions(shell: Shell, mut cmd: clap::Command, out: &mut impl Write) -> Result<()> {
+    let name = cmd.get_name().to_string();
+    clap_complete::generate(shell, &mut cmd, name, out);
+    out.flush().map_err(|e| HugsError::ShellOutput { what: "completion script", cause: e })
+}
+
+/// Write the man page, in roff, to `out`
+pub fn write_manpage(cmd: clap::Command, out: &mut impl Write) -> Result<()> {
+    clap_mangen::Man::new(cmd)
+        .render(out)
+        .map_err(|e| HugsError::ShellOutput { what: "man page", cause: e })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::{CommandFactory, ValueEnum};
+
+    use crate::Args;
+
+    const COMMANDS: [&str; 5] = ["dev", "build", "render", "new", "doc"];
+
+    #[test]
+    fn test_completions_list_every_command() {
+        for shell in Shell::value_variants() {
+            let mut out = Vec::new();
+            write_completions(*shell, Args::command(), &mut out).unwrap();
+            let script = String::from_utf8(out).unwrap();
+
+            for command in COMMANDS {
+                assert!(script.contains(command), "{shell} completions are missing `{command}`");
+            }
+            assert!(script.contains("single-file"), "{shell} completions are missing build's flags");
+        }
+    }
+
+    #[test]
+    fn test_manpage_lists_every_command() {
+        let mut out = Vec::new();
+        write_manpage(Args::command(), &mut out).unwrap();
+        let page = String::from_utf8(out).unwrap();
+
+        assert!(page.starts_with(".ie \\n(.g .ds Aq"), "expected roff, got {}", &page[..40.min(page.len())]);
+        for command in COMMANDS {
+            assert!(page.contains(&format!("hugs\\-{command}")), "the man page is missing `{command}`");
+        }
+    }
+}
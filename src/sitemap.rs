@@ -1,46 +1,85 @@
 use minijinja::{Environment, context};
 use serde::Serialize;
+use serde_yaml::Value as YamlValue;
 
 use crate::config::SiteMetadata;
-use crate::error::{HugsError, Result};
-use crate::feed::extract_date_from_frontmatter;
+use crate::dates::{DateParser, extract_dates};
+use crate::error::{HugsError, Result, TemplateHints};
+use crate::lastmod::LastmodHistory;
 use crate::run::PageInfo;
+use crate::url::SiteUrl;
 
 const SITEMAP_TEMPLATE: &str = include_str!("templates/sitemap.jinja");
 
+/// Where a site's own sitemap template lives
+const SITEMAP_TEMPLATE_FILE: &str = "_/sitemap.jinja";
+
 #[derive(Serialize)]
-struct SitemapEntry {
+struct SitemapEntry<'a> {
     loc: String,
     lastmod: Option<String>,
+    /// Everything `pages()` knows about the page, for alternates, images and the like
+    page: &'a PageInfo,
 }
 
-/// Generate a sitemap.xml for all pages
-pub fn generate_sitemap(pages: &[PageInfo], site_metadata: &SiteMetadata) -> Result<String> {
+/// Generate a sitemap.xml for all pages but those with `sitemap: false`, with the site's
+/// `_/sitemap.jinja` when it has one. Pages without a date take their lastmod from `history`, when
+/// a build has seen them change.
+pub fn generate_sitemap(
+    pages: &[PageInfo],
+    site_metadata: &SiteMetadata,
+    dates: &DateParser,
+    custom_template: Option<&str>,
+    history: Option<&LastmodHistory>,
+) -> Result<String> {
     let base_url = site_metadata
         .url
         .as_ref()
         .ok_or(HugsError::SitemapMissingUrl)?;
-    let base_url = base_url.trim_end_matches('/');
 
     let entries: Vec<SitemapEntry> = pages
         .iter()
+        .filter(|page| page.frontmatter.get("sitemap").and_then(|v| v.as_bool()) != Some(false))
         .map(|page| {
-            let url_with_slash = if page.url.ends_with('/') {
-                page.url.clone()
-            } else {
-                format!("{}/", page.url)
-            };
-
-            let lastmod = extract_date_from_frontmatter(&page.frontmatter)
-                .map(|dt| dt.format("%Y-%m-%d").to_string());
+            let lastmod = extract_dates(&page.frontmatter, dates)
+                .last_modified()
+                .or_else(|| history?.last_changed(page.url.as_ref()))
+                .map(|dt| dates.local(dt).format("%Y-%m-%d").to_string());
 
             SitemapEntry {
-                loc: format!("{}{}", base_url, url_with_slash),
+                loc: base_url.absolute_url(&page.url.with_trailing_slash()),
                 lastmod,
+                page,
             }
         })
         .collect();
 
+    match custom_template {
+        Some(template) => render_custom(template, &entries, site_metadata),
+        None => render_builtin(&entries),
+    }
+}
+
+/// Check `_/sitemap.jinja` once at load time by rendering it for a sample page, pointing any
+/// error at the template
+pub fn validate_sitemap_template(template: &str, site_metadata: &SiteMetadata) -> Result<()> {
+    let page = PageInfo {
+        url: SiteUrl::parse("/example"),
+        file_path: String::from("example.md"),
+        frontmatter: YamlValue::Mapping(
+            [(YamlValue::from("title"), YamlValue::from("Example"))].into_iter().collect(),
+        ),
+    };
+    let entry = SitemapEntry {
+        loc: String::from("https://example.com/example/"),
+        lastmod: Some(String::from("2024-01-01")),
+        page: &page,
+    };
+
+    render_custom(template, &[entry], site_metadata).map(|_| ())
+}
+
+fn render_builtin(entries: &[SitemapEntry]) -> Result<String> {
     let mut env = Environment::new();
     env.add_template("sitemap", SITEMAP_TEMPLATE)
         .map_err(|e| HugsError::SitemapTemplate {
@@ -57,3 +96,106 @@ pub fn generate_sitemap(pages: &[PageInfo], site_metadata: &SiteMetadata) -> Res
             reason: e.to_string(),
         })
 }
+
+fn render_custom(template: &str, entries: &[SitemapEntry], site_metadata: &SiteMetadata) -> Result<String> {
+    let env = Environment::new();
+    let ctx = context! {
+        entries => entries,
+        site => context! {
+            title => site_metadata.title,
+            description => site_metadata.description,
+            url => site_metadata.url,
+            author => site_metadata.author,
+            language => site_metadata.language,
+        },
+    };
+
+    env.render_str(template, ctx).map_err(|e| {
+        HugsError::template_render_named(
+            SITEMAP_TEMPLATE_FILE,
+            template,
+            &e,
+            &TemplateHints::from_environment(&env),
+            0,
+            0,
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(url: &str, frontmatter: &str) -> PageInfo {
+        PageInfo {
+            url: SiteUrl::parse(url),
+            file_path: format!("{}.md", url.trim_matches('/')),
+            frontmatter: serde_yaml::from_str(frontmatter).unwrap(),
+        }
+    }
+
+    fn site() -> SiteMetadata {
+        SiteMetadata {
+            url: Some(String::from("https://example.com/").try_into().unwrap()),
+            language: String::from("en-us"),
+            ..SiteMetadata::default()
+        }
+    }
+
+    #[test]
+    fn test_builtin_sitemap_is_unchanged() {
+        let pages = [
+            page("/", "title: Home"),
+            page("/blog/café", "title: Café\ndate: 2024-03-05\nupdated: 2024-04-01"),
+        ];
+
+        assert_eq!(
+            generate_sitemap(&pages, &site(), &DateParser::default(), None, None).unwrap(),
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+            <urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n  \
+            <url>\n    <loc>https://example.com/</loc>\n  </url>\n  \
+            <url>\n    <loc>https://example.com/blog/caf%C3%A9/</loc>\n    <lastmod>2024-04-01</lastmod>\n  </url>\n\
+            </urlset>"
+        );
+    }
+
+    #[test]
+    fn test_custom_template_sees_frontmatter() {
+        let template = "{% for entry in entries %}{{ entry.loc }} {{ entry.page.title }} \
+            {% for image in entry.page.images %}[{{ image }}]{% endfor %}\n{% endfor %}{{ site.language }}";
+        let pages = [page("/gallery", "title: Gallery\nimages: [a.png, b.png]"), page("/about", "title: About")];
+
+        assert_eq!(
+            generate_sitemap(&pages, &site(), &DateParser::default(), Some(template), None).unwrap(),
+            "https://example.com/gallery/ Gallery [a.png][b.png]\nhttps://example.com/about/ About \nen-us"
+        );
+    }
+
+    #[test]
+    fn test_pages_can_leave_the_sitemap() {
+        let pages = [
+            page("/", "title: Home\nsitemap: true"),
+            page("/about", "title: About"),
+            page("/thanks", "title: Thanks\nsitemap: false"),
+        ];
+
+        let template = "{% for entry in entries %}{{ entry.loc }}\n{% endfor %}";
+        assert_eq!(
+            generate_sitemap(&pages, &site(), &DateParser::default(), Some(template), None).unwrap(),
+            "https://example.com/\nhttps://example.com/about/\n"
+        );
+    }
+
+    #[test]
+    fn test_broken_template_points_at_the_file() {
+        let error = validate_sitemap_template("{% for entry in entries %}{{ entry.loc | nope }}{% endfor %}", &site())
+            .unwrap_err();
+        let HugsError::TemplateRender { src, span, .. } = &error else {
+            panic!("expected a TemplateRender error, got {error:?}");
+        };
+        assert_eq!(src.name(), "_/sitemap.jinja");
+        assert!(span.offset() > 0, "the span should point into the template");
+
+        assert!(validate_sitemap_template("{{ entries | length }}", &site()).is_ok());
+    }
+}
@@ -0,0 +1,98 @@
+//! Pages that took longer than `[dev] render_timeout_secs` to render in `hugs dev`.
+//!
+//! A page that timed out isn't rendered again until the site reloads: each try would tie up a
+//! thread for as long as the render runs, and live reload asks again on every save. I serve the
+//! timeout page straight away instead, with a link to try once more. Like the render cache, this
+//! goes by the generation of the site data, so a reload gives every page a fresh start.
+
+use std::collections::HashMap;
+
+#[derive(Debug, Default)]
+pub struct SlowPages {
+    generation: u64,
+    pages: HashMap<String, SlowPage>,
+}
+
+#[derive(Debug, Default)]
+struct SlowPage {
+    /// Timeouts in a row
+    timeouts: u32,
+    /// The next request renders the page again, from the timeout page's retry link
+    retry: bool,
+}
+
+impl SlowPages {
+    /// How many times in a row `url` has timed out under `generation`, if it's to be skipped
+    /// rather than rendered. A retry lets one request through.
+    pub fn skip(&mut self, url: &str, generation: u64) -> Option<u32> {
+        self.move_to(generation);
+        if generation != self.generation {
+            return None;
+        }
+        let page = self.pages.get_mut(url)?;
+        if std::mem::take(&mut page.retry) {
+            return None;
+        }
+        Some(page.timeouts)
+    }
+
+    /// Record that rendering `url` under `generation` timed out, giving the timeouts in a row
+    pub fn timed_out(&mut self, url: &str, generation: u64) -> u32 {
+        self.move_to(generation);
+        let page = self.pages.entry(url.to_string()).or_default();
+        page.timeouts += 1;
+        page.timeouts
+    }
+
+    /// Record that `url` rendered in time
+    pub fn rendered(&mut self, url: &str) {
+        self.pages.remove(url);
+    }
+
+    /// Let the next request for `url` render it again
+    pub fn retry(&mut self, url: &str) {
+        if let Some(page) = self.pages.get_mut(url) {
+            page.retry = true;
+        }
+    }
+
+    /// Forget everything from before the site reloaded as `generation`
+    fn move_to(&mut self, generation: u64) {
+        if generation > self.generation {
+            self.pages.clear();
+            self.generation = generation;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_slow_pages_are_skipped_until_a_retry_or_reload() {
+        let mut slow = SlowPages::default();
+        assert_eq!(slow.skip("/slow", 1), None);
+        assert_eq!(slow.timed_out("/slow", 1), 1);
+        assert_eq!(slow.skip("/slow", 1), Some(1));
+        assert_eq!(slow.skip("/fast", 1), None);
+
+        // A retry renders it once, and another timeout counts on from the last
+        slow.retry("/slow");
+        assert_eq!(slow.skip("/slow", 1), None);
+        assert_eq!(slow.timed_out("/slow", 1), 2);
+        assert_eq!(slow.skip("/slow", 1), Some(2));
+
+        // A retry that renders in time clears it
+        slow.retry("/slow");
+        assert_eq!(slow.skip("/slow", 1), None);
+        slow.rendered("/slow");
+        assert_eq!(slow.skip("/slow", 1), None);
+
+        // So does reloading the site
+        slow.timed_out("/slow", 1);
+        assert_eq!(slow.skip("/slow", 2), None);
+        // A request still on the old site data doesn't bring it back
+        assert_eq!(slow.skip("/slow", 1), None);
+    }
+}
@@ -0,0 +1,326 @@
+//! `[sri]`: Subresource Integrity for the external scripts and stylesheets a site loads. Once
+//! `hugs build` has written the pages, I look for `<script src>` and `<link href>` tags pointing
+//! at the hosts in `[sri] hosts`, fetch each file once, and add its sha384 hash as `integrity`,
+//! with `crossorigin="anonymous"` so the browser checks it.
+//!
+//! The hashes are pinned in `sri-lock.toml`, next to config.toml. A pinned file isn't fetched
+//! again, so builds work offline, and a file that changes upstream is refused by browsers rather
+//! than quietly getting a new hash. The hash of each file I do fetch is kept in `.hugs-cache/` too,
+//! so a build that fails before writing the lock doesn't fetch it again. With `verify = true` I
+//! fetch pinned files anyway, past the cache, and fail the build when one changed. `hugs dev` never
+//! adds integrity attributes.
+
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha384};
+
+use crate::build_cache::BuildCache;
+use crate::config::SriConfig;
+use crate::console;
+use crate::error::{HugsError, Result};
+use crate::inline::{StartTag, rewrite_start_tags};
+use crate::lastmod::hash_html;
+use crate::mem_stats::WrittenPage;
+use crate::run::PageInfo;
+use crate::text_output::{output_text, write_text_output_atomically};
+
+/// File name of the lockfile, at the site root
+pub const LOCK_FILE: &str = "sri-lock.toml";
+
+const LOCK_HEADER: &str = "# Integrity hashes of the external files this site loads, pinned by `hugs build`.
+# Delete a line and build with --no-cache to fetch that file again.
+
+";
+
+/// `rel` values of the links a browser checks against their `integrity`
+const CHECKED_RELS: &[&str] = &["stylesheet", "preload", "modulepreload"];
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct SriLock {
+    /// Hash of each file, by URL
+    #[serde(default)]
+    resources: BTreeMap<String, String>,
+}
+
+/// The `integrity` value for a file with these contents
+pub fn integrity(bytes: &[u8]) -> String {
+    format!("sha384-{}", STANDARD.encode(Sha384::digest(bytes)))
+}
+
+/// The URL to fetch for `url` when it's on one of `hosts`. Protocol-relative URLs are fetched
+/// over HTTPS, and plain HTTP ones are left alone.
+fn pinned_url(url: &str, hosts: &[String]) -> Option<String> {
+    let url = url.trim().replace("&amp;", "&");
+    let rest = url.strip_prefix("https://").or_else(|| url.strip_prefix("//"))?;
+    let authority = &rest[..rest.find(['/', '?', '#']).unwrap_or(rest.len())];
+    let host = authority.rsplit('@').next().unwrap_or(authority);
+    let host = host.split(':').next().unwrap_or(host);
+
+    hosts
+        .iter()
+        .any(|allowed| allowed.eq_ignore_ascii_case(host))
+        .then(|| format!("https://{}", rest))
+}
+
+/// The URL a tag loads that its `integrity` would cover: a script's `src`, or a stylesheet or
+/// preload link's `href`
+fn checked_url(tag: &StartTag) -> Option<&str> {
+    match tag.name.as_str() {
+        "script" => tag.attr("src"),
+        "link" => {
+            let rel = tag.attr("rel")?;
+            rel.split_ascii_whitespace()
+                .any(|r| CHECKED_RELS.iter().any(|checked| r.eq_ignore_ascii_case(checked)))
+                .then(|| tag.attr("href"))
+                .flatten()
+        }
+        _ => None,
+    }
+}
+
+/// The files on `hosts` that the tags in `html` load without an `integrity` of their own
+fn external_files(html: &str, hosts: &[String]) -> Vec<String> {
+    let mut files = Vec::new();
+    rewrite_start_tags(html, |tag| {
+        if !tag.has_attr("integrity")
+            && let Some(url) = checked_url(tag).and_then(|url| pinned_url(url, hosts))
+            && !files.contains(&url)
+        {
+            files.push(url);
+        }
+        None
+    });
+    files
+}
+
+/// `html` with `integrity` added to the tags loading files that have a hash in `hashes`
+fn add_integrity(html: &str, hosts: &[String], hashes: &BTreeMap<String, String>) -> String {
+    rewrite_start_tags(html, |tag| {
+        if tag.has_attr("integrity") {
+            return None;
+        }
+        let hash = hashes.get(&pinned_url(checked_url(tag)?, hosts)?)?;
+
+        let mut tag = tag.clone();
+        tag.push_attr("integrity", hash);
+        if !tag.has_attr("crossorigin") {
+            tag.push_attr("crossorigin", "anonymous");
+        }
+        Some(tag.to_html())
+    })
+}
+
+/// How long fetching one file may take
+const FETCH_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Fetch `url`, following redirects
+fn fetch(agent: &ureq::Agent, url: &str) -> std::result::Result<Vec<u8>, String> {
+    let mut response = agent.get(url).call().map_err(|e| match e {
+        ureq::Error::StatusCode(status) => format!("the server answered {}", status),
+        e => e.to_string(),
+    })?;
+    response.body_mut().read_to_vec().map_err(|e| e.to_string())
+}
+
+/// Pin a hash for each of `urls` in `lock`, hashing the ones it doesn't have (or every one, to
+/// `verify` them) with `hash`. Returns a warning for each file that couldn't be fetched, or an
+/// error for the first pinned file that changed.
+fn pin(
+    urls: &[String],
+    lock: &mut SriLock,
+    verify: bool,
+    hash: impl Fn(&str) -> std::result::Result<String, String> + Sync,
+) -> Result<Vec<HugsError>> {
+    let to_fetch: Vec<&String> = urls.iter().filter(|url| verify || !lock.resources.contains_key(*url)).collect();
+    if to_fetch.is_empty() {
+        return Ok(Vec::new());
+    }
+    console::status("Fetching", format!("{} external files to hash", to_fetch.len()));
+
+    let fetched: Vec<(&String, std::result::Result<String, String>)> =
+        to_fetch.into_par_iter().map(|url| (url, hash(url))).collect();
+
+    let mut warnings = Vec::new();
+    for (url, result) in fetched {
+        match (result, lock.resources.get(url)) {
+            (Ok(hash), Some(pinned)) => {
+                if hash != *pinned {
+                    return Err(HugsError::SriChanged { url: url.as_str().into(), pinned: pinned.clone(), fetched: hash });
+                }
+            }
+            (Ok(hash), None) => {
+                lock.resources.insert(url.clone(), hash);
+            }
+            (Err(reason), _) => warnings.push(HugsError::SriFetchFailed { url: url.as_str().into(), reason }),
+        }
+    }
+    Ok(warnings)
+}
+
+fn load_lock(path: &Path) -> Result<SriLock> {
+    match std::fs::read_to_string(path) {
+        Ok(contents) => toml::from_str(&contents).map_err(|e| HugsError::SriLockParse {
+            path: path.into(),
+            reason: e.message().to_string(),
+        }),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(SriLock::default()),
+        Err(e) => Err(HugsError::FileRead { path: path.into(), cause: e }),
+    }
+}
+
+/// Add `integrity` attributes to the written pages (and 404.html), pinning new hashes in the
+/// site's `sri-lock.toml`. `written` gets the new sizes and hashes of the pages that changed.
+/// Returns a warning for each file that couldn't be fetched.
+pub fn add_subresource_integrity(
+    pages: &[PageInfo],
+    written: &mut [WrittenPage],
+    site_path: &Path,
+    output_path: &Path,
+    config: &SriConfig,
+    cache: &BuildCache,
+    normalize: bool,
+) -> Result<Vec<HugsError>> {
+    let files: Vec<(Option<String>, PathBuf)> = pages
+        .iter()
+        .map(|page| (Some(page.url.to_string()), output_path.join(page.url.output_path())))
+        .chain([(None, output_path.join("404.html"))])
+        .collect();
+    let htmls: Vec<(Option<String>, PathBuf, String)> = files
+        .into_iter()
+        .filter_map(|(url, file)| std::fs::read_to_string(&file).ok().map(|html| (url, file, html)))
+        .collect();
+
+    let mut seen = HashSet::new();
+    let urls: Vec<String> = htmls
+        .iter()
+        .flat_map(|(_, _, html)| external_files(html, &config.hosts))
+        .filter(|url| seen.insert(url.clone()))
+        .collect();
+    if urls.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let lock_path = site_path.join(LOCK_FILE);
+    let mut lock = load_lock(&lock_path)?;
+    let pinned = lock.resources.len();
+    let agent: ureq::Agent = ureq::Agent::config_builder().timeout_global(Some(FETCH_TIMEOUT)).build().into();
+    let hash = |url: &str| fetch(&agent, url).map(|bytes| integrity(&bytes));
+    let warnings = if config.verify {
+        pin(&urls, &mut lock, true, hash)?
+    } else {
+        pin(&urls, &mut lock, false, |url| cache.try_get_or_compute("sri", url, || hash(url)))?
+    };
+
+    if lock.resources.len() != pinned {
+        let contents = format!("{}{}", LOCK_HEADER, toml::to_string(&lock).expect("the lock is plain TOML"));
+        std::fs::write(&lock_path, contents).map_err(|e| HugsError::FileWrite { path: (&lock_path).into(), cause: e })?;
+    }
+
+    for (url, file, html) in htmls {
+        let updated = add_integrity(&html, &config.hosts, &lock.resources);
+        if updated == html {
+            continue;
+        }
+        let updated = output_text(&updated, normalize);
+        write_text_output_atomically(&file, &updated, false)?;
+        if let Some(page) = written.iter_mut().find(|page| Some(&page.url) == url.as_ref()) {
+            page.bytes = updated.len() as u64;
+            page.hash = hash_html(updated.as_bytes());
+        }
+    }
+
+    Ok(warnings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hosts() -> Vec<String> {
+        vec![String::from("cdn.example"), String::from("fonts.example")]
+    }
+
+    const PAGE: &str = r#"<head><link rel="stylesheet" href="https://fonts.example/css?family=Inter&amp;display=swap"><link rel="icon" href="https://cdn.example/icon.png"><script src="//cdn.example:443/lib.js" defer></script><script src="https://other.example/x.js"></script><script src="https://cdn.example/pinned.js" integrity="sha384-mine" crossorigin="use-credentials"></script><script>let s = '<script src="https://cdn.example/lib.js">';</script></head>"#;
+
+    #[test]
+    fn test_finds_files_on_allowed_hosts() {
+        assert_eq!(
+            external_files(PAGE, &hosts()),
+            ["https://fonts.example/css?family=Inter&display=swap", "https://cdn.example:443/lib.js"]
+        );
+        assert_eq!(pinned_url("http://cdn.example/lib.js", &hosts()), None);
+        assert_eq!(pinned_url("https://CDN.example/lib.js", &hosts()).as_deref(), Some("https://CDN.example/lib.js"));
+        assert_eq!(pinned_url("https://cdn.example.evil/lib.js", &hosts()), None);
+    }
+
+    #[test]
+    fn test_adds_integrity_to_pinned_files() {
+        let mut lock = SriLock::default();
+        let hash = |url: &str| match url {
+            "https://cdn.example:443/lib.js" => Ok(integrity(b"console.log(1)")),
+            _ => Err(String::from("the server answered 404")),
+        };
+
+        let warnings = pin(&external_files(PAGE, &hosts()), &mut lock, false, hash).unwrap();
+        assert!(matches!(&warnings[..], [HugsError::SriFetchFailed { url, .. }] if url.0.starts_with("https://fonts.example/")));
+        assert_eq!(lock.resources.len(), 1);
+
+        let html = add_integrity(PAGE, &hosts(), &lock.resources);
+        let hash = integrity(b"console.log(1)");
+        assert!(html.contains(&format!(r#"<script src="//cdn.example:443/lib.js" defer integrity="{hash}" crossorigin="anonymous">"#)), "{html}");
+        // Unfetched, off the list, already hashed, or not a tag at all: left as written
+        assert!(html.contains(r#"<link rel="stylesheet" href="https://fonts.example/css?family=Inter&amp;display=swap">"#));
+        assert!(html.contains(r#"<link rel="icon" href="https://cdn.example/icon.png">"#));
+        assert!(html.contains(r#"integrity="sha384-mine" crossorigin="use-credentials""#));
+        assert!(html.contains(r#"let s = '<script src="https://cdn.example/lib.js">';"#));
+    }
+
+    #[test]
+    fn test_pinned_files_are_not_fetched_unless_verified() {
+        let url = String::from("https://cdn.example/lib.js");
+        let mut lock = SriLock::default();
+        lock.resources.insert(url.clone(), integrity(b"v1"));
+
+        let unreachable = |_: &str| -> std::result::Result<String, String> { panic!("pinned files aren't fetched") };
+        assert!(pin(std::slice::from_ref(&url), &mut lock, false, unreachable).unwrap().is_empty());
+
+        let changed = pin(std::slice::from_ref(&url), &mut lock, true, |_| Ok(integrity(b"v2")));
+        assert!(matches!(changed, Err(HugsError::SriChanged { pinned, .. }) if pinned == integrity(b"v1")));
+        assert_eq!(lock.resources[&url], integrity(b"v1"));
+    }
+
+    #[test]
+    fn test_fetched_hashes_are_cached_until_pinned() {
+        let site = tempfile::tempdir().unwrap();
+        let cache = BuildCache::open(site.path());
+        let url = String::from("https://cdn.example/lib.js");
+        let fetches = std::sync::atomic::AtomicUsize::new(0);
+        let hash = |url: &str| {
+            cache.try_get_or_compute("sri", url, || {
+                fetches.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                Ok::<_, String>(integrity(b"v1"))
+            })
+        };
+
+        // A build that fetched the file but never wrote its lock
+        pin(std::slice::from_ref(&url), &mut SriLock::default(), false, hash).unwrap();
+        let mut lock = SriLock::default();
+        pin(std::slice::from_ref(&url), &mut lock, false, hash).unwrap();
+        assert_eq!(lock.resources[&url], integrity(b"v1"));
+        assert_eq!(fetches.into_inner(), 1);
+    }
+
+    #[test]
+    fn test_integrity_is_base64_sha384() {
+        // The example from the Subresource Integrity spec
+        assert_eq!(
+            integrity(b"alert('Hello, world.');"),
+            "sha384-H8BRh8j48O9oYatfu5AZzq6A9RINhZO5H16dQZngK7T62em8MUt1FLm52t+eX6xO"
+        );
+    }
+}
@@ -0,0 +1,382 @@
+//! `help()`, `| help` and `is help` in templates. Each one fails on purpose with a marker carrying
+//! what it saw, base64 encoded so any value survives the trip through MiniJinja's error; the error
+//! code decodes the marker back and answers with [`TemplateHelp`]. In the terminal that's wrapped
+//! to the window, and on the dev error page each list flows to fit the browser instead.
+
+use std::fmt::{self, Write};
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use owo_colors::OwoColorize;
+
+use crate::run::escape_html;
+
+/// Help marker prefixes used to identify help requests in error messages
+pub const HELP_MARKER_FUNCTION: &str = "__hugs_help_function__";
+pub const HELP_MARKER_FILTER: &str = "__hugs_help_filter__";
+pub const HELP_MARKER_TEST: &str = "__hugs_help_test__";
+
+/// Width I assume when I can't tell how wide the terminal is
+const DEFAULT_TERMINAL_WIDTH: usize = 80;
+
+/// Room miette takes around help text: `  help: ` before it, and a margin of two after
+const HELP_INDENT: usize = 10;
+
+/// Narrowest I'll wrap lists to, however small the terminal
+const MIN_HELP_WIDTH: usize = 30;
+
+/// A variable in scope where `help()` was called, with its value as MiniJinja prints it
+#[derive(Debug, Clone, PartialEq)]
+pub struct HelpVariable {
+    pub name: String,
+    pub value: String,
+}
+
+/// What a template asked for help with, decoded from its marker
+#[derive(Debug, Clone, PartialEq)]
+pub enum HelpRequest {
+    /// `help()`, with the variables it could see
+    Everything { variables: Vec<HelpVariable> },
+    /// `value | help`
+    Filter { kind: String, value: String },
+    /// `value is help`
+    Test { kind: String, value: String },
+}
+
+fn decode(b64: &str) -> String {
+    STANDARD
+        .decode(b64)
+        .ok()
+        .and_then(|b| String::from_utf8(b).ok())
+        .unwrap_or_else(|| "?".to_string())
+}
+
+/// The marker `help()` fails with, for these variables and their values
+pub fn function_marker(variables: &[HelpVariable]) -> String {
+    let entries: Vec<String> = variables
+        .iter()
+        .map(|var| format!("{}:{}", STANDARD.encode(&var.name), STANDARD.encode(&var.value)))
+        .collect();
+    format!("{}:variables={}", HELP_MARKER_FUNCTION, entries.join(","))
+}
+
+/// The marker `| help` (with [`HELP_MARKER_FILTER`]) or `is help` (with [`HELP_MARKER_TEST`])
+/// fails with, for a value of `kind`
+pub fn value_marker(marker: &str, kind: &str, value: &str) -> String {
+    format!("{}:kind={}:value={}", marker, kind, STANDARD.encode(value))
+}
+
+impl HelpRequest {
+    /// Decode an error detail, if it's one of the help markers
+    pub fn parse(detail: &str) -> Option<Self> {
+        if let Some(rest) = detail.strip_prefix(HELP_MARKER_FUNCTION) {
+            let variables = rest
+                .find("variables=")
+                .map(|pos| &rest[pos + "variables=".len()..])
+                .unwrap_or("")
+                .split(',')
+                .filter_map(|entry| entry.split_once(':'))
+                .map(|(name, value)| HelpVariable { name: decode(name), value: decode(value) })
+                .collect();
+            return Some(HelpRequest::Everything { variables });
+        }
+
+        let (rest, is_filter) = match detail.strip_prefix(HELP_MARKER_FILTER) {
+            Some(rest) => (rest, true),
+            None => (detail.strip_prefix(HELP_MARKER_TEST)?, false),
+        };
+        let mut kind = String::new();
+        let mut value = "?".to_string();
+        for part in rest.split(':') {
+            if let Some(k) = part.strip_prefix("kind=") {
+                kind = k.to_string();
+            } else if let Some(v) = part.strip_prefix("value=") {
+                value = decode(v);
+            }
+        }
+
+        Some(if is_filter { HelpRequest::Filter { kind, value } } else { HelpRequest::Test { kind, value } })
+    }
+
+    /// The label for where the template asked
+    pub fn reason(&self) -> &'static str {
+        match self {
+            HelpRequest::Everything { .. } => "you asked for help here",
+            HelpRequest::Filter { .. } => "you asked for filter help here",
+            HelpRequest::Test { .. } => "you asked for test help here",
+        }
+    }
+}
+
+/// Help under a template error: either advice written out as text, or the sections
+/// a help request answers with
+#[derive(Debug, Clone)]
+pub enum TemplateHelp {
+    Text(String),
+    Sections(Vec<HelpSection>),
+}
+
+/// One part of the answer to a help request, like "Filters you can apply"
+#[derive(Debug, Clone)]
+pub struct HelpSection {
+    /// `None` for closing notes, which aren't under a heading
+    pub title: Option<String>,
+    pub body: HelpBody,
+}
+
+/// What's in a [`HelpSection`]. Text in `Lines` and titles can mark code with backticks.
+#[derive(Debug, Clone)]
+pub enum HelpBody {
+    /// Names, listed to fit the width they have
+    Items(Vec<String>),
+    Variables(Vec<HelpVariable>),
+    /// A single value, on its own line
+    Value(String),
+    Lines(Vec<String>),
+}
+
+impl HelpSection {
+    pub fn new(title: impl Into<String>, body: HelpBody) -> Self {
+        Self { title: Some(title.into()), body }
+    }
+
+    pub fn note(lines: &[&str]) -> Self {
+        Self { title: None, body: HelpBody::Lines(lines.iter().map(|line| line.to_string()).collect()) }
+    }
+
+    /// A list of `items`, or `empty` when there are none
+    pub fn items(title: impl Into<String>, items: Vec<String>, empty: &str) -> Self {
+        let body = if items.is_empty() { HelpBody::Lines(vec![empty.to_string()]) } else { HelpBody::Items(items) };
+        Self::new(title, body)
+    }
+}
+
+impl From<String> for TemplateHelp {
+    fn from(text: String) -> Self {
+        TemplateHelp::Text(text)
+    }
+}
+
+/// `text` with its `code` spans colored (and their backticks dropped) for the terminal
+fn highlight_code(text: &str) -> String {
+    let mut out = String::new();
+    for (i, part) in text.split('`').enumerate() {
+        if i % 2 == 1 {
+            out.push_str(&part.bright_yellow().to_string());
+        } else {
+            out.push_str(part);
+        }
+    }
+    out
+}
+
+/// `text` escaped for HTML, with its `code` spans in `<code>`
+fn html_with_code(text: &str) -> String {
+    let mut out = String::new();
+    for (i, part) in text.split('`').enumerate() {
+        if i % 2 == 1 {
+            write!(out, "<code>{}</code>", escape_html(part)).unwrap();
+        } else {
+            out.push_str(&escape_html(part));
+        }
+    }
+    out
+}
+
+/// Wrap a list of items into lines with a maximum character width
+fn wrap_items_to_lines(items: &[String], max_width: usize) -> String {
+    let mut result = String::new();
+    let mut current_line = String::from("  ");
+    let mut current_len = 2; // Account for leading indent
+
+    for (i, item) in items.iter().enumerate() {
+        let separator = if i > 0 { ", " } else { "" };
+        let addition_len = separator.len() + item.chars().count();
+
+        if current_len + addition_len > max_width && current_len > 2 {
+            result.push_str(&current_line);
+            result.push('\n');
+            current_line = format!("  {}", item.bright_yellow());
+            current_len = 2 + item.chars().count();
+        } else {
+            current_line.push_str(separator);
+            current_line.push_str(&item.bright_yellow().to_string());
+            current_len += addition_len;
+        }
+    }
+
+    if current_len > 2 {
+        result.push_str(&current_line);
+        result.push('\n');
+    }
+
+    result
+}
+
+/// Columns help has in this terminal, after miette's indent
+fn terminal_help_width() -> usize {
+    terminal_size::terminal_size()
+        .map(|(terminal_size::Width(width), _)| width as usize)
+        .unwrap_or(DEFAULT_TERMINAL_WIDTH)
+        .saturating_sub(HELP_INDENT)
+        .max(MIN_HELP_WIDTH)
+}
+
+impl TemplateHelp {
+    /// Render for a terminal `width` columns wide
+    pub fn to_terminal(&self, width: usize) -> String {
+        let sections = match self {
+            TemplateHelp::Text(text) => return text.clone(),
+            TemplateHelp::Sections(sections) => sections,
+        };
+
+        let mut out = String::new();
+        for (i, section) in sections.iter().enumerate() {
+            if i > 0 {
+                out.push('\n');
+            }
+            let indent = match &section.title {
+                Some(title) => {
+                    writeln!(out, "{}:", highlight_code(title)).unwrap();
+                    "  "
+                }
+                None => "",
+            };
+            match &section.body {
+                HelpBody::Items(items) => out.push_str(&wrap_items_to_lines(items, width)),
+                HelpBody::Variables(variables) => {
+                    for var in variables {
+                        writeln!(
+                            out,
+                            "  {} = {}",
+                            var.name.bright_yellow(),
+                            var.value.truecolor(255, 165, 0) // Orange
+                        )
+                        .unwrap();
+                    }
+                }
+                HelpBody::Value(value) => writeln!(out, "    {}", value.bright_yellow()).unwrap(),
+                HelpBody::Lines(lines) => {
+                    for line in lines {
+                        writeln!(out, "{}{}", indent, highlight_code(line)).unwrap();
+                    }
+                }
+            }
+        }
+        out
+    }
+
+    /// Render for the dev error page, or `None` for text help, which the report already shows
+    pub fn to_html(&self) -> Option<String> {
+        let TemplateHelp::Sections(sections) = self else {
+            return None;
+        };
+
+        let mut html = String::from(
+            "<section class=\"help\" aria-labelledby=\"help-heading\">\n<h2 id=\"help-heading\">Help</h2>\n",
+        );
+        for section in sections {
+            if let Some(title) = &section.title {
+                writeln!(html, "<h3>{}</h3>", html_with_code(title)).unwrap();
+            }
+            match &section.body {
+                HelpBody::Items(items) => {
+                    html.push_str("<ul class=\"help-items\">");
+                    for item in items {
+                        write!(html, "<li><code>{}</code></li>", escape_html(item)).unwrap();
+                    }
+                    html.push_str("</ul>\n");
+                }
+                HelpBody::Variables(variables) => {
+                    html.push_str("<dl class=\"help-variables\">");
+                    for var in variables {
+                        write!(
+                            html,
+                            "<div><dt><code>{}</code></dt><dd><code>{}</code></dd></div>",
+                            escape_html(&var.name),
+                            escape_html(&var.value)
+                        )
+                        .unwrap();
+                    }
+                    html.push_str("</dl>\n");
+                }
+                HelpBody::Value(value) => {
+                    writeln!(html, "<pre class=\"help-value\"><code>{}</code></pre>", escape_html(value)).unwrap()
+                }
+                HelpBody::Lines(lines) => {
+                    for line in lines {
+                        writeln!(html, "<p>{}</p>", html_with_code(line)).unwrap();
+                    }
+                }
+            }
+        }
+        html.push_str("</section>\n");
+        Some(html)
+    }
+}
+
+impl fmt::Display for TemplateHelp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_terminal(terminal_help_width()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(count: usize) -> Vec<String> {
+        (0..count).map(|i| format!("filter{i}")).collect()
+    }
+
+    #[test]
+    fn test_markers_round_trip() {
+        let variables = vec![
+            HelpVariable { name: "title".into(), value: "\"a: b, c\"".into() },
+            HelpVariable { name: "tags".into(), value: "[\"x\"]".into() },
+        ];
+        assert_eq!(
+            HelpRequest::parse(&function_marker(&variables)),
+            Some(HelpRequest::Everything { variables })
+        );
+        assert_eq!(
+            HelpRequest::parse(&value_marker(HELP_MARKER_TEST, "String", "x:y=z")),
+            Some(HelpRequest::Test { kind: "String".into(), value: "x:y=z".into() })
+        );
+        assert_eq!(
+            HelpRequest::parse(&function_marker(&[])),
+            Some(HelpRequest::Everything { variables: Vec::new() })
+        );
+        assert_eq!(HelpRequest::parse("unknown filter foo"), None);
+    }
+
+    #[test]
+    fn test_lists_wrap_to_the_terminal_width() {
+        let help = TemplateHelp::Sections(vec![HelpSection::new("Filters you can apply", HelpBody::Items(names(30)))]);
+
+        for width in [40, 120] {
+            let terminal = crate::error::strip_ansi(&help.to_terminal(width));
+            let lines: Vec<&str> = terminal.lines().skip(1).collect();
+            assert!(lines.iter().all(|line| line.chars().count() <= width), "{terminal}");
+            assert!(lines.iter().any(|line| line.chars().count() > width - 12), "{terminal}");
+            assert_eq!(lines.join(" ").matches("filter").count(), 30);
+        }
+    }
+
+    #[test]
+    fn test_html_lists_flow_and_escape() {
+        let help = TemplateHelp::Sections(vec![
+            HelpSection::new("You're filtering a `String` with value", HelpBody::Value("\"<b>\"".into())),
+            HelpSection::items("Tests you can use", vec!["odd".into(), "even".into()], "none"),
+            HelpSection::items("Macros you've defined", Vec::new(), "No macros yet"),
+            HelpSection::note(&["Write `{%- if x -%}` & more"]),
+        ]);
+        let html = help.to_html().unwrap();
+
+        assert!(html.contains("<h3>You&#39;re filtering a <code>String</code> with value</h3>"), "{html}");
+        assert!(html.contains("<pre class=\"help-value\"><code>&quot;&lt;b&gt;&quot;</code></pre>"), "{html}");
+        assert!(html.contains("<ul class=\"help-items\"><li><code>odd</code></li><li><code>even</code></li></ul>"), "{html}");
+        assert!(html.contains("<p>No macros yet</p>"), "{html}");
+        assert!(html.contains("<p>Write <code>{%- if x -%}</code> &amp; more</p>"), "{html}");
+        assert!(TemplateHelp::Text("plain".into()).to_html().is_none());
+    }
+}
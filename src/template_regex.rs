@@ -0,0 +1,153 @@
+//! The `matches` test and `regex_replace` filter.
+//!
+//! Patterns use the regex crate's syntax, which has no lookaround or backreferences, so matching
+//! always takes linear time however the pattern is written. Compiled patterns are kept by pattern
+//! string, so a test inside a loop over every page compiles once.
+
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex, PoisonError};
+
+use minijinja::Value;
+use regex::Regex;
+
+/// Longest pattern I'll compile, in bytes
+const MAX_PATTERN_LEN: usize = 1000;
+
+/// Compiled patterns kept at once; the cache starts over when it fills up
+const MAX_CACHED_PATTERNS: usize = 256;
+
+/// Starts the detail of every invalid pattern error, so the template error help can explain it
+pub const INVALID_PATTERN_PREFIX: &str = "invalid regex pattern";
+
+static PATTERNS: LazyLock<Mutex<HashMap<String, Regex>>> = LazyLock::new(Default::default);
+
+fn compiled(pattern: &str) -> Result<Regex, minijinja::Error> {
+    if pattern.len() > MAX_PATTERN_LEN {
+        return Err(invalid_pattern(
+            pattern,
+            &format!("it's {} bytes long, and the limit is {}", pattern.len(), MAX_PATTERN_LEN),
+        ));
+    }
+
+    let mut patterns = PATTERNS.lock().unwrap_or_else(PoisonError::into_inner);
+    if let Some(regex) = patterns.get(pattern) {
+        return Ok(regex.clone());
+    }
+
+    let regex = Regex::new(pattern).map_err(|e| invalid_pattern(pattern, &e.to_string()))?;
+    if patterns.len() >= MAX_CACHED_PATTERNS {
+        patterns.clear();
+    }
+    patterns.insert(pattern.to_string(), regex.clone());
+    Ok(regex)
+}
+
+fn invalid_pattern(pattern: &str, reason: &str) -> minijinja::Error {
+    minijinja::Error::new(
+        minijinja::ErrorKind::InvalidOperation,
+        format!("{} `{}`: {}", INVALID_PATTERN_PREFIX, pattern, reason),
+    )
+}
+
+/// The text a value is matched against. Undefined and none have none.
+fn text_of(value: &Value) -> Option<String> {
+    if value.is_undefined() || value.is_none() {
+        return None;
+    }
+    Some(value.as_str().map(String::from).unwrap_or_else(|| value.to_string()))
+}
+
+/// The matches test checks a value against a regex, anywhere in it unless the pattern is anchored.
+///
+/// Usage in templates:
+///   {% if page.url is matches("^/blog/\\d{4}/") %}
+pub fn matches_test(value: Value, pattern: &str) -> Result<bool, minijinja::Error> {
+    let regex = compiled(pattern)?;
+    Ok(text_of(&value).is_some_and(|text| regex.is_match(&text)))
+}
+
+/// The regex_replace filter replaces every match of a regex. The replacement can refer to groups
+/// as `$1` or `${name}`.
+///
+/// Usage in templates:
+///   {{ page.url | regex_replace("^/blog/(\\d{4})/.*", "$1") }}  -> 2024
+pub fn regex_replace_filter(value: Value, pattern: &str, replacement: &str) -> Result<Value, minijinja::Error> {
+    let regex = compiled(pattern)?;
+    let Some(text) = text_of(&value) else {
+        return Ok(value);
+    };
+
+    let replaced = regex.replace_all(&text, replacement).into_owned();
+    if value.is_safe() {
+        Ok(Value::from_safe_string(replaced))
+    } else {
+        Ok(Value::from(replaced))
+    }
+}
+
+/// Help for an invalid pattern error, pointing out what the regex syntax leaves out when the
+/// pattern needed it
+pub fn invalid_pattern_help(detail: &str) -> String {
+    let mut help = String::from(
+        "This pattern isn't a regex I can use. The error above says what's wrong with it.\n\n",
+    );
+
+    if detail.contains("look-around") {
+        help.push_str(
+            "Lookahead and lookbehind like (?=...) and (?<=...) aren't supported. Without them (and \
+             backreferences) no pattern can make matching take forever, which keeps builds fast. \
+             Match the surrounding text instead, and pull out the part you want with a group in \
+             regex_replace.\n\n",
+        );
+    } else if detail.contains("backreferences") {
+        help.push_str(
+            "Backreferences like \\1 aren't supported, since they can make matching take forever. \
+             In a regex_replace replacement, refer to groups as $1 instead.\n\n",
+        );
+    }
+
+    help.push_str(
+        "Remember that a backslash in a template string needs doubling: write \"\\\\d\" for \\d.",
+    );
+    help
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use minijinja::Environment;
+
+    fn env() -> Environment<'static> {
+        let mut env = Environment::new();
+        env.add_test("matches", matches_test);
+        env.add_filter("regex_replace", regex_replace_filter);
+        env
+    }
+
+    #[test]
+    fn test_matches_and_regex_replace() {
+        let env = env();
+        let render = |template: &str, url: &str| env.render_str(template, minijinja::context! { url }).unwrap();
+
+        let template = r#"{% if url is matches("^/blog/\\d{4}/") %}dated{% else %}plain{% endif %}"#;
+        assert_eq!(render(template, "/blog/2024/hello"), "dated");
+        assert_eq!(render(template, "/blog/hello"), "plain");
+        assert_eq!(env.render_str(r#"{{ missing is matches(".") }}"#, ()).unwrap(), "False");
+
+        let template = r#"{{ url | regex_replace("^/blog/(?<year>\\d{4})/(.*)$", "$2 (${year})") }}"#;
+        assert_eq!(render(template, "/blog/2024/hello"), "hello (2024)");
+    }
+
+    #[test]
+    fn test_invalid_pattern_explains_lookbehind() {
+        let env = env();
+        let error = env.render_str(r#"{{ "x" is matches("(?<=a)b") }}"#, ()).unwrap_err();
+        let detail = error.detail().unwrap();
+        assert!(detail.starts_with(INVALID_PATTERN_PREFIX), "{detail}");
+        assert!(invalid_pattern_help(detail).contains("Lookahead and lookbehind"));
+
+        let long = "a".repeat(MAX_PATTERN_LEN + 1);
+        let error = env.render_str(&format!(r#"{{{{ "x" is matches("{long}") }}}}"#), ()).unwrap_err();
+        assert!(error.detail().unwrap().contains("the limit is"));
+    }
+}
@@ -0,0 +1,201 @@
+//! Writing the text files a build makes: pages, feeds, the sitemap, stylesheets and manifests.
+//!
+//! With `[build] output_normalize`, each one is written with LF line endings, no whitespace at the
+//! end of a line and exactly one newline at the end, the way pre-commit hooks like
+//! `end-of-file-fixer` and `trailing-whitespace` want it. Then a `dist/` kept in git stays the way
+//! I wrote it. Static files are copied as they are either way.
+
+use std::borrow::Cow;
+use std::io::{self, Write};
+use std::path::Path;
+
+use crate::error::{HugsError, Result};
+
+/// `text` the way it's written: as it is, or normalized when `normalize` is on
+pub fn output_text(text: &str, normalize: bool) -> Cow<'_, str> {
+    if !normalize || is_normalized(text) {
+        return Cow::Borrowed(text);
+    }
+
+    let mut normalized = String::with_capacity(text.len() + 1);
+    for line in text.trim_end().lines() {
+        normalized.push_str(line.trim_end());
+        normalized.push('\n');
+    }
+    Cow::Owned(normalized)
+}
+
+/// Whether `text` already ends in one newline and has no `\r` or whitespace before a newline, so
+/// the common case of a minified page doesn't copy it
+fn is_normalized(text: &str) -> bool {
+    let Some(body) = text.strip_suffix('\n') else {
+        return false;
+    };
+    !text.contains('\r') && !body.ends_with(char::is_whitespace) && !body.split('\n').any(|line| line.ends_with(char::is_whitespace))
+}
+
+/// Normalizes text on its way to `inner` the way [`output_text`] does, for pages streamed to disk
+/// that never sit in memory whole. Passes everything through as it is when `normalize` is off.
+pub struct NormalizingWriter<W> {
+    inner: W,
+    normalize: bool,
+    /// Whitespace since the last thing that wasn't, held back until it's known not to end a line
+    pending: String,
+    /// The start of a character split across writes
+    partial: Vec<u8>,
+    wrote_text: bool,
+}
+
+impl<W: Write> NormalizingWriter<W> {
+    pub fn new(inner: W, normalize: bool) -> Self {
+        Self { inner, normalize, pending: String::new(), partial: Vec::new(), wrote_text: false }
+    }
+
+    /// End the text with its one newline, and hand back the writer
+    pub fn finish(mut self) -> io::Result<W> {
+        if self.normalize {
+            if !self.partial.is_empty() {
+                let rest = String::from_utf8_lossy(&std::mem::take(&mut self.partial)).into_owned();
+                self.push(&rest)?;
+            }
+            if self.wrote_text {
+                self.inner.write_all(b"\n")?;
+            }
+        }
+        Ok(self.inner)
+    }
+
+    fn push(&mut self, text: &str) -> io::Result<()> {
+        let mut start = 0;
+        for (index, c) in text.char_indices() {
+            if !c.is_whitespace() {
+                continue;
+            }
+            if start < index {
+                self.write_text(&text[start..index])?;
+            }
+            self.pending.push(c);
+            start = index + c.len_utf8();
+        }
+        if start < text.len() {
+            self.write_text(&text[start..])?;
+        }
+        Ok(())
+    }
+
+    /// Write `text`, which has no whitespace, after the whitespace held back before it. What came
+    /// before each newline in that ended a line, so only the newlines and the indent after the last
+    /// one are kept.
+    fn write_text(&mut self, text: &str) -> io::Result<()> {
+        let newlines = self.pending.matches('\n').count();
+        let indent = self.pending.rsplit('\n').next().unwrap_or("");
+        self.inner.write_all("\n".repeat(newlines).as_bytes())?;
+        self.inner.write_all(indent.as_bytes())?;
+        self.pending.clear();
+        self.wrote_text = true;
+        self.inner.write_all(text.as_bytes())
+    }
+}
+
+impl<W: Write> Write for NormalizingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if !self.normalize {
+            return self.inner.write(buf);
+        }
+
+        self.partial.extend_from_slice(buf);
+        let bytes = std::mem::take(&mut self.partial);
+        let (text, rest) = match std::str::from_utf8(&bytes) {
+            Ok(text) => (text, &[][..]),
+            // Hold on to a character that isn't finished yet
+            Err(e) if e.error_len().is_none() => {
+                let (valid, rest) = bytes.split_at(e.valid_up_to());
+                (std::str::from_utf8(valid).expect("checked up to here"), rest)
+            }
+            Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+        };
+        self.push(text)?;
+        self.partial = rest.to_vec();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Write `text` to `path`, normalized when `normalize` is on
+pub async fn write_text_output(path: &Path, text: &str, normalize: bool) -> Result<()> {
+    tokio::fs::write(path, output_text(text, normalize).as_bytes())
+        .await
+        .map_err(|e| HugsError::FileWrite { path: path.into(), cause: e })
+}
+
+/// [`write_text_output`] through a temporary file moved into place, so a reader never sees half
+/// of it
+pub fn write_text_output_atomically(path: &Path, text: &str, normalize: bool) -> Result<()> {
+    write_atomically(path, &output_text(text, normalize))
+}
+
+/// Write `text` to a temporary file next to `path` and move it into place, so an interrupted run
+/// never leaves half a page behind
+pub fn write_atomically(path: &Path, text: &str) -> Result<()> {
+    let write_error = |cause| HugsError::FileWrite { path: path.into(), cause };
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let mut temp = tempfile::NamedTempFile::new_in(dir).map_err(write_error)?;
+    temp.write_all(text.as_bytes()).map_err(write_error)?;
+    if let Ok(metadata) = std::fs::metadata(path) {
+        let _ = temp.as_file().set_permissions(metadata.permissions());
+    }
+    temp.persist(path).map_err(|e| write_error(e.error))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalizes_line_ends_and_trailing_whitespace() {
+        assert_eq!(output_text("a  \r\nb\t\r\n\r\n\n", true), "a\nb\n");
+        assert_eq!(output_text("  indented\n\n  kept  \n", true), "  indented\n\n  kept\n");
+        assert_eq!(output_text("", true), "");
+        assert_eq!(output_text("a  \r\n", false), "a  \r\n");
+    }
+
+    #[test]
+    fn test_minified_html_only_gains_the_final_newline() {
+        let html = "<!doctype html><html><body><p>Hi <b>there</b></p></body></html>";
+        assert_eq!(output_text(html, true), format!("{}\n", html));
+
+        let written = output_text(html, true).into_owned();
+        assert!(matches!(output_text(&written, true), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn test_streamed_text_is_normalized_the_same() {
+        let texts = ["a  \r\nb\t\r\n\r\n\n", "  indented\n\n  kept  \n", "", "\n\n", "x", "\n\nx", "<p>é  \u{a0}\n</p>\r\n  ", "\t a\r b \n"];
+        for text in texts {
+            for chunk in [1, 2, 3, 64] {
+                let mut out = NormalizingWriter::new(Vec::new(), true);
+                for bytes in text.as_bytes().chunks(chunk) {
+                    out.write_all(bytes).unwrap();
+                }
+                let streamed = String::from_utf8(out.finish().unwrap()).unwrap();
+                assert_eq!(streamed, output_text(text, true), "{text:?} in chunks of {chunk}");
+            }
+        }
+
+        let mut out = NormalizingWriter::new(Vec::new(), false);
+        out.write_all(b"a  \r\n").unwrap();
+        assert_eq!(out.finish().unwrap(), b"a  \r\n");
+    }
+
+    #[test]
+    fn test_normalizing_twice_changes_nothing() {
+        for text in ["a \nb\r\n", "\n\n", "x", " \t\n  y  \n\n", "<pre>\n  code  \n</pre>"] {
+            let once = output_text(text, true).into_owned();
+            assert_eq!(output_text(&once, true), once, "{text:?}");
+        }
+    }
+}
@@ -0,0 +1,154 @@
+//! Where the time goes while a page renders.
+//!
+//! The render code marks each phase with [`phase`] or a [`PhaseTimer`], which do nothing unless
+//! the render runs inside [`measure`]. Phases nest: a template that renders markdown pauses the
+//! template's clock while the markdown runs, so every moment is counted once, under the innermost
+//! phase. The recording is task-local rather than thread-local, since two requests can take turns
+//! on one dev server worker thread.
+
+use std::cell::RefCell;
+use std::time::{Duration, Instant};
+
+use serde::{Serialize, Serializer};
+
+/// The parts of rendering a page that are timed separately
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Phase {
+    /// Rendering a template, including setting up its environment
+    Template,
+    /// Turning markdown into HTML
+    Markdown,
+    /// Highlighting the code blocks in that HTML
+    Highlight,
+    /// Minifying the finished page
+    Minify,
+}
+
+/// Time spent in each phase of one render
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize)]
+pub struct RenderTimings {
+    #[serde(rename = "template_ms", serialize_with = "as_millis")]
+    pub template: Duration,
+    #[serde(rename = "markdown_ms", serialize_with = "as_millis")]
+    pub markdown: Duration,
+    #[serde(rename = "highlight_ms", serialize_with = "as_millis")]
+    pub highlight: Duration,
+    #[serde(rename = "minify_ms", serialize_with = "as_millis")]
+    pub minify: Duration,
+}
+
+impl RenderTimings {
+    pub fn add(&mut self, phase: Phase, elapsed: Duration) {
+        match phase {
+            Phase::Template => self.template += elapsed,
+            Phase::Markdown => self.markdown += elapsed,
+            Phase::Highlight => self.highlight += elapsed,
+            Phase::Minify => self.minify += elapsed,
+        }
+    }
+}
+
+/// A duration as fractional milliseconds, for JSON
+pub fn as_millis<S: Serializer>(duration: &Duration, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+    serializer.serialize_f64(duration.as_secs_f64() * 1000.0)
+}
+
+#[derive(Default)]
+struct Recorder {
+    totals: RenderTimings,
+    /// Phases under way, innermost last, each with when its clock last started
+    running: Vec<(Phase, Instant)>,
+}
+
+tokio::task_local! {
+    static RECORDER: RefCell<Recorder>;
+}
+
+/// Run `render`, timing the phases it marks
+pub async fn measure<T>(render: impl Future<Output = T>) -> (T, RenderTimings) {
+    RECORDER
+        .scope(RefCell::default(), async {
+            let result = render.await;
+            (result, RECORDER.with(|recorder| recorder.borrow().totals))
+        })
+        .await
+}
+
+/// Time `run` as `phase`, when something is measuring
+pub fn phase<T>(phase: Phase, run: impl FnOnce() -> T) -> T {
+    let _timer = PhaseTimer::start(phase);
+    run()
+}
+
+/// One phase under way, counted until it's dropped
+pub struct PhaseTimer {
+    measuring: bool,
+}
+
+impl PhaseTimer {
+    pub fn start(phase: Phase) -> Self {
+        let measuring = RECORDER
+            .try_with(|recorder| {
+                let now = Instant::now();
+                let recorder = &mut *recorder.borrow_mut();
+                if let Some((outer, started)) = recorder.running.last_mut() {
+                    recorder.totals.add(*outer, now - *started);
+                    *started = now;
+                }
+                recorder.running.push((phase, now));
+            })
+            .is_ok();
+        PhaseTimer { measuring }
+    }
+}
+
+impl Drop for PhaseTimer {
+    fn drop(&mut self) {
+        if !self.measuring {
+            return;
+        }
+        let _ = RECORDER.try_with(|recorder| {
+            let now = Instant::now();
+            let recorder = &mut *recorder.borrow_mut();
+            if let Some((phase, started)) = recorder.running.pop() {
+                recorder.totals.add(phase, now - started);
+            }
+            if let Some((_, started)) = recorder.running.last_mut() {
+                *started = now;
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn busy(duration: Duration) {
+        let start = Instant::now();
+        while start.elapsed() < duration {}
+    }
+
+    #[tokio::test]
+    async fn test_nested_phases_are_counted_once() {
+        let start = Instant::now();
+        let ((), timings) = measure(async {
+            phase(Phase::Template, || {
+                busy(Duration::from_millis(10));
+                phase(Phase::Markdown, || busy(Duration::from_millis(20)));
+            });
+            phase(Phase::Highlight, || busy(Duration::from_millis(5)));
+        })
+        .await;
+        let elapsed = start.elapsed();
+
+        assert!(timings.template >= Duration::from_millis(10), "{timings:?}");
+        assert!(timings.template + timings.markdown + timings.highlight <= elapsed, "{timings:?} in {elapsed:?}");
+        assert!(timings.markdown >= Duration::from_millis(20), "{timings:?}");
+        assert!(timings.highlight >= Duration::from_millis(5), "{timings:?}");
+        assert_eq!(timings.minify, Duration::ZERO);
+
+        // Outside `measure` there's nothing to record into, and nothing goes wrong
+        assert_eq!(phase(Phase::Template, || 1), 1);
+    }
+}
@@ -0,0 +1,283 @@
+//! `hugs upgrade`: move a site made for an older Hugs onto what this one expects, when a change
+//! like a setting taking a new shape means editing the site's files.
+//!
+//! Each change is a migration with a level. `upgrade_level` in config.toml records the last one
+//! the site has had, so running `hugs upgrade` again only looks at newer ones. A migration only
+//! edits a site that still has the old layout, so a site made after it just has its level raised.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use serde::Deserialize;
+use toml::Spanned;
+
+use crate::config::SiteConfig;
+use crate::console;
+use crate::error::{HugsError, Result};
+use crate::migrate::print_diff;
+use crate::text_output::write_atomically;
+
+const CONFIG_FILE: &str = "config.toml";
+
+/// One change to how sites are laid out
+struct Migration {
+    /// What `upgrade_level` becomes once the site has had it
+    level: u32,
+    /// What it does, as a line of output
+    summary: &'static str,
+    /// Whether the site still has the old layout
+    detect: fn(&Site) -> bool,
+    /// Make the change, in the site's files as the earlier migrations left them
+    apply: fn(&mut Site) -> std::io::Result<()>,
+}
+
+/// Every migration, lowest level first. A new one goes at the end, with the next level.
+const MIGRATIONS: &[Migration] = &[Migration {
+    level: 1,
+    summary: "`minify = true` or `false` becomes a `[build.minify]` table, where `js` and `svg` can go too",
+    detect: minify_is_bool,
+    apply: minify_table,
+}];
+
+/// The site's files as the migrations so far have left them. Nothing is written until they've
+/// all run.
+struct Site {
+    path: PathBuf,
+    /// New contents of the files changed so far, by path relative to the site
+    changed: BTreeMap<String, String>,
+}
+
+impl Site {
+    /// `file`, relative to the site, as it is now, or empty when it doesn't exist
+    fn read(&self, file: &str) -> std::io::Result<String> {
+        match self.changed.get(file) {
+            Some(text) => Ok(text.clone()),
+            None => self.original(file),
+        }
+    }
+
+    /// `file` as it was before any migration
+    fn original(&self, file: &str) -> std::io::Result<String> {
+        let path = self.path.join(file);
+        if !path.exists() {
+            return Ok(String::new());
+        }
+        std::fs::read_to_string(&path).map_err(|e| std::io::Error::new(e.kind(), format!("{}: {}", file, e)))
+    }
+
+    fn write(&mut self, file: &str, text: String) {
+        self.changed.insert(file.to_string(), text);
+    }
+}
+
+/// Apply the migrations the site at `site_path` hasn't had and record its new level. With
+/// `dry_run`, print what would change instead.
+pub async fn run_upgrade(site_path: PathBuf, dry_run: bool) -> Result<()> {
+    let config = SiteConfig::load(&site_path).await?;
+    let latest = MIGRATIONS.last().map_or(0, |migration| migration.level);
+    if config.upgrade_level >= latest {
+        console::status("Upgraded", format!("{} is up to date (level {})", site_path.display(), config.upgrade_level));
+        return Ok(());
+    }
+
+    let read_error = |cause| HugsError::FileRead { path: (&site_path).into(), cause };
+    let mut site = Site { path: site_path.clone(), changed: BTreeMap::new() };
+    for migration in MIGRATIONS.iter().filter(|migration| migration.level > config.upgrade_level) {
+        if !(migration.detect)(&site) {
+            continue;
+        }
+        let verb = if dry_run { "Would apply" } else { "Applying" };
+        console::status(verb, migration.summary);
+        (migration.apply)(&mut site).map_err(read_error)?;
+    }
+    let config_source = site.read(CONFIG_FILE).map_err(read_error)?;
+    site.write(CONFIG_FILE, with_upgrade_level(&config_source, latest));
+
+    for (file, after) in &site.changed {
+        let path = site_path.join(file);
+        if dry_run {
+            print_diff(file, &site.original(file).map_err(read_error)?, after);
+        } else {
+            write_atomically(&path, after)?;
+        }
+    }
+
+    let verb = if dry_run { "Would upgrade" } else { "Upgraded" };
+    console::status(verb, format!("{} from level {} to {}", site_path.display(), config.upgrade_level, latest));
+    Ok(())
+}
+
+/// Where `upgrade_level` is written in config.toml
+#[derive(Deserialize)]
+struct UpgradeLevelLocation {
+    upgrade_level: Option<Spanned<toml::Value>>,
+}
+
+/// `config` with `upgrade_level` set to `level`, written in place when it's there already and at
+/// the top otherwise, where a key outside any table has to go
+fn with_upgrade_level(config: &str, level: u32) -> String {
+    if let Some(span) = toml::from_str::<UpgradeLevelLocation>(config)
+        .ok()
+        .and_then(|location| location.upgrade_level)
+        .map(|value| value.span())
+    {
+        return format!("{}{}{}", &config[..span.start], level, &config[span.end..]);
+    }
+    let separator = if config.is_empty() { "" } else { "\n" };
+    format!("# The last `hugs upgrade` this site has had\nupgrade_level = {}\n{}{}", level, separator, config)
+}
+
+/// Where `build.minify` is written in config.toml
+#[derive(Deserialize)]
+struct MinifyLocation {
+    build: Option<BuildMinifyLocation>,
+}
+
+#[derive(Deserialize)]
+struct BuildMinifyLocation {
+    minify: Option<Spanned<toml::Value>>,
+}
+
+/// The `minify` value in config.toml when it's `true` or `false`, and where it is
+fn minify_bool(config: &str) -> Option<(bool, std::ops::Range<usize>)> {
+    let value = toml::from_str::<MinifyLocation>(config).ok()?.build?.minify?;
+    let span = value.span();
+    value.into_inner().as_bool().map(|minify| (minify, span))
+}
+
+fn minify_is_bool(site: &Site) -> bool {
+    site.read(CONFIG_FILE).is_ok_and(|config| minify_bool(&config).is_some())
+}
+
+/// `minify = true` under `[build]` becomes `[build.minify]` with `html` and `css` set, which
+/// is what the bool meant. Written any other way, like `build.minify = true`, the value becomes
+/// an inline table in place.
+fn minify_table(site: &mut Site) -> std::io::Result<()> {
+    let config = site.read(CONFIG_FILE)?;
+    let Some((minify, span)) = minify_bool(&config) else {
+        return Ok(());
+    };
+
+    let line_start = config[..span.start].rfind('\n').map_or(0, |newline| newline + 1);
+    let line_end = config[span.end..].find('\n').map_or(config.len(), |newline| span.end + newline + 1);
+    let in_build_table = config[line_start..span.start].trim_start().starts_with("minify")
+        && config[..line_start].lines().rev().find_map(table_header) == Some("build");
+
+    let upgraded = if in_build_table {
+        // The table goes where the `[build]` table ends, at the next header or the end of the file
+        let mut section_end = config.len();
+        let mut offset = line_end;
+        for line in config[line_end..].split_inclusive('\n') {
+            if table_header(line).is_some() {
+                section_end = offset;
+                break;
+            }
+            offset += line.len();
+        }
+        let build = format!("{}{}", &config[..line_start], &config[line_end..section_end]);
+        // A comment after the old value moves up to the table's header
+        let comment = config[span.end..line_end].trim();
+        let header = if comment.starts_with('#') { format!("[build.minify]  {}", comment) } else { String::from("[build.minify]") };
+        let table = format!("{}\nhtml = {1}\ncss = {1}\n", header, minify);
+        let rest = &config[section_end..];
+        if rest.is_empty() {
+            format!("{}\n\n{}", build.trim_end(), table)
+        } else {
+            format!("{}\n\n{}\n{}", build.trim_end(), table, rest)
+        }
+    } else {
+        format!("{}{{ html = {minify}, css = {minify} }}{}", &config[..span.start], &config[span.end..])
+    };
+    site.write(CONFIG_FILE, upgraded);
+    Ok(())
+}
+
+/// The table a line like `[build]` or `[build.minify]  # comment` starts, without the brackets.
+/// Arrays of tables, like `[[feeds]]`, count too.
+fn table_header(line: &str) -> Option<&str> {
+    let line = line.split('#').next()?.trim();
+    let name = line.strip_prefix('[')?.strip_suffix(']')?;
+    let name = name.strip_prefix('[').and_then(|name| name.strip_suffix(']')).unwrap_or(name);
+    Some(name.trim())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn site_with_config(config: &str) -> tempfile::TempDir {
+        let site = tempfile::tempdir().unwrap();
+        std::fs::write(site.path().join(CONFIG_FILE), config).unwrap();
+        site
+    }
+
+    fn config_of(site: &tempfile::TempDir) -> String {
+        std::fs::read_to_string(site.path().join(CONFIG_FILE)).unwrap()
+    }
+
+    const LEGACY: &str = "# My site\n[site]\ntitle = \"Notes\"\n\n[build]\nminify = false  # keep it readable\nreading_speed = 200\n\n[build.syntax_highlighting]\ntheme = \"nord\"\n";
+
+    #[tokio::test]
+    async fn test_a_dry_run_changes_nothing() {
+        let site = site_with_config(LEGACY);
+        run_upgrade(site.path().to_path_buf(), true).await.unwrap();
+        assert_eq!(config_of(&site), LEGACY);
+    }
+
+    #[tokio::test]
+    async fn test_upgrading_moves_minify_to_a_table_once() {
+        let site = site_with_config(LEGACY);
+        run_upgrade(site.path().to_path_buf(), false).await.unwrap();
+        let upgraded = config_of(&site);
+        assert_eq!(
+            upgraded,
+            "# The last `hugs upgrade` this site has had\nupgrade_level = 1\n\n# My site\n[site]\ntitle = \"Notes\"\n\n[build]\nreading_speed = 200\n\n[build.minify]  # keep it readable\nhtml = false\ncss = false\n\n[build.syntax_highlighting]\ntheme = \"nord\"\n"
+        );
+
+        // It means what it meant before
+        let config = SiteConfig::load(&site.path().to_path_buf()).await.unwrap();
+        assert!(!config.build.minify.html() && !config.build.minify.css());
+        assert_eq!(config.build.syntax_highlighting.theme, "nord");
+
+        // Running it again finds nothing to do
+        run_upgrade(site.path().to_path_buf(), false).await.unwrap();
+        assert_eq!(config_of(&site), upgraded);
+    }
+
+    #[tokio::test]
+    async fn test_sites_without_the_old_layout_only_record_their_level() {
+        let current = "[build.minify]\nhtml = true\njs = true\n";
+        let site = site_with_config(current);
+        run_upgrade(site.path().to_path_buf(), false).await.unwrap();
+        assert_eq!(config_of(&site), format!("# The last `hugs upgrade` this site has had\nupgrade_level = 1\n\n{}", current));
+
+        // A level already written is updated where it is
+        assert_eq!(with_upgrade_level("upgrade_level = 0 # old\n[site]\n", 3), "upgrade_level = 3 # old\n[site]\n");
+        assert_eq!(with_upgrade_level("", 1), "# The last `hugs upgrade` this site has had\nupgrade_level = 1\n");
+    }
+
+    #[test]
+    fn test_minify_is_moved_however_it_was_written() {
+        let mut site = Site { path: PathBuf::from("/nowhere"), changed: BTreeMap::new() };
+        for (legacy, upgraded) in [
+            ("build.minify = true\n[site]\n", "build.minify = { html = true, css = true }\n[site]\n"),
+            ("build = { minify = false }\n", "build = { minify = { html = false, css = false } }\n"),
+            ("[build]\nminify = true", "[build]\n\n[build.minify]\nhtml = true\ncss = true\n"),
+        ] {
+            site.write(CONFIG_FILE, legacy.to_string());
+            assert!(minify_is_bool(&site), "{legacy}");
+            minify_table(&mut site).unwrap();
+            let config = site.read(CONFIG_FILE).unwrap();
+            assert_eq!(config, upgraded);
+            assert!(!minify_is_bool(&site) && toml::from_str::<SiteConfig>(&config).is_ok(), "{config}");
+        }
+    }
+
+    #[test]
+    fn test_table_headers_are_recognized() {
+        assert_eq!(table_header("[build]"), Some("build"));
+        assert_eq!(table_header("  [build.minify]  # pages"), Some("build.minify"));
+        assert_eq!(table_header("[[feeds]]"), Some("feeds"));
+        assert_eq!(table_header("minify = [true]"), None);
+    }
+}
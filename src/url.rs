@@ -0,0 +1,458 @@
+//! Page URLs. A `SiteUrl` is a path on the site, decoded (`/my notes/café`) so it lines up with
+//! file names, starting with `/`, and ending with `/` only for a directory index. Every page URL
+//! is built, compared and written out through here, so the slash rules live in one place.
+//!
+//! A `BaseUrl` is `site.url`, where the site is published. It may have a path of its own, like
+//! `https://example.com/blog` for a site hosted under `/blog`, and every absolute URL is made by
+//! [`BaseUrl::absolute_url`] so the page's path always goes after it, exactly once.
+
+use std::borrow::Cow;
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+
+use percent_encoding::{AsciiSet, NON_ALPHANUMERIC, utf8_percent_encode};
+use serde::{Deserialize, Serialize};
+
+use crate::config::{UrlStyle, UrlTransliteration};
+
+/// Characters escaped in emitted URL paths: everything except unreserved characters and `/`
+const URL_PATH_ENCODE_SET: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~')
+    .remove(b'/');
+
+/// Percent-encode a page URL for output (canonical links, sitemap, feeds).
+///
+/// Page URLs stay decoded internally so they line up with file names (`/my notes/café`).
+/// The dev server gets request paths already decoded by actix, so both sides resolve
+/// the same files; this is only applied when a URL is written out.
+pub fn encode_url_path(url: &str) -> String {
+    utf8_percent_encode(url, URL_PATH_ENCODE_SET).to_string()
+}
+
+/// A path relative to the site root (or the content folder) with `/` between its parts, whatever
+/// the platform separates them with, so `blog\post.md` on Windows is `blog/post.md` like everywhere
+/// else. URLs, path classes, dynamic page patterns and the page file names templates see are all
+/// built from this, never from the path's own spelling.
+pub fn relative_path_to_url_string(path: &Path) -> String {
+    path.components()
+        .filter_map(|component| match component {
+            Component::Normal(part) => Some(part.to_string_lossy()),
+            Component::ParentDir => Some(Cow::Borrowed("..")),
+            Component::CurDir | Component::RootDir | Component::Prefix(_) => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SiteUrl(String);
+
+impl SiteUrl {
+    pub fn root() -> Self {
+        Self(String::from("/"))
+    }
+
+    /// The URL of a page file, given relative to the site root: `about.md` is `/about`,
+    /// `blog/index.md` is `/blog/` and `index.md` is `/`
+    pub fn from_file_path(path: &Path, transliteration: UrlTransliteration) -> Self {
+        Self::from_segments(&relative_path_to_url_string(&path.with_extension("")), transliteration)
+    }
+
+    /// The URL of a `/`-separated path without its extension, like `blog/index` or `tags/rust`
+    /// once a dynamic page's value is filled in
+    pub fn from_segments(path: &str, transliteration: UrlTransliteration) -> Self {
+        let path = url_path_segments(path, transliteration);
+
+        if path == "index" {
+            // Root index.md -> /
+            Self::root()
+        } else if let Some(dir) = path.strip_suffix("/index") {
+            // Directory index.md -> /path/to/dir/ (with trailing slash for correct relative URL resolution)
+            Self(format!("/{}/", dir))
+        } else {
+            // Regular file -> /path/to/file
+            Self(format!("/{}", path))
+        }
+    }
+
+    /// A URL someone wrote, in frontmatter, config.toml or a request. It's taken as written, with
+    /// a leading `/` added when it's missing.
+    pub fn parse(url: &str) -> Self {
+        if url.starts_with('/') { Self(url.to_string()) } else { Self(format!("/{}", url)) }
+    }
+
+    /// The URL as a string, like `/blog/first-post`
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Whether this is a directory index (or the root), which is where relative links resolve from
+    pub fn is_index(&self) -> bool {
+        self.0.ends_with('/')
+    }
+
+    /// Whether both name the same page, however their slashes are written: `/blog`, `blog/` and
+    /// `/blog/` all do
+    pub fn same_page(&self, other: &str) -> bool {
+        self.0.trim_matches('/') == other.trim_matches('/')
+    }
+
+    /// Whether `other` is this page written in either `url_style`: `/about`, `/about.html` and
+    /// `/about/index.html` all name the page at `/about.html`, and the page at `/about`
+    pub fn same_page_in_any_style(&self, other: &str) -> bool {
+        if self.is_index() {
+            return self.same_page(other);
+        }
+        let bare = |url: &str| {
+            let url = url.trim_matches('/');
+            url.strip_suffix("/index.html").or_else(|| url.strip_suffix(".html")).unwrap_or(url).to_string()
+        };
+        bare(&self.0) == bare(other)
+    }
+
+    /// This URL the way `url_style` writes it. In the `html` style a page that isn't a directory
+    /// index is an HTML file, `/about.html`; indexes keep their trailing slash either way, and a
+    /// URL that already ends in `.html` is left as it is.
+    pub fn in_style(self, style: UrlStyle) -> Self {
+        match style {
+            UrlStyle::Html if !self.is_index() && !self.is_html_file() => Self(format!("{}.html", self.0)),
+            _ => self,
+        }
+    }
+
+    /// Whether this names an HTML file rather than a directory, like `/about.html`
+    fn is_html_file(&self) -> bool {
+        !self.is_index() && self.0.ends_with(".html")
+    }
+
+    /// Whether this page is under the `prefix` directory, leaving out the directory's own index.
+    /// Whole path segments are compared, so `/blogroll` isn't within `/blog`.
+    pub fn is_within(&self, prefix: &str) -> bool {
+        let dir = prefix.trim_end_matches('/');
+        self.0.strip_prefix(dir).and_then(|rest| rest.strip_prefix('/')).is_some_and(|rest| !rest.is_empty())
+    }
+
+    /// Whether this page is directly in the `prefix` directory: a page there, or the index of one
+    /// of its subdirectories, but nothing deeper
+    pub fn is_child_of(&self, prefix: &str) -> bool {
+        self.is_within(prefix) && !self.0[prefix.trim_end_matches('/').len() + 1..].trim_end_matches('/').contains('/')
+    }
+
+    /// This URL with a trailing slash, the form the sitemap lists. An HTML file has none.
+    pub fn with_trailing_slash(&self) -> Self {
+        if self.is_index() || self.is_html_file() { self.clone() } else { Self(format!("{}/", self.0)) }
+    }
+
+    /// This URL without a trailing slash, the form canonical links use. The root stays `/`.
+    pub fn without_trailing_slash(&self) -> Self {
+        match self.0.trim_end_matches('/') {
+            "" => Self::root(),
+            trimmed => Self(trimmed.to_string()),
+        }
+    }
+
+    /// The percent-encoded path, for writing into HTML, XML and headers
+    pub fn encoded(&self) -> Cow<'_, str> {
+        utf8_percent_encode(&self.0, URL_PATH_ENCODE_SET).into()
+    }
+
+    /// Where a page with this URL is written, relative to the output directory
+    pub fn output_path(&self) -> PathBuf {
+        let dir = self.0.trim_matches('/');
+        if dir.is_empty() {
+            // / -> index.html
+            PathBuf::from("index.html")
+        } else if self.is_html_file() {
+            // /about.html -> about.html
+            PathBuf::from(dir)
+        } else {
+            // /about and /blog/ -> about/index.html and blog/index.html
+            Path::new(dir).join("index.html")
+        }
+    }
+}
+
+impl AsRef<str> for SiteUrl {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for SiteUrl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Templates see page URLs as plain strings
+impl Serialize for SiteUrl {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0)
+    }
+}
+
+impl PartialEq<str> for SiteUrl {
+    fn eq(&self, other: &str) -> bool {
+        self.0 == other
+    }
+}
+
+impl PartialEq<&str> for SiteUrl {
+    fn eq(&self, other: &&str) -> bool {
+        self.0 == *other
+    }
+}
+
+impl PartialEq<String> for SiteUrl {
+    fn eq(&self, other: &String) -> bool {
+        &self.0 == other
+    }
+}
+
+/// `site.url`: the absolute http(s) URL the site is published at, checked when the config loads.
+/// It's kept without a trailing slash, so `https://example.com/blog/` is `https://example.com/blog`.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct BaseUrl(::url::Url);
+
+impl BaseUrl {
+    /// The absolute, percent-encoded URL of `url` on the site, like
+    /// `https://example.com/blog/my%20notes` for `/my notes` under `https://example.com/blog`
+    pub fn absolute_url(&self, url: &SiteUrl) -> String {
+        format!("{}{}", self, url.encoded())
+    }
+
+    /// Whether the site lives under a path on its host, rather than at its root
+    pub fn has_path(&self) -> bool {
+        self.0.path() != "/"
+    }
+}
+
+impl TryFrom<String> for BaseUrl {
+    type Error = String;
+
+    fn try_from(value: String) -> std::result::Result<Self, Self::Error> {
+        let invalid = |why: &str| format!("`{}` isn't a URL I can publish the site at: {}", value, why);
+        let mut url = ::url::Url::parse(value.trim()).map_err(|e| invalid(&format!("{}. Write it out in full, like `https://example.com`", e)))?;
+        if !matches!(url.scheme(), "http" | "https") || url.host_str().is_none_or(str::is_empty) {
+            return Err(invalid("it needs to start with http:// or https:// and a host, like `https://example.com`"));
+        }
+        if url.query().is_some() || url.fragment().is_some() {
+            return Err(invalid("page paths go after it, so it can't have a `?query` or `#fragment`"));
+        }
+
+        let path = url.path().trim_end_matches('/').to_string();
+        url.set_path(&path);
+        Ok(BaseUrl(url))
+    }
+}
+
+impl From<BaseUrl> for String {
+    fn from(base: BaseUrl) -> Self {
+        base.to_string()
+    }
+}
+
+impl fmt::Display for BaseUrl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.0.as_str().trim_end_matches('/'))
+    }
+}
+
+/// The page a request path asks for, without the `.html` the `html` url_style adds, so
+/// `blog/post.html` and `blog/post` find the same page file whichever style the site uses
+pub fn without_html_suffix(path: &str) -> &str {
+    match path.strip_suffix(".html") {
+        Some(bare) if !bare.is_empty() && !bare.ends_with('/') && bare != "index" && !bare.ends_with("/index") => bare,
+        _ => path,
+    }
+}
+
+/// A `/`-separated path with each segment spelled the way `transliteration` asks
+fn url_path_segments(path: &str, transliteration: UrlTransliteration) -> String {
+    match transliteration {
+        UrlTransliteration::Keep => path.to_string(),
+        UrlTransliteration::Ascii => path.split('/').map(|segment| url_segment(segment, transliteration)).collect::<Vec<_>>().join("/"),
+    }
+}
+
+/// A file or folder name, or a dynamic parameter value, as it appears in a URL. In ASCII it's
+/// transliterated, lowercased and everything but letters, digits, `.`, `_` and `~` becomes a single
+/// `-`: `Grüße aus Köln` is `grusse-aus-koln` and `日本語` is `ri-ben-yu`. A segment with nothing
+/// left to spell stays as written.
+fn url_segment(segment: &str, transliteration: UrlTransliteration) -> String {
+    if transliteration == UrlTransliteration::Keep {
+        return segment.to_string();
+    }
+
+    let mut slug = String::with_capacity(segment.len());
+    for c in deunicode::deunicode(segment).chars() {
+        if c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '~') {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    let slug = slug.trim_end_matches('-');
+
+    if slug.is_empty() { segment.to_string() } else { slug.to_string() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use UrlTransliteration::{Ascii, Keep};
+
+    #[test]
+    fn test_file_paths_to_urls_outputs_and_canonicals() {
+        // file, transliteration, URL, output file, sitemap loc, canonical
+        let cases = [
+            ("index.md", Keep, "/", "index.html", "https://example.com/", "https://example.com/"),
+            ("about.md", Keep, "/about", "about/index.html", "https://example.com/about/", "https://example.com/about"),
+            ("blog/index.md", Keep, "/blog/", "blog/index.html", "https://example.com/blog/", "https://example.com/blog"),
+            ("blog/2024/recap.md", Keep, "/blog/2024/recap", "blog/2024/recap/index.html", "https://example.com/blog/2024/recap/", "https://example.com/blog/2024/recap"),
+            ("docs/guide/index.md", Keep, "/docs/guide/", "docs/guide/index.html", "https://example.com/docs/guide/", "https://example.com/docs/guide"),
+            ("my notes/café.md", Keep, "/my notes/café", "my notes/café/index.html", "https://example.com/my%20notes/caf%C3%A9/", "https://example.com/my%20notes/caf%C3%A9"),
+            ("Über uns/index.md", Ascii, "/uber-uns/", "uber-uns/index.html", "https://example.com/uber-uns/", "https://example.com/uber-uns"),
+            ("Über uns/index.md", Keep, "/Über uns/", "Über uns/index.html", "https://example.com/%C3%9Cber%20uns/", "https://example.com/%C3%9Cber%20uns"),
+        ];
+
+        for (file, transliteration, url, output, loc, canonical) in cases {
+            let site_url = SiteUrl::from_file_path(Path::new(file), transliteration);
+            assert_eq!(site_url, url, "URL of {file}");
+            assert_eq!(site_url.output_path(), Path::new(output), "output of {file}");
+            let base = BaseUrl::try_from(String::from("https://example.com/")).unwrap();
+            assert_eq!(base.absolute_url(&site_url.with_trailing_slash()), loc, "sitemap loc of {file}");
+            assert_eq!(base.absolute_url(&site_url.without_trailing_slash()), canonical, "canonical of {file}");
+        }
+    }
+
+    #[test]
+    fn test_relative_paths_are_joined_with_slashes() {
+        let path: PathBuf = ["blog", "2024", "recap.md"].iter().collect();
+        assert_eq!(relative_path_to_url_string(&path), "blog/2024/recap.md");
+        assert_eq!(relative_path_to_url_string(Path::new("./blog/index.md")), "blog/index.md");
+        assert_eq!(relative_path_to_url_string(Path::new("")), "");
+        assert_eq!(SiteUrl::from_file_path(&path, Keep), "/blog/2024/recap");
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_windows_separators_become_slashes() {
+        assert_eq!(relative_path_to_url_string(Path::new(r"blog\2024\recap.md")), "blog/2024/recap.md");
+        assert_eq!(SiteUrl::from_file_path(Path::new(r"blog\index.md"), Keep), "/blog/");
+        assert_eq!(SiteUrl::from_file_path(Path::new(r"Über uns\index.md"), Ascii), "/uber-uns/");
+    }
+
+    #[test]
+    fn test_dynamic_values_to_urls() {
+        // path with the value filled in, transliteration, URL, output file
+        let cases = [
+            ("tags/rust", Keep, "/tags/rust", "tags/rust/index.html"),
+            ("tags/hello world", Keep, "/tags/hello world", "tags/hello world/index.html"),
+            ("tags/Hello World", Ascii, "/tags/hello-world", "tags/hello-world/index.html"),
+            ("archive/2024/index", Keep, "/archive/2024/", "archive/2024/index.html"),
+            ("index", Keep, "/", "index.html"),
+        ];
+
+        for (path, transliteration, url, output) in cases {
+            let site_url = SiteUrl::from_segments(path, transliteration);
+            assert_eq!(site_url, url, "URL of {path}");
+            assert_eq!(site_url.output_path(), Path::new(output), "output of {path}");
+        }
+    }
+
+    #[test]
+    fn test_html_url_style() {
+        // file, URL, output file, sitemap loc
+        let cases = [
+            ("index.md", "/", "index.html", "/"),
+            ("about.md", "/about.html", "about.html", "/about.html"),
+            ("blog/index.md", "/blog/", "blog/index.html", "/blog/"),
+            ("blog/2024/recap.md", "/blog/2024/recap.html", "blog/2024/recap.html", "/blog/2024/recap.html"),
+        ];
+        for (file, url, output, loc) in cases {
+            let site_url = SiteUrl::from_file_path(Path::new(file), Keep).in_style(UrlStyle::Html);
+            assert_eq!(site_url, url, "URL of {file}");
+            assert_eq!(site_url.output_path(), Path::new(output), "output of {file}");
+            assert_eq!(site_url.with_trailing_slash(), loc, "sitemap loc of {file}");
+        }
+        assert_eq!(SiteUrl::parse("/about").in_style(UrlStyle::Directory), "/about");
+        assert_eq!(SiteUrl::parse("/old.html").in_style(UrlStyle::Html), "/old.html");
+
+        // Either style's URL finds the page in the other
+        for written in ["/about", "about/", "/about.html", "/about/index.html"] {
+            assert!(SiteUrl::parse("/about.html").same_page_in_any_style(written), "{written}");
+            assert!(SiteUrl::parse("/about").same_page_in_any_style(written), "{written}");
+        }
+        assert!(!SiteUrl::parse("/blog/").same_page_in_any_style("/blog.html"));
+
+        assert_eq!(without_html_suffix("blog/post.html"), "blog/post");
+        for kept in ["blog/post", "index.html", "blog/index.html", ".html"] {
+            assert_eq!(without_html_suffix(kept), kept);
+        }
+    }
+
+    #[test]
+    fn test_comparing_urls() {
+        let post = SiteUrl::parse("/blog/first-post");
+        for prefix in ["/blog", "/blog/", "/", ""] {
+            assert!(post.is_within(prefix), "{post} should be within {prefix:?}");
+        }
+        assert!(!post.is_within("/blo"));
+        assert!(!SiteUrl::parse("/blogroll").is_within("/blog"));
+        assert!(!SiteUrl::parse("/blog/").is_within("/blog"));
+        assert!(!SiteUrl::parse("/blog").is_within("/blog/"));
+        assert!(!SiteUrl::root().is_within("/"));
+
+        assert!(post.is_child_of("/blog/") && SiteUrl::parse("/blog/2024/").is_child_of("/blog"));
+        assert!(!SiteUrl::parse("/blog/2024/recap").is_child_of("/blog") && !post.is_child_of("/"));
+
+        assert_eq!(SiteUrl::parse("blog/first-post"), post);
+        for written in ["/blog/first-post", "blog/first-post/", "/blog/first-post/"] {
+            assert!(post.same_page(written), "{post} should be the page at {written}");
+        }
+        assert!(!post.same_page("/blog/first"));
+
+        assert_eq!(encode_url_path("/blog/first-post/"), "/blog/first-post/");
+        assert_eq!(encode_url_path("/my docs/hello world"), "/my%20docs/hello%20world");
+        assert_eq!(encode_url_path("/notes/a#b?c"), "/notes/a%23b%3Fc");
+        assert_eq!(encode_url_path("/café/100%"), "/caf%C3%A9/100%25");
+    }
+
+    #[test]
+    fn test_url_segments_in_ascii() {
+        let ascii = |segment| url_segment(segment, Ascii);
+        assert_eq!(ascii("Grüße aus Köln"), "grusse-aus-koln");
+        assert_eq!(ascii("日本語"), "ri-ben-yu");
+        assert_eq!(ascii("--Hello,  World!--"), "hello-world");
+        assert_eq!(ascii("v1.2_final"), "v1.2_final");
+        assert_eq!(ascii("index"), "index");
+        assert_eq!(url_segment("Grüße", Keep), "Grüße");
+    }
+
+    #[test]
+    fn test_base_urls_with_a_path() {
+        let base = |written: &str| BaseUrl::try_from(written.to_string());
+
+        let blog = base("https://example.com/blog/").unwrap();
+        assert_eq!(blog, base("https://example.com/blog").unwrap());
+        assert_eq!(blog.to_string(), "https://example.com/blog");
+        assert!(blog.has_path());
+        assert_eq!(blog.absolute_url(&SiteUrl::parse("/my notes/")), "https://example.com/blog/my%20notes/");
+        assert_eq!(blog.absolute_url(&SiteUrl::root()), "https://example.com/blog/");
+
+        let root = base("https://example.com/").unwrap();
+        assert_eq!(root.to_string(), "https://example.com");
+        assert!(!root.has_path());
+        assert_eq!(root.absolute_url(&SiteUrl::parse("/about")), "https://example.com/about");
+
+        for invalid in ["example.com", "/blog", "ftp://example.com", "https://example.com/?a=1", "https://example.com/#top"] {
+            assert!(base(invalid).is_err(), "{invalid} should be rejected");
+        }
+    }
+}
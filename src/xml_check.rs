@@ -0,0 +1,141 @@
+//! A check that the feeds and sitemap a build writes are well-formed XML, before they're written.
+//!
+//! Feed readers and search engines drop a document over one bad character, and nothing else in a
+//! build would notice. A document that fails isn't written, and the build warns instead, naming
+//! the page behind it when one page is: `find_culprit` splits the pages in half until the one
+//! that breaks the document on its own is left.
+
+use std::borrow::Cow;
+
+use quick_xml::Reader;
+use quick_xml::events::Event;
+
+/// Whether XML 1.0 allows `c` anywhere in a document. Tabs and line breaks are the only control
+/// characters it takes, even escaped.
+fn is_xml_char(c: char) -> bool {
+    matches!(c, '\t' | '\n' | '\r') || (c >= ' ' && !matches!(c, '\u{FFFE}' | '\u{FFFF}'))
+}
+
+/// `text` without the characters XML can't hold, for titles and descriptions that came with a
+/// stray control character pasted in
+pub fn xml_text(text: &str) -> Cow<'_, str> {
+    if text.chars().all(is_xml_char) {
+        Cow::Borrowed(text)
+    } else {
+        Cow::Owned(text.chars().filter(|&c| is_xml_char(c)).collect())
+    }
+}
+
+/// Line `offset` is on in `xml`, counting from 1
+fn line_at(xml: &str, offset: usize) -> usize {
+    xml[..offset.min(xml.len())].matches('\n').count() + 1
+}
+
+/// Why `xml` isn't a well-formed document, if it isn't
+pub fn check_well_formed(xml: &str) -> Option<String> {
+    if let Some((offset, c)) = xml.char_indices().find(|&(_, c)| !is_xml_char(c)) {
+        return Some(format!("line {} has the character U+{:04X}, which XML doesn't allow", line_at(xml, offset), c as u32));
+    }
+
+    let mut reader = Reader::from_str(xml);
+    let mut open: Vec<String> = Vec::new();
+    let mut roots = 0;
+    loop {
+        let event = match reader.read_event() {
+            Ok(event) => event,
+            Err(e) => return Some(format!("line {}: {}", line_at(xml, reader.error_position() as usize), e)),
+        };
+        let position = || line_at(xml, reader.buffer_position() as usize);
+        match event {
+            Event::Start(ref tag) | Event::Empty(ref tag) => {
+                if open.is_empty() {
+                    roots += 1;
+                }
+                if let Some(Err(e)) = tag.attributes().find(Result::is_err) {
+                    return Some(format!("line {}: {}", position(), e));
+                }
+                if matches!(event, Event::Start(_)) {
+                    open.push(tag.name().as_ref().to_string());
+                }
+            }
+            Event::End(_) => {
+                open.pop();
+            }
+            Event::GeneralRef(reference) => {
+                let known = match reference.resolve_char_ref() {
+                    Ok(Some(c)) => is_xml_char(c),
+                    Ok(None) => matches!(reference.as_ref(), "lt" | "gt" | "amp" | "apos" | "quot"),
+                    Err(_) => false,
+                };
+                if !known {
+                    return Some(format!("line {}: &{}; isn't a character XML knows", position(), reference.as_ref()));
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    match (open.last(), roots) {
+        (Some(tag), _) => Some(format!("it ends before <{}> is closed", tag)),
+        (None, 0) => Some(String::from("it has no root element")),
+        (None, 1) => None,
+        (None, _) => Some(String::from("it has more than one root element")),
+    }
+}
+
+/// The index of the item in `items` that makes `fails` fail on its own, found by trying each
+/// half of the items that fail. None when no single item does, like when the document fails
+/// with no items at all.
+pub fn find_culprit<T>(items: &[T], fails: impl Fn(&[T]) -> bool) -> Option<usize> {
+    if fails(&[]) {
+        return None;
+    }
+    let mut range = 0..items.len();
+    while range.len() > 1 {
+        let middle = range.start + range.len() / 2;
+        range = if fails(&items[range.start..middle]) {
+            range.start..middle
+        } else if fails(&items[middle..range.end]) {
+            middle..range.end
+        } else {
+            return None;
+        };
+    }
+    (range.len() == 1 && fails(&items[range.clone()])).then_some(range.start)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_well_formed_documents_pass() {
+        assert_eq!(check_well_formed("<?xml version=\"1.0\"?>\n<rss a=\"&amp;\"><item>1 &lt; 2 &#233;</item><br/></rss>"), None);
+
+        for (xml, reason) in [
+            ("<rss><item>\u{8}</item></rss>", "U+0008"),
+            ("<rss><item>&#8;</item></rss>", "&#8;"),
+            ("<rss><item>&nbsp;</item></rss>", "&nbsp;"),
+            ("<urlset>\n<url><loc>/a</loc></url>\n<url><lo", "line 3"),
+            ("<urlset><url></urlset>", "line 1"),
+            ("<rss><item>", "<item>"),
+            ("<a/><b/>", "more than one root"),
+            ("<a x=\"1\" x=\"2\"/>", "line 1"),
+        ] {
+            let found = check_well_formed(xml);
+            assert!(found.as_deref().is_some_and(|found| found.contains(reason)), "{xml}: {found:?}");
+        }
+    }
+
+    #[test]
+    fn test_the_item_that_breaks_a_document_is_found() {
+        let items: Vec<u32> = (0..13).collect();
+        assert_eq!(find_culprit(&items, |items| items.contains(&9)), Some(9));
+        assert_eq!(find_culprit(&items, |items| items.contains(&0)), Some(0));
+        // Nothing fails alone
+        assert_eq!(find_culprit(&items, |items| items.contains(&3) && items.contains(&11)), None);
+        assert_eq!(find_culprit(&items, |_| true), None);
+        assert_eq!(xml_text("Tab\tand back\u{8}space"), "Tab\tand backspace");
+    }
+}
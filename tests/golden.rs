@@ -0,0 +1,362 @@
+//! Golden-file tests for the full build pipeline.
+//!
+//! Every directory in `tests/fixtures/` is a fixture: `site/` is built with `hugs build`
+//! and each emitted file is compared against its counterpart in `expected/`.
+//! Adding a fixture is just adding a directory.
+//!
+//! To regenerate the expected output after an intentional change, run:
+//!
+//!     HUGS_UPDATE_GOLDEN=1 cargo test --test golden
+
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use similar::{ChangeTag, TextDiff};
+use walkdir::WalkDir;
+
+const UPDATE_ENV: &str = "HUGS_UPDATE_GOLDEN";
+
+fn fixtures_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures")
+}
+
+/// Relative paths of every file under `root`, sorted
+fn list_files(root: &Path) -> BTreeSet<PathBuf> {
+    if !root.exists() {
+        return BTreeSet::new();
+    }
+
+    WalkDir::new(root)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .map(|e| e.path().strip_prefix(root).unwrap().to_path_buf())
+        .collect()
+}
+
+/// Build a fixture site into a fresh temp dir
+fn build_fixture(site: &Path) -> tempfile::TempDir {
+    let output = tempfile::tempdir().expect("failed to create temp dir");
+    build_into(site, &output.path().join("dist"), &["--no-cache"]);
+    output
+}
+
+/// Build a site into `dist` with `args`, replacing an earlier build there
+fn build_into(site: &Path, dist: &Path, args: &[&str]) {
+    let result = Command::new(env!("CARGO_BIN_EXE_hugs"))
+        .arg("build")
+        .args(args)
+        .arg(site)
+        .arg("--output")
+        .arg(dist)
+        .output()
+        .expect("failed to run hugs");
+
+    assert!(
+        result.status.success(),
+        "hugs build failed for {}:\n{}",
+        site.display(),
+        String::from_utf8_lossy(&result.stderr)
+    );
+}
+
+/// A readable unified-style diff of two text files
+fn render_diff(expected: &str, actual: &str) -> String {
+    let diff = TextDiff::from_lines(expected, actual);
+    let mut out = String::new();
+
+    for group in diff.grouped_ops(2) {
+        out.push_str("  ...\n");
+        for op in group {
+            for change in diff.iter_changes(&op) {
+                let sign = match change.tag() {
+                    ChangeTag::Delete => "- ",
+                    ChangeTag::Insert => "+ ",
+                    ChangeTag::Equal => "  ",
+                };
+                out.push_str(sign);
+                out.push_str(change.value());
+                if change.missing_newline() {
+                    out.push('\n');
+                }
+            }
+        }
+    }
+
+    out
+}
+
+/// Compare a fixture's build output against its expected files, returning a report per mismatch
+fn check_fixture(fixture: &Path) -> Vec<String> {
+    let name = fixture.file_name().unwrap().to_string_lossy().to_string();
+    let build = build_fixture(&fixture.join("site"));
+    let actual_dir = build.path().join("dist");
+    let expected_dir = fixture.join("expected");
+
+    if std::env::var_os(UPDATE_ENV).is_some() {
+        if expected_dir.exists() {
+            std::fs::remove_dir_all(&expected_dir).unwrap();
+        }
+        for relative in list_files(&actual_dir) {
+            let dest = expected_dir.join(&relative);
+            std::fs::create_dir_all(dest.parent().unwrap()).unwrap();
+            std::fs::copy(actual_dir.join(&relative), dest).unwrap();
+        }
+        return Vec::new();
+    }
+
+    let actual_files = list_files(&actual_dir);
+    let expected_files = list_files(&expected_dir);
+    let mut failures = Vec::new();
+
+    for relative in expected_files.difference(&actual_files) {
+        failures.push(format!("[{}] {} was expected but not emitted", name, relative.display()));
+    }
+    for relative in actual_files.difference(&expected_files) {
+        failures.push(format!("[{}] {} was emitted but isn't expected", name, relative.display()));
+    }
+
+    for relative in actual_files.intersection(&expected_files) {
+        let actual = std::fs::read(actual_dir.join(relative)).unwrap();
+        let expected = std::fs::read(expected_dir.join(relative)).unwrap();
+        if actual == expected {
+            continue;
+        }
+
+        let report = match (String::from_utf8(expected), String::from_utf8(actual)) {
+            (Ok(expected), Ok(actual)) => render_diff(&expected, &actual),
+            _ => String::from("  (binary files differ)\n"),
+        };
+        failures.push(format!("[{}] {} differs:\n{}", name, relative.display(), report));
+    }
+
+    failures
+}
+
+#[test]
+fn golden_fixtures_match() {
+    let mut fixtures: Vec<PathBuf> = std::fs::read_dir(fixtures_dir())
+        .expect("tests/fixtures should exist")
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.join("site").is_dir())
+        .collect();
+    fixtures.sort();
+    assert!(!fixtures.is_empty(), "no fixtures found in tests/fixtures");
+
+    let failures: Vec<String> = fixtures.iter().flat_map(|f| check_fixture(f)).collect();
+
+    assert!(
+        failures.is_empty(),
+        "{} golden file mismatch(es):\n\n{}\n\nIf these changes are intentional, regenerate with {}=1 cargo test --test golden",
+        failures.len(),
+        failures.join("\n"),
+        UPDATE_ENV
+    );
+}
+
+/// Copy a fixture's site into a temp dir so a test can edit it
+fn copy_site(site: &Path) -> tempfile::TempDir {
+    let copy = tempfile::tempdir().expect("failed to create temp dir");
+    for relative in list_files(site) {
+        let dest = copy.path().join(&relative);
+        std::fs::create_dir_all(dest.parent().unwrap()).unwrap();
+        std::fs::copy(site.join(&relative), dest).unwrap();
+    }
+    copy
+}
+
+#[test]
+fn pretty_output_diffs_one_line_per_changed_word() {
+    let site = copy_site(&fixtures_dir().join("pretty/site"));
+    let page = |build: &tempfile::TempDir| std::fs::read_to_string(build.path().join("dist/index.html")).unwrap();
+
+    let before = page(&build_fixture(site.path()));
+    let index = site.path().join("index.md");
+    let source = std::fs::read_to_string(&index).unwrap();
+    std::fs::write(&index, source.replace("cleanly", "neatly")).unwrap();
+    let after = page(&build_fixture(site.path()));
+
+    let diff = TextDiff::from_lines(&before, &after);
+    let changed: Vec<(ChangeTag, &str)> = diff
+        .iter_all_changes()
+        .filter(|change| change.tag() != ChangeTag::Equal)
+        .map(|change| (change.tag(), change.value()))
+        .collect();
+
+    assert_eq!(changed.len(), 2, "expected a one-line diff:\n{}", render_diff(&before, &after));
+    assert!(changed[0].1.contains("cleanly") && changed[1].1.contains("neatly"));
+}
+
+#[test]
+fn strict_builds_write_everything_then_fail_on_warnings() {
+    // The basic fixture has a static file in the way of a page, which is only a warning
+    let output = tempfile::tempdir().expect("failed to create temp dir");
+    let result = Command::new(env!("CARGO_BIN_EXE_hugs"))
+        .arg("build")
+        .arg("--no-cache")
+        .arg(fixtures_dir().join("basic/site"))
+        .arg("--output")
+        .arg(output.path().join("dist"))
+        .arg("--strict")
+        .output()
+        .expect("failed to run hugs");
+
+    let stderr = String::from_utf8_lossy(&result.stderr);
+    assert_eq!(result.status.code(), Some(2), "expected the strict exit code:\n{}", stderr);
+    assert!(stderr.contains("Warnings by code:") && stderr.contains("hugs::build::output_collision"), "{}", stderr);
+    assert!(output.path().join("dist/index.html").exists() && output.path().join("dist/sitemap.xml").exists());
+
+    // Without warnings there's nothing to fail on
+    build_fixture(&fixtures_dir().join("pretty/site"));
+}
+
+#[test]
+fn keep_going_builds_every_other_page_then_fails() {
+    let site = copy_site(&fixtures_dir().join("pretty/site"));
+    std::fs::write(site.path().join("broken.md"), "---\ntitle: Broken\n---\n\n{{ nope( }}\n").unwrap();
+    std::fs::write(site.path().join("missing.md"), "---\ntitle: Missing\n---\n\n{{ no_such_function() }}\n").unwrap();
+    let build = |args: &[&str]| {
+        let output = tempfile::tempdir().expect("failed to create temp dir");
+        let result = Command::new(env!("CARGO_BIN_EXE_hugs"))
+            .arg("build")
+            .arg("--no-cache")
+            .arg(site.path())
+            .arg("--output")
+            .arg(output.path().join("dist"))
+            .args(args)
+            .output()
+            .expect("failed to run hugs");
+        (result.status.success(), String::from_utf8_lossy(&result.stderr).to_string(), output)
+    };
+
+    // The first failure stops the build
+    let (ok, _, output) = build(&[]);
+    assert!(!ok && !output.path().join("dist/sitemap.xml").exists());
+
+    let (ok, stderr, output) = build(&["--keep-going"]);
+    assert!(!ok, "failed pages should fail the build");
+    assert!(stderr.contains("2 pages failed to build") && stderr.contains("Failures by code:"), "{}", stderr);
+    assert!(stderr.contains("broken.md") && stderr.contains("missing.md"), "{}", stderr);
+    assert!(stderr.contains("hugs::build::pages_failed"), "{}", stderr);
+    let dist = output.path().join("dist");
+    assert!(dist.join("index.html").exists() && dist.join("notes/index.html").exists() && dist.join("sitemap.xml").exists());
+    assert!(!dist.join("broken/index.html").exists());
+    let sitemap = std::fs::read_to_string(dist.join("sitemap.xml")).unwrap();
+    assert!(sitemap.contains("/notes/") && !sitemap.contains("/broken/") && !sitemap.contains("/missing/"), "{}", sitemap);
+
+    let config = site.path().join("config.toml");
+    let source = std::fs::read_to_string(&config).unwrap();
+    std::fs::write(&config, source.replace("[build]", "[build]\nkeep_going = true\nfailed_pages = \"placeholder\"")).unwrap();
+    let (ok, _, output) = build(&[]);
+    assert!(!ok);
+    let placeholder = std::fs::read_to_string(output.path().join("dist/broken/index.html")).unwrap();
+    assert!(placeholder.contains("This page failed to build"), "{}", placeholder);
+}
+
+#[test]
+fn sitemap_lastmod_follows_changed_pages() {
+    let site = copy_site(&fixtures_dir().join("basic/site"));
+    let output = tempfile::tempdir().expect("failed to create temp dir");
+    let dist = output.path().join("dist");
+    let sitemap = || {
+        // The history is kept in `.hugs-cache/`, so these builds use it
+        build_into(site.path(), &dist, &[]);
+        std::fs::read_to_string(dist.join("sitemap.xml")).unwrap()
+    };
+    let entry = |sitemap: &str, loc: &str| {
+        let loc = format!("<loc>https://golden.example{}</loc>", loc);
+        sitemap.split("<url>").find(|entry| entry.contains(&loc)).unwrap().to_string()
+    };
+    let edit = |file: &str| {
+        let path = site.path().join(file);
+        let source = std::fs::read_to_string(&path).unwrap();
+        std::fs::write(&path, format!("{}\nOne more line.\n", source)).unwrap();
+    };
+
+    // The first build has nothing to compare with, so undated pages get no lastmod
+    let first = sitemap();
+    assert!(!entry(&first, "/about/").contains("<lastmod>"), "{}", first);
+    assert_eq!(sitemap(), first, "an unchanged rebuild should leave the sitemap alone");
+    assert!(site.path().join(".hugs-cache/lastmod.json").exists());
+    assert!(!dist.join(".hugs-lastmod.json").exists(), "the history isn't published");
+
+    // Only the changed page moves, and a frontmatter date always wins
+    edit("about.md");
+    edit("blog/first-post.md");
+    let third = sitemap();
+    assert!(entry(&third, "/about/").contains("<lastmod>"), "{}", third);
+    assert!(entry(&third, "/blog/first-post/").contains("<lastmod>2024-01-15</lastmod>"), "{}", third);
+    for loc in ["/", "/blog/", "/lists/"] {
+        assert_eq!(entry(&third, loc), entry(&first, loc));
+    }
+}
+
+#[test]
+fn render_prints_the_page_the_build_writes() {
+    let site = fixtures_dir().join("basic/site");
+    let render = |args: &[&str]| {
+        let result = Command::new(env!("CARGO_BIN_EXE_hugs"))
+            .arg("render")
+            .args(args)
+            .arg("--site")
+            .arg(&site)
+            .output()
+            .expect("failed to run hugs");
+        (result.status.success(), String::from_utf8_lossy(&result.stdout).to_string(), String::from_utf8_lossy(&result.stderr).to_string())
+    };
+    let expected = |file: &str| std::fs::read_to_string(fixtures_dir().join("basic/expected").join(file)).unwrap();
+
+    // Dynamic pages are found by URL, like in the dev server
+    for (target, file) in [("/tags/rust", "tags/rust/index.html"), ("about.md", "about/index.html")] {
+        let (ok, stdout, stderr) = render(&[target]);
+        assert!(ok, "hugs render {} failed:\n{}", target, stderr);
+        assert_eq!(stdout.trim_end(), expected(file).trim_end(), "hugs render {}", target);
+    }
+
+    let (ok, raw, _) = render(&["/blog/first-post", "--raw"]);
+    assert!(ok);
+    assert_eq!(raw, "<p>Hello from the first post. It takes about 1 minute to read.</p>\n");
+
+    let (ok, stdout, stderr) = render(&["/nope"]);
+    assert!(!ok && stdout.is_empty() && stderr.contains("hugs::render::page_not_found"), "{}", stderr);
+}
+
+#[test]
+fn orphan_reports_list_pages_nothing_links_to() {
+    let build = |format: &str| {
+        let output = tempfile::tempdir().expect("failed to create temp dir");
+        let result = Command::new(env!("CARGO_BIN_EXE_hugs"))
+            .arg("build")
+            .arg("--no-cache")
+            .arg(fixtures_dir().join("orphans/site"))
+            .arg("--output")
+            .arg(output.path().join("dist"))
+            .arg("--format")
+            .arg(format)
+            .output()
+            .expect("failed to run hugs");
+        assert!(result.status.success(), "{}", String::from_utf8_lossy(&result.stderr));
+        (String::from_utf8_lossy(&result.stdout).to_string(), String::from_utf8_lossy(&result.stderr).to_string())
+    };
+
+    // Posts listed by the home page's pages() loop and the nav's About page aren't orphans, and
+    // neither are the pages marked `orphan_ok`. Unlinked topic pages are reported by their file.
+    let (stdout, _) = build("json");
+    let report: serde_json::Value = serde_json::from_str(&stdout).expect("--format json should print JSON");
+    assert_eq!(
+        report,
+        serde_json::json!({
+            "orphans": [{ "url": "/forgotten", "file": "forgotten.md" }],
+            "dynamic_orphans": [{ "file": "topics/[topic].md", "pages": 3, "orphans": ["/topics/pruned", "/topics/stale"] }],
+            "warnings": [],
+        })
+    );
+
+    let (stdout, stderr) = build("text");
+    assert!(stdout.is_empty(), "{}", stdout);
+    assert_eq!(stderr.matches("hugs::build::orphan_page").count(), 1, "{}", stderr);
+    assert!(stderr.contains("/forgotten"), "{}", stderr);
+    assert_eq!(stderr.matches("hugs::build::dynamic_orphans").count(), 1, "{}", stderr);
+}